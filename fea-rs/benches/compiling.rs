@@ -0,0 +1,27 @@
+//! A benchmark for the whole compiler: parsing, resolving and building tables
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use fea_rs::{
+    Compiler, GlyphMap,
+    compile::{NopFeatureProvider, NopVariationInfo},
+};
+
+const OSWALD_FEA: &str = "./test-data/real-files/oswald/features.fea";
+const OSWALD_GLYPHS: &str = include_str!("../test-data/real-files/oswald/glyph_order.txt");
+
+fn compile(glyph_order: &GlyphMap) -> Vec<u8> {
+    Compiler::<NopFeatureProvider, NopVariationInfo>::new(OSWALD_FEA, glyph_order)
+        .compile_binary()
+        .unwrap()
+}
+
+fn compiling(c: &mut Criterion) {
+    let glyph_order = fea_rs::compile::parse_glyph_order(OSWALD_GLYPHS).unwrap();
+    c.benchmark_group("fea-compile")
+        .bench_function("oswald", |b| b.iter(|| compile(black_box(&glyph_order))));
+}
+
+criterion_group!(benches, compiling);
+criterion_main!(benches);
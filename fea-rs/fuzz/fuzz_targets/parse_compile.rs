@@ -0,0 +1,37 @@
+//! Parse, validate and compile arbitrary input, which should never panic.
+//!
+//! Run with `cargo fuzz run parse_compile` from the `fea-rs` directory.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use fea_rs::{
+    GlyphMap, Opts,
+    compile::{self, NopFeatureProvider, NopVariationInfo},
+};
+use libfuzzer_sys::fuzz_target;
+
+static GLYPH_MAP: LazyLock<GlyphMap> = LazyLock::new(|| {
+    compile::parse_glyph_order(include_str!(
+        "../../test-data/compile-tests/mini-latin/glyph_order.txt"
+    ))
+    .unwrap()
+});
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let (tree, diagnostics) = fea_rs::parse::parse_string(text.as_ref());
+    // like the compiler, we validate even if parsing failed
+    let validation = compile::validate::<NopVariationInfo>(&tree, &GLYPH_MAP, None);
+    if diagnostics.has_errors() || validation.has_errors() {
+        return;
+    }
+    let _ = compile::compile::<NopVariationInfo, NopFeatureProvider>(
+        &tree,
+        &GLYPH_MAP,
+        None,
+        None,
+        Opts::new(),
+    );
+});
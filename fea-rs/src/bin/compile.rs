@@ -2,13 +2,22 @@
 
 use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use fea_rs::{
     GlyphMap,
     compile::{
         self, Compiler, MockVariationInfo, NopFeatureProvider, Opts,
-        error::{CompilerError, FontGlyphOrderError, GlyphOrderError, UfoGlyphOrderError},
+        error::{
+            CompilerError, FontGlyphOrderError, GlyphOrderError, SourceGlyphOrderError, TtxError,
+        },
     },
+    decompile::{self, DecompileError},
+    format,
+};
+use write_fonts::{
+    BuilderError,
+    read::{FontRef, ReadError},
+    types::Tag,
 };
 
 /// Attempt to compile features into a font file.
@@ -26,6 +35,13 @@ fn main() {
 fn run() -> Result<(), Error> {
     env_logger::init();
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Fmt(fmt_args)) => return fmt_args.run(),
+        Some(Command::Decompile(decompile_args)) => return decompile_args.run(),
+        Some(Command::Compile(compile_args)) => return compile_args.run(),
+        Some(Command::Validate(validate_args)) => return validate_args.run(),
+        None => (),
+    }
     let (fea, glyph_names) = args.get_inputs()?;
     if !fea.exists() {
         return Err(Error::EmptyFeatureFile);
@@ -35,7 +51,9 @@ fn run() -> Result<(), Error> {
     let opts = args.opts();
 
     let mut compiler: Compiler<'_, NopFeatureProvider, MockVariationInfo> =
-        Compiler::new(fea, &glyph_names).with_opts(opts);
+        Compiler::new(fea, &glyph_names)
+            .with_opts(opts)
+            .with_include_dirs(args.include_dirs.clone());
     if let Some(var_info) = var_info.as_ref() {
         log::info!("compiling with {} mock variation axes", var_info.axes.len());
         for axis in &var_info.axes {
@@ -50,12 +68,36 @@ fn run() -> Result<(), Error> {
 
         compiler = compiler.with_variable_info(var_info);
     }
-    let compiled = compiler.compile()?;
+    let compiled = compiler.compile().inspect_err(|err| {
+        if let Some(diagnostics) = err.diagnostics().filter(|_| args.json) {
+            println!("{}", diagnostics.to_json());
+        }
+    })?;
+
+    if args.stats {
+        let stats = compiled.stats();
+        if args.json {
+            println!("{}", stats.to_json());
+        } else {
+            print!("{stats}");
+        }
+    }
+
+    if let Some(path) = args.ttx.as_deref() {
+        let ttx = compiled.to_ttx(&glyph_names)?;
+        log::info!("writing ttx to {}", path.display());
+        std::fs::write(path, ttx)?;
+    }
 
     let path = args.out_path();
-    let raw_font = compiled
-        .to_binary(&glyph_names)
-        .expect("ttf compile failed");
+    let raw_font = match args.merge_font() {
+        Some(font_path) => {
+            let bytes = std::fs::read(font_path)?;
+            let font = FontRef::new(&bytes)?;
+            compiled.apply_to_font(font)
+        }
+        None => compiled.to_binary(&glyph_names),
+    }?;
 
     log::info!("writing {} bytes to {}", raw_font.len(), path.display());
     std::fs::write(path, raw_font).map_err(Into::into)
@@ -65,14 +107,16 @@ fn run() -> Result<(), Error> {
 enum Error {
     #[error("io error: '{0}'")]
     File(#[from] std::io::Error),
-    #[error("Couldn't read UFO: '{0}'")]
-    Ufo(Box<norad::error::FontLoadError>),
     #[error("invalid glyph map: '{0}'")]
     InvalidGlyphMap(#[from] GlyphOrderError),
     #[error("Couldn't get glyph order from UFO: '{0}'")]
-    UfoBadGlyphOrder(#[from] UfoGlyphOrderError),
+    UfoBadGlyphOrder(Box<SourceGlyphOrderError>),
     #[error("Couldn't get glyph order from font: '{0}")]
     FontBadGlyphOrder(#[from] FontGlyphOrderError),
+    #[error("Couldn't read font: '{0}'")]
+    BadFont(#[from] ReadError),
+    #[error("Couldn't build font: {0}")]
+    BuildFail(#[from] BuilderError),
     #[error("The provided feature file is empty")]
     EmptyFeatureFile,
     #[error("No glyph order provided")]
@@ -81,23 +125,48 @@ enum Error {
     BadAxisInfo { line: usize, message: String },
     #[error("{}", .0.display_verbose())]
     CompileFail(#[from] CompilerError),
+    #[error("Couldn't format '{}':\n{message}", path.display())]
+    FormatFail { path: PathBuf, message: String },
+    #[error("{n_warnings} warnings reported, but at most {max} are allowed")]
+    TooManyWarnings { n_warnings: usize, max: usize },
+    #[error("{n_files} file(s) are not formatted")]
+    Unformatted { n_files: usize },
+    #[error("Couldn't write TTX: {0}")]
+    TtxFail(#[from] TtxError),
+    #[error("Couldn't decompile font: {0}")]
+    DecompileFail(#[from] DecompileError),
 }
 
 /// Compile FEA files
 #[derive(Parser, Debug)]
-#[command(author, version, long_about = None)]
+#[command(
+    author,
+    version,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Display more information about failures
     ///
     /// This includes errors encountered, as well as the generated diffs when
     /// comparison fails.
     #[arg(short, long)]
     verbose: bool,
+    /// Print diagnostics to stdout as JSON, if compilation fails.
+    ///
+    /// This also applies to the output of `--stats`.
+    #[arg(long)]
+    json: bool,
     /// The main input; either a FEA file or a UFO.
     ///
     /// If a FEA file, you will also need to provide a glyph order.
-    /// If a UFO file, the public.glyphOrder key must be present.
-    input: PathBuf,
+    /// If a UFO file, the glyph order is taken from the public.glyphOrder key,
+    /// with any other glyphs sorted at the end.
+    #[arg(required = true)]
+    input: Option<PathBuf>,
     /// Path to a file containing the glyph order.
     ///
     /// This should be a utf-8 encoded file with one name per line,
@@ -109,11 +178,18 @@ struct Args {
     #[arg(short, long, group = "glyph_source")]
     font: Option<PathBuf>,
 
+    /// Add the compiled tables to the font passed with `--font`.
+    ///
+    /// Existing layout tables in that font are replaced, and all other
+    /// tables are copied unchanged.
+    #[arg(short, long, requires = "font")]
+    merge: bool,
+
     /// Variable fonts only: a path to a file containing info on variation axes.
     ///
-    /// Note that we can not correctly compile variable fonts, because we do not
-    /// have the ability to compile deltas. This functionality is provided for
-    /// debugging.
+    /// Deltas for variable metrics are computed from the locations given in the
+    /// FEA, but no fvar or other variation tables are written; this functionality
+    /// is provided for debugging.
     ///
     /// This should be a utf-8 encoded file containing a list of axes and their
     /// (min, default, max) values, in user coordinates.
@@ -142,15 +218,292 @@ struct Args {
 
     #[arg(long)]
     skip_gsub: bool,
+
+    /// Only compile the feature with this tag.
+    ///
+    /// May be passed multiple times; by default all features are compiled.
+    #[arg(long = "feature", value_parser = parse_tag)]
+    features: Vec<Tag>,
+
+    /// Accept glyph names that are not allowed by the spec, as feaLib does.
+    #[arg(long)]
+    liberal_glyph_names: bool,
+
+    /// Additional directories to search for included files.
+    ///
+    /// May be passed multiple times; directories are searched in order.
+    #[arg(short = 'I', long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
+
+    /// Print statistics about the generated layout tables to stdout.
+    ///
+    /// With `--json`, the statistics are printed as JSON.
+    #[arg(long)]
+    stats: bool,
+
+    /// Also write the generated BASE, GDEF, GSUB, GPOS and STAT tables as TTX
+    /// to this path.
+    ///
+    /// The output matches that of fontTools' `ttx`, and can be diffed against
+    /// the output of feaLib.
+    #[arg(long)]
+    ttx: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Format FEA files.
+    Fmt(FmtArgs),
+    /// Decompile the GSUB, GPOS and GDEF tables of a font to FEA.
+    Decompile(DecompileArgs),
+    /// Compile a FEA file into a font, taking the glyph order from a source.
+    Compile(CompileArgs),
+    /// Check that a FEA file compiles, without writing any output.
+    Validate(ValidateArgs),
+}
+
+/// Compile a FEA file and write a font containing the layout tables.
+#[derive(clap::Args, Debug)]
+struct CompileArgs {
+    /// The FEA file to compile.
+    fea: PathBuf,
+
+    /// Where to take the glyph order from.
+    ///
+    /// This may be a compiled font, a UFO, a designspace (in which case the
+    /// default source is used) or a utf-8 file listing one glyph name per
+    /// line, in glyph id order.
+    ///
+    /// If this is a font, the compiled tables are added to a copy of it;
+    /// otherwise the output contains only the layout tables.
+    glyph_source: PathBuf,
+
+    /// Path to write the generated font.
+    out_path: PathBuf,
+
+    /// Additional directories to search for included files.
+    ///
+    /// May be passed multiple times; directories are searched in order.
+    #[arg(short = 'I', long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
+}
+
+impl CompileArgs {
+    fn run(&self) -> Result<(), Error> {
+        if !self.fea.exists() {
+            return Err(Error::EmptyFeatureFile);
+        }
+        let font_bytes = is_font_path(&self.glyph_source)
+            .then(|| std::fs::read(&self.glyph_source))
+            .transpose()?;
+        let glyph_map = match font_bytes.as_deref() {
+            Some(bytes) => GlyphMap::from_font(bytes)?,
+            None => load_glyph_map(&self.glyph_source)?,
+        };
+
+        let compiled =
+            Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(&self.fea, &glyph_map)
+                .with_include_dirs(self.include_dirs.clone())
+                .compile()?;
+
+        let raw_font = match font_bytes.as_deref() {
+            Some(bytes) => compiled.apply_to_font(FontRef::new(bytes)?),
+            None => compiled.to_binary(&glyph_map),
+        }?;
+
+        log::info!(
+            "writing {} bytes to {}",
+            raw_font.len(),
+            self.out_path.display()
+        );
+        std::fs::write(&self.out_path, raw_font).map_err(Into::into)
+    }
+}
+
+/// Check that a FEA file compiles, without writing anything.
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// The FEA file to check.
+    fea: PathBuf,
+
+    /// Where to take the glyph order from.
+    ///
+    /// This may be a compiled font, a UFO, a designspace or a glyph order file,
+    /// as for the `compile` subcommand.
+    glyph_source: PathBuf,
+
+    /// Additional directories to search for included files.
+    #[arg(short = 'I', long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
+
+    /// Fail if more than this many warnings are reported.
+    #[arg(long)]
+    max_warnings: Option<usize>,
+
+    /// Print diagnostics to stdout as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+impl ValidateArgs {
+    fn run(&self) -> Result<(), Error> {
+        if !self.fea.exists() {
+            return Err(Error::EmptyFeatureFile);
+        }
+        let glyph_map = load_glyph_map(&self.glyph_source)?;
+        let result =
+            Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(&self.fea, &glyph_map)
+                .with_include_dirs(self.include_dirs.clone())
+                .check();
+        let warnings = match result {
+            Ok(warnings) => warnings,
+            Err(err) => {
+                if let Some(diagnostics) = err.diagnostics().filter(|_| self.json) {
+                    println!("{}", diagnostics.to_json());
+                }
+                return Err(err.into());
+            }
+        };
+        if self.json {
+            println!("{}", warnings.to_json());
+        } else if !warnings.is_empty() {
+            eprintln!("{}", warnings.display());
+        }
+        match self.max_warnings {
+            Some(max) if warnings.len() > max => Err(Error::TooManyWarnings {
+                n_warnings: warnings.len(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_font_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["ttf", "otf", "ttc"]
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Load a glyph order from a font, UFO, designspace or glyph order file.
+fn load_glyph_map(path: &Path) -> Result<GlyphMap, Error> {
+    if is_font_path(path) {
+        return Ok(GlyphMap::from_font(&std::fs::read(path)?)?);
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ufo") => Ok(GlyphMap::from_ufo(path)?),
+        Some("designspace") => Ok(GlyphMap::from_designspace(path)?),
+        _ => {
+            let contents = std::fs::read_to_string(path)?;
+            Ok(compile::parse_glyph_order(&contents)?)
+        }
+    }
+}
+
+/// Format FEA files in place.
+#[derive(clap::Args, Debug)]
+struct FmtArgs {
+    /// The files to format.
+    ///
+    /// Included files are not followed, and must be passed separately.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Don't write anything, but exit with an error if any file would change.
+    #[arg(long)]
+    check: bool,
+
+    /// Print the formatted output to stdout instead of modifying files.
+    #[arg(long, conflicts_with = "check")]
+    stdout: bool,
+}
+
+impl FmtArgs {
+    fn run(&self) -> Result<(), Error> {
+        let mut n_unformatted = 0;
+        for path in &self.paths {
+            let text = std::fs::read_to_string(path)?;
+            let formatted = format::format_string(&text).map_err(|errors| Error::FormatFail {
+                path: path.clone(),
+                message: errors
+                    .iter()
+                    .filter(|diagnostic| diagnostic.is_error())
+                    .map(|diagnostic| {
+                        let line = text[..diagnostic.span().start].matches('\n').count() + 1;
+                        format!("line {line}: {}", diagnostic.text())
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            })?;
+            if self.stdout {
+                print!("{formatted}");
+            } else if formatted != text {
+                if self.check {
+                    eprintln!("{} is not formatted", path.display());
+                    n_unformatted += 1;
+                } else {
+                    std::fs::write(path, formatted)?;
+                }
+            }
+        }
+        if n_unformatted > 0 {
+            return Err(Error::Unformatted {
+                n_files: n_unformatted,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Decompile the layout tables of a font into FEA.
+#[derive(clap::Args, Debug)]
+struct DecompileArgs {
+    /// The font file to decompile.
+    font: PathBuf,
+
+    /// Path to a file containing the glyph order.
+    ///
+    /// If omitted, glyph names are taken from the font's post table.
+    #[arg(short, long)]
+    glyph_order: Option<PathBuf>,
+
+    /// Path to write the FEA to. Defaults to stdout.
+    #[arg(short, long)]
+    out_path: Option<PathBuf>,
+}
+
+impl DecompileArgs {
+    fn run(&self) -> Result<(), Error> {
+        let bytes = std::fs::read(&self.font)?;
+        let glyph_map = match self.glyph_order.as_deref() {
+            Some(path) => compile::parse_glyph_order(&std::fs::read_to_string(path)?)?,
+            None => GlyphMap::from_font(&bytes)?,
+        };
+        let fea = decompile::decompile(&FontRef::new(&bytes)?, &glyph_map)?;
+        match self.out_path.as_deref() {
+            Some(path) => std::fs::write(path, fea)?,
+            None => print!("{fea}"),
+        }
+        Ok(())
+    }
 }
 
 impl Args {
+    fn input(&self) -> &Path {
+        self.input
+            .as_deref()
+            .expect("input is required without a subcommand")
+    }
+
     pub fn get_inputs(&self) -> Result<(PathBuf, GlyphMap), Error> {
-        if self.input.extension() == Some("ufo".as_ref()) {
-            let request = norad::DataRequest::none().lib(true);
-            let font = norad::Font::load_requested_data(&self.input, request)?;
-            let glyph_order = compile::get_ufo_glyph_order(&font)?;
-            let fea_path = self.input.join("features.fea");
+        let input = self.input();
+        if input.extension() == Some("ufo".as_ref()) {
+            let glyph_order = GlyphMap::from_ufo(input)?;
+            let fea_path = input.join("features.fea");
             Ok((fea_path, glyph_order))
         } else {
             let order = if let Some(path) = self.glyph_order() {
@@ -158,11 +511,11 @@ impl Args {
                 compile::parse_glyph_order(&contents)?
             } else if let Some(path) = self.font.as_deref() {
                 let bytes = std::fs::read(path)?;
-                compile::get_post_glyph_order(&bytes)?
+                GlyphMap::from_font(&bytes)?
             } else {
                 return Err(Error::MissingGlyphOrder);
             };
-            Ok((self.input.clone(), order))
+            Ok((input.to_owned(), order))
         }
     }
 
@@ -183,15 +536,25 @@ impl Args {
         self.axis_info.as_deref()
     }
 
+    fn merge_font(&self) -> Option<&Path> {
+        self.font.as_deref().filter(|_| self.merge)
+    }
+
     fn glyph_order(&self) -> Option<&Path> {
         self.glyph_order.as_deref()
     }
 
     fn opts(&self) -> Opts {
-        Opts::new()
+        let opts = Opts::new()
             .make_post_table(self.post)
             .compile_gpos(!self.skip_gpos)
             .compile_gsub(!self.skip_gsub)
+            .liberal_glyph_names(self.liberal_glyph_names);
+        if self.features.is_empty() {
+            opts
+        } else {
+            opts.features(self.features.iter().copied())
+        }
     }
 
     fn out_path(&self) -> &Path {
@@ -202,8 +565,12 @@ impl Args {
 }
 
 #[cfg(feature = "norad")]
-impl From<norad::error::FontLoadError> for Error {
-    fn from(src: norad::error::FontLoadError) -> Error {
-        Error::Ufo(Box::new(src))
+impl From<SourceGlyphOrderError> for Error {
+    fn from(src: SourceGlyphOrderError) -> Error {
+        Error::UfoBadGlyphOrder(Box::new(src))
     }
 }
+
+fn parse_tag(raw: &str) -> Result<Tag, String> {
+    Tag::new_checked(raw.as_bytes()).map_err(|e| e.to_string())
+}
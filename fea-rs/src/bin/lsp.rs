@@ -0,0 +1,67 @@
+//! A language server for FEA files, communicating over stdin and stdout.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use fea_rs::{
+    GlyphMap,
+    compile::{
+        self,
+        error::{FontGlyphOrderError, GlyphOrderError},
+    },
+    lsp::Server,
+};
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+    let result = args.glyph_map().and_then(|glyph_map| {
+        Ok(Server::new(glyph_map).run(std::io::stdin().lock(), std::io::stdout().lock())?)
+    });
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("io error: '{0}'")]
+    File(#[from] std::io::Error),
+    #[error("invalid glyph map: '{0}'")]
+    InvalidGlyphMap(#[from] GlyphOrderError),
+    #[error("Couldn't get glyph order from font: '{0}")]
+    FontBadGlyphOrder(#[from] FontGlyphOrderError),
+}
+
+/// Run a language server for FEA files.
+///
+/// Without a glyph order, only syntax errors are reported.
+#[derive(Parser, Debug)]
+#[command(author, version, long_about = None)]
+struct Args {
+    /// Path to a file containing the glyph order.
+    ///
+    /// This should be a utf-8 encoded file with one name per line,
+    /// sorted in glyphid order.
+    #[arg(short, long, group = "glyph_source")]
+    glyph_order: Option<PathBuf>,
+
+    /// Path to a font file to be used to calculate glyph order.
+    #[arg(short, long, group = "glyph_source")]
+    font: Option<PathBuf>,
+}
+
+impl Args {
+    fn glyph_map(&self) -> Result<Option<GlyphMap>, Error> {
+        if let Some(path) = self.glyph_order.as_deref() {
+            let contents = std::fs::read_to_string(path)?;
+            Ok(Some(compile::parse_glyph_order(&contents)?))
+        } else if let Some(path) = self.font.as_deref() {
+            let bytes = std::fs::read(path)?;
+            Ok(Some(GlyphMap::from_font(&bytes)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -12,7 +12,11 @@ fn main() {
     env_logger::init();
     let args = Args::parse();
 
-    let results = ttx::run_fonttools_tests(args.test_filter);
+    let results = if args.builtin {
+        ttx::run_fonttools_tests_builtin(args.test_filter)
+    } else {
+        ttx::run_fonttools_tests(args.test_filter)
+    };
 
     if let Some(to_compare) = args
         .compare
@@ -70,6 +74,11 @@ struct Args {
     /// e.g.: -t "spec5,GPOS" matches spec5h1.fea, spec5fi2.fea, GPOS_2.fea, etc
     #[arg(short, long = "test")]
     test_filter: Option<String>,
+    /// Compare the layout tables directly, without running `ttx`.
+    ///
+    /// Failures are reported as a list of structural differences.
+    #[arg(short, long)]
+    builtin: bool,
     /// Write diffs to a ./wip directory
     #[arg(short, long)]
     write_diff: bool,
@@ -9,7 +9,7 @@ pub use write_fonts::types::GlyphId16;
 mod glyph_class;
 mod glyph_map;
 
-pub(crate) use glyph_class::GlyphClass;
+pub(crate) use glyph_class::{GlyphClass, GlyphClassInterner, SharedGlyphSet};
 
 pub use glyph_class::GlyphSet;
 pub use glyph_map::GlyphMap;
@@ -18,6 +18,7 @@ pub use glyph_map::GlyphMap;
 ///
 /// Various places in the FEA spec accept either a single glyph or a glyph class.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum GlyphOrClass {
     /// A resolved GlyphId
     Glyph(GlyphId16),
@@ -41,6 +42,15 @@ pub(crate) struct MarkClass {
     pub(crate) members: Vec<(GlyphClass, Option<AnchorBuilder>)>,
 }
 
+impl MarkClass {
+    /// The members of this class that have an anchor.
+    pub(crate) fn anchored_members(&self) -> impl Iterator<Item = (&GlyphClass, &AnchorBuilder)> {
+        self.members
+            .iter()
+            .filter_map(|(glyphs, anchor)| Some((glyphs, anchor.as_ref()?)))
+    }
+}
+
 impl<T: Into<GlyphName>> From<T> for GlyphIdent {
     fn from(src: T) -> Self {
         GlyphIdent::Name(src.into())
@@ -51,7 +61,7 @@ impl Display for GlyphIdent {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
             GlyphIdent::Name(name) => write!(f, "{name}"),
-            GlyphIdent::Cid(cid) => write!(f, "Cid({cid})"),
+            GlyphIdent::Cid(cid) => write!(f, "\\{cid}"),
         }
     }
 }
@@ -1,3 +1,10 @@
+use std::{
+    collections::{HashMap, HashSet, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::Arc,
+};
+
 use write_fonts::{read::collections::IntSet, types::GlyphId16};
 
 use super::GlyphOrClass;
@@ -21,8 +28,21 @@ use super::GlyphOrClass;
 /// See the [spec docs] for more information.
 ///
 /// [spec docs]: http://adobe-type-tools.github.io/afdko/OpenTypeFeatureFileSpecification.html#2g-glyph-classes
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub(crate) struct GlyphClass(Vec<GlyphId16>);
+///
+/// The glyphs are reference counted and the hash is computed once, so cloning
+/// and hashing a class are cheap; classes produced by a [`GlyphClassInterner`]
+/// share storage with every identical class from the same interner, and
+/// compare by pointer.
+#[derive(Clone, Debug, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<GlyphId16>", into = "Vec<GlyphId16>")
+)]
+pub(crate) struct GlyphClass {
+    glyphs: Arc<[GlyphId16]>,
+    hash: u64,
+}
 
 /// A sorted set of unique glyph ids.
 ///
@@ -30,12 +50,48 @@ pub(crate) struct GlyphClass(Vec<GlyphId16>);
 /// from how we use it when building tables that contain OpenType glyph ClassDefs.
 pub type GlyphSet = IntSet<GlyphId16>;
 
+/// A shared, immutable [`GlyphSet`].
+///
+/// Like [`GlyphClass`], this is cheap to clone and to hash, which makes it
+/// suitable as a map key; sets produced by a [`GlyphClassInterner`] share
+/// storage with every equal set from the same interner, and compare by pointer.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "GlyphSet", into = "GlyphSet")
+)]
+pub(crate) struct SharedGlyphSet {
+    set: Arc<GlyphSet>,
+    hash: u64,
+}
+
+/// A registry of glyph classes, so that identical classes share storage.
+///
+/// Large feature files resolve the same classes many times over; interning
+/// them means each distinct class is allocated once, and that its
+/// [`GlyphSet`] is only computed once and shared by every class with the
+/// same glyphs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GlyphClassInterner {
+    classes: HashMap<GlyphClass, Option<SharedGlyphSet>>,
+    sets: HashSet<SharedGlyphSet>,
+}
+
+fn hash_of(item: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl GlyphClass {
-    /// An empty glyph class
-    pub const EMPTY: Self = GlyphClass(Vec::new());
+    fn new(glyphs: Arc<[GlyphId16]>) -> Self {
+        let hash = hash_of(&glyphs);
+        GlyphClass { glyphs, hash }
+    }
 
     pub(crate) fn items(&self) -> &[GlyphId16] {
-        &self.0
+        &self.glyphs
     }
 
     /// Return a `GlyphSet` containing the unique glyphs in this class.
@@ -48,13 +104,120 @@ impl GlyphClass {
     }
 
     pub(crate) fn len(&self) -> usize {
-        self.0.len()
+        self.glyphs.len()
+    }
+}
+
+impl Default for GlyphClass {
+    fn default() -> Self {
+        GlyphClass::new(Arc::new([]))
+    }
+}
+
+impl PartialEq for GlyphClass {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.glyphs, &other.glyphs)
+            || (self.hash == other.hash && self.glyphs == other.glyphs)
+    }
+}
+
+impl Hash for GlyphClass {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl SharedGlyphSet {
+    /// Return the set, cloning it only if it is shared.
+    pub(crate) fn into_inner(self) -> GlyphSet {
+        Arc::unwrap_or_clone(self.set)
+    }
+}
+
+impl Deref for SharedGlyphSet {
+    type Target = GlyphSet;
+
+    fn deref(&self) -> &GlyphSet {
+        &self.set
+    }
+}
+
+impl PartialEq for SharedGlyphSet {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.set, &other.set) || (self.hash == other.hash && self.set == other.set)
+    }
+}
+
+impl Eq for SharedGlyphSet {}
+
+impl Hash for SharedGlyphSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl From<GlyphSet> for SharedGlyphSet {
+    fn from(set: GlyphSet) -> Self {
+        let hash = hash_of(&set);
+        SharedGlyphSet {
+            set: Arc::new(set),
+            hash,
+        }
+    }
+}
+
+impl From<SharedGlyphSet> for GlyphSet {
+    fn from(value: SharedGlyphSet) -> Self {
+        value.into_inner()
+    }
+}
+
+impl std::iter::FromIterator<GlyphId16> for SharedGlyphSet {
+    fn from_iter<T: IntoIterator<Item = GlyphId16>>(iter: T) -> Self {
+        iter.into_iter().collect::<GlyphSet>().into()
+    }
+}
+
+impl GlyphClassInterner {
+    /// Return the interned copy of this class, adding it if it is new.
+    pub(crate) fn intern(&mut self, class: GlyphClass) -> GlyphClass {
+        if let Some((existing, _)) = self.classes.get_key_value(&class) {
+            return existing.clone();
+        }
+        self.classes.insert(class.clone(), None);
+        class
+    }
+
+    /// Return the unique glyphs in this class, computing them at most once.
+    ///
+    /// Classes with the same glyphs in any order share the returned set.
+    pub(crate) fn glyph_set(&mut self, class: &GlyphClass) -> SharedGlyphSet {
+        if let Some(Some(set)) = self.classes.get(class) {
+            return set.clone();
+        }
+        let set = SharedGlyphSet::from(class.to_glyph_set());
+        let set = match self.sets.get(&set) {
+            Some(existing) => existing.clone(),
+            None => {
+                self.sets.insert(set.clone());
+                set
+            }
+        };
+        let class = self.intern(class.clone());
+        self.classes.insert(class, Some(set.clone()));
+        set
+    }
+
+    /// The number of distinct classes seen so far.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.classes.len()
     }
 }
 
 impl std::iter::FromIterator<GlyphId16> for GlyphClass {
     fn from_iter<T: IntoIterator<Item = GlyphId16>>(iter: T) -> Self {
-        GlyphClass(iter.into_iter().collect())
+        GlyphClass::new(iter.into_iter().collect())
     }
 }
 
@@ -64,13 +227,19 @@ impl<'a> std::iter::IntoIterator for &'a GlyphClass {
     type IntoIter = std::slice::Iter<'a, GlyphId16>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter()
+        self.glyphs.iter()
     }
 }
 
 impl From<Vec<GlyphId16>> for GlyphClass {
     fn from(src: Vec<GlyphId16>) -> GlyphClass {
-        GlyphClass(src)
+        GlyphClass::new(src.into())
+    }
+}
+
+impl From<GlyphClass> for Vec<GlyphId16> {
+    fn from(src: GlyphClass) -> Vec<GlyphId16> {
+        src.items().to_vec()
     }
 }
 
@@ -82,8 +251,7 @@ impl From<GlyphClass> for GlyphSet {
 
 impl From<GlyphId16> for GlyphClass {
     fn from(src: GlyphId16) -> GlyphClass {
-        let slice: &[_] = &[src];
-        GlyphClass(slice.into())
+        GlyphClass::new(Arc::new([src]))
     }
 }
 
@@ -92,7 +260,50 @@ impl From<GlyphOrClass> for GlyphClass {
         match src {
             GlyphOrClass::Class(class) => class,
             GlyphOrClass::Glyph(id) => id.into(),
-            GlyphOrClass::Null => GlyphClass::EMPTY,
+            GlyphOrClass::Null => GlyphClass::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(ids: &[u16]) -> GlyphClass {
+        ids.iter().copied().map(GlyphId16::new).collect()
+    }
+
+    #[test]
+    fn interned_classes_share_storage() {
+        let mut interner = GlyphClassInterner::default();
+        let one = interner.intern(class(&[1, 2, 3]));
+        let two = interner.intern(class(&[1, 2, 3]));
+        let other = interner.intern(class(&[3, 2, 1]));
+        assert!(Arc::ptr_eq(&one.glyphs, &two.glyphs));
+        assert!(!Arc::ptr_eq(&one.glyphs, &other.glyphs));
+        assert_ne!(one, other);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn glyph_set_is_cached() {
+        let mut interner = GlyphClassInterner::default();
+        let first = interner.glyph_set(&class(&[5, 4, 5]));
+        let second = interner.glyph_set(&class(&[5, 4, 5]));
+        assert!(Arc::ptr_eq(&first.set, &second.set));
+        assert_eq!(
+            first.iter().map(|gid| gid.to_u16()).collect::<Vec<_>>(),
+            [4, 5]
+        );
+        assert_eq!(interner.len(), 1);
+    }
+
+    #[test]
+    fn reordered_classes_share_glyph_set() {
+        let mut interner = GlyphClassInterner::default();
+        let first = interner.glyph_set(&class(&[1, 2]));
+        let second = interner.glyph_set(&class(&[2, 1, 2]));
+        assert!(Arc::ptr_eq(&first.set, &second.set));
+        assert_eq!(interner.len(), 2);
+    }
+}
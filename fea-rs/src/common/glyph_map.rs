@@ -1,10 +1,18 @@
-use write_fonts::tables::post::Post;
+use write_fonts::{
+    read::{FontRef, TableProvider},
+    tables::post::Post,
+};
 
 use super::{GlyphId16, GlyphIdent};
+use crate::compile::error::{FontGlyphOrderError, GlyphOrderError};
+#[cfg(feature = "norad")]
+use crate::compile::error::{SourceGlyphOrderError, UfoGlyphOrderError};
 use fontdrasil::types::GlyphName;
+#[cfg(feature = "norad")]
+use std::path::Path;
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryInto,
     iter::FromIterator,
 };
@@ -14,22 +22,103 @@ use std::{
 /// This is used to map from names or CIDS encountered in a FEA file to the actual
 /// GlyphId16s that will be used in the final font.
 ///
-/// Currently, the only way to construct this type is by calling `collect()`
-/// on an iterator of some type that impls `Into<GlyphIdent>` (such as `&str`,
-/// `GlyphName`, or `u16` (if using CIDs)).
+/// This can be constructed by calling `collect()` on an iterator of some type
+/// that impls `Into<GlyphIdent>` (such as `&str`, `GlyphName`, or `u16` (if
+/// using CIDs)), or from an existing font with [`GlyphMap::from_font`].
 ///
 /// ```
 /// # use fea_rs::GlyphMap;
 /// let myglyphs = GlyphMap::from_iter(["a", "b", "gee", "whiz"]);
 /// ```
+///
+/// Glyphs can also have aliases, such as the development names used in a
+/// font's sources when the map contains the final production names (or the
+/// other way around). Glyph names in FEA resolve against either set; see
+/// [`GlyphMap::add_alias`].
 #[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GlyphMap {
     names: HashMap<GlyphName, GlyphId16>,
     cids: HashMap<u16, GlyphId16>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    aliases: BTreeMap<GlyphName, GlyphId16>,
 }
 
 impl GlyphMap {
+    /// Read the glyph order of a compiled TrueType or OpenType font.
+    ///
+    /// The number of glyphs comes from the 'maxp' table, and names from the
+    /// 'post' table. Glyphs that have no name in 'post' (for instance because
+    /// it is a version 3 table) are named the way fontTools names them: glyph
+    /// zero is `.notdef`, glyphs in the 'cmap' table are named `uniXXXX` or
+    /// `uXXXXX` after their lowest codepoint, and other glyphs are named
+    /// `glyphXXXXX` after their id. Duplicate names are made unique by adding
+    /// a suffix such as `#1`.
+    pub fn from_font(font_data: &[u8]) -> Result<GlyphMap, FontGlyphOrderError> {
+        let font = FontRef::new(font_data)?;
+        let num_glyphs = font.maxp()?.num_glyphs();
+        let post = font.post().ok();
+        let mut codepoints = HashMap::new();
+        if let Some((_, _, subtable)) = font.cmap().ok().and_then(|cmap| cmap.best_subtable()) {
+            for (codepoint, gid) in subtable.iter() {
+                let lowest = codepoints.entry(gid.to_u32()).or_insert(codepoint);
+                *lowest = codepoint.min(*lowest);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        Ok((0..num_glyphs)
+            .map(|gid| {
+                let name = match post
+                    .as_ref()
+                    .and_then(|post| post.glyph_name(GlyphId16::new(gid)))
+                    .filter(|name| !name.is_empty())
+                {
+                    Some(name) => name.to_owned(),
+                    None if gid == 0 => ".notdef".to_owned(),
+                    None => match codepoints.get(&(gid as u32)) {
+                        Some(cp @ 0..=0xFFFF) => format!("uni{cp:04X}"),
+                        Some(cp) => format!("u{cp:X}"),
+                        None => format!("glyph{gid:05}"),
+                    },
+                };
+                let mut unique = name.clone();
+                let mut suffix = 1;
+                while !seen.insert(unique.clone()) {
+                    unique = format!("{name}#{suffix}");
+                    suffix += 1;
+                }
+                GlyphName::new(unique)
+            })
+            .collect())
+    }
+
+    /// Load the glyph order of a UFO.
+    ///
+    /// This follows ufo2ft: glyphs are ordered by the `public.glyphOrder` key
+    /// in `lib.plist`, with `.notdef` first and any glyphs missing from the
+    /// key sorted by name at the end. Names in the key that are not glyphs in
+    /// the default layer are ignored.
+    #[cfg(feature = "norad")]
+    pub fn from_ufo(path: impl AsRef<Path>) -> Result<GlyphMap, SourceGlyphOrderError> {
+        let request = norad::DataRequest::none().lib(true).default_layer(true);
+        let font = norad::Font::load_requested_data(path, request)?;
+        Ok(ufo_glyph_order(&font)?)
+    }
+
+    /// Load the glyph order of the default source of a designspace.
+    ///
+    /// The default source is the one located at the default value of every
+    /// axis; its glyph order is loaded as with [`GlyphMap::from_ufo`].
+    #[cfg(feature = "norad")]
+    pub fn from_designspace(path: impl AsRef<Path>) -> Result<GlyphMap, SourceGlyphOrderError> {
+        let path = path.as_ref();
+        let designspace = norad::designspace::DesignSpaceDocument::load(path)?;
+        let source = default_source(&designspace).ok_or(SourceGlyphOrderError::NoDefaultSource)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        GlyphMap::from_ufo(dir.join(&source.filename))
+    }
+
     /// The total number of glyphs
     pub fn len(&self) -> usize {
         self.names.len() + self.cids.len()
@@ -54,6 +143,57 @@ impl GlyphMap {
             .collect()
     }
 
+    /// Like [`reverse_map`][Self::reverse_map], but naming glyphs by their
+    /// alias where they have one.
+    ///
+    /// If a glyph has more than one alias, the first in sort order is used.
+    pub fn reverse_map_with_aliases(&self) -> BTreeMap<GlyphId16, GlyphIdent> {
+        let mut map = self.reverse_map();
+        // iterate in reverse so the first alias wins
+        for (alias, id) in self.aliases.iter().rev() {
+            map.insert(*id, GlyphIdent::Name(alias.clone()));
+        }
+        map
+    }
+
+    /// Add an alias for a glyph already in the map.
+    ///
+    /// After this, `alias` resolves to the same glyph as `name`. Adding an
+    /// alias that is already the name of the same glyph does nothing.
+    pub fn add_alias(
+        &mut self,
+        alias: impl Into<GlyphName>,
+        name: &str,
+    ) -> Result<(), GlyphOrderError> {
+        let alias = alias.into();
+        let Some(id) = self.names.get(name).copied() else {
+            return Err(GlyphOrderError::UnknownAliasTarget {
+                alias: alias.to_string(),
+                name: name.to_owned(),
+            });
+        };
+        match self.get(alias.as_str()) {
+            Some(existing) if existing == id => {
+                if !self.names.contains_key(&alias) {
+                    self.aliases.insert(alias, id);
+                }
+                Ok(())
+            }
+            Some(_) => Err(GlyphOrderError::AliasConflict {
+                alias: alias.to_string(),
+            }),
+            None => {
+                self.aliases.insert(alias, id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Iterate the aliases in this map, and the glyphs they refer to.
+    pub fn aliases(&self) -> impl Iterator<Item = (&GlyphName, GlyphId16)> + '_ {
+        self.aliases.iter().map(|(alias, id)| (alias, *id))
+    }
+
     /// Iterate the idents in this map, in GID order.
     ///
     /// This is really only intended to be used to create new glyphmaps for testing.
@@ -61,10 +201,15 @@ impl GlyphMap {
         self.reverse_map().into_values()
     }
 
+    /// Iterate the glyph names and aliases in this map, in no particular order.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &GlyphName> + '_ {
+        self.names.keys().chain(self.aliases.keys())
+    }
+
     /// Return `true` if the map contains the provided `GlyphIdent`.
     pub fn contains<Q: ?Sized + sealed::AsGlyphIdent>(&self, key: &Q) -> bool {
         if let Some(name) = key.named() {
-            self.names.contains_key(name)
+            self.names.contains_key(name) || self.aliases.contains_key(name)
         } else if let Some(cid) = key.cid() {
             self.cids.contains_key(cid)
         } else {
@@ -75,7 +220,10 @@ impl GlyphMap {
     /// Return the `GlyphId16` for the provided `GlyphIdent`
     pub fn get<Q: ?Sized + sealed::AsGlyphIdent>(&self, key: &Q) -> Option<GlyphId16> {
         if let Some(name) = key.named() {
-            self.names.get(name).copied()
+            self.names
+                .get(name)
+                .or_else(|| self.aliases.get(name))
+                .copied()
         } else if let Some(cid) = key.cid() {
             self.cids.get(cid).copied()
         } else {
@@ -109,8 +257,90 @@ impl<T: Into<GlyphIdent>> FromIterator<T> for GlyphMap {
                 GlyphIdent::Name(name) => names.insert(name, idx),
             };
         }
-        GlyphMap { names, cids }
+        GlyphMap {
+            names,
+            cids,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+/// The glyph order of a UFO, with `.notdef` first and unordered glyphs sorted
+/// at the end.
+#[cfg(feature = "norad")]
+fn ufo_glyph_order(font: &norad::Font) -> Result<GlyphMap, UfoGlyphOrderError> {
+    let mut remaining = font
+        .iter_names()
+        .map(|name| name.to_string())
+        .collect::<std::collections::BTreeSet<_>>();
+    let ordered = match font.lib.get("public.glyphOrder") {
+        Some(order) => order
+            .as_array()
+            .and_then(|names| names.iter().map(|name| name.as_string()).collect())
+            .ok_or(UfoGlyphOrderError::Malformed)?,
+        None => Vec::new(),
+    };
+    let mut order = Vec::with_capacity(remaining.len());
+    for name in std::iter::once(".notdef").chain(ordered) {
+        if remaining.remove(name) {
+            order.push(name.to_owned());
+        }
     }
+    order.extend(remaining);
+    Ok(order.into_iter().map(GlyphName::new).collect())
+}
+
+/// The source at the default location on every axis.
+#[cfg(feature = "norad")]
+fn default_source(
+    designspace: &norad::designspace::DesignSpaceDocument,
+) -> Option<&norad::designspace::Source> {
+    // axis defaults are user coordinates, and source locations are design
+    // coordinates
+    let defaults = designspace
+        .axes
+        .iter()
+        .map(|axis| {
+            let design = match axis.map.as_deref() {
+                Some(map) if !map.is_empty() => piecewise_linear(map, axis.default),
+                _ => axis.default,
+            };
+            (axis.name.as_str(), design)
+        })
+        .collect::<HashMap<_, _>>();
+    designspace.sources.iter().find(|source| {
+        source.location.iter().all(|dim| {
+            let Some(default) = defaults.get(dim.name.as_str()) else {
+                return true;
+            };
+            dim.xvalue
+                .is_none_or(|value| (value - default).abs() < 1e-3)
+        })
+    })
+}
+
+/// Map a user coordinate to a design coordinate using an axis map.
+#[cfg(feature = "norad")]
+fn piecewise_linear(map: &[norad::designspace::AxisMapping], value: f32) -> f32 {
+    let mut map = map
+        .iter()
+        .map(|mapping| (mapping.input, mapping.output))
+        .collect::<Vec<_>>();
+    map.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (first, last) = (map[0], map[map.len() - 1]);
+    if value <= first.0 {
+        return first.1 + value - first.0;
+    }
+    if value >= last.0 {
+        return last.1 + value - last.0;
+    }
+    map.windows(2)
+        .find(|pair| value <= pair[1].0)
+        .map(|pair| {
+            let ((in0, out0), (in1, out1)) = (pair[0], pair[1]);
+            out0 + (out1 - out0) * (value - in0) / (in1 - in0)
+        })
+        .unwrap_or(value)
 }
 
 mod sealed {
@@ -176,3 +406,176 @@ mod sealed {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use write_fonts::{
+        FontBuilder,
+        tables::{cmap::Cmap, maxp::Maxp},
+        types::GlyphId,
+    };
+
+    use super::*;
+
+    fn make_font(num_glyphs: u16, post: Post) -> Vec<u8> {
+        let cmap = Cmap::from_mappings([
+            ('a', GlyphId::new(1)),
+            ('\u{1F600}', GlyphId::new(4)),
+            ('A', GlyphId::new(2)),
+            ('B', GlyphId::new(2)),
+            ('b', GlyphId::new(3)),
+        ])
+        .unwrap();
+        FontBuilder::new()
+            .add_table(&Maxp::new(num_glyphs))
+            .unwrap()
+            .add_table(&post)
+            .unwrap()
+            .add_table(&cmap)
+            .unwrap()
+            .build()
+    }
+
+    fn names(map: &GlyphMap) -> Vec<String> {
+        map.reverse_map()
+            .into_values()
+            .map(|ident| match ident {
+                GlyphIdent::Name(name) => name.to_string(),
+                GlyphIdent::Cid(cid) => cid.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aliases() {
+        let mut map = GlyphMap::from_iter([".notdef", "uni00E9", "uni00E8"]);
+        map.add_alias("eacute", "uni00E9").unwrap();
+        map.add_alias("egrave", "uni00E8").unwrap();
+        map.add_alias("e_acute", "uni00E9").unwrap();
+        // aliasing a glyph to its own name is fine
+        map.add_alias("uni00E8", "uni00E8").unwrap();
+
+        assert_eq!(map.get("eacute"), map.get("uni00E9"));
+        assert!(map.contains("egrave"));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.aliases().count(), 3);
+        assert_eq!(names(&map), [".notdef", "uni00E9", "uni00E8"]);
+        let aliased = map
+            .reverse_map_with_aliases()
+            .into_values()
+            .map(|ident| ident.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(aliased, [".notdef", "e_acute", "egrave"]);
+
+        assert!(matches!(
+            map.add_alias("eacute", "uni00E8"),
+            Err(GlyphOrderError::AliasConflict { .. })
+        ));
+        assert!(matches!(
+            map.add_alias("agrave", "uni00E0"),
+            Err(GlyphOrderError::UnknownAliasTarget { .. })
+        ));
+    }
+
+    #[test]
+    fn from_font_post_names() {
+        let post = Post::new_v2([".notdef", "a", "smiley", "a"]);
+        let map = GlyphMap::from_font(&make_font(4, post)).unwrap();
+        assert_eq!(names(&map), [".notdef", "a", "smiley", "a#1"]);
+    }
+
+    #[test]
+    fn from_font_without_post_names() {
+        let post = Post {
+            version: write_fonts::types::Version16Dot16::VERSION_3_0,
+            ..Default::default()
+        };
+        let map = GlyphMap::from_font(&make_font(6, post)).unwrap();
+        assert_eq!(
+            names(&map),
+            [
+                ".notdef",
+                "uni0061",
+                "uni0041",
+                "uni0062",
+                "u1F600",
+                "glyph00005"
+            ]
+        );
+    }
+
+    /// Write a UFO with the given glyphs and glyph order to a temporary
+    /// directory.
+    #[cfg(feature = "norad")]
+    fn write_ufo(dir: &Path, glyphs: &[&str], order: &[&str]) {
+        let mut font = norad::Font::new();
+        for name in glyphs {
+            font.default_layer_mut()
+                .insert_glyph(norad::Glyph::new(name));
+        }
+        font.save(dir).unwrap();
+        let order = order
+            .iter()
+            .map(|name| format!("<string>{name}</string>"))
+            .collect::<String>();
+        std::fs::write(
+            dir.join("lib.plist"),
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <plist version=\"1.0\"><dict>\
+                 <key>public.glyphOrder</key><array>{order}</array>\
+                 </dict></plist>\n"
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "norad")]
+    fn from_ufo_and_designspace() {
+        let dir = std::env::temp_dir().join(format!("fea-rs-glyph-map-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        write_ufo(
+            &dir.join("Light.ufo"),
+            &["a", "b", ".notdef"],
+            &["b", "a", ".notdef"],
+        );
+        write_ufo(
+            &dir.join("Regular.ufo"),
+            &["c", "a", "b", ".notdef"],
+            &["b", "missing", "a"],
+        );
+        let regular = [".notdef", "b", "a", "c"];
+        let map = GlyphMap::from_ufo(dir.join("Regular.ufo")).unwrap();
+        assert_eq!(names(&map), regular);
+
+        // the default is 400 in user coordinates, which maps to 90
+        std::fs::write(
+            dir.join("test.designspace"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<designspace format="4.1">
+  <axes>
+    <axis tag="wght" name="Weight" minimum="300" maximum="700" default="400">
+      <map input="300" output="50"/>
+      <map input="400" output="90"/>
+      <map input="700" output="200"/>
+    </axis>
+  </axes>
+  <sources>
+    <source filename="Light.ufo" name="Light">
+      <location><dimension name="Weight" xvalue="50"/></location>
+    </source>
+    <source filename="Regular.ufo" name="Regular">
+      <location><dimension name="Weight" xvalue="90"/></location>
+    </source>
+  </sources>
+</designspace>
+"#,
+        )
+        .unwrap();
+        let map = GlyphMap::from_designspace(dir.join("test.designspace")).unwrap();
+        assert_eq!(names(&map), regular);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 //! Compiling OpenType Layout tables
 
-use crate::{DiagnosticSet, GlyphMap, parse::ParseTree};
+use crate::{DiagnosticSet, GlyphIdent, GlyphMap, parse::ParseTree};
 use fontdrasil::types::GlyphName;
 use write_fonts::{tables::layout::builders::CaretValueBuilder as CaretValue, types::GlyphId16};
 
@@ -12,6 +12,7 @@ use self::{
 #[cfg(feature = "norad")]
 use self::error::UfoGlyphOrderError;
 
+pub use cancel::CancellationToken;
 pub use compiler::Compiler;
 pub use feature_writer::{
     FeatureBuilder, FeatureProvider, InsertionPoint, NopFeatureProvider, PendingLookup,
@@ -19,23 +20,33 @@ pub use feature_writer::{
 pub use language_system::LanguageSystem;
 pub use lookups::{FeatureKey, LookupId};
 pub use opts::Opts;
-pub use output::Compilation;
+pub use output::{AnonBlock, Compilation, UnknownTable};
+pub use state::CompilationState;
+pub use stats::{CompilationStats, FeatureStats, LookupStats, TableStats};
+pub use tables::GdefBuilder;
 pub use variations::{AxisLocation, NopVariationInfo, VariationInfo};
 
 #[cfg(any(test, feature = "test", feature = "cli"))]
 pub use variations::MockVariationInfo;
 
+mod cancel;
+mod closure;
 mod compile_ctx;
 mod compiler;
+mod dedup;
 pub mod error;
 mod feature_writer;
 mod features;
+mod glyph_class;
 mod glyph_range;
 pub(crate) mod glyphsapp_syntax_ext;
 mod language_system;
 mod lookups;
+mod merge;
 mod opts;
 mod output;
+mod state;
+mod stats;
 mod tables;
 mod tags;
 mod validate;
@@ -46,8 +57,19 @@ pub fn validate<V: VariationInfo>(
     node: &ParseTree,
     glyph_map: &GlyphMap,
     fvar: Option<&V>,
+) -> DiagnosticSet {
+    validate_with_cancellation(node, glyph_map, fvar, None)
+}
+
+/// Run the validation pass, stopping early if `cancellation` is cancelled.
+pub(crate) fn validate_with_cancellation<V: VariationInfo>(
+    node: &ParseTree,
+    glyph_map: &GlyphMap,
+    fvar: Option<&V>,
+    cancellation: Option<&CancellationToken>,
 ) -> DiagnosticSet {
     let mut ctx = validate::ValidationCtx::new(node.source_map(), glyph_map, fvar);
+    ctx.cancellation = cancellation.cloned();
     ctx.validate_root(&node.typed_root());
     DiagnosticSet::new(ctx.errors, node, usize::MAX)
 }
@@ -62,7 +84,7 @@ pub fn compile<V: VariationInfo, T: FeatureProvider>(
     extra_features: Option<&T>,
     opts: Opts,
 ) -> Result<(Compilation, DiagnosticSet), DiagnosticSet> {
-    let mut ctx = CompilationCtx::new(glyph_map, tree.source_map(), var_info, extra_features, opts);
+    let mut ctx = CompilationCtx::new(glyph_map, tree, var_info, extra_features, opts);
     ctx.compile(&tree.typed_root());
     match ctx.build() {
         Ok((compilation, warnings)) => {
@@ -80,6 +102,9 @@ pub fn compile<V: VariationInfo, T: FeatureProvider>(
 ///
 /// If the public.glyphOrder key is missing, or the glyphOrder is malformed,
 /// this will return `None`.
+///
+/// To also include glyphs that are missing from the public.glyphOrder key, use
+/// [`GlyphMap::from_ufo`].
 #[cfg(feature = "norad")]
 pub fn get_ufo_glyph_order(font: &norad::Font) -> Result<GlyphMap, UfoGlyphOrderError> {
     static GLYPH_ORDER_KEY: &str = "public.glyphOrder";
@@ -99,7 +124,9 @@ pub fn get_ufo_glyph_order(font: &norad::Font) -> Result<GlyphMap, UfoGlyphOrder
 
 /// A helper function for extracting glyph order from a font with a 'post' table
 ///
-/// If 'post' is missing or malformed, this will return `None`.
+/// If 'post' is missing or malformed, or does not contain glyph names, this
+/// returns an error. [`GlyphMap::from_font`] also handles fonts without glyph
+/// names.
 pub fn get_post_glyph_order(font_data: &[u8]) -> Result<GlyphMap, FontGlyphOrderError> {
     use write_fonts::{
         from_obj::ToOwnedTable,
@@ -128,7 +155,8 @@ pub fn get_post_glyph_order(font_data: &[u8]) -> Result<GlyphMap, FontGlyphOrder
 
 /// Extract a glyph order from an ordered list of glyph names.
 ///
-/// Input must contain one glyph per line.
+/// Input must contain one glyph per line. For CID-keyed fonts, a line may
+/// instead contain a CID in FEA notation, e.g. `\\123`.
 pub fn parse_glyph_order(glyphs: &str) -> Result<GlyphMap, GlyphOrderError> {
     let map: GlyphMap = glyphs
         .lines()
@@ -138,8 +166,14 @@ pub fn parse_glyph_order(glyphs: &str) -> Result<GlyphMap, GlyphOrderError> {
                 Err(GlyphOrderError::NameError {
                     name: line.to_owned(),
                 })
+            } else if let Some(cid) = line.strip_prefix('\\') {
+                cid.parse::<u16>()
+                    .map(GlyphIdent::Cid)
+                    .map_err(|_| GlyphOrderError::NameError {
+                        name: line.to_owned(),
+                    })
             } else {
-                Ok(GlyphName::new(line))
+                Ok(GlyphName::new(line).into())
             }
         })
         .collect::<Result<_, _>>()?;
@@ -163,4 +197,13 @@ mod tests {
         assert_eq!(glyph_map.get("e.fina"), Some(GlyphId16::new(214)));
         assert!(!glyph_map.contains("e.nada"));
     }
+
+    #[test]
+    fn load_cid_glyph_map() {
+        let glyph_map = parse_glyph_order(".notdef\n\\1\n\\2\n\\40\n").unwrap();
+        assert_eq!(glyph_map.len(), 4);
+        assert_eq!(glyph_map.get(&40u16), Some(GlyphId16::new(3)));
+        assert!(!glyph_map.contains(&3u16));
+        assert!(parse_glyph_order(".notdef\n\\abc\n").is_err());
+    }
 }
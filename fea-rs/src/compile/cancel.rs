@@ -0,0 +1,38 @@
+//! Aborting a compilation that is in progress.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// A flag that can be used to abort an in-progress compilation.
+///
+/// This is intended for hosts such as editors, which may want to give up on a
+/// compilation when the source changes. Clone the token, pass one copy to
+/// [`Compiler::with_cancellation`], and call [`cancel`] on the other; the
+/// compiler checks the token between top-level statements, and returns
+/// [`CompilerError::Cancelled`] with whatever diagnostics it has found so far.
+///
+/// [`Compiler::with_cancellation`]: super::Compiler::with_cancellation
+/// [`cancel`]: Self::cancel
+/// [`CompilerError::Cancelled`]: super::error::CompilerError::Cancelled
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token, which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that any compilation using this token stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// `true` if [`cancel`](Self::cancel) has been called on this token or
+    /// any of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
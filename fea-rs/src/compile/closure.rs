@@ -0,0 +1,200 @@
+//! Computing the closure of a set of glyphs over GSUB lookups
+//!
+//! The closure is computed the same way for a compiled GSUB table and for
+//! the lookups that are still being compiled: each kind of subtable, built or
+//! not, lists its rules through the [`SubstitutionRules`] trait.
+
+use std::collections::BTreeSet;
+
+use write_fonts::{
+    tables::{
+        gsub::{
+            AlternateSubstFormat1, ExtensionSubtable, LigatureSubstFormat1, MultipleSubstFormat1,
+            ReverseChainSingleSubstFormat1, SingleSubst, SubstitutionLookup,
+            builders::{
+                AlternateSubBuilder, LigatureSubBuilder, MultipleSubBuilder, SingleSubBuilder,
+            },
+        },
+        layout::{Lookup, builders::LookupBuilder},
+    },
+    types::GlyphId16,
+};
+
+/// The substitution rules of a GSUB lookup or subtable.
+pub(crate) trait SubstitutionRules {
+    /// Call `f` with each rule: the glyph it applies to, any other glyphs
+    /// that must also be present (the other components of a ligature), and
+    /// the glyphs it can produce.
+    ///
+    /// Contextual lookups have no rules of their own; the lookups they
+    /// reference are part of the lookup list.
+    #[allow(clippy::type_complexity)]
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16]));
+}
+
+/// Return `glyphs`, plus every glyph reachable from them through these lookups.
+///
+/// This is shared by [`Compilation::gsub_closure`], which passes the lookups of
+/// the compiled GSUB table, and [`FeatureBuilder::gsub_closure`], which passes
+/// the lookup builders.
+///
+/// The conditions of contextual lookups are not evaluated; the lookups they
+/// reference are part of the lookup list, and are applied unconditionally.
+/// The result is therefore a superset of the glyphs reachable when shaping.
+///
+/// [`Compilation::gsub_closure`]: super::Compilation::gsub_closure
+/// [`FeatureBuilder::gsub_closure`]: super::FeatureBuilder::gsub_closure
+pub(crate) fn gsub_closure<'a, T: SubstitutionRules + 'a>(
+    lookups: impl Iterator<Item = &'a T> + Clone,
+    glyphs: impl IntoIterator<Item = GlyphId16>,
+) -> BTreeSet<GlyphId16> {
+    let mut glyphs = glyphs.into_iter().collect::<BTreeSet<_>>();
+    loop {
+        let len = glyphs.len();
+        for lookup in lookups.clone() {
+            let mut new = Vec::new();
+            lookup.for_each_rule(&mut |target, others, replacements| {
+                if glyphs.contains(&target) && others.iter().all(|gid| glyphs.contains(gid)) {
+                    new.extend_from_slice(replacements);
+                }
+            });
+            glyphs.extend(new);
+        }
+        if glyphs.len() == len {
+            return glyphs;
+        }
+    }
+}
+
+impl SubstitutionRules for SubstitutionLookup {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        match self {
+            SubstitutionLookup::Single(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Multiple(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Alternate(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Ligature(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Reverse(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Extension(lookup) => {
+                for sub in lookup.subtables.iter() {
+                    match &**sub {
+                        ExtensionSubtable::Single(ext) => ext.extension.for_each_rule(f),
+                        ExtensionSubtable::Multiple(ext) => ext.extension.for_each_rule(f),
+                        ExtensionSubtable::Alternate(ext) => ext.extension.for_each_rule(f),
+                        ExtensionSubtable::Ligature(ext) => ext.extension.for_each_rule(f),
+                        ExtensionSubtable::Reverse(ext) => ext.extension.for_each_rule(f),
+                        ExtensionSubtable::Contextual(_)
+                        | ExtensionSubtable::ChainContextual(_) => (),
+                    }
+                }
+            }
+            SubstitutionLookup::Contextual(_) | SubstitutionLookup::ChainContextual(_) => (),
+        }
+    }
+}
+
+impl<T: SubstitutionRules> SubstitutionRules for Lookup<T> {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for sub in self.subtables.iter() {
+            sub.for_each_rule(f);
+        }
+    }
+}
+
+impl<T: SubstitutionRules> SubstitutionRules for LookupBuilder<T> {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for sub in self.subtables.iter() {
+            sub.for_each_rule(f);
+        }
+    }
+}
+
+impl SubstitutionRules for SingleSubst {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        match self {
+            SingleSubst::Format1(sub) => {
+                for gid in sub.coverage.iter() {
+                    let replacement =
+                        GlyphId16::new(gid.to_u16().wrapping_add_signed(sub.delta_glyph_id));
+                    f(gid, &[], &[replacement]);
+                }
+            }
+            SingleSubst::Format2(sub) => {
+                for (gid, replacement) in sub.coverage.iter().zip(sub.substitute_glyph_ids.iter()) {
+                    f(gid, &[], std::slice::from_ref(replacement));
+                }
+            }
+        }
+    }
+}
+
+impl SubstitutionRules for MultipleSubstFormat1 {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (gid, seq) in self.coverage.iter().zip(self.sequences.iter()) {
+            f(gid, &[], &seq.substitute_glyph_ids);
+        }
+    }
+}
+
+impl SubstitutionRules for AlternateSubstFormat1 {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (gid, set) in self.coverage.iter().zip(self.alternate_sets.iter()) {
+            f(gid, &[], &set.alternate_glyph_ids);
+        }
+    }
+}
+
+impl SubstitutionRules for LigatureSubstFormat1 {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (gid, set) in self.coverage.iter().zip(self.ligature_sets.iter()) {
+            for lig in set.ligatures.iter() {
+                f(
+                    gid,
+                    &lig.component_glyph_ids,
+                    std::slice::from_ref(&lig.ligature_glyph),
+                );
+            }
+        }
+    }
+}
+
+impl SubstitutionRules for ReverseChainSingleSubstFormat1 {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (gid, replacement) in self.coverage.iter().zip(self.substitute_glyph_ids.iter()) {
+            f(gid, &[], std::slice::from_ref(replacement));
+        }
+    }
+}
+
+impl SubstitutionRules for SingleSubBuilder {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (target, replacement) in self.iter() {
+            f(target, &[], &[replacement]);
+        }
+    }
+}
+
+impl SubstitutionRules for MultipleSubBuilder {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (target, replacement) in self.iter() {
+            f(*target, &[], replacement);
+        }
+    }
+}
+
+impl SubstitutionRules for AlternateSubBuilder {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (target, alternate) in self.iter_pairs() {
+            f(target, &[], &[alternate]);
+        }
+    }
+}
+
+impl SubstitutionRules for LigatureSubBuilder {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (first, ligatures) in self.iter() {
+            for (components, ligature) in ligatures.iter() {
+                f(*first, components, std::slice::from_ref(ligature));
+            }
+        }
+    }
+}
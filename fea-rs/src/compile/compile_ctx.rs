@@ -3,7 +3,7 @@
 //! This is the final stage of the pipeline, which walks the parsed and validated
 //! AST and generates the output.
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryInto,
     ops::Range,
 };
@@ -12,7 +12,6 @@ use fontdrasil::coords::{DesignCoord, NormalizedCoord, NormalizedLocation, UserC
 use smol_str::SmolStr;
 use write_fonts::{
     tables::{
-        self,
         gdef::GlyphClassDef,
         gpos::{
             ValueFormat,
@@ -21,18 +20,18 @@ use write_fonts::{
             },
         },
         layout::{
-            ConditionFormat1, ConditionSet, FeatureVariations, LookupFlag,
+            ConditionFormat1, ConditionSet, LookupFlag,
             builders::{CaretValueBuilder as CaretValue, DeviceOrDeltas, Metric},
         },
-        variations::ivs_builder::{RemapVariationIndices, VariationStoreBuilder},
+        variations::VariationRegion,
     },
     types::{F2Dot14, NameId, Tag},
 };
 
 use crate::{
-    Diagnostic, GlyphIdent, GlyphMap, Kind, NodeOrToken, Opts,
-    common::{GlyphClass, GlyphId16, GlyphOrClass, GlyphSet, MarkClass},
-    parse::SourceMap,
+    Diagnostic, DiagnosticCode, GlyphIdent, GlyphMap, Kind, NodeOrToken, Opts, ParseTree,
+    common::{GlyphClass, GlyphClassInterner, GlyphId16, GlyphOrClass, MarkClass, SharedGlyphSet},
+    parse::{LineCol, SourceList, SourceMap},
     token_tree::{
         Token,
         typed::{self, AstNode},
@@ -41,16 +40,18 @@ use crate::{
 };
 
 use super::{
-    VariationInfo,
+    CancellationToken, VariationInfo,
+    error::CompileError,
     feature_writer::{FeatureBuilder, FeatureProvider, InsertionPoint},
     features::{
         AaltFeature, ActiveFeature, AllFeatures, ConditionSetMap, CvParams, SizeFeature,
         SpecialVerticalFeatureState,
     },
-    glyph_range,
+    glyph_class, glyph_range,
     language_system::{DefaultLanguageSystems, LanguageSystem},
-    lookups::{AllLookups, FilterSetId, LookupFlagInfo, LookupId, SomeLookup},
-    output::Compilation,
+    lookups::{AllLookups, FilterSetId, LookupDebugInfo, LookupFlagInfo, LookupId, SomeLookup},
+    output::{AnonBlock, Compilation, DebugLookups, UnknownTable},
+    state::CompilationState,
     tables::{GlyphClassDefExt, ScriptRecord, Tables},
     tags,
 };
@@ -72,6 +73,7 @@ pub struct CompilationCtx<'a, F: FeatureProvider, V: VariationInfo> {
     glyph_map: &'a GlyphMap,
     reverse_glyph_map: BTreeMap<GlyphId16, GlyphIdent>,
     source_map: &'a SourceMap,
+    sources: &'a SourceList,
     variation_info: Option<&'a V>,
     feature_writer: Option<&'a F>,
     opts: Opts,
@@ -87,48 +89,106 @@ pub struct CompilationCtx<'a, F: FeatureProvider, V: VariationInfo> {
     /// Manages adding rules to lookups and assigning `LookupId`s during compilation
     lookups: AllLookups,
     /// Tracks the lookup flags state
+    ///
+    /// This matches feaLib: the flags are reset at the start and end of each
+    /// feature block, at each `script` statement, and at the start and end of
+    /// standalone lookup blocks. A lookup block inside a feature inherits the
+    /// feature's flags, and any flags it sets remain in effect after it ends.
     lookup_flags: LookupFlagInfo,
     active_feature: Option<ActiveFeature>,
     vertical_feature: SpecialVerticalFeatureState,
     script: Option<Tag>,
     glyph_class_defs: HashMap<SmolStr, GlyphClass>,
+    // every class resolved during compilation, so that identical classes
+    // share storage and their glyph sets are only built once.
+    glyph_classes: GlyphClassInterner,
+    // glyph class literals that were resolved ahead of time on the rayon
+    // thread pool, keyed by their position.
+    resolved_class_literals: HashMap<usize, Vec<GlyphId16>>,
     mark_classes: HashMap<SmolStr, MarkClass>,
     anchor_defs: HashMap<SmolStr, (Anchor, usize)>,
     value_record_defs: HashMap<SmolStr, ValueRecord>,
+    // every variable metric resolved so far, keyed by its sorted locations.
+    // kerning often repeats the same values, and this means each is only
+    // solved once and always produces the same deltas, which then share a
+    // row in the variation store.
+    variable_metrics: HashMap<Vec<(NormalizedLocation, i16)>, Metric>,
     conditionset_defs: ConditionSetMap,
-    mark_attach_class_id: HashMap<GlyphSet, u16>,
-    mark_filter_sets: HashMap<GlyphSet, FilterSetId>,
+    // keyed on the interned sets, so finding a set neither rehashes nor copies its glyphs
+    mark_attach_class_id: HashMap<SharedGlyphSet, u16>,
+    mark_filter_sets: HashMap<SharedGlyphSet, FilterSetId>,
     // feature blocks can include `# Automatic Code` comments that instruct us
     // on where we should merge in code generated from an external provider.
     // when we encounter these we record what lookup id would be logically next,
     // and we will use that for the generated lookups.
     // We also store the start pos of the comment, to break ties.
     insert_markers: HashMap<Tag, InsertionPoint>,
+    anon_blocks: Vec<AnonBlock>,
+    unknown_tables: Vec<UnknownTable>,
+    // the first rule for each target in the current lookup, so that we can
+    // report later rules that conflict with it. This is reset whenever a
+    // rule is added to a different lookup.
+    rule_sites: HashMap<RuleTarget, (Range<usize>, RuleValue)>,
+    rule_sites_lookup: Option<LookupId>,
+    // the number of components of each ligature glyph in a mark-to-ligature
+    // rule, and where it was first given.
+    lig_component_counts: HashMap<GlyphId16, (usize, Range<usize>)>,
+    // the outputs of ligature substitutions, used when inferring glyph classes
+    gsub_ligature_glyphs: BTreeSet<GlyphId16>,
+    // where the STAT table's ElidedFallbackNameID was given, so that we can
+    // report it if it isn't in the name table
+    stat_fallback_name_id: Option<Range<usize>>,
+    // the start of the top-level or block statement being resolved
+    statement_start: usize,
+    pub(crate) cancellation: Option<CancellationToken>,
+}
+
+/// The target of a rule, used to detect conflicting rules in a lookup.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum RuleTarget {
+    Sequence(Vec<GlyphId16>),
+    Pair(GlyphId16, GlyphId16),
+}
+
+/// The value of a rule, used to detect conflicting rules in a lookup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RuleValue {
+    Glyphs(Vec<GlyphId16>),
+    Single(ValueRecord),
+    Pair(ValueRecord, ValueRecord),
 }
 
 impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     pub(crate) fn new(
         glyph_map: &'a GlyphMap,
-        source_map: &'a SourceMap,
+        tree: &'a ParseTree,
         variation_info: Option<&'a V>,
         feature_writer: Option<&'a F>,
         opts: Opts,
     ) -> Self {
         CompilationCtx {
             glyph_map,
-            reverse_glyph_map: glyph_map.reverse_map(),
-            source_map,
+            reverse_glyph_map: if opts.glyph_aliases_in_diagnostics {
+                glyph_map.reverse_map_with_aliases()
+            } else {
+                glyph_map.reverse_map()
+            },
+            source_map: tree.source_map(),
+            sources: &tree.sources,
             variation_info,
             feature_writer,
             errors: Vec::new(),
             tables: Tables::default(),
             default_lang_systems: Default::default(),
             glyph_class_defs: Default::default(),
-            lookups: Default::default(),
+            glyph_classes: Default::default(),
+            resolved_class_literals: Default::default(),
+            lookups: AllLookups::new(&opts),
             features: Default::default(),
             mark_classes: Default::default(),
             anchor_defs: Default::default(),
             value_record_defs: Default::default(),
+            variable_metrics: Default::default(),
             conditionset_defs: Default::default(),
             lookup_flags: Default::default(),
             active_feature: Default::default(),
@@ -138,6 +198,15 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             mark_filter_sets: Default::default(),
             opts,
             insert_markers: Default::default(),
+            anon_blocks: Default::default(),
+            unknown_tables: Default::default(),
+            rule_sites: Default::default(),
+            rule_sites_lookup: None,
+            lig_component_counts: Default::default(),
+            gsub_ligature_glyphs: Default::default(),
+            stat_fallback_name_id: None,
+            statement_start: 0,
+            cancellation: None,
         }
     }
 
@@ -146,35 +215,54 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     /// Walks the statements in the AST in order, accumulating state and any
     /// errors encountered.
     pub(crate) fn compile(&mut self, node: &typed::Root) {
+        #[cfg(feature = "parallel")]
+        if self.opts.parallel {
+            self.resolved_class_literals = resolve_class_literals_parallel(node, self.glyph_map);
+        }
         for item in node.statements() {
-            if let Some(language_system) = typed::LanguageSystem::cast(item) {
+            if self.is_cancelled() {
+                return;
+            }
+            if let Some(language_system) = typed::LanguageSystem::cast(&item) {
                 self.add_language_system(language_system);
-            } else if let Some(class_def) = typed::GlyphClassDef::cast(item) {
+            } else if let Some(class_def) = typed::GlyphClassDef::cast(&item) {
                 self.define_glyph_class(class_def);
-            } else if let Some(mark_def) = typed::MarkClassDef::cast(item) {
+            } else if let Some(mark_def) = typed::MarkClassDef::cast(&item) {
                 self.define_mark_class(mark_def);
-            } else if let Some(anchor_def) = typed::AnchorDef::cast(item) {
+            } else if let Some(anchor_def) = typed::AnchorDef::cast(&item) {
                 self.define_named_anchor(anchor_def);
-            } else if let Some(item) = typed::ValueRecordDef::cast(item) {
+            } else if let Some(item) = typed::ValueRecordDef::cast(&item) {
                 self.define_named_value_record(item);
-            } else if let Some(node) = typed::ConditionSet::cast(item) {
+            } else if let Some(node) = typed::ConditionSet::cast(&item) {
                 self.define_condition_set(node);
-            } else if let Some(feature) = typed::Feature::cast(item) {
+            } else if let Some(feature) = typed::Feature::cast(&item) {
                 self.add_feature(feature);
-            } else if let Some(node) = typed::FeatureVariation::cast(item) {
+            } else if let Some(node) = typed::FeatureVariation::cast(&item) {
                 self.add_feature_variation(node);
-            } else if let Some(lookup) = typed::LookupBlock::cast(item) {
+            } else if let Some(lookup) = typed::LookupBlock::cast(&item) {
                 self.resolve_lookup_block(lookup);
-            } else if item.kind() == Kind::AnonBlockNode {
-                // noop
-            } else if let Some(table) = typed::Table::cast(item) {
+            } else if let Some(block) = typed::AnonBlock::cast(&item) {
+                // a block without a label has already been reported by the parser
+                if let Some(label) = block.label() {
+                    self.anon_blocks.push(AnonBlock {
+                        tag: label.text.to_string(),
+                        contents: block.contents(),
+                    });
+                }
+            } else if let Some(table) = typed::Table::cast(&item) {
                 self.resolve_table(table);
             } else if !item.kind().is_trivia() {
-                let span = get_reasonable_length_span(item);
-                self.error(span, format!("unhandled top-level item: '{}'", item.kind()));
+                let span = get_reasonable_length_span(&item);
+                self.error(
+                    DiagnosticCode::Internal,
+                    span,
+                    format!("unhandled top-level item: '{}'", item.kind()),
+                );
             }
         }
 
+        self.check_stat_fallback_name();
+
         // NOTE: this is the easiest place for us to do this, but we
         // could potentially be more performant by running this in parallel,
         // immediately after parsing?
@@ -186,110 +274,84 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         self.features.dedupe_lookups();
     }
 
+    /// `true` if our cancellation token has been cancelled.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
     pub(crate) fn build(&mut self) -> Result<(Compilation, Vec<Diagnostic>), Vec<Diagnostic>> {
         if self.errors.iter().any(Diagnostic::is_error) {
             return Err(self.errors.clone());
         }
+        Ok((self.take_state().build(), self.errors.clone()))
+    }
 
-        let mut name_builder = self.tables.name.clone();
-        let stat = self
-            .tables
-            .stat
-            .as_ref()
-            .map(|raw| raw.build(&mut name_builder));
-
-        // the var store builder is required so that variable metrics/anchors
-        // in the GPOS table can be collected into an ItemVariationStore
-        let axis_count = self
-            .variation_info
-            .map(|info| info.axis_count())
-            .unwrap_or_default();
-        let mut ivs = VariationStoreBuilder::new(axis_count);
-
-        let (mut gsub, mut gpos) = self.lookups.build(&self.features, &mut ivs, &self.opts);
-        // if ivs hasn't been used, we don't want to create a GDEF table just for it.
-        if !ivs.is_empty() {
-            self.tables
-                .gdef
-                .get_or_insert_with(|| {
-                    // If we're creating a new GdefBuilder here, it means
-                    // finalize_gdef_table() discarded the previous one because
-                    // it was empty. That means no explicit glyph classes were
-                    // declared in the FEA, so mark the classes as inferred.
-                    // https://github.com/googlefonts/fontc/issues/1847
-                    super::tables::GdefBuilder {
-                        glyph_classes_were_inferred: true,
-                        ..Default::default()
-                    }
-                })
-                .var_store = Some(ivs);
-        // but if we _do_ have a gdef table, always add the var store,
-        // since we might still add ligature carets to it
-        } else if let Some(gdef) = self.tables.gdef.as_mut() {
-            gdef.var_store = Some(ivs);
+    /// Take the state needed to build the tables, leaving this context empty.
+    pub(crate) fn take_state(&mut self) -> CompilationState {
+        let debug_lookups = self.opts.debug_table.then(|| self.debug_table());
+        CompilationState {
+            opts: self.opts.clone(),
+            axis_count: self
+                .variation_info
+                .map(|info| info.axis_count())
+                .unwrap_or_default(),
+            tables: std::mem::take(&mut self.tables),
+            features: std::mem::take(&mut self.features),
+            lookups: std::mem::take(&mut self.lookups),
+            conditionset_defs: std::mem::take(&mut self.conditionset_defs),
+            insert_markers: std::mem::take(&mut self.insert_markers),
+            anon_blocks: std::mem::take(&mut self.anon_blocks),
+            unknown_tables: std::mem::take(&mut self.unknown_tables),
+            debug_lookups,
         }
+    }
 
-        let (gdef, key_map) = match self.tables.gdef.as_ref().map(|raw| raw.build()) {
-            Some((gdef, key_map)) => (Some(gdef), key_map),
-            None => (None, None),
+    /// Generate the entries of a `Debg` table.
+    ///
+    /// This uses the format written by feaLib, recording for each lookup the
+    /// location in the FEA where it was defined, its name (if it was a named
+    /// lookup) and a feature that uses it, as `[script, language, feature]`.
+    fn debug_table(&self) -> DebugLookups {
+        let location = |site: usize| {
+            let (file, range) = self.source_map.resolve_range(site..site);
+            let source = self.sources.get(&file).expect("all sources are known");
+            let LineCol { line, col } = source.line_index().line_col(range.start);
+            format!("{}:{}:{}", source.path().display(), line + 1, col + 1)
         };
-
-        let feature_params = self.features.build_feature_params(&mut name_builder);
-
-        if let Some(gsub) = gsub.as_mut() {
-            if let Some(variations) = gsub.feature_variations.as_mut() {
-                sort_feature_variations(variations, |condset| {
-                    self.conditionset_defs.sort_order(condset)
-                });
-            }
-            for record in gsub.feature_list.feature_records.iter_mut() {
-                if let Some(params) = feature_params.get(&record.feature_tag) {
-                    record.feature.feature_params = params.clone().into();
-                }
-            }
-        }
-        if let Some(gpos) = gpos.as_mut() {
-            if let Some(key_map) = key_map {
-                // all VariationIndex tables (in value records and anchors)
-                // currently have temporary indices; now that we've built the
-                // ItemVariationStore we need to go and update them all.
-                gpos.remap_variation_indices(&key_map);
-            }
-            if let Some(variations) = gpos.feature_variations.as_mut() {
-                sort_feature_variations(variations, |condset| {
-                    self.conditionset_defs.sort_order(condset)
-                });
-            }
-
-            for record in gpos.feature_list.feature_records.iter_mut() {
-                if let Some(params) = feature_params.get(&record.feature_tag) {
-                    record.feature.feature_params = params.clone().into();
-                }
-            }
+        let (gsub, gpos) = self.lookups.debug_info(&self.features);
+        let entries = |lookups: Vec<LookupDebugInfo>| -> Vec<String> {
+            lookups
+                .into_iter()
+                .map(|info| {
+                    let location = info
+                        .site
+                        .map(|site| json_string(&location(site)))
+                        .unwrap_or_else(|| "null".into());
+                    let name = info
+                        .name
+                        .map(|name| json_string(&name))
+                        .unwrap_or_else(|| "null".into());
+                    let feature = info
+                        .feature
+                        .map(|(key, _)| {
+                            format!(
+                                "[{}, {}, {}]",
+                                json_string(&key.script.to_string()),
+                                json_string(&key.language.to_string()),
+                                json_string(&key.feature.to_string()),
+                            )
+                        })
+                        .unwrap_or_else(|| "null".into());
+                    format!("[{location}, {name}, {feature}]")
+                })
+                .collect()
+        };
+        DebugLookups {
+            gsub: entries(gsub),
+            gpos: entries(gpos),
         }
-
-        let gdef_classes = self.tables.gdef.as_ref().and_then(|gdef| {
-            (!gdef.glyph_classes_were_inferred).then(|| gdef.glyph_classes.clone())
-        });
-
-        Ok((
-            Compilation {
-                head: self.tables.head.as_ref().map(|raw| raw.build(None)),
-                hhea: self.tables.hhea.clone(),
-                vhea: self.tables.vhea.clone(),
-                os2: self.tables.os2.as_ref().map(|raw| raw.build()),
-                gdef,
-                base: self.tables.base.as_ref().map(|raw| raw.build()),
-                name: name_builder.build(),
-                stat,
-                gsub,
-                gpos,
-                opts: self.opts.clone(),
-                gdef_classes,
-                insert_markers: self.insert_markers.clone(),
-            },
-            self.errors.clone(),
-        ))
     }
 
     // returns the ligcaret values; we add them after finalizing gdef
@@ -300,6 +362,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
         let mut builder = FeatureBuilder::new(
             &self.default_lang_systems,
+            &self.lookups,
             &mut self.tables,
             &mut self.mark_filter_sets,
         );
@@ -331,11 +394,18 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         // if the FEA included a GDEF block, use that, otherwise create an empty table
         let mut gdef = self.tables.gdef.take().unwrap_or_default();
         // infer glyph classes, if they were not declared explicitly
-        if gdef.glyph_classes.is_empty() {
+        if gdef.glyph_classes.is_empty() && self.opts.infer_glyph_classes {
             gdef.glyph_classes_were_inferred = true;
             self.lookups.infer_glyph_classes(|glyph, class_id| {
                 gdef.glyph_classes.insert(glyph, class_id);
             });
+            if self.opts.infer_gsub_ligature_classes {
+                for glyph in self.gsub_ligature_glyphs.iter() {
+                    gdef.glyph_classes
+                        .entry(*glyph)
+                        .or_insert(GlyphClassDef::Ligature);
+                }
+            }
             for glyph in self
                 .mark_classes
                 .values()
@@ -347,10 +417,17 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
 
         if !self.mark_attach_class_id.is_empty() {
+            // insert in id order, so the result doesn't depend on hash order
+            let mut sorted = self
+                .mark_attach_class_id
+                .iter()
+                .map(|(cls, id)| (*id, cls))
+                .collect::<Vec<_>>();
+            sorted.sort_unstable_by_key(|(id, _)| *id);
             gdef.mark_attach_class.extend(
-                self.mark_attach_class_id
-                    .iter()
-                    .flat_map(|(cls, id)| cls.iter().map(|gid| (gid, *id))),
+                sorted
+                    .into_iter()
+                    .flat_map(|(id, cls)| cls.iter().map(move |gid| (gid, id))),
             );
         }
 
@@ -358,29 +435,123 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             let mut sorted = self
                 .mark_filter_sets
                 .iter()
-                .map(|(cls, id)| (*id, cls.clone()))
+                .map(|(cls, id)| (*id, cls))
                 .collect::<Vec<_>>();
-            sorted.sort_unstable();
-            gdef.mark_glyph_sets = sorted.into_iter().map(|(_, cls)| cls).collect();
+            sorted.sort_unstable_by_key(|(id, _)| *id);
+            gdef.mark_glyph_sets = sorted
+                .into_iter()
+                .map(|(_, cls)| cls.clone().into_inner())
+                .collect();
         }
 
         if gdef.ligature_pos.is_empty() {
             gdef.ligature_pos = generated_lig_carets;
         }
+        self.check_lig_caret_counts(&gdef.ligature_pos);
 
         if !gdef.is_empty() {
             self.tables.gdef = Some(gdef);
         }
     }
 
-    fn error(&mut self, range: Range<usize>, message: impl Into<String>) {
+    fn error(&mut self, code: DiagnosticCode, range: Range<usize>, message: impl Into<String>) {
         let (file, range) = self.source_map.resolve_range(range);
-        self.errors.push(Diagnostic::error(file, range, message));
+        self.errors
+            .push(Diagnostic::error(file, range, message).with_code(code));
     }
 
-    fn warning(&mut self, range: Range<usize>, message: impl Into<String>) {
+    fn warning(&mut self, code: DiagnosticCode, range: Range<usize>, message: impl Into<String>) {
         let (file, range) = self.source_map.resolve_range(range);
-        self.errors.push(Diagnostic::warning(file, range, message));
+        self.errors
+            .push(Diagnostic::warning(file, range, message).with_code(code));
+    }
+
+    /// Add a related location, such as an earlier rule, to the last error or
+    /// warning.
+    fn related(&mut self, range: Range<usize>, message: impl Into<String>) {
+        let (file, range) = self.source_map.resolve_range(range);
+        if let Some(diagnostic) = self.errors.last_mut() {
+            diagnostic.add_related(file, range, message);
+        }
+    }
+
+    fn report(&mut self, error: CompileError) {
+        self.error(error.code(), error.range(), error.to_string());
+    }
+
+    /// Report the error, if any, and return the default value in its place.
+    ///
+    /// Compilation fails if any error is reported, so the default is only
+    /// used so that we can keep going and find any other errors.
+    fn ok_or_report<T: Default>(&mut self, result: Result<T, CompileError>) -> T {
+        result.unwrap_or_else(|err| {
+            self.report(err);
+            T::default()
+        })
+    }
+
+    /// Returns `true` if a rule should be added to the current lookup.
+    ///
+    /// If an earlier rule in the lookup has the same target but a different
+    /// value, we keep the earlier rule and warn, matching feaLib.
+    fn check_rule_conflict(
+        &mut self,
+        range: Range<usize>,
+        target: RuleTarget,
+        value: RuleValue,
+    ) -> bool {
+        let Some(lookup_id) = self.lookups.current_id() else {
+            return true;
+        };
+        if self.rule_sites_lookup != Some(lookup_id) {
+            self.rule_sites_lookup = Some(lookup_id);
+            self.rule_sites.clear();
+        }
+        let Some((prev_range, prev_value)) = self.rule_sites.get(&target) else {
+            self.rule_sites.insert(target, (range, value));
+            return true;
+        };
+        if *prev_value == value {
+            return true;
+        }
+        let names = match &target {
+            RuleTarget::Sequence(glyphs) => glyphs.clone(),
+            RuleTarget::Pair(one, two) => vec![*one, *two],
+        }
+        .into_iter()
+        .map(|gid| self.glyph_name(gid))
+        .collect::<Vec<_>>()
+        .join(" ");
+        let prev_location = self.describe_location(prev_range.clone(), range.clone());
+        let prev_range = prev_range.clone();
+        self.warning(
+            DiagnosticCode::ConflictingRule,
+            range,
+            format!("conflicting rule for '{names}', keeping the earlier rule at {prev_location}"),
+        );
+        self.related(prev_range, "earlier rule");
+        false
+    }
+
+    // the name of a glyph, for use in diagnostics
+    fn glyph_name(&self, glyph: GlyphId16) -> String {
+        match self.reverse_glyph_map.get(&glyph) {
+            Some(name) => name.to_string(),
+            None => format!("GID {}", glyph.to_u16()),
+        }
+    }
+
+    // a human-readable location, with the path only if it differs from that of `relative_to`
+    fn describe_location(&self, range: Range<usize>, relative_to: Range<usize>) -> String {
+        let (file, range) = self.source_map.resolve_range(range);
+        let (other_file, _) = self.source_map.resolve_range(relative_to);
+        let source = self.sources.get(&file).unwrap();
+        let (line, col) = source.line_col_for_offset(range.start);
+        if file == other_file {
+            format!("{line}:{col}")
+        } else {
+            format!("{}:{line}:{col}", source.path().display())
+        }
     }
 
     fn add_language_system(&mut self, language_system: typed::LanguageSystem) {
@@ -421,7 +592,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         self.script = None;
     }
 
-    fn start_lookup_block(&mut self, name: &Token) {
+    fn start_lookup_block(&mut self, name: &Token, use_extension: bool) {
         if let Some((id, _name)) = self.lookups.finish_current() {
             assert!(_name.is_none(), "lookup blocks cannot be nested");
             self.add_lookup_to_current_feature_if_present(id);
@@ -432,7 +603,8 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
 
         self.vertical_feature.begin_lookup_block();
-        self.lookups.start_named(name.text.clone());
+        self.lookups
+            .start_named(name.text.clone(), use_extension, self.statement_start);
     }
 
     fn end_lookup_block(&mut self) {
@@ -508,7 +680,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn set_lookup_flag(&mut self, node: typed::LookupFlag) {
         self.lookup_flags.clear();
         if let Some(number) = node.number() {
-            let raw = number.parse_unsigned().unwrap() & 0xff;
+            let raw = self.resolve_unsigned(&number) & 0xff;
             // match fonttools in masking out the high parts:
             //https://github.com/fonttools/fonttools/blame/e89d7db4f4/Lib/fontTools/feaLib/builder.py#L1198
             self.lookup_flags.flags = LookupFlag::from_bits_truncate(raw);
@@ -526,53 +698,72 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 Kind::IgnoreLigaturesKw => flags |= LookupFlag::IGNORE_LIGATURES,
                 Kind::IgnoreMarksKw => flags |= LookupFlag::IGNORE_MARKS,
 
-                //FIXME: we are not enforcing some requirements here. in particular,
-                // The glyph sets of the referenced classes must not overlap, and the MarkAttachmentType statement can reference at most 15 different classes.
                 Kind::MarkAttachmentTypeKw => {
-                    let node = iter
-                        .next()
-                        .and_then(typed::GlyphClass::cast)
-                        .expect("validated");
+                    let Some(node) = iter.next().and_then(typed::GlyphClass::cast) else {
+                        self.report(CompileError::Missing {
+                            expected: "glyph class",
+                            range: next.range(),
+                        });
+                        continue;
+                    };
                     let mark_attach_set = self.resolve_mark_attach_class(&node);
                     flags.set_mark_attachment_class(mark_attach_set);
                 }
                 Kind::UseMarkFilteringSetKw => {
-                    let node = iter
-                        .next()
-                        .and_then(typed::GlyphClass::cast)
-                        .expect("validated");
+                    let Some(node) = iter.next().and_then(typed::GlyphClass::cast) else {
+                        self.report(CompileError::Missing {
+                            expected: "glyph class",
+                            range: next.range(),
+                        });
+                        continue;
+                    };
                     let filter_set = self.resolve_mark_filter_set(&node);
                     flags |= LookupFlag::USE_MARK_FILTERING_SET;
                     mark_filter_set = Some(filter_set);
                 }
-                other => unreachable!("mark statements have been validated: '{:?}'", other),
+                other => self.error(
+                    DiagnosticCode::Internal,
+                    next.range(),
+                    format!("unexpected lookupflag '{other}'"),
+                ),
             }
         }
         self.lookup_flags = LookupFlagInfo::new(flags, mark_filter_set);
     }
 
-    fn resolve_mark_attach_class(&mut self, glyphs: &typed::GlyphClass) -> u16 {
-        let glyphs = self.resolve_glyph_class(glyphs);
-        let mark_set = glyphs.to_glyph_set();
+    // validation ensures that the glyph sets of the referenced classes don't
+    // overlap. It also checks that there are at most 15 different classes, but
+    // we may be compiling without validation, so that is checked again here.
+    fn resolve_mark_attach_class(&mut self, node: &typed::GlyphClass) -> u16 {
+        const MAX_MARK_ATTACH_CLASSES: usize = 15;
+
+        let glyphs = self.resolve_glyph_class(node);
+        let mark_set = self.glyph_classes.glyph_set(&glyphs);
         if let Some(id) = self.mark_attach_class_id.get(&mark_set) {
             return *id;
         }
-
+        if self.mark_attach_class_id.len() == MAX_MARK_ATTACH_CLASSES {
+            self.error(
+                DiagnosticCode::LimitExceeded,
+                node.range(),
+                format!("at most {MAX_MARK_ATTACH_CLASSES} MarkAttachmentType classes are allowed"),
+            );
+            return 0;
+        }
         let id = self.mark_attach_class_id.len() as u16 + 1;
-        //FIXME: I don't understand what is not allowed here
-
         self.mark_attach_class_id.insert(mark_set, id);
         id
     }
 
     fn resolve_mark_filter_set(&mut self, glyphs: &typed::GlyphClass) -> u16 {
         let glyphs = self.resolve_glyph_class(glyphs);
-        let set = glyphs.to_glyph_set();
-        let id = self.mark_filter_sets.len();
-        *self
-            .mark_filter_sets
-            .entry(set)
-            .or_insert_with(|| id.try_into().unwrap())
+        let set = self.glyph_classes.glyph_set(&glyphs);
+        if let Some(id) = self.mark_filter_sets.get(&set) {
+            return *id;
+        }
+        let id = self.mark_filter_sets.len().try_into().unwrap();
+        self.mark_filter_sets.insert(set, id);
+        id
     }
 
     pub fn add_subtable_break(&mut self) {
@@ -581,12 +772,19 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
     }
 
+    fn current_lookup(&mut self) -> &mut SomeLookup {
+        self.lookups.current_mut().expect("rules have a lookup")
+    }
+
     fn ensure_current_lookup_type(&mut self, kind: Kind) -> &mut SomeLookup {
         if !self.lookups.has_current_kind(kind) || !self.lookups.has_same_flags(self.lookup_flags) {
             //FIXME: find another way of ensuring that named lookup blocks don't
             //contain mismatched rules
             //assert!(!self.lookups.is_named(), "ensure rule type in validation");
-            if let Some(lookup) = self.lookups.start_lookup(kind, self.lookup_flags) {
+            if let Some(lookup) =
+                self.lookups
+                    .start_lookup(kind, self.lookup_flags, self.statement_start)
+            {
                 self.add_lookup_to_current_feature_if_present(lookup);
             }
         }
@@ -623,7 +821,11 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             typed::GsubStatement::Type6(rule) => self.add_contextual_sub(&rule),
             typed::GsubStatement::Ignore(rule) => self.add_contextual_sub_ignore(&rule),
             typed::GsubStatement::Type8(rule) => self.add_reverse_contextual_sub(&rule),
-            _ => self.warning(node.range(), "unimplemented rule type"),
+            _ => self.warning(
+                DiagnosticCode::Unsupported,
+                node.range(),
+                "unimplemented rule type",
+            ),
         }
     }
 
@@ -638,36 +840,51 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             // This is explicitly forbidden in the OpenType spec, and
             // explicitly encouraged in the FEA spec, and everyone else does it.
             // see https://github.com/adobe-type-tools/afdko/issues/1438
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
+            self.ensure_current_lookup_type(Kind::GsubType2);
             for target in target.iter() {
-                lookup.add_gsub_type_2(target, vec![]);
-            }
-        } else if self.lookups.has_current_kind(Kind::GsubType2)
-            && self.lookups.has_same_flags(self.lookup_flags)
-        {
-            // we combine chains of mixed single & multi-sub rules into multi-sub lookups
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
-            for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
-                lookup.add_gsub_type_2(target, vec![replacement]);
-            }
-        } else if self.lookups.has_current_kind(Kind::GsubType4)
-            && self.lookups.has_same_flags(self.lookup_flags)
-        {
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType4);
-            for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
-                if lookup.add_gsub_type_4(vec![target], replacement) {
-                    self.error(node.range(), "Ligature substitution shadows existing rule");
-                    return;
+                if self.check_sub_rule_conflict(node.range(), vec![target], vec![]) {
+                    self.current_lookup().add_gsub_type_2(target, vec![]);
                 }
             }
-        } else {
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType1);
-            for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
-                lookup.add_gsub_type_1(target, replacement);
+            return;
+        }
+
+        // we combine chains of mixed single & multi-sub or ligature-sub rules
+        // into multi-sub or ligature-sub lookups
+        let kind = [Kind::GsubType2, Kind::GsubType4]
+            .into_iter()
+            .find(|kind| {
+                self.lookups.has_current_kind(*kind)
+                    && self.lookups.has_same_flags(self.lookup_flags)
+            })
+            .unwrap_or(Kind::GsubType1);
+        self.ensure_current_lookup_type(kind);
+        for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
+            if !self.check_sub_rule_conflict(node.range(), vec![target], vec![replacement]) {
+                continue;
+            }
+            let lookup = self.current_lookup();
+            match kind {
+                Kind::GsubType2 => lookup.add_gsub_type_2(target, vec![replacement]),
+                Kind::GsubType4 => lookup.add_gsub_type_4(vec![target], replacement),
+                _ => lookup.add_gsub_type_1(target, replacement),
             }
         }
     }
 
+    fn check_sub_rule_conflict(
+        &mut self,
+        range: Range<usize>,
+        target: Vec<GlyphId16>,
+        replacement: Vec<GlyphId16>,
+    ) -> bool {
+        self.check_rule_conflict(
+            range,
+            RuleTarget::Sequence(target),
+            RuleValue::Glyphs(replacement),
+        )
+    }
+
     fn resolve_single_sub_glyphs(
         &mut self,
         node: &typed::Gsub1,
@@ -687,11 +904,19 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             .unwrap_or(GlyphOrClass::Null);
         match (target_ids, replace_ids) {
             (GlyphOrClass::Null, _) => {
-                self.error(target.range(), "NULL is not a valid substitution target");
+                self.error(
+                    DiagnosticCode::InvalidRule,
+                    target.range(),
+                    "NULL is not a valid substitution target",
+                );
                 None
             }
             (GlyphOrClass::Glyph(_), GlyphOrClass::Class(_)) => {
-                self.error(replace.unwrap().range(), "cannot sub glyph by glyph class");
+                self.error(
+                    DiagnosticCode::InvalidRule,
+                    replace.unwrap().range(),
+                    "cannot sub glyph by glyph class",
+                );
                 None
             }
             // treat singleton class as single glyph, per the spec:
@@ -704,6 +929,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             }
             (GlyphOrClass::Class(c1), GlyphOrClass::Class(c2)) if c1.len() != c2.len() => {
                 self.error(
+                    DiagnosticCode::InvalidRule,
                     replace.unwrap().range(),
                     format!(
                         "class has different length ({}) than target ({})",
@@ -730,6 +956,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             if item.is_class() && item.len() != target.len() {
                 let raw = node.replacement().nth(i).unwrap();
                 self.error(
+                    DiagnosticCode::InvalidRule,
                     raw.range(),
                     "replacement class must have same length as target",
                 );
@@ -743,9 +970,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         if self.lookups.has_same_flags(self.lookup_flags) {
             self.lookups.promote_single_sub_to_multi_if_necessary();
         }
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
+        self.ensure_current_lookup_type(Kind::GsubType2);
         for (i, target) in target.iter().enumerate() {
-            let replacement = replacements
+            let replacement: Vec<_> = replacements
                 .iter()
                 .filter_map(|r| match r {
                     GlyphOrClass::Glyph(gid) => Some(*gid),
@@ -754,15 +981,22 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 })
                 .collect();
 
-            lookup.add_gsub_type_2(target, replacement);
+            if self.check_sub_rule_conflict(node.range(), vec![target], replacement.clone()) {
+                self.current_lookup().add_gsub_type_2(target, replacement);
+            }
         }
     }
 
     fn add_alternate_sub(&mut self, node: &typed::Gsub3) {
         let target = self.resolve_glyph(&node.target());
-        let alts = self.resolve_glyph_class(&node.alternates());
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType3);
-        lookup.add_gsub_type_3(target, alts.iter().collect());
+        let alts: Vec<_> = self
+            .resolve_glyph_class(&node.alternates())
+            .iter()
+            .collect();
+        self.ensure_current_lookup_type(Kind::GsubType3);
+        if self.check_sub_rule_conflict(node.range(), vec![target], alts.clone()) {
+            self.current_lookup().add_gsub_type_3(target, alts);
+        }
     }
 
     fn add_ligature_sub(&mut self, node: &typed::Gsub4) {
@@ -775,12 +1009,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         if self.lookups.has_same_flags(self.lookup_flags) {
             self.lookups.promote_single_sub_to_liga_if_necessary();
         }
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType4);
+        self.ensure_current_lookup_type(Kind::GsubType4);
+        self.gsub_ligature_glyphs.insert(replacement);
 
         for target in sequence_enumerator(&target) {
-            if lookup.add_gsub_type_4(target, replacement) {
-                self.error(node.range(), "Ligature substitution shadows existing rule");
-                return;
+            if self.check_sub_rule_conflict(node.range(), target.clone(), vec![replacement]) {
+                self.current_lookup().add_gsub_type_4(target, replacement);
             }
         }
     }
@@ -798,11 +1032,18 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     .map(|inp| self.resolve_glyph_or_class(&inp.target()))
                     .collect::<Vec<_>>();
                 let mut iter = rule.replacements();
-                let replacement_node = iter.next().unwrap();
+                let Some(replacement_node) = iter.next() else {
+                    self.report(CompileError::Missing {
+                        expected: "replacement glyph",
+                        range: rule.range(),
+                    });
+                    return None;
+                };
                 let unexpected_extra_item = iter.next();
                 let replacement = match self.resolve_glyph_or_class(&replacement_node) {
                     _ if unexpected_extra_item.is_some() => {
                         self.error(
+                            DiagnosticCode::InvalidRule,
                             unexpected_extra_item.unwrap().range(),
                             "ligature sub accepts only single replacement glyph",
                         );
@@ -812,12 +1053,14 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     GlyphOrClass::Class(cls) if cls.len() == 1 => cls.iter().next().unwrap(),
                     _ => {
                         self.error(
+                            DiagnosticCode::InvalidRule,
                             replacement_node.range(),
                             "ligature sub accepts only single glyph as replacement",
                         );
                         return None;
                     }
                 };
+                self.gsub_ligature_glyphs.insert(replacement);
                 let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
                 let mut to_return = None;
                 for target in sequence_enumerator(&target) {
@@ -861,6 +1104,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                         if item.is_class() && item.len() != targets.len() {
                             let raw = rule.replacements().nth(i).unwrap();
                             self.error(
+                                DiagnosticCode::InvalidRule,
                                 raw.range(),
                                 "replacement class must have same length as target",
                             );
@@ -907,9 +1151,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 }
 
                 for lookup in item.lookups() {
-                    let id = self.lookups.get_named(&lookup.label().text).unwrap(); // validated already
+                    let Some(id) = self.resolve_lookup_name(&lookup.label()) else {
+                        continue;
+                    };
                     if matches!(id, LookupId::Gpos(_)) {
                         self.error(
+                            DiagnosticCode::InconsistentLookup,
                             lookup.label().range(),
                             "Invalid lookup: expected GSUB, found GPOS",
                         );
@@ -952,9 +1199,15 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn add_single_pos(&mut self, node: &typed::Gpos1) {
         let ids = self.resolve_glyph_or_class(&node.target());
         let record = self.resolve_value_record(&node.value());
-        let lookup = self.ensure_current_lookup_type(Kind::GposType1);
+        self.ensure_current_lookup_type(Kind::GposType1);
         for id in ids.iter() {
-            lookup.add_gpos_type_1(id, record.clone());
+            if self.check_rule_conflict(
+                node.range(),
+                RuleTarget::Sequence(vec![id]),
+                RuleValue::Single(record.clone()),
+            ) {
+                self.lookups.add_gpos_type_1(id, record.clone());
+            }
         }
     }
 
@@ -974,24 +1227,35 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             in_vert_feature,
         );
 
-        let lookup = self.ensure_current_lookup_type(Kind::GposType2);
+        self.ensure_current_lookup_type(Kind::GposType2);
 
         if (first_ids.is_class() || second_ids.is_class()) && node.enum_().is_none() {
-            lookup.add_gpos_type_2_class(
-                first_ids.to_class().unwrap().into(),
-                second_ids.to_class().unwrap().into(),
-                first_value,
-                second_value,
-            )
+            // glyph pairs take precedence over class pairs, so these never
+            // conflict with them. Conflicts with other class pair rules are
+            // deliberately not checked: overlapping classes start a new
+            // subtable, but the same pair of classes in one subtable is not
+            // reported.
+            let first = self.glyph_classes.glyph_set(&first_ids.to_class().unwrap());
+            let second = self
+                .glyph_classes
+                .glyph_set(&second_ids.to_class().unwrap());
+            self.lookups
+                .add_gpos_type_2_class(first, second, first_value, second_value)
         } else {
             for first in first_ids.iter() {
                 for second in second_ids.iter() {
-                    lookup.add_gpos_type_2_pair(
-                        first,
-                        second,
-                        first_value.clone(),
-                        second_value.clone(),
-                    );
+                    if self.check_rule_conflict(
+                        node.range(),
+                        RuleTarget::Pair(first, second),
+                        RuleValue::Pair(first_value.clone(), second_value.clone()),
+                    ) {
+                        self.lookups.add_gpos_type_2_pair(
+                            first,
+                            second,
+                            first_value.clone(),
+                            second_value.clone(),
+                        );
+                    }
                 }
             }
         }
@@ -1003,22 +1267,38 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         // will fail.
         let entry = self.resolve_anchor(&node.entry());
         let exit = self.resolve_anchor(&node.exit());
-        let lookup = self.ensure_current_lookup_type(Kind::GposType3);
+        self.ensure_current_lookup_type(Kind::GposType3);
         for id in ids.iter() {
-            lookup.add_gpos_type_3(id, entry.clone(), exit.clone())
+            self.lookups
+                .add_gpos_type_3(id, entry.clone(), exit.clone())
         }
     }
 
     fn add_mark_to_base(&mut self, node: &typed::Gpos4) {
         let base_ids = self.resolve_glyph_or_class(&node.base());
+        let bases = base_ids.iter().collect::<Vec<_>>();
         let _ = self.ensure_current_lookup_type(Kind::GposType4);
         for mark in node.attachments() {
             let base_anchor = self.resolve_anchor(&mark.anchor());
 
-            let mark_class_node = mark.mark_class_name().expect("checked in validation");
+            let Some(mark_class_node) = self.expect_mark_class_name(&mark) else {
+                continue;
+            };
             let class_name = mark_class_node.text().to_owned();
-            let mark_class = self.mark_classes.get(&class_name).unwrap();
+            let mark_class = match get_mark_class(&self.mark_classes, &mark_class_node) {
+                Ok(mark_class) => mark_class,
+                Err(err) => {
+                    self.report(err);
+                    continue;
+                }
+            };
 
+            self.lookups.reserve_mark_attachment(
+                &class_name,
+                mark_class,
+                &bases,
+                base_anchor.as_ref(),
+            );
             // access the lookup through the field, so the borrow checker
             // doesn't think we're borrowing all of self
             //TODO: we do validation here because our validation pass isn't smart
@@ -1028,22 +1308,17 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 .current_mut()
                 .unwrap()
                 .with_gpos_type_4(|subtable| {
-                    for (glyphs, mark_anchor) in &mark_class.members {
-                        let anchor = mark_anchor
-                            .as_ref()
-                            .expect("no null anchors in mark-to-base (check validation)");
+                    for (glyphs, anchor) in mark_class.anchored_members() {
                         for glyph in glyphs.iter() {
                             subtable.insert_mark(glyph, &class_name, anchor.clone())?;
                         }
                     }
+                    // a NULL base anchor leaves the offset for this class empty
                     for base in base_ids.iter() {
-                        subtable.insert_base(
-                            base,
-                            &class_name,
-                            base_anchor
-                                .clone()
-                                .expect("no null anchors in mark-to-base"),
-                        )
+                        match base_anchor.as_ref() {
+                            Some(anchor) => subtable.insert_base(base, &class_name, anchor.clone()),
+                            None => subtable.insert_null_base(base),
+                        }
                     }
                     Ok(())
                 });
@@ -1061,6 +1336,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         // to that component
 
         let mut components = Vec::new();
+        let mut mark_class_names = Vec::new();
         for component in node.ligature_components() {
             let _lookup = self.ensure_current_lookup_type(Kind::GposType5);
 
@@ -1077,24 +1353,31 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                         continue;
                     }
                 };
-                let component_anchor = component_anchor.unwrap();
                 let class_name = mark_class_node.text();
-                let mark_class = self.mark_classes.get(class_name).unwrap();
+                let mark_class = match get_mark_class(&self.mark_classes, &mark_class_node) {
+                    Ok(mark_class) => mark_class,
+                    Err(err) => {
+                        self.report(err);
+                        continue;
+                    }
+                };
+                mark_class_names.push(class_name.to_string());
 
                 // access the lookup through the field, so the borrow checker
                 // doesn't think we're borrowing all of self
                 //TODO: we do validation here because our validation pass isn't smart
                 //enough. We need to not just validate a rule, but every rule in a lookup.
-                anchor_records.insert(class_name.to_string(), component_anchor);
+                // a NULL anchor with a mark class still registers the class,
+                // but leaves the offset for this component empty
+                if let Some(component_anchor) = component_anchor {
+                    anchor_records.insert(class_name.to_string(), component_anchor);
+                }
                 let maybe_err = self
                     .lookups
                     .current_mut()
                     .unwrap()
                     .with_gpos_type_5(|subtable| {
-                        for (glyphs, mark_anchor) in &mark_class.members {
-                            let anchor = mark_anchor
-                                .as_ref()
-                                .expect("no null anchors on marks (check validation)");
+                        for (glyphs, anchor) in mark_class.anchored_members() {
                             for glyph in glyphs.iter() {
                                 subtable.insert_mark(glyph, class_name, anchor.clone())?;
                             }
@@ -1106,28 +1389,118 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             components.push(anchor_records);
         }
 
+        let range = node.base().range();
+        let base_ids = base_ids
+            .iter()
+            .filter(|base| self.check_lig_component_count(*base, components.len(), &range))
+            .collect::<Vec<_>>();
+        let mark_classes = mark_class_names
+            .iter()
+            .filter_map(|name| Some((name.as_str(), self.mark_classes.get(name.as_str())?)));
+        self.lookups
+            .reserve_mark_to_ligature(mark_classes, &base_ids, &components);
         self.lookups
             .current_mut()
             .unwrap()
             .with_gpos_type_5(|subtable| {
-                for base in base_ids.iter() {
-                    subtable.add_ligature_components_directly(base, components.clone());
+                for base in base_ids {
+                    // a ligature in an earlier rule of this subtable keeps the
+                    // anchors for the other mark classes
+                    if subtable.lig_glyphs().any(|gid| gid == base) {
+                        let classes = components
+                            .iter()
+                            .flat_map(|anchors| anchors.keys())
+                            .collect::<BTreeSet<_>>();
+                        for class in classes {
+                            let anchors = components
+                                .iter()
+                                .map(|anchors| anchors.get(class).cloned())
+                                .collect();
+                            subtable.insert_ligature(base, class, anchors);
+                        }
+                    } else {
+                        subtable.add_ligature_components_directly(base, components.clone());
+                    }
                 }
             })
     }
 
+    /// Returns `true` if `n_components` is consistent with any earlier
+    /// mark-to-ligature rule for this ligature glyph.
+    fn check_lig_component_count(
+        &mut self,
+        glyph: GlyphId16,
+        n_components: usize,
+        range: &Range<usize>,
+    ) -> bool {
+        let Some((prev_count, prev_range)) = self.lig_component_counts.get(&glyph).cloned() else {
+            self.lig_component_counts
+                .insert(glyph, (n_components, range.clone()));
+            return true;
+        };
+        if prev_count == n_components {
+            return true;
+        }
+        let name = self.glyph_name(glyph);
+        let prev_location = self.describe_location(prev_range.clone(), range.clone());
+        let message = format!(
+            "ligature '{name}' has {n_components} components, \
+             but {prev_count} in the rule at {prev_location}"
+        );
+        self.error(DiagnosticCode::ConflictingRule, range.clone(), message);
+        self.related(prev_range, "earlier rule");
+        false
+    }
+
+    /// Warn if the ligature carets in GDEF disagree with the number of
+    /// components used in mark-to-ligature rules.
+    fn check_lig_caret_counts(&mut self, ligature_pos: &BTreeMap<GlyphId16, Vec<CaretValue>>) {
+        let mut mismatched = self
+            .lig_component_counts
+            .iter()
+            .filter_map(|(gid, (count, range))| {
+                let carets = ligature_pos.get(gid)?;
+                (carets.len() + 1 != *count).then(|| (range.clone(), *gid, *count, carets.len()))
+            })
+            .collect::<Vec<_>>();
+        mismatched.sort_by_key(|(range, gid, ..)| (range.start, *gid));
+        for (range, gid, count, n_carets) in mismatched {
+            let name = self.glyph_name(gid);
+            let message = format!(
+                "ligature '{name}' has {count} components, \
+                 but {n_carets} ligature carets in GDEF"
+            );
+            self.warning(DiagnosticCode::ConflictingRule, range, message);
+        }
+    }
+
     //FIXME: this is basically identical to type 4, but the validation stuff
     //makes it all a big PITA. when we have better validation, we can probably improve this
     //significantly.
     fn add_mark_to_mark(&mut self, node: &typed::Gpos6) {
         let base_ids = self.resolve_glyph_or_class(&node.base());
+        let bases = base_ids.iter().collect::<Vec<_>>();
         let _ = self.ensure_current_lookup_type(Kind::GposType6);
         for mark in node.attachments() {
             let base_anchor = self.resolve_anchor(&mark.anchor());
-            let mark_class_node = mark.mark_class_name().expect("checked in validation");
+            let Some(mark_class_node) = self.expect_mark_class_name(&mark) else {
+                continue;
+            };
             let class_name = mark_class_node.text();
-            let mark_class = self.mark_classes.get(mark_class_node.text()).unwrap();
+            let mark_class = match get_mark_class(&self.mark_classes, &mark_class_node) {
+                Ok(mark_class) => mark_class,
+                Err(err) => {
+                    self.report(err);
+                    continue;
+                }
+            };
 
+            self.lookups.reserve_mark_attachment(
+                class_name,
+                mark_class,
+                &bases,
+                base_anchor.as_ref(),
+            );
             //TODO: we do validation here because our validation pass isn't smart
             //enough. We need to not just validate a rule, but every rule in a lookup.
             let maybe_err = self
@@ -1135,22 +1508,17 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 .current_mut()
                 .unwrap()
                 .with_gpos_type_6(|subtable| {
-                    for (glyphs, mark_anchor) in &mark_class.members {
-                        let anchor = mark_anchor
-                            .as_ref()
-                            .expect("no null anchors in mark-to-mark (check validation)");
+                    for (glyphs, anchor) in mark_class.anchored_members() {
                         for glyph in glyphs.iter() {
                             subtable.insert_mark1(glyph, class_name, anchor.clone())?;
                         }
                     }
+                    // a NULL base anchor leaves the offset for this class empty
                     for base in base_ids.iter() {
-                        subtable.insert_mark2(
-                            base,
-                            class_name,
-                            base_anchor
-                                .clone()
-                                .expect("no null anchors in mark-to-mark"),
-                        );
+                        match base_anchor.as_ref() {
+                            Some(anchor) => subtable.insert_mark2(base, class_name, anchor.clone()),
+                            None => subtable.insert_null_mark2(base),
+                        }
                     }
                     Ok(())
                 });
@@ -1165,6 +1533,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     ) {
         if let Some(PreviouslyAssignedClass { class, .. }) = maybe_err {
             self.error(
+                DiagnosticCode::ClassConflict,
                 range,
                 format!("mark class includes glyph in class '{class}', already used in lookup.",),
             );
@@ -1197,9 +1566,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 }
 
                 for lookup in item.lookups() {
-                    let id = self.lookups.get_named(&lookup.label().text).unwrap();
+                    let Some(id) = self.resolve_lookup_name(&lookup.label()) else {
+                        continue;
+                    };
                     if matches!(id, LookupId::Gsub(_)) {
                         self.error(
+                            DiagnosticCode::InconsistentLookup,
                             lookup.label().range(),
                             "Invalid lookup type: expected GPOS, found GSUB",
                         );
@@ -1250,11 +1622,15 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
 
         if let Some(name) = record.named() {
-            return self
+            let result = self
                 .value_record_defs
                 .get(name.as_str())
                 .cloned()
-                .expect("checked in validation");
+                .ok_or_else(|| CompileError::UndefinedValueRecord {
+                    name: name.text.clone(),
+                    range: name.range(),
+                });
+            return self.ok_or_report(result);
         }
 
         if let Some(adv) = record.advance() {
@@ -1318,6 +1694,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn resolve_variable_metric(&mut self, metric: &typed::VariableMetric) -> Metric {
         let Some(var_info) = self.variation_info else {
             self.error(
+                DiagnosticCode::RequiresVariableFont,
                 metric.range(),
                 "variable metric only valid when compiling variable font",
             );
@@ -1329,8 +1706,13 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             let mut pos = NormalizedLocation::new();
             for axis_value in metric_loc.location().items() {
                 let tag = axis_value.axis_tag().to_raw();
-                // All the tags are valid if we made it here, safe to unwrap
-                let (_, axis) = var_info.axis(tag).unwrap();
+                let Some((_, axis)) = var_info.axis(tag) else {
+                    self.report(CompileError::UnknownAxis {
+                        tag,
+                        range: axis_value.axis_tag().range(),
+                    });
+                    return Default::default();
+                };
                 let coord = match axis_value.value().parse() {
                     super::AxisLocation::Normalized(value) => NormalizedCoord::new(value),
                     super::AxisLocation::User(value) => {
@@ -1344,13 +1726,26 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             }
             locations.insert(pos, metric_loc.value().parse_signed());
         }
+        let mut key = locations
+            .iter()
+            .map(|(pos, value)| (pos.clone(), *value))
+            .collect::<Vec<_>>();
+        key.sort_unstable();
+        if let Some(resolved) = self.variable_metrics.get(&key) {
+            return resolved.clone();
+        }
         match var_info.resolve_variable_metric(&locations) {
-            Ok((default, deltas)) => Metric {
-                default,
-                device_or_deltas: DeviceOrDeltas::Deltas(deltas),
-            },
+            Ok((default, deltas)) => {
+                let resolved = metric_with_deltas(default, deltas);
+                self.variable_metrics.insert(key, resolved.clone());
+                resolved
+            }
             Err(e) => {
-                self.error(metric.range(), format!("failed to compute deltas: '{e}'"));
+                self.error(
+                    DiagnosticCode::InvalidVariation,
+                    metric.range(),
+                    format!("failed to compute deltas: '{e}'"),
+                );
                 Default::default()
             }
         }
@@ -1359,6 +1754,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn resolve_glyphs_number_value(&mut self, number_value: &typed::GlyphsAppNumber) -> Metric {
         let Some(var_info) = self.variation_info else {
             self.error(
+                DiagnosticCode::RequiresVariableFont,
                 number_value.range(),
                 "glyphsapp number value only valid when compiling variable font",
             );
@@ -1387,12 +1783,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         let locations = var_info.resolve_variable_metric(&locations);
 
         match locations {
-            Ok((default, deltas)) => Metric {
-                default,
-                device_or_deltas: DeviceOrDeltas::Deltas(deltas),
-            },
+            Ok((default, deltas)) => metric_with_deltas(default, deltas),
             Err(e) => {
                 self.error(
+                    DiagnosticCode::InvalidVariation,
                     number_value.range(),
                     format!("failed to resolve number value: '{e}'"),
                 );
@@ -1408,7 +1802,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         } else if let Some(alias) = class_decl.class_alias() {
             self.resolve_named_glyph_class(&alias)
         } else {
-            panic!("write more code I guess");
+            self.error(
+                DiagnosticCode::Internal,
+                class_decl.range(),
+                "malformed glyph class definition",
+            );
+            return;
         };
 
         self.glyph_class_defs.insert(name.text().clone(), glyphs);
@@ -1430,6 +1829,11 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn add_feature(&mut self, feature: typed::Feature) {
         let tag = feature.tag();
         let tag_raw = tag.to_raw();
+        if !self.opts.compile_feature(tag_raw) {
+            self.skip_feature(feature.statements());
+            return;
+        }
+        trace_span!("feature", tag = tag_raw);
         self.start_feature(tag, None);
         if tag_raw == tags::AALT {
             self.resolve_aalt_feature(&feature);
@@ -1441,7 +1845,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             self.resolve_character_variant_feature(tag_raw, &feature);
         } else {
             for item in feature.statements() {
-                self.resolve_statement(item);
+                self.resolve_statement(&item);
             }
         }
         self.end_feature();
@@ -1449,27 +1853,48 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
     fn add_feature_variation(&mut self, node: typed::FeatureVariation) {
         let tag = node.tag();
-        let conditions = self.resolve_condition_set(node.condition_set());
+        if !self.opts.compile_feature(tag.to_raw()) {
+            self.skip_feature(node.statements());
+            return;
+        }
+        let conditions = self.resolve_condition_set(node.condition_set().as_ref());
         self.start_feature(tag, Some(conditions));
         for item in node.statements() {
-            self.resolve_statement(item);
+            self.resolve_statement(&item);
         }
         self.end_feature();
     }
 
+    /// Handle a feature block that was excluded by [`Opts::features`].
+    ///
+    /// The rules are dropped, but anything defined in the block may be
+    /// referenced elsewhere, so we still resolve those definitions.
+    fn skip_feature(&mut self, statements: impl Iterator<Item = NodeOrToken>) {
+        for item in statements {
+            self.statement_start = item.range().start;
+            if let Some(glyph_def) = typed::GlyphClassDef::cast(&item) {
+                self.define_glyph_class(glyph_def);
+            } else if let Some(glyph_def) = typed::MarkClassDef::cast(&item) {
+                self.define_mark_class(glyph_def);
+            } else if let Some(lookup) = typed::LookupBlock::cast(&item) {
+                self.resolve_lookup_block(lookup);
+            }
+        }
+    }
+
     fn resolve_aalt_feature(&mut self, feature: &typed::Feature) {
         let mut aalt = AaltFeature::default();
         for item in feature.statements() {
-            if let Some(node) = typed::Gsub1::cast(item) {
+            if let Some(node) = typed::Gsub1::cast(&item) {
                 let Some((target, replacement)) = self.resolve_single_sub_glyphs(&node) else {
                     continue;
                 };
                 aalt.extend(target.iter().zip(replacement.into_iter_for_target()))
-            } else if let Some(node) = typed::Gsub3::cast(item) {
+            } else if let Some(node) = typed::Gsub3::cast(&item) {
                 let target = self.resolve_glyph(&node.target());
                 let alts = self.resolve_glyph_class(&node.alternates());
                 aalt.extend(std::iter::repeat(target).zip(alts.iter()));
-            } else if let Some(feature) = typed::FeatureRef::cast(item) {
+            } else if let Some(feature) = typed::FeatureRef::cast(&item) {
                 aalt.add_feature_reference(feature.feature().to_raw());
             }
         }
@@ -1479,7 +1904,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn resolve_stylistic_set_feature(&mut self, tag: Tag, feature: &typed::Feature) {
         let mut names = BTreeMap::new();
         for item in feature.statements() {
-            if let Some(feature_name) = typed::FeatureNames::cast(item) {
+            if let Some(feature_name) = typed::FeatureNames::cast(&item) {
                 for name_spec in feature_name.statements() {
                     let resolved = self.resolve_name_spec(&name_spec);
                     // in the case of duplicate names, last writer wins, see
@@ -1489,7 +1914,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 }
                 continue;
             }
-            self.resolve_statement(item);
+            self.resolve_statement(&item);
         }
         if !names.is_empty() {
             self.features
@@ -1501,9 +1926,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     fn resolve_character_variant_feature(&mut self, tag: Tag, feature: &typed::Feature) {
         let mut seen_cv_params = false;
         for item in feature.statements() {
-            if let Some(cv_params) = typed::CvParameters::cast(item) {
+            if let Some(cv_params) = typed::CvParameters::cast(&item) {
                 if seen_cv_params {
                     self.warning(
+                        DiagnosticCode::DuplicateStatement,
                         cv_params.range(),
                         "Duplicate cvParameters block will be ignored. \
                         This is not disallowed by the spec, but is not currently supported.",
@@ -1515,7 +1941,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 }
                 continue;
             }
-            self.resolve_statement(item);
+            self.resolve_statement(&item);
         }
     }
 
@@ -1539,12 +1965,6 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 .map(|x| self.resolve_name_spec(&x))
                 .collect();
         }
-        if let Some(node) = cv_params.sample_text_name() {
-            params.sample_text_name = node
-                .statements()
-                .map(|x| self.resolve_name_spec(&x))
-                .collect();
-        }
         for node in cv_params.param_ui_label_name() {
             params.param_ui_label_names.push(
                 node.statements()
@@ -1553,35 +1973,37 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             );
         }
         for c in cv_params.characters() {
-            params.characters.push(c.value().parse_char().unwrap());
+            let value = c.value();
+            match value.parse_char() {
+                Ok(c) => params.characters.push(c),
+                Err(message) => self.report(CompileError::BadValue {
+                    message,
+                    range: value.range(),
+                }),
+            }
         }
         params
     }
 
     fn resolve_size_feature(&mut self, feature: &typed::Feature) {
-        //FIXME: I thought this was signed, but I now think it's
-        // unsigned? Double check with spec and ensure this is validated
-        fn resolve_decipoint(node: &typed::FloatLike) -> u16 {
-            match node {
-                typed::FloatLike::Number(n) => n.parse_unsigned(),
-                typed::FloatLike::Float(f) => {
-                    let f = f.parse();
-                    ((f * 10.0).round() as i16).try_into().ok()
-                }
-            }
-            .expect("validated at parse time")
-        }
-
         let mut size = SizeFeature::default();
         for statement in feature.statements() {
-            if let Some(node) = typed::SizeMenuName::cast(statement) {
+            if let Some(node) = typed::SizeMenuName::cast(&statement) {
                 size.names.push(self.resolve_name_spec(&node.spec()));
-            } else if let Some(node) = typed::Parameters::cast(statement) {
-                size.design_size = resolve_decipoint(&node.design_size());
-                size.identifier = node.subfamily().parse_unsigned().unwrap();
+            } else if let Some(node) = typed::Parameters::cast(&statement) {
+                size.design_size = self.resolve_decipoint(&node.design_size());
+                size.identifier = self.resolve_unsigned(&node.subfamily());
                 if size.identifier != 0 {
-                    size.range_start = resolve_decipoint(&node.range_start().unwrap());
-                    size.range_end = resolve_decipoint(&node.range_end().unwrap());
+                    match (node.range_start(), node.range_end()) {
+                        (Some(start), Some(end)) => {
+                            size.range_start = self.resolve_decipoint(&start);
+                            size.range_end = self.resolve_decipoint(&end);
+                        }
+                        _ => self.report(CompileError::Missing {
+                            expected: "range start and end",
+                            range: node.range(),
+                        }),
+                    }
                 }
             }
         }
@@ -1592,6 +2014,23 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         self.features.size = Some(size);
     }
 
+    //FIXME: I thought this was signed, but I now think it's
+    // unsigned? Double check with spec and ensure this is validated
+    fn resolve_decipoint(&mut self, node: &typed::FloatLike) -> u16 {
+        let value = match node {
+            typed::FloatLike::Number(n) => n.parse_unsigned(),
+            typed::FloatLike::Float(f) => {
+                let f = f.parse();
+                ((f * 10.0).round() as i16).try_into().ok()
+            }
+        };
+        let result = value.ok_or_else(|| CompileError::BadValue {
+            message: "expected positive number".into(),
+            range: node.range(),
+        });
+        self.ok_or_report(result)
+    }
+
     fn resolve_table(&mut self, table: typed::Table) {
         match table {
             typed::Table::Base(table) => self.resolve_base(&table),
@@ -1603,7 +2042,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             typed::Table::Head(table) => self.resolve_head(&table),
             typed::Table::Os2(table) => self.resolve_os2(&table),
             typed::Table::Stat(table) => self.resolve_stat(&table),
-            _ => (),
+            typed::Table::Other(ref other) => self.unknown_tables.push(UnknownTable {
+                tag: table.tag().to_raw(),
+                contents: other.contents(),
+            }),
         }
     }
 
@@ -1649,7 +2091,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
     fn resolve_name(&mut self, table: &typed::NameTable) {
         for record in table.statements() {
-            let name_id = NameId::new(record.name_id().parse().unwrap());
+            let name_id = NameId::new(self.resolve_dec_oct_hex(&record.name_id()));
             let spec = self.resolve_name_spec(&record.entry());
             self.tables.name.add(name_id, spec);
         }
@@ -1660,59 +2102,99 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         for item in table.statements() {
             match item {
                 typed::Os2TableItem::Number(val) => {
-                    let value = val.number().parse_unsigned().unwrap();
+                    let value = self.resolve_unsigned(&val.number());
                     match val.keyword().text.as_str() {
-                        "WeightClass" => os2.us_weight_class = value,
-                        "WidthClass" => os2.us_width_class = value,
+                        "WeightClass" => os2.us_weight_class = Some(value),
+                        "WidthClass" => os2.us_width_class = Some(value),
                         "LowerOpSize" => os2.us_lower_optical_point_size = Some(value),
                         "UpperOpSize" => os2.us_upper_optical_point_size = Some(value),
-                        "FSType" => os2.fs_type = value,
-                        _ => unreachable!("checked at parse time"),
+                        "FSType" => os2.fs_type = Some(value),
+                        other => self.error(
+                            DiagnosticCode::Internal,
+                            val.keyword().range(),
+                            format!("unexpected keyword '{other}'"),
+                        ),
                     }
                 }
                 typed::Os2TableItem::Metric(val) => {
-                    let value = val.metric().parse_simple().expect("checked in validation");
+                    let value = self.resolve_simple_metric(&val.metric());
                     match val.keyword().kind {
-                        Kind::TypoAscenderKw => os2.s_typo_ascender = value,
-                        Kind::TypoDescenderKw => os2.s_typo_descender = value,
-                        Kind::TypoLineGapKw => os2.s_typo_line_gap = value,
-                        Kind::XHeightKw => os2.sx_height = value,
-                        Kind::CapHeightKw => os2.s_cap_height = value,
-                        Kind::WinAscentKw => os2.us_win_ascent = value as u16,
-                        Kind::WinDescentKw => os2.us_win_descent = value as u16,
-                        _ => unreachable!("checked at parse time"),
+                        Kind::TypoAscenderKw => os2.s_typo_ascender = Some(value),
+                        Kind::TypoDescenderKw => os2.s_typo_descender = Some(value),
+                        Kind::TypoLineGapKw => os2.s_typo_line_gap = Some(value),
+                        Kind::XHeightKw => os2.sx_height = Some(value),
+                        Kind::CapHeightKw => os2.s_cap_height = Some(value),
+                        Kind::WinAscentKw => os2.us_win_ascent = Some(value as u16),
+                        Kind::WinDescentKw => os2.us_win_descent = Some(value as u16),
+                        other => self.error(
+                            DiagnosticCode::Internal,
+                            val.keyword().range(),
+                            format!("unexpected keyword '{other}'"),
+                        ),
                     }
                 }
                 typed::Os2TableItem::NumberList(list) => match list.keyword().kind {
                     Kind::PanoseKw => {
+                        let panose = os2.panose_10.get_or_insert_default();
                         for (i, val) in list.values().enumerate() {
-                            os2.panose_10[i] = val.parse_signed() as u8;
+                            panose[i] = val.parse_signed() as u8;
                         }
                     }
                     Kind::UnicodeRangeKw => {
+                        let unicode_range = os2.unicode_range.get_or_insert_default();
                         for val in list.values() {
-                            os2.unicode_range.set_bit(val.parse_signed() as _);
+                            unicode_range.set_bit(val.parse_signed() as _);
                         }
                     }
                     Kind::CodePageRangeKw => {
+                        let code_page_range = os2.code_page_range.get_or_insert_default();
                         for val in list.values() {
-                            os2.code_page_range
-                                .add_code_page(val.parse_unsigned().unwrap());
+                            let code_page = self.resolve_unsigned(&val);
+                            code_page_range.add_code_page(code_page);
                         }
                     }
-                    _ => unreachable!("checked at parse time"),
+                    other => self.error(
+                        DiagnosticCode::Internal,
+                        list.keyword().range(),
+                        format!("unexpected keyword '{other}'"),
+                    ),
+                },
+                typed::Os2TableItem::Vendor(item) => match item.parse_tag() {
+                    Ok(tag) => os2.ach_vend_id = Some(tag),
+                    Err(err) => self.report(CompileError::BadValue {
+                        message: err.to_string(),
+                        range: item.value().range(),
+                    }),
                 },
-                typed::Os2TableItem::Vendor(item) => {
-                    os2.ach_vend_id = item.parse_tag().expect("validated");
-                }
                 typed::Os2TableItem::FamilyClass(item) => {
-                    os2.s_family_class = item.value().parse().unwrap() as i16
+                    os2.s_family_class = Some(self.resolve_dec_oct_hex(&item.value()) as i16)
                 }
             }
         }
         self.tables.os2 = Some(os2);
     }
 
+    /// Check that the STAT table's ElidedFallbackNameID is in the name table.
+    ///
+    /// The name table may be defined after the STAT table, so this is done
+    /// once all the tables have been resolved.
+    fn check_stat_fallback_name(&mut self) {
+        let Some(range) = self.stat_fallback_name_id.take() else {
+            return;
+        };
+        let id = match self.tables.stat.as_ref().map(|stat| &stat.name) {
+            Some(super::tables::StatFallbackName::Id(id)) => *id,
+            _ => return,
+        };
+        if !self.tables.name.contains_id(id) {
+            self.error(
+                DiagnosticCode::UndefinedNameId,
+                range,
+                format!("ElidedFallbackNameID '{id}' does not exist in the name table"),
+            );
+        }
+    }
+
     fn resolve_stat(&mut self, table: &typed::StatTable) {
         let mut stat = super::tables::StatBuilder {
             name: super::tables::StatFallbackName::Id(u16::MAX.into()),
@@ -1724,22 +2206,22 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             match item {
                 typed::StatTableItem::ElidedFallbackName(name) => {
                     if let Some(id) = name.elided_fallback_name_id() {
-                        //FIXME: validate
-                        stat.name = super::tables::StatFallbackName::Id(
-                            id.parse_unsigned().unwrap().into(),
-                        );
+                        stat.name =
+                            super::tables::StatFallbackName::Id(self.resolve_unsigned(&id).into());
+                        self.stat_fallback_name_id = Some(id.range());
                     } else {
                         let names = name.names().map(|n| self.resolve_name_spec(&n)).collect();
                         stat.name = super::tables::StatFallbackName::Record(names);
                     }
                 }
                 typed::StatTableItem::AxisValue(value) => {
-                    stat.values.push(self.resolve_stat_axis_value(&value));
+                    if let Some(value) = self.resolve_stat_axis_value(&value) {
+                        stat.values.push(value);
+                    }
                 }
                 typed::StatTableItem::DesignAxis(value) => {
                     let tag = value.tag().to_raw();
-                    let ordering = value.ordering().parse_unsigned().unwrap();
-                    //FIXME: validate
+                    let ordering = self.resolve_unsigned(&value.ordering());
                     let name = value.names().map(|n| self.resolve_name_spec(&n)).collect();
                     stat.records.push(super::tables::AxisRecord {
                         tag,
@@ -1752,7 +2234,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         self.tables.stat = Some(stat);
     }
 
-    fn resolve_stat_axis_value(&mut self, node: &typed::StatAxisValue) -> super::tables::AxisValue {
+    fn resolve_stat_axis_value(
+        &mut self,
+        node: &typed::StatAxisValue,
+    ) -> Option<super::tables::AxisValue> {
         use super::tables::AxisLocation;
         let mut flags = 0;
         let mut name = Vec::new();
@@ -1804,46 +2289,55 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             }
         }
 
-        super::tables::AxisValue {
+        let Some(location) = location else {
+            self.report(CompileError::Missing {
+                expected: "axis value location",
+                range: node.range(),
+            });
+            return None;
+        };
+        Some(super::tables::AxisValue {
             flags,
             name,
-            location: location.unwrap(),
-        }
+            location,
+        })
     }
 
     fn resolve_hhea(&mut self, table: &typed::HheaTable) {
-        let mut hhea = tables::hhea::Hhea::default();
+        let mut hhea = super::tables::HheaBuilder::default();
         for record in table.metrics() {
             let keyword = record.keyword();
-            let value = record
-                .metric()
-                .parse_simple()
-                .expect("checked during validation");
+            let value = self.resolve_simple_metric(&record.metric());
             match keyword.kind {
-                Kind::CaretOffsetKw => hhea.caret_offset = value,
-                Kind::AscenderKw => hhea.ascender = value.into(),
-                Kind::DescenderKw => hhea.descender = value.into(),
-                Kind::LineGapKw => hhea.line_gap = value.into(),
-                other => panic!("bug in parser, unexpected token '{other}'"),
+                Kind::CaretOffsetKw => hhea.caret_offset = Some(value),
+                Kind::AscenderKw => hhea.ascender = Some(value),
+                Kind::DescenderKw => hhea.descender = Some(value),
+                Kind::LineGapKw => hhea.line_gap = Some(value),
+                other => self.error(
+                    DiagnosticCode::Internal,
+                    keyword.range(),
+                    format!("unexpected keyword '{other}'"),
+                ),
             }
         }
         self.tables.hhea = Some(hhea);
     }
 
     fn resolve_vhea(&mut self, table: &typed::VheaTable) {
-        let mut vhea = tables::vhea::Vhea::default();
+        let mut vhea = super::tables::VheaBuilder::default();
         for record in table.metrics() {
             let keyword = record.keyword();
-            let value = record
-                .metric()
-                .parse_simple()
-                .expect("checked during validation");
+            let value = self.resolve_simple_metric(&record.metric());
 
             match keyword.kind {
-                Kind::VertTypoAscenderKw => vhea.ascender = value.into(),
-                Kind::VertTypoDescenderKw => vhea.descender = value.into(),
-                Kind::VertTypoLineGapKw => vhea.line_gap = value.into(),
-                other => panic!("bug in parser, unexpected token '{other}'"),
+                Kind::VertTypoAscenderKw => vhea.ascender = Some(value),
+                Kind::VertTypoDescenderKw => vhea.descender = Some(value),
+                Kind::VertTypoLineGapKw => vhea.line_gap = Some(value),
+                other => self.error(
+                    DiagnosticCode::Internal,
+                    keyword.range(),
+                    format!("unexpected keyword '{other}'"),
+                ),
             }
         }
         self.tables.vhea = Some(vhea);
@@ -1871,39 +2365,53 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     let glyphs = self.resolve_glyph_or_class(&rule.target());
                     let indices = rule
                         .indices()
-                        .map(|n| n.parse_unsigned().unwrap())
+                        .map(|n| self.resolve_unsigned(&n))
                         .collect::<Vec<_>>();
-                    assert!(!indices.is_empty(), "check this in validation");
+                    if indices.is_empty() {
+                        self.report(CompileError::Missing {
+                            expected: "contour point index",
+                            range: rule.range(),
+                        });
+                    }
                     for glyph in glyphs.iter() {
-                        gdef.attach
-                            .entry(glyph)
-                            .or_default()
-                            .extend(indices.iter().copied());
+                        gdef.add_attach_points(glyph, indices.iter().copied());
                     }
                 }
                 typed::GdefTableItem::LigatureCaret(rule) => {
                     let target = rule.target();
                     let glyphs = self.resolve_glyph_or_class(&target);
                     let carets: Vec<_> = match rule.values() {
-                        typed::LigatureCaretValue::Pos(items) => items
+                        Some(typed::LigatureCaretValue::Pos(items)) => items
                             .values()
                             .map(|n| CaretValue::Coordinate {
                                 default: n.parse_signed(),
                                 deltas: DeviceOrDeltas::None,
                             })
                             .collect(),
-                        typed::LigatureCaretValue::Index(items) => items
+                        Some(typed::LigatureCaretValue::Index(items)) => items
                             .values()
-                            .map(|n| CaretValue::PointIndex(n.parse_unsigned().unwrap()))
+                            .map(|n| CaretValue::PointIndex(self.resolve_unsigned(&n)))
                             .collect(),
+                        None => {
+                            self.error(
+                                DiagnosticCode::Internal,
+                                rule.range(),
+                                "expected 'LigatureCaretByPos' or 'LigatureCaretByIndex'",
+                            );
+                            continue;
+                        }
                     };
                     for glyph in glyphs.iter() {
-                        //NOTE: only one rule allowed per glyph; if a glyph already
-                        //has carets set, we skip it. We could warn here but this is
-                        //a very niche feature, so :shrug:
-                        gdef.ligature_pos
-                            .entry(glyph)
-                            .or_insert_with(|| carets.clone());
+                        // only one rule allowed per glyph; if a glyph already
+                        // has carets set, we skip it (matching feaLib)
+                        if !gdef.add_ligature_carets(glyph, carets.clone()) {
+                            let name = self.glyph_name(glyph);
+                            self.warning(
+                                DiagnosticCode::DuplicateStatement,
+                                target.range(),
+                                format!("ligature carets for '{name}' already set, ignoring"),
+                            );
+                        }
                     }
                 }
 
@@ -1920,9 +2428,13 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                         if let Err((bad_glyph, old_class)) =
                             gdef.add_glyph_class(self.resolve_glyph_class(&class), id)
                         {
-                            let bad_name = self.reverse_glyph_map.get(&bad_glyph).unwrap();
+                            let bad_name = self.glyph_name(bad_glyph);
                             let class_name = old_class.display();
-                            self.error(class.range(), format!("class includes glyph '{bad_name}', already in class {class_name}"));
+                            let message = format!(
+                                "class includes glyph '{bad_name}', \
+                                 already in class {class_name}"
+                            );
+                            self.error(DiagnosticCode::ClassConflict, class.range(), message);
                         }
                     }
                 }
@@ -1932,56 +2444,108 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     }
 
     fn resolve_head(&mut self, table: &typed::HeadTable) {
-        let mut head = super::tables::HeadBuilder::default();
-        let font_rev = table.statements().last().unwrap().value();
-        head.font_revision = font_rev.parse_fixed();
-        self.tables.head = Some(head);
+        // an empty block doesn't override anything
+        let Some(font_rev) = table.statements().last().map(|stmt| stmt.value()) else {
+            return;
+        };
+        self.tables.head = Some(super::tables::HeadBuilder {
+            font_revision: font_rev.parse_fixed(),
+        });
     }
 
     fn resolve_name_spec(&mut self, node: &typed::NameSpec) -> super::tables::NameSpec {
         const WIN_DEFAULT_IDS: (u16, u16) = (1, 0x0409);
         const MAC_DEFAULT_IDS: (u16, u16) = (0, 0);
 
-        let platform_id = node
-            .platform_id()
-            .map(|n| n.parse().unwrap())
-            .unwrap_or(tags::WIN_PLATFORM_ID);
+        let platform_id = match node.platform_id() {
+            Some(id) => self.resolve_dec_oct_hex(&id),
+            None => tags::WIN_PLATFORM_ID,
+        };
 
         let (encoding_id, language_id) = match node.platform_and_language_ids() {
-            Some((platform, language)) => (platform.parse().unwrap(), language.parse().unwrap()),
+            Some((platform, language)) => (
+                self.resolve_dec_oct_hex(&platform),
+                self.resolve_dec_oct_hex(&language),
+            ),
             None => match platform_id {
                 tags::MAC_PLATFORM_ID => MAC_DEFAULT_IDS,
                 tags::WIN_PLATFORM_ID => WIN_DEFAULT_IDS,
-                _ => panic!("missed validation"),
+                _ => {
+                    if let Some(id) = node.platform_id() {
+                        self.report(CompileError::BadValue {
+                            message: "platform id must be one of '1' or '3'".into(),
+                            range: id.range(),
+                        });
+                    }
+                    WIN_DEFAULT_IDS
+                }
             },
         };
         super::tables::NameSpec {
             platform_id,
             encoding_id,
             language_id,
-            string: node.string().into(),
+            string: node.string(),
         }
     }
 
+    fn resolve_unsigned(&mut self, number: &typed::Number) -> u16 {
+        let result = number
+            .parse_unsigned()
+            .ok_or_else(|| CompileError::BadValue {
+                message: "expected positive number".into(),
+                range: number.range(),
+            });
+        self.ok_or_report(result)
+    }
+
+    fn resolve_simple_metric(&mut self, metric: &typed::Metric) -> i16 {
+        let result = metric.parse_simple().ok_or_else(|| CompileError::BadValue {
+            message: "variable metrics not yet supported".into(),
+            range: metric.range(),
+        });
+        self.ok_or_report(result)
+    }
+
+    fn resolve_dec_oct_hex(&mut self, node: &typed::DecOctHex) -> u16 {
+        let result = node.parse().map_err(|message| CompileError::BadValue {
+            message,
+            range: node.range(),
+        });
+        self.ok_or_report(result)
+    }
+
     fn resolve_lookup_ref(&mut self, lookup: typed::LookupRef) {
-        let id = self
-            .lookups
-            .get_named(&lookup.label().text)
-            .expect("checked in validation pass");
-        self.add_lookup_to_current_feature_if_present(id);
+        if let Some(id) = self.resolve_lookup_name(&lookup.label()) {
+            self.add_lookup_to_current_feature_if_present(id);
+        }
+    }
+
+    fn resolve_lookup_name(&mut self, label: &Token) -> Option<LookupId> {
+        let result =
+            self.lookups
+                .get_named(&label.text)
+                .ok_or_else(|| CompileError::UndefinedLookup {
+                    name: label.text.clone(),
+                    range: label.range(),
+                });
+        self.ok_or_report(result.map(Some))
     }
 
     fn resolve_lookup_block(&mut self, lookup: typed::LookupBlock) {
-        self.start_lookup_block(lookup.label());
+        trace_span!("lookup", name = lookup.label().as_str());
+        // top-level blocks aren't resolved as statements, so set this here
+        self.statement_start = lookup.range().start;
+        self.start_lookup_block(&lookup.label(), lookup.use_extension().is_some());
 
-        //let use_extension = lookup.use_extension().is_some();
         for item in lookup.statements() {
-            self.resolve_statement(item);
+            self.resolve_statement(&item);
         }
         self.end_lookup_block();
     }
 
     fn resolve_statement(&mut self, item: &NodeOrToken) {
+        self.statement_start = item.range().start;
         if let Some(script) = typed::Script::cast(item) {
             self.set_script(script);
         } else if let Some(language) = typed::Language::cast(item) {
@@ -2016,6 +2580,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             );
             if self.active_feature.is_none() {
                 self.warning(
+                    DiagnosticCode::IgnoredStatement,
                     item.range(),
                     "Insertion marker outside feature block will be ignored",
                 );
@@ -2026,6 +2591,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             if let Some((id, _name)) = self.lookups.finish_current() {
                 if _name.is_some() {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         item.range(),
                         "insertion marker cannot be inside named lookup block",
                     );
@@ -2054,7 +2620,11 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     range.start..end
                 }
             };
-            self.error(span, format!("unhandled statement: '{}'", item.kind()));
+            self.error(
+                DiagnosticCode::Internal,
+                span,
+                format!("unhandled statement: '{}'", item.kind()),
+            );
         }
     }
 
@@ -2072,6 +2642,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             Some(a) if !a.x.has_device_or_deltas() && !a.y.has_device_or_deltas() => a,
             _ => {
                 return self.error(
+                    DiagnosticCode::InvalidAnchor,
                     anchor_block.range(),
                     "named anchor definition can only be in format A or B",
                 );
@@ -2082,29 +2653,39 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             .anchor_defs
             .insert(name.text.clone(), (anchor, anchor_def.range().start))
         {
-            self.error(name.range(), "duplicate anchor definition");
+            self.error(
+                DiagnosticCode::DuplicateDefinition,
+                name.range(),
+                "duplicate anchor definition",
+            );
         }
     }
 
+    /// Resolve an anchor, returning `None` for a NULL anchor.
     fn resolve_anchor(&mut self, item: &typed::Anchor) -> Option<Anchor> {
+        let result = self.try_resolve_anchor(item);
+        self.ok_or_report(result)
+    }
+
+    fn try_resolve_anchor(&mut self, item: &typed::Anchor) -> Result<Option<Anchor>, CompileError> {
         if item.null().is_some() {
-            return None;
+            return Ok(None);
         }
 
         if let Some(name) = item.name() {
-            match self.anchor_defs.get(&name.text) {
-                Some((anchor, pos)) if *pos < item.range().start => return Some(anchor.clone()),
-                _ => {
-                    self.error(name.range(), "anchor is not defined");
-                    return None;
-                }
-            }
+            return match self.anchor_defs.get(&name.text) {
+                Some((anchor, pos)) if *pos < item.range().start => Ok(Some(anchor.clone())),
+                _ => Err(CompileError::UndefinedAnchor {
+                    name: name.text.clone(),
+                    range: name.range(),
+                }),
+            };
         }
 
-        let Some((x, y)) = item.coords() else {
-            self.error(item.range(), "unexpected parse failure, please file a bug");
-            return None;
+        let bad_anchor = || CompileError::BadAnchor {
+            range: item.range(),
         };
+        let (x, y) = item.coords().ok_or_else(bad_anchor)?;
 
         let x = self.resolve_metric(&x);
         let y = self.resolve_metric(&y);
@@ -2115,30 +2696,38 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         };
 
         if let Some(point) = item.contourpoint() {
-            match point.parse_unsigned() {
-                Some(point) => anchor.contourpoint = Some(point),
-                None => panic!("negative contourpoint, go fix your parser"),
-            }
+            anchor.contourpoint = Some(point.parse_unsigned().ok_or_else(bad_anchor)?);
         } else if let Some((x_dev, y_dev)) = item.devices() {
             anchor.x.device_or_deltas = x_dev.compile().into();
             anchor.y.device_or_deltas = y_dev.compile().into();
         }
-        Some(anchor)
+        Ok(Some(anchor))
     }
 
     fn define_condition_set(&mut self, node: typed::ConditionSet) {
         let Some(var_info) = self.variation_info else {
-            unreachable!("checked in validation pass");
+            self.error(
+                DiagnosticCode::RequiresVariableFont,
+                node.range(),
+                "condition set only valid when compiling variable font",
+            );
+            return;
         };
         let label = node.label();
-        let conditions = node
-            .conditions()
-            .map(|cond| {
-                let tag = cond.tag().to_raw();
-                let min = UserCoord::new(cond.min_value().parse_signed());
-                let max = UserCoord::new(cond.max_value().parse_signed());
-                let (axis_index, axis) = var_info.axis(tag).unwrap();
+        let mut conditions = Vec::new();
+        for cond in node.conditions() {
+            let tag = cond.tag().to_raw();
+            let min = UserCoord::new(cond.min_value().parse_signed());
+            let max = UserCoord::new(cond.max_value().parse_signed());
+            let Some((axis_index, axis)) = var_info.axis(tag) else {
+                self.report(CompileError::UnknownAxis {
+                    tag,
+                    range: cond.tag().range(),
+                });
+                continue;
+            };
 
+            conditions.push(
                 ConditionFormat1 {
                     axis_index: axis_index as u16,
                     filter_range_min_value: F2Dot14::from_f32(
@@ -2148,9 +2737,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                         max.to_normalized(&axis.converter).to_f64() as _,
                     ),
                 }
-                .into()
-            })
-            .collect();
+                .into(),
+            );
+        }
         let conditionset = ConditionSet::new(conditions);
         self.conditionset_defs
             .insert(label.text.clone(), conditionset);
@@ -2158,11 +2747,18 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
     // if none, then this is a 'null' condition set (e.g. no conditions)
     fn resolve_condition_set(&mut self, name: Option<&Token>) -> ConditionSet {
-        let condset = name
-            .as_ref()
-            .map(|name| self.conditionset_defs.get(&name.text).expect("validated"))
-            .cloned()
-            .unwrap_or_default();
+        let result = match name {
+            Some(name) => self
+                .conditionset_defs
+                .get(&name.text)
+                .cloned()
+                .ok_or_else(|| CompileError::UndefinedConditionSet {
+                    name: name.text.clone(),
+                    range: name.range(),
+                }),
+            None => Ok(Default::default()),
+        };
+        let condset = self.ok_or_report(result);
         self.conditionset_defs.register_use(&condset);
         condset
     }
@@ -2185,7 +2781,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         match item {
             typed::Glyph::Named(name) => self.resolve_glyph_name(name),
             typed::Glyph::Cid(name) => self.resolve_cid(name),
-            typed::Glyph::Null(_) => GlyphId16::NOTDEF,
+            typed::Glyph::Null(null) => self.ok_or_report(Err(CompileError::NullGlyph {
+                range: null.range(),
+            })),
         }
     }
 
@@ -2197,42 +2795,70 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     }
 
     fn resolve_glyph_class_literal(&mut self, class: &typed::GlyphClassLiteral) -> GlyphClass {
+        if let Some(glyphs) = self.resolved_class_literals.remove(&class.range().start) {
+            return self.glyph_classes.intern(glyphs.into());
+        }
+        if let Some(glyphs) = glyph_class::resolve_class_literal(self.glyph_map, class, |name| {
+            self.named_glyph_class(name.text())
+        }) {
+            return self.glyph_classes.intern(glyphs.into());
+        }
+        // something in the class can't be resolved; go through it again,
+        // reporting what went wrong.
         let mut glyphs = Vec::new();
         for item in class.items() {
             if let Some(id) =
-                typed::GlyphName::cast(item).map(|name| self.resolve_glyph_name(&name))
+                typed::GlyphName::cast(&item).map(|name| self.resolve_glyph_name(&name))
             {
                 glyphs.push(id);
-            } else if let Some(id) = typed::Cid::cast(item).map(|cid| self.resolve_cid(&cid)) {
+            } else if let Some(id) = typed::Cid::cast(&item).map(|cid| self.resolve_cid(&cid)) {
                 glyphs.push(id);
-            } else if let Some(range) = typed::GlyphRange::cast(item) {
+            } else if let Some(range) = typed::GlyphRange::cast(&item) {
                 self.add_glyphs_from_range(&range, &mut glyphs);
-            } else if let Some(alias) = typed::GlyphClassName::cast(item) {
+            } else if let Some(alias) = typed::GlyphClassName::cast(&item) {
                 glyphs.extend(self.resolve_named_glyph_class(&alias).items());
             } else {
-                panic!("unexptected kind in class literal: '{}'", item.kind());
+                self.error(
+                    DiagnosticCode::Internal,
+                    item.range(),
+                    format!("unexpected item in glyph class: '{}'", item.kind()),
+                );
             }
         }
-        glyphs.into()
+        self.glyph_classes.intern(glyphs.into())
     }
 
-    fn resolve_named_glyph_class(&mut self, name: &typed::GlyphClassName) -> GlyphClass {
-        self.glyph_class_defs
-            .get(name.text())
-            .cloned()
-            .or_else(|| {
-                self.mark_classes.get(name.text()).map(|cls| {
-                    cls.members
-                        .iter()
-                        .flat_map(|(glyphs, _)| glyphs.iter())
-                        .collect()
-                })
+    /// The glyphs in the glyph or mark class with this name, if it is defined.
+    fn named_glyph_class(&self, name: &str) -> Option<GlyphClass> {
+        self.glyph_class_defs.get(name).cloned().or_else(|| {
+            self.mark_classes.get(name).map(|cls| {
+                cls.members
+                    .iter()
+                    .flat_map(|(glyphs, _)| glyphs.iter())
+                    .collect()
             })
-            .unwrap()
+        })
+    }
+
+    fn resolve_named_glyph_class(&mut self, name: &typed::GlyphClassName) -> GlyphClass {
+        let result =
+            self.named_glyph_class(name.text())
+                .ok_or_else(|| CompileError::UndefinedClass {
+                    name: name.text().clone(),
+                    range: name.range(),
+                });
+        self.ok_or_report(result)
     }
 
     fn resolve_glyph_name(&mut self, name: &typed::GlyphName) -> GlyphId16 {
-        self.glyph_map.get(name.text()).unwrap()
+        let result = self
+            .glyph_map
+            .get(name.text())
+            .ok_or_else(|| CompileError::UnknownGlyph {
+                name: name.text().clone(),
+                range: name.range(),
+            });
+        self.ok_or_report(result)
     }
 
     fn resolve_lookahead_sequence(
@@ -2252,7 +2878,25 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     }
 
     fn resolve_cid(&mut self, cid: &typed::Cid) -> GlyphId16 {
-        self.glyph_map.get(&cid.parse()).unwrap()
+        let result = self
+            .glyph_map
+            .get(&cid.parse())
+            .ok_or_else(|| CompileError::UnknownCid {
+                cid: cid.parse(),
+                range: cid.range(),
+            });
+        self.ok_or_report(result)
+    }
+
+    fn expect_mark_class_name(
+        &mut self,
+        mark: &typed::AnchorMark,
+    ) -> Option<typed::GlyphClassName> {
+        let result = mark.mark_class_name().ok_or_else(|| CompileError::Missing {
+            expected: "mark class",
+            range: mark.range(),
+        });
+        self.ok_or_report(result.map(Some))
     }
 
     fn add_glyphs_from_range(&mut self, range: &typed::GlyphRange, out: &mut Vec<GlyphId16>) {
@@ -2261,48 +2905,87 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
         match (start.kind, end.kind) {
             (Kind::Cid, Kind::Cid) => {
-                if let Err(err) = glyph_range::cid(start, end, |cid| {
-                    match self.glyph_map.get(&cid) {
-                        Some(id) => out.push(id),
-                        None => {
-                            // this is techincally allowed, but we error for now
-                            self.error(
-                                range.range(),
-                                format!("Range member '{cid}' does not exist in font"),
-                            );
-                        }
-                    }
+                // members of a CID range that aren't in the font are skipped
+                if let Err(message) = glyph_range::cid(&start, &end, |cid| {
+                    out.extend(self.glyph_map.get(&cid));
                 }) {
-                    self.error(range.range(), err);
+                    self.report(CompileError::BadRange {
+                        message,
+                        range: range.range(),
+                    });
                 }
             }
             (Kind::GlyphName, Kind::GlyphName) => {
-                if let Err(err) = glyph_range::named(start, end, |name| {
+                let mut missing = Vec::new();
+                if let Err(message) = glyph_range::named(&start, &end, |name| {
                     match self.glyph_map.get(name) {
                         Some(id) => out.push(id),
-                        None => {
-                            // this is techincally allowed, but we error for now
-                            self.error(
-                                range.range(),
-                                format!("Range member '{name}' does not exist in font"),
-                            );
-                        }
+                        // this is techincally allowed, but we error for now
+                        None => missing.push(SmolStr::new(name)),
                     }
                 }) {
-                    self.error(range.range(), err);
+                    self.report(CompileError::BadRange {
+                        message,
+                        range: range.range(),
+                    });
+                }
+                for name in missing {
+                    self.report(CompileError::UnknownGlyph {
+                        name,
+                        range: range.range(),
+                    });
                 }
             }
-            (_, _) => self.error(range.range(), "Invalid types in glyph range"),
+            (_, _) => self.report(CompileError::BadRange {
+                message: "Invalid types in glyph range".into(),
+                range: range.range(),
+            }),
         }
     }
 }
 
+/// Resolve the glyph class literals in each feature and lookup block on the
+/// rayon thread pool, returning the glyphs of each class keyed by its position.
+///
+/// Statements still have to be resolved in order, but the classes they use
+/// can be resolved up front. Classes that reference a named class are skipped,
+/// since they depend on the definitions that precede them, as are classes
+/// that would report an error, so that the serial pass reports it in order.
+#[cfg(feature = "parallel")]
+fn resolve_class_literals_parallel(
+    root: &typed::Root,
+    glyph_map: &GlyphMap,
+) -> HashMap<usize, Vec<GlyphId16>> {
+    use rayon::prelude::*;
+    let blocks = root
+        .statements()
+        .filter(|item| matches!(item.kind(), Kind::FeatureNode | Kind::LookupBlockNode))
+        .filter_map(NodeOrToken::into_node)
+        .collect::<Vec<_>>();
+    blocks
+        .par_iter()
+        .flat_map_iter(|block| {
+            let mut cursor = block.cursor();
+            let mut resolved = Vec::new();
+            while let Some(item) = cursor.current() {
+                if let Some(class) = typed::GlyphClassLiteral::cast(&item) {
+                    resolved.extend(
+                        glyph_class::resolve_class_literal(glyph_map, &class, |_| None)
+                            .map(|glyphs| (class.range().start, glyphs)),
+                    );
+                }
+                cursor.advance();
+            }
+            resolved
+        })
+        .collect()
+}
+
 fn sequence_enumerator(sequence: &[GlyphOrClass]) -> Vec<Vec<GlyphId16>> {
-    assert!(sequence.len() >= 2);
-    let split = sequence.split_first();
     let mut result = Vec::new();
-    let (left, right) = split.unwrap();
-    sequence_enumerator_impl(Vec::new(), left, right, &mut result);
+    if let Some((left, right)) = sequence.split_first() {
+        sequence_enumerator_impl(Vec::new(), left, right, &mut result);
+    }
     result
 }
 
@@ -2323,18 +3006,6 @@ fn sequence_enumerator_impl(
     }
 }
 
-fn sort_feature_variations(
-    variations: &mut FeatureVariations,
-    order_fn: impl Fn(&ConditionSet) -> usize,
-) {
-    variations
-        .feature_variation_records
-        .sort_by_key(|record| match record.condition_set.as_ref() {
-            Some(condition) => order_fn(condition),
-            None => order_fn(&Default::default()),
-        })
-}
-
 /// Returns a span suitable for associating an error.
 ///
 /// If this is a token, we take the whole token. If it's a node, we take
@@ -2351,6 +3022,20 @@ fn get_reasonable_length_span(node: &NodeOrToken) -> Range<usize> {
     }
 }
 
+/// Create a metric from a default value and a set of deltas.
+///
+/// A metric that does not vary is a plain scalar, so that zero values can be
+/// pruned from value records and no variation store entry is needed.
+fn metric_with_deltas(default: i16, deltas: Vec<(VariationRegion, i16)>) -> Metric {
+    if deltas.iter().all(|(_, delta)| *delta == 0) {
+        return default.into();
+    }
+    Metric {
+        default,
+        device_or_deltas: DeviceOrDeltas::Deltas(deltas),
+    }
+}
+
 /// Modify this value record for the special requirements of pairpos lookups
 ///
 /// In pair pos tables, if a value record is all zeros (but not null) then
@@ -2369,6 +3054,49 @@ fn for_pair_pos(record: ValueRecord, in_vert_feature: bool) -> ValueRecord {
     out
 }
 
+/// Look up the mark class used in a mark attachment rule.
+///
+/// Every mark in a class used this way must have an anchor.
+fn get_mark_class<'a>(
+    mark_classes: &'a HashMap<SmolStr, MarkClass>,
+    name: &typed::GlyphClassName,
+) -> Result<&'a MarkClass, CompileError> {
+    let mark_class =
+        mark_classes
+            .get(name.text())
+            .ok_or_else(|| CompileError::UndefinedMarkClass {
+                name: name.text().clone(),
+                range: name.range(),
+            })?;
+    if mark_class
+        .members
+        .iter()
+        .any(|(_, anchor)| anchor.is_none())
+    {
+        return Err(CompileError::NullMarkAnchor {
+            name: name.text().clone(),
+            range: name.range(),
+        });
+    }
+    Ok(mark_class)
+}
+
+/// Quote and escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
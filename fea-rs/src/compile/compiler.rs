@@ -6,11 +6,14 @@ use std::{
 };
 
 use crate::{
-    DiagnosticSet, GlyphMap,
-    parse::{FileSystemResolver, SourceResolver},
+    Diagnostic, DiagnosticConfig, DiagnosticSet, GlyphMap,
+    parse::{DEFAULT_MAX_INCLUDE_DEPTH, FileSystemResolver, ParseContext, SourceResolver},
 };
 
-use super::{Compilation, FeatureProvider, Opts, VariationInfo, error::CompilerError};
+use super::{
+    CancellationToken, Compilation, CompilationState, FeatureProvider, Opts, VariationInfo,
+    error::CompilerError,
+};
 
 const DEFAULT_N_MESSAGES_TO_PRINT: usize = 100;
 
@@ -28,7 +31,10 @@ const DEFAULT_N_MESSAGES_TO_PRINT: usize = 100;
 /// ```
 pub struct Compiler<'a, F: FeatureProvider, V: VariationInfo> {
     root_path: PathBuf,
+    additional_roots: Vec<PathBuf>,
     project_root: Option<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    max_include_depth: usize,
     glyph_map: &'a GlyphMap,
     // variable fonts only
     var_info: Option<&'a V>,
@@ -37,6 +43,8 @@ pub struct Compiler<'a, F: FeatureProvider, V: VariationInfo> {
     // if you're compiling manually you are responsible for handling warnings.
     print_warnings: bool,
     max_n_errors: usize,
+    diagnostic_config: DiagnosticConfig,
+    cancellation: Option<CancellationToken>,
     opts: Opts,
     resolver: Option<Box<dyn SourceResolver>>,
 }
@@ -53,6 +61,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
     pub fn new(root_path: impl Into<PathBuf>, glyph_map: &'a GlyphMap) -> Self {
         Compiler {
             root_path: root_path.into(),
+            additional_roots: Vec::new(),
             glyph_map,
             var_info: None,
             feature_writer: None,
@@ -60,10 +69,25 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
             print_warnings: false,
             resolver: Default::default(),
             project_root: Default::default(),
+            include_dirs: Default::default(),
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
             max_n_errors: DEFAULT_N_MESSAGES_TO_PRINT,
+            diagnostic_config: Default::default(),
+            cancellation: None,
         }
     }
 
+    /// Specify additional root sources to compile after the first.
+    ///
+    /// The roots are compiled into a single set of tables, as if they were
+    /// concatenated in order; each can use the glyph classes and named
+    /// lookups defined in the roots before it. These paths are resolved in the
+    /// same way as the first root, and not relative to it.
+    pub fn with_additional_roots(mut self, roots: Vec<PathBuf>) -> Self {
+        self.additional_roots = roots;
+        self
+    }
+
     /// Provide a custom `SourceResolver`, for mapping paths to their contents.
     pub fn with_resolver(mut self, resolver: impl SourceResolver + 'static) -> Self {
         self.resolver = Some(Box::new(resolver));
@@ -96,6 +120,15 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
         self
     }
 
+    /// Configure how warnings are reported.
+    ///
+    /// Warnings can be promoted to errors, silenced, or limited in number;
+    /// see [`DiagnosticConfig`] for details.
+    pub fn with_diagnostic_config(mut self, config: DiagnosticConfig) -> Self {
+        self.diagnostic_config = config;
+        self
+    }
+
     /// Specify an explicit project root.
     ///
     /// This is useful in cases where import resolution is based on an explicit
@@ -105,6 +138,35 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
         self
     }
 
+    /// Specify additional directories to search for included files.
+    ///
+    /// These are searched in order, after the project root and the directory
+    /// of the including file. They are ignored if a custom resolver is provided.
+    pub fn with_include_dirs(mut self, include_dirs: Vec<PathBuf>) -> Self {
+        self.include_dirs = include_dirs;
+        self
+    }
+
+    /// Specify the maximum depth of nested include statements (default is 50).
+    ///
+    /// An include statement in the root file has a depth of 1. Statements
+    /// that are nested too deeply, or that would include a file in itself,
+    /// are reported as errors along with the chain of files that led to them.
+    pub fn with_max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = max_include_depth;
+        self
+    }
+
+    /// Provide a [`CancellationToken`] that can be used to stop compilation.
+    ///
+    /// If the token is cancelled, compilation stops at the next top-level
+    /// statement, and [`CompilerError::Cancelled`] is returned with any
+    /// diagnostics reported so far.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
     /// Specify additional compiler options.
     pub fn with_opts(mut self, opts: Opts) -> Self {
         self.opts = opts;
@@ -119,6 +181,36 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
     ///
     /// [`compile_binary`]: Self::compile_binary
     pub fn compile(self) -> Result<Compilation, CompilerError> {
+        self.compile_state().map(|state| {
+            trace_span!("build");
+            state.build()
+        })
+    }
+
+    /// Parse, validate and compile this source, stopping before any tables
+    /// are built.
+    ///
+    /// The returned [`CompilationState`] can be built into a [`Compilation`]
+    /// later; with the `serde` feature it can also be serialized, so that it
+    /// can be cached between runs.
+    pub fn compile_state(self) -> Result<CompilationState, CompilerError> {
+        self.run(true)
+            .map(|(state, _)| state.expect("requested the compilation state"))
+    }
+
+    /// Parse, validate and compile this source, without building any tables.
+    ///
+    /// This is intended for checking sources, for instance in CI. If there
+    /// are no errors, this returns the warnings that were reported, after
+    /// applying the [`DiagnosticConfig`].
+    pub fn check(self) -> Result<DiagnosticSet, CompilerError> {
+        self.run(false).map(|(_, warnings)| warnings)
+    }
+
+    fn run(
+        self,
+        keep_state: bool,
+    ) -> Result<(Option<CompilationState>, DiagnosticSet), CompilerError> {
         let resolver = self.resolver.unwrap_or_else(|| {
             let project_root = self.project_root.unwrap_or_else(|| {
                 Path::new(&self.root_path)
@@ -126,33 +218,92 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
                     .map(PathBuf::from)
                     .unwrap_or_default()
             });
-            Box::new(FileSystemResolver::new(project_root))
+            Box::new(FileSystemResolver::new(project_root).with_include_dirs(self.include_dirs))
         });
 
-        let (tree, diagnostics) =
-            crate::parse::ParseContext::parse(self.root_path, Some(self.glyph_map), resolver)?
-                .generate_parse_tree();
-        print_warnings_return_errors(diagnostics, self.print_warnings, self.max_n_errors)
+        let mut reporter = WarningReporter {
+            config: self.diagnostic_config,
+            print_warnings: self.print_warnings,
+            max_to_print: self.max_n_errors,
+            warnings: Vec::new(),
+        };
+        let cancellation = self.cancellation;
+        let is_cancelled = || {
+            cancellation
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+        };
+        let (tree, mut diagnostics) = {
+            trace_span!("parse", path = self.root_path.display());
+            let glyph_map = Some(self.glyph_map);
+            let liberal = self.opts.liberal_glyph_names;
+            let ctx = if self.additional_roots.is_empty() {
+                ParseContext::parse(self.root_path, glyph_map, resolver, liberal)?
+            } else {
+                let mut roots = self.additional_roots;
+                roots.insert(0, self.root_path);
+                ParseContext::parse_roots(&roots, glyph_map, resolver, liberal)?
+            };
+            ctx.with_max_include_depth(self.max_include_depth)
+                .generate_parse_tree()
+        };
+        if diagnostics.has_errors() {
+            // validate whatever parsed successfully, so that we can report
+            // as many problems as possible in a single run.
+            diagnostics.extend(super::validate_with_cancellation(
+                &tree,
+                self.glyph_map,
+                self.var_info,
+                cancellation.as_ref(),
+            ));
+        }
+        if is_cancelled() {
+            return Err(reporter.cancelled(diagnostics));
+        }
+        reporter
+            .print_warnings_return_errors(diagnostics)
             .map_err(CompilerError::ParseFail)?;
-        let diagnostics = super::validate(&tree, self.glyph_map, self.var_info);
-        print_warnings_return_errors(diagnostics, self.print_warnings, self.max_n_errors)
+        let diagnostics = {
+            trace_span!("validate");
+            super::validate_with_cancellation(
+                &tree,
+                self.glyph_map,
+                self.var_info,
+                cancellation.as_ref(),
+            )
+        };
+        if is_cancelled() {
+            return Err(reporter.cancelled(diagnostics));
+        }
+        reporter
+            .print_warnings_return_errors(diagnostics)
             .map_err(CompilerError::ValidationFail)?;
         let mut ctx = super::CompilationCtx::new(
             self.glyph_map,
-            tree.source_map(),
+            &tree,
             self.var_info,
             self.feature_writer,
             self.opts,
         );
-        ctx.compile(&tree.typed_root());
+        ctx.cancellation = cancellation.clone();
+        {
+            trace_span!("resolve");
+            ctx.compile(&tree.typed_root());
+        }
 
         // we 'take' the errors here because it's easier for us to handle the
         // warnings using our helper method.
         let messages = std::mem::take(&mut ctx.errors);
         let diagnostics = DiagnosticSet::new(messages, &tree, self.max_n_errors);
-        print_warnings_return_errors(diagnostics, self.print_warnings, self.max_n_errors)
+        if is_cancelled() {
+            return Err(reporter.cancelled(diagnostics));
+        }
+        reporter
+            .print_warnings_return_errors(diagnostics)
             .map_err(CompilerError::CompilationFail)?;
-        Ok(ctx.build().unwrap().0) // we've taken the errors, so this can't fail
+        let warnings = DiagnosticSet::new(reporter.warnings, &tree, self.max_n_errors);
+        let state = keep_state.then(|| ctx.take_state());
+        Ok((state, warnings))
     }
 
     /// Compile to a binary font.
@@ -162,23 +313,48 @@ impl<'a, F: FeatureProvider, V: VariationInfo> Compiler<'a, F, V> {
     }
 }
 
-fn print_warnings_return_errors(
-    mut diagnostics: DiagnosticSet,
+/// Applies the diagnostic config to each compilation stage in turn.
+struct WarningReporter {
+    config: DiagnosticConfig,
     print_warnings: bool,
     max_to_print: usize,
-) -> Result<(), DiagnosticSet> {
-    diagnostics.set_max_to_print(max_to_print);
-    let warnings = diagnostics.split_off_warnings();
-    if let Some(warnings) = warnings
-        && print_warnings
-    {
-        // get around a CI check denying eprintln
-        let _ = writeln!(std::io::stderr(), "{}", warnings.display());
-    }
-
-    if diagnostics.is_empty() {
-        Ok(())
-    } else {
-        Err(diagnostics)
+    // every warning reported so far
+    warnings: Vec<Diagnostic>,
+}
+
+impl WarningReporter {
+    /// Combine the warnings so far with the diagnostics of the current stage.
+    fn cancelled(self, mut diagnostics: DiagnosticSet) -> CompilerError {
+        let mut messages = self.warnings;
+        messages.append(&mut diagnostics.messages);
+        diagnostics.messages = messages;
+        diagnostics.set_max_to_print(self.max_to_print);
+        CompilerError::Cancelled(diagnostics)
+    }
+
+    fn print_warnings_return_errors(
+        &mut self,
+        mut diagnostics: DiagnosticSet,
+    ) -> Result<(), DiagnosticSet> {
+        let n_warnings = diagnostics.apply_config(&self.config);
+        // the warning limit applies to the compilation as a whole
+        if let Some(max) = self.config.max_warnings.as_mut() {
+            *max -= n_warnings;
+        }
+        diagnostics.set_max_to_print(self.max_to_print);
+        let warnings = diagnostics.split_off_warnings();
+        if let Some(warnings) = warnings {
+            if self.print_warnings {
+                // get around a CI check denying eprintln
+                let _ = writeln!(std::io::stderr(), "{}", warnings.display());
+            }
+            self.warnings.extend(warnings.messages);
+        }
+
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
     }
 }
@@ -0,0 +1,317 @@
+//! Removing duplicate lookups from the built GSUB and GPOS tables, and
+//! reordering the lookups that remain.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap},
+};
+
+use write_fonts::{FontWrite, tables::layout::Feature, validate::Validate};
+
+use super::merge::{Layout, LayoutTable, LookupRefs};
+
+/// How the lookups of a table were changed by [`dedup_lookups`] or
+/// [`reorder_lookups`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LookupRemapping {
+    /// The new index of each original lookup.
+    ///
+    /// A lookup that was removed has the index of the lookup that replaced it.
+    pub(crate) new_indices: Vec<u16>,
+    /// The original index of each lookup in the new lookup list.
+    pub(crate) order: Vec<usize>,
+}
+
+impl LookupRemapping {
+    fn identity(n_lookups: usize) -> Self {
+        LookupRemapping {
+            new_indices: (0..n_lookups as u16).collect(),
+            order: (0..n_lookups).collect(),
+        }
+    }
+
+    /// The number of lookups that were removed.
+    pub(crate) fn n_removed(&self) -> usize {
+        self.new_indices.len() - self.order.len()
+    }
+
+    /// Arrange `items`, which has an item for each original lookup, to match
+    /// the new lookup list.
+    pub(crate) fn reorder<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        self.order
+            .iter()
+            .filter_map(|idx| items.get(*idx).cloned())
+            .collect()
+    }
+
+    /// The new position of a point in the original lookup list.
+    ///
+    /// This is the smallest new index of the remaining lookups that were at or
+    /// after this position, or the end of the list. This is only meaningful if
+    /// no lookup was moved across the position, which [`reorder_lookups`]
+    /// guarantees for the barriers it is given.
+    pub(crate) fn new_position(&self, position: usize) -> usize {
+        // the order is not monotonic after reordering, but the first lookup
+        // at or after the position also has the smallest new index
+        self.order
+            .iter()
+            .position(|idx| *idx >= position)
+            .unwrap_or(self.order.len())
+    }
+
+    // apply another remapping after this one
+    fn then(&mut self, next: LookupRemapping) {
+        for idx in self.new_indices.iter_mut() {
+            *idx = next.new_indices[*idx as usize];
+        }
+        self.order = next.order.iter().map(|idx| self.order[*idx]).collect();
+    }
+}
+
+/// Replace lookups that are identical to an earlier lookup with that lookup.
+///
+/// Returns the new table, and how its lookups were changed.
+pub(crate) fn dedup_lookups<T>(table: T) -> (T, LookupRemapping)
+where
+    T: LayoutTable,
+    T::Lookup: FontWrite + Validate,
+{
+    let mut layout = table.into_layout();
+    let mut remapping = LookupRemapping::identity(layout.lookups.len());
+    // replacing the lookups referenced by contextual lookups can make those
+    // identical, so we keep going until nothing changes.
+    loop {
+        let replacements = find_replacements(&layout);
+        if replacements.is_empty() {
+            break;
+        }
+        // the remaining lookups keep their order; each duplicate is mapped to
+        // the new index of its replacement, which always comes before it.
+        let mut new_indices = Vec::with_capacity(layout.lookups.len());
+        let mut next = 0u16;
+        for idx in 0..layout.lookups.len() {
+            match replacements.get(&idx) {
+                Some(replacement) => new_indices.push(new_indices[*replacement]),
+                None => {
+                    new_indices.push(next);
+                    next += 1;
+                }
+            }
+        }
+        remapping.then(apply_new_indices(&mut layout, new_indices));
+    }
+    (T::from_layout(layout), remapping)
+}
+
+/// Reorder lookups so that smaller lookups come first, without changing the
+/// order in which the lookups of any language system are applied.
+///
+/// Each lookup in the lookup list is reached by a 16-bit offset, so putting
+/// the small lookups first means that as many lookups as possible are within
+/// reach of the lookup list. Lookups that are only used by contextual
+/// lookups, or that are used in different language systems, can be freely
+/// moved relative to one another.
+///
+/// No lookup is moved across any of the positions in `barriers`, so that
+/// lookups inserted at one of these positions later on still come after
+/// every lookup that was before it, and before every lookup that was after.
+///
+/// Returns the new table, and how its lookups were changed.
+pub(crate) fn reorder_lookups<T>(table: T, barriers: &BTreeSet<usize>) -> (T, LookupRemapping)
+where
+    T: LayoutTable,
+    T::Lookup: FontWrite + Validate,
+{
+    let mut layout = table.into_layout();
+    let n_lookups = layout.lookups.len();
+    // a lookup that is too large to serialize goes last
+    let sizes = layout
+        .lookups
+        .iter()
+        .map(|lookup| write_fonts::dump_table(lookup).map_or(usize::MAX, |bytes| bytes.len()))
+        .collect::<Vec<_>>();
+
+    // the lookups between two barriers form a segment, and every segment
+    // is placed before the next.
+    let segments = (0..n_lookups)
+        .map(|idx| barriers.range(..=idx).count())
+        .collect::<Vec<_>>();
+
+    // each lookup has to come after the lookups before it in every language
+    // system that uses it; this is a topological sort, preferring the
+    // earliest segment and then the smallest lookup whenever there is a
+    // choice. A lookup only ever follows lookups with smaller indices, so
+    // the lookups of a segment are all ready before those of the next are
+    // needed.
+    let mut n_before = vec![0usize; n_lookups];
+    let mut after = vec![Vec::new(); n_lookups];
+    for lookups in lang_sys_lookups(&layout) {
+        let lookups = lookups
+            .into_iter()
+            .filter(|idx| *idx < n_lookups)
+            .collect::<Vec<_>>();
+        for pair in lookups.windows(2) {
+            after[pair[0]].push(pair[1]);
+            n_before[pair[1]] += 1;
+        }
+    }
+    let mut ready = (0..n_lookups)
+        .filter(|idx| n_before[*idx] == 0)
+        .map(|idx| Reverse((segments[idx], sizes[idx], idx)))
+        .collect::<BinaryHeap<_>>();
+    let mut new_indices = vec![0u16; n_lookups];
+    let mut next = 0u16;
+    while let Some(Reverse((_, _, idx))) = ready.pop() {
+        new_indices[idx] = next;
+        next += 1;
+        for later in &after[idx] {
+            n_before[*later] -= 1;
+            if n_before[*later] == 0 {
+                ready.push(Reverse((segments[*later], sizes[*later], *later)));
+            }
+        }
+    }
+    debug_assert_eq!(next as usize, n_lookups, "lookup order is acyclic");
+
+    let remapping = apply_new_indices(&mut layout, new_indices);
+    (T::from_layout(layout), remapping)
+}
+
+/// Find lookups that can be replaced by an identical earlier lookup.
+///
+/// Lookups are applied in lookup list order, so a lookup is only replaced if
+/// no language system could apply the earlier lookup, or any lookup between
+/// the two, along with it.
+///
+/// Returns a map from the index of each duplicate to its replacement.
+fn find_replacements<L: FontWrite + Validate>(layout: &Layout<L>) -> BTreeMap<usize, usize> {
+    let mut lang_systems = lang_sys_lookups(layout);
+    let mut seen = HashMap::<Vec<u8>, Vec<usize>>::new();
+    let mut replacements = BTreeMap::new();
+    for (idx, lookup) in layout.lookups.iter().enumerate() {
+        // a lookup that is too large to serialize is left alone
+        let Ok(bytes) = write_fonts::dump_table(lookup) else {
+            continue;
+        };
+        let candidates = seen.entry(bytes).or_default();
+        let replacement = candidates.iter().rev().copied().find(|candidate| {
+            lang_systems.iter().all(|lookups| {
+                !lookups.contains(&idx) || lookups.range(*candidate..idx).next().is_none()
+            })
+        });
+        match replacement {
+            Some(replacement) => {
+                for lookups in lang_systems.iter_mut() {
+                    if lookups.remove(&idx) {
+                        lookups.insert(replacement);
+                    }
+                }
+                replacements.insert(idx, replacement);
+            }
+            None => candidates.push(idx),
+        }
+    }
+    replacements
+}
+
+/// The lookups that may be applied in each language system.
+///
+/// This includes the lookups of any alternate features from feature
+/// variations.
+fn lang_sys_lookups<L>(layout: &Layout<L>) -> Vec<BTreeSet<usize>> {
+    let mut feature_lookups = layout
+        .features
+        .feature_records
+        .iter()
+        .map(|record| lookup_indices(&record.feature).collect::<BTreeSet<_>>())
+        .collect::<Vec<_>>();
+    for substitution in layout
+        .variations
+        .iter()
+        .flat_map(|variations| variations.feature_variation_records.iter())
+        .filter_map(|record| record.feature_table_substitution.as_ref())
+        .flat_map(|substitution| substitution.substitutions.iter())
+    {
+        if let Some(lookups) = feature_lookups.get_mut(substitution.feature_index as usize) {
+            lookups.extend(lookup_indices(&substitution.alternate_feature));
+        }
+    }
+
+    layout
+        .scripts
+        .script_records
+        .iter()
+        .flat_map(|record| {
+            record.script.default_lang_sys.as_ref().into_iter().chain(
+                record
+                    .script
+                    .lang_sys_records
+                    .iter()
+                    .map(|lang| &*lang.lang_sys),
+            )
+        })
+        .map(|lang_sys| {
+            lang_sys
+                .feature_indices
+                .iter()
+                .chain(Some(&lang_sys.required_feature_index))
+                .filter_map(|idx| feature_lookups.get(*idx as usize))
+                .flatten()
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+fn lookup_indices(feature: &Feature) -> impl Iterator<Item = usize> + '_ {
+    feature.lookup_list_indices.iter().map(|idx| *idx as usize)
+}
+
+/// Move each lookup to its new index, and update every reference to them.
+///
+/// Where several lookups have the same new index, the first is kept and the
+/// others are removed.
+fn apply_new_indices<L: LookupRefs>(
+    layout: &mut Layout<L>,
+    new_indices: Vec<u16>,
+) -> LookupRemapping {
+    let n_new = new_indices.iter().max().map_or(0, |idx| *idx as usize + 1);
+    let mut lookups = (0..n_new).map(|_| None).collect::<Vec<_>>();
+    let mut order = vec![0; n_new];
+    for (idx, lookup) in std::mem::take(&mut layout.lookups).into_iter().enumerate() {
+        let slot = &mut lookups[new_indices[idx] as usize];
+        if slot.is_none() {
+            *slot = Some(lookup);
+            order[new_indices[idx] as usize] = idx;
+        }
+    }
+    layout.lookups = lookups
+        .into_iter()
+        .map(|lookup| lookup.expect("every new index is used"))
+        .collect();
+
+    let remap = |idx: u16| new_indices[idx as usize];
+    let remap_feature = |feature: &mut Feature| {
+        for idx in feature.lookup_list_indices.iter_mut() {
+            *idx = remap(*idx);
+        }
+        feature.lookup_list_indices.sort_unstable();
+        feature.lookup_list_indices.dedup();
+    };
+    for lookup in layout.lookups.iter_mut() {
+        lookup.remap_lookup_indices(&remap);
+    }
+    for record in layout.features.feature_records.iter_mut() {
+        remap_feature(&mut record.feature);
+    }
+    for substitution in layout
+        .variations
+        .iter_mut()
+        .flat_map(|variations| variations.feature_variation_records.iter_mut())
+        .filter_map(|record| record.feature_table_substitution.as_mut())
+        .flat_map(|substitution| substitution.substitutions.iter_mut())
+    {
+        remap_feature(&mut substitution.alternate_feature);
+    }
+    LookupRemapping { new_indices, order }
+}
@@ -1,10 +1,15 @@
 //! Error types related to compilation
 
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
-use write_fonts::{BuilderError, read::ReadError};
+use smol_str::SmolStr;
+use write_fonts::{
+    BuilderError,
+    read::ReadError,
+    types::{GlyphId16, Tag},
+};
 
-use crate::{DiagnosticSet, parse::SourceLoadError};
+use crate::{DiagnosticCode, DiagnosticSet, parse::SourceLoadError};
 
 /// An error that occurs when extracting a glyph order from a UFO.
 #[derive(Clone, Debug, thiserror::Error)]
@@ -17,6 +22,25 @@ pub enum UfoGlyphOrderError {
     Malformed,
 }
 
+/// An error that occurs when loading a glyph order from UFO or designspace
+/// sources.
+#[cfg(feature = "norad")]
+#[derive(Debug, thiserror::Error)]
+pub enum SourceGlyphOrderError {
+    /// Failed to load a UFO
+    #[error("Failed to load UFO: '{0}'")]
+    UfoLoad(#[from] norad::error::FontLoadError),
+    /// Failed to load a designspace
+    #[error("Failed to load designspace: '{0}'")]
+    DesignSpaceLoad(#[from] norad::error::DesignSpaceLoadError),
+    /// No source in the designspace is at the default location
+    #[error("No source at the default location in designspace")]
+    NoDefaultSource,
+    /// The UFO's glyph order is invalid
+    #[error(transparent)]
+    GlyphOrder(#[from] UfoGlyphOrderError),
+}
+
 /// An error that occurs when extracting a glyph order from a font file.
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum FontGlyphOrderError {
@@ -42,6 +66,14 @@ pub enum GlyphOrderError {
     /// Missing .notdef glyph
     #[error("The first glyph must be '.notdef'")]
     MissingNotDef,
+    /// An alias refers to a glyph that is not in the glyph map
+    #[error("Alias '{alias}' refers to unknown glyph '{name}'")]
+    #[allow(missing_docs)]
+    UnknownAliasTarget { alias: String, name: String },
+    /// An alias is already the name of a different glyph
+    #[error("Alias '{alias}' is already the name of a different glyph")]
+    #[allow(missing_docs)]
+    AliasConflict { alias: String },
 }
 
 /// An error reported by the compiler
@@ -56,10 +88,157 @@ pub enum CompilerError {
     ValidationFail(DiagnosticSet),
     #[error("FEA compilation failed with {} errors", .0.messages.len())]
     CompilationFail(DiagnosticSet),
+    /// Compilation was stopped by a [`CancellationToken`].
+    ///
+    /// This contains the diagnostics (including warnings) found before
+    /// compilation stopped.
+    ///
+    /// [`CancellationToken`]: super::CancellationToken
+    #[error("FEA compilation was cancelled")]
+    Cancelled(DiagnosticSet),
     #[error(transparent)]
     WriteFail(#[from] BuilderError),
 }
 
+/// An error that occurs when writing compiled tables as TTX
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum TtxError {
+    #[error(transparent)]
+    WriteFail(#[from] BuilderError),
+    #[error("Failed to read compiled tables: '{0}'")]
+    ReadFail(#[from] ReadError),
+}
+
+/// An error that occurs when merging two [`Compilation`]s.
+///
+/// [`Compilation`]: super::Compilation
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MergeError {
+    /// Both compilations have a variation store in GDEF
+    #[error("Both compilations have a GDEF variation store")]
+    VariationStoreConflict,
+    /// A glyph is in a different mark attachment class in each compilation
+    #[error(
+        "Glyph {} is in a different mark attachment class in each compilation",
+        .glyph.to_u16()
+    )]
+    #[allow(missing_docs)]
+    MarkAttachClassConflict { glyph: GlyphId16 },
+    /// There are more mark attachment classes than fit in a lookup flag
+    #[error("Too many mark attachment classes")]
+    TooManyMarkAttachClasses,
+    /// There are more mark filtering sets than can be referenced by a lookup
+    #[error("Too many mark filtering sets")]
+    TooManyMarkGlyphSets,
+    /// A language system has a different required feature in each compilation
+    #[error(
+        "{table} language system '{script}/{language}' has a different required feature in each compilation"
+    )]
+    #[allow(missing_docs)]
+    RequiredFeatureConflict {
+        table: Tag,
+        script: Tag,
+        language: Tag,
+    },
+    /// The merged table has more lookups or features than fit in a u16
+    #[error("Too many lookups or features in the merged {0} table")]
+    TableOverflow(Tag),
+}
+
+/// An error encountered while resolving an item during compilation.
+///
+/// Most of these are caught during validation; if one slips through it is
+/// reported as a diagnostic, instead of panicking.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum CompileError {
+    #[error("glyph '{name}' is not in the font")]
+    UnknownGlyph { name: SmolStr, range: Range<usize> },
+    #[error("CID {cid} is not in the font")]
+    UnknownCid { cid: u16, range: Range<usize> },
+    #[error("glyph class '{name}' is not defined")]
+    UndefinedClass { name: SmolStr, range: Range<usize> },
+    #[error("mark class '{name}' is not defined")]
+    UndefinedMarkClass { name: SmolStr, range: Range<usize> },
+    #[error("lookup '{name}' is not defined")]
+    UndefinedLookup { name: SmolStr, range: Range<usize> },
+    #[error("condition set '{name}' is not defined")]
+    UndefinedConditionSet { name: SmolStr, range: Range<usize> },
+    #[error("value record '{name}' is not defined")]
+    UndefinedValueRecord { name: SmolStr, range: Range<usize> },
+    #[error("anchor '{name}' is not defined")]
+    UndefinedAnchor { name: SmolStr, range: Range<usize> },
+    #[error("invalid anchor")]
+    BadAnchor { range: Range<usize> },
+    #[error("mark class '{name}' contains a NULL anchor")]
+    NullMarkAnchor { name: SmolStr, range: Range<usize> },
+    #[error("axis '{tag}' is not in the font")]
+    UnknownAxis { tag: Tag, range: Range<usize> },
+    #[error("NULL glyph is not allowed here")]
+    NullGlyph { range: Range<usize> },
+    #[error("{message}")]
+    BadRange {
+        message: String,
+        range: Range<usize>,
+    },
+    #[error("expected {expected}")]
+    Missing {
+        expected: &'static str,
+        range: Range<usize>,
+    },
+    #[error("{message}")]
+    BadValue {
+        message: String,
+        range: Range<usize>,
+    },
+}
+
+impl CompileError {
+    /// The range in the source where this error occurred.
+    pub(crate) fn range(&self) -> Range<usize> {
+        match self {
+            CompileError::UnknownGlyph { range, .. }
+            | CompileError::UnknownCid { range, .. }
+            | CompileError::UndefinedClass { range, .. }
+            | CompileError::UndefinedMarkClass { range, .. }
+            | CompileError::UndefinedLookup { range, .. }
+            | CompileError::UndefinedConditionSet { range, .. }
+            | CompileError::UndefinedValueRecord { range, .. }
+            | CompileError::UndefinedAnchor { range, .. }
+            | CompileError::BadAnchor { range }
+            | CompileError::NullMarkAnchor { range, .. }
+            | CompileError::UnknownAxis { range, .. }
+            | CompileError::NullGlyph { range }
+            | CompileError::BadRange { range, .. }
+            | CompileError::Missing { range, .. }
+            | CompileError::BadValue { range, .. } => range.clone(),
+        }
+    }
+
+    /// The kind of problem, for reporting.
+    pub(crate) fn code(&self) -> DiagnosticCode {
+        match self {
+            CompileError::UnknownGlyph { .. } | CompileError::UnknownCid { .. } => {
+                DiagnosticCode::UndefinedGlyph
+            }
+            CompileError::UndefinedClass { .. } => DiagnosticCode::UndefinedClass,
+            CompileError::UndefinedMarkClass { .. } => DiagnosticCode::UndefinedMarkClass,
+            CompileError::UndefinedLookup { .. } => DiagnosticCode::UndefinedLookup,
+            CompileError::UndefinedConditionSet { .. } => DiagnosticCode::UndefinedConditionSet,
+            CompileError::UndefinedValueRecord { .. } => DiagnosticCode::UndefinedValueRecord,
+            CompileError::UndefinedAnchor { .. } => DiagnosticCode::UndefinedAnchor,
+            CompileError::BadAnchor { .. } | CompileError::NullMarkAnchor { .. } => {
+                DiagnosticCode::InvalidAnchor
+            }
+            CompileError::UnknownAxis { .. } => DiagnosticCode::UndefinedAxis,
+            CompileError::NullGlyph { .. } => DiagnosticCode::InvalidRule,
+            CompileError::BadRange { .. } => DiagnosticCode::InvalidGlyphRange,
+            CompileError::Missing { .. } => DiagnosticCode::Internal,
+            CompileError::BadValue { .. } => DiagnosticCode::InvalidValue,
+        }
+    }
+}
+
 impl CompilerError {
     /// Return a `Display` type that reports the location and nature of syntax errors
     pub fn display_verbose(&self) -> impl Display + '_ {
@@ -81,7 +260,8 @@ impl CompilerError {
         match self {
             CompilerError::ParseFail(x)
             | CompilerError::ValidationFail(x)
-            | CompilerError::CompilationFail(x) => Some(x),
+            | CompilerError::CompilationFail(x)
+            | CompilerError::Cancelled(x) => Some(x),
             _ => None,
         }
     }
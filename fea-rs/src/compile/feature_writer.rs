@@ -6,9 +6,11 @@ use write_fonts::{
     tables::{
         gpos::builders::{
             CursivePosBuilder, MarkToBaseBuilder, MarkToLigBuilder, MarkToMarkBuilder,
-            PairPosBuilder,
+            PairPosBuilder, SinglePosBuilder, ValueRecordBuilder,
+        },
+        gsub::builders::{
+            AlternateSubBuilder, LigatureSubBuilder, MultipleSubBuilder, SingleSubBuilder,
         },
-        gsub::builders::SingleSubBuilder,
         layout::{ConditionSet, LookupFlag, builders::LookupBuilder},
     },
     types::{GlyphId16, Tag},
@@ -16,6 +18,7 @@ use write_fonts::{
 
 use crate::{
     GlyphSet,
+    common::SharedGlyphSet,
     compile::tags::{LANG_DFLT, SCRIPT_DFLT},
 };
 
@@ -31,6 +34,15 @@ use super::{
 };
 
 /// A trait that can be implemented by the client to do custom feature writing.
+///
+/// This is how lookups built in code (for instance kerning generated from
+/// source metrics) are combined with the rules in the FEA. The merge is
+/// deterministic: GSUB lookups are appended to the lookup list in the order
+/// they were added, and GPOS lookups are placed the way ufo2ft's feature
+/// writers would place them, at the `# Automatic Code` insertion point of
+/// their feature if the FEA has one, and otherwise after the existing lookups.
+/// GPOS features that no feature writer knows about are appended in order of
+/// their tag.
 pub trait FeatureProvider {
     /// The client can write additional features into the provided builder
     fn add_features(&self, builder: &mut FeatureBuilder);
@@ -52,12 +64,13 @@ pub(crate) struct RawFeatureVariations {
 /// A structure that allows client code to add additional features to the compilation.
 pub struct FeatureBuilder<'a> {
     pub(crate) language_systems: &'a DefaultLanguageSystems,
+    lookups: &'a AllLookups,
     pub(crate) tables: &'a mut Tables,
     pub(crate) pos_lookups: Vec<(LookupId, PositionLookup)>,
     pub(crate) sub_lookups: Vec<(LookupId, SubstitutionLookup)>,
     pub(crate) features: BTreeMap<FeatureKey, FeatureLookups>,
     pub(crate) lig_carets: BTreeMap<GlyphId16, Vec<CaretValue>>,
-    mark_filter_sets: &'a mut HashMap<GlyphSet, FilterSetId>,
+    mark_filter_sets: &'a mut HashMap<SharedGlyphSet, FilterSetId>,
     feature_variations: Option<RawFeatureVariations>,
 }
 
@@ -119,6 +132,50 @@ impl<T> PendingLookup<T> {
     }
 }
 
+impl PendingLookup<PairPosBuilder> {
+    /// Add glyph pair rules to the last subtable of this lookup.
+    ///
+    /// A subtable is added if the lookup has none. This is the same as calling
+    /// [`PairPosBuilder::insert_pair`] for each rule, so where two rules cover
+    /// the same pair the first one is kept, as the FEA spec requires.
+    ///
+    /// The builder keeps glyph pairs in sorted maps keyed by the first glyph,
+    /// which already groups them and cannot be pre-sized, so the rules are
+    /// not sorted or grouped here.
+    pub fn extend_pairs(
+        &mut self,
+        pairs: impl IntoIterator<Item = (GlyphId16, ValueRecordBuilder, GlyphId16, ValueRecordBuilder)>,
+    ) {
+        let subtable = self.last_subtable();
+        for (one, val_one, two, val_two) in pairs {
+            subtable.insert_pair(one, val_one, two, val_two);
+        }
+    }
+
+    /// Add class pair rules to the last subtable of this lookup.
+    ///
+    /// A subtable is added if the lookup has none. This is the same as calling
+    /// [`PairPosBuilder::insert_classes`] for each rule. The rules are inserted
+    /// in order and cannot be regrouped, since a rule starts a new subtable
+    /// whenever its classes overlap those already in the current one.
+    pub fn extend_classes(
+        &mut self,
+        rules: impl IntoIterator<Item = (GlyphSet, ValueRecordBuilder, GlyphSet, ValueRecordBuilder)>,
+    ) {
+        let subtable = self.last_subtable();
+        for (one, val_one, two, val_two) in rules {
+            subtable.insert_classes(one, val_one, two, val_two);
+        }
+    }
+
+    fn last_subtable(&mut self) -> &mut PairPosBuilder {
+        if self.subtables.is_empty() {
+            self.subtables.push(Default::default());
+        }
+        self.subtables.last_mut().unwrap()
+    }
+}
+
 /// An externally created lookup.
 ///
 /// This exists so that we can avoid making our internal types `pub`.
@@ -133,11 +190,13 @@ enum Inner {
 impl<'a> FeatureBuilder<'a> {
     pub(crate) fn new(
         language_systems: &'a DefaultLanguageSystems,
+        lookups: &'a AllLookups,
         tables: &'a mut Tables,
-        mark_filter_sets: &'a mut HashMap<GlyphSet, u16>,
+        mark_filter_sets: &'a mut HashMap<SharedGlyphSet, FilterSetId>,
     ) -> Self {
         Self {
             language_systems,
+            lookups,
             tables,
             pos_lookups: Default::default(),
             sub_lookups: Default::default(),
@@ -158,6 +217,20 @@ impl<'a> FeatureBuilder<'a> {
         self.tables.gdef.as_ref()
     }
 
+    /// Return every glyph reachable from `glyphs` through GSUB substitutions.
+    ///
+    /// This includes the lookups in the FEA, as well as any GSUB lookups that
+    /// have already been added to this builder.
+    pub fn gsub_closure(&self, glyphs: impl IntoIterator<Item = GlyphId16>) -> BTreeSet<GlyphId16> {
+        super::closure::gsub_closure(
+            self.lookups
+                .gsub_lookups()
+                .iter()
+                .chain(self.sub_lookups.iter().map(|(_, lookup)| lookup)),
+            glyphs,
+        )
+    }
+
     /// Add caret positions for the GDEF `LigCaretList` table
     pub fn add_lig_carets(&mut self, lig_carets: BTreeMap<GlyphId16, Vec<CaretValue>>) {
         self.lig_carets = lig_carets;
@@ -231,7 +304,7 @@ impl<'a> FeatureBuilder<'a> {
 
     fn get_filter_set_id(&mut self, cls: GlyphSet) -> FilterSetId {
         let next_id = self.mark_filter_sets.len();
-        *self.mark_filter_sets.entry(cls).or_insert_with(|| {
+        *self.mark_filter_sets.entry(cls.into()).or_insert_with(|| {
             next_id
                 .try_into()
                 // is this in any way an expected error condition?
@@ -275,6 +348,10 @@ macro_rules! impl_builder_trait {
 }
 
 impl_builder_trait!(SingleSubBuilder, Gsub);
+impl_builder_trait!(MultipleSubBuilder, Gsub);
+impl_builder_trait!(AlternateSubBuilder, Gsub);
+impl_builder_trait!(LigatureSubBuilder, Gsub);
+impl_builder_trait!(SinglePosBuilder, Gpos);
 impl_builder_trait!(PairPosBuilder, Gpos);
 impl_builder_trait!(MarkToBaseBuilder, Gpos);
 impl_builder_trait!(MarkToMarkBuilder, Gpos);
@@ -289,6 +366,7 @@ const ABVM: Tag = Tag::new(b"abvm");
 const BLWM: Tag = Tag::new(b"blwm");
 const KERN: Tag = Tag::new(b"kern");
 const DIST: Tag = Tag::new(b"dist");
+const WRITER_FEATURES: [Tag; 7] = [CURS, KERN, DIST, ABVM, BLWM, MARK, MKMK];
 
 /// All of the state that is generated by the external provider
 pub(crate) struct ExternalFeatures {
@@ -307,6 +385,7 @@ pub(crate) struct ExternalFeatures {
 /// generated features (e.g. kern, mark) while preserving the correct ordering
 /// relative to hand-written feature code.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InsertionPoint {
     /// The position in the lookup list to insert a set of new lookups.
     pub lookup_id: LookupId,
@@ -359,13 +438,11 @@ impl MergeCtx<'_> {
         self.do_curs();
         self.do_kern_and_dist();
         self.do_marks();
+        self.do_other_features();
 
         // okay so now 'processed_lookups' should contain insertion points for
         // all of our lookups
-
-        if !self.ext_pos_lookups.is_empty() {
-            log::warn!("feature merging left unhandled features!");
-        }
+        debug_assert!(self.ext_pos_lookups.is_empty());
         let mut id_map = self.finalize_gpos();
         self.finalize_gsub(&mut id_map);
 
@@ -604,6 +681,35 @@ impl MergeCtx<'_> {
         self.finalize_lookups_for_feature(MKMK, inserts[3].unwrap());
     }
 
+    fn do_other_features(&mut self) {
+        // no feature writer generates these, so there is no behaviour to
+        // match; we use the feature's insertion point if it has one, and
+        // otherwise append the features in tag order.
+        let others = self
+            .ext_features
+            .keys()
+            .map(|key| key.feature)
+            .filter(|tag| !WRITER_FEATURES.contains(tag))
+            .collect::<BTreeSet<_>>();
+        for tag in others {
+            let pos = self
+                .insert_markers
+                .get(&tag)
+                .copied()
+                .unwrap_or_else(|| self.insertion_point_for_append());
+            self.finalize_lookups_for_feature(tag, pos);
+        }
+
+        // and finally, lookups that aren't referenced by any feature (they
+        // may be referenced from other lookups) go at the end.
+        if !self.ext_pos_lookups.is_empty() {
+            let pos = self.insertion_point_for_append();
+            let lookups = std::mem::take(&mut self.ext_pos_lookups);
+            self.processed_lookups
+                .push((pos, lookups.into_iter().collect()));
+        }
+    }
+
     fn finalize_lookups_for_feature(&mut self, feature: Tag, pos: InsertionPoint) {
         let lookups = self.take_lookups_for_features(&[feature]);
         if !lookups.is_empty() {
@@ -619,10 +725,12 @@ impl MergeCtx<'_> {
             .collect()
     }
 
+    // a lookup may belong to more than one feature, in which case it is placed
+    // with the first of them that we process; GSUB ids are skipped.
     fn take_lookups_for_features(&mut self, features: &[Tag]) -> Vec<(LookupId, PositionLookup)> {
         self.lookup_ids_for_features(features)
             .into_iter()
-            .map(|id| (id, self.ext_pos_lookups.remove(&id).unwrap()))
+            .filter_map(|id| self.ext_pos_lookups.remove_entry(&id))
             .collect()
     }
 
@@ -686,6 +794,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn extend_pair_lookup() {
+        let gid = GlyphId16::new;
+        let value = |x: i16| ValueRecordBuilder::new().with_x_advance(x);
+        let pairs = [
+            (gid(3), value(-10), gid(4), ValueRecordBuilder::new()),
+            (gid(1), value(-20), gid(2), ValueRecordBuilder::new()),
+            (gid(3), value(-30), gid(1), ValueRecordBuilder::new()),
+            // replaces the first rule
+            (gid(3), value(-40), gid(4), ValueRecordBuilder::new()),
+        ];
+        let classes = [(
+            [gid(5), gid(6)].into_iter().collect::<GlyphSet>(),
+            value(-50),
+            [gid(7)].into_iter().collect::<GlyphSet>(),
+            ValueRecordBuilder::new(),
+        )];
+
+        let mut expected = PairPosBuilder::default();
+        for (one, val_one, two, val_two) in pairs.clone() {
+            expected.insert_pair(one, val_one, two, val_two);
+        }
+        for (one, val_one, two, val_two) in classes.clone() {
+            expected.insert_classes(one, val_one, two, val_two);
+        }
+
+        let mut lookup = PendingLookup::new(Vec::new(), LookupFlag::empty(), None);
+        lookup.extend_pairs(pairs);
+        lookup.extend_classes(classes);
+        assert_eq!(lookup.subtables(), [expected]);
+    }
+
     #[test]
     fn merge_external_lookups_before() {
         let mut all = AllLookups::default();
@@ -876,6 +1016,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn other_features_go_after_writer_features() {
+        const CPSP: Tag = Tag::new(b"cpsp");
+        const VKRN: Tag = Tag::new(b"vkrn");
+        let mut external = mock_external_features(&[VKRN, CPSP, KERN]);
+        let markers = make_markers_with_order([]);
+        let mut all = AllLookups::default();
+        let mut all_feats = AllFeatures::default();
+        external.merge_into(&mut all, &mut all_feats, &markers);
+        assert_eq!(all_feats.feature_order_for_test(), [KERN, CPSP, VKRN]);
+
+        // but an explicit marker is respected
+        let mut external = mock_external_features(&[VKRN, CPSP, KERN]);
+        let markers = make_markers_with_order([VKRN]);
+        let mut all = AllLookups::default();
+        let mut all_feats = AllFeatures::default();
+        external.merge_into(&mut all, &mut all_feats, &markers);
+        assert_eq!(all_feats.feature_order_for_test(), [VKRN, KERN, CPSP]);
+    }
+
     #[test]
     fn kern_and_dist_respect_input_order() {
         // for kern/dist lookups are inserted together, and respect the lookup
@@ -1,7 +1,8 @@
 //! Logic for tracking features during compilation
 
-use std::collections::{BTreeMap, HashMap, HashSet, hash_map};
+use std::collections::{BTreeMap, HashMap, HashSet, btree_map};
 
+use indexmap::{IndexMap, map::Entry as IndexEntry};
 use smol_str::SmolStr;
 use write_fonts::{
     tables::layout::{ConditionSet, FeatureParams, SizeParams, StylisticSetParams},
@@ -16,16 +17,19 @@ use super::{
 };
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct FeatureLookups {
     /// the base (not variation specific) lookups
     pub(crate) base: Vec<LookupId>,
-    pub(crate) variations: HashMap<ConditionSet, Vec<LookupId>>,
+    /// lookups for each conditionset, in the order they were first seen
+    pub(crate) variations: IndexMap<ConditionSet, Vec<LookupId>>,
 }
 
 /// A type to store accumulated features during compilation
 ///
 /// We update this type as we encounter feature blocks in the source FEA.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct AllFeatures {
     pub(crate) features: BTreeMap<FeatureKey, FeatureLookups>,
     required_features: HashSet<FeatureKey>,
@@ -45,8 +49,8 @@ pub(crate) struct ActiveFeature {
     condition_set: Option<ConditionSet>,
     default_systems: DefaultLanguageSystems,
     current_lang_sys: Option<LanguageSystem>,
-    lookups: HashMap<LanguageSystem, Vec<LookupId>>,
-    script_default_lookups: HashMap<Tag, Vec<LookupId>>,
+    lookups: BTreeMap<LanguageSystem, Vec<LookupId>>,
+    script_default_lookups: BTreeMap<Tag, Vec<LookupId>>,
 }
 
 /// State required to generate the aalt feature.
@@ -55,6 +59,7 @@ pub(crate) struct ActiveFeature {
 /// the aalt feature block, and then we use this to generate the aalt lookups
 /// once we've finished processing the input.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct AaltFeature {
     aalt_features: Vec<Tag>,
     pub(crate) all_alts: HashMap<GlyphId16, Vec<GlyphId16>>,
@@ -64,6 +69,7 @@ pub(crate) struct AaltFeature {
 
 /// Helper for compiling the `size` feature
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct SizeFeature {
     pub design_size: u16,
     pub identifier: u16,
@@ -73,6 +79,7 @@ pub(crate) struct SizeFeature {
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct CvParams {
     pub feat_ui_label_name: Vec<NameSpec>,
     pub feat_ui_tooltip_text_name: Vec<NameSpec>,
@@ -97,6 +104,7 @@ pub(crate) enum SpecialVerticalFeatureState {
 /// maps names to conditionsets, also tracking declaration order (which
 /// is maintained in the final output table)
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ConditionSetMap {
     named_conditionsets: HashMap<SmolStr, ConditionSet>,
     // used for sorting
@@ -273,7 +281,7 @@ impl AllFeatures {
                     // this is very unlikely; it would require feature variations
                     // specified in FEA as well as from a designspace or similar.
                     // In this case we will just append, but maybe we should log too?
-                    hash_map::Entry::Occupied(entry) => {
+                    IndexEntry::Occupied(entry) => {
                         let var_lookups = entry.into_mut();
                         var_lookups.extend_from_slice(&lookups);
                         log::warn!(
@@ -281,7 +289,7 @@ impl AllFeatures {
                             key.feature
                         );
                     }
-                    hash_map::Entry::Vacant(entry) => {
+                    IndexEntry::Vacant(entry) => {
                         entry.insert(lookups);
                     }
                 }
@@ -372,34 +380,40 @@ impl ActiveFeature {
         // but if this is a fully-resolved language system, we add the default
         // lookups now, when we have access to the 'exclude_dflt' flag.
         if system.language != tags::LANG_DFLT {
-            let mut lookups = Vec::new();
-            if !exclude_dflt {
-                let script_dflt = LanguageSystem {
-                    script: system.script,
-                    language: tags::LANG_DFLT,
-                };
-                // if *either* this is an explicit default, or this is part of
-                // a script where script/dflt is an explicit default, and we have
-                // seen a script keyword, add the default lookups
-                if self.default_systems.contains(&system)
-                    || (self.default_systems.contains(&script_dflt)
-                        && self.script_default_lookups.contains_key(&system.script))
-                {
-                    lookups.extend(
-                        self.lookups
-                            .get(&LanguageSystem::default())
-                            .into_iter()
-                            .flat_map(|v| v.iter().copied()),
-                    );
-                }
-                lookups.extend(
-                    self.script_default_lookups
-                        .get(&system.script)
+            let script_dflt = LanguageSystem {
+                script: system.script,
+                language: tags::LANG_DFLT,
+            };
+            // lookups registered before the first script statement apply to
+            // every registered language system; if the script default is
+            // registered, they are inherited through it by all of its languages.
+            let mut defaults = Vec::new();
+            if self.default_systems.contains(&system) || self.default_systems.contains(&script_dflt)
+            {
+                defaults.extend(
+                    self.lookups
+                        .get(&LanguageSystem::default())
                         .into_iter()
                         .flat_map(|v| v.iter().copied()),
                 );
             }
-            self.lookups.entry(system).or_insert_with(|| lookups);
+            defaults.extend(
+                self.script_default_lookups
+                    .get(&system.script)
+                    .into_iter()
+                    .flat_map(|v| v.iter().copied()),
+            );
+            match self.lookups.entry(system) {
+                // a repeated language statement with 'exclude_dflt' drops any
+                // default lookups that were included the first time around
+                btree_map::Entry::Occupied(mut entry) if exclude_dflt => {
+                    entry.get_mut().retain(|id| !defaults.contains(id))
+                }
+                btree_map::Entry::Occupied(_) => (),
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(if exclude_dflt { Vec::new() } else { defaults });
+                }
+            }
         }
 
         self.current_lang_sys = Some(system);
@@ -757,4 +771,42 @@ mod tests {
         let key = LATN_DEF.to_feature_key(TAG_TEST);
         assert_eq!(built.get_base(&key), Some([id8].as_slice()));
     }
+
+    #[test]
+    fn language_inherits_registered_script_default() {
+        let defaults = default_systems([DFLT_DFLT, LATN_DFLT]);
+        let [id1, id2] = make_ids();
+
+        let mut feature = ActiveFeature::new(TAG_TEST, defaults, None);
+        feature.add_lookup(id1);
+        // no lookups are added directly to the script default
+        feature.set_system(LATN_DFLT, false);
+        feature.set_system(LATN_DEU, false);
+        feature.add_lookup(id2);
+
+        let built = feature.build_features();
+        let key = LATN_DFLT.to_feature_key(TAG_TEST);
+        assert_eq!(built.get_base(&key), Some([id1].as_slice()));
+        // latn dflt is registered, so DEU inherits the root defaults through it
+        let key = LATN_DEU.to_feature_key(TAG_TEST);
+        assert_eq!(built.get_base(&key), Some([id1, id2].as_slice()));
+    }
+
+    #[test]
+    fn repeated_language_exclude_dflt() {
+        let defaults = default_systems([DFLT_DFLT, LATN_DFLT]);
+        let [id1, id2, id3] = make_ids();
+
+        let mut feature = ActiveFeature::new(TAG_TEST, defaults, None);
+        feature.add_lookup(id1);
+        feature.set_system(LATN_DFLT, false);
+        feature.set_system(LATN_DEU, false);
+        feature.add_lookup(id2);
+        feature.set_system(LATN_DEU, true);
+        feature.add_lookup(id3);
+
+        let built = feature.build_features();
+        let key = LATN_DEU.to_feature_key(TAG_TEST);
+        assert_eq!(built.get_base(&key), Some([id2, id3].as_slice()));
+    }
 }
@@ -0,0 +1,62 @@
+//! Resolving the glyphs in a glyph class.
+//!
+//! Validation, compilation, and the parallel pre-pass that resolves class
+//! literals ahead of compilation all need to expand class literals; they share
+//! this implementation so that they always agree on a class's members.
+
+use crate::{
+    GlyphMap, Kind,
+    common::{GlyphClass, GlyphId16},
+    token_tree::typed::{self, AstNode},
+};
+
+use super::glyph_range;
+
+/// The glyphs in a class literal, or `None` if some member can't be resolved.
+///
+/// A member can't be resolved if it is a glyph or CID that is not in the font,
+/// a malformed range, a named range with a member that is not in the font, or
+/// a named class for which `named_class` returns `None`. As in the spec,
+/// members of a CID range that aren't in the font are skipped.
+///
+/// This does not report anything; a caller that needs to explain why a class
+/// failed to resolve has to walk its members itself.
+pub(crate) fn resolve_class_literal(
+    glyph_map: &GlyphMap,
+    class: &typed::GlyphClassLiteral,
+    named_class: impl Fn(&typed::GlyphClassName) -> Option<GlyphClass>,
+) -> Option<Vec<GlyphId16>> {
+    let mut glyphs = Vec::new();
+    for item in class.items() {
+        if let Some(name) = typed::GlyphName::cast(&item) {
+            glyphs.push(glyph_map.get(name.text())?);
+        } else if let Some(cid) = typed::Cid::cast(&item) {
+            glyphs.push(glyph_map.get(&cid.parse())?);
+        } else if let Some(range) = typed::GlyphRange::cast(&item) {
+            let (start, end) = (range.start(), range.end());
+            match (start.kind, end.kind) {
+                (Kind::Cid, Kind::Cid) => glyph_range::cid(&start, &end, |cid| {
+                    glyphs.extend(glyph_map.get(&cid));
+                })
+                .ok()?,
+                (Kind::GlyphName, Kind::GlyphName) => {
+                    let mut missing = false;
+                    glyph_range::named(&start, &end, |name| match glyph_map.get(name) {
+                        Some(id) => glyphs.push(id),
+                        None => missing = true,
+                    })
+                    .ok()?;
+                    if missing {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        } else if let Some(alias) = typed::GlyphClassName::cast(&item) {
+            glyphs.extend(named_class(&alias)?.items());
+        } else {
+            return None;
+        }
+    }
+    Some(glyphs)
+}
@@ -130,26 +130,21 @@ fn get_diff_range(one: &str, two: &str) -> Range<usize> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        GlyphMap, Kind, Node,
-        common::GlyphIdent,
-        token_tree::TreeBuilder,
-        typed::{self, AstNode},
-    };
+    use crate::{GlyphMap, Kind, Node, common::GlyphIdent, token_tree::TreeBuilder, typed};
 
     use super::*;
 
     /// A helper for testing, that just returns the names/cids that should be part
     /// of a given range. (This does not test if they're in the font.)
     fn glyph_range(node: &Node) -> Result<Vec<GlyphIdent>, String> {
-        let range = typed::GlyphRange::cast(&node.clone().into()).unwrap();
+        let range = typed::GlyphRange::try_from_node(node).unwrap();
         let start = range.start();
         let end = range.end();
         let mut result = Vec::new();
 
         match (start.kind, end.kind) {
-            (Kind::Cid, Kind::Cid) => cid(start, end, |cid| result.push(GlyphIdent::Cid(cid)))?,
-            (Kind::GlyphName, Kind::GlyphName) => named(start, end, |string| {
+            (Kind::Cid, Kind::Cid) => cid(&start, &end, |cid| result.push(GlyphIdent::Cid(cid)))?,
+            (Kind::GlyphName, Kind::GlyphName) => named(&start, &end, |string| {
                 result.push(GlyphIdent::Name(string.into()))
             })?,
             (_, _) => return Err("Invalid glyph range".to_string()),
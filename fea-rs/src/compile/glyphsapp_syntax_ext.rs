@@ -147,7 +147,7 @@ mod tests {
         let node = crate::parse::parse_node(text, |p| {
             crate::parse::grammar::expect_glyphs_number_value(p, TokenSet::EMPTY);
         });
-        let node = typed::GlyphsAppNumber::cast(&node.into()).unwrap();
+        let node = typed::GlyphsAppNumber::try_from_node(&node).unwrap();
         node.iter()
             .find_map(typed::GlyphsAppNumberExpr::cast)
             .unwrap()
@@ -1,16 +1,19 @@
 //! gsub/gpos lookup table stuff
 
 mod contextual;
+mod mark_to_base;
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryInto,
     fmt::Debug,
 };
 
+use indexmap::IndexMap;
 use smol_str::SmolStr;
 
 use write_fonts::{
+    FontWrite, OffsetMarker,
     tables::{
         gdef::GlyphClassDef,
         gpos::{
@@ -30,51 +33,385 @@ use write_fonts::{
         layout::{
             ConditionSet as RawConditionSet, Feature, FeatureList, FeatureRecord,
             FeatureTableSubstitution, FeatureTableSubstitutionRecord, FeatureVariationRecord,
-            FeatureVariations, LangSys, LangSysRecord, LookupFlag, LookupList, Script, ScriptList,
-            ScriptRecord,
-            builders::{Builder, LookupBuilder},
+            FeatureVariations, LangSys, LangSysRecord, Lookup, LookupFlag, LookupList, Script,
+            ScriptList, ScriptRecord,
+            builders::{Builder, DeviceOrDeltas, LookupBuilder, Metric},
         },
         variations::ivs_builder::VariationStoreBuilder,
     },
     types::Tag,
+    validate::Validate,
 };
 
 use crate::{
     Kind, Opts,
-    common::{GlyphId16, GlyphOrClass, GlyphSet},
+    common::{GlyphId16, GlyphOrClass, GlyphSet, MarkClass, SharedGlyphSet},
     compile::lookups::contextual::ChainOrNot,
 };
 
-use super::{features::AllFeatures, tags};
+use super::{closure::SubstitutionRules, features::AllFeatures, stats, tags};
 
 use contextual::{
     ContextualLookupBuilder, PosChainContextBuilder, PosContextBuilder, ReverseChainBuilder,
     SubChainContextBuilder, SubContextBuilder,
 };
+use mark_to_base::{MarkToBaseSubtable, MarkToMarkSubtable};
 
 pub(crate) type FilterSetId = u16;
 
+/// Serde impls for the write-fonts `LookupBuilder`, which doesn't provide them.
+///
+/// The GSUB subtable builders in write-fonts don't implement serde either, so
+/// those are stored as a list of their rules.
+#[cfg(feature = "serde")]
+mod serde_lookup_builder {
+    use std::collections::BTreeSet;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+    use write_fonts::tables::{
+        gpos::builders::{
+            CursivePosBuilder, MarkToBaseBuilder, MarkToLigBuilder, MarkToMarkBuilder,
+            PairPosBuilder, SinglePosBuilder,
+        },
+        gsub::builders::{
+            AlternateSubBuilder, LigatureSubBuilder, MultipleSubBuilder, SingleSubBuilder,
+        },
+        layout::{LookupFlag, builders::LookupBuilder},
+    };
+
+    use super::{
+        FilterSetId, GlyphId16, MarkToBaseSubtable, MarkToMarkSubtable,
+        contextual::{
+            PosChainContextBuilder, PosContextBuilder, ReverseChainBuilder, SubChainContextBuilder,
+            SubContextBuilder,
+        },
+    };
+
+    /// The serialized form of a subtable builder.
+    pub(super) trait SubtableRepr: Sized {
+        type Repr: Serialize + DeserializeOwned;
+        fn to_repr(&self) -> Self::Repr;
+        fn from_repr(repr: Self::Repr) -> Self;
+    }
+
+    macro_rules! repr_as_self {
+        ($($builder:ty),*) => {
+            $(
+                impl SubtableRepr for $builder {
+                    type Repr = $builder;
+                    fn to_repr(&self) -> Self::Repr {
+                        self.clone()
+                    }
+                    fn from_repr(repr: Self::Repr) -> Self {
+                        repr
+                    }
+                }
+            )*
+        };
+    }
+
+    repr_as_self!(
+        SinglePosBuilder,
+        PairPosBuilder,
+        CursivePosBuilder,
+        MarkToLigBuilder,
+        PosContextBuilder,
+        PosChainContextBuilder,
+        SubContextBuilder,
+        SubChainContextBuilder,
+        ReverseChainBuilder
+    );
+
+    impl SubtableRepr for MarkToBaseSubtable {
+        type Repr = (MarkToBaseBuilder, BTreeSet<GlyphId16>);
+        fn to_repr(&self) -> Self::Repr {
+            (self.builder.clone(), self.null_bases.clone())
+        }
+        fn from_repr((builder, null_bases): Self::Repr) -> Self {
+            MarkToBaseSubtable {
+                builder,
+                null_bases,
+            }
+        }
+    }
+
+    impl SubtableRepr for MarkToMarkSubtable {
+        type Repr = (MarkToMarkBuilder, BTreeSet<GlyphId16>);
+        fn to_repr(&self) -> Self::Repr {
+            (self.builder.clone(), self.null_mark2s.clone())
+        }
+        fn from_repr((builder, null_mark2s): Self::Repr) -> Self {
+            MarkToMarkSubtable {
+                builder,
+                null_mark2s,
+            }
+        }
+    }
+
+    impl SubtableRepr for SingleSubBuilder {
+        type Repr = Vec<(GlyphId16, GlyphId16)>;
+        fn to_repr(&self) -> Self::Repr {
+            self.iter().collect()
+        }
+        fn from_repr(repr: Self::Repr) -> Self {
+            let mut builder = SingleSubBuilder::default();
+            for (target, replacement) in repr {
+                builder.insert(target, replacement);
+            }
+            builder
+        }
+    }
+
+    impl SubtableRepr for MultipleSubBuilder {
+        type Repr = Vec<(GlyphId16, Vec<GlyphId16>)>;
+        fn to_repr(&self) -> Self::Repr {
+            self.iter()
+                .map(|(target, seq)| (*target, seq.clone()))
+                .collect()
+        }
+        fn from_repr(repr: Self::Repr) -> Self {
+            let mut builder = MultipleSubBuilder::default();
+            for (target, replacement) in repr {
+                builder.insert(target, replacement);
+            }
+            builder
+        }
+    }
+
+    impl SubtableRepr for AlternateSubBuilder {
+        type Repr = Vec<(GlyphId16, Vec<GlyphId16>)>;
+        fn to_repr(&self) -> Self::Repr {
+            self.iter()
+                .map(|(target, alts)| (*target, alts.clone()))
+                .collect()
+        }
+        fn from_repr(repr: Self::Repr) -> Self {
+            let mut builder = AlternateSubBuilder::default();
+            for (target, replacement) in repr {
+                builder.insert(target, replacement);
+            }
+            builder
+        }
+    }
+
+    impl SubtableRepr for LigatureSubBuilder {
+        type Repr = Vec<(Vec<GlyphId16>, GlyphId16)>;
+        fn to_repr(&self) -> Self::Repr {
+            self.iter()
+                .flat_map(|(first, ligs)| {
+                    ligs.iter().map(|(rest, replacement)| {
+                        let target = std::iter::once(*first).chain(rest.iter().copied());
+                        (target.collect(), *replacement)
+                    })
+                })
+                .collect()
+        }
+        fn from_repr(repr: Self::Repr) -> Self {
+            let mut builder = LigatureSubBuilder::default();
+            for (target, replacement) in repr {
+                builder.insert(target, replacement);
+            }
+            builder
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Repr<T> {
+        flags: LookupFlag,
+        mark_set: Option<FilterSetId>,
+        subtables: Vec<T>,
+    }
+
+    pub(super) fn serialize<T: SubtableRepr, S: Serializer>(
+        lookup: &LookupBuilder<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Repr {
+            flags: lookup.flags,
+            mark_set: lookup.mark_set,
+            subtables: lookup.subtables.iter().map(T::to_repr).collect(),
+        }
+        .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, T: SubtableRepr, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<LookupBuilder<T>, D::Error> {
+        let repr = Repr::<T::Repr>::deserialize(deserializer)?;
+        Ok(LookupBuilder {
+            flags: repr.flags,
+            mark_set: repr.mark_set,
+            subtables: repr.subtables.into_iter().map(T::from_repr).collect(),
+        })
+    }
+}
+
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct AllLookups {
     current: Option<SomeLookup>,
     current_name: Option<SmolStr>,
     gpos: Vec<PositionLookup>,
     gsub: Vec<SubstitutionLookup>,
     named: HashMap<SmolStr, LookupId>,
+    // whether the current named lookup was declared with `useExtension`
+    current_use_extension: bool,
+    // lookups that should be wrapped in extension subtables
+    extension: HashSet<LookupId>,
+    // the position in the tree of the statement that started each lookup
+    sites: HashMap<LookupId, usize>,
+    // the position of the current named lookup block
+    current_name_site: Option<usize>,
+    // the class pair rules of the current subtable, which are added to the
+    // lookup together once the subtable is finished. Lookups are always
+    // finished before the state is saved, so this is empty then.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pending_class_pairs: Vec<ClassPairRule>,
+    // whether overlapping classes in class pair rules are split; see
+    // `Opts::merge_class_pairs`
+    merge_class_pairs: bool,
+    // the size of the class pair subtable in the current lookup
+    class_pair_size: ClassPairSize,
+    // the size of the mark attachment subtable in the current lookup
+    mark_attach_size: MarkAttachSize,
+    // the size of the mark-to-ligature subtable in the current lookup
+    mark_lig_size: MarkLigSize,
+    // the size of the single, glyph pair and cursive rules in the current lookup
+    rule_size: RuleSize,
+    // the size of the finished subtables of the current lookup
+    finished_size: usize,
+    // the estimated size of each GPOS lookup built from rules; the write-fonts
+    // subtable builders don't expose their contents, so these are tracked as
+    // the rules are added.
+    gpos_sizes: HashMap<LookupId, usize>,
+}
+
+/// A class pair rule: the first and second classes, and their value records.
+type ClassPairRule = (SharedGlyphSet, SharedGlyphSet, ValueRecord, ValueRecord);
+
+/// An estimate of the size of a class-based pair positioning subtable.
+///
+/// These subtables contain a value record for every combination of first and
+/// second class, which means that large ones can overflow the 16-bit offsets
+/// to their coverage and class definition tables. We track this as rules are
+/// added, so that we can start a new subtable before this happens.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ClassPairSize {
+    first_classes: HashSet<SharedGlyphSet>,
+    second_classes: HashSet<SharedGlyphSet>,
+    first_glyphs: usize,
+    second_glyphs: usize,
+    // the size of the first and second value records, together
+    record_size: usize,
+    // the value formats of every first and second record, which the records
+    // of a subtable share
+    formats: (u16, u16),
+    // the size of the device tables of every record
+    device_bytes: usize,
+}
+
+/// An estimate of the size of a mark-to-base or mark-to-mark subtable.
+///
+/// The base array has an anchor offset for every combination of base glyph
+/// and mark class, so subtables with many bases and classes can overflow the
+/// 16-bit offsets to the anchors.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct MarkAttachSize {
+    classes: HashSet<SmolStr>,
+    marks: HashSet<GlyphId16>,
+    bases: HashSet<GlyphId16>,
+    // an upper bound on the number of distinct anchors
+    anchors: usize,
+    // an upper bound on the size of those anchors, with their device tables
+    anchor_bytes: usize,
+}
+
+/// An estimate of the size of a mark-to-ligature subtable.
+///
+/// Unlike the other mark attachment subtables, these are never split, so
+/// this is only used to estimate the size of the lookup.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct MarkLigSize {
+    classes: HashSet<SmolStr>,
+    marks: HashSet<GlyphId16>,
+    // the number of components of each ligature
+    ligatures: HashMap<GlyphId16, usize>,
+    // an upper bound on the size of the distinct anchors
+    anchor_bytes: usize,
+}
+
+/// An estimate of the size of the single, glyph pair and cursive rules in a lookup.
+///
+/// The subtables for these rules grow with each rule, so this covers the
+/// whole lookup rather than a single subtable.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct RuleSize {
+    n_rules: usize,
+    // the size of each rule apart from its value records
+    bytes: usize,
+    // the value formats of every first and second record; write-fonts may
+    // give the records of a subtable the union of their formats
+    formats: (u16, u16),
+    // the size of device tables and anchors
+    extra_bytes: usize,
+}
+
+/// What we know about where a lookup came from, for debugging.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LookupDebugInfo {
+    /// The position in the tree of the statement that started this lookup,
+    /// or of its lookup block, if it was named.
+    ///
+    /// This is `None` for lookups that were not generated from the FEA.
+    pub(crate) site: Option<usize>,
+    /// The name of the lookup block, if it was named.
+    pub(crate) name: Option<SmolStr>,
+    /// The first feature that uses this lookup, and its position in that
+    /// feature's lookups.
+    pub(crate) feature: Option<(FeatureKey, usize)>,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum PositionLookup {
-    Single(LookupBuilder<SinglePosBuilder>),
-    Pair(LookupBuilder<PairPosBuilder>),
-    Cursive(LookupBuilder<CursivePosBuilder>),
-    MarkToBase(LookupBuilder<MarkToBaseBuilder>),
-    MarkToLig(LookupBuilder<MarkToLigBuilder>),
-    MarkToMark(LookupBuilder<MarkToMarkBuilder>),
+    Single(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<SinglePosBuilder>,
+    ),
+    Pair(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<PairPosBuilder>,
+    ),
+    Cursive(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<CursivePosBuilder>,
+    ),
+    MarkToBase(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<MarkToBaseSubtable>,
+    ),
+    MarkToLig(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<MarkToLigBuilder>,
+    ),
+    MarkToMark(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<MarkToMarkSubtable>,
+    ),
     // currently unused, matching feaLib: <https://github.com/fonttools/fonttools/issues/2539>
     #[allow(dead_code)]
-    Contextual(LookupBuilder<PosContextBuilder>),
-    ChainedContextual(LookupBuilder<PosChainContextBuilder>),
+    Contextual(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<PosContextBuilder>,
+    ),
+    ChainedContextual(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<PosChainContextBuilder>,
+    ),
 }
 
 // a litle helper to implement this conversion trait.
@@ -92,25 +429,70 @@ macro_rules! impl_into_lookup {
     };
 }
 
+impl_into_lookup!(SinglePosBuilder, PositionLookup, Single);
 impl_into_lookup!(PairPosBuilder, PositionLookup, Pair);
-impl_into_lookup!(MarkToBaseBuilder, PositionLookup, MarkToBase);
-impl_into_lookup!(MarkToMarkBuilder, PositionLookup, MarkToMark);
 impl_into_lookup!(MarkToLigBuilder, PositionLookup, MarkToLig);
 impl_into_lookup!(CursivePosBuilder, PositionLookup, Cursive);
 impl_into_lookup!(SingleSubBuilder, SubstitutionLookup, Single);
+impl_into_lookup!(MultipleSubBuilder, SubstitutionLookup, Multiple);
+impl_into_lookup!(AlternateSubBuilder, SubstitutionLookup, Alternate);
+impl_into_lookup!(LigatureSubBuilder, SubstitutionLookup, Ligature);
+
+impl From<LookupBuilder<MarkToBaseBuilder>> for PositionLookup {
+    fn from(src: LookupBuilder<MarkToBaseBuilder>) -> PositionLookup {
+        PositionLookup::MarkToBase(LookupBuilder {
+            flags: src.flags,
+            mark_set: src.mark_set,
+            subtables: src.subtables.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+impl From<LookupBuilder<MarkToMarkBuilder>> for PositionLookup {
+    fn from(src: LookupBuilder<MarkToMarkBuilder>) -> PositionLookup {
+        PositionLookup::MarkToMark(LookupBuilder {
+            flags: src.flags,
+            mark_set: src.mark_set,
+            subtables: src.subtables.into_iter().map(Into::into).collect(),
+        })
+    }
+}
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum SubstitutionLookup {
-    Single(LookupBuilder<SingleSubBuilder>),
-    Multiple(LookupBuilder<MultipleSubBuilder>),
-    Alternate(LookupBuilder<AlternateSubBuilder>),
-    Ligature(LookupBuilder<LigatureSubBuilder>),
-    Contextual(LookupBuilder<SubContextBuilder>),
-    ChainedContextual(LookupBuilder<SubChainContextBuilder>),
-    Reverse(LookupBuilder<ReverseChainBuilder>),
+    Single(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<SingleSubBuilder>,
+    ),
+    Multiple(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<MultipleSubBuilder>,
+    ),
+    Alternate(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<AlternateSubBuilder>,
+    ),
+    Ligature(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<LigatureSubBuilder>,
+    ),
+    Contextual(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<SubContextBuilder>,
+    ),
+    ChainedContextual(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<SubChainContextBuilder>,
+    ),
+    Reverse(
+        #[cfg_attr(feature = "serde", serde(with = "serde_lookup_builder"))]
+        LookupBuilder<ReverseChainBuilder>,
+    ),
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum SomeLookup {
     GsubLookup(SubstitutionLookup),
     GposLookup(PositionLookup),
@@ -120,6 +502,7 @@ pub(crate) enum SomeLookup {
 
 /// IDs assigned to lookups during compilation
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LookupId {
     /// An id for a GPOS lookup
     Gpos(usize),
@@ -158,6 +541,7 @@ pub(crate) struct LookupIdMap {
 
 /// Tracks the current lookupflags state
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct LookupFlagInfo {
     pub(crate) flags: LookupFlag,
     pub(crate) mark_filter_set: Option<FilterSetId>,
@@ -181,8 +565,9 @@ pub(crate) struct PosSubBuilder<T> {
     scripts: BTreeMap<Tag, BTreeMap<Tag, LangSys>>,
     // map a feature tag + set of lookups to an index
     features: BTreeMap<(Tag, Vec<LookupIdx>), FeatureIdx>,
-    // map a conditionset to a map of target features and the lookups to substitute
-    variations: HashMap<RawConditionSet, HashMap<FeatureIdx, Vec<LookupIdx>>>,
+    // map a conditionset to a map of target features and the lookups to substitute;
+    // conditionsets keep insertion order, and are sorted again after building
+    variations: IndexMap<RawConditionSet, BTreeMap<FeatureIdx, Vec<LookupIdx>>>,
 }
 
 trait RemapIds {
@@ -271,9 +656,77 @@ impl PositionLookup {
             PositionLookup::ChainedContextual(lookup) => lookup.force_subtable_break(),
         }
     }
+
+    /// An estimate of the serialized size of this lookup, without building it.
+    ///
+    /// This is an upper bound; see [`SubstitutionLookup::estimated_size`].
+    /// Returns `None` for lookups whose subtables are built entirely by
+    /// write-fonts, which does not expose their contents; the sizes of those
+    /// are tracked by [`AllLookups`] as their rules are added.
+    fn estimated_size(&self) -> Option<usize> {
+        match self {
+            PositionLookup::Contextual(lookup) => {
+                Some(estimate_lookup(lookup, PosContextBuilder::estimated_size))
+            }
+            PositionLookup::ChainedContextual(lookup) => Some(estimate_lookup(
+                lookup,
+                PosChainContextBuilder::estimated_size,
+            )),
+            _ => None,
+        }
+    }
 }
 
 impl SubstitutionLookup {
+    /// An estimate of the serialized size of this lookup, without building it.
+    ///
+    /// This is an upper bound: it assumes the largest format for every table,
+    /// and that no tables are shared. It is much cheaper than building and
+    /// serializing the lookup.
+    pub(crate) fn estimated_size(&self) -> usize {
+        match self {
+            SubstitutionLookup::Single(lookup) => estimate_lookup(lookup, |sub| {
+                let n_glyphs = sub.iter().count();
+                6 + 2 * n_glyphs + coverage_size(n_glyphs)
+            }),
+            SubstitutionLookup::Multiple(lookup) => estimate_lookup(lookup, |sub| {
+                let (n_glyphs, sequences) =
+                    sub.iter().fold((0, 0), |(n_glyphs, size), (_, sequence)| {
+                        (n_glyphs + 1, size + 4 + 2 * sequence.len())
+                    });
+                6 + coverage_size(n_glyphs) + sequences
+            }),
+            SubstitutionLookup::Alternate(lookup) => estimate_lookup(lookup, |sub| {
+                let targets = sub
+                    .iter_pairs()
+                    .map(|(target, _)| target)
+                    .collect::<BTreeSet<_>>();
+                let n_alternates = sub.iter_pairs().count();
+                6 + coverage_size(targets.len()) + 4 * targets.len() + 2 * n_alternates
+            }),
+            SubstitutionLookup::Ligature(lookup) => estimate_lookup(lookup, |sub| {
+                let (n_glyphs, sets) =
+                    sub.iter().fold((0, 0), |(n_glyphs, size), (_, ligatures)| {
+                        let ligatures = ligatures
+                            .iter()
+                            .map(|(components, _)| 6 + 2 * components.len())
+                            .sum::<usize>();
+                        (n_glyphs + 1, size + 4 + ligatures)
+                    });
+                6 + coverage_size(n_glyphs) + sets
+            }),
+            SubstitutionLookup::Contextual(lookup) => {
+                estimate_lookup(lookup, SubContextBuilder::estimated_size)
+            }
+            SubstitutionLookup::ChainedContextual(lookup) => {
+                estimate_lookup(lookup, SubChainContextBuilder::estimated_size)
+            }
+            SubstitutionLookup::Reverse(lookup) => {
+                estimate_lookup(lookup, ReverseChainBuilder::estimated_size)
+            }
+        }
+    }
+
     fn remap_ids(&mut self, id_map: &LookupIdMap) {
         match self {
             SubstitutionLookup::Contextual(lookup) => lookup.remap_ids(id_map),
@@ -307,6 +760,19 @@ impl SubstitutionLookup {
     }
 }
 
+impl SubstitutionRules for SubstitutionLookup {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        match self {
+            SubstitutionLookup::Single(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Multiple(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Alternate(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Ligature(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Reverse(lookup) => lookup.for_each_rule(f),
+            SubstitutionLookup::Contextual(_) | SubstitutionLookup::ChainedContextual(_) => (),
+        }
+    }
+}
+
 impl Builder for PositionLookup {
     type Output = write_gpos::PositionLookup;
 
@@ -373,6 +839,14 @@ impl Builder for SubstitutionLookup {
 }
 
 impl AllLookups {
+    /// Create an empty set of lookups for a compilation with these options.
+    pub(crate) fn new(opts: &Opts) -> Self {
+        AllLookups {
+            merge_class_pairs: opts.merge_class_pairs,
+            ..Default::default()
+        }
+    }
+
     fn push(&mut self, lookup: SomeLookup) -> LookupId {
         match lookup {
             SomeLookup::GsubLookup(sub) => {
@@ -398,6 +872,7 @@ impl AllLookups {
                         .push(PositionLookup::ChainedContextual(lookup.convert())),
                 }
                 self.gpos.extend(anon_lookups);
+                self.share_site(id, self.gpos.len());
                 id
             }
             SomeLookup::GsubContextual(lookup) => {
@@ -413,11 +888,26 @@ impl AllLookups {
                         .push(SubstitutionLookup::ChainedContextual(lookup.convert())),
                 }
                 self.gsub.extend(anon_lookups);
+                self.share_site(id, self.gsub.len());
                 id
             }
         }
     }
 
+    // anonymous lookups created by a contextual rule share its position
+    fn share_site(&mut self, root: LookupId, end: usize) {
+        let Some(site) = self.sites.get(&root).copied() else {
+            return;
+        };
+        for idx in root.to_raw() + 1..end {
+            let id = match root {
+                LookupId::Gpos(_) => LookupId::Gpos(idx),
+                _ => LookupId::Gsub(idx),
+            };
+            self.sites.insert(id, site);
+        }
+    }
+
     pub(crate) fn get_named(&self, name: &str) -> Option<LookupId> {
         self.named.get(name).copied()
     }
@@ -430,6 +920,17 @@ impl AllLookups {
         self.current.is_some()
     }
 
+    /// The id that the current lookup will have when it is finished.
+    pub(crate) fn current_id(&self) -> Option<LookupId> {
+        self.current.as_ref().map(|lookup| {
+            if is_gpos_rule(lookup.kind()) {
+                self.next_gpos_id()
+            } else {
+                self.next_gsub_id()
+            }
+        })
+    }
+
     pub(crate) fn next_gpos_id(&self) -> LookupId {
         LookupId::Gpos(self.gpos.len())
     }
@@ -448,7 +949,9 @@ impl AllLookups {
         pos: usize,
         lookups: impl IntoIterator<Item = PositionLookup>,
     ) {
+        let len = self.gpos.len();
         self.gpos.splice(pos..pos, lookups);
+        self.bump_ids(LookupId::Gpos(pos), self.gpos.len() - len);
     }
 
     /// insert a sequence of lookups into the GPOS list at a specific pos.
@@ -461,7 +964,96 @@ impl AllLookups {
         pos: usize,
         lookups: impl IntoIterator<Item = SubstitutionLookup>,
     ) {
+        let len = self.gsub.len();
         self.gsub.splice(pos..pos, lookups);
+        self.bump_ids(LookupId::Gsub(pos), self.gsub.len() - len);
+    }
+
+    /// Adjust the ids of extension lookups after `n` lookups are inserted at `pos`.
+    // update the ids we track after `n` lookups are inserted at `pos`
+    fn bump_ids(&mut self, pos: LookupId, n: usize) {
+        let bump = |id: LookupId| match (id, pos) {
+            (LookupId::Gpos(idx), LookupId::Gpos(pos)) if idx >= pos => LookupId::Gpos(idx + n),
+            (LookupId::Gsub(idx), LookupId::Gsub(pos)) if idx >= pos => LookupId::Gsub(idx + n),
+            (other, _) => other,
+        };
+        self.extension = self.extension.iter().copied().map(bump).collect();
+        self.named.values_mut().for_each(|id| *id = bump(*id));
+        self.sites = self
+            .sites
+            .drain()
+            .map(|(id, site)| (bump(id), site))
+            .collect();
+        self.gpos_sizes = self
+            .gpos_sizes
+            .drain()
+            .map(|(id, size)| (bump(id), size))
+            .collect();
+    }
+
+    /// Debug info for each lookup in the GSUB and GPOS lookup lists.
+    pub(crate) fn debug_info(
+        &self,
+        features: &AllFeatures,
+    ) -> (Vec<LookupDebugInfo>, Vec<LookupDebugInfo>) {
+        let mut gsub = vec![LookupDebugInfo::default(); self.gsub.len()];
+        let mut gpos = vec![LookupDebugInfo::default(); self.gpos.len()];
+        fn slot<'a>(
+            gsub: &'a mut [LookupDebugInfo],
+            gpos: &'a mut [LookupDebugInfo],
+            id: LookupId,
+        ) -> Option<&'a mut LookupDebugInfo> {
+            match id {
+                LookupId::Gsub(idx) => gsub.get_mut(idx),
+                LookupId::Gpos(idx) => gpos.get_mut(idx),
+                _ => None,
+            }
+        }
+        for (id, site) in &self.sites {
+            if let Some(info) = slot(&mut gsub, &mut gpos, *id) {
+                info.site = Some(*site);
+            }
+        }
+        for (name, id) in &self.named {
+            if let Some(info) = slot(&mut gsub, &mut gpos, *id) {
+                info.name = Some(name.clone());
+            }
+        }
+        for (key, lookups) in &features.features {
+            for (i, id) in lookups.iter_ids().enumerate() {
+                if let Some(info) = slot(&mut gsub, &mut gpos, id) {
+                    info.feature.get_or_insert((*key, i));
+                }
+            }
+        }
+        (gsub, gpos)
+    }
+
+    /// Names for each lookup in the GSUB and GPOS lookup lists, for debugging.
+    ///
+    /// Named lookups use their name in the FEA. Other lookups are named for
+    /// the first feature (by tag, script and language) that uses them and
+    /// their position in that feature, such as `kern/DFLT/dflt auto #3`, or
+    /// else for their position in the lookup list.
+    pub(crate) fn lookup_names(&self, features: &AllFeatures) -> (Vec<String>, Vec<String>) {
+        let name = |(i, info): (usize, LookupDebugInfo)| match info {
+            LookupDebugInfo {
+                name: Some(name), ..
+            } => name.to_string(),
+            LookupDebugInfo {
+                feature: Some((key, pos)),
+                ..
+            } => format!(
+                "{}/{}/{} auto #{pos}",
+                key.feature, key.script, key.language
+            ),
+            _ => format!("anonymous #{i}"),
+        };
+        let (gsub, gpos) = self.debug_info(features);
+        (
+            gsub.into_iter().enumerate().map(name).collect(),
+            gpos.into_iter().enumerate().map(name).collect(),
+        )
     }
 
     /// Returns `true` if there is an active lookup of this kind
@@ -475,6 +1067,8 @@ impl AllLookups {
 
     // `false` if we didn't have an active lookup
     pub(crate) fn add_subtable_break(&mut self) -> bool {
+        self.flush_class_pairs();
+        self.finish_subtable_size();
         if let Some(current) = self.current.as_mut() {
             match current {
                 SomeLookup::GsubLookup(lookup) => lookup.force_subtable_break(),
@@ -488,13 +1082,183 @@ impl AllLookups {
         }
     }
 
+    /// Add a class pair rule to the current lookup, which must be a pair lookup.
+    ///
+    /// The rule is added when the current subtable is finished, so that the
+    /// classes of all its rules can be merged; see [`merge_class_pairs`].
+    pub(crate) fn add_gpos_type_2_class(
+        &mut self,
+        one: SharedGlyphSet,
+        two: SharedGlyphSet,
+        val_one: ValueRecord,
+        val_two: ValueRecord,
+    ) {
+        self.pending_class_pairs.push((one, two, val_one, val_two));
+    }
+
+    /// Add the pending class pair rules to the current lookup.
+    ///
+    /// If the current subtable would become too large to serialize, a new
+    /// subtable is started.
+    fn flush_class_pairs(&mut self) {
+        if self.pending_class_pairs.is_empty() {
+            return;
+        }
+        let mut rules = std::mem::take(&mut self.pending_class_pairs);
+        if self.merge_class_pairs {
+            rules = merge_class_pairs(rules);
+        }
+        for (one, two, val_one, val_two) in rules {
+            let record_size = value_record_size(&val_one) + value_record_size(&val_two);
+            if !self.class_pair_size.insert(&one, &two, record_size) {
+                log::debug!("class pair subtable is too large, adding a subtable break");
+                self.add_subtable_break();
+                self.class_pair_size.insert(&one, &two, record_size);
+            }
+            self.class_pair_size.add_records(&val_one, &val_two);
+            self.current
+                .as_mut()
+                .expect("rules have a lookup")
+                .add_gpos_type_2_class(one.into_inner(), two.into_inner(), val_one, val_two);
+        }
+    }
+
+    /// Make room for a mark-to-base or mark-to-mark rule in the current lookup.
+    ///
+    /// If adding the rule would make the current subtable too large to
+    /// serialize, a new subtable is started.
+    pub(crate) fn reserve_mark_attachment(
+        &mut self,
+        class_name: &str,
+        mark_class: &MarkClass,
+        bases: &[GlyphId16],
+        base_anchor: Option<&Anchor>,
+    ) {
+        let marks = mark_class
+            .anchored_members()
+            .flat_map(|(glyphs, _)| glyphs.iter())
+            .collect::<Vec<_>>();
+        let n_anchors = mark_class.anchored_members().count() + usize::from(base_anchor.is_some());
+        if !self
+            .mark_attach_size
+            .insert(class_name, &marks, bases, n_anchors)
+        {
+            log::debug!("mark attachment subtable is too large, adding a subtable break");
+            self.add_subtable_break();
+            self.mark_attach_size
+                .insert(class_name, &marks, bases, n_anchors);
+        }
+        self.mark_attach_size.anchor_bytes += mark_class
+            .anchored_members()
+            .map(|(_, anchor)| anchor)
+            .chain(base_anchor)
+            .map(anchor_size)
+            .sum::<usize>();
+    }
+
+    /// Record a mark-to-ligature rule in the size estimate of the current lookup.
+    ///
+    /// `components` has the anchor for each mark class, for each component
+    /// of the ligatures.
+    pub(crate) fn reserve_mark_to_ligature<'a>(
+        &mut self,
+        mark_classes: impl IntoIterator<Item = (&'a str, &'a MarkClass)>,
+        ligatures: &[GlyphId16],
+        components: &[BTreeMap<String, Anchor>],
+    ) {
+        let size = &mut self.mark_lig_size;
+        for (class_name, mark_class) in mark_classes {
+            size.classes.insert(class_name.into());
+            for (glyphs, anchor) in mark_class.anchored_members() {
+                size.marks.extend(glyphs.iter());
+                size.anchor_bytes += anchor_size(anchor);
+            }
+        }
+        for ligature in ligatures {
+            let n_components = size.ligatures.entry(*ligature).or_default();
+            *n_components = components.len().max(*n_components);
+        }
+        let component_anchors = components
+            .iter()
+            .flat_map(|anchors| anchors.values())
+            .map(anchor_size)
+            .sum::<usize>();
+        size.anchor_bytes += ligatures.len() * component_anchors;
+    }
+
+    /// Add a single positioning rule to the current lookup.
+    pub(crate) fn add_gpos_type_1(&mut self, id: GlyphId16, record: ValueRecord) {
+        self.rule_size.add_rule(2, Some(&record), None);
+        self.current
+            .as_mut()
+            .expect("rules have a lookup")
+            .add_gpos_type_1(id, record);
+    }
+
+    /// Add a glyph pair rule to the current lookup, which must be a pair lookup.
+    pub(crate) fn add_gpos_type_2_pair(
+        &mut self,
+        one: GlyphId16,
+        two: GlyphId16,
+        val_one: ValueRecord,
+        val_two: ValueRecord,
+    ) {
+        // the second glyph, and the coverage, offset and count of a new pair set
+        self.rule_size.add_rule(8, Some(&val_one), Some(&val_two));
+        self.current
+            .as_mut()
+            .expect("rules have a lookup")
+            .add_gpos_type_2_pair(one, two, val_one, val_two);
+    }
+
+    /// Add a cursive attachment rule to the current lookup.
+    pub(crate) fn add_gpos_type_3(
+        &mut self,
+        id: GlyphId16,
+        entry: Option<Anchor>,
+        exit: Option<Anchor>,
+    ) {
+        // the coverage and the entry-exit record
+        self.rule_size.add_rule(6, None, None);
+        self.rule_size.extra_bytes += entry
+            .iter()
+            .chain(exit.iter())
+            .map(anchor_size)
+            .sum::<usize>();
+        self.current
+            .as_mut()
+            .expect("rules have a lookup")
+            .add_gpos_type_3(id, entry, exit);
+    }
+
+    /// Add the size of the current subtable to the size of the current
+    /// lookup, and start tracking a new subtable.
+    fn finish_subtable_size(&mut self) {
+        self.finished_size +=
+            self.class_pair_size.size() + self.mark_attach_size.size() + self.mark_lig_size.size();
+        self.class_pair_size = Default::default();
+        self.mark_attach_size = Default::default();
+        self.mark_lig_size = Default::default();
+    }
+
     // doesn't start it, just stashes the name
-    pub(crate) fn start_named(&mut self, name: SmolStr) {
+    pub(crate) fn start_named(&mut self, name: SmolStr, use_extension: bool, site: usize) {
         self.current_name = Some(name);
+        self.current_use_extension = use_extension;
+        self.current_name_site = Some(site);
     }
 
-    pub(crate) fn start_lookup(&mut self, kind: Kind, flags: LookupFlagInfo) -> Option<LookupId> {
-        let finished_id = self.current.take().map(|lookup| self.push(lookup));
+    /// Start a new lookup, finishing the current one if there is one.
+    ///
+    /// `site` is the position of the statement that required the new lookup.
+    pub(crate) fn start_lookup(
+        &mut self,
+        kind: Kind,
+        flags: LookupFlagInfo,
+        site: usize,
+    ) -> Option<LookupId> {
+        self.flush_class_pairs();
+        let finished_id = self.current.take().map(|lookup| self.finish_lookup(lookup));
         let mut new_one = SomeLookup::new(kind, flags.flags, flags.mark_filter_set);
 
         let new_id = if is_gpos_rule(kind) {
@@ -509,13 +1273,46 @@ impl AllLookups {
             //SomeLookup::GsubReverse(_) => (),
             SomeLookup::GsubLookup(_) | SomeLookup::GposLookup(_) => (),
         }
+        self.sites
+            .insert(new_id, self.current_name_site.unwrap_or(site));
         self.current = Some(new_one);
         finished_id
     }
 
+    /// Push a finished lookup, marking it as an extension if it is part of a
+    /// `useExtension` block.
+    fn finish_lookup(&mut self, lookup: SomeLookup) -> LookupId {
+        self.finish_subtable_size();
+        let size = LOOKUP_HEADER_SIZE
+            + std::mem::take(&mut self.finished_size)
+            + std::mem::take(&mut self.rule_size).size();
+        let is_gpos = matches!(lookup, SomeLookup::GposLookup(_));
+        let id = self.push(lookup);
+        if is_gpos {
+            self.gpos_sizes.insert(id, size);
+        }
+        if self.current_use_extension {
+            // this includes any anonymous lookups pushed after the root,
+            // which are also extensions in feaLib.
+            let end = match id {
+                LookupId::Gpos(_) => self.gpos.len(),
+                _ => self.gsub.len(),
+            };
+            self.extension
+                .extend((id.to_raw()..end).map(|idx| match id {
+                    LookupId::Gpos(_) => LookupId::Gpos(idx),
+                    _ => LookupId::Gsub(idx),
+                }));
+        }
+        id
+    }
+
     pub(crate) fn finish_current(&mut self) -> Option<(LookupId, Option<SmolStr>)> {
-        if let Some(lookup) = self.current.take() {
-            let id = self.push(lookup);
+        self.current_name_site = None;
+        self.flush_class_pairs();
+        let finished = self.current.take().map(|lookup| self.finish_lookup(lookup));
+        self.current_use_extension = false;
+        if let Some(id) = finished {
             if let Some(name) = self.current_name.take() {
                 self.named.insert(name.clone(), id);
                 Some((id, Some(name)))
@@ -610,7 +1407,13 @@ impl AllLookups {
                 _ => (),
             }
         }
-        //TODO: the spec says to do gsub too, but fonttools doesn't?
+        // the outputs of ligature substitutions are handled separately, since
+        // feaLib does not consider them (see Opts::infer_gsub_ligature_classes)
+    }
+
+    /// The GSUB lookups, in order.
+    pub(crate) fn gsub_lookups(&self) -> &[SubstitutionLookup] {
+        &self.gsub
     }
 
     /// Return the aalt-relevant lookups for this lookup Id.
@@ -723,7 +1526,9 @@ impl AllLookups {
             _ => (),
         });
 
+        let n_lookups = lookups.len();
         self.gsub.splice(insert_point..insert_point, lookups);
+        self.bump_ids(LookupId::Gsub(insert_point), n_lookups);
 
         lookup_ids
     }
@@ -782,8 +1587,676 @@ impl AllLookups {
             }
         }
 
-        (gsub_builder.build(var_store), gpos_builder.build(var_store))
+        let (mut gsub, mut gpos) = build_tables(gsub_builder, gpos_builder, var_store, opts);
+        for id in &self.extension {
+            match id {
+                LookupId::Gpos(idx) => {
+                    if let Some(lookup) = gpos
+                        .as_mut()
+                        .and_then(|gpos| gpos.lookup_list.lookups.get_mut(*idx))
+                    {
+                        let raw = std::mem::take(&mut **lookup);
+                        **lookup = gpos_extension(raw);
+                    }
+                }
+                LookupId::Gsub(idx) => {
+                    if let Some(lookup) = gsub
+                        .as_mut()
+                        .and_then(|gsub| gsub.lookup_list.lookups.get_mut(*idx))
+                    {
+                        let raw = std::mem::take(&mut **lookup);
+                        **lookup = gsub_extension(raw);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        (gsub, gpos)
+    }
+
+    /// An estimate of the serialized size of each GSUB and GPOS lookup,
+    /// without building them.
+    ///
+    /// These are upper bounds. The size of a GPOS lookup is `None` if it was
+    /// not built from rules in the FEA, for instance if it was added by a
+    /// feature writer.
+    pub(crate) fn estimated_sizes(&self) -> (Vec<usize>, Vec<Option<usize>>) {
+        let gsub = self
+            .gsub
+            .iter()
+            .map(SubstitutionLookup::estimated_size)
+            .collect();
+        let gpos = self
+            .gpos
+            .iter()
+            .enumerate()
+            .map(|(i, lookup)| {
+                lookup
+                    .estimated_size()
+                    .or_else(|| self.gpos_sizes.get(&LookupId::Gpos(i)).copied())
+            })
+            .collect();
+        (gsub, gpos)
+    }
+
+    /// Convert lookups to extension lookups where the built tables need it.
+    ///
+    /// This serializes the lookups, so it must be called after any variation
+    /// indices in the GPOS table have been remapped.
+    pub(crate) fn promote_to_extension(
+        &self,
+        gsub: Option<&mut write_gsub::Gsub>,
+        gpos: Option<&mut write_gpos::Gpos>,
+    ) {
+        // the estimates are upper bounds, assuming the largest format for
+        // every table and that nothing is shared. If the lookup list and every
+        // lookup together fit within 16-bit offsets, then so does every offset
+        // between them and their subtables, however they are packed, so the
+        // table doesn't need to be serialized to check it. Otherwise we check
+        // each lookup, and then the table as a whole, since the subtables can
+        // overflow even when the lookup list fits.
+        let (gsub_sizes, gpos_sizes) = self.estimated_sizes();
+        let gsub_may_overflow = !lookups_fit(gsub_sizes.into_iter().map(Some));
+        let gpos_may_overflow = !lookups_fit(gpos_sizes);
+        if let Some(gsub) = gsub.filter(|_| gsub_may_overflow) {
+            promote_oversized_lookups(
+                &mut gsub.lookup_list.lookups,
+                stats::gsub_lookup,
+                gsub_extension,
+            );
+            promote_until_table_fits(
+                gsub,
+                |gsub| gsub.lookup_list.lookups.as_mut_slice(),
+                stats::gsub_lookup,
+                gsub_extension,
+            );
+        }
+        if let Some(gpos) = gpos.filter(|_| gpos_may_overflow) {
+            promote_oversized_lookups(
+                &mut gpos.lookup_list.lookups,
+                stats::gpos_lookup,
+                gpos_extension,
+            );
+            promote_until_table_fits(
+                gpos,
+                |gpos| gpos.lookup_list.lookups.as_mut_slice(),
+                stats::gpos_lookup,
+                gpos_extension,
+            );
+        }
+    }
+}
+
+/// Build the GSUB and GPOS tables.
+///
+/// With the `parallel` feature, GSUB (which does not use the variation store)
+/// is built alongside GPOS, with its lookups built in parallel. The GPOS
+/// lookups share the variation store, and are always built in order.
+#[cfg(feature = "parallel")]
+fn build_tables(
+    gsub: PosSubBuilder<SubstitutionLookup>,
+    gpos: PosSubBuilder<PositionLookup>,
+    var_store: &mut VariationStoreBuilder,
+    opts: &Opts,
+) -> (Option<write_gsub::Gsub>, Option<write_gpos::Gpos>) {
+    if opts.parallel {
+        rayon::join(|| gsub.build_parallel(), || gpos.build(var_store))
+    } else {
+        (gsub.build(var_store), gpos.build(var_store))
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn build_tables(
+    gsub: PosSubBuilder<SubstitutionLookup>,
+    gpos: PosSubBuilder<PositionLookup>,
+    var_store: &mut VariationStoreBuilder,
+    _opts: &Opts,
+) -> (Option<write_gsub::Gsub>, Option<write_gpos::Gpos>) {
+    (gsub.build(var_store), gpos.build(var_store))
+}
+
+impl ClassPairSize {
+    /// The largest size we allow for a subtable.
+    const MAX_SIZE: usize = u16::MAX as usize;
+
+    /// Record a rule, returning `false` if this would make the subtable too large.
+    ///
+    /// If this returns `false`, nothing is recorded.
+    fn insert(
+        &mut self,
+        first: &SharedGlyphSet,
+        second: &SharedGlyphSet,
+        record_size: usize,
+    ) -> bool {
+        let new_first = !self.first_classes.contains(first);
+        let new_second = !self.second_classes.contains(second);
+        let n_first = self.first_classes.len() + usize::from(new_first);
+        let n_second = self.second_classes.len() + usize::from(new_second);
+        let first_glyphs = self.first_glyphs + if new_first { first.iter().count() } else { 0 };
+        let second_glyphs = self.second_glyphs + if new_second { second.iter().count() } else { 0 };
+        let record_size = self.record_size.max(record_size);
+
+        // the header, then a coverage table and two class defs, where we assume
+        // that each glyph needs two bytes. The second class def has an implicit
+        // class 0, which still needs a row of records.
+        let size = 16
+            + 3 * 6
+            + 2 * (2 * first_glyphs + second_glyphs)
+            + n_first * (n_second + 1) * record_size;
+        // an empty subtable can't get any smaller, so we always accept the first rule
+        if size > Self::MAX_SIZE && !self.first_classes.is_empty() {
+            return false;
+        }
+
+        if new_first {
+            self.first_classes.insert(first.clone());
+        }
+        if new_second {
+            self.second_classes.insert(second.clone());
+        }
+        self.first_glyphs = first_glyphs;
+        self.second_glyphs = second_glyphs;
+        self.record_size = record_size;
+        true
+    }
+
+    /// Record the value records of a rule that was inserted.
+    fn add_records(&mut self, first: &ValueRecord, second: &ValueRecord) {
+        self.formats.0 |= first.format().bits();
+        self.formats.1 |= second.format().bits();
+        self.device_bytes += value_record_device_size(first) + value_record_device_size(second);
+    }
+
+    /// An upper bound on the size of the subtable, with its device tables.
+    fn size(&self) -> usize {
+        if self.first_classes.is_empty() {
+            return 0;
+        }
+        let record_size = format_size(self.formats.0) + format_size(self.formats.1);
+        // the offset to the subtable and its header, the coverage and the class
+        // defs, and a record for every pair of classes, including class 0.
+        2 + 16
+            + coverage_size(self.first_glyphs)
+            + class_def_size(self.first_glyphs)
+            + class_def_size(self.second_glyphs)
+            + (self.first_classes.len() + 1) * (self.second_classes.len() + 1) * record_size
+            + self.device_bytes
+    }
+}
+
+impl MarkAttachSize {
+    /// The largest size we allow for a subtable.
+    const MAX_SIZE: usize = u16::MAX as usize;
+    /// The size of an anchor table without devices; this is the largest format.
+    const ANCHOR_SIZE: usize = 10;
+
+    /// Record a rule, returning `false` if this would make the subtable too large.
+    ///
+    /// If this returns `false`, nothing is recorded.
+    fn insert(
+        &mut self,
+        class: &str,
+        marks: &[GlyphId16],
+        bases: &[GlyphId16],
+        n_anchors: usize,
+    ) -> bool {
+        let n_classes = self.classes.len() + usize::from(!self.classes.contains(class));
+        let n_marks =
+            self.marks.len() + marks.iter().filter(|gid| !self.marks.contains(gid)).count();
+        let n_bases =
+            self.bases.len() + bases.iter().filter(|gid| !self.bases.contains(gid)).count();
+        let anchors = self.anchors + n_anchors;
+
+        let size = Self::tables_size(n_classes, n_marks, n_bases) + anchors * Self::ANCHOR_SIZE;
+        // an empty subtable can't get any smaller, so we always accept the first rule
+        if size > Self::MAX_SIZE && !self.classes.is_empty() {
+            return false;
+        }
+
+        self.classes.insert(class.into());
+        self.marks.extend(marks);
+        self.bases.extend(bases);
+        self.anchors = anchors;
+        true
+    }
+
+    /// The size of the subtable without its anchors.
+    ///
+    /// This is the header and two coverage tables, where we assume that each
+    /// glyph needs two bytes, then the mark and base arrays.
+    fn tables_size(n_classes: usize, n_marks: usize, n_bases: usize) -> usize {
+        12 + coverage_size(n_marks)
+            + coverage_size(n_bases)
+            + (2 + 4 * n_marks)
+            + (2 + 2 * n_bases * n_classes)
+    }
+
+    /// An upper bound on the size of the subtable, with its anchors.
+    fn size(&self) -> usize {
+        if self.classes.is_empty() {
+            return 0;
+        }
+        // the offset to the subtable, then the subtable
+        2 + Self::tables_size(self.classes.len(), self.marks.len(), self.bases.len())
+            + self.anchor_bytes
+    }
+}
+
+impl MarkLigSize {
+    /// An upper bound on the size of the subtable, with its anchors.
+    fn size(&self) -> usize {
+        if self.classes.is_empty() && self.ligatures.is_empty() {
+            return 0;
+        }
+        let n_marks = self.marks.len();
+        let n_ligatures = self.ligatures.len();
+        let n_components = self.ligatures.values().sum::<usize>();
+        // the offset to the subtable and its header, the coverage tables, the
+        // mark array, and the ligature array with an attach table for each
+        // ligature, which has an anchor offset for each component and class.
+        2 + 12
+            + coverage_size(n_marks)
+            + coverage_size(n_ligatures)
+            + (2 + 4 * n_marks)
+            + (2 + 4 * n_ligatures + 2 * n_components * self.classes.len())
+            + self.anchor_bytes
+    }
+}
+
+impl RuleSize {
+    /// write-fonts may put rules with different value formats in separate
+    /// subtables, so we allow for a subtable header, its coverage header and
+    /// the offset to it for every rule.
+    const SUBTABLE_SIZE: usize = 16;
+
+    /// Record a rule that needs `bytes`, as well as its value records.
+    fn add_rule(
+        &mut self,
+        bytes: usize,
+        first: Option<&ValueRecord>,
+        second: Option<&ValueRecord>,
+    ) {
+        self.n_rules += 1;
+        self.bytes += bytes + Self::SUBTABLE_SIZE;
+        if let Some(record) = first {
+            self.formats.0 |= record.format().bits();
+            self.extra_bytes += value_record_device_size(record);
+        }
+        if let Some(record) = second {
+            self.formats.1 |= record.format().bits();
+            self.extra_bytes += value_record_device_size(record);
+        }
+    }
+
+    /// An upper bound on the size of these rules, in every subtable.
+    fn size(&self) -> usize {
+        let record_size = format_size(self.formats.0) + format_size(self.formats.1);
+        self.bytes + self.n_rules * record_size + self.extra_bytes
+    }
+}
+
+/// An estimate of the size of a lookup, given an estimate for each subtable.
+fn estimate_lookup<T>(lookup: &LookupBuilder<T>, subtable_size: impl Fn(&T) -> usize) -> usize {
+    let header = 6 + 2 * lookup.subtables.len() + 2 * usize::from(lookup.mark_set.is_some());
+    header + lookup.subtables.iter().map(subtable_size).sum::<usize>()
+}
+
+/// The size of a lookup with no subtables, with a mark filtering set and the
+/// offset to its first subtable.
+const LOOKUP_HEADER_SIZE: usize = 10;
+
+/// The size of a coverage table with this many glyphs, in the largest format.
+fn coverage_size(n_glyphs: usize) -> usize {
+    4 + 2 * n_glyphs
+}
+
+/// The size of a class def with this many glyphs, in the largest format.
+///
+/// write-fonts picks the smaller of the two formats, and format 2 needs at
+/// most one range per glyph.
+fn class_def_size(n_glyphs: usize) -> usize {
+    4 + 6 * n_glyphs
+}
+
+/// The size of a value record with this value format.
+fn format_size(format: u16) -> usize {
+    2 * format.count_ones() as usize
+}
+
+/// The size of the device or variation index table of a metric, if any.
+fn device_size(metric: &Metric) -> usize {
+    match &metric.device_or_deltas {
+        DeviceOrDeltas::Device(device) => 6 + 2 * device.delta_value.len(),
+        DeviceOrDeltas::Deltas(_) => 6,
+        DeviceOrDeltas::None => 0,
+    }
+}
+
+/// The size of the device tables of a value record.
+fn value_record_device_size(record: &ValueRecord) -> usize {
+    [
+        &record.x_placement,
+        &record.y_placement,
+        &record.x_advance,
+        &record.y_advance,
+    ]
+    .into_iter()
+    .flatten()
+    .map(device_size)
+    .sum()
+}
+
+/// The size of an anchor table in the largest format, with its device tables.
+fn anchor_size(anchor: &Anchor) -> usize {
+    MarkAttachSize::ANCHOR_SIZE + device_size(&anchor.x) + device_size(&anchor.y)
+}
+
+/// The number of bytes needed to encode this value record.
+fn value_record_size(record: &ValueRecord) -> usize {
+    [
+        &record.x_placement,
+        &record.y_placement,
+        &record.x_advance,
+        &record.y_advance,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|metric| if metric.has_device_or_deltas() { 4 } else { 2 })
+    .sum()
+}
+
+/// Convert the largest lookups in a lookup list to use extension subtables,
+/// if they would otherwise not be reachable through its 16-bit offsets.
+///
+/// This only considers the lookup list; the table may still fail to serialize.
+fn promote_oversized_lookups<T: FontWrite + Validate + Default>(
+    lookups: &mut [OffsetMarker<T>],
+    info: impl Fn(&T) -> (&'static str, bool, usize),
+    to_extension: impl Fn(T) -> T,
+) {
+    let sizes = lookups
+        .iter()
+        .map(|lookup| {
+            let (_, is_extension, n_subtables) = info(&**lookup);
+            // a lookup that is too large to serialize needs promoting regardless
+            let size =
+                (!is_extension).then(|| stats::size(&**lookup).unwrap_or(MAX_LOOKUP_LIST_SIZE + 1));
+            (size, extension_lookup_size(n_subtables))
+        })
+        .collect::<Vec<_>>();
+    for idx in lookups_to_promote(&sizes) {
+        log::debug!("promoting lookup {idx} to extension");
+        let raw = std::mem::take(&mut *lookups[idx]);
+        *lookups[idx] = to_extension(raw);
+    }
+}
+
+/// Convert more lookups to extension lookups, largest first, until `table`
+/// serializes.
+///
+/// Even when the lookup list fits, the packer may not be able to place every
+/// subtable within reach of its lookup's 16-bit offsets; an extension subtable
+/// can be placed anywhere after the lookup list.
+///
+/// This is a heuristic. The largest lookups are promoted first, in batches
+/// that double in size (one lookup, then two, then four and so on), and the
+/// table is serialized after each batch, so it is only serialized a
+/// logarithmic number of times. This can promote up to twice as many lookups
+/// as are needed, and the lookups that are promoted are not necessarily the
+/// ones whose subtables overflowed. If the table still does not serialize
+/// once every lookup is an extension lookup, it is left as it is.
+///
+/// Only a failure to pack the table counts as an overflow: a table that fails
+/// validation is left alone, since extension lookups would not help.
+fn promote_until_table_fits<Table, T>(
+    table: &mut Table,
+    lookups: impl Fn(&mut Table) -> &mut [OffsetMarker<T>],
+    info: impl Fn(&T) -> (&'static str, bool, usize),
+    to_extension: impl Fn(T) -> T,
+) where
+    Table: FontWrite + Validate,
+    T: FontWrite + Validate + Default,
+{
+    if !overflows(table) {
+        return;
+    }
+    // largest last, so that they are taken first
+    let mut candidates = lookups(table)
+        .iter()
+        .enumerate()
+        .filter(|(_, lookup)| !info(&***lookup).1)
+        .map(|(idx, lookup)| (stats::size(&**lookup).unwrap_or(usize::MAX), idx))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    let mut batch_size = 1;
+    while !candidates.is_empty() {
+        let batch = candidates.split_off(candidates.len().saturating_sub(batch_size));
+        let lookups = lookups(table);
+        for (_, idx) in batch {
+            log::debug!("promoting lookup {idx} to extension so that the table fits");
+            let raw = std::mem::take(&mut *lookups[idx]);
+            *lookups[idx] = to_extension(raw);
+        }
+        if !overflows(table) {
+            return;
+        }
+        batch_size *= 2;
+    }
+    log::warn!("table overflows even with every lookup as an extension lookup");
+}
+
+/// Returns `true` if `table` cannot be serialized because its offsets overflow.
+fn overflows(table: &(impl FontWrite + Validate)) -> bool {
+    match write_fonts::dump_table(table) {
+        Ok(_) => false,
+        Err(write_fonts::error::Error::PackingFailed(_)) => true,
+        Err(err) => {
+            log::warn!("not checking for offset overflow: {err}");
+            false
+        }
+    }
+}
+
+/// Split the classes of these class pair rules so that they can share a subtable.
+///
+/// A subtable can only have classes that are identical or disjoint, so
+/// overlapping classes would otherwise each need a new subtable. Instead, each
+/// class is split into pieces that are in exactly the same classes, and each
+/// rule becomes a rule for every combination of the pieces of its classes.
+/// Where two rules cover the same pair of pieces, the earlier rule wins, as it
+/// would if the later rule were in a later subtable.
+fn merge_class_pairs(rules: Vec<ClassPairRule>) -> Vec<ClassPairRule> {
+    let first_pieces = split_classes(rules.iter().map(|(one, ..)| one));
+    let second_pieces = split_classes(rules.iter().map(|(_, two, ..)| two));
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for ((_, _, val_one, val_two), (firsts, seconds)) in rules
+        .into_iter()
+        .zip(first_pieces.iter().zip(&second_pieces))
+    {
+        for first in firsts {
+            for second in seconds {
+                if seen.insert((first, second)) {
+                    result.push((
+                        first.clone(),
+                        second.clone(),
+                        val_one.clone(),
+                        val_two.clone(),
+                    ));
+                }
+            }
+        }
     }
+    result
+}
+
+/// Split each class into pieces, where the glyphs of each piece are in
+/// exactly the same classes.
+///
+/// Classes that are identical or disjoint from all the others are not split.
+fn split_classes<'a>(
+    classes: impl Iterator<Item = &'a SharedGlyphSet>,
+) -> Vec<Vec<SharedGlyphSet>> {
+    // identical classes share an id, so that they are split the same way
+    let mut ids = HashMap::new();
+    let class_ids = classes
+        .map(|class| {
+            let next_id = ids.len();
+            *ids.entry(class).or_insert(next_id)
+        })
+        .collect::<Vec<_>>();
+    let mut memberships = BTreeMap::<GlyphId16, Vec<usize>>::new();
+    for (class, id) in &ids {
+        for glyph in class.iter() {
+            memberships.entry(glyph).or_default().push(*id);
+        }
+    }
+    let mut pieces = BTreeMap::<Vec<usize>, Vec<GlyphId16>>::new();
+    for (glyph, mut class_ids) in memberships {
+        class_ids.sort_unstable();
+        pieces.entry(class_ids).or_default().push(glyph);
+    }
+    // keep the pieces of each class in glyph order
+    let mut pieces = pieces.into_iter().collect::<Vec<_>>();
+    pieces.sort_unstable_by_key(|(_, glyphs)| glyphs[0]);
+    let mut pieces_by_id = vec![Vec::new(); ids.len()];
+    for (class_ids, glyphs) in pieces {
+        let piece = glyphs.into_iter().collect::<SharedGlyphSet>();
+        for id in class_ids {
+            pieces_by_id[id].push(piece.clone());
+        }
+    }
+    class_ids
+        .into_iter()
+        .map(|id| pieces_by_id[id].clone())
+        .collect()
+}
+
+/// The largest size of the lookup list and the lookups it references directly.
+const MAX_LOOKUP_LIST_SIZE: usize = u16::MAX as usize;
+
+/// Whether lookups of these estimated sizes are certain to fit in a lookup list.
+///
+/// An estimate of `None` means that the size of that lookup is unknown.
+fn lookups_fit(estimates: impl IntoIterator<Item = Option<usize>>) -> bool {
+    let mut total = 2;
+    for estimate in estimates {
+        let Some(size) = estimate else {
+            return false;
+        };
+        total += 2 + size;
+    }
+    total <= MAX_LOOKUP_LIST_SIZE
+}
+
+/// The size of an extension lookup with this many subtables.
+///
+/// This is the lookup header (assuming a mark filtering set) and an offset and
+/// an extension subtable for each subtable; the subtables themselves can be
+/// placed anywhere.
+fn extension_lookup_size(n_subtables: usize) -> usize {
+    8 + n_subtables * (2 + 8)
+}
+
+/// Choose which lookups to convert to extension lookups, largest first.
+///
+/// Each item in `sizes` is the serialized size of a lookup (or `None` if it is
+/// already an extension lookup) and its size as an extension lookup. Returns
+/// the indices of the lookups to convert.
+fn lookups_to_promote(sizes: &[(Option<usize>, usize)]) -> Vec<usize> {
+    let mut total = 2
+        + 2 * sizes.len()
+        + sizes
+            .iter()
+            .map(|(size, ext_size)| size.unwrap_or(*ext_size))
+            .sum::<usize>();
+    let mut candidates = sizes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (size, ext_size))| size.map(|size| (size, *ext_size, i)))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+
+    let mut result = Vec::new();
+    while total > MAX_LOOKUP_LIST_SIZE {
+        let Some((size, ext_size, idx)) = candidates.pop() else {
+            break;
+        };
+        total = total - size + ext_size;
+        result.push(idx);
+    }
+    result
+}
+
+/// Wrap each subtable in a lookup, preserving the lookup's flags.
+fn wrap_subtables<T, U: Default>(lookup: Lookup<T>, wrap: impl Fn(T) -> U) -> Lookup<U> {
+    let mut result = Lookup::new(
+        lookup.lookup_flag,
+        lookup
+            .subtables
+            .into_iter()
+            .map(|sub| wrap(sub.into_inner()))
+            .collect(),
+    );
+    result.mark_filtering_set = lookup.mark_filtering_set;
+    result
+}
+
+/// Convert a GSUB lookup to use extension (type 7) subtables
+fn gsub_extension(lookup: write_gsub::SubstitutionLookup) -> write_gsub::SubstitutionLookup {
+    use write_gsub::{ExtensionSubstFormat1 as Ext, ExtensionSubtable, SubstitutionLookup as L};
+    L::Extension(match lookup {
+        L::Single(lookup) => wrap_subtables(lookup, |t| ExtensionSubtable::Single(Ext::new(1, t))),
+        L::Multiple(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Multiple(Ext::new(2, t)))
+        }
+        L::Alternate(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Alternate(Ext::new(3, t)))
+        }
+        L::Ligature(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Ligature(Ext::new(4, t)))
+        }
+        L::Contextual(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Contextual(Ext::new(5, t)))
+        }
+        L::ChainContextual(lookup) => wrap_subtables(lookup, |t| {
+            ExtensionSubtable::ChainContextual(Ext::new(6, t))
+        }),
+        L::Reverse(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Reverse(Ext::new(8, t)))
+        }
+        L::Extension(lookup) => lookup,
+    })
+}
+
+/// Convert a GPOS lookup to use extension (type 9) subtables
+fn gpos_extension(lookup: write_gpos::PositionLookup) -> write_gpos::PositionLookup {
+    use write_gpos::{ExtensionPosFormat1 as Ext, ExtensionSubtable, PositionLookup as L};
+    L::Extension(match lookup {
+        L::Single(lookup) => wrap_subtables(lookup, |t| ExtensionSubtable::Single(Ext::new(1, t))),
+        L::Pair(lookup) => wrap_subtables(lookup, |t| ExtensionSubtable::Pair(Ext::new(2, t))),
+        L::Cursive(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Cursive(Ext::new(3, t)))
+        }
+        L::MarkToBase(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::MarkToBase(Ext::new(4, t)))
+        }
+        L::MarkToLig(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::MarkToLig(Ext::new(5, t)))
+        }
+        L::MarkToMark(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::MarkToMark(Ext::new(6, t)))
+        }
+        L::Contextual(lookup) => {
+            wrap_subtables(lookup, |t| ExtensionSubtable::Contextual(Ext::new(7, t)))
+        }
+        L::ChainContextual(lookup) => wrap_subtables(lookup, |t| {
+            ExtensionSubtable::ChainContextual(Ext::new(8, t))
+        }),
+        L::Extension(lookup) => lookup,
+    })
 }
 
 impl LookupId {
@@ -886,7 +2359,6 @@ impl SomeLookup {
                 Kind::GsubType5 => {
                     SubstitutionLookup::Contextual(LookupBuilder::new(flags, filter))
                 }
-                Kind::GsubType7 => unimplemented!("extension"),
                 Kind::GsubType8 => SubstitutionLookup::Reverse(LookupBuilder::new(flags, filter)),
                 other => panic!("illegal kind for lookup: '{other}'"),
             };
@@ -948,7 +2420,9 @@ impl SomeLookup {
         val_two: ValueRecord,
     ) {
         if let SomeLookup::GposLookup(PositionLookup::Pair(table)) = self {
-            let subtable = table.last_mut().unwrap();
+            // subtable breaks only apply to class pairs; as in feaLib, all
+            // glyph pairs go in a single subtable, before any class subtables.
+            let subtable = table.subtables.first_mut().unwrap();
             subtable.insert_pair(one, val_one, two, val_two)
         } else {
             panic!("lookup mismatch");
@@ -983,7 +2457,10 @@ impl SomeLookup {
         }
     }
 
-    pub(crate) fn with_gpos_type_4<R>(&mut self, f: impl FnOnce(&mut MarkToBaseBuilder) -> R) -> R {
+    pub(crate) fn with_gpos_type_4<R>(
+        &mut self,
+        f: impl FnOnce(&mut MarkToBaseSubtable) -> R,
+    ) -> R {
         if let SomeLookup::GposLookup(PositionLookup::MarkToBase(table)) = self {
             let subtable = table.last_mut().unwrap();
             f(subtable)
@@ -1001,7 +2478,10 @@ impl SomeLookup {
         }
     }
 
-    pub(crate) fn with_gpos_type_6<R>(&mut self, f: impl FnOnce(&mut MarkToMarkBuilder) -> R) -> R {
+    pub(crate) fn with_gpos_type_6<R>(
+        &mut self,
+        f: impl FnOnce(&mut MarkToMarkSubtable) -> R,
+    ) -> R {
         if let SomeLookup::GposLookup(PositionLookup::MarkToMark(table)) = self {
             let subtable = table.last_mut().unwrap();
             f(subtable)
@@ -1055,24 +2535,13 @@ impl SomeLookup {
         }
     }
 
-    /// Returns `true` if this replacement shadows an existing rule.
-    ///
-    /// In this case the rule is not added, and the client should report an error.
-    pub(crate) fn add_gsub_type_4(
-        &mut self,
-        target: Vec<GlyphId16>,
-        replacement: GlyphId16,
-    ) -> bool {
+    pub(crate) fn add_gsub_type_4(&mut self, target: Vec<GlyphId16>, replacement: GlyphId16) {
         if let SomeLookup::GsubLookup(SubstitutionLookup::Ligature(table)) = self {
             let subtable = table.last_mut().unwrap();
-            if !subtable.can_add(&target, replacement) {
-                return true;
-            }
             subtable.insert(target, replacement);
         } else {
             panic!("lookup mismatch");
         }
-        false
     }
 
     pub(crate) fn add_gsub_type_8(
@@ -1164,7 +2633,7 @@ where
     #[allow(clippy::type_complexity)] // i love my big dumb tuple
     fn build_raw(
         self,
-        var_store: &mut VariationStoreBuilder,
+        build_lookups: impl FnOnce(Vec<T>) -> Vec<T::Output>,
     ) -> Option<(
         LookupList<T::Output>,
         ScriptList,
@@ -1199,11 +2668,7 @@ where
             })
             .collect::<Vec<_>>();
 
-        let lookups = self
-            .lookups
-            .into_iter()
-            .map(|x| x.build(var_store))
-            .collect();
+        let lookups = build_lookups(self.lookups);
 
         let variations = if self.variations.is_empty() {
             None
@@ -1246,7 +2711,7 @@ impl Builder for PosSubBuilder<PositionLookup> {
     type Output = Option<write_gpos::Gpos>;
 
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
-        self.build_raw(var_store)
+        self.build_raw(|lookups| build_serial(lookups, var_store))
             .map(|(lookups, scripts, features, variations)| {
                 let mut gpos = write_gpos::Gpos::new(scripts, features, lookups);
                 gpos.feature_variations = variations.into();
@@ -1259,13 +2724,55 @@ impl Builder for PosSubBuilder<SubstitutionLookup> {
     type Output = Option<write_gsub::Gsub>;
 
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
-        self.build_raw(var_store)
+        self.build_with(|lookups| build_serial(lookups, var_store))
+    }
+}
+
+impl PosSubBuilder<SubstitutionLookup> {
+    fn build_with(
+        self,
+        build_lookups: impl FnOnce(Vec<SubstitutionLookup>) -> Vec<write_gsub::SubstitutionLookup>,
+    ) -> Option<write_gsub::Gsub> {
+        self.build_raw(build_lookups)
             .map(|(lookups, scripts, features, variations)| {
                 let mut gsub = write_gsub::Gsub::new(scripts, features, lookups);
                 gsub.feature_variations = variations.into();
                 gsub
             })
     }
+
+    /// Build the GSUB table, building each lookup on the rayon thread pool.
+    ///
+    /// GSUB lookups never touch the variation store, so each one can be built
+    /// independently; the order of the lookup list is preserved.
+    #[cfg(feature = "parallel")]
+    fn build_parallel(self) -> Option<write_gsub::Gsub> {
+        use rayon::prelude::*;
+        self.build_with(|lookups| {
+            lookups
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, lookup)| {
+                    trace_span!("build_lookup", index = i);
+                    lookup.build(&mut VariationStoreBuilder::new(0))
+                })
+                .collect()
+        })
+    }
+}
+
+fn build_serial<T: Builder>(
+    lookups: Vec<T>,
+    var_store: &mut VariationStoreBuilder,
+) -> Vec<T::Output> {
+    lookups
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            trace_span!("build_lookup", index = i);
+            x.build(var_store)
+        })
+        .collect()
 }
 
 impl FeatureKey {
@@ -1301,3 +2808,448 @@ fn is_gpos_rule(kind: Kind) -> bool {
             | Kind::GposType8
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::closure::gsub_closure;
+
+    #[test]
+    fn split_large_class_pair_subtables() {
+        let class = |gid: u16| -> SharedGlyphSet { [GlyphId16::new(gid)].into_iter().collect() };
+        let mut lookups = AllLookups::default();
+        lookups.start_lookup(Kind::GposType2, LookupFlagInfo::default(), 0);
+        // 200 * 201 records of two bytes each is more than fits in one subtable
+        for first in 0..200 {
+            for second in 1000..1200 {
+                lookups.add_gpos_type_2_class(
+                    class(first),
+                    class(second),
+                    ValueRecord::new().with_x_advance(-10),
+                    ValueRecord::new(),
+                );
+            }
+        }
+        lookups.finish_current();
+
+        let PositionLookup::Pair(lookup) = &lookups.gpos[0] else {
+            panic!("expected a pair lookup");
+        };
+        assert!(lookup.subtables.len() > 1);
+    }
+
+    #[test]
+    fn merge_overlapping_class_pairs() {
+        let glyphs =
+            |gids: &[u16]| -> SharedGlyphSet { gids.iter().copied().map(GlyphId16::new).collect() };
+        let value = |x: i16| ValueRecord::new().with_x_advance(x);
+        let rule = |one: &[u16], two: &[u16], x: i16| {
+            (glyphs(one), glyphs(two), value(x), ValueRecord::new())
+        };
+        let rules = vec![
+            rule(&[1, 2], &[10, 11], -10),
+            // overlaps the second class of the first rule
+            rule(&[1, 2], &[11, 12], -20),
+            rule(&[3], &[10], -30),
+        ];
+        let merged = merge_class_pairs(rules.clone());
+        assert_eq!(
+            merged,
+            [
+                rule(&[1, 2], &[10], -10),
+                rule(&[1, 2], &[11], -10),
+                rule(&[1, 2], &[12], -20),
+                rule(&[3], &[10], -30),
+            ]
+        );
+
+        // and the rules all fit in one subtable
+        let mut lookups = AllLookups::new(&Opts::new().merge_class_pairs(true));
+        lookups.start_lookup(Kind::GposType2, LookupFlagInfo::default(), 0);
+        for (one, two, val_one, val_two) in rules {
+            lookups.add_gpos_type_2_class(one, two, val_one, val_two);
+        }
+        lookups.finish_current();
+        let built = lookups.gpos[0]
+            .clone()
+            .build(&mut VariationStoreBuilder::new(0));
+        assert_eq!(stats::gpos_lookup(&built).2, 1);
+    }
+
+    #[test]
+    fn split_large_mark_attachment_subtables() {
+        let bases = (0..2000).map(GlyphId16::new).collect::<Vec<_>>();
+        let mut size = MarkAttachSize::default();
+        // each class adds an offset to the base array for each base
+        let n_accepted = (0..20u16)
+            .take_while(|i| {
+                let mark = GlyphId16::new(5000 + i);
+                size.insert(&format!("class_{i}"), &[mark], &bases, 2)
+            })
+            .count();
+        assert!((1..20).contains(&n_accepted), "{n_accepted}");
+        // rules for existing classes and glyphs only add anchors
+        assert!(size.insert("class_0", &[GlyphId16::new(5000)], &bases, 2));
+    }
+
+    #[test]
+    fn use_extension_with_flag_change() {
+        let gid = GlyphId16::new;
+        let mut lookups = AllLookups::default();
+        lookups.start_named("ext".into(), true, 0);
+        lookups.start_lookup(Kind::GsubType1, LookupFlagInfo::default(), 0);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_1(gid(1), gid(2));
+        // a change of flags in the middle of the block starts a new lookup
+        let flags = LookupFlagInfo::new(LookupFlag::IGNORE_MARKS, None);
+        lookups.start_lookup(Kind::GsubType1, flags, 1);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_1(gid(3), gid(4));
+        lookups.finish_current();
+        // this lookup is not part of the block
+        lookups.start_lookup(Kind::GsubType1, LookupFlagInfo::default(), 2);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_1(gid(5), gid(6));
+        lookups.finish_current();
+
+        assert_eq!(lookups.gsub.len(), 3);
+        let mut extension = lookups.extension.iter().copied().collect::<Vec<_>>();
+        extension.sort();
+        assert_eq!(extension, [LookupId::Gsub(0), LookupId::Gsub(1)]);
+    }
+
+    #[test]
+    fn gsub_closure_over_builders() {
+        let gid = GlyphId16::new;
+        let mut lookups = AllLookups::default();
+        // the ligature lookup comes first, so this needs more than one pass
+        lookups.start_lookup(Kind::GsubType4, LookupFlagInfo::default(), 0);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_4(vec![gid(2), gid(3)], gid(4));
+        lookups.start_lookup(Kind::GsubType1, LookupFlagInfo::default(), 0);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_1(gid(1), gid(2));
+        lookups.finish_current();
+
+        let closure = |glyphs: &[u16]| {
+            gsub_closure(
+                lookups.gsub_lookups().iter(),
+                glyphs.iter().copied().map(gid),
+            )
+        };
+        assert_eq!(closure(&[1]), BTreeSet::from([gid(1), gid(2)]));
+        assert_eq!(
+            closure(&[1, 3]),
+            BTreeSet::from([gid(1), gid(2), gid(3), gid(4)])
+        );
+        assert_eq!(closure(&[2]), BTreeSet::from([gid(2)]));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let gid = GlyphId16::new;
+        let mut lookups = AllLookups::default();
+        lookups.start_lookup(Kind::GsubType4, LookupFlagInfo::default(), 0);
+        lookups
+            .current_mut()
+            .unwrap()
+            .add_gsub_type_4(vec![gid(2), gid(3)], gid(4));
+        // a contextual lookup, with an anonymous single sub lookup
+        lookups.start_lookup(Kind::GsubType6, LookupFlagInfo::default(), 1);
+        let lookup = lookups.current_mut().unwrap();
+        let inline = lookup
+            .as_gsub_contextual()
+            .add_anon_gsub_type_1(GlyphOrClass::Glyph(gid(5)), GlyphOrClass::Glyph(gid(6)));
+        lookup.add_contextual_rule(
+            vec![GlyphOrClass::Class([gid(1), gid(2)].into_iter().collect())],
+            vec![(GlyphOrClass::Glyph(gid(5)), vec![inline])],
+            Vec::new(),
+        );
+        lookups.finish_current();
+        assert_eq!(lookups.gsub.len(), 3);
+
+        let json = serde_json::to_string(&lookups.gsub).unwrap();
+        let loaded: Vec<SubstitutionLookup> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{loaded:?}"), format!("{:?}", lookups.gsub));
+    }
+
+    #[test]
+    fn estimated_sizes_are_upper_bounds() {
+        let gid = GlyphId16::new;
+        let class = |gids: &[u16]| GlyphOrClass::Class(gids.iter().copied().map(gid).collect());
+        let mut lookups = AllLookups::default();
+        lookups.start_lookup(Kind::GsubType4, LookupFlagInfo::default(), 0);
+        let lookup = lookups.current_mut().unwrap();
+        lookup.add_gsub_type_4(vec![gid(2), gid(3)], gid(20));
+        lookup.add_gsub_type_4(vec![gid(2), gid(4), gid(5)], gid(21));
+        lookup.add_gsub_type_4(vec![gid(6), gid(7)], gid(22));
+        lookups.start_lookup(Kind::GsubType6, LookupFlagInfo::default(), 1);
+        let lookup = lookups.current_mut().unwrap();
+        let inline = lookup
+            .as_gsub_contextual()
+            .add_anon_gsub_type_1(class(&[5, 6, 7]), class(&[15, 16, 17]));
+        lookup.add_contextual_rule(
+            vec![class(&[1, 2])],
+            vec![(class(&[5, 6, 7]), vec![inline])],
+            vec![class(&[8, 9, 10, 11])],
+        );
+        lookups.finish_current();
+        assert_eq!(lookups.gsub.len(), 3);
+
+        for lookup in &lookups.gsub {
+            let estimate = lookup.estimated_size();
+            let built = lookup.clone().build(&mut VariationStoreBuilder::new(0));
+            let size = stats::size(&built).unwrap();
+            assert!(estimate >= size, "{estimate} < {size}: {lookup:?}");
+        }
+        assert!(lookups_fit(
+            lookups.gsub.iter().map(|l| Some(l.estimated_size()))
+        ));
+        assert!(!lookups_fit([Some(100), None]));
+        assert!(!lookups_fit([Some(40_000), Some(30_000)]));
+    }
+
+    #[test]
+    fn estimated_gpos_sizes_are_upper_bounds() {
+        let gid = GlyphId16::new;
+        let glyphs = |gids: &[u16]| -> SharedGlyphSet { gids.iter().copied().map(gid).collect() };
+        let class = |gids: &[u16]| GlyphOrClass::Class(gids.iter().copied().map(gid).collect());
+        let flags = LookupFlagInfo::default();
+        let mut lookups = AllLookups::default();
+
+        lookups.start_lookup(Kind::GposType1, flags, 0);
+        lookups.add_gpos_type_1(gid(1), ValueRecord::new().with_x_advance(10));
+        lookups.add_gpos_type_1(gid(2), ValueRecord::new().with_y_placement(-20));
+
+        lookups.start_lookup(Kind::GposType2, flags, 1);
+        lookups.add_gpos_type_2_pair(
+            gid(1),
+            gid(2),
+            ValueRecord::new().with_x_advance(-10),
+            ValueRecord::new(),
+        );
+        lookups.add_gpos_type_2_pair(
+            gid(1),
+            gid(3),
+            ValueRecord::new().with_x_advance(-15),
+            ValueRecord::new().with_x_placement(5),
+        );
+
+        lookups.start_lookup(Kind::GposType2, flags, 2);
+        lookups.add_gpos_type_2_class(
+            glyphs(&[1, 2, 3]),
+            glyphs(&[10, 11]),
+            ValueRecord::new().with_x_advance(-10),
+            ValueRecord::new(),
+        );
+        lookups.add_gpos_type_2_class(
+            glyphs(&[4]),
+            glyphs(&[12, 13, 14]),
+            ValueRecord::new().with_x_advance(20),
+            ValueRecord::new(),
+        );
+
+        lookups.start_lookup(Kind::GposType3, flags, 3);
+        lookups.add_gpos_type_3(gid(5), Some(Anchor::new(0, 100)), None);
+        lookups.add_gpos_type_3(
+            gid(6),
+            Some(Anchor::new(0, 100)),
+            Some(Anchor::new(500, 100)),
+        );
+
+        let top = MarkClass {
+            members: vec![(
+                [gid(20), gid(21)].into_iter().collect(),
+                Some(Anchor::new(100, 500)),
+            )],
+        };
+        let bottom = MarkClass {
+            members: vec![([gid(22)].into_iter().collect(), Some(Anchor::new(100, 0)))],
+        };
+        lookups.start_lookup(Kind::GposType4, flags, 4);
+        for (name, class, base_anchor) in [
+            ("top", &top, Anchor::new(250, 600)),
+            ("bottom", &bottom, Anchor::new(250, -10)),
+        ] {
+            let bases = [gid(1), gid(2), gid(3)];
+            lookups.reserve_mark_attachment(name, class, &bases, Some(&base_anchor));
+            lookups.current_mut().unwrap().with_gpos_type_4(|subtable| {
+                for (glyphs, anchor) in class.anchored_members() {
+                    for glyph in glyphs.iter() {
+                        subtable.insert_mark(glyph, name, anchor.clone()).unwrap();
+                    }
+                }
+                for base in bases {
+                    subtable.insert_base(base, name, base_anchor.clone());
+                }
+            });
+        }
+
+        lookups.start_lookup(Kind::GposType5, flags, 5);
+        let components = vec![
+            BTreeMap::from([("top".to_string(), Anchor::new(200, 600))]),
+            BTreeMap::from([("top".to_string(), Anchor::new(700, 600))]),
+        ];
+        let ligatures = [gid(30), gid(31)];
+        lookups.reserve_mark_to_ligature([("top", &top)], &ligatures, &components);
+        lookups.current_mut().unwrap().with_gpos_type_5(|subtable| {
+            for (glyphs, anchor) in top.anchored_members() {
+                for glyph in glyphs.iter() {
+                    subtable.insert_mark(glyph, "top", anchor.clone()).unwrap();
+                }
+            }
+            for ligature in ligatures {
+                subtable.add_ligature_components_directly(ligature, components.clone());
+            }
+        });
+
+        // a base mark with a NULL anchor still gets a record
+        lookups.start_lookup(Kind::GposType6, flags, 6);
+        let mark2s = [gid(40), gid(41)];
+        lookups.reserve_mark_attachment("top", &top, &mark2s, None);
+        lookups.current_mut().unwrap().with_gpos_type_6(|subtable| {
+            for (glyphs, anchor) in top.anchored_members() {
+                for glyph in glyphs.iter() {
+                    subtable.insert_mark1(glyph, "top", anchor.clone()).unwrap();
+                }
+            }
+            for mark2 in mark2s {
+                subtable.insert_null_mark2(mark2);
+            }
+        });
+
+        lookups.start_lookup(Kind::GposType8, flags, 7);
+        let lookup = lookups.current_mut().unwrap();
+        let inline = lookup.as_gpos_contextual().add_anon_gpos_type_1(
+            &class(&[5, 6, 7]),
+            ValueRecord::new()
+                .with_x_advance(30)
+                .with_x_placement(-10)
+                .with_y_placement(5),
+        );
+        lookup.add_contextual_rule(
+            vec![class(&[1, 2])],
+            vec![(class(&[5, 6, 7]), vec![inline])],
+            vec![class(&[8, 9, 10, 11]), GlyphOrClass::Glyph(gid(12))],
+        );
+        lookups.finish_current();
+        assert_eq!(lookups.gpos.len(), 9);
+
+        let (_, estimates) = lookups.estimated_sizes();
+        for (lookup, estimate) in lookups.gpos.iter().zip(estimates) {
+            // the lookup used by the contextual rule was not built from rules
+            // of its own, and may not have an estimate
+            let Some(estimate) = estimate else {
+                continue;
+            };
+            let built = lookup.clone().build(&mut VariationStoreBuilder::new(0));
+            let size = stats::size(&built).unwrap();
+            assert!(estimate >= size, "{estimate} < {size}: {lookup:?}");
+        }
+    }
+
+    #[test]
+    fn promote_lookups_in_large_gsub() {
+        let gid = GlyphId16::new;
+        let mut lookups = AllLookups::default();
+        // each lookup is about 6kB, so they can't all be reached through
+        // 16-bit offsets
+        for i in 0..40u16 {
+            lookups.start_lookup(Kind::GsubType1, LookupFlagInfo::default(), i.into());
+            let lookup = lookups.current_mut().unwrap();
+            for glyph in 0..1500u16 {
+                // an irregular mapping, so that the subtable can't use a delta
+                lookup.add_gsub_type_1(gid(glyph), gid((glyph * 7 + i) % 3000 + 1));
+            }
+        }
+        lookups.finish_current();
+        let built = lookups
+            .gsub
+            .iter()
+            .map(|lookup| lookup.clone().build(&mut VariationStoreBuilder::new(0)))
+            .collect::<Vec<_>>();
+        let mut gsub = write_gsub::Gsub::new(
+            Default::default(),
+            Default::default(),
+            LookupList::new(built),
+        );
+
+        lookups.promote_to_extension(Some(&mut gsub), None);
+        let n_extensions = gsub
+            .lookup_list
+            .lookups
+            .iter()
+            .filter(|lookup| stats::gsub_lookup(lookup).1)
+            .count();
+        assert!(n_extensions > 0);
+        assert!(
+            n_extensions < 40,
+            "only the largest lookups should be promoted"
+        );
+        assert!(stats::size(&gsub).is_some(), "GSUB still overflows");
+    }
+
+    #[test]
+    fn no_promotion_when_table_fits() {
+        let gid = GlyphId16::new;
+        let mut lookups = AllLookups::default();
+        for i in 0..3u16 {
+            lookups.start_lookup(Kind::GsubType1, LookupFlagInfo::default(), i.into());
+            lookups
+                .current_mut()
+                .unwrap()
+                .add_gsub_type_1(gid(i), gid(i + 10));
+        }
+        lookups.finish_current();
+        let built = lookups
+            .gsub
+            .iter()
+            .map(|lookup| lookup.clone().build(&mut VariationStoreBuilder::new(0)))
+            .collect::<Vec<_>>();
+        let mut gsub = write_gsub::Gsub::new(
+            Default::default(),
+            Default::default(),
+            LookupList::new(built),
+        );
+        promote_until_table_fits(
+            &mut gsub,
+            |gsub| gsub.lookup_list.lookups.as_mut_slice(),
+            stats::gsub_lookup,
+            gsub_extension,
+        );
+        assert!(
+            gsub.lookup_list
+                .lookups
+                .iter()
+                .all(|lookup| !stats::gsub_lookup(lookup).1)
+        );
+    }
+
+    #[test]
+    fn promote_largest_lookups() {
+        let small = [(Some(100), 18), (None, 18), (Some(2000), 18)];
+        assert!(lookups_to_promote(&small).is_empty());
+
+        let large = [
+            (Some(40_000), 18),
+            (Some(100), 18),
+            (None, 18),
+            (Some(30_000), 18),
+        ];
+        assert_eq!(lookups_to_promote(&large), [0]);
+        // sometimes one is not enough
+        let huge = [(Some(70_000), 18), (Some(50_000), 18), (Some(20_000), 18)];
+        assert_eq!(lookups_to_promote(&huge), [0, 1]);
+    }
+}
@@ -20,15 +20,17 @@ use write_fonts::{
     validate::Validate,
 };
 
-use crate::common::GlyphOrClass;
+use crate::{common::GlyphOrClass, compile::closure::SubstitutionRules};
 
 use super::{
     Builder, FilterSetId, LookupBuilder, LookupId, PositionLookup, RemapIds, SubstitutionLookup,
+    coverage_size,
 };
 
 /// When building a contextual/chaining contextual rule, we also build a
 /// bunch of anonymous lookups.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ContextualLookupBuilder<T> {
     pub(super) flags: LookupFlag,
     pub(super) mark_set: Option<FilterSetId>,
@@ -258,23 +260,28 @@ impl ContextualLookupBuilder<SubstitutionLookup> {
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ContextBuilder {
     rules: Vec<ContextRule>,
 }
 
 // we use separate types here to ensure we don't mix lookups
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct PosContextBuilder(ContextBuilder);
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct SubContextBuilder(ContextBuilder);
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ReverseChainBuilder {
     rules: Vec<ReverseSubRule>,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ReverseSubRule {
     backtrack: Vec<GlyphOrClass>,
     context: BTreeMap<GlyphId16, GlyphId16>,
@@ -282,15 +289,19 @@ struct ReverseSubRule {
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ChainContextBuilder(ContextBuilder);
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct PosChainContextBuilder(ChainContextBuilder);
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct SubChainContextBuilder(ChainContextBuilder);
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ContextRule {
     backtrack: Vec<GlyphOrClass>,
     context: Vec<(GlyphOrClass, Vec<LookupId>)>,
@@ -454,9 +465,38 @@ impl SubContextBuilder {
     pub(crate) fn iter_lookups(&self) -> impl Iterator<Item = LookupId> + '_ {
         self.0.iter_lookups()
     }
+
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.0.estimated_size()
+    }
+}
+
+impl PosContextBuilder {
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.0.estimated_size()
+    }
 }
 
 impl ContextRule {
+    /// An upper bound on the size of this rule, as a format 3 subtable.
+    ///
+    /// This includes the offset to the subtable from its lookup.
+    fn estimated_size(&self) -> usize {
+        let n_lookups = self
+            .context
+            .iter()
+            .map(|(_, lookups)| lookups.len())
+            .sum::<usize>();
+        let coverages = self
+            .backtrack
+            .iter()
+            .chain(&self.lookahead)
+            .chain(self.context.iter().map(|(glyphs, _)| glyphs))
+            .map(|glyphs| 2 + coverage_size(glyphs.len()))
+            .sum::<usize>();
+        2 + 10 + 4 * n_lookups + coverages
+    }
+
     /// If `other` has a single input position and the same backtrack,
     /// lookahead, and lookups as `self`, merge `other`'s input glyphs into
     /// `self` and return `true`.  Otherwise return `false` unchanged.
@@ -559,6 +599,14 @@ impl RemapIds for ContextBuilder {
 }
 
 impl ContextBuilder {
+    /// An upper bound on the size of these rules once built.
+    ///
+    /// Format 3 is always possible, and we only use another format if it is
+    /// smaller, so we estimate the size of the format 3 subtables.
+    fn estimated_size(&self) -> usize {
+        self.rules.iter().map(ContextRule::estimated_size).sum()
+    }
+
     fn build(
         self,
         var_store: Option<&mut VariationStoreBuilder>,
@@ -567,6 +615,7 @@ impl ContextBuilder {
         assert!(self.rules.iter().all(|rule| !rule.is_chain_rule()));
         let format_1 = self.build_format_1(in_gpos).map(|x| vec![x]);
         let format_2 = self.build_format_2(in_gpos).map(|x| vec![x]);
+        let mut coverages = CoverageInterner::default();
         let format_3 = self
             .rules
             .into_iter()
@@ -574,7 +623,7 @@ impl ContextBuilder {
                 let cov_tables = rule
                     .context
                     .iter()
-                    .map(|(seq, _)| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|(seq, _)| coverages.get(seq))
                     .collect();
                 let seq_lookups = rule.lookup_records(in_gpos);
 
@@ -730,6 +779,7 @@ impl ChainContextBuilder {
 
     /// format 3 is always possible; it also generates a subtable for each rule.
     fn build_format_3(self, in_gpos: bool) -> Vec<write_layout::ChainedSequenceContext> {
+        let mut coverages = CoverageInterner::default();
         self.0
             .rules
             .into_iter()
@@ -737,17 +787,17 @@ impl ChainContextBuilder {
                 let backtrack = rule
                     .backtrack
                     .iter()
-                    .map(|seq| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|seq| coverages.get(seq))
                     .collect();
                 let lookahead = rule
                     .lookahead
                     .iter()
-                    .map(|seq| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|seq| coverages.get(seq))
                     .collect();
                 let input = rule
                     .context
                     .iter()
-                    .map(|(seq, _)| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|(seq, _)| coverages.get(seq))
                     .collect();
                 let seq_lookups = rule.lookup_records(in_gpos);
 
@@ -775,6 +825,16 @@ impl SubChainContextBuilder {
     pub(crate) fn iter_lookups(&self) -> impl Iterator<Item = LookupId> + '_ {
         self.0.iter_lookups()
     }
+
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.0.0.estimated_size()
+    }
+}
+
+impl PosChainContextBuilder {
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.0.0.estimated_size()
+    }
 }
 
 impl Builder for PosChainContextBuilder {
@@ -804,6 +864,34 @@ impl RemapIds for SubChainContextBuilder {
         self.0.0.remap_ids(id_map);
     }
 }
+
+/// Builds the coverage tables for format 3 subtables.
+///
+/// Rules in the same lookup often repeat a backtrack or lookahead sequence.
+/// Each distinct set of glyphs is only built once here, and the serializer
+/// writes identical tables only once, so the copies share a single table
+/// in the compiled font.
+#[derive(Default)]
+struct CoverageInterner(HashMap<Vec<GlyphId16>, write_layout::CoverageTable>);
+
+impl CoverageInterner {
+    fn get(&mut self, glyphs: &GlyphOrClass) -> write_layout::CoverageTable {
+        let mut key = glyphs.iter().collect::<Vec<_>>();
+        key.sort_unstable();
+        key.dedup();
+        self.0
+            .entry(key)
+            .or_insert_with_key(|glyphs| {
+                glyphs
+                    .iter()
+                    .copied()
+                    .collect::<CoverageTableBuilder>()
+                    .build()
+            })
+            .clone()
+    }
+}
+
 // invariant: at least one item must be Some
 fn pick_best_format<T: FontWrite + Validate>(subtables: [Option<Vec<T>>; 3]) -> Vec<T> {
     // first see if there's only one table present, in which case we can exit early:
@@ -852,24 +940,50 @@ impl ReverseChainBuilder {
             lookahead,
         })
     }
+
+    /// An upper bound on the size of these rules once built.
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let coverages = rule
+                    .backtrack
+                    .iter()
+                    .chain(&rule.lookahead)
+                    .map(|glyphs| 2 + coverage_size(glyphs.len()))
+                    .sum::<usize>();
+                let n_glyphs = rule.context.len();
+                2 + 10 + 2 * n_glyphs + coverage_size(n_glyphs) + coverages
+            })
+            .sum()
+    }
+}
+
+impl SubstitutionRules for ReverseChainBuilder {
+    fn for_each_rule(&self, f: &mut dyn FnMut(GlyphId16, &[GlyphId16], &[GlyphId16])) {
+        for (target, replacement) in self.rules.iter().flat_map(|rule| rule.context.iter()) {
+            f(*target, &[], std::slice::from_ref(replacement));
+        }
+    }
 }
 
 impl Builder for ReverseChainBuilder {
     type Output = Vec<ReverseChainSingleSubstFormat1>;
 
     fn build(self, _: &mut VariationStoreBuilder) -> Self::Output {
+        let mut coverages = CoverageInterner::default();
         self.rules
             .into_iter()
             .map(|rule| {
                 let backtrack = rule
                     .backtrack
                     .iter()
-                    .map(|seq| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|seq| coverages.get(seq))
                     .collect();
                 let lookahead = rule
                     .lookahead
                     .iter()
-                    .map(|seq| seq.iter().collect::<CoverageTableBuilder>().build())
+                    .map(|seq| coverages.get(seq))
                     .collect();
                 let input = rule
                     .context
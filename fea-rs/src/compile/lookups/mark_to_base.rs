@@ -0,0 +1,205 @@
+//! The mark-to-base and mark-to-mark subtable builders
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Deref, DerefMut},
+};
+
+use write_fonts::{
+    OffsetMarker,
+    tables::{
+        gpos::{
+            self as write_gpos,
+            builders::{MarkToBaseBuilder, MarkToMarkBuilder},
+        },
+        layout::{
+            CoverageTable,
+            builders::{Builder, CoverageTableBuilder},
+        },
+        variations::ivs_builder::VariationStoreBuilder,
+    },
+    types::GlyphId16,
+};
+
+/// A mark-to-base subtable, including bases whose anchors are all NULL.
+///
+/// The write-fonts builder only adds a base glyph along with an anchor, so a
+/// rule like `pos base a <anchor NULL> mark @TOP;` would leave `a` out of the
+/// base coverage. feaLib keeps it, with a null anchor offset for every mark
+/// class, and so do we: those glyphs are tracked here and added to the
+/// subtable after it is built.
+///
+/// This derefs to the write-fonts builder, which is used for everything else.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MarkToBaseSubtable {
+    pub(super) builder: MarkToBaseBuilder,
+    pub(super) null_bases: BTreeSet<GlyphId16>,
+}
+
+impl MarkToBaseSubtable {
+    /// Add a base glyph whose anchor is NULL.
+    ///
+    /// This has no effect if the glyph also has an anchor for some class.
+    pub(crate) fn insert_null_base(&mut self, glyph: GlyphId16) {
+        self.null_bases.insert(glyph);
+    }
+
+    /// The glyphs in the base coverage, in no particular order.
+    pub(crate) fn base_glyphs(&self) -> impl Iterator<Item = GlyphId16> + '_ {
+        self.builder
+            .base_glyphs()
+            .chain(self.null_bases.iter().copied())
+    }
+}
+
+impl Deref for MarkToBaseSubtable {
+    type Target = MarkToBaseBuilder;
+
+    fn deref(&self) -> &Self::Target {
+        &self.builder
+    }
+}
+
+impl DerefMut for MarkToBaseSubtable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.builder
+    }
+}
+
+impl From<MarkToBaseBuilder> for MarkToBaseSubtable {
+    fn from(builder: MarkToBaseBuilder) -> Self {
+        MarkToBaseSubtable {
+            builder,
+            null_bases: Default::default(),
+        }
+    }
+}
+
+impl Builder for MarkToBaseSubtable {
+    type Output = Vec<write_gpos::MarkBasePosFormat1>;
+
+    fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+        let mut subtables = self.builder.build(var_store);
+        // the builder always produces a single subtable
+        if let Some(subtable) = subtables.first_mut() {
+            add_null_bases(subtable, self.null_bases);
+        }
+        subtables
+    }
+}
+
+/// A mark-to-mark subtable, including base marks whose anchors are all NULL.
+///
+/// This is the mark-to-mark equivalent of [`MarkToBaseSubtable`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MarkToMarkSubtable {
+    pub(super) builder: MarkToMarkBuilder,
+    pub(super) null_mark2s: BTreeSet<GlyphId16>,
+}
+
+impl MarkToMarkSubtable {
+    /// Add a base mark whose anchor is NULL.
+    ///
+    /// This has no effect if the glyph also has an anchor for some class.
+    pub(crate) fn insert_null_mark2(&mut self, glyph: GlyphId16) {
+        self.null_mark2s.insert(glyph);
+    }
+
+    /// The glyphs in the base mark coverage, in no particular order.
+    pub(crate) fn mark2_glyphs(&self) -> impl Iterator<Item = GlyphId16> + '_ {
+        self.builder
+            .mark2_glyphs()
+            .chain(self.null_mark2s.iter().copied())
+    }
+}
+
+impl Deref for MarkToMarkSubtable {
+    type Target = MarkToMarkBuilder;
+
+    fn deref(&self) -> &Self::Target {
+        &self.builder
+    }
+}
+
+impl DerefMut for MarkToMarkSubtable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.builder
+    }
+}
+
+impl From<MarkToMarkBuilder> for MarkToMarkSubtable {
+    fn from(builder: MarkToMarkBuilder) -> Self {
+        MarkToMarkSubtable {
+            builder,
+            null_mark2s: Default::default(),
+        }
+    }
+}
+
+impl Builder for MarkToMarkSubtable {
+    type Output = Vec<write_gpos::MarkMarkPosFormat1>;
+
+    fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+        let mut subtables = self.builder.build(var_store);
+        // the builder always produces a single subtable
+        if let Some(subtable) = subtables.first_mut() {
+            let n_classes = n_mark_classes(&subtable.mark1_array);
+            add_null_records(
+                &mut subtable.mark2_coverage,
+                &mut subtable.mark2_array.mark2_records,
+                self.null_mark2s,
+                || write_gpos::Mark2Record::new(vec![None; n_classes]),
+            );
+        }
+        subtables
+    }
+}
+
+/// Add each of `glyphs` that isn't already a base, with a null anchor for
+/// every mark class.
+fn add_null_bases(subtable: &mut write_gpos::MarkBasePosFormat1, glyphs: BTreeSet<GlyphId16>) {
+    let n_classes = n_mark_classes(&subtable.mark_array);
+    add_null_records(
+        &mut subtable.base_coverage,
+        &mut subtable.base_array.base_records,
+        glyphs,
+        || write_gpos::BaseRecord::new(vec![None; n_classes]),
+    );
+}
+
+/// The number of mark classes used by the marks in this array.
+fn n_mark_classes(marks: &write_gpos::MarkArray) -> usize {
+    marks
+        .mark_records
+        .iter()
+        .map(|record| record.mark_class as usize + 1)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Add a record for each of `glyphs` that isn't already in `coverage`, keeping
+/// the records in coverage order.
+fn add_null_records<R>(
+    coverage: &mut OffsetMarker<CoverageTable>,
+    records: &mut Vec<R>,
+    glyphs: BTreeSet<GlyphId16>,
+    null_record: impl Fn() -> R,
+) {
+    if glyphs.is_empty() {
+        return;
+    }
+    let mut by_glyph = coverage
+        .iter()
+        .zip(std::mem::take(records))
+        .collect::<BTreeMap<_, _>>();
+    for glyph in glyphs {
+        by_glyph.entry(glyph).or_insert_with(&null_record);
+    }
+    *coverage = by_glyph
+        .keys()
+        .copied()
+        .collect::<CoverageTableBuilder>()
+        .build()
+        .into();
+    *records = by_glyph.into_values().collect();
+}
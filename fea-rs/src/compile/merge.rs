@@ -0,0 +1,856 @@
+//! Merging the layout tables of two compilations
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use write_fonts::{
+    OffsetMarker,
+    read::TopLevelTable,
+    tables::{
+        gdef::{AttachList, AttachPoint, Gdef, LigCaretList, LigGlyph, MarkGlyphSets},
+        gpos::{self, Gpos, PositionLookup},
+        gsub::{self, Gsub, SubstitutionLookup},
+        layout::{
+            ChainedSequenceContext, ClassDef, CoverageTable, Feature, FeatureList, FeatureRecord,
+            FeatureTableSubstitutionRecord, FeatureVariations, LangSys, LangSysRecord, Lookup,
+            LookupFlag, LookupList, Script, ScriptList, ScriptRecord, SequenceContext,
+            SequenceLookupRecord, builders::CoverageTableBuilder,
+        },
+    },
+    types::{GlyphId16, Tag},
+};
+
+use super::{Compilation, LookupId, error::MergeError, output::DebugLookups, tags};
+
+/// The `Debg` entry for a lookup that we know nothing about.
+const EMPTY_DEBUG_ENTRY: &str = "[null, null, null]";
+
+/// A feature in the merged table, identified by the features it combines.
+///
+/// This is the index of our feature and of theirs, either of which may be
+/// missing.
+type FeaturePair = (Option<u16>, Option<u16>);
+
+/// The merged mark attachment classes, and the new value of each of theirs.
+type MarkAttachClasses = (BTreeMap<GlyphId16, u16>, HashMap<u16, u16>);
+
+/// Merge the layout tables of `theirs` into `ours`.
+///
+/// Everything that can fail is checked before anything is modified.
+pub(crate) fn merge(ours: &mut Compilation, theirs: Compilation) -> Result<(), MergeError> {
+    let gdef = merge_gdef(ours.gdef.as_ref(), theirs.gdef.as_ref())?;
+    let gsub_plan = theirs
+        .gsub
+        .as_ref()
+        .map(|gsub| LayoutPlan::new(ours.gsub.as_ref(), gsub))
+        .transpose()?;
+    let gpos_plan = theirs
+        .gpos
+        .as_ref()
+        .map(|gpos| LayoutPlan::new(ours.gpos.as_ref(), gpos))
+        .transpose()?;
+
+    let n_gsub_lookups = ours
+        .gsub
+        .as_ref()
+        .map_or(0, |gsub| gsub.lookup_list.lookups.len());
+    let n_gpos_lookups = ours
+        .gpos
+        .as_ref()
+        .map_or(0, |gpos| gpos.lookup_list.lookups.len());
+    let their_gsub_lookups = theirs
+        .gsub
+        .as_ref()
+        .map_or(0, |gsub| gsub.lookup_list.lookups.len());
+    let their_gpos_lookups = theirs
+        .gpos
+        .as_ref()
+        .map_or(0, |gpos| gpos.lookup_list.lookups.len());
+
+    let remap = match gdef {
+        Some((gdef, remap)) => {
+            ours.gdef = Some(gdef);
+            remap
+        }
+        None => Default::default(),
+    };
+    if let (Some(plan), Some(gsub)) = (gsub_plan, theirs.gsub) {
+        ours.gsub = Some(plan.apply(ours.gsub.take(), gsub, &remap));
+    }
+    if let (Some(plan), Some(gpos)) = (gpos_plan, theirs.gpos) {
+        ours.gpos = Some(plan.apply(ours.gpos.take(), gpos, &remap));
+    }
+    ours.gsub_lookup_names.extend(theirs.gsub_lookup_names);
+    ours.gpos_lookup_names.extend(theirs.gpos_lookup_names);
+    if ours.debug_lookups.is_some() || theirs.debug_lookups.is_some() {
+        // a compilation without a Debg table gets an empty entry per lookup
+        let entries = |debug: Option<DebugLookups>, n_gsub: usize, n_gpos: usize| {
+            debug.unwrap_or_else(|| DebugLookups {
+                gsub: vec![EMPTY_DEBUG_ENTRY.into(); n_gsub],
+                gpos: vec![EMPTY_DEBUG_ENTRY.into(); n_gpos],
+            })
+        };
+        let mut debug = entries(ours.debug_lookups.take(), n_gsub_lookups, n_gpos_lookups);
+        let theirs = entries(theirs.debug_lookups, their_gsub_lookups, their_gpos_lookups);
+        debug.gsub.extend(theirs.gsub);
+        debug.gpos.extend(theirs.gpos);
+        ours.debg = Some(debug.to_bytes());
+        ours.debug_lookups = Some(debug);
+    }
+    for (tag, mut marker) in theirs.insert_markers {
+        match &mut marker.lookup_id {
+            LookupId::Gsub(idx) => *idx += n_gsub_lookups,
+            LookupId::Gpos(idx) => *idx += n_gpos_lookups,
+            _ => (),
+        }
+        ours.insert_markers.entry(tag).or_insert(marker);
+    }
+    if let Some(classes) = theirs.gdef_classes {
+        let ours = ours.gdef_classes.get_or_insert_default();
+        for (glyph, class) in classes {
+            ours.entry(glyph).or_insert(class);
+        }
+    }
+    Ok(())
+}
+
+/// How references to GDEF in their lookups change in the merged tables.
+#[derive(Clone, Debug, Default)]
+struct GdefRemap {
+    mark_attach_classes: HashMap<u16, u16>,
+    // the new index of each of their mark filtering sets
+    mark_filtering_sets: Vec<u16>,
+}
+
+impl GdefRemap {
+    fn remap_lookup(&self, lookup: &mut impl LookupRefs, lookup_delta: u16) {
+        let (flags, filter_set) = lookup.flags_mut();
+        if let Some(class) = flags.mark_attachment_class()
+            && let Some(new_class) = self.mark_attach_classes.get(&class)
+        {
+            flags.set_mark_attachment_class(*new_class);
+        }
+        if let Some(set) = filter_set.as_mut()
+            && let Some(new_set) = self.mark_filtering_sets.get(*set as usize)
+        {
+            *set = *new_set;
+        }
+        lookup.remap_lookup_indices(&|idx| idx + lookup_delta);
+    }
+}
+
+/// Combine two GDEF tables.
+///
+/// Returns `None` if they don't have a GDEF table, in which case ours is
+/// unchanged.
+fn merge_gdef(
+    ours: Option<&Gdef>,
+    theirs: Option<&Gdef>,
+) -> Result<Option<(Gdef, GdefRemap)>, MergeError> {
+    let Some(theirs) = theirs else {
+        return Ok(None);
+    };
+    let Some(ours) = ours else {
+        return Ok(Some((theirs.clone(), GdefRemap::default())));
+    };
+    if ours.item_var_store.as_ref().is_some() && theirs.item_var_store.as_ref().is_some() {
+        return Err(MergeError::VariationStoreConflict);
+    }
+
+    // for glyph classes, ours take precedence
+    let mut glyph_classes = class_def_map(theirs.glyph_class_def.as_ref());
+    glyph_classes.extend(class_def_map(ours.glyph_class_def.as_ref()));
+    let (mark_attach_classes, mark_attach_remap) = merge_mark_attach_classes(
+        ours.mark_attach_class_def.as_ref(),
+        theirs.mark_attach_class_def.as_ref(),
+    )?;
+    let (mark_glyph_sets, mark_filtering_sets) = merge_mark_glyph_sets(
+        ours.mark_glyph_sets_def.as_ref(),
+        theirs.mark_glyph_sets_def.as_ref(),
+    )?;
+    let attach_list = merge_glyph_tables(
+        attach_points(ours.attach_list.as_ref()),
+        attach_points(theirs.attach_list.as_ref()),
+    )
+    .map(|(coverage, points)| AttachList::new(coverage, points));
+    let lig_caret_list = merge_glyph_tables(
+        lig_glyphs(ours.lig_caret_list.as_ref()),
+        lig_glyphs(theirs.lig_caret_list.as_ref()),
+    )
+    .map(|(coverage, lig_glyphs)| LigCaretList::new(coverage, lig_glyphs));
+
+    let mut gdef = Gdef::new(
+        to_class_def(glyph_classes),
+        attach_list,
+        lig_caret_list,
+        to_class_def(mark_attach_classes),
+    );
+    gdef.mark_glyph_sets_def = (!mark_glyph_sets.is_empty())
+        .then(|| MarkGlyphSets::new(mark_glyph_sets))
+        .into();
+    gdef.item_var_store = if ours.item_var_store.as_ref().is_some() {
+        ours.item_var_store.clone()
+    } else {
+        theirs.item_var_store.clone()
+    };
+    let remap = GdefRemap {
+        mark_attach_classes: mark_attach_remap,
+        mark_filtering_sets,
+    };
+    Ok(Some((gdef, remap)))
+}
+
+fn class_def_map(class_def: Option<&ClassDef>) -> BTreeMap<GlyphId16, u16> {
+    class_def
+        .into_iter()
+        .flat_map(|class_def| class_def.iter())
+        .collect()
+}
+
+fn to_class_def(classes: BTreeMap<GlyphId16, u16>) -> Option<ClassDef> {
+    (!classes.is_empty()).then(|| classes.into_iter().collect())
+}
+
+/// Combine mark attachment classes, giving their classes new values.
+///
+/// A class of theirs that has the same glyphs as one of ours is mapped to it;
+/// other classes are added after ours. Returns the merged classes, and the
+/// new value of each of their classes.
+fn merge_mark_attach_classes(
+    ours: Option<&ClassDef>,
+    theirs: Option<&ClassDef>,
+) -> Result<MarkAttachClasses, MergeError> {
+    let group = |classes: &BTreeMap<GlyphId16, u16>| {
+        let mut groups = BTreeMap::<u16, BTreeSet<GlyphId16>>::new();
+        for (glyph, class) in classes {
+            groups.entry(*class).or_default().insert(*glyph);
+        }
+        groups
+    };
+    let mut merged = class_def_map(ours);
+    let ours_groups = group(&merged);
+    let mut next_class = ours_groups.keys().last().map_or(1, |class| class + 1);
+    let mut remap = HashMap::new();
+    for (class, glyphs) in group(&class_def_map(theirs)) {
+        let new_class = match ours_groups.iter().find(|(_, ours)| **ours == glyphs) {
+            Some((existing, _)) => *existing,
+            None => {
+                next_class += 1;
+                next_class - 1
+            }
+        };
+        // the class is stored in the high byte of the lookup flag
+        if new_class > u8::MAX as u16 {
+            return Err(MergeError::TooManyMarkAttachClasses);
+        }
+        for glyph in glyphs {
+            if *merged.entry(glyph).or_insert(new_class) != new_class {
+                return Err(MergeError::MarkAttachClassConflict { glyph });
+            }
+        }
+        remap.insert(class, new_class);
+    }
+    Ok((merged, remap))
+}
+
+/// Combine mark glyph sets, reusing ours where they are the same.
+///
+/// Returns the merged sets, and the new index of each of their sets.
+fn merge_mark_glyph_sets(
+    ours: Option<&MarkGlyphSets>,
+    theirs: Option<&MarkGlyphSets>,
+) -> Result<(Vec<CoverageTable>, Vec<u16>), MergeError> {
+    let mut sets = ours
+        .into_iter()
+        .flat_map(|sets| sets.coverages.iter())
+        .map(|coverage| (**coverage).clone())
+        .collect::<Vec<_>>();
+    let remap = theirs
+        .into_iter()
+        .flat_map(|sets| sets.coverages.iter())
+        .map(|coverage| {
+            let idx = match sets.iter().position(|set| set.iter().eq(coverage.iter())) {
+                Some(idx) => idx,
+                None => {
+                    sets.push((**coverage).clone());
+                    sets.len() - 1
+                }
+            };
+            // the set is referenced by a u16 in the lookup
+            idx.try_into().map_err(|_| MergeError::TooManyMarkGlyphSets)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((sets, remap))
+}
+
+fn attach_points(list: Option<&AttachList>) -> impl Iterator<Item = (GlyphId16, &AttachPoint)> {
+    list.into_iter()
+        .flat_map(|list| list.coverage.iter().zip(list.attach_points.iter()))
+        .map(|(glyph, point)| (glyph, &**point))
+}
+
+fn lig_glyphs(list: Option<&LigCaretList>) -> impl Iterator<Item = (GlyphId16, &LigGlyph)> {
+    list.into_iter()
+        .flat_map(|list| list.coverage.iter().zip(list.lig_glyphs.iter()))
+        .map(|(glyph, lig_glyph)| (glyph, &**lig_glyph))
+}
+
+/// Combine two sets of tables that are keyed by a coverage table.
+///
+/// If both have a table for a glyph, ours is used.
+fn merge_glyph_tables<'a, T: Clone + 'a>(
+    ours: impl Iterator<Item = (GlyphId16, &'a T)>,
+    theirs: impl Iterator<Item = (GlyphId16, &'a T)>,
+) -> Option<(CoverageTable, Vec<T>)> {
+    let mut tables = theirs.collect::<BTreeMap<_, _>>();
+    tables.extend(ours);
+    (!tables.is_empty()).then(|| {
+        let coverage = tables
+            .keys()
+            .copied()
+            .collect::<CoverageTableBuilder>()
+            .build();
+        (coverage, tables.into_values().cloned().collect())
+    })
+}
+
+/// The parts of a lookup that refer to GDEF or to other lookups.
+pub(super) trait LookupRefs {
+    /// The lookup flags and mark filtering set
+    fn flags_mut(&mut self) -> (&mut LookupFlag, &mut Option<u16>);
+
+    /// Change the index of each lookup referenced by a contextual subtable.
+    fn remap_lookup_indices(&mut self, remap: &dyn Fn(u16) -> u16);
+}
+
+fn flags_mut<T>(lookup: &mut Lookup<T>) -> (&mut LookupFlag, &mut Option<u16>) {
+    (&mut lookup.lookup_flag, &mut lookup.mark_filtering_set)
+}
+
+impl LookupRefs for SubstitutionLookup {
+    fn flags_mut(&mut self) -> (&mut LookupFlag, &mut Option<u16>) {
+        match self {
+            SubstitutionLookup::Single(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Multiple(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Alternate(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Ligature(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Contextual(lookup) => flags_mut(lookup),
+            SubstitutionLookup::ChainContextual(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Extension(lookup) => flags_mut(lookup),
+            SubstitutionLookup::Reverse(lookup) => flags_mut(lookup),
+        }
+    }
+
+    fn remap_lookup_indices(&mut self, remap: &dyn Fn(u16) -> u16) {
+        match self {
+            SubstitutionLookup::Contextual(lookup) => lookup
+                .subtables
+                .iter_mut()
+                .for_each(|sub| remap_context(sub, remap)),
+            SubstitutionLookup::ChainContextual(lookup) => lookup
+                .subtables
+                .iter_mut()
+                .for_each(|sub| remap_chain_context(sub, remap)),
+            SubstitutionLookup::Extension(lookup) => {
+                for sub in lookup.subtables.iter_mut() {
+                    match &mut **sub {
+                        gsub::ExtensionSubtable::Contextual(ext) => {
+                            remap_context(&mut ext.extension, remap)
+                        }
+                        gsub::ExtensionSubtable::ChainContextual(ext) => {
+                            remap_chain_context(&mut ext.extension, remap)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl LookupRefs for PositionLookup {
+    fn flags_mut(&mut self) -> (&mut LookupFlag, &mut Option<u16>) {
+        match self {
+            PositionLookup::Single(lookup) => flags_mut(lookup),
+            PositionLookup::Pair(lookup) => flags_mut(lookup),
+            PositionLookup::Cursive(lookup) => flags_mut(lookup),
+            PositionLookup::MarkToBase(lookup) => flags_mut(lookup),
+            PositionLookup::MarkToLig(lookup) => flags_mut(lookup),
+            PositionLookup::MarkToMark(lookup) => flags_mut(lookup),
+            PositionLookup::Contextual(lookup) => flags_mut(lookup),
+            PositionLookup::ChainContextual(lookup) => flags_mut(lookup),
+            PositionLookup::Extension(lookup) => flags_mut(lookup),
+        }
+    }
+
+    fn remap_lookup_indices(&mut self, remap: &dyn Fn(u16) -> u16) {
+        match self {
+            PositionLookup::Contextual(lookup) => lookup
+                .subtables
+                .iter_mut()
+                .for_each(|sub| remap_context(sub, remap)),
+            PositionLookup::ChainContextual(lookup) => lookup
+                .subtables
+                .iter_mut()
+                .for_each(|sub| remap_chain_context(sub, remap)),
+            PositionLookup::Extension(lookup) => {
+                for sub in lookup.subtables.iter_mut() {
+                    match &mut **sub {
+                        gpos::ExtensionSubtable::Contextual(ext) => {
+                            remap_context(&mut ext.extension, remap)
+                        }
+                        gpos::ExtensionSubtable::ChainContextual(ext) => {
+                            remap_chain_context(&mut ext.extension, remap)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn remap_records(records: &mut [SequenceLookupRecord], remap: &dyn Fn(u16) -> u16) {
+    for record in records {
+        record.lookup_list_index = remap(record.lookup_list_index);
+    }
+}
+
+fn remap_context(context: &mut SequenceContext, remap: &dyn Fn(u16) -> u16) {
+    match context {
+        SequenceContext::Format1(table) => {
+            for set in table
+                .seq_rule_sets
+                .iter_mut()
+                .filter_map(|set| set.as_mut())
+            {
+                for rule in set.seq_rules.iter_mut() {
+                    remap_records(&mut rule.seq_lookup_records, remap);
+                }
+            }
+        }
+        SequenceContext::Format2(table) => {
+            for set in table
+                .class_seq_rule_sets
+                .iter_mut()
+                .filter_map(|set| set.as_mut())
+            {
+                for rule in set.class_seq_rules.iter_mut() {
+                    remap_records(&mut rule.seq_lookup_records, remap);
+                }
+            }
+        }
+        SequenceContext::Format3(table) => remap_records(&mut table.seq_lookup_records, remap),
+    }
+}
+
+fn remap_chain_context(context: &mut ChainedSequenceContext, remap: &dyn Fn(u16) -> u16) {
+    match context {
+        ChainedSequenceContext::Format1(table) => {
+            for set in table
+                .chained_seq_rule_sets
+                .iter_mut()
+                .filter_map(|set| set.as_mut())
+            {
+                for rule in set.chained_seq_rules.iter_mut() {
+                    remap_records(&mut rule.seq_lookup_records, remap);
+                }
+            }
+        }
+        ChainedSequenceContext::Format2(table) => {
+            for set in table
+                .chained_class_seq_rule_sets
+                .iter_mut()
+                .filter_map(|set| set.as_mut())
+            {
+                for rule in set.chained_class_seq_rules.iter_mut() {
+                    remap_records(&mut rule.seq_lookup_records, remap);
+                }
+            }
+        }
+        ChainedSequenceContext::Format3(table) => {
+            remap_records(&mut table.seq_lookup_records, remap)
+        }
+    }
+}
+
+/// The contents of a GSUB or GPOS table.
+pub(super) struct Layout<L> {
+    pub(super) scripts: ScriptList,
+    pub(super) features: FeatureList,
+    pub(super) lookups: Vec<L>,
+    pub(super) variations: Option<FeatureVariations>,
+}
+
+impl<L> Default for Layout<L> {
+    fn default() -> Self {
+        Layout {
+            scripts: Default::default(),
+            features: Default::default(),
+            lookups: Vec::new(),
+            variations: None,
+        }
+    }
+}
+
+/// Shared access to the GSUB and GPOS tables.
+pub(super) trait LayoutTable: TopLevelTable + Sized {
+    type Lookup: LookupRefs;
+
+    fn script_list(&self) -> &ScriptList;
+    fn feature_list(&self) -> &FeatureList;
+    fn lookup_count(&self) -> usize;
+    fn into_layout(self) -> Layout<Self::Lookup>;
+    fn from_layout(layout: Layout<Self::Lookup>) -> Self;
+}
+
+macro_rules! impl_layout_table {
+    ($table:ident, $lookup:ty) => {
+        impl LayoutTable for $table {
+            type Lookup = $lookup;
+
+            fn script_list(&self) -> &ScriptList {
+                &self.script_list
+            }
+
+            fn feature_list(&self) -> &FeatureList {
+                &self.feature_list
+            }
+
+            fn lookup_count(&self) -> usize {
+                self.lookup_list.lookups.len()
+            }
+
+            fn into_layout(self) -> Layout<$lookup> {
+                Layout {
+                    scripts: self.script_list.into_inner(),
+                    features: self.feature_list.into_inner(),
+                    lookups: self
+                        .lookup_list
+                        .into_inner()
+                        .lookups
+                        .into_iter()
+                        .map(OffsetMarker::into_inner)
+                        .collect(),
+                    variations: self.feature_variations.into_inner(),
+                }
+            }
+
+            fn from_layout(layout: Layout<$lookup>) -> Self {
+                let mut table = $table::new(
+                    layout.scripts,
+                    layout.features,
+                    LookupList::new(layout.lookups),
+                );
+                table.feature_variations = layout.variations.into();
+                table
+            }
+        }
+    };
+}
+
+impl_layout_table!(Gsub, SubstitutionLookup);
+impl_layout_table!(Gpos, PositionLookup);
+
+/// A language system in the merged table.
+#[derive(Clone, Debug, Default)]
+struct MergedLangSys {
+    required: Option<FeaturePair>,
+    features: Vec<FeaturePair>,
+}
+
+/// How the features and language systems of two tables are combined.
+struct LayoutPlan {
+    lang_systems: BTreeMap<Tag, BTreeMap<Tag, MergedLangSys>>,
+    // the merged features, in feature list order
+    features: Vec<FeaturePair>,
+}
+
+impl LayoutPlan {
+    fn new<T: LayoutTable>(ours: Option<&T>, theirs: &T) -> Result<Self, MergeError> {
+        let lookup_count = ours.map_or(0, T::lookup_count) + theirs.lookup_count();
+        if lookup_count > u16::MAX as usize {
+            return Err(MergeError::TableOverflow(T::TAG));
+        }
+        let ours_systems = lang_systems(ours.map(T::script_list));
+        let theirs_systems = lang_systems(Some(theirs.script_list()));
+        let ours_tags = feature_tags(ours.map(T::feature_list));
+        let theirs_tags = feature_tags(Some(theirs.feature_list()));
+        let tag = |(ours_idx, theirs_idx): FeaturePair| match ours_idx {
+            Some(idx) => ours_tags[idx as usize],
+            None => theirs_tags[theirs_idx.unwrap() as usize],
+        };
+
+        let keys = ours_systems
+            .iter()
+            .chain(&theirs_systems)
+            .flat_map(|(script, langs)| langs.keys().map(|lang| (*script, *lang)))
+            .collect::<BTreeSet<_>>();
+        let mut lang_systems = BTreeMap::<Tag, BTreeMap<Tag, MergedLangSys>>::new();
+        for (script, language) in keys {
+            let ours_sys = resolve_lang_sys(&ours_systems, script, language);
+            let theirs_sys = resolve_lang_sys(&theirs_systems, script, language);
+            let required = match (required_feature(ours_sys), required_feature(theirs_sys)) {
+                (None, None) => None,
+                (Some(ours), Some(theirs))
+                    if tag((Some(ours), None)) != tag((None, Some(theirs))) =>
+                {
+                    return Err(MergeError::RequiredFeatureConflict {
+                        table: T::TAG,
+                        script,
+                        language,
+                    });
+                }
+                pair => Some(pair),
+            };
+
+            // each of their features is paired with one of ours with the same tag
+            let mut features = ours_sys
+                .into_iter()
+                .flat_map(|sys| sys.feature_indices.iter())
+                .map(|idx| (Some(*idx), None))
+                .collect::<Vec<FeaturePair>>();
+            for idx in theirs_sys
+                .into_iter()
+                .flat_map(|sys| sys.feature_indices.iter())
+            {
+                let their_tag = tag((None, Some(*idx)));
+                match features
+                    .iter_mut()
+                    .find(|pair| pair.0.is_some() && pair.1.is_none() && tag(**pair) == their_tag)
+                {
+                    Some(pair) => pair.1 = Some(*idx),
+                    None => features.push((None, Some(*idx))),
+                }
+            }
+            lang_systems
+                .entry(script)
+                .or_default()
+                .insert(language, MergedLangSys { required, features });
+        }
+
+        let mut features = lang_systems
+            .values()
+            .flat_map(|langs| langs.values())
+            .flat_map(|sys| sys.required.iter().chain(&sys.features))
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        features.sort_by_key(|pair| (tag(*pair), *pair));
+        if features.len() > u16::MAX as usize {
+            return Err(MergeError::TableOverflow(T::TAG));
+        }
+        Ok(LayoutPlan {
+            lang_systems,
+            features,
+        })
+    }
+
+    fn apply<T: LayoutTable>(self, ours: Option<T>, theirs: T, remap: &GdefRemap) -> T {
+        let ours = ours.map(T::into_layout).unwrap_or_default();
+        let theirs = theirs.into_layout();
+        let delta = ours.lookups.len() as u16;
+        let feature_ids = self
+            .features
+            .iter()
+            .enumerate()
+            .map(|(idx, pair)| (*pair, idx as u16))
+            .collect::<HashMap<_, _>>();
+
+        let feature_records = self
+            .features
+            .iter()
+            .map(|(ours_idx, theirs_idx)| {
+                let ours_record = feature_record(&ours.features, *ours_idx);
+                let theirs_record = feature_record(&theirs.features, *theirs_idx);
+                let tag = ours_record.or(theirs_record).unwrap().feature_tag;
+                let feature = merge_features(
+                    ours_record.map(|rec| &*rec.feature),
+                    theirs_record.map(|rec| &*rec.feature),
+                    delta,
+                );
+                FeatureRecord::new(tag, feature)
+            })
+            .collect();
+
+        let script_records = self
+            .lang_systems
+            .iter()
+            .map(|(script_tag, langs)| {
+                let mut script = Script::default();
+                for (lang_tag, merged) in langs {
+                    let mut lang_sys = LangSys::default();
+                    if let Some(required) = merged.required {
+                        lang_sys.required_feature_index = feature_ids[&required];
+                    }
+                    lang_sys.feature_indices = merged
+                        .features
+                        .iter()
+                        .map(|pair| feature_ids[pair])
+                        .collect();
+                    lang_sys.feature_indices.sort_unstable();
+                    if *lang_tag == tags::LANG_DFLT {
+                        script.default_lang_sys = lang_sys.into();
+                    } else {
+                        script
+                            .lang_sys_records
+                            .push(LangSysRecord::new(*lang_tag, lang_sys));
+                    }
+                }
+                ScriptRecord::new(*script_tag, script)
+            })
+            .collect();
+
+        // our variations are checked first; each alternate feature also gets
+        // the lookups of the feature it was merged with.
+        let mut variation_records = Vec::new();
+        for mut record in ours
+            .variations
+            .into_iter()
+            .flat_map(|variations| variations.feature_variation_records)
+        {
+            if let Some(substitution) = record.feature_table_substitution.as_mut() {
+                substitution.substitutions = self.remap_substitutions(
+                    &substitution.substitutions,
+                    |pair| pair.0,
+                    |alternate, pair| {
+                        let theirs = feature_record(&theirs.features, pair.1);
+                        merge_features(Some(alternate), theirs.map(|rec| &*rec.feature), delta)
+                    },
+                );
+            }
+            variation_records.push(record);
+        }
+        for mut record in theirs
+            .variations
+            .into_iter()
+            .flat_map(|variations| variations.feature_variation_records)
+        {
+            if let Some(substitution) = record.feature_table_substitution.as_mut() {
+                substitution.substitutions = self.remap_substitutions(
+                    &substitution.substitutions,
+                    |pair| pair.1,
+                    |alternate, pair| {
+                        let ours = feature_record(&ours.features, pair.0);
+                        merge_features(ours.map(|rec| &*rec.feature), Some(alternate), delta)
+                    },
+                );
+            }
+            variation_records.push(record);
+        }
+
+        let mut lookups = ours.lookups;
+        lookups.extend(theirs.lookups.into_iter().map(|mut lookup| {
+            remap.remap_lookup(&mut lookup, delta);
+            lookup
+        }));
+        T::from_layout(Layout {
+            scripts: ScriptList::new(script_records),
+            features: FeatureList::new(feature_records),
+            lookups,
+            variations: (!variation_records.is_empty())
+                .then(|| FeatureVariations::new(variation_records)),
+        })
+    }
+
+    /// Rewrite a feature table substitution to use the merged features.
+    ///
+    /// `side` selects the index of the original feature from a merged
+    /// feature, and `merge` combines an alternate feature with the rest of the
+    /// merged feature.
+    fn remap_substitutions(
+        &self,
+        substitutions: &[FeatureTableSubstitutionRecord],
+        side: impl Fn(FeaturePair) -> Option<u16>,
+        merge: impl Fn(&Feature, FeaturePair) -> Feature,
+    ) -> Vec<FeatureTableSubstitutionRecord> {
+        self.features
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, pair)| {
+                let original = side(*pair)?;
+                let substitution = substitutions
+                    .iter()
+                    .find(|sub| sub.feature_index == original)?;
+                Some(FeatureTableSubstitutionRecord::new(
+                    idx as u16,
+                    merge(&substitution.alternate_feature, *pair),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// The language systems in a script list, keyed by script and language tag.
+fn lang_systems(scripts: Option<&ScriptList>) -> BTreeMap<Tag, BTreeMap<Tag, &LangSys>> {
+    let mut result = BTreeMap::<Tag, BTreeMap<Tag, &LangSys>>::new();
+    for record in scripts
+        .into_iter()
+        .flat_map(|list| list.script_records.iter())
+    {
+        let langs = result.entry(record.script_tag).or_default();
+        if let Some(lang_sys) = record.script.default_lang_sys.as_ref() {
+            langs.insert(tags::LANG_DFLT, lang_sys);
+        }
+        for lang in record.script.lang_sys_records.iter() {
+            langs.insert(lang.lang_sys_tag, &lang.lang_sys);
+        }
+    }
+    result
+}
+
+/// The language system that a shaper would use for this script and language.
+///
+/// If the language is missing we use the script's default, and if the script
+/// is missing we use the default script.
+fn resolve_lang_sys<'a>(
+    systems: &BTreeMap<Tag, BTreeMap<Tag, &'a LangSys>>,
+    script: Tag,
+    language: Tag,
+) -> Option<&'a LangSys> {
+    match systems.get(&script) {
+        Some(langs) => langs.get(&language).or_else(|| langs.get(&tags::LANG_DFLT)),
+        None => systems
+            .get(&tags::SCRIPT_DFLT)
+            .and_then(|langs| langs.get(&tags::LANG_DFLT)),
+    }
+    .copied()
+}
+
+fn required_feature(lang_sys: Option<&LangSys>) -> Option<u16> {
+    lang_sys
+        .map(|sys| sys.required_feature_index)
+        .filter(|idx| *idx != 0xFFFF)
+}
+
+fn feature_tags(features: Option<&FeatureList>) -> Vec<Tag> {
+    features
+        .into_iter()
+        .flat_map(|list| list.feature_records.iter())
+        .map(|record| record.feature_tag)
+        .collect()
+}
+
+fn feature_record(features: &FeatureList, idx: Option<u16>) -> Option<&FeatureRecord> {
+    idx.map(|idx| &features.feature_records[idx as usize])
+}
+
+/// Combine our feature with theirs, moving their lookups after ours.
+fn merge_features(ours: Option<&Feature>, theirs: Option<&Feature>, delta: u16) -> Feature {
+    let mut lookups = ours
+        .map(|feature| feature.lookup_list_indices.clone())
+        .unwrap_or_default();
+    lookups.extend(
+        theirs
+            .into_iter()
+            .flat_map(|feature| feature.lookup_list_indices.iter())
+            .map(|idx| idx + delta),
+    );
+    let params = ours
+        .and_then(|feature| feature.feature_params.as_ref())
+        .or_else(|| theirs.and_then(|feature| feature.feature_params.as_ref()))
+        .cloned();
+    Feature::new(params, lookups)
+}
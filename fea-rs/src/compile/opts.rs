@@ -5,15 +5,28 @@
 // a very important part of our API, and a more natural place for us to specify
 // options is in the 'Compiler' struct itself.
 
+use std::collections::BTreeSet;
+
+use write_fonts::types::Tag;
+
 const DEFAULT_N_MESSAGES_TO_PRINT: usize = 100;
 
 /// Options for configuring compilation behaviour.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Opts {
     pub(crate) make_post_table: bool,
     pub(crate) max_n_errors: usize,
     pub(crate) compile_gsub: bool,
     pub(crate) compile_gpos: bool,
+    pub(crate) infer_glyph_classes: bool,
+    pub(crate) infer_gsub_ligature_classes: bool,
+    pub(crate) glyph_aliases_in_diagnostics: bool,
+    pub(crate) parallel: bool,
+    pub(crate) features: Option<BTreeSet<Tag>>,
+    pub(crate) debug_table: bool,
+    pub(crate) liberal_glyph_names: bool,
+    pub(crate) merge_class_pairs: bool,
 }
 
 impl Opts {
@@ -50,6 +63,106 @@ impl Opts {
         self.compile_gsub = flag;
         self
     }
+
+    /// Specify whether GDEF glyph classes should be inferred when the FEA does
+    /// not declare them explicitly. Default is `true`.
+    ///
+    /// Mark glyphs are taken from `markClass` definitions, and base, ligature
+    /// and mark glyphs from the mark positioning rules, as in feaLib.
+    pub fn infer_glyph_classes(mut self, flag: bool) -> Self {
+        self.infer_glyph_classes = flag;
+        self
+    }
+
+    /// Specify whether the outputs of ligature substitutions should be
+    /// inferred to be ligature glyphs. Default is `false`.
+    ///
+    /// This is what the FEA spec describes, but feaLib does not do it. Glyphs
+    /// classified by the mark positioning rules are unaffected.
+    pub fn infer_gsub_ligature_classes(mut self, flag: bool) -> Self {
+        self.infer_gsub_ligature_classes = flag;
+        self
+    }
+
+    /// Specify whether diagnostics should name glyphs by their alias in the
+    /// [`GlyphMap`], where they have one. Default is `false`.
+    ///
+    /// Glyph names written in the FEA always resolve against both names and
+    /// aliases; this only affects how glyphs are named in messages.
+    ///
+    /// [`GlyphMap`]: crate::GlyphMap
+    pub fn glyph_aliases_in_diagnostics(mut self, flag: bool) -> Self {
+        self.glyph_aliases_in_diagnostics = flag;
+        self
+    }
+
+    /// Specify whether independent blocks should be resolved, and lookups
+    /// built, in parallel.
+    ///
+    /// This has no effect unless the `parallel` feature is enabled, in which
+    /// case it defaults to `true`. The output is identical either way.
+    pub fn parallel(mut self, flag: bool) -> Self {
+        self.parallel = flag;
+        self
+    }
+
+    /// Only compile the feature blocks with these tags. Default is to compile
+    /// all features.
+    ///
+    /// The whole FEA is still parsed and validated, and glyph classes, mark
+    /// classes and named lookups defined inside the other feature blocks
+    /// remain available; only the rules in those blocks are skipped. Note
+    /// that an `aalt` feature only sees the features that are compiled.
+    ///
+    /// Features added by a [`FeatureProvider`] are not affected.
+    ///
+    /// [`FeatureProvider`]: crate::compile::FeatureProvider
+    pub fn features(mut self, tags: impl IntoIterator<Item = Tag>) -> Self {
+        self.features = Some(tags.into_iter().collect());
+        self
+    }
+
+    /// Specify whether a `Debg` table should be generated. Default is `false`.
+    ///
+    /// This records where in the FEA each lookup was defined, in the format
+    /// used by feaLib, so that shaping issues in the built font can be traced
+    /// back to the source.
+    pub fn debug_table(mut self, flag: bool) -> Self {
+        self.debug_table = flag;
+        self
+    }
+
+    /// Specify whether glyph names outside of the FEA spec should be accepted.
+    /// Default is `false`.
+    ///
+    /// Development fonts often use names that the spec does not allow, such
+    /// as names that start with a period or that contain `!` or `/`. If this
+    /// is `true` we accept the same names as feaLib, reporting a warning for
+    /// each name that would otherwise have been rejected.
+    pub fn liberal_glyph_names(mut self, flag: bool) -> Self {
+        self.liberal_glyph_names = flag;
+        self
+    }
+
+    /// Specify whether overlapping classes in class pair rules should be split
+    /// so that the rules can share a subtable. Default is `false`.
+    ///
+    /// feaLib starts a new subtable whenever a class overlaps a different class
+    /// in the current subtable, which can produce many subtables for large
+    /// kerning lookups. If this is `true`, overlapping classes are split into
+    /// pieces instead, and where two rules cover the same pair of glyphs the
+    /// earlier rule wins, as it does when the rules are in separate subtables.
+    pub fn merge_class_pairs(mut self, flag: bool) -> Self {
+        self.merge_class_pairs = flag;
+        self
+    }
+
+    /// Returns `true` if the feature with this tag should be compiled.
+    pub(crate) fn compile_feature(&self, tag: Tag) -> bool {
+        self.features
+            .as_ref()
+            .is_none_or(|features| features.contains(&tag))
+    }
 }
 
 impl Default for Opts {
@@ -59,6 +172,14 @@ impl Default for Opts {
             max_n_errors: DEFAULT_N_MESSAGES_TO_PRINT,
             compile_gsub: true,
             compile_gpos: true,
+            infer_glyph_classes: true,
+            infer_gsub_ligature_classes: false,
+            glyph_aliases_in_diagnostics: false,
+            parallel: cfg!(feature = "parallel"),
+            features: None,
+            debug_table: false,
+            liberal_glyph_names: false,
+            merge_class_pairs: false,
         }
     }
 }
@@ -104,4 +225,103 @@ mod tests {
         assert!(compilation.gpos.is_none());
         assert!(compilation.gsub.is_some());
     }
+
+    #[test]
+    fn only_some_features() {
+        let glyph_order = Path::new(OSWALD_DIR).join("glyph_order.txt");
+        let features = Path::new(OSWALD_DIR).join("features.fea");
+        let glyph_order = std::fs::read_to_string(glyph_order).unwrap();
+        let glyph_order = crate::compile::parse_glyph_order(&glyph_order).unwrap();
+        let compilation =
+            Compiler::<NopFeatureProvider, MockVariationInfo>::new(&features, &glyph_order)
+                .with_opts(Opts::new().features([Tag::new(b"kern"), Tag::new(b"mark")]))
+                .compile()
+                .unwrap();
+
+        let gpos = compilation.gpos.unwrap();
+        let mut tags = gpos
+            .feature_list
+            .feature_records
+            .iter()
+            .map(|rec| rec.feature_tag)
+            .collect::<Vec<_>>();
+        tags.dedup();
+        assert_eq!(tags, [Tag::new(b"kern"), Tag::new(b"mark")]);
+        // no features were selected from GSUB
+        assert!(
+            compilation
+                .gsub
+                .is_none_or(|gsub| gsub.feature_list.feature_records.is_empty())
+        );
+    }
+
+    #[test]
+    fn liberal_glyph_names() {
+        let glyph_order = crate::compile::parse_glyph_order(".notdef\na\n.alt\n").unwrap();
+        let compile = |opts: Opts| {
+            Compiler::<NopFeatureProvider, MockVariationInfo>::new("features.fea", &glyph_order)
+                .with_resolver(|_: &Path| Ok("feature test { sub a by .alt; } test;".into()))
+                .print_warnings(false)
+                .with_opts(opts)
+                .compile()
+        };
+
+        assert!(compile(Opts::new()).is_err());
+        let compilation = compile(Opts::new().liberal_glyph_names(true)).unwrap();
+        assert!(compilation.gsub.is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_matches_serial() {
+        use crate::compile::error::CompilerError;
+
+        let glyph_order = Path::new(OSWALD_DIR).join("glyph_order.txt");
+        let features = Path::new(OSWALD_DIR).join("features.fea");
+        let glyph_order = std::fs::read_to_string(glyph_order).unwrap();
+        let glyph_order = crate::compile::parse_glyph_order(&glyph_order).unwrap();
+        let compile = |opts: Opts| {
+            Compiler::<NopFeatureProvider, MockVariationInfo>::new(&features, &glyph_order)
+                .with_opts(opts)
+                .compile()
+                .unwrap()
+                .to_binary(&glyph_order)
+                .unwrap()
+        };
+
+        let serial = compile(Opts::new().parallel(false));
+        let parallel = compile(Opts::new().parallel(true));
+        assert!(serial == parallel, "parallel output differs from serial");
+
+        // errors in classes resolved ahead of time are reported in order
+        let glyph_order = crate::compile::parse_glyph_order(".notdef\na\nb\n").unwrap();
+        let compile = |opts: Opts| {
+            Compiler::<NopFeatureProvider, MockVariationInfo>::new("features.fea", &glyph_order)
+                .with_resolver(|_: &Path| {
+                    Ok("feature test { sub [a c] by b; sub [a b] by [b a]; } test;".into())
+                })
+                .print_warnings(false)
+                .with_opts(opts)
+                .compile()
+                .map(|_| ())
+                .unwrap_err()
+        };
+        // file ids are allocated per compilation, so compare everything else
+        let summarize = |err: CompilerError| {
+            let (CompilerError::ValidationFail(diagnostics)
+            | CompilerError::CompilationFail(diagnostics)) = err
+            else {
+                panic!("unexpected error {err:?}");
+            };
+            diagnostics
+                .diagnostics()
+                .iter()
+                .map(|d| (d.code, d.level, d.text().to_owned(), d.span()))
+                .collect::<Vec<_>>()
+        };
+        let serial = summarize(compile(Opts::new().parallel(false)));
+        let parallel = summarize(compile(Opts::new().parallel(true)));
+        assert!(!serial.is_empty());
+        assert_eq!(serial, parallel);
+    }
 }
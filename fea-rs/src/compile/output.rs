@@ -1,20 +1,29 @@
 //! The result of a compilation
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use write_fonts::{
     BuilderError, FontBuilder,
+    from_obj::ToOwnedTable,
+    read::{FontRef, TableProvider, TopLevelTable},
     tables::{
         self as wtables, gdef::GlyphClassDef, layout::FeatureParams, maxp::Maxp, stat::AxisValue,
     },
     types::{GlyphId16, NameId, Tag},
 };
 
-use super::Opts;
 use super::feature_writer::InsertionPoint;
+use super::{
+    LookupId, Opts,
+    dedup::LookupRemapping,
+    error::{MergeError, TtxError},
+    tables::TableOverrides,
+};
 
 use crate::GlyphMap;
 
+const DEBG: Tag = Tag::new(b"Debg");
+
 /// The tables generated by this compilation.
 ///
 /// All tables are optional, and the set of tables that are present depends
@@ -24,8 +33,12 @@ use crate::GlyphMap;
 /// may either interact with these directly, or else they may use the [`to_binary`]
 /// method to generate a binary font.
 ///
+/// This can only be created by the compiler, and more fields may be added, so
+/// it cannot be constructed or exhaustively destructured outside this crate.
+///
 /// [`to_binary`]: Compilation::to_binary
 /// [`write-fonts`]: https://docs.rs/write-fonts/latest/write_fonts/
+#[non_exhaustive]
 pub struct Compilation {
     /// The options passed in for this compilation.
     pub(crate) opts: Opts,
@@ -62,6 +75,117 @@ pub struct Compilation {
     /// features (e.g. kern, mark) and need to know the correct ordering
     /// relative to hand-written feature code.
     pub insert_markers: HashMap<Tag, InsertionPoint>,
+    /// Any `anonymous` (or `anon`) blocks in the source, in the order they
+    /// were declared.
+    ///
+    /// The contents of these blocks are not interpreted by the compiler; they
+    /// are passed through so that other tools can make use of them.
+    pub anon_blocks: Vec<AnonBlock>,
+    /// Any `table` blocks for tables that we do not support, in the order
+    /// they were declared.
+    ///
+    /// These are reported with a warning, and otherwise passed through
+    /// unchanged so that sources written for newer tools can still be
+    /// compiled.
+    pub unknown_tables: Vec<UnknownTable>,
+    /// A name for each lookup in the GSUB lookup list, in lookup list order.
+    ///
+    /// Lookups defined in named `lookup` blocks use their name; others are
+    /// given a name based on the first feature that uses them, such as
+    /// `liga/latn/dflt auto #0`, or failing that their index, such as
+    /// `anonymous #7`. This is intended for labelling lookups in debugging
+    /// tools.
+    pub gsub_lookup_names: Vec<String>,
+    /// A name for each lookup in the GPOS lookup list, in lookup list order.
+    ///
+    /// See [`gsub_lookup_names`](Self::gsub_lookup_names) for details.
+    pub gpos_lookup_names: Vec<String>,
+    /// The contents of the `Debg` table, if one was requested.
+    ///
+    /// See [`Opts::debug_table`] for details.
+    pub debg: Option<Vec<u8>>,
+    /// The entries of the `Debg` table, so that it can be updated if lookups
+    /// are removed or reordered.
+    pub(crate) debug_lookups: Option<DebugLookups>,
+    /// The values set in FEA for tables that only some fields can be set for,
+    /// so that [`apply_to_font`](Self::apply_to_font) can keep the rest.
+    pub(crate) overrides: TableOverrides,
+}
+
+/// The entries of a `Debg` table.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct DebugLookups {
+    /// For each GSUB lookup, the JSON array describing it
+    pub(crate) gsub: Vec<String>,
+    /// For each GPOS lookup, the JSON array describing it
+    pub(crate) gpos: Vec<String>,
+}
+
+/// The contents of an `anonymous` block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnonBlock {
+    /// The label (tag) of the block
+    pub tag: String,
+    /// The raw text between the block's braces, including whitespace.
+    pub contents: String,
+}
+
+/// The contents of a `table` block for a table that we do not support.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnknownTable {
+    /// The tag of the table
+    pub tag: Tag,
+    /// The raw text between the block's braces, including whitespace.
+    pub contents: String,
+}
+
+/// Add the records in `ours` to `existing`, replacing any with the same key.
+fn merge_names(
+    mut existing: wtables::name::Name,
+    ours: &wtables::name::Name,
+) -> wtables::name::Name {
+    let key = |record: &wtables::name::NameRecord| {
+        (
+            record.platform_id,
+            record.encoding_id,
+            record.language_id,
+            record.name_id,
+        )
+    };
+    let replaced = ours.name_record.iter().map(key).collect::<BTreeSet<_>>();
+    existing
+        .name_record
+        .retain(|record| !replaced.contains(&key(record)));
+    existing
+        .name_record
+        .extend(ours.name_record.iter().cloned());
+    existing.name_record.sort();
+    existing
+}
+
+impl DebugLookups {
+    /// The contents of the `Debg` table, in the JSON format written by feaLib.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let tables = [("GPOS", &self.gpos), ("GSUB", &self.gsub)]
+            .into_iter()
+            .map(|(tag, entries)| {
+                let entries = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, entry)| format!("\"{i}\": {entry}"))
+                    .collect::<Vec<_>>();
+                format!("\"{tag}\": {{{}}}", entries.join(", "))
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"com.github.fonttools.feaLib\": {{{}}}}}",
+            tables.join(", ")
+        )
+        .into_bytes()
+    }
 }
 
 impl Compilation {
@@ -76,6 +200,160 @@ impl Compilation {
             || self.stat.is_some()
     }
 
+    /// Summarize the size and structure of the generated layout tables.
+    pub fn stats(&self) -> super::CompilationStats {
+        super::CompilationStats::new(self)
+    }
+
+    /// Compute the closure of `glyphs` over all the lookups in the GSUB table.
+    ///
+    /// This returns the input glyphs, plus every glyph that can be produced
+    /// from them by some sequence of substitutions. Contextual conditions are
+    /// not evaluated, so the result may include glyphs that are unreachable
+    /// in practice.
+    pub fn gsub_closure(&self, glyphs: impl IntoIterator<Item = GlyphId16>) -> BTreeSet<GlyphId16> {
+        let lookups = self
+            .gsub
+            .iter()
+            .flat_map(|gsub| gsub.lookup_list.lookups.iter().map(|lookup| &**lookup));
+        super::closure::gsub_closure(lookups, glyphs)
+    }
+
+    /// Merge the GDEF, GSUB and GPOS tables of another compilation into ours.
+    ///
+    /// This allows combining separately compiled features, such as hand-written
+    /// features and generated kerning or mark positioning, without having to
+    /// concatenate their FEA sources.
+    ///
+    /// The other compilation's lookups are appended to ours, and each of its
+    /// features is combined with our feature of the same tag in each language
+    /// system. Where a language system only exists in one compilation, the
+    /// other contributes the features of the language system that a shaper
+    /// would fall back to. Our feature variations are evaluated before the
+    /// other compilation's.
+    ///
+    /// GDEF mark attachment classes and mark filtering sets are combined, and
+    /// the other compilation's lookups are updated to refer to them. Where both
+    /// assign a glyph class or an attachment point to a glyph, ours is kept.
+    /// Other tables are not changed.
+    ///
+    /// The other compilation's lookup names and `Debg` entries are appended to
+    /// ours; if only one compilation has a `Debg` table, the lookups of the
+    /// other are given empty entries. Its
+    /// [`insert_markers`](Self::insert_markers) are moved to point at the same
+    /// lookups in the merged tables, and are added for each feature that does
+    /// not already have one of ours.
+    ///
+    /// If this returns an error, `self` is unchanged.
+    pub fn merge(&mut self, other: Compilation) -> Result<(), MergeError> {
+        super::merge::merge(self, other)
+    }
+
+    /// Remove lookups that are identical to an earlier lookup in the same table.
+    ///
+    /// Identical lookups commonly arise when the same rules are written for
+    /// several scripts or languages. Each feature that used a removed lookup
+    /// uses the earlier copy instead, as do any contextual lookups that
+    /// referred to it. Because lookups are applied in lookup list order, a
+    /// lookup is only removed if this does not change the order in which the
+    /// lookups of any language system are applied; the remaining lookups keep
+    /// their relative order.
+    ///
+    /// The entries for removed lookups are also removed from
+    /// [`gsub_lookup_names`](Self::gsub_lookup_names),
+    /// [`gpos_lookup_names`](Self::gpos_lookup_names) and the `Debg` table,
+    /// and [`insert_markers`](Self::insert_markers) are updated to match.
+    ///
+    /// Returns the total number of lookups removed.
+    pub fn dedup_lookups(&mut self) -> usize {
+        let mut n_removed = 0;
+        if let Some(gsub) = self.gsub.take() {
+            let (gsub, remapping) = super::dedup::dedup_lookups(gsub);
+            self.gsub = Some(gsub);
+            n_removed += remapping.n_removed();
+            self.apply_lookup_remapping(false, &remapping);
+        }
+        if let Some(gpos) = self.gpos.take() {
+            let (gpos, remapping) = super::dedup::dedup_lookups(gpos);
+            self.gpos = Some(gpos);
+            n_removed += remapping.n_removed();
+            self.apply_lookup_remapping(true, &remapping);
+        }
+        n_removed
+    }
+
+    /// Reorder the lookups in the GSUB and GPOS tables so that smaller lookups
+    /// come first.
+    ///
+    /// Each lookup is reached by a 16-bit offset from the lookup list, so
+    /// moving small lookups to the front keeps as many lookups as possible
+    /// within reach, which can avoid the need for extension lookups. Lookups
+    /// are applied in lookup list order, so the lookups of each language
+    /// system keep their relative order; only lookups that are never applied
+    /// together are moved past one another.
+    ///
+    /// No lookup is moved past one of the
+    /// [`insert_markers`](Self::insert_markers), so lookups inserted at a
+    /// marker still come after every lookup that was before it.
+    ///
+    /// [`gsub_lookup_names`](Self::gsub_lookup_names),
+    /// [`gpos_lookup_names`](Self::gpos_lookup_names), the `Debg` table and
+    /// [`insert_markers`](Self::insert_markers) are updated to match.
+    pub fn reorder_lookups(&mut self) {
+        if let Some(gsub) = self.gsub.take() {
+            let barriers = self.insert_marker_positions(false);
+            let (gsub, remapping) = super::dedup::reorder_lookups(gsub, &barriers);
+            self.gsub = Some(gsub);
+            self.apply_lookup_remapping(false, &remapping);
+        }
+        if let Some(gpos) = self.gpos.take() {
+            let barriers = self.insert_marker_positions(true);
+            let (gpos, remapping) = super::dedup::reorder_lookups(gpos, &barriers);
+            self.gpos = Some(gpos);
+            self.apply_lookup_remapping(true, &remapping);
+        }
+    }
+
+    /// The lookup list positions of the insertion markers in the GSUB or GPOS
+    /// table.
+    fn insert_marker_positions(&self, is_gpos: bool) -> BTreeSet<usize> {
+        self.insert_markers
+            .values()
+            .filter_map(|marker| match (marker.lookup_id, is_gpos) {
+                (LookupId::Gpos(idx), true) | (LookupId::Gsub(idx), false) => Some(idx),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Update everything that refers to lookups by index after the lookups of
+    /// the GSUB or GPOS table have been removed or reordered.
+    fn apply_lookup_remapping(&mut self, is_gpos: bool, remapping: &LookupRemapping) {
+        let names = if is_gpos {
+            &mut self.gpos_lookup_names
+        } else {
+            &mut self.gsub_lookup_names
+        };
+        *names = remapping.reorder(names);
+        if let Some(debug_lookups) = self.debug_lookups.as_mut() {
+            let entries = if is_gpos {
+                &mut debug_lookups.gpos
+            } else {
+                &mut debug_lookups.gsub
+            };
+            *entries = remapping.reorder(entries);
+            self.debg = Some(debug_lookups.to_bytes());
+        }
+        for marker in self.insert_markers.values_mut() {
+            match (&mut marker.lookup_id, is_gpos) {
+                (LookupId::Gpos(idx), true) | (LookupId::Gsub(idx), false) => {
+                    *idx = remapping.new_position(*idx)
+                }
+                _ => (),
+            }
+        }
+    }
+
     /// Remap any `NameId`s in the name table and anywhere they are referenced.
     ///
     /// This is used for merging the results of our compilation with other
@@ -181,6 +459,9 @@ impl Compilation {
         add_if_some!(self.stat);
         add_if_some!(self.gsub);
         add_if_some!(self.gpos);
+        if let Some(debg) = self.debg.as_ref() {
+            builder.add_raw(DEBG, debg.as_slice());
+        }
         Ok(builder)
     }
 
@@ -202,4 +483,63 @@ impl Compilation {
 
         Ok(builder.build())
     }
+
+    /// Write the generated BASE, GDEF, GSUB, GPOS and STAT tables as TTX.
+    ///
+    /// This matches the output of `ttx -t BASE -t GDEF -t GSUB -t GPOS -t STAT`
+    /// on the binary produced by [`to_binary`][Self::to_binary], and is
+    /// intended for comparing our output with that of feaLib.
+    pub fn to_ttx(&self, glyph_map: &GlyphMap) -> Result<String, TtxError> {
+        let bytes = self.to_binary(glyph_map)?;
+        let font = FontRef::new(&bytes)?;
+        crate::ttx::dump(&font, glyph_map).map_err(Into::into)
+    }
+
+    /// Add the output tables to an existing font, returning the new font data.
+    ///
+    /// Tables in `font` are replaced by generated tables with the same tag,
+    /// and all other tables are copied unchanged. As in feaLib, any existing
+    /// `GDEF`, `GSUB`, `GPOS` or `BASE` table that was not generated is dropped,
+    /// since it would not be consistent with the new layout tables.
+    ///
+    /// The `head`, `hhea`, `vhea` and `OS/2` tables are the exception: FEA
+    /// only sets some of their fields, so those fields are updated in the
+    /// font's own tables and everything else is kept. Likewise, the generated
+    /// `name` records are added to the font's `name` table, replacing any
+    /// with the same name ID, platform, encoding and language.
+    pub fn apply_to_font(&self, font: FontRef<'_>) -> Result<Vec<u8>, BuilderError> {
+        const LAYOUT_TABLES: [Tag; 4] = [
+            wtables::gdef::Gdef::TAG,
+            wtables::gsub::Gsub::TAG,
+            wtables::gpos::Gpos::TAG,
+            wtables::base::Base::TAG,
+        ];
+        let mut builder = self.to_font_builder()?;
+        let overrides = &self.overrides;
+        if let Some(head) = overrides.head.as_ref() {
+            builder.add_table(&head.build(Some(&font)))?;
+        }
+        if let Some(hhea) = overrides.hhea.as_ref() {
+            builder.add_table(&hhea.build(Some(&font)))?;
+        }
+        if let Some(vhea) = overrides.vhea.as_ref() {
+            builder.add_table(&vhea.build(Some(&font)))?;
+        }
+        if let Some(os2) = overrides.os2.as_ref() {
+            builder.add_table(&os2.build(Some(&font)))?;
+        }
+        if let (Some(name), Ok(existing)) = (self.name.as_ref(), font.name()) {
+            builder.add_table(&merge_names(existing.to_owned_table(), name))?;
+        }
+        for record in font.table_directory().table_records() {
+            let tag = record.tag();
+            if builder.contains(tag) || LAYOUT_TABLES.contains(&tag) {
+                continue;
+            }
+            if let Some(data) = font.data_for_tag(tag) {
+                builder.add_raw(tag, data.as_bytes());
+            }
+        }
+        Ok(builder.build())
+    }
 }
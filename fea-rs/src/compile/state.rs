@@ -0,0 +1,216 @@
+//! The state of a compilation before any tables are built
+
+use std::collections::HashMap;
+
+use write_fonts::{
+    tables::{
+        layout::{ConditionSet, FeatureVariations},
+        variations::ivs_builder::{RemapVariationIndices, VariationStoreBuilder},
+    },
+    types::Tag,
+};
+
+use super::{
+    Opts,
+    feature_writer::InsertionPoint,
+    features::{AllFeatures, ConditionSetMap},
+    lookups::AllLookups,
+    output::{AnonBlock, Compilation, DebugLookups, UnknownTable},
+    tables::{GdefBuilder, TableOverrides, Tables},
+};
+
+/// The state of a compilation after the FEA has been resolved and any
+/// external features have been merged, but before any tables are built.
+///
+/// This is returned by [`Compiler::compile_state`], and can be turned into a
+/// [`Compilation`] with [`build`](Self::build). With the `serde` feature it
+/// can be serialized, so that a pipeline can cache it and skip parsing and
+/// resolving the FEA when none of its inputs have changed.
+///
+/// The serialized form is not stable between versions of this crate, and a
+/// state saved by another version may fail to deserialize; this should be
+/// treated as a cache miss. For instance, states saved before the `Debg`
+/// entries were stored per lookup have a `debg` field in place of
+/// `debug_lookups`, and cannot be loaded.
+///
+/// [`Compiler::compile_state`]: super::Compiler::compile_state
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompilationState {
+    pub(crate) opts: Opts,
+    pub(crate) axis_count: u16,
+    pub(crate) tables: Tables,
+    pub(crate) features: AllFeatures,
+    pub(crate) lookups: AllLookups,
+    pub(crate) conditionset_defs: ConditionSetMap,
+    pub(crate) insert_markers: HashMap<Tag, InsertionPoint>,
+    pub(crate) anon_blocks: Vec<AnonBlock>,
+    pub(crate) unknown_tables: Vec<UnknownTable>,
+    pub(crate) debug_lookups: Option<DebugLookups>,
+}
+
+impl CompilationState {
+    /// An estimate of the serialized size of each GSUB and GPOS lookup,
+    /// without building them.
+    ///
+    /// The sizes are in lookup list order, and are upper bounds. The size of a
+    /// GPOS lookup is `None` if it was added by a feature writer, since those
+    /// are not built from rules in the FEA.
+    pub fn estimated_lookup_sizes(&self) -> (Vec<usize>, Vec<Option<usize>>) {
+        self.lookups.estimated_sizes()
+    }
+
+    /// Build the tables for this compilation.
+    pub fn build(mut self) -> Compilation {
+        let mut name_builder = self.tables.name.clone();
+        let stat = self
+            .tables
+            .stat
+            .as_ref()
+            .map(|raw| raw.build(&mut name_builder));
+
+        // the var store builder is required so that variable metrics/anchors
+        // in the GPOS table can be collected into an ItemVariationStore
+        let mut ivs = VariationStoreBuilder::new(self.axis_count);
+
+        let (mut gsub, mut gpos) = self.lookups.build(&self.features, &mut ivs, &self.opts);
+        let (gsub_lookup_names, gpos_lookup_names) = self.lookups.lookup_names(&self.features);
+        // if ivs hasn't been used, we don't want to create a GDEF table just for it.
+        if !ivs.is_empty() {
+            self.tables
+                .gdef
+                .get_or_insert_with(|| {
+                    // If we're creating a new GdefBuilder here, it means
+                    // finalize_gdef_table() discarded the previous one because
+                    // it was empty. That means no explicit glyph classes were
+                    // declared in the FEA, so mark the classes as inferred.
+                    // https://github.com/googlefonts/fontc/issues/1847
+                    GdefBuilder {
+                        glyph_classes_were_inferred: true,
+                        ..Default::default()
+                    }
+                })
+                .var_store = Some(ivs);
+        // but if we _do_ have a gdef table, always add the var store,
+        // since we might still add ligature carets to it
+        } else if let Some(gdef) = self.tables.gdef.as_mut() {
+            gdef.var_store = Some(ivs);
+        }
+
+        let (gdef, key_map) = match self.tables.gdef.as_ref().map(|raw| raw.build()) {
+            Some((gdef, key_map)) => (Some(gdef), key_map),
+            None => (None, None),
+        };
+
+        let feature_params = self.features.build_feature_params(&mut name_builder);
+
+        if let Some(gsub) = gsub.as_mut() {
+            if let Some(variations) = gsub.feature_variations.as_mut() {
+                sort_feature_variations(variations, |condset| {
+                    self.conditionset_defs.sort_order(condset)
+                });
+            }
+            for record in gsub.feature_list.feature_records.iter_mut() {
+                if let Some(params) = feature_params.get(&record.feature_tag) {
+                    record.feature.feature_params = params.clone().into();
+                }
+            }
+        }
+        if let (Some(gpos), Some(key_map)) = (gpos.as_mut(), key_map) {
+            // all VariationIndex tables (in value records and anchors)
+            // currently have temporary indices; now that we've built the
+            // ItemVariationStore we need to go and update them all.
+            gpos.remap_variation_indices(&key_map);
+        }
+        self.lookups
+            .promote_to_extension(gsub.as_mut(), gpos.as_mut());
+        if let Some(gpos) = gpos.as_mut() {
+            if let Some(variations) = gpos.feature_variations.as_mut() {
+                sort_feature_variations(variations, |condset| {
+                    self.conditionset_defs.sort_order(condset)
+                });
+            }
+
+            for record in gpos.feature_list.feature_records.iter_mut() {
+                if let Some(params) = feature_params.get(&record.feature_tag) {
+                    record.feature.feature_params = params.clone().into();
+                }
+            }
+        }
+
+        let gdef_classes = self.tables.gdef.as_ref().and_then(|gdef| {
+            (!gdef.glyph_classes_were_inferred).then(|| gdef.glyph_classes.clone())
+        });
+
+        Compilation {
+            head: self.tables.head.as_ref().map(|raw| raw.build(None)),
+            hhea: self.tables.hhea.as_ref().map(|raw| raw.build(None)),
+            vhea: self.tables.vhea.as_ref().map(|raw| raw.build(None)),
+            os2: self.tables.os2.as_ref().map(|raw| raw.build(None)),
+            gdef,
+            base: self.tables.base.as_ref().map(|raw| raw.build()),
+            name: name_builder.build(),
+            stat,
+            gsub,
+            gpos,
+            opts: self.opts,
+            gdef_classes,
+            insert_markers: self.insert_markers,
+            anon_blocks: self.anon_blocks,
+            unknown_tables: self.unknown_tables,
+            gsub_lookup_names,
+            gpos_lookup_names,
+            debg: self.debug_lookups.as_ref().map(DebugLookups::to_bytes),
+            debug_lookups: self.debug_lookups,
+            overrides: TableOverrides {
+                head: self.tables.head,
+                hhea: self.tables.hhea,
+                vhea: self.tables.vhea,
+                os2: self.tables.os2,
+            },
+        }
+    }
+}
+
+fn sort_feature_variations(
+    variations: &mut FeatureVariations,
+    order_fn: impl Fn(&ConditionSet) -> usize,
+) {
+    variations
+        .feature_variation_records
+        .sort_by_key(|record| match record.condition_set.as_ref() {
+            Some(condition) => order_fn(condition),
+            None => order_fn(&Default::default()),
+        })
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        Compiler,
+        compile::{MockVariationInfo, NopFeatureProvider, Opts},
+    };
+
+    static OSWALD_DIR: &str = "./test-data/real-files/oswald";
+
+    #[test]
+    fn serialized_state_builds_the_same_font() {
+        let glyph_order = Path::new(OSWALD_DIR).join("glyph_order.txt");
+        let features = Path::new(OSWALD_DIR).join("features.fea");
+        let glyph_order = std::fs::read_to_string(glyph_order).unwrap();
+        let glyph_order = crate::compile::parse_glyph_order(&glyph_order).unwrap();
+        let compiler = || {
+            Compiler::<NopFeatureProvider, MockVariationInfo>::new(&features, &glyph_order)
+                .with_opts(Opts::new().debug_table(true))
+        };
+
+        let expected = compiler().compile_binary().unwrap();
+        let state = compiler().compile_state().unwrap();
+        let bytes = bincode::serialize(&state).unwrap();
+        let loaded: super::CompilationState = bincode::deserialize(&bytes).unwrap();
+        let actual = loaded.build().to_binary(&glyph_order).unwrap();
+        assert!(expected == actual, "cached state builds a different font");
+    }
+}
@@ -0,0 +1,355 @@
+//! Statistics about the tables generated by a compilation
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Write},
+};
+
+use write_fonts::{
+    FontWrite,
+    tables::{
+        gdef::Gdef,
+        gpos::{self, PositionLookup},
+        gsub::{self, SubstitutionLookup},
+        layout::{FeatureList, Lookup},
+    },
+    types::Tag,
+    validate::Validate,
+};
+
+use super::Compilation;
+
+/// A summary of the size and structure of the layout tables in a
+/// [`Compilation`].
+///
+/// This is intended for tracking the growth of a font's layout tables over
+/// time. Sizes are estimates: each table and lookup is serialized on its own,
+/// so they don't account for sharing between lookups, and a table that is too
+/// large to serialize has no size.
+///
+/// The [`Display`] impl renders a human-readable report, and
+/// [`to_json`](CompilationStats::to_json) a machine-readable one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompilationStats {
+    /// Statistics for the GSUB and GPOS tables, if they were generated
+    pub tables: Vec<TableStats>,
+    /// The size of the GDEF table, if one was generated
+    pub gdef_size: Option<usize>,
+    /// The number of mark attachment classes in GDEF
+    pub mark_attachment_classes: usize,
+    /// The number of mark filtering sets in GDEF
+    pub mark_filtering_sets: usize,
+}
+
+/// Statistics for a GSUB or GPOS table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableStats {
+    /// The table tag
+    pub tag: Tag,
+    /// The size of the table in bytes
+    pub size: Option<usize>,
+    /// The features in the table, sorted by tag
+    pub features: Vec<FeatureStats>,
+    /// The lookups in the table, in lookup list order
+    pub lookups: Vec<LookupStats>,
+}
+
+/// Statistics for a feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureStats {
+    /// The feature tag
+    pub tag: Tag,
+    /// The number of distinct lookups used by the feature, across all
+    /// scripts and languages
+    pub lookup_count: usize,
+}
+
+/// Statistics for a single lookup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupStats {
+    /// The index of the lookup in the lookup list
+    pub index: usize,
+    /// The type of the lookup, such as `PairPos` or `LigatureSubst`
+    ///
+    /// For extension lookups, this is the type of the wrapped subtables.
+    pub kind: &'static str,
+    /// `true` if this lookup uses extension subtables
+    pub extension: bool,
+    /// The number of subtables
+    pub subtable_count: usize,
+    /// The size of the lookup and its subtables in bytes
+    pub size: Option<usize>,
+}
+
+impl CompilationStats {
+    pub(crate) fn new(compilation: &Compilation) -> Self {
+        let mut stats = CompilationStats::default();
+        if let Some(gsub) = compilation.gsub.as_ref() {
+            stats.tables.push(TableStats::new(
+                Tag::new(b"GSUB"),
+                gsub,
+                &gsub.feature_list,
+                &gsub.lookup_list.lookups,
+                gsub_lookup,
+            ));
+        }
+        if let Some(gpos) = compilation.gpos.as_ref() {
+            stats.tables.push(TableStats::new(
+                Tag::new(b"GPOS"),
+                gpos,
+                &gpos.feature_list,
+                &gpos.lookup_list.lookups,
+                gpos_lookup,
+            ));
+        }
+        if let Some(gdef) = compilation.gdef.as_ref() {
+            stats.add_gdef(gdef);
+        }
+        stats
+    }
+
+    fn add_gdef(&mut self, gdef: &Gdef) {
+        self.gdef_size = size(gdef);
+        self.mark_attachment_classes = gdef
+            .mark_attach_class_def
+            .as_ref()
+            .map(|class_def| {
+                class_def
+                    .iter()
+                    .map(|(_, class)| class)
+                    .collect::<BTreeSet<_>>()
+                    .len()
+            })
+            .unwrap_or_default();
+        self.mark_filtering_sets = gdef
+            .mark_glyph_sets_def
+            .as_ref()
+            .map(|sets| sets.coverages.len())
+            .unwrap_or_default();
+    }
+
+    /// Render these statistics as a JSON object.
+    pub fn to_json(&self) -> String {
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| {
+                let features = table
+                    .features
+                    .iter()
+                    .map(|feature| {
+                        format!(
+                            r#"{{"tag":{},"lookup_count":{}}}"#,
+                            json_string(&feature.tag.to_string()),
+                            feature.lookup_count
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let lookups = table
+                    .lookups
+                    .iter()
+                    .map(|lookup| {
+                        format!(
+                            r#"{{"index":{},"kind":{},"extension":{},"subtable_count":{},"size":{}}}"#,
+                            lookup.index,
+                            json_string(lookup.kind),
+                            lookup.extension,
+                            lookup.subtable_count,
+                            json_size(lookup.size),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    r#"{{"tag":{},"size":{},"features":[{}],"lookups":[{}]}}"#,
+                    json_string(&table.tag.to_string()),
+                    json_size(table.size),
+                    features.join(","),
+                    lookups.join(","),
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            r#"{{"tables":[{}],"gdef_size":{},"mark_attachment_classes":{},"mark_filtering_sets":{}}}"#,
+            tables.join(","),
+            json_size(self.gdef_size),
+            self.mark_attachment_classes,
+            self.mark_filtering_sets,
+        )
+    }
+}
+
+impl TableStats {
+    fn new<T: FontWrite + Validate, L: FontWrite + Validate>(
+        tag: Tag,
+        table: &T,
+        features: &FeatureList,
+        lookups: &[impl std::ops::Deref<Target = L>],
+        lookup_stats: impl Fn(&L) -> (&'static str, bool, usize),
+    ) -> Self {
+        let mut by_tag = BTreeMap::<Tag, BTreeSet<u16>>::new();
+        for record in features.feature_records.iter() {
+            by_tag
+                .entry(record.feature_tag)
+                .or_default()
+                .extend(record.feature.lookup_list_indices.iter().copied());
+        }
+        TableStats {
+            tag,
+            size: size(table),
+            features: by_tag
+                .into_iter()
+                .map(|(tag, lookups)| FeatureStats {
+                    tag,
+                    lookup_count: lookups.len(),
+                })
+                .collect(),
+            lookups: lookups
+                .iter()
+                .enumerate()
+                .map(|(index, lookup)| {
+                    let (kind, extension, subtable_count) = lookup_stats(lookup);
+                    LookupStats {
+                        index,
+                        kind,
+                        extension,
+                        subtable_count,
+                        size: size(&**lookup),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+pub(super) fn gsub_lookup(lookup: &SubstitutionLookup) -> (&'static str, bool, usize) {
+    match lookup {
+        SubstitutionLookup::Single(lookup) => ("SingleSubst", false, count(lookup)),
+        SubstitutionLookup::Multiple(lookup) => ("MultipleSubst", false, count(lookup)),
+        SubstitutionLookup::Alternate(lookup) => ("AlternateSubst", false, count(lookup)),
+        SubstitutionLookup::Ligature(lookup) => ("LigatureSubst", false, count(lookup)),
+        SubstitutionLookup::Contextual(lookup) => ("ContextSubst", false, count(lookup)),
+        SubstitutionLookup::ChainContextual(lookup) => ("ChainContextSubst", false, count(lookup)),
+        SubstitutionLookup::Reverse(lookup) => ("ReverseChainSingleSubst", false, count(lookup)),
+        SubstitutionLookup::Extension(lookup) => {
+            let kind = match lookup.subtables.first().map(|sub| &**sub) {
+                Some(gsub::ExtensionSubtable::Single(_)) => "SingleSubst",
+                Some(gsub::ExtensionSubtable::Multiple(_)) => "MultipleSubst",
+                Some(gsub::ExtensionSubtable::Alternate(_)) => "AlternateSubst",
+                Some(gsub::ExtensionSubtable::Ligature(_)) => "LigatureSubst",
+                Some(gsub::ExtensionSubtable::Contextual(_)) => "ContextSubst",
+                Some(gsub::ExtensionSubtable::ChainContextual(_)) => "ChainContextSubst",
+                Some(gsub::ExtensionSubtable::Reverse(_)) => "ReverseChainSingleSubst",
+                None => "ExtensionSubst",
+            };
+            (kind, true, count(lookup))
+        }
+    }
+}
+
+pub(super) fn gpos_lookup(lookup: &PositionLookup) -> (&'static str, bool, usize) {
+    match lookup {
+        PositionLookup::Single(lookup) => ("SinglePos", false, count(lookup)),
+        PositionLookup::Pair(lookup) => ("PairPos", false, count(lookup)),
+        PositionLookup::Cursive(lookup) => ("CursivePos", false, count(lookup)),
+        PositionLookup::MarkToBase(lookup) => ("MarkBasePos", false, count(lookup)),
+        PositionLookup::MarkToLig(lookup) => ("MarkLigPos", false, count(lookup)),
+        PositionLookup::MarkToMark(lookup) => ("MarkMarkPos", false, count(lookup)),
+        PositionLookup::Contextual(lookup) => ("ContextPos", false, count(lookup)),
+        PositionLookup::ChainContextual(lookup) => ("ChainContextPos", false, count(lookup)),
+        PositionLookup::Extension(lookup) => {
+            let kind = match lookup.subtables.first().map(|sub| &**sub) {
+                Some(gpos::ExtensionSubtable::Single(_)) => "SinglePos",
+                Some(gpos::ExtensionSubtable::Pair(_)) => "PairPos",
+                Some(gpos::ExtensionSubtable::Cursive(_)) => "CursivePos",
+                Some(gpos::ExtensionSubtable::MarkToBase(_)) => "MarkBasePos",
+                Some(gpos::ExtensionSubtable::MarkToLig(_)) => "MarkLigPos",
+                Some(gpos::ExtensionSubtable::MarkToMark(_)) => "MarkMarkPos",
+                Some(gpos::ExtensionSubtable::Contextual(_)) => "ContextPos",
+                Some(gpos::ExtensionSubtable::ChainContextual(_)) => "ChainContextPos",
+                None => "ExtensionPos",
+            };
+            (kind, true, count(lookup))
+        }
+    }
+}
+
+fn count<T>(lookup: &Lookup<T>) -> usize {
+    lookup.subtables.len()
+}
+
+pub(super) fn size(table: &(impl FontWrite + Validate)) -> Option<usize> {
+    write_fonts::dump_table(table).ok().map(|bytes| bytes.len())
+}
+
+fn json_size(size: Option<usize>) -> String {
+    size.map(|size| size.to_string())
+        .unwrap_or_else(|| "null".into())
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Display for CompilationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for table in &self.tables {
+            writeln!(
+                f,
+                "{}: {}, {} features, {} lookups",
+                table.tag,
+                DisplaySize(table.size),
+                table.features.len(),
+                table.lookups.len()
+            )?;
+            for feature in &table.features {
+                writeln!(
+                    f,
+                    "  feature {}: {} lookups",
+                    feature.tag, feature.lookup_count
+                )?;
+            }
+            for lookup in &table.lookups {
+                writeln!(
+                    f,
+                    "  lookup {}: {}{}, {} subtables, {}",
+                    lookup.index,
+                    lookup.kind,
+                    if lookup.extension { " (extension)" } else { "" },
+                    lookup.subtable_count,
+                    DisplaySize(lookup.size),
+                )?;
+            }
+        }
+        if self.gdef_size.is_some() {
+            writeln!(
+                f,
+                "GDEF: {}, {} mark attachment classes, {} mark filtering sets",
+                DisplaySize(self.gdef_size),
+                self.mark_attachment_classes,
+                self.mark_filtering_sets
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct DisplaySize(Option<usize>);
+
+impl Display for DisplaySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(size) => write!(f, "{size} bytes"),
+            None => f.write_str("unknown size"),
+        }
+    }
+}
@@ -27,17 +27,19 @@ mod os2;
 mod stat;
 
 pub(crate) use base::{BASELINE_TAGS, BaseAxisBuilder, BaseBuilder, ScriptRecord};
-pub(crate) use gdef::{GdefBuilder, GlyphClassDefExt};
+pub use gdef::GdefBuilder;
+pub(crate) use gdef::GlyphClassDefExt;
 pub(crate) use name::{NameBuilder, NameSpec};
 pub(crate) use os2::{CodePageRange, Os2Builder};
 pub(crate) use stat::{AxisLocation, AxisRecord, AxisValue, StatBuilder, StatFallbackName};
 
 /// The explicit tables allowed in a fea file
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Tables {
     pub head: Option<HeadBuilder>,
-    pub hhea: Option<tables::hhea::Hhea>,
-    pub vhea: Option<tables::vhea::Vhea>,
+    pub hhea: Option<HheaBuilder>,
+    pub vhea: Option<VheaBuilder>,
     pub vmtx: Option<VmtxBuilder>,
     pub name: NameBuilder,
     pub gdef: Option<GdefBuilder>,
@@ -46,12 +48,50 @@ pub(crate) struct Tables {
     pub stat: Option<StatBuilder>,
 }
 
+/// The tables for which FEA only sets some fields.
+///
+/// When compiling into an existing font, these are applied on top of that
+/// font's tables, so that the fields FEA does not set keep their values.
 #[derive(Clone, Debug, Default)]
+pub(crate) struct TableOverrides {
+    pub head: Option<HeadBuilder>,
+    pub hhea: Option<HheaBuilder>,
+    pub vhea: Option<VheaBuilder>,
+    pub os2: Option<Os2Builder>,
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct HeadBuilder {
     pub font_revision: Fixed,
 }
 
+/// The fields of the `hhea` table that can be set in FEA.
+///
+/// Fields that are `None` were not set, and keep the value from the font
+/// when the table is merged into an existing font.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct HheaBuilder {
+    pub caret_offset: Option<i16>,
+    pub ascender: Option<i16>,
+    pub descender: Option<i16>,
+    pub line_gap: Option<i16>,
+}
+
+/// The fields of the `vhea` table that can be set in FEA.
+///
+/// As with [`HheaBuilder`], unset fields keep the value from the font.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct VheaBuilder {
+    pub ascender: Option<i16>,
+    pub descender: Option<i16>,
+    pub line_gap: Option<i16>,
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct VmtxBuilder {
     pub origins_y: Vec<(GlyphId16, i16)>,
     pub advances_y: Vec<(GlyphId16, i16)>,
@@ -81,3 +121,42 @@ impl HeadBuilder {
         head
     }
 }
+
+impl HheaBuilder {
+    pub(crate) fn build(&self, font: Option<&FontRef>) -> tables::hhea::Hhea {
+        let mut hhea: tables::hhea::Hhea = font
+            .and_then(|f| f.hhea().map(|x| x.to_owned_table()).ok())
+            .unwrap_or_default();
+        if let Some(caret_offset) = self.caret_offset {
+            hhea.caret_offset = caret_offset;
+        }
+        if let Some(ascender) = self.ascender {
+            hhea.ascender = ascender.into();
+        }
+        if let Some(descender) = self.descender {
+            hhea.descender = descender.into();
+        }
+        if let Some(line_gap) = self.line_gap {
+            hhea.line_gap = line_gap.into();
+        }
+        hhea
+    }
+}
+
+impl VheaBuilder {
+    pub(crate) fn build(&self, font: Option<&FontRef>) -> tables::vhea::Vhea {
+        let mut vhea: tables::vhea::Vhea = font
+            .and_then(|f| f.vhea().map(|x| x.to_owned_table()).ok())
+            .unwrap_or_default();
+        if let Some(ascender) = self.ascender {
+            vhea.ascender = ascender.into();
+        }
+        if let Some(descender) = self.descender {
+            vhea.descender = descender.into();
+        }
+        if let Some(line_gap) = self.line_gap {
+            vhea.line_gap = line_gap.into();
+        }
+        vhea
+    }
+}
@@ -36,12 +36,14 @@ pub const MATH: Tag = Tag::new(b"math");
 pub const ROMN: Tag = Tag::new(b"romn");
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct BaseBuilder {
     vert: BaseAxisBuilder,
     horiz: BaseAxisBuilder,
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct BaseAxisBuilder {
     tag_list: Vec<Tag>,
     script_list: Vec<ScriptRecord>,
@@ -49,6 +51,7 @@ pub(crate) struct BaseAxisBuilder {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct ScriptRecord {
     pub script: Tag,
     pub default_baseline_tag: Tag,
@@ -56,6 +59,7 @@ pub(crate) struct ScriptRecord {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct MinMax {
     pub min: i16,
     pub max: i16,
@@ -77,7 +81,16 @@ impl BaseAxisBuilder {
         mut script_list: Vec<ScriptRecord>,
         minmax: Vec<(Tag, Tag, (i16, i16))>,
     ) -> Self {
+        // the tag list must be sorted, and the values of each script record
+        // need to be reordered to match.
+        let mut order = (0..tag_list.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| tag_list[*i]);
         tag_list.sort_unstable();
+        for rec in script_list.iter_mut() {
+            // this is checked during validation
+            debug_assert_eq!(rec.values.len(), order.len(), "{}", rec.script);
+            rec.values = order.iter().map(|i| rec.values[*i]).collect();
+        }
         script_list.sort_unstable_by_key(|rec| rec.script);
         let mut minmaxmap = HashMap::new();
         for (script, lang, (min, max)) in minmax {
@@ -177,4 +190,24 @@ mod tests {
     fn base_tags_sorted() {
         assert!(BASELINE_TAGS.is_sorted());
     }
+
+    #[test]
+    fn unsorted_tag_list_reorders_values() {
+        let builder = BaseAxisBuilder::new(
+            vec![ROMN, IDEO],
+            vec![ScriptRecord {
+                script: Tag::new(b"latn"),
+                default_baseline_tag: ROMN,
+                values: vec![0, -120],
+            }],
+            Vec::new(),
+        );
+        assert_eq!(builder.tag_list, [IDEO, ROMN]);
+        assert_eq!(builder.script_list[0].values, [-120, 0]);
+
+        let axis = builder.build().unwrap();
+        let script = &axis.base_script_list.base_script_records[0].base_script;
+        let values = script.base_values.as_ref().unwrap();
+        assert_eq!(values.default_baseline_index, 1);
+    }
 }
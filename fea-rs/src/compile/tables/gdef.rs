@@ -6,7 +6,7 @@
 //!
 //! [gdef-spec]: http://adobe-type-tools.github.io/afdko/OpenTypeFeatureFileSpecification.html#9b-gdef-table
 
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, btree_map::Entry};
 use std::fmt::Display;
 
 use write_fonts::types::GlyphId16;
@@ -27,23 +27,93 @@ use write_fonts::tables::{
 use super::{VariationIndexRemapping, VariationStoreBuilder};
 use crate::common::{GlyphClass, GlyphSet};
 
-/// Data collected from a GDEF block.
+/// A builder for the GDEF table.
+///
+/// During compilation this collects the contents of a GDEF block, as well as
+/// the data that is inferred from the rest of the FEA (such as mark classes,
+/// mark filtering sets, and ligature carets from a feature writer), but it can
+/// also be used on its own.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GdefBuilder {
+    /// The class of each glyph
     pub glyph_classes: HashMap<GlyphId16, GlyphClassDef>,
     /// if `true`, then glyph classes were not declared explicitly.
     ///
     /// we track this because it is an important distinction when using the
     /// glyph classes for manually generated kern/markpos lookups
     pub glyph_classes_were_inferred: bool,
+    /// Attachment point indices, for the `AttachList`
     pub attach: BTreeMap<GlyphId16, BTreeSet<u16>>,
+    /// Ligature caret positions, for the `LigCaretList`
     pub ligature_pos: BTreeMap<GlyphId16, Vec<CaretValueBuilder>>,
+    /// The mark attachment class of each glyph
     pub mark_attach_class: BTreeMap<GlyphId16, u16>,
+    /// The mark glyph sets, in order; these are referenced by their index
     pub mark_glyph_sets: Vec<GlyphSet>,
+    /// The variation store, if this is a variable font
+    ///
+    /// This is only added when the tables are built, so it is not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub var_store: Option<VariationStoreBuilder>,
 }
 
 impl GdefBuilder {
+    /// Add glyph classes that were inferred from other data.
+    ///
+    /// This does nothing if glyph classes have already been set; if it adds
+    /// any classes, they are marked as inferred.
+    pub fn infer_glyph_classes(
+        &mut self,
+        classes: impl IntoIterator<Item = (GlyphId16, GlyphClassDef)>,
+    ) {
+        if !self.glyph_classes.is_empty() && !self.glyph_classes_were_inferred {
+            return;
+        }
+        self.glyph_classes.extend(classes);
+        self.glyph_classes_were_inferred = !self.glyph_classes.is_empty();
+    }
+
+    /// Add attachment point indices for a glyph.
+    pub fn add_attach_points(&mut self, glyph: GlyphId16, points: impl IntoIterator<Item = u16>) {
+        self.attach.entry(glyph).or_default().extend(points);
+    }
+
+    /// Set the ligature carets for a glyph.
+    ///
+    /// If the glyph already has carets, they are kept, and this returns `false`.
+    pub fn add_ligature_carets(
+        &mut self,
+        glyph: GlyphId16,
+        carets: Vec<CaretValueBuilder>,
+    ) -> bool {
+        match self.ligature_pos.entry(glyph) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(carets);
+                true
+            }
+        }
+    }
+
+    /// Add a mark glyph set, returning its index.
+    ///
+    /// If an identical set has already been added, its index is returned.
+    pub fn add_mark_glyph_set(&mut self, glyphs: GlyphSet) -> u16 {
+        let idx = match self.mark_glyph_sets.iter().position(|set| *set == glyphs) {
+            Some(idx) => idx,
+            None => {
+                self.mark_glyph_sets.push(glyphs);
+                self.mark_glyph_sets.len() - 1
+            }
+        };
+        idx.try_into().expect("too many mark glyph sets")
+    }
+
+    /// Build the table.
+    ///
+    /// If the table has a variation store, this also returns the mapping from
+    /// the temporary variation indices used while building to their final values.
     pub fn build(&self) -> (tables::gdef::Gdef, Option<VariationIndexRemapping>) {
         let mut var_store = self
             .var_store
@@ -150,7 +220,8 @@ impl GdefBuilder {
         Ok(())
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
+    /// Returns `true` if the table would have no contents.
+    pub fn is_empty(&self) -> bool {
         self.glyph_classes.is_empty()
             && self.attach.is_empty()
             && self.ligature_pos.is_empty()
@@ -175,3 +246,53 @@ impl GlyphClassDefExt for GlyphClassDef {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_gdef_data() {
+        let gid = GlyphId16::new;
+        let mut builder = GdefBuilder::default();
+        assert!(builder.is_empty());
+
+        builder.infer_glyph_classes([(gid(1), GlyphClassDef::Base), (gid(2), GlyphClassDef::Mark)]);
+        assert!(builder.glyph_classes_were_inferred);
+        builder.add_attach_points(gid(1), [3, 1]);
+        builder.add_attach_points(gid(1), [2]);
+        assert!(builder.add_ligature_carets(gid(3), vec![CaretValueBuilder::PointIndex(4)]));
+        assert!(!builder.add_ligature_carets(gid(3), vec![CaretValueBuilder::PointIndex(5)]));
+
+        let marks: GlyphSet = [gid(2)].into_iter().collect();
+        let more_marks: GlyphSet = [gid(2), gid(4)].into_iter().collect();
+        assert_eq!(builder.add_mark_glyph_set(marks.clone()), 0);
+        assert_eq!(builder.add_mark_glyph_set(more_marks), 1);
+        assert_eq!(builder.add_mark_glyph_set(marks), 0);
+
+        let (gdef, remapping) = builder.build();
+        assert!(remapping.is_none());
+        assert!(gdef.glyph_class_def.is_some());
+        assert_eq!(
+            gdef.attach_list.as_ref().unwrap().attach_points[0].point_indices,
+            [1, 2, 3]
+        );
+        assert_eq!(gdef.lig_caret_list.as_ref().unwrap().lig_glyphs.len(), 1);
+        assert_eq!(
+            gdef.mark_glyph_sets_def.as_ref().unwrap().coverages.len(),
+            2
+        );
+    }
+
+    #[test]
+    fn explicit_glyph_classes_are_kept() {
+        let gid = GlyphId16::new;
+        let mut builder = GdefBuilder::default();
+        builder
+            .glyph_classes
+            .insert(gid(1), GlyphClassDef::Ligature);
+        builder.infer_glyph_classes([(gid(1), GlyphClassDef::Base), (gid(2), GlyphClassDef::Mark)]);
+        assert!(!builder.glyph_classes_were_inferred);
+        assert_eq!(builder.glyph_classes.len(), 1);
+    }
+}
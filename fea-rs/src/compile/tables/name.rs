@@ -8,6 +8,7 @@ use write_fonts::{read::tables::name::Encoding, types::NameId};
 use crate::compile::tags::{MAC_PLATFORM_ID, WIN_PLATFORM_ID};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct NameBuilder {
     records: Vec<(NameId, NameSpec)>,
     // the last used non-reserved nameid value.
@@ -15,6 +16,7 @@ pub(crate) struct NameBuilder {
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct NameSpec {
     pub platform_id: u16,
     pub encoding_id: u16,
@@ -41,7 +43,16 @@ impl Default for NameBuilder {
 impl NameBuilder {
     pub(crate) fn add(&mut self, name_id: NameId, name_spec: NameSpec) {
         self.last_nonreserved_id = self.last_nonreserved_id.max(name_id);
-        self.records.push((name_id, name_spec));
+        // if we already have a string for this id & platform/encoding/language,
+        // the later one wins (this matches feaLib)
+        match self
+            .records
+            .iter_mut()
+            .find(|(id, spec)| *id == name_id && spec.key() == name_spec.key())
+        {
+            Some((_, existing)) => *existing = name_spec,
+            None => self.records.push((name_id, name_spec)),
+        }
     }
 
     pub(crate) fn add_anon_group(&mut self, entries: &[NameSpec]) -> NameId {
@@ -227,4 +238,28 @@ mod tests {
         nb.add_anon_group(&[blank, mallard.clone()]);
         assert_eq!(vec![(NameId::new(256), mallard)], nb.records);
     }
+
+    #[test]
+    fn duplicate_names_last_wins() {
+        let win = |string: &str| NameSpec {
+            platform_id: 3,
+            encoding_id: 1,
+            language_id: 0x409,
+            string: string.into(),
+        };
+        let mac = NameSpec {
+            platform_id: 1,
+            encoding_id: 0,
+            language_id: 0,
+            string: "mac".into(),
+        };
+        let mut nb = NameBuilder::default();
+        nb.add(NameId::new(256), win("first"));
+        nb.add(NameId::new(256), mac.clone());
+        nb.add(NameId::new(256), win("second"));
+        assert_eq!(
+            vec![(NameId::new(256), win("second")), (NameId::new(256), mac)],
+            nb.records
+        );
+    }
 }
@@ -1,32 +1,44 @@
 //! The OS/2 table
 
-use write_fonts::types::Tag;
+use write_fonts::{
+    from_obj::ToOwnedTable,
+    read::{FontRef, TableProvider},
+    tables::os2::Os2,
+    types::Tag,
+};
 
 /// [ulUnicodeRangeN](https://learn.microsoft.com/en-us/typography/opentype/spec/os2#ulunicoderange1-bits-031ulunicoderange2-bits-3263ulunicoderange3-bits-6495ulunicoderange4-bits-96127)
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct UnicodeRange([u32; 4]);
 
 /// [ulCodePageRangeN](https://learn.microsoft.com/en-us/typography/opentype/spec/os2#ulcodepagerange1-bits-031ulcodepagerange2-bits-3263)
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct CodePageRange([u32; 2]);
 
+/// The fields of the `OS/2` table that can be set in FEA.
+///
+/// Fields that are `None` were not set, and keep the value from the font
+/// when the table is merged into an existing font.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Os2Builder {
-    pub us_weight_class: u16,
-    pub us_width_class: u16,
-    pub fs_type: u16,
-    pub s_family_class: i16,
-    pub panose_10: [u8; 10],
-    pub unicode_range: UnicodeRange,
-    pub ach_vend_id: Tag,
-    pub us_win_ascent: u16,
-    pub us_win_descent: u16,
-    pub code_page_range: CodePageRange,
-    pub sx_height: i16,
-    pub s_cap_height: i16,
-    pub s_typo_ascender: i16,
-    pub s_typo_descender: i16,
-    pub s_typo_line_gap: i16,
+    pub us_weight_class: Option<u16>,
+    pub us_width_class: Option<u16>,
+    pub fs_type: Option<u16>,
+    pub s_family_class: Option<i16>,
+    pub panose_10: Option<[u8; 10]>,
+    pub unicode_range: Option<UnicodeRange>,
+    pub ach_vend_id: Option<Tag>,
+    pub us_win_ascent: Option<u16>,
+    pub us_win_descent: Option<u16>,
+    pub code_page_range: Option<CodePageRange>,
+    pub sx_height: Option<i16>,
+    pub s_cap_height: Option<i16>,
+    pub s_typo_ascender: Option<i16>,
+    pub s_typo_descender: Option<i16>,
+    pub s_typo_line_gap: Option<i16>,
     pub us_lower_optical_point_size: Option<u16>,
     pub us_upper_optical_point_size: Option<u16>,
 }
@@ -59,45 +71,84 @@ fn set_bit_impl<const N: usize>(array: &mut [u32; N], bit: u8) {
 }
 
 impl Os2Builder {
-    pub fn build(&self) -> write_fonts::tables::os2::Os2 {
-        let [ul_code_page_range_1, ul_code_page_range_2] = self.code_page_range.0;
-        let [
-            ul_unicode_range_1,
-            ul_unicode_range_2,
-            ul_unicode_range_3,
-            ul_unicode_range_4,
-        ] = self.unicode_range.0;
+    pub fn build(&self, font: Option<&FontRef>) -> Os2 {
+        let mut os2 = font
+            .and_then(|f| f.os2().map(|x| x.to_owned_table()).ok())
+            .unwrap_or_else(|| Os2 {
+                ul_code_page_range_1: Some(0),
+                ul_code_page_range_2: Some(0),
+                sx_height: Some(0),
+                s_cap_height: Some(0),
+                //TODO: these are defined in fea, but we want them to be present
+                //since other v2 fields are? I assume they get overwritten anyway?
+                us_default_char: Some(0),
+                us_max_context: Some(0),
+                us_break_char: Some(0),
+                ..Default::default()
+            });
 
-        write_fonts::tables::os2::Os2 {
-            us_weight_class: self.us_weight_class,
-            us_width_class: self.us_width_class,
-            fs_type: self.fs_type,
-            s_family_class: self.s_family_class,
-            ul_unicode_range_1,
-            ul_unicode_range_2,
-            ul_unicode_range_3,
-            ul_unicode_range_4,
-            panose_10: self.panose_10,
-            ach_vend_id: self.ach_vend_id,
-            s_typo_ascender: self.s_typo_ascender,
-            s_typo_descender: self.s_typo_descender,
-            s_typo_line_gap: self.s_typo_line_gap,
-            us_win_ascent: self.us_win_ascent,
-            us_win_descent: self.us_win_descent,
-            ul_code_page_range_1: Some(ul_code_page_range_1),
-            ul_code_page_range_2: Some(ul_code_page_range_2),
-            sx_height: Some(self.sx_height),
-            s_cap_height: Some(self.s_cap_height),
-            //TODO: these are defined in fea, but we want them to be present
-            //since other v2 fields are? I assume they get overwritten anyway?
-            us_default_char: Some(0),
-            us_max_context: Some(0),
-            us_break_char: Some(0),
-            //TODO: ensure at validation that if one is present, the other is?
-            us_lower_optical_point_size: self.us_lower_optical_point_size,
-            us_upper_optical_point_size: self.us_upper_optical_point_size,
-            ..Default::default()
+        macro_rules! set_if_some {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(value) = self.$field {
+                        os2.$field = value;
+                    }
+                )*
+            };
         }
+        set_if_some!(
+            us_weight_class,
+            us_width_class,
+            fs_type,
+            s_family_class,
+            panose_10,
+            ach_vend_id,
+            s_typo_ascender,
+            s_typo_descender,
+            s_typo_line_gap,
+            us_win_ascent,
+            us_win_descent
+        );
+        if let Some(UnicodeRange(ranges)) = &self.unicode_range {
+            [
+                os2.ul_unicode_range_1,
+                os2.ul_unicode_range_2,
+                os2.ul_unicode_range_3,
+                os2.ul_unicode_range_4,
+            ] = *ranges;
+        }
+        if let Some(CodePageRange([range_1, range_2])) = &self.code_page_range {
+            os2.ul_code_page_range_1 = Some(*range_1);
+            os2.ul_code_page_range_2 = Some(*range_2);
+        }
+        if self.sx_height.is_some() {
+            os2.sx_height = self.sx_height;
+        }
+        if self.s_cap_height.is_some() {
+            os2.s_cap_height = self.s_cap_height;
+        }
+        // validation ensures these are either both present or both absent
+        if self.us_lower_optical_point_size.is_some() {
+            os2.us_lower_optical_point_size = self.us_lower_optical_point_size;
+            os2.us_upper_optical_point_size = self.us_upper_optical_point_size;
+        }
+        // a font's table may be an older version than the fields we set need,
+        // and each version includes all the fields of the versions before it
+        let needs_v2 = os2.us_lower_optical_point_size.is_some()
+            || os2.sx_height.is_some()
+            || os2.s_cap_height.is_some();
+        if needs_v2 {
+            os2.sx_height.get_or_insert(0);
+            os2.s_cap_height.get_or_insert(0);
+            os2.us_default_char.get_or_insert(0);
+            os2.us_break_char.get_or_insert(0);
+            os2.us_max_context.get_or_insert(0);
+        }
+        if needs_v2 || os2.ul_code_page_range_1.is_some() {
+            os2.ul_code_page_range_1.get_or_insert(0);
+            os2.ul_code_page_range_2.get_or_insert(0);
+        }
+        os2
     }
 }
 
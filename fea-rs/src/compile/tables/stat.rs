@@ -10,6 +10,7 @@ use write_fonts::{
 use super::{NameBuilder, NameSpec};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct StatBuilder {
     pub name: StatFallbackName,
     pub records: Vec<AxisRecord>,
@@ -17,6 +18,7 @@ pub(crate) struct StatBuilder {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct AxisRecord {
     pub tag: Tag,
     pub name: Vec<NameSpec>,
@@ -24,6 +26,7 @@ pub(crate) struct AxisRecord {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct AxisValue {
     pub flags: u16,
     pub name: Vec<NameSpec>,
@@ -31,6 +34,7 @@ pub(crate) struct AxisValue {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AxisLocation {
     One {
         tag: Tag,
@@ -51,6 +55,7 @@ pub enum AxisLocation {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum StatFallbackName {
     Id(NameId),
     Record(Vec<NameSpec>),
@@ -59,10 +64,8 @@ pub(crate) enum StatFallbackName {
 impl StatBuilder {
     pub(crate) fn build(&self, name_builder: &mut NameBuilder) -> write_stat::Stat {
         let elided_fallback_name_id = match &self.name {
-            StatFallbackName::Id(id) if name_builder.contains_id(*id) => *id,
-            StatFallbackName::Id(id) => {
-                panic!("ElidedFallbackNameID '{id}' does not exist in font")
-            }
+            // an id that is not in the name table is reported during compilation
+            StatFallbackName::Id(id) => *id,
             StatFallbackName::Record(names) => name_builder.add_anon_group(names),
         };
 
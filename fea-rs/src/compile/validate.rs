@@ -5,19 +5,24 @@
 //! and that other constraints of the spec are upheld.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     ops::Range,
 };
 
 use smol_str::SmolStr;
-use write_fonts::{read::tables::name::Encoding, types::Tag};
+use write_fonts::{
+    read::tables::name::Encoding,
+    tables::layout::LookupFlag,
+    types::{GlyphId16, Tag},
+};
 
 use super::{
-    VariationInfo, glyph_range,
+    CancellationToken, VariationInfo, glyph_class, glyph_range,
     tags::{self, WIN_PLATFORM_ID},
 };
 use crate::{
-    Diagnostic, GlyphMap, Kind, NodeOrToken,
+    Diagnostic, DiagnosticCode, GlyphMap, Kind, Node, NodeOrToken,
+    common::GlyphClass,
     parse::SourceMap,
     token_tree::{
         Token,
@@ -34,18 +39,29 @@ pub struct ValidationCtx<'a, V: VariationInfo> {
     default_lang_systems: HashSet<(SmolStr, SmolStr)>,
     seen_non_default_script: bool,
     lookup_defs: HashMap<SmolStr, Token>,
+    used_lookups: HashSet<SmolStr>,
     // class and position
     glyph_class_defs: HashMap<SmolStr, Token>,
+    used_glyph_classes: HashSet<SmolStr>,
     mark_class_defs: HashSet<SmolStr>,
     mark_class_used: Option<Token>,
+    // the glyphs in each class, or `None` if some member is not in the font
+    glyph_class_members: HashMap<SmolStr, Option<GlyphClass>>,
+    mark_class_members: HashMap<SmolStr, Option<GlyphClass>>,
+    mark_attach_classes: Vec<BTreeSet<GlyphId16>>,
     anchor_defs: HashMap<SmolStr, Token>,
     value_record_defs: HashMap<SmolStr, Token>,
     condition_set_defs: HashMap<SmolStr, Token>,
     aalt_referenced_features: HashMap<Tag, typed::Tag>,
     all_features: HashSet<Tag>,
+    // the ids in the name table, and the STAT table's ElidedFallbackNameID,
+    // which has to be one of them
+    name_ids: HashSet<u16>,
+    stat_fallback_name_id: Option<(u16, Range<usize>)>,
     // we cache these because they're reused a lot and slightly expensive to query
     // see <https://glyphsapp.com/learn/tokens#g-number-values>
     glyphs_app_number_idents: HashSet<SmolStr>,
+    pub(crate) cancellation: Option<CancellationToken>,
 }
 
 impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
@@ -62,60 +78,197 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             default_lang_systems: Default::default(),
             seen_non_default_script: false,
             glyph_class_defs: Default::default(),
+            used_glyph_classes: Default::default(),
             lookup_defs: Default::default(),
+            used_lookups: Default::default(),
             mark_class_defs: Default::default(),
             mark_class_used: None,
+            glyph_class_members: Default::default(),
+            mark_class_members: Default::default(),
+            mark_attach_classes: Default::default(),
             anchor_defs: Default::default(),
             value_record_defs: Default::default(),
             condition_set_defs: Default::default(),
             aalt_referenced_features: Default::default(),
             all_features: Default::default(),
+            name_ids: Default::default(),
+            stat_fallback_name_id: None,
             glyphs_app_number_idents: Default::default(),
+            cancellation: None,
         }
     }
 
-    fn error(&mut self, range: Range<usize>, message: impl Into<String>) {
+    fn error(&mut self, code: DiagnosticCode, range: Range<usize>, message: impl Into<String>) {
         let (file, range) = self.source_map.resolve_range(range);
-        self.errors.push(Diagnostic::error(file, range, message));
+        self.errors
+            .push(Diagnostic::error(file, range, message).with_code(code));
     }
 
-    fn warning(&mut self, range: Range<usize>, message: impl Into<String>) {
+    fn warning(&mut self, code: DiagnosticCode, range: Range<usize>, message: impl Into<String>) {
         let (file, range) = self.source_map.resolve_range(range);
-        self.errors.push(Diagnostic::warning(file, range, message));
+        self.errors
+            .push(Diagnostic::warning(file, range, message).with_code(code));
+    }
+
+    /// Add a related location, such as a previous definition, to the last
+    /// error or warning.
+    fn related(&mut self, range: Range<usize>, message: impl Into<String>) {
+        let (file, range) = self.source_map.resolve_range(range);
+        if let Some(diagnostic) = self.errors.last_mut() {
+            diagnostic.add_related(file, range, message);
+        }
     }
 
     pub(crate) fn validate_root(&mut self, node: &typed::Root) {
         for item in node.statements() {
-            if let Some(language_system) = typed::LanguageSystem::cast(item) {
+            if self
+                .cancellation
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                // the final checks need to have seen the whole source
+                return;
+            }
+            if self.skip_malformed(&item) {
+                continue;
+            } else if let Some(language_system) = typed::LanguageSystem::cast(&item) {
                 self.validate_language_system(&language_system)
-            } else if let Some(class_def) = typed::GlyphClassDef::cast(item) {
+            } else if let Some(class_def) = typed::GlyphClassDef::cast(&item) {
                 self.validate_glyph_class_def(&class_def);
-            } else if let Some(mark_def) = typed::MarkClassDef::cast(item) {
+            } else if let Some(mark_def) = typed::MarkClassDef::cast(&item) {
                 self.validate_mark_class_def(&mark_def);
-            } else if let Some(anchor_def) = typed::AnchorDef::cast(item) {
+            } else if let Some(anchor_def) = typed::AnchorDef::cast(&item) {
                 self.validate_anchor_def(&anchor_def);
-            } else if let Some(feature) = typed::Feature::cast(item) {
+            } else if let Some(feature) = typed::Feature::cast(&item) {
                 self.validate_feature(&feature);
-            } else if let Some(table) = typed::Table::cast(item) {
+            } else if let Some(table) = typed::Table::cast(&item) {
                 self.validate_table(&table);
-            } else if let Some(lookup) = typed::LookupBlock::cast(item) {
+            } else if let Some(lookup) = typed::LookupBlock::cast(&item) {
                 self.validate_lookup_block(&lookup, None);
-            } else if let Some(node) = typed::ValueRecordDef::cast(item) {
+            } else if let Some(node) = typed::ValueRecordDef::cast(&item) {
                 self.validate_value_record_def(&node);
-            } else if let Some(node) = typed::ConditionSet::cast(item) {
+            } else if let Some(node) = typed::ConditionSet::cast(&item) {
                 self.validate_condition_set(&node);
-            } else if let Some(node) = typed::FeatureVariation::cast(item) {
+            } else if let Some(node) = typed::FeatureVariation::cast(&item) {
                 self.validate_feature_variation(&node);
-            } else if item.kind() == Kind::AnonKw {
-                unimplemented!("anon")
             }
         }
         self.finalize();
     }
 
+    /// `true` if this item failed to parse, and should not be validated.
+    ///
+    /// Feature and lookup blocks are only skipped if the block itself is
+    /// malformed; otherwise their statements are checked individually. If a
+    /// skipped item defines a name we still record it, so that references to
+    /// it are not also reported as undefined.
+    fn skip_malformed(&mut self, item: &NodeOrToken) -> bool {
+        let Some(node) = item.as_node() else {
+            return false;
+        };
+        let malformed = match node.kind() {
+            Kind::FeatureNode | Kind::LookupBlockNode | Kind::VariationNode => node.error,
+            _ => node.contains_error(),
+        };
+        if malformed {
+            self.declare_malformed(node);
+        }
+        malformed
+    }
+
+    fn declare_malformed(&mut self, node: &Node) {
+        let find_token = |kind| {
+            node.iter_children()
+                .filter_map(NodeOrToken::into_token)
+                .find(|t| t.kind == kind)
+        };
+        match node.kind() {
+            Kind::GlyphClassDefNode => {
+                if let Some(name) = find_token(Kind::NamedGlyphClass) {
+                    self.glyph_class_defs
+                        .entry(name.text.clone())
+                        .or_insert(name);
+                }
+            }
+            Kind::MarkClassNode => {
+                // the glyphs being marked may also be a named class
+                if let Some(name) = node
+                    .iter_children()
+                    .filter_map(NodeOrToken::into_token)
+                    .filter(|t| t.kind == Kind::NamedGlyphClass)
+                    .last()
+                {
+                    self.mark_class_defs.insert(name.text.clone());
+                }
+            }
+            Kind::AnchorDefNode => {
+                if let Some(name) = find_token(Kind::Ident) {
+                    self.anchor_defs.entry(name.text.clone()).or_insert(name);
+                }
+            }
+            Kind::ValueRecordDefNode => {
+                if let Some(name) = find_token(Kind::Ident) {
+                    self.value_record_defs
+                        .entry(name.text.clone())
+                        .or_insert(name);
+                }
+            }
+            Kind::LookupBlockNode => {
+                if let Some(name) = find_token(Kind::Label) {
+                    self.lookup_defs.entry(name.text.clone()).or_insert(name);
+                }
+            }
+            Kind::ConditionSetNode => {
+                if let Some(name) = find_token(Kind::Label) {
+                    self.condition_set_defs
+                        .entry(name.text.clone())
+                        .or_insert(name);
+                }
+            }
+            _ => (),
+        }
+    }
+
     /// perform any analysis required after seeing all items
     fn finalize(&mut self) {
         self.finalize_aalt();
+        self.finalize_stat();
+        self.finalize_unused();
+    }
+
+    /// the name table may come after the STAT table that refers to it
+    fn finalize_stat(&mut self) {
+        if let Some((id, range)) = self.stat_fallback_name_id.take()
+            && !self.name_ids.contains(&id)
+        {
+            self.error(
+                DiagnosticCode::UndefinedNameId,
+                range,
+                format!("ElidedFallbackNameID '{id}' does not exist in the name table"),
+            );
+        }
+    }
+
+    /// warn about named lookups and glyph classes that are never referenced
+    fn finalize_unused(&mut self) {
+        let mut unused = self
+            .lookup_defs
+            .iter()
+            .filter(|(name, _)| !self.used_lookups.contains(*name))
+            .map(|(name, token)| (token.range(), format!("lookup '{name}' is never used")))
+            .chain(
+                self.glyph_class_defs
+                    .iter()
+                    .filter(|(name, _)| !self.used_glyph_classes.contains(*name))
+                    .map(|(name, token)| {
+                        (token.range(), format!("glyph class '{name}' is never used"))
+                    }),
+            )
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|(range, _)| range.start);
+        for (range, message) in unused {
+            self.warning(DiagnosticCode::Unused, range, message);
+        }
     }
 
     fn finalize_aalt(&mut self) {
@@ -127,7 +280,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             .map(|(_, node)| node.clone())
             .collect::<Vec<_>>();
         for tag in bad {
-            self.warning(tag.range(), "Referenced feature not found.");
+            self.warning(
+                DiagnosticCode::UndefinedFeature,
+                tag.range(),
+                "Referenced feature not found.",
+            );
         }
     }
 
@@ -138,14 +295,18 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         if script.text() == "DFLT" && lang.text() == "dflt" && !self.default_lang_systems.is_empty()
         {
             self.error(
+                DiagnosticCode::MisplacedStatement,
                 node.range(),
-                "'DFLT dftl' must be first languagesystem statement",
+                "'DFLT dflt' must be first languagesystem statement",
             );
             return;
         }
+        self.check_script_tag(&script);
+        self.check_language_tag(&lang);
         if script.text() == "DFLT" {
             if self.seen_non_default_script {
                 self.error(
+                    DiagnosticCode::MisplacedStatement,
                     script.range(),
                     "languagesystem with 'DFLT' script tag must precede non-'DFLT' languagesystems",
                 );
@@ -159,41 +320,105 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             .default_lang_systems
             .insert((script.text().clone(), lang.text().clone()))
         {
-            self.warning(node.range(), "Duplicate languagesystem definition");
+            self.warning(
+                DiagnosticCode::DuplicateStatement,
+                node.range(),
+                "Duplicate languagesystem definition",
+            );
+        }
+    }
+
+    fn check_script_tag(&mut self, tag: &typed::Tag) {
+        let raw = tag.to_raw();
+        if raw == tags::SCRIPT_DFLT {
+            return;
+        }
+        if raw == tags::LANG_DFLT {
+            self.warning(
+                DiagnosticCode::InvalidTag,
+                tag.range(),
+                "'dflt' is the default language tag, the default script is 'DFLT'",
+            );
+        } else if !is_well_formed_tag(raw, |b| b.is_ascii_lowercase()) {
+            self.warning(
+                DiagnosticCode::InvalidTag,
+                tag.range(),
+                format!("malformed script tag '{raw}', script tags are lowercase"),
+            );
+        }
+    }
+
+    fn check_language_tag(&mut self, tag: &typed::Tag) {
+        let raw = tag.to_raw();
+        if raw == tags::LANG_DFLT {
+            return;
+        }
+        if raw == tags::SCRIPT_DFLT {
+            self.warning(
+                DiagnosticCode::InvalidTag,
+                tag.range(),
+                "'DFLT' is the default script tag, the default language is 'dflt'",
+            );
+        } else if !is_well_formed_tag(raw, |b| b.is_ascii_uppercase()) {
+            self.warning(
+                DiagnosticCode::InvalidTag,
+                tag.range(),
+                format!("malformed language tag '{raw}', language tags are uppercase"),
+            );
         }
     }
 
     fn validate_glyph_class_def(&mut self, node: &typed::GlyphClassDef) {
         let name = node.class_name();
-        if let Some(_prev) = self
+        if let Some(prev) = self
             .glyph_class_defs
             .insert(name.text().to_owned(), name.token().clone())
         {
-            self.warning(name.range(), "duplicate glyph class definition");
-            //TODO: use previous span to show previous declaration
+            self.warning(
+                DiagnosticCode::DuplicateDefinition,
+                name.range(),
+                "duplicate glyph class definition",
+            );
+            self.related(prev.range(), "previous definition");
             //TODO: have help message
         }
         if let Some(literal) = node.class_def() {
-            self.validate_glyph_class_literal(&literal, false);
+            self.validate_glyph_class_literal(&literal);
+            let members = self.class_literal_glyphs(&literal).map(GlyphClass::from);
+            self.glyph_class_members
+                .insert(name.text().to_owned(), members);
         } else if let Some(alias) = node.class_alias() {
-            self.validate_glyph_class_ref(&alias, false);
+            self.validate_glyph_class_ref(&alias);
+            let members = self.named_class_glyphs(&alias);
+            self.glyph_class_members
+                .insert(name.text().to_owned(), members);
         } else {
-            self.error(node.range(), "unknown parser bug?");
+            self.error(
+                DiagnosticCode::Internal,
+                node.range(),
+                "unknown parser bug?",
+            );
         }
     }
 
     fn validate_anchor_def(&mut self, node: &typed::AnchorDef) {
-        if let Some(_prev) = self
+        if let Some(prev) = self
             .anchor_defs
             .insert(node.name().text.clone(), node.name().clone())
         {
-            self.warning(node.name().range(), "duplicate anchor name");
+            self.warning(
+                DiagnosticCode::DuplicateDefinition,
+                node.name().range(),
+                "duplicate anchor name",
+            );
+            self.related(prev.range(), "previous definition");
         }
     }
 
     fn validate_mark_class_def(&mut self, node: &typed::MarkClassDef) {
         if let Some(_use_site) = self.mark_class_used.as_ref() {
             self.error(
+                DiagnosticCode::MisplacedStatement,
                 node.keyword().range(),
                 "all markClass definitions must precede any use of a mark class in the file",
             );
@@ -207,18 +432,40 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         self.validate_glyph_or_class(&node.glyph_class());
         self.mark_class_defs
             .insert(node.mark_class_name().text().clone());
-        self.validate_anchor(&node.anchor());
+        let members = self.glyph_or_class_glyphs(&node.glyph_class());
+        let all_members = self
+            .mark_class_members
+            .entry(node.mark_class_name().text().clone())
+            .or_insert_with(|| Some(GlyphClass::default()));
+        *all_members = match (all_members.take(), members) {
+            (Some(all_members), Some(members)) => Some(all_members.iter().chain(members).collect()),
+            _ => None,
+        };
+        let anchor = node.anchor();
+        if anchor.null().is_some() {
+            self.error(
+                DiagnosticCode::InvalidAnchor,
+                anchor.range(),
+                "mark class anchor cannot be NULL",
+            );
+        }
+        self.validate_anchor(&anchor);
     }
 
     fn validate_value_record_def(&mut self, node: &typed::ValueRecordDef) {
         let record = node.value_record();
         self.validate_value_record(&record);
         let name = node.name();
-        if let Some(_prev) = self
+        if let Some(prev) = self
             .value_record_defs
             .insert(name.text.clone(), name.clone())
         {
-            self.warning(name.range(), "duplicate value record name");
+            self.warning(
+                DiagnosticCode::DuplicateDefinition,
+                name.range(),
+                "duplicate value record name",
+            );
+            self.related(prev.range(), "previous definition");
         }
     }
 
@@ -226,12 +473,18 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let label = node.label().to_owned();
         if self.variation_info.is_none() {
             self.error(
+                DiagnosticCode::RequiresVariableFont,
                 node.keyword().range(),
                 "conditionset only valid when compiling variable font",
             );
         }
-        if let Some(_prev) = self.condition_set_defs.insert(label.text.clone(), label) {
-            self.warning(node.label().range(), "duplicate condition set definition");
+        if let Some(prev) = self.condition_set_defs.insert(label.text.clone(), label) {
+            self.warning(
+                DiagnosticCode::DuplicateDefinition,
+                node.label().range(),
+                "duplicate condition set definition",
+            );
+            self.related(prev.range(), "previous definition");
         }
 
         for condition in node.conditions() {
@@ -245,11 +498,16 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             return;
         };
         let Some((_, axis)) = var_info.axis(condition.tag().to_raw()) else {
-            self.error(condition.tag().range(), "unknown axis");
+            self.error(
+                DiagnosticCode::UndefinedAxis,
+                condition.tag().range(),
+                "unknown axis",
+            );
             return;
         };
         if (condition.min_value().parse_signed() as f64) < axis.min.into_inner().0 {
             self.error(
+                DiagnosticCode::ValueOutOfRange,
                 condition.min_value().range(),
                 format!(
                     "value is less than axis minimum ({})",
@@ -259,6 +517,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         }
         if (condition.max_value().parse_signed() as f64) > axis.max.into_inner().0 {
             self.error(
+                DiagnosticCode::ValueOutOfRange,
                 condition.max_value().range(),
                 format!(
                     "value is more than axis maximum ({})",
@@ -266,23 +525,42 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 ),
             );
         }
+        if condition.min_value().parse_signed() > condition.max_value().parse_signed() {
+            self.error(
+                DiagnosticCode::InvalidVariation,
+                condition.range(),
+                "condition minimum is greater than its maximum",
+            );
+        }
     }
 
     fn validate_feature_variation(&mut self, node: &typed::FeatureVariation) {
         let feature_tag = node.tag();
         if let Some(cond_set) = node.condition_set() {
             if !self.condition_set_defs.contains_key(cond_set.as_str()) {
-                self.error(cond_set.range(), "undefined conditionset");
+                self.error(
+                    DiagnosticCode::UndefinedConditionSet,
+                    cond_set.range(),
+                    "undefined conditionset",
+                );
             }
-        } else {
-            assert!(node.null().is_some(), "go fix your parser");
+        } else if node.null().is_none() {
+            self.error(
+                DiagnosticCode::UnexpectedToken,
+                node.range(),
+                "expected conditionset name or NULL",
+            );
         }
         self.validate_feature_statements(feature_tag.to_raw(), node.statements());
     }
 
     fn validate_mark_class(&mut self, node: &typed::GlyphClassName) {
         if !self.mark_class_defs.contains(node.text()) {
-            self.error(node.range(), "undefined mark class");
+            self.error(
+                DiagnosticCode::UndefinedMarkClass,
+                node.range(),
+                "undefined mark class",
+            );
         }
     }
 
@@ -297,11 +575,25 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             typed::Table::Name(table) => self.validate_name(table),
             typed::Table::Os2(table) => self.validate_os2(table),
             typed::Table::Stat(table) => self.validate_stat(table),
-            _ => self.error(node.tag().range(), "unsupported table type"),
+            typed::Table::Other(_) => {
+                let tag = node.tag();
+                self.warning(
+                    DiagnosticCode::UnknownTable,
+                    tag.range(),
+                    format!("unsupported table '{}' will be passed through", tag.text()),
+                )
+            }
         }
     }
 
     fn validate_base(&mut self, node: &typed::BaseTable) {
+        for range in node.iter_unknown_axis_items() {
+            self.error(
+                DiagnosticCode::Internal,
+                range,
+                "expected a horizontal or vertical axis keyword",
+            );
+        }
         if let Some(horiz_tags) = node.horiz_base_tag_list() {
             self.validate_base_axis(
                 &horiz_tags,
@@ -322,10 +614,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         &mut self,
         taglist: &typed::BaseTagList,
         script_list: Option<typed::BaseScriptList>,
-        _minmax: impl Iterator<Item = typed::BaseMinMax>,
+        minmax: impl Iterator<Item = typed::BaseMinMax>,
     ) {
         let Some(script_list) = script_list else {
             return self.error(
+                DiagnosticCode::MissingStatement,
                 taglist.range(),
                 "Tag list without ScriptList is meaningless",
             );
@@ -333,13 +626,28 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
 
         for tag in taglist.tags() {
             if !super::tables::BASELINE_TAGS.contains(&tag.to_raw()) {
-                self.warning(tag.range(), "not a known baseline tag");
+                self.warning(
+                    DiagnosticCode::InvalidTag,
+                    tag.range(),
+                    "not a known baseline tag",
+                );
             }
         }
-        let all_tags = taglist.tags().map(|t| t.to_raw()).collect::<HashSet<_>>();
+        let mut all_tags = HashSet::new();
+        for tag in taglist.tags() {
+            if !all_tags.insert(tag.to_raw()) {
+                self.error(
+                    DiagnosticCode::DuplicateDefinition,
+                    tag.range(),
+                    "duplicate baseline tag",
+                );
+            }
+        }
+        let n_tags = taglist.tags().count();
         for record in script_list.script_records() {
             if !all_tags.contains(&record.default_baseline().to_raw()) {
                 self.error(
+                    DiagnosticCode::InvalidTag,
                     record.default_baseline().range(),
                     "tag not in base tag list",
                 );
@@ -348,13 +656,28 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             // "The number of baseline values for a particular script should be
             // the same as the number of baseline tags in the corresponding BaseTagList."
             // (https://adobe-type-tools.github.io/afdko/OpenTypeFeatureFileSpecification.html#:~:text=The%20number%20of%20baseline%20values)
-            if record.values().count() != all_tags.len() {
+            if record.values().count() != n_tags {
                 self.error(
+                    DiagnosticCode::InvalidValue,
                     record.range(),
                     "must have exactly one value for each declared baseline tag",
                 );
             }
         }
+
+        let all_scripts = script_list
+            .script_records()
+            .map(|rec| rec.script().to_raw())
+            .collect::<HashSet<_>>();
+        for record in minmax {
+            if !all_scripts.contains(&record.script().to_raw()) {
+                self.warning(
+                    DiagnosticCode::IgnoredStatement,
+                    record.script().range(),
+                    "script not in ScriptList, MinMax will be ignored",
+                );
+            }
+        }
     }
 
     fn validate_hhea(&mut self, node: &typed::HheaTable) {
@@ -379,6 +702,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         for record in metrics {
             if record.metric().parse_simple().is_none() {
                 self.error(
+                    DiagnosticCode::Unsupported,
                     record.metric().range(),
                     "variable metrics not yet supported",
                 );
@@ -393,17 +717,25 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     }
 
     fn validate_os2(&mut self, node: &typed::Os2Table) {
+        let mut lower_op_size = None;
+        let mut upper_op_size = None;
         for item in node.statements() {
             match item {
                 typed::Os2TableItem::NumberList(item) => match item.keyword().kind {
                     Kind::PanoseKw => {
                         for number in item.values() {
                             match number.parse_unsigned() {
-                                None => self.error(number.range(), "expected positive number"),
+                                None => self.error(
+                                    DiagnosticCode::ValueOutOfRange,
+                                    number.range(),
+                                    "expected positive number",
+                                ),
                                 Some(0..=127) => (),
-                                Some(_) => {
-                                    self.error(number.range(), "expected value in range 0..128")
-                                }
+                                Some(_) => self.error(
+                                    DiagnosticCode::ValueOutOfRange,
+                                    number.range(),
+                                    "expected value in range 0..128",
+                                ),
                             }
                         }
                     }
@@ -411,6 +743,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                         for number in item.values() {
                             if !(0..128).contains(&number.parse_signed()) {
                                 self.error(
+                                    DiagnosticCode::ValueOutOfRange,
                                     number.range(),
                                     "expected value in unicode character range 0..=127",
                                 );
@@ -424,7 +757,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                             )
                             .is_none()
                             {
-                                self.error(number.range(), "not a valid code page");
+                                self.error(
+                                    DiagnosticCode::InvalidValue,
+                                    number.range(),
+                                    "not a valid code page",
+                                );
                             }
                         }
                     }
@@ -436,6 +773,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                         Ok(raw_val) => {
                             if let Err((cls, sub)) = validate_os2_family_class(raw_val) {
                                 self.warning(
+                                    DiagnosticCode::InvalidValue,
                                     val.range(),
                                     format!(
                                         "Class {cls}, subclass {sub} is not a known sFamilyClass"
@@ -443,19 +781,23 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                                 )
                             }
                         }
-                        Err(e) => self.error(val.range(), e),
+                        Err(e) => self.error(DiagnosticCode::InvalidValue, val.range(), e),
                     };
                 }
                 typed::Os2TableItem::Metric(i) => {
                     if matches!(i.keyword().kind, Kind::WinAscentKw | Kind::WinDescentKw) {
                         let val = i.metric();
                         match val.parse_simple() {
-                            None => {
-                                self.error(val.range(), "variable metrics not yet supports in OS/2")
-                            }
-                            Some(x) if x.is_negative() => {
-                                self.error(val.range(), "expected positive number")
-                            }
+                            None => self.error(
+                                DiagnosticCode::Unsupported,
+                                val.range(),
+                                "variable metrics not yet supports in OS/2",
+                            ),
+                            Some(x) if x.is_negative() => self.error(
+                                DiagnosticCode::ValueOutOfRange,
+                                val.range(),
+                                "expected positive number",
+                            ),
                             Some(_) => (),
                         }
                     }
@@ -463,32 +805,101 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 typed::Os2TableItem::Number(item) => {
                     let val = item.number();
                     if val.parse_unsigned().is_none() {
-                        self.error(val.range(), "expected positive number");
+                        self.error(
+                            DiagnosticCode::ValueOutOfRange,
+                            val.range(),
+                            "expected positive number",
+                        );
+                    }
+                    match item.keyword().text.as_str() {
+                        "LowerOpSize" => lower_op_size = Some(item.keyword().range()),
+                        "UpperOpSize" => upper_op_size = Some(item.keyword().range()),
+                        _ => (),
                     }
                 }
                 typed::Os2TableItem::Vendor(item) => {
                     if let Err(e) = item.parse_tag() {
-                        self.error(item.value().range(), format!("invalid tag: '{e}'"));
+                        self.error(
+                            DiagnosticCode::InvalidTag,
+                            item.value().range(),
+                            format!("invalid tag: '{e}'"),
+                        );
                     }
                 }
             }
         }
+        // the optical size fields are only meaningful as a pair
+        match (lower_op_size, upper_op_size) {
+            (Some(range), None) => self.error(
+                DiagnosticCode::MissingStatement,
+                range,
+                "LowerOpSize requires UpperOpSize",
+            ),
+            (None, Some(range)) => self.error(
+                DiagnosticCode::MissingStatement,
+                range,
+                "UpperOpSize requires LowerOpSize",
+            ),
+            _ => (),
+        }
     }
 
     fn validate_stat(&mut self, node: &typed::StatTable) {
         let mut seen_fallback_name = false;
+        let design_axes = node
+            .statements()
+            .filter_map(|item| match item {
+                typed::StatTableItem::DesignAxis(axis) => Some(axis.tag().to_raw()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let mut seen_design_axes = HashMap::new();
         for item in node.statements() {
             match item {
-                typed::StatTableItem::ElidedFallbackName(_) => {
+                typed::StatTableItem::ElidedFallbackName(ref name) => {
                     if seen_fallback_name {
-                        self.error(item.range(), "fallback name must only be defined once");
+                        self.error(
+                            DiagnosticCode::DuplicateStatement,
+                            item.range(),
+                            "fallback name must only be defined once",
+                        );
                     }
                     seen_fallback_name = true;
+                    if let Some(id) = name.elided_fallback_name_id() {
+                        match id.parse_unsigned() {
+                            Some(value) => {
+                                self.stat_fallback_name_id = Some((value, id.range()));
+                            }
+                            None => self.error(
+                                DiagnosticCode::ValueOutOfRange,
+                                id.range(),
+                                "expected positive number",
+                            ),
+                        }
+                    }
+                }
+                typed::StatTableItem::DesignAxis(ref axis) => {
+                    let tag = axis.tag();
+                    if let Some(prev) = seen_design_axes.insert(tag.to_raw(), tag.range()) {
+                        self.error(
+                            DiagnosticCode::DuplicateDefinition,
+                            tag.range(),
+                            "DesignAxis already defined for this tag",
+                        );
+                        self.related(prev, "previous definition");
+                    }
                 }
                 typed::StatTableItem::AxisValue(axis) => {
                     let mut seen_location_format = None;
                     for item in axis.statements() {
                         if let typed::StatAxisValueItem::Location(loc) = item {
+                            if !design_axes.contains(&loc.tag().to_raw()) {
+                                self.error(
+                                    DiagnosticCode::UndefinedAxis,
+                                    loc.tag().range(),
+                                    "location tag must be defined by a DesignAxis statement",
+                                );
+                            }
                             let format = match loc.value() {
                                 typed::StatLocationValue::Value(_) => 'a',
                                 typed::StatLocationValue::MinMax { .. } => 'b',
@@ -497,18 +908,25 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                             let prev_format = seen_location_format.replace(format);
                             match (prev_format, format) {
                                 (Some('a'), 'a') => (),
-                                (Some(_), 'a') => self.error(loc.range(), "multiple location statements, but previous statement was not format 'a'"),
-                                (Some(_), 'b' | 'c') => self.error(loc.range(),format!("location statement format '{format}' must be only statement")),
+                                (Some(_), 'a') => self.error(DiagnosticCode::MisplacedStatement, loc.range(), "multiple location statements, but previous statement was not format 'a'"),
+                                (Some(_), 'b' | 'c') => self.error(DiagnosticCode::MisplacedStatement, loc.range(),format!("location statement format '{format}' must be only statement")),
                                 _ => (),
                             }
                         }
                     }
+                    if seen_location_format.is_none() {
+                        self.error(
+                            DiagnosticCode::MissingStatement,
+                            axis.range(),
+                            "AxisValue must have a location statement",
+                        );
+                    }
                 }
-                _ => (),
             }
         }
         if !seen_fallback_name {
             self.error(
+                DiagnosticCode::MissingStatement,
                 node.tag().range(),
                 "STAT table must include 'ElidedFallbackName' or 'ElidedFallbackNameID'",
             );
@@ -518,8 +936,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     fn validate_name(&mut self, node: &typed::NameTable) {
         for record in node.statements() {
             let name_id = record.name_id();
-            if let Err(e) = name_id.parse() {
-                self.error(name_id.range(), e);
+            match name_id.parse() {
+                Ok(id) => {
+                    self.name_ids.insert(id);
+                }
+                Err(e) => self.error(DiagnosticCode::InvalidValue, name_id.range(), e),
             }
             self.validate_name_spec(&record.entry());
         }
@@ -529,28 +950,34 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let mut platform = None;
         if let Some(id) = spec.platform_id() {
             match id.parse() {
-                Err(e) => self.error(id.range(), e),
+                Err(e) => self.error(DiagnosticCode::InvalidValue, id.range(), e),
                 Ok(n @ 1 | n @ 3) => platform = Some(n),
-                Ok(_) => self.error(id.range(), "platform id must be one of '1' or '3'"),
+                Ok(_) => self.error(
+                    DiagnosticCode::InvalidValue,
+                    id.range(),
+                    "platform id must be one of '1' or '3'",
+                ),
             }
         };
 
         let platform = platform.unwrap_or(WIN_PLATFORM_ID);
 
-        if let Err((range, err)) = validate_name_string_encoding(platform, spec.string_token()) {
-            self.error(range, err);
+        if let Err((range, err)) = validate_name_string_encoding(platform, &spec.string_token()) {
+            self.error(DiagnosticCode::InvalidValue, range, err);
         }
         if let Some((platspec, language)) = spec.platform_and_language_ids() {
             match (platspec.parse(), language.parse()) {
-                (Ok(a), Ok(_)) if Encoding::new(platform, a) == Encoding::Unknown => {
-                    self.warning(spec.range(), "character encoding unsupported")
-                }
+                (Ok(a), Ok(_)) if Encoding::new(platform, a) == Encoding::Unknown => self.warning(
+                    DiagnosticCode::Unsupported,
+                    spec.range(),
+                    "character encoding unsupported",
+                ),
                 (a, b) => {
                     if let Err(e) = a {
-                        self.error(platspec.range(), e);
+                        self.error(DiagnosticCode::InvalidValue, platspec.range(), e);
                     }
                     if let Err(e) = b {
-                        self.error(language.range(), e);
+                        self.error(DiagnosticCode::InvalidValue, language.range(), e);
                     }
                 }
             };
@@ -562,26 +989,30 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             match statement {
                 typed::GdefTableItem::ClassDef(node) => {
                     if let Some(cls) = node.base_glyphs() {
-                        self.validate_glyph_class(&cls, true);
+                        self.validate_glyph_class(&cls);
                     }
 
                     if let Some(cls) = node.ligature_glyphs() {
-                        self.validate_glyph_class(&cls, true);
+                        self.validate_glyph_class(&cls);
                     }
 
                     if let Some(cls) = node.mark_glyphs() {
-                        self.validate_glyph_class(&cls, true);
+                        self.validate_glyph_class(&cls);
                     }
 
                     if let Some(cls) = node.component_glyphs() {
-                        self.validate_glyph_class(&cls, true);
+                        self.validate_glyph_class(&cls);
                     }
                 }
                 typed::GdefTableItem::Attach(node) => {
                     self.validate_glyph_or_class(&node.target());
                     for idx in node.indices() {
                         if idx.parse_unsigned().is_none() {
-                            self.error(idx.range(), "contourpoint indexes must be non-negative");
+                            self.error(
+                                DiagnosticCode::ValueOutOfRange,
+                                idx.range(),
+                                "contourpoint indexes must be non-negative",
+                            );
                         }
                     }
                 }
@@ -589,12 +1020,24 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 //to resolve glyphs here in order to track that.
                 typed::GdefTableItem::LigatureCaret(node) => {
                     self.validate_glyph_or_class(&node.target());
-                    if let typed::LigatureCaretValue::Index(node) = node.values() {
-                        for idx in node.values() {
-                            if idx.parse_unsigned().is_none() {
-                                self.error(idx.range(), "contourpoint index must be non-negative");
+                    match node.values() {
+                        Some(typed::LigatureCaretValue::Index(node)) => {
+                            for idx in node.values() {
+                                if idx.parse_unsigned().is_none() {
+                                    self.error(
+                                        DiagnosticCode::ValueOutOfRange,
+                                        idx.range(),
+                                        "contourpoint index must be non-negative",
+                                    );
+                                }
                             }
                         }
+                        Some(typed::LigatureCaretValue::Pos(_)) => (),
+                        None => self.error(
+                            DiagnosticCode::Internal,
+                            node.range(),
+                            "expected 'LigatureCaretByPos' or 'LigatureCaretByIndex'",
+                        ),
                     }
                 }
             }
@@ -605,17 +1048,26 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let mut prev = None;
         for statement in node.statements() {
             if let Some(prev) = prev.replace(statement.range()) {
-                self.warning(prev, "FontRevision overwritten by subsequent statement");
+                self.warning(
+                    DiagnosticCode::DuplicateStatement,
+                    prev,
+                    "FontRevision overwritten by subsequent statement",
+                );
             }
             let value = statement.value();
             let (int, fract) = value.text().split_once('.').expect("checked at parse time");
             if int.parse::<i16>().is_err() {
                 let start = value.range().start;
-                self.error(start..start + int.len(), "value exceeds 16bit limit");
+                self.error(
+                    DiagnosticCode::ValueOutOfRange,
+                    start..start + int.len(),
+                    "value exceeds 16bit limit",
+                );
             }
             if fract.len() != 3 {
                 let start = value.range().start + int.len();
                 self.warning(
+                    DiagnosticCode::InvalidValue,
                     start..start + fract.len(),
                     "version number should have exactly three decimal places",
                 );
@@ -645,13 +1097,16 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     }
 
     // shared between features and feature variations
-    fn validate_feature_statements<'b>(
+    fn validate_feature_statements(
         &mut self,
         feature_tag: Tag,
-        iter: impl Iterator<Item = &'b NodeOrToken>,
+        iter: impl Iterator<Item = NodeOrToken>,
     ) {
         let mut has_seen_rule = false;
         for item in iter {
+            if self.skip_malformed(&item) {
+                continue;
+            }
             if item.kind() == Kind::ScriptNode
                 || item.kind() == Kind::LanguageNode
                 || item.kind() == Kind::SubtableNode
@@ -659,61 +1114,67 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 || item.kind() == Kind::Comment
             {
                 // lgtm
-            } else if let Some(node) = typed::CvParameters::cast(item) {
+            } else if let Some(node) = typed::CvParameters::cast(&item) {
                 if !tags::is_character_variant(feature_tag) {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         node.keyword().range(),
                         "cvParameters block only valid in cv01-cv99 features",
                     );
                 } else if has_seen_rule {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         node.keyword().range(),
                         "cvParameters must precede any rules",
                     );
                 } else {
                     self.validate_character_variant_items(&node);
                 }
-            } else if let Some(node) = typed::FeatureNames::cast(item) {
+            } else if let Some(node) = typed::FeatureNames::cast(&item) {
                 if !tags::is_stylistic_set(feature_tag) {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         node.keyword().range(),
                         "featureNames block only valid in ss01-ss20 features",
                     );
                 } else if has_seen_rule {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         node.keyword().range(),
                         "featureNames must precede any rules",
                     );
                 } else {
                     self.validate_stylistic_set_items(&node);
                 }
-            } else if let Some(node) = typed::LookupRef::cast(item) {
+            } else if let Some(node) = typed::LookupRef::cast(&item) {
                 self.validate_lookup_ref(&node);
                 has_seen_rule = true;
-            } else if let Some(node) = typed::LookupBlock::cast(item) {
+            } else if let Some(node) = typed::LookupBlock::cast(&item) {
                 self.validate_lookup_block(&node, Some(feature_tag));
                 has_seen_rule = true;
-            } else if let Some(node) = typed::LookupFlag::cast(item) {
+            } else if let Some(node) = typed::LookupFlag::cast(&item) {
                 self.validate_lookupflag(&node);
-            } else if let Some(node) = typed::GsubStatement::cast(item) {
+            } else if let Some(node) = typed::GsubStatement::cast(&item) {
                 self.validate_gsub_statement(&node);
                 has_seen_rule = true;
-            } else if let Some(node) = typed::GposStatement::cast(item) {
+            } else if let Some(node) = typed::GposStatement::cast(&item) {
                 self.validate_gpos_statement(&node);
                 has_seen_rule = true;
-            } else if let Some(node) = typed::GlyphClassDef::cast(item) {
+            } else if let Some(node) = typed::GlyphClassDef::cast(&item) {
                 self.validate_glyph_class_def(&node);
-            } else if let Some(node) = typed::MarkClassDef::cast(item) {
+            } else if let Some(node) = typed::MarkClassDef::cast(&item) {
                 self.validate_mark_class_def(&node);
-            } else if let Some(_node) = typed::FeatureNames::cast(item) {
-                self.warning(item.range(), "Only one featureNames block is allowed, it must preceed all rules, and it is only valid in features ss01-ss20");
-            } else if let Some(node) = typed::FeatureRef::cast(item) {
+            } else if let Some(_node) = typed::FeatureNames::cast(&item) {
+                self.warning(DiagnosticCode::MisplacedStatement, item.range(), "Only one featureNames block is allowed, it must preceed all rules, and it is only valid in features ss01-ss20");
+            } else if let Some(node) = typed::FeatureRef::cast(&item) {
                 self.error(
+                    DiagnosticCode::MisplacedStatement,
                     node.keyword().range(),
                     "feature reference only valid in 'aalt' feature",
                 );
             } else {
                 self.error(
+                    DiagnosticCode::Internal,
                     item.range(),
                     format!("unhandled item '{}' in feature", item.kind()),
                 );
@@ -735,32 +1196,45 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             Kind::ParamUiLabelNameIdKw,
         ] {
             if node.find_node(kind).is_none() {
-                self.warning(node.keyword().range(), format!("missing '{kind}' node"));
+                self.warning(
+                    DiagnosticCode::MissingStatement,
+                    node.keyword().range(),
+                    format!("missing '{kind}' node"),
+                );
             }
         }
     }
 
     fn validate_aalt_feature(&mut self, node: &typed::Feature) {
         for item in node.statements() {
-            if let Some(node) = typed::GsubStatement::cast(item) {
+            if self.skip_malformed(&item) {
+                continue;
+            }
+            if let Some(node) = typed::GsubStatement::cast(&item) {
                 match node {
                     typed::GsubStatement::Type1(_) | typed::GsubStatement::Type3(_) => {
                         self.validate_gsub_statement(&node)
                     }
                     _ => self.error(
+                        DiagnosticCode::MisplacedStatement,
                         node.range(),
                         "only Single and Alternate rules allowed in aalt feature",
                     ),
                 }
-            } else if let Some(node) = typed::FeatureRef::cast(item) {
+            } else if let Some(node) = typed::FeatureRef::cast(&item) {
                 let tag = node.feature();
                 let range = tag.range();
                 let raw_tag = tag.to_raw();
                 if self.aalt_referenced_features.insert(raw_tag, tag).is_some() {
-                    self.warning(range, "feature already declared")
+                    self.warning(
+                        DiagnosticCode::DuplicateStatement,
+                        range,
+                        "feature already declared",
+                    )
                 }
             } else if !item.kind().is_trivia() {
                 self.error(
+                    DiagnosticCode::MisplacedStatement,
                     item.range(),
                     "aalt can only contain feature names and single or alternate sub rules.",
                 );
@@ -772,19 +1246,24 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let mut param = None;
         let mut menu_name_count = 0;
         for item in node.statements() {
-            if let Some(node) = typed::Parameters::cast(item) {
+            if self.skip_malformed(&item) {
+                continue;
+            }
+            if let Some(node) = typed::Parameters::cast(&item) {
                 if param.is_some() {
                     self.error(
+                        DiagnosticCode::DuplicateStatement,
                         node.range(),
                         "size feature can have only one 'parameters' statement",
                     );
                 }
                 param = Some(node);
-            } else if let Some(node) = typed::SizeMenuName::cast(item) {
+            } else if let Some(node) = typed::SizeMenuName::cast(&item) {
                 self.validate_name_spec(&node.spec());
                 menu_name_count += 1;
             } else if !item.kind().is_trivia() {
                 self.error(
+                    DiagnosticCode::MisplacedStatement,
                     item.range(),
                     "size can only contain feature names and single or alternate sub rules.",
                 );
@@ -793,6 +1272,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
 
         match param {
             None => self.error(
+                DiagnosticCode::MissingStatement,
                 node.tag().range(),
                 "size feature must include a 'parameters' statement",
             ),
@@ -804,6 +1284,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 {
                     //TODO: better diagnostics
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         param.range(),
                         "if subfamily is omitted, there must be no 'sizemenuname' statements",
                     );
@@ -816,6 +1297,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let name = node.label();
         if in_feature == Some(tags::AALT) || in_feature == Some(tags::SIZE) {
             self.error(
+                DiagnosticCode::MisplacedStatement,
                 name.range(),
                 format!(
                     "lookups are not allowed in '{}' feature",
@@ -829,17 +1311,26 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         // and not anywhere else. Instead of a bool we store the decl range,
         // for error reporting
         let mut has_reset_lookup_flag = None;
-        if let Some(_prev) = self.lookup_defs.insert(name.text.clone(), name.clone()) {
-            //TODO: annotate with previous location
+        // a lookup defined inside a feature is used by that feature
+        if in_feature.is_some() {
+            self.used_lookups.insert(name.text.clone());
+        }
+        if let Some(prev) = self.lookup_defs.insert(name.text.clone(), name.clone()) {
             self.error(
+                DiagnosticCode::DuplicateDefinition,
                 name.range(),
                 format!("A lookup named '{}' has already been defined", name.text),
             );
+            self.related(prev.range(), "previous definition");
         }
         for item in node.statements() {
+            if self.skip_malformed(&item) {
+                continue;
+            }
             if item.kind().is_rule() {
                 if let Some(lookup_flag) = has_reset_lookup_flag.take() {
                     self.error(
+                        DiagnosticCode::InconsistentLookup,
                         lookup_flag,
                         "all rules in named lookup must have same lookup flags",
                     );
@@ -851,6 +1342,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     Some(Kind::GsubType1 | Kind::GsubType4)
                         if matches!(item.kind(), Kind::GsubType1 | Kind::GsubType4) => {}
                     Some(kind) if kind != item.kind() => self.error(
+                        DiagnosticCode::InconsistentLookup,
                         item.range(),
                         format!(
                             "multiple rule types in lookup block (saw '{}' after '{}')",
@@ -864,43 +1356,47 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             if item.kind() == Kind::ScriptNode || item.kind() == Kind::LanguageNode {
                 if in_feature.is_none() {
                     self.error(
+                        DiagnosticCode::MisplacedStatement,
                         item.range(),
                         "script and language statements not allowed in standalone lookup blocks",
                     );
                 }
             } else if item.kind() == Kind::SubtableNode {
                 // lgtm
-            } else if let Some(node) = typed::LookupRef::cast(item) {
+            } else if let Some(node) = typed::LookupRef::cast(&item) {
                 if in_feature.is_none() {
                     //TODO: verify that this is accurate
                     self.warning(
+                        DiagnosticCode::IgnoredStatement,
                         node.range(),
                         "lookup reference outside of feature is ignored",
                     );
                 }
                 self.validate_lookup_ref(&node);
-            } else if let Some(node) = typed::LookupBlock::cast(item) {
+            } else if let Some(node) = typed::LookupBlock::cast(&item) {
                 self.error(
+                    DiagnosticCode::MisplacedStatement,
                     node.keyword().range(),
                     "lookup blocks cannot contain other blocks",
                 );
-            } else if let Some(node) = typed::LookupFlag::cast(item) {
+            } else if let Some(node) = typed::LookupFlag::cast(&item) {
                 if kind.is_some() {
                     has_reset_lookup_flag = Some(node.range());
                 }
                 self.validate_lookupflag(&node);
-            } else if let Some(node) = typed::GsubStatement::cast(item) {
+            } else if let Some(node) = typed::GsubStatement::cast(&item) {
                 self.validate_gsub_statement(&node);
-            } else if let Some(node) = typed::GposStatement::cast(item) {
+            } else if let Some(node) = typed::GposStatement::cast(&item) {
                 self.validate_gpos_statement(&node);
-            } else if let Some(node) = typed::GlyphClassDef::cast(item) {
+            } else if let Some(node) = typed::GlyphClassDef::cast(&item) {
                 self.validate_glyph_class_def(&node);
-            } else if let Some(node) = typed::MarkClassDef::cast(item) {
+            } else if let Some(node) = typed::MarkClassDef::cast(&item) {
                 self.validate_mark_class_def(&node);
             } else if item.kind() == Kind::Semi {
                 // continue
             } else {
                 self.error(
+                    DiagnosticCode::Internal,
                     item.range(),
                     format!("unhandled item '{}' in lookup block", item.kind()),
                 );
@@ -936,9 +1432,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     self.validate_anchor(&mark.anchor());
                     match mark.mark_class_name() {
                         Some(name) => self.validate_mark_class(&name),
-                        None => {
-                            self.error(mark.range(), "mark-to-base attachments should not be null")
-                        }
+                        None => self.error(
+                            DiagnosticCode::InvalidAnchor,
+                            mark.range(),
+                            "mark-to-base attachments should not be null",
+                        ),
                     }
                 }
             }
@@ -954,6 +1452,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                             None => {
                                 if anchor.null().is_none() {
                                     self.error(
+                                        DiagnosticCode::InvalidAnchor,
                                         anchor.range(),
                                         "non-NULL anchor must specify mark class",
                                     );
@@ -970,9 +1469,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     self.validate_anchor(&mark.anchor());
                     match mark.mark_class_name() {
                         Some(name) => self.validate_mark_class(&name),
-                        None => {
-                            self.error(mark.range(), "mark-to-mark attachments should not be null")
-                        }
+                        None => self.error(
+                            DiagnosticCode::InvalidAnchor,
+                            mark.range(),
+                            "mark-to-mark attachments should not be null",
+                        ),
                     }
                 }
             }
@@ -1003,6 +1504,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 self.validate_lookup_ref(&lookup);
                 if seen_inline {
                     self.error(
+                        DiagnosticCode::InvalidRule,
                         lookup.range(),
                         "rule cannot have both explicit lookups and inline position values",
                     );
@@ -1012,6 +1514,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             if let Some(value) = item.valuerecord() {
                 if seen_lookup {
                     self.error(
+                        DiagnosticCode::InvalidRule,
                         value.range(),
                         "rule cannot have both inline rules and explicit lookups",
                     );
@@ -1040,12 +1543,16 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 }
                 if count < 2 {
                     let range = range_for_iter(rule.replacement()).unwrap_or_else(|| rule.range());
-                    self.error(range, "sequence must contain at least two items");
+                    self.error(
+                        DiagnosticCode::InvalidRule,
+                        range,
+                        "sequence must contain at least two items",
+                    );
                 }
             }
             typed::GsubStatement::Type3(rule) => {
                 self.validate_glyph(&rule.target());
-                self.validate_glyph_class(&rule.alternates(), false);
+                self.validate_glyph_class(&rule.alternates());
             }
             typed::GsubStatement::Type4(rule) => {
                 let mut count = 0;
@@ -1055,7 +1562,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 }
                 if count < 2 {
                     let range = range_for_iter(rule.target()).unwrap_or_else(|| rule.range());
-                    self.error(range, "sequence must contain at least two items");
+                    self.error(
+                        DiagnosticCode::InvalidRule,
+                        range,
+                        "sequence must contain at least two items",
+                    );
                 }
                 self.validate_glyph(&rule.replacement());
             }
@@ -1080,6 +1591,17 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                         if item.is_class() {
                             inline_class_sub = true;
                         }
+                        // deletion is a multiple substitution with an empty
+                        // output, which can only replace a single glyph
+                        if let typed::GlyphOrClass::Null(null) = &item
+                            && rule.input().items().nth(1).is_some()
+                        {
+                            self.error(
+                                DiagnosticCode::InvalidRule,
+                                null.range(),
+                                "NULL can only replace a single marked glyph or class",
+                            );
+                        }
                     }
                 }
 
@@ -1087,7 +1609,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 for (i, item) in input_seq.items().enumerate() {
                     let target = item.target();
                     if i == 0 && inline_class_sub && !target.is_class() {
-                        self.error(
+                        self.error(DiagnosticCode::InvalidRule,
                             input_seq.range(),
                             "if replacing by glyph class, input sequence must be a single glyph class",
                         );
@@ -1096,6 +1618,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     for lookup in item.lookups() {
                         if has_inline_rule {
                             self.error(
+                                DiagnosticCode::InvalidRule,
                                 lookup.range(),
                                 "named lookup not allowed in statement that includes inline rule",
                             );
@@ -1112,6 +1635,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 for (i, item) in rule.input().items().enumerate() {
                     if i > 0 {
                         self.error(
+                            DiagnosticCode::InvalidRule,
                             item.range(),
                             "rsub rules can have only one item in the input sequence",
                         );
@@ -1120,16 +1644,20 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                         self.validate_glyph_or_class(&target);
                         input_class = item.target().is_class();
                         if let Some(lookup) = item.lookups().next() {
-                            self.error(lookup.range(), "explicit lookups in rsub rules are not supported, although they should be. Please file an issue at https://github.com/cmyr/fea-rs/issues");
+                            self.error(DiagnosticCode::Unsupported, lookup.range(), "explicit lookups in rsub rules are not supported, although they should be. Please file an issue at https://github.com/cmyr/fea-rs/issues");
                         }
                     }
                 }
                 if let Some(inline) = rule.inline_rule() {
                     if let Some(class) = inline.replacement_class() {
                         debug_assert!(inline.replacement_glyphs().next().is_none());
-                        self.validate_glyph_class(&class, true);
+                        self.validate_glyph_class(&class);
                         if !input_class {
-                            self.error(class.range(), "class can only substitute another class");
+                            self.error(
+                                DiagnosticCode::InvalidRule,
+                                class.range(),
+                                "class can only substitute another class",
+                            );
                         }
                     } else if let Some(glyph) = inline.replacement_glyphs().next() {
                         self.validate_glyph(&glyph);
@@ -1153,15 +1681,24 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         if let Some(number) = node.number() {
             match number.text().parse::<u16>() {
                 Ok(val) => {
-                    if val > 0xff {
-                        self.warning(
+                    if val & LookupFlag::USE_MARK_FILTERING_SET.to_bits() != 0 {
+                        self.error(DiagnosticCode::InvalidLookupFlag,
+                            number.range(),
+                            "UseMarkFilteringSet requires a glyph class, and cannot be set by a number",
+                        );
+                    } else if val > 0xff {
+                        self.warning(DiagnosticCode::InvalidLookupFlag,
                         number.range(),
                         "the high byte of lookupflag literals is not portable and will be ignored.",
                     );
                     }
                 }
                 Err(_) => {
-                    self.error(number.range(), "value must be a positive 16 bit integer");
+                    self.error(
+                        DiagnosticCode::InvalidLookupFlag,
+                        number.range(),
+                        "value must be a positive 16 bit integer",
+                    );
                 }
             }
             return;
@@ -1185,8 +1722,12 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 Kind::MarkAttachmentTypeKw if !mark_set => {
                     mark_set = true;
                     match iter.next().and_then(typed::GlyphClass::cast) {
-                        Some(node) => self.validate_glyph_class(&node, true),
+                        Some(node) => {
+                            self.validate_glyph_class(&node);
+                            self.validate_mark_attach_class(&node);
+                        }
                         None => self.error(
+                            DiagnosticCode::InvalidLookupFlag,
                             next.range(),
                             "MarkAttachmentType should be followed by glyph class",
                         ),
@@ -1195,10 +1736,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 Kind::UseMarkFilteringSetKw if !filter_set => {
                     filter_set = true;
                     match iter.next().and_then(typed::GlyphClass::cast) {
-                        Some(node) => self.validate_glyph_class(&node, true),
+                        Some(node) => self.validate_glyph_class(&node),
                         None => self.error(
+                            DiagnosticCode::InvalidLookupFlag,
                             next.range(),
-                            "MarkAttachmentType should be followed by glyph class",
+                            "UseMarkFilteringSet should be followed by glyph class",
                         ),
                     }
                 }
@@ -1207,21 +1749,104 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                 | Kind::IgnoreMarksKw
                 | Kind::IgnoreLigaturesKw
                 | Kind::MarkAttachmentTypeKw
-                | Kind::UseMarkFilteringSetKw => {
-                    self.error(next.range(), "duplicate value in lookupflag")
-                }
+                | Kind::UseMarkFilteringSetKw => self.error(
+                    DiagnosticCode::InvalidLookupFlag,
+                    next.range(),
+                    "duplicate value in lookupflag",
+                ),
+
+                _ => self.error(
+                    DiagnosticCode::InvalidLookupFlag,
+                    next.range(),
+                    "invalid lookupflag value",
+                ),
+            }
+        }
+    }
+
+    // the glyph sets of the classes used with MarkAttachmentType must not
+    // overlap, and there can be at most 15 different classes.
+    fn validate_mark_attach_class(&mut self, node: &typed::GlyphClass) {
+        const MAX_MARK_ATTACH_CLASSES: usize = 15;
+
+        let Some(glyphs) = self.glyph_class_set(node) else {
+            // a missing glyph has already been reported
+            return;
+        };
+        if self.mark_attach_classes.contains(&glyphs) {
+            return;
+        }
+        if self.mark_attach_classes.len() == MAX_MARK_ATTACH_CLASSES {
+            return self.error(
+                DiagnosticCode::LimitExceeded,
+                node.range(),
+                format!("at most {MAX_MARK_ATTACH_CLASSES} MarkAttachmentType classes are allowed"),
+            );
+        }
+        let overlap = glyphs.iter().find(|gid| {
+            self.mark_attach_classes
+                .iter()
+                .any(|existing| existing.contains(gid))
+        });
+        if let Some(gid) = overlap {
+            let reverse_map = self.glyph_map.reverse_map();
+            let name = reverse_map.get(gid).unwrap();
+            return self.error(
+                DiagnosticCode::ClassConflict,
+                node.range(),
+                format!("glyph '{name}' is already in a different MarkAttachmentType class"),
+            );
+        }
+        self.mark_attach_classes.push(glyphs);
+    }
+
+    /// The glyphs in this class, or `None` if some member is not in the font.
+    fn glyph_class_set(&self, node: &typed::GlyphClass) -> Option<BTreeSet<GlyphId16>> {
+        match node {
+            typed::GlyphClass::Literal(lit) => self
+                .class_literal_glyphs(lit)
+                .map(|glyphs| glyphs.into_iter().collect()),
+            typed::GlyphClass::Named(name) => self
+                .named_class_glyphs(name)
+                .map(|glyphs| glyphs.iter().collect()),
+        }
+    }
 
-                _ => self.error(next.range(), "invalid lookupflag value"),
+    fn glyph_or_class_glyphs(&self, node: &typed::GlyphOrClass) -> Option<Vec<GlyphId16>> {
+        match node {
+            typed::GlyphOrClass::Glyph(name) => {
+                self.glyph_map.get(name.text()).map(|gid| vec![gid])
             }
+            typed::GlyphOrClass::Cid(cid) => self.glyph_map.get(&cid.parse()).map(|gid| vec![gid]),
+            typed::GlyphOrClass::Class(class) => self.class_literal_glyphs(class),
+            typed::GlyphOrClass::NamedClass(name) => self
+                .named_class_glyphs(name)
+                .map(|glyphs| glyphs.items().to_vec()),
+            typed::GlyphOrClass::Null(_) => None,
         }
     }
 
+    // uses the same resolution as compilation, so that we agree on the members
+    fn class_literal_glyphs(&self, node: &typed::GlyphClassLiteral) -> Option<Vec<GlyphId16>> {
+        glyph_class::resolve_class_literal(self.glyph_map, node, |name| {
+            self.named_class_glyphs(name)
+        })
+    }
+
+    fn named_class_glyphs(&self, node: &typed::GlyphClassName) -> Option<GlyphClass> {
+        self.glyph_class_members
+            .get(node.text())
+            .or_else(|| self.mark_class_members.get(node.text()))
+            .cloned()
+            .flatten()
+    }
+
     fn validate_glyph_or_class(&mut self, node: &typed::GlyphOrClass) {
         match node {
             typed::GlyphOrClass::Glyph(name) => self.validate_glyph_name(name),
             typed::GlyphOrClass::Cid(cid) => self.validate_cid(cid),
-            typed::GlyphOrClass::Class(class) => self.validate_glyph_class_literal(class, true),
-            typed::GlyphOrClass::NamedClass(name) => self.validate_glyph_class_ref(name, true),
+            typed::GlyphOrClass::Class(class) => self.validate_glyph_class_literal(class),
+            typed::GlyphOrClass::NamedClass(name) => self.validate_glyph_class_ref(name),
             typed::GlyphOrClass::Null(_) => (),
         }
     }
@@ -1230,51 +1855,57 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         match node {
             typed::Glyph::Named(name) => self.validate_glyph_name(name),
             typed::Glyph::Cid(cid) => self.validate_cid(cid),
-            typed::Glyph::Null(_) => (),
+            typed::Glyph::Null(null) => self.error(
+                DiagnosticCode::InvalidRule,
+                null.range(),
+                "NULL is not a valid glyph here",
+            ),
         }
     }
 
-    fn validate_glyph_class(&mut self, node: &typed::GlyphClass, accept_mark_class: bool) {
+    fn validate_glyph_class(&mut self, node: &typed::GlyphClass) {
         match node {
-            typed::GlyphClass::Literal(lit) => {
-                self.validate_glyph_class_literal(lit, accept_mark_class)
-            }
-            typed::GlyphClass::Named(name) => {
-                self.validate_glyph_class_ref(name, accept_mark_class)
-            }
+            typed::GlyphClass::Literal(lit) => self.validate_glyph_class_literal(lit),
+            typed::GlyphClass::Named(name) => self.validate_glyph_class_ref(name),
         }
     }
 
-    fn validate_glyph_class_literal(
-        &mut self,
-        node: &typed::GlyphClassLiteral,
-        accept_mark_class: bool,
-    ) {
+    fn validate_glyph_class_literal(&mut self, node: &typed::GlyphClassLiteral) {
         for item in node.items() {
-            if let Some(id) = typed::GlyphName::cast(item) {
+            if let Some(id) = typed::GlyphName::cast(&item) {
                 self.validate_glyph_name(&id);
-            } else if let Some(id) = typed::Cid::cast(item) {
+            } else if let Some(id) = typed::Cid::cast(&item) {
                 self.validate_cid(&id);
-            } else if let Some(range) = typed::GlyphRange::cast(item) {
+            } else if let Some(range) = typed::GlyphRange::cast(&item) {
                 self.validate_glyph_range(&range);
-            } else if let Some(alias) = typed::GlyphClassName::cast(item) {
-                self.validate_glyph_class_ref(&alias, accept_mark_class);
+            } else if let Some(alias) = typed::GlyphClassName::cast(&item) {
+                self.validate_glyph_class_ref(&alias);
                 // these two cases indicate existing errors
             } else if !item.kind().is_trivia()
                 && item.kind() != Kind::Ident
                 && item.kind() != Kind::GlyphNameOrRange
             {
-                self.warning(item.range(), format!("unexpected item {}", item.kind()));
+                self.warning(
+                    DiagnosticCode::Internal,
+                    item.range(),
+                    format!("unexpected item {}", item.kind()),
+                );
             }
         }
     }
 
     fn validate_glyph_name(&mut self, name: &typed::GlyphName) {
         if self.glyph_map.get(name.text()).is_none() {
-            self.error(name.range(), "glyph not in font");
+            let candidates = self.glyph_map.names().map(|name| name.as_str());
+            let message = match closest_match(name.text(), candidates) {
+                Some(suggestion) => format!("glyph not in font, did you mean '{suggestion}'?"),
+                None => "glyph not in font".to_string(),
+            };
+            self.error(DiagnosticCode::UndefinedGlyph, name.range(), message);
         }
         if name.text() == ".null" {
             self.warning(
+                DiagnosticCode::NonStandardSyntax,
                 name.range(),
                 "'.null' is not a valid glyph name, and may \
                 not be supported on all compilers. You should prefer the name 'NULL', and \
@@ -1285,22 +1916,41 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
 
     fn validate_cid(&mut self, cid: &typed::Cid) {
         if self.glyph_map.get(&cid.parse()).is_none() {
-            self.error(cid.range(), "CID not in font");
+            self.error(
+                DiagnosticCode::UndefinedGlyph,
+                cid.range(),
+                "CID not in font",
+            );
         }
     }
 
-    fn validate_glyph_class_ref(&mut self, node: &typed::GlyphClassName, accept_mark_class: bool) {
-        if accept_mark_class && self.mark_class_defs.contains(node.text()) {
-            return;
-        }
-        if !self.glyph_class_defs.contains_key(node.text()) {
-            self.error(node.range(), "undefined glyph class");
+    // a mark class name can be used anywhere a glyph class is legal
+    fn validate_glyph_class_ref(&mut self, node: &typed::GlyphClassName) {
+        self.used_glyph_classes.insert(node.text().clone());
+        if !self.glyph_class_defs.contains_key(node.text())
+            && !self.mark_class_defs.contains(node.text())
+        {
+            let candidates = self
+                .glyph_class_defs
+                .keys()
+                .chain(self.mark_class_defs.iter())
+                .map(SmolStr::as_str);
+            let message = match closest_match(node.text(), candidates) {
+                Some(suggestion) => format!("undefined glyph class, did you mean '{suggestion}'?"),
+                None => "undefined glyph class".to_string(),
+            };
+            self.error(DiagnosticCode::UndefinedClass, node.range(), message);
         }
     }
 
     fn validate_lookup_ref(&mut self, node: &typed::LookupRef) {
+        self.used_lookups.insert(node.label().text.clone());
         if !self.lookup_defs.contains_key(&node.label().text) {
-            self.error(node.label().range(), "lookup is not defined");
+            self.error(
+                DiagnosticCode::UndefinedLookup,
+                node.label().range(),
+                "lookup is not defined",
+            );
         }
     }
 
@@ -1310,31 +1960,38 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
 
         match (start.kind, end.kind) {
             (Kind::Cid, Kind::Cid) => {
-                if let Err(err) = glyph_range::cid(start, end, |cid| {
-                    if self.glyph_map.get(&cid).is_none() {
-                        // this is techincally allowed, but we error for now
-                        self.warning(
-                            range.range(),
-                            format!("Range member '{cid}' does not exist in font"),
-                        );
-                    }
+                // CID ranges may be sparse; we only complain if they're empty
+                let mut any_present = false;
+                if let Err(err) = glyph_range::cid(&start, &end, |cid| {
+                    any_present |= self.glyph_map.contains(&cid);
                 }) {
-                    self.error(range.range(), err);
+                    self.error(DiagnosticCode::InvalidGlyphRange, range.range(), err);
+                } else if !any_present {
+                    self.error(
+                        DiagnosticCode::UndefinedGlyph,
+                        range.range(),
+                        "no member of CID range exists in font",
+                    );
                 }
             }
             (Kind::GlyphName, Kind::GlyphName) => {
-                if let Err(err) = glyph_range::named(start, end, |name| {
+                if let Err(err) = glyph_range::named(&start, &end, |name| {
                     if self.glyph_map.get(name).is_none() {
                         self.warning(
+                            DiagnosticCode::UndefinedGlyph,
                             range.range(),
                             format!("Range member '{name}' does not exist in font"),
                         );
                     }
                 }) {
-                    self.error(range.range(), err);
+                    self.error(DiagnosticCode::InvalidGlyphRange, range.range(), err);
                 }
             }
-            (_, _) => self.error(range.range(), "Invalid types in glyph range"),
+            (_, _) => self.error(
+                DiagnosticCode::InvalidGlyphRange,
+                range.range(),
+                "Invalid types in glyph range",
+            ),
         }
     }
 
@@ -1342,24 +1999,74 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         if let Some(name) = node.named()
             && !self.value_record_defs.contains_key(&name.text)
         {
-            self.error(name.range(), "undefined value record name");
+            self.error(
+                DiagnosticCode::UndefinedValueRecord,
+                name.range(),
+                "undefined value record name",
+            );
         }
 
         for metric in node.all_metrics() {
             self.validate_metric(&metric);
         }
+        for device in node.device().into_iter().flatten() {
+            self.validate_device(&device);
+        }
+    }
+
+    fn validate_device(&mut self, device: &typed::Device) {
+        let mut seen = HashSet::new();
+        for (ppem, pixels) in device.entries() {
+            match ppem.parse_unsigned() {
+                None => self.error(
+                    DiagnosticCode::ValueOutOfRange,
+                    ppem.range(),
+                    "ppem size must be a non-negative integer",
+                ),
+                Some(size) if !seen.insert(size) => self.error(
+                    DiagnosticCode::DuplicateStatement,
+                    ppem.range(),
+                    "duplicate ppem size in device table",
+                ),
+                Some(_) => (),
+            }
+            if i8::try_from(pixels.parse_signed()).is_err() {
+                self.error(
+                    DiagnosticCode::ValueOutOfRange,
+                    pixels.range(),
+                    "device delta must be in range [-128, 127]",
+                );
+            }
+        }
     }
 
     fn validate_anchor(&mut self, anchor: &typed::Anchor) {
         if let Some(name) = anchor.name()
             && !self.anchor_defs.contains_key(&name.text)
         {
-            self.error(name.range(), "undefined anchor name");
+            self.error(
+                DiagnosticCode::UndefinedAnchor,
+                name.range(),
+                "undefined anchor name",
+            );
         }
         if let Some((one, two)) = anchor.coords() {
             self.validate_metric(&one);
             self.validate_metric(&two);
         }
+        if let Some(point) = anchor
+            .contourpoint()
+            .filter(|point| point.parse_unsigned().is_none())
+        {
+            self.error(
+                DiagnosticCode::ValueOutOfRange,
+                point.range(),
+                "contourpoint must be a non-negative integer",
+            );
+        }
+        for device in anchor.devices().into_iter().flat_map(|(x, y)| [x, y]) {
+            self.validate_device(&device);
+        }
     }
 
     fn validate_metric(&mut self, metric: &typed::Metric) {
@@ -1375,25 +2082,43 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     fn validate_variable_metric(&mut self, metric: &typed::VariableMetric) {
         let Some(var_info) = self.variation_info else {
             self.error(
+                DiagnosticCode::RequiresVariableFont,
                 metric.range(),
                 "variable metrics only supported in variable font",
             );
             return;
         };
 
+        let mut seen_locations = HashSet::new();
         for location_val in metric.location_values() {
+            let mut location = Vec::new();
             for item in location_val.location().items() {
-                let Some((_, axis)) = var_info.axis(item.axis_tag().to_raw()) else {
-                    self.error(item.axis_tag().range(), "unknown axis");
+                let tag = item.axis_tag().to_raw();
+                let val = item.value().parse();
+                if location.iter().any(|(prev, _)| *prev == tag) {
+                    self.error(
+                        DiagnosticCode::DuplicateStatement,
+                        item.axis_tag().range(),
+                        "axis already specified in location",
+                    );
+                    continue;
+                }
+                location.push((tag, val));
+                let Some((_, axis)) = var_info.axis(tag) else {
+                    self.error(
+                        DiagnosticCode::UndefinedAxis,
+                        item.axis_tag().range(),
+                        "unknown axis",
+                    );
                     continue;
                 };
-                let val = item.value().parse();
                 match val {
                     super::AxisLocation::User(val) => {
                         let min = axis.min.into_inner().0;
                         let max = axis.max.into_inner().0;
                         if val.0 < min || val.0 > max {
                             self.error(
+                                DiagnosticCode::ValueOutOfRange,
                                 item.value().range(),
                                 format!("value exceeds expected range ({min}, {max})"),
                             );
@@ -1403,6 +2128,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     super::AxisLocation::Normalized(val) => {
                         if val.0 < -1.0 || val.0 > 1.0 {
                             self.error(
+                                DiagnosticCode::ValueOutOfRange,
                                 item.value().range(),
                                 "normalized value should be in range (-1.0, 1.0)",
                             );
@@ -1410,6 +2136,14 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     }
                 }
             }
+            location.sort_unstable_by_key(|(tag, _)| *tag);
+            if !seen_locations.insert(location) {
+                self.error(
+                    DiagnosticCode::DuplicateStatement,
+                    location_val.location().range(),
+                    "location already has a value in this metric",
+                );
+            }
         }
     }
 
@@ -1425,7 +2159,11 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         let mut iter = expr.items();
         match iter.next() {
             Some(typed::GlyphsAppExprItem::Operator(op)) => {
-                return self.error(op.range(), "expression must begin with value");
+                return self.error(
+                    DiagnosticCode::InvalidValue,
+                    op.range(),
+                    "expression must begin with value",
+                );
             }
             Some(typed::GlyphsAppExprItem::Ident(ident)) => {
                 self.validate_glyphsapp_number_name(&ident)
@@ -1435,14 +2173,28 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
         while let Some(first) = iter.next() {
             match (&first, iter.next()) {
                 (typed::GlyphsAppExprItem::Operator(_), None) => {
-                    return self.error(first.range(), "expression should end in value");
+                    return self.error(
+                        DiagnosticCode::InvalidValue,
+                        first.range(),
+                        "expression should end in value",
+                    );
                 }
                 (
                     typed::GlyphsAppExprItem::Operator(_),
                     Some(typed::GlyphsAppExprItem::Operator(op)),
-                ) => return self.error(op.range(), "operator can only follow value"),
+                ) => {
+                    return self.error(
+                        DiagnosticCode::InvalidValue,
+                        op.range(),
+                        "operator can only follow value",
+                    );
+                }
                 (typed::GlyphsAppExprItem::Ident(_) | typed::GlyphsAppExprItem::Lit(_), _) => {
-                    return self.error(first.range(), "value cannot follow other value");
+                    return self.error(
+                        DiagnosticCode::InvalidValue,
+                        first.range(),
+                        "value cannot follow other value",
+                    );
                 }
                 (
                     typed::GlyphsAppExprItem::Operator(_),
@@ -1456,6 +2208,7 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     fn validate_glyphsapp_number_name(&mut self, ident: &typed::GlyphsAppNumberName) {
         let Some(var_info) = self.variation_info else {
             self.error(
+                DiagnosticCode::RequiresVariableFont,
                 ident.range(),
                 "glyphsapp number values only supported in variable font",
             );
@@ -1468,8 +2221,54 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
             self.glyphs_app_number_idents.insert(ident.text().clone());
             return;
         }
-        self.error(ident.range(), "unknown number name");
+        self.error(
+            DiagnosticCode::InvalidValue,
+            ident.range(),
+            "unknown number name",
+        );
+    }
+}
+
+/// Whether `tag` is letters of the expected case or digits, optionally padded
+/// with trailing spaces (as in 'nko ' or 'TRK ').
+fn is_well_formed_tag(tag: Tag, is_expected_case: impl Fn(u8) -> bool) -> bool {
+    let bytes = tag.into_bytes();
+    let len = bytes.iter().rposition(|b| *b != b' ').map_or(0, |i| i + 1);
+    len > 0
+        && bytes[..len]
+            .iter()
+            .all(|b| is_expected_case(*b) || b.is_ascii_digit())
+}
+
+/// Return the candidate closest to `name`, if any is close enough to be a likely typo.
+///
+/// Ties are broken alphabetically, so that the result does not depend on the
+/// iteration order of `candidates`.
+fn closest_match<'b>(name: &str, candidates: impl Iterator<Item = &'b str>) -> Option<&'b str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .filter_map(|candidate| {
+            let distance = edit_distance(name, candidate);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut current);
     }
+    prev[b.len()]
 }
 
 fn range_for_iter<T: AstNode>(mut iter: impl Iterator<Item = T>) -> Option<Range<usize>> {
@@ -210,7 +210,7 @@ impl MockVariationInfo {
 
 #[cfg(any(test, feature = "test", feature = "cli"))]
 impl VariationInfo for MockVariationInfo {
-    type Error = NopError;
+    type Error = fontdrasil::variations::DeltaError;
 
     fn axis(&self, axis_tag: Tag) -> Option<(usize, &Axis)> {
         self.axes.iter().enumerate().find_map(|(i, axis)| {
@@ -224,9 +224,29 @@ impl VariationInfo for MockVariationInfo {
 
     fn resolve_variable_metric(
         &self,
-        _locations: &HashMap<NormalizedLocation, i16>,
+        locations: &HashMap<NormalizedLocation, i16>,
     ) -> Result<(i16, Vec<(VariationRegion, i16)>), Self::Error> {
-        Ok(Default::default())
+        use fontdrasil::{types::Axes, variations::VariationModel};
+        use write_fonts::OtRound;
+
+        let point_seqs: HashMap<_, _> = locations
+            .iter()
+            .map(|(pos, value)| (pos.clone(), vec![*value as f64]))
+            .collect();
+        let axes = Axes::new(self.axes.clone());
+        let model = VariationModel::new(point_seqs.keys().cloned().collect(), axes.axis_order());
+
+        let mut default = 0;
+        let mut deltas = Vec::new();
+        for (region, values) in model.deltas(&point_seqs)? {
+            let value: i16 = values[0].ot_round();
+            if region.is_default() {
+                default = value;
+            } else {
+                deltas.push((region.to_write_fonts_variation_region(&axes), value));
+            }
+        }
+        Ok((default, deltas))
     }
 
     fn axis_count(&self) -> u16 {
@@ -236,7 +256,7 @@ impl VariationInfo for MockVariationInfo {
     fn resolve_glyphs_number_value(
         &self,
         _: &str,
-    ) -> Result<HashMap<NormalizedLocation, f64>, NopError> {
+    ) -> Result<HashMap<NormalizedLocation, f64>, Self::Error> {
         Ok(Default::default())
     }
 }
@@ -0,0 +1,1144 @@
+//! Decompiling binary layout tables back into FEA.
+//!
+//! The main entry point is [`decompile`], which reads the GSUB, GPOS and GDEF
+//! tables of a font and writes a feature file that compiles to equivalent
+//! tables. This is useful for round-trip testing of the compiler, and for
+//! recovering editable sources from existing binaries.
+//!
+//! [`decompile_feature_writer`] does the same for the lookups added by a
+//! [`FeatureProvider`], so that rules generated in code can be reviewed as FEA.
+//!
+//! Every lookup is written as a standalone named lookup block, and features
+//! then reference these lookups for each script and language that uses them.
+//! Lookups are written in lookup list order, except that a lookup that is only
+//! used by contextual rules is moved before the first lookup that uses it,
+//! since FEA requires a named lookup to be defined before it is referenced.
+//! Lookups are named for their position in the output, so decompiling a font
+//! compiled from the output produces the same names.
+//!
+//! Some things have no FEA equivalent, and are skipped:
+//!
+//! - device tables and variation indices; only default values are written
+//! - feature parameters (such as those of the `size` or `ssXX` features)
+//! - feature variations
+//!
+//! If a contextual lookup refers to a later lookup that is also used directly by
+//! a feature, the lookups cannot be reordered without changing the order in
+//! which they are applied; this is reported as an error.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+};
+
+use indexmap::{IndexMap, IndexSet};
+use write_fonts::{
+    BuilderError,
+    read::{
+        FontRef, ReadError, TableProvider,
+        tables::{
+            gdef::{CaretValue, Gdef},
+            gpos::{AnchorTable, ValueRecord},
+            layout::{ClassDef, CoverageTable, FeatureList, LookupFlag, ScriptList},
+        },
+    },
+    types::{GlyphId16, Tag},
+};
+
+use crate::{
+    GlyphMap,
+    compile::{FeatureProvider, NopVariationInfo, error::CompilerError},
+};
+
+mod contextual;
+mod gpos;
+mod gsub;
+
+const GSUB: Tag = Tag::new(b"GSUB");
+const GPOS: Tag = Tag::new(b"GPOS");
+const AALT: Tag = Tag::new(b"aalt");
+const DFLT_SCRIPT: Tag = Tag::new(b"DFLT");
+const DFLT_LANG: Tag = Tag::new(b"dflt");
+const INDENT: &str = "    ";
+
+/// An error that occurs while decompiling.
+#[derive(Debug, thiserror::Error)]
+pub enum DecompileError {
+    /// Failed to read font data
+    #[error("Failed to read font data: '{0}'")]
+    ReadError(#[from] ReadError),
+    /// Failed to build the tables to decompile
+    #[error("Failed to build font: '{0}'")]
+    BuildError(#[from] BuilderError),
+    /// Failed to compile the lookups added by a feature writer
+    #[error("Failed to compile feature writer output: {0}")]
+    CompileError(#[from] CompilerError),
+    /// A glyph in the font is missing from the glyph map
+    #[error("Glyph id {0} is not in the glyph map")]
+    UnknownGlyph(GlyphId16),
+    /// A contextual lookup refers to a lookup that cannot be defined before it
+    #[error(
+        "{table} lookup {lookup} references lookup {target}, which cannot be defined before it"
+    )]
+    #[allow(missing_docs)]
+    ForwardLookupReference {
+        table: Tag,
+        lookup: u16,
+        target: u16,
+    },
+}
+
+/// Decompile the layout tables in `font` into FEA source.
+///
+/// The `glyph_map` is used to name glyphs, and must contain every glyph in
+/// the font; [`GlyphMap::from_font`] can be used to get one from the font
+/// itself.
+pub fn decompile(font: &FontRef, glyph_map: &GlyphMap) -> Result<String, DecompileError> {
+    let gdef = optional_table(font.gdef())?;
+    let mut decompiler = Decompiler::new(glyph_map, gdef);
+    if let Some(gsub) = optional_table(font.gsub())? {
+        let lookups = gsub
+            .lookup_list()?
+            .lookups()
+            .iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let references = lookups
+            .iter()
+            .map(|lookup| decompiler.gsub_lookup_references(lookup))
+            .collect::<Result<Vec<_>, _>>()?;
+        decompiler.table(
+            GSUB,
+            &gsub.script_list()?,
+            &gsub.feature_list()?,
+            &references,
+            |decompiler, i| decompiler.gsub_lookup(i, &lookups[i as usize]),
+            |decompiler, i| decompiler.aalt_rules(&lookups[i as usize]),
+        )?;
+    }
+    if let Some(gpos) = optional_table(font.gpos())? {
+        let lookups = gpos
+            .lookup_list()?
+            .lookups()
+            .iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let references = lookups
+            .iter()
+            .map(|lookup| decompiler.gpos_lookup_references(lookup))
+            .collect::<Result<Vec<_>, _>>()?;
+        decompiler.table(
+            GPOS,
+            &gpos.script_list()?,
+            &gpos.feature_list()?,
+            &references,
+            |decompiler, i| decompiler.gpos_lookup(i, &lookups[i as usize]),
+            |_, _| Ok(None),
+        )?;
+    }
+    decompiler.finish()
+}
+
+/// Write the lookups and features added by a [`FeatureProvider`] as FEA.
+///
+/// The provider's lookups are compiled on their own and then decompiled, so
+/// the output is subject to the same limitations as [`decompile`]. Since there
+/// is no FEA, the features are registered under the `DFLT dflt` language
+/// system unless the provider names others.
+pub fn decompile_feature_writer(
+    feature_writer: &impl FeatureProvider,
+    glyph_map: &GlyphMap,
+) -> Result<String, DecompileError> {
+    let (tree, _) = crate::parse::parse_string("");
+    let (compilation, _) = crate::compile::compile::<NopVariationInfo, _>(
+        &tree,
+        glyph_map,
+        None,
+        Some(feature_writer),
+        Default::default(),
+    )
+    .map_err(CompilerError::CompilationFail)?;
+    let bytes = compilation.to_binary(glyph_map)?;
+    decompile(&FontRef::new(&bytes)?, glyph_map)
+}
+
+pub(crate) fn optional_table<T>(table: Result<T, ReadError>) -> Result<Option<T>, ReadError> {
+    match table {
+        Ok(table) => Ok(Some(table)),
+        Err(ReadError::TableIsMissing(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+struct Decompiler<'a> {
+    names: HashMap<GlyphId16, String>,
+    num_glyphs: u16,
+    gdef: Option<Gdef<'a>>,
+    language_systems: IndexSet<(Tag, Tag)>,
+    // glyph class and markClass definitions
+    classes: String,
+    defined_classes: IndexSet<String>,
+    // the contents of each mark class, mapped to its name
+    mark_classes: HashMap<Vec<(GlyphId16, String)>, String>,
+    // the names of the lookups in the current table, by lookup index
+    lookup_names: HashMap<u16, String>,
+    lookups: String,
+    features: String,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(glyph_map: &GlyphMap, gdef: Option<Gdef<'a>>) -> Self {
+        let names = glyph_map
+            .reverse_map()
+            .into_iter()
+            .map(|(gid, ident)| {
+                let name = ident.to_string();
+                // names that would be parsed as keywords need to be escaped
+                if crate::parse::is_keyword(&name) || name == "NULL" {
+                    (gid, format!("\\{name}"))
+                } else {
+                    (gid, name)
+                }
+            })
+            .collect();
+        Decompiler {
+            names,
+            num_glyphs: glyph_map.len().try_into().unwrap_or(u16::MAX),
+            gdef,
+            language_systems: Default::default(),
+            classes: Default::default(),
+            defined_classes: Default::default(),
+            mark_classes: Default::default(),
+            lookup_names: Default::default(),
+            lookups: Default::default(),
+            features: Default::default(),
+        }
+    }
+
+    /// Decompile the lookups and features of a GSUB or GPOS table.
+    ///
+    /// `references` are the lookups referenced by each lookup's contextual
+    /// rules, and `write_lookup` writes the lookup at a given index.
+    /// `aalt_rules` returns the rules of a lookup if it can be written directly
+    /// in the 'aalt' feature block.
+    fn table(
+        &mut self,
+        table: Tag,
+        script_list: &ScriptList,
+        feature_list: &FeatureList,
+        references: &[Vec<u16>],
+        mut write_lookup: impl FnMut(&mut Self, u16) -> Result<(), DecompileError>,
+        aalt_rules: impl Fn(&Self, u16) -> Result<Option<Vec<String>>, DecompileError>,
+    ) -> Result<(), DecompileError> {
+        // feature tag -> (script, language, lookup indices)
+        let mut features: IndexMap<Tag, Vec<(Tag, Tag, Vec<u16>)>> = IndexMap::new();
+        for script_record in script_list.script_records() {
+            let script_tag = script_record.script_tag();
+            let script = script_record.script(script_list.offset_data())?;
+            let default = script.default_lang_sys().transpose()?;
+            let lang_systems = default
+                .map(|lang_sys| Ok((DFLT_LANG, lang_sys)))
+                .into_iter()
+                .chain(script.lang_sys_records().iter().map(|record| {
+                    record
+                        .lang_sys(script.offset_data())
+                        .map(|lang_sys| (record.lang_sys_tag(), lang_sys))
+                }));
+            for lang_system in lang_systems {
+                let (lang_tag, lang_sys) = lang_system?;
+                for feature_idx in lang_sys.feature_indices() {
+                    let Some(record) = feature_list
+                        .feature_records()
+                        .get(feature_idx.get() as usize)
+                    else {
+                        return Err(ReadError::OutOfBounds.into());
+                    };
+                    let feature = record.feature(feature_list.offset_data())?;
+                    let lookups = feature
+                        .lookup_list_indices()
+                        .iter()
+                        .map(|idx| idx.get())
+                        .collect::<Vec<_>>();
+                    if !lookups.is_empty() {
+                        self.language_systems.insert((script_tag, lang_tag));
+                        features
+                            .entry(record.feature_tag())
+                            .or_default()
+                            .push((script_tag, lang_tag, lookups));
+                    }
+                }
+            }
+        }
+
+        let in_features = features
+            .values()
+            .flatten()
+            .flat_map(|(_, _, lookups)| lookups.iter().copied())
+            .collect::<HashSet<_>>();
+        let (aalt_lookups, aalt_rules) = self.aalt(&features, references, aalt_rules)?;
+        if aalt_rules.is_some() {
+            features.shift_remove(&AALT);
+        }
+        let mut order = lookup_order(table, references, &in_features)?;
+        order.retain(|lookup| !aalt_lookups.contains(lookup));
+        self.lookup_names = order
+            .iter()
+            .enumerate()
+            .map(|(i, lookup)| (*lookup, format!("{table}_{i}")))
+            .collect();
+        for lookup in order {
+            write_lookup(self, lookup)?;
+        }
+
+        if let Some(rules) = aalt_rules {
+            writeln!(self.features, "\nfeature aalt {{").unwrap();
+            for rule in rules {
+                writeln!(self.features, "{INDENT}{rule};").unwrap();
+            }
+            writeln!(self.features, "}} aalt;").unwrap();
+        }
+        for (feature_tag, lang_systems) in features {
+            let feature_tag = fea_tag(feature_tag);
+            writeln!(self.features, "\nfeature {feature_tag} {{").unwrap();
+            let mut prev_script = None;
+            for (script, lang, lookups) in lang_systems {
+                if prev_script != Some(script) {
+                    writeln!(self.features, "{INDENT}script {};", fea_tag(script)).unwrap();
+                    prev_script = Some(script);
+                }
+                if lang != DFLT_LANG {
+                    writeln!(
+                        self.features,
+                        "{INDENT}language {} exclude_dflt;",
+                        fea_tag(lang)
+                    )
+                    .unwrap();
+                }
+                for lookup in lookups {
+                    let name = self.lookup_name(lookup)?;
+                    writeln!(self.features, "{INDENT}lookup {name};").unwrap();
+                }
+            }
+            writeln!(self.features, "}} {feature_tag};").unwrap();
+        }
+        Ok(())
+    }
+
+    /// The lookups of the 'aalt' feature, and their rules, if they can be
+    /// written directly in the feature block.
+    ///
+    /// The compiler builds the lookups for 'aalt' from the rules in its block
+    /// (ignoring any lookup references), so this is how 'aalt' round-trips.
+    /// It is only possible if these lookups are not used anywhere else.
+    #[allow(clippy::type_complexity)]
+    fn aalt(
+        &self,
+        features: &IndexMap<Tag, Vec<(Tag, Tag, Vec<u16>)>>,
+        references: &[Vec<u16>],
+        aalt_rules: impl Fn(&Self, u16) -> Result<Option<Vec<String>>, DecompileError>,
+    ) -> Result<(BTreeSet<u16>, Option<Vec<String>>), DecompileError> {
+        let lookups_for = |tag: Option<Tag>| {
+            features
+                .iter()
+                .filter(|(feature, _)| tag.is_none_or(|tag| tag == **feature))
+                .flat_map(|(_, lang_systems)| lang_systems.iter())
+                .flat_map(|(_, _, lookups)| lookups.iter().copied())
+                .collect::<BTreeSet<_>>()
+        };
+        let aalt_lookups = lookups_for(Some(AALT));
+        let used_elsewhere = features
+            .iter()
+            .filter(|(feature, _)| **feature != AALT)
+            .flat_map(|(_, lang_systems)| lang_systems.iter())
+            .flat_map(|(_, _, lookups)| lookups.iter())
+            .chain(references.iter().flatten())
+            .any(|lookup| aalt_lookups.contains(lookup));
+        if aalt_lookups.is_empty() || used_elsewhere {
+            return Ok(Default::default());
+        }
+        let mut rules = Vec::new();
+        for lookup in &aalt_lookups {
+            let Some(lookup_rules) = aalt_rules(self, *lookup)? else {
+                return Ok(Default::default());
+            };
+            rules.extend(lookup_rules);
+        }
+        Ok((aalt_lookups, Some(rules)))
+    }
+
+    fn finish(self) -> Result<String, DecompileError> {
+        let gdef = self.gdef_block()?;
+        let mut out = String::new();
+        // DFLT must come first
+        let mut language_systems = self.language_systems.iter().collect::<Vec<_>>();
+        language_systems.sort_by_key(|(script, _)| *script != DFLT_SCRIPT);
+        for (script, lang) in language_systems {
+            writeln!(
+                out,
+                "languagesystem {} {};",
+                fea_tag(*script),
+                fea_tag(*lang)
+            )
+            .unwrap();
+        }
+        for section in [&self.classes, &self.lookups, &self.features, &gdef] {
+            if !section.is_empty() {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(section.trim_start_matches('\n'));
+            }
+        }
+        Ok(out)
+    }
+
+    fn gdef_block(&self) -> Result<String, DecompileError> {
+        let Some(gdef) = self.gdef.as_ref() else {
+            return Ok(String::new());
+        };
+        let mut statements = Vec::new();
+        if let Some(class_def) = gdef.glyph_class_def().transpose()? {
+            let classes = self.class_members(&class_def);
+            let slots = (1..=4)
+                .map(|class| match classes.get(&class) {
+                    Some(glyphs) => self.glyph_class(glyphs),
+                    None => Ok(String::new()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            statements.push(format!("GlyphClassDef {}", slots.join(", ")));
+        }
+        if let Some(attach_list) = gdef.attach_list().transpose()? {
+            let coverage = attach_list.coverage()?;
+            for (glyph, points) in coverage.iter().zip(attach_list.attach_points().iter()) {
+                let points = points?
+                    .point_indices()
+                    .iter()
+                    .map(|idx| idx.get().to_string())
+                    .collect::<Vec<_>>();
+                statements.push(format!(
+                    "Attach {} {}",
+                    self.glyph(glyph)?,
+                    points.join(" ")
+                ));
+            }
+        }
+        if let Some(caret_list) = gdef.lig_caret_list().transpose()? {
+            let coverage = caret_list.coverage()?;
+            for (glyph, lig_glyph) in coverage.iter().zip(caret_list.lig_glyphs().iter()) {
+                let mut positions = Vec::new();
+                let mut indices = Vec::new();
+                for caret in lig_glyph?.caret_values().iter() {
+                    match caret? {
+                        CaretValue::Format1(caret) => positions.push(caret.coordinate()),
+                        CaretValue::Format2(caret) => indices.push(caret.caret_value_point_index()),
+                        CaretValue::Format3(caret) => positions.push(caret.coordinate()),
+                    }
+                }
+                let name = self.glyph(glyph)?;
+                if !positions.is_empty() {
+                    statements.push(format!("LigatureCaretByPos {name} {}", join(&positions)));
+                }
+                if !indices.is_empty() {
+                    statements.push(format!("LigatureCaretByIndex {name} {}", join(&indices)));
+                }
+            }
+        }
+        if statements.is_empty() {
+            return Ok(String::new());
+        }
+        let mut out = String::from("table GDEF {\n");
+        for statement in statements {
+            writeln!(out, "{INDENT}{statement};").unwrap();
+        }
+        out.push_str("} GDEF;\n");
+        Ok(out)
+    }
+
+    /// Write the start of a lookup block, including its lookupflag.
+    fn start_lookup(
+        &mut self,
+        index: u16,
+        flags: LookupFlag,
+        mark_filtering_set: Option<u16>,
+        use_extension: bool,
+    ) -> Result<(), DecompileError> {
+        let name = self.lookup_name(index)?;
+        let extension = if use_extension { " useExtension" } else { "" };
+        writeln!(self.lookups, "\nlookup {name}{extension} {{").unwrap();
+
+        let mut flag_names = [
+            (LookupFlag::RIGHT_TO_LEFT, "RightToLeft"),
+            (LookupFlag::IGNORE_BASE_GLYPHS, "IgnoreBaseGlyphs"),
+            (LookupFlag::IGNORE_LIGATURES, "IgnoreLigatures"),
+            (LookupFlag::IGNORE_MARKS, "IgnoreMarks"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| name.to_string())
+        .collect::<Vec<_>>();
+        if let Some(class) = flags.mark_attachment_class() {
+            let name = self.mark_attach_class(class)?;
+            flag_names.push(format!("MarkAttachmentType {name}"));
+        }
+        if let Some(set) =
+            mark_filtering_set.filter(|_| flags.contains(LookupFlag::USE_MARK_FILTERING_SET))
+        {
+            let name = self.mark_filtering_set(set)?;
+            flag_names.push(format!("UseMarkFilteringSet {name}"));
+        }
+        if !flag_names.is_empty() {
+            self.statement(&format!("lookupflag {}", flag_names.join(" ")));
+        }
+        Ok(())
+    }
+
+    fn end_lookup(&mut self, index: u16) -> Result<(), DecompileError> {
+        let name = self.lookup_name(index)?;
+        writeln!(self.lookups, "}} {name};").unwrap();
+        Ok(())
+    }
+
+    fn lookup_name(&self, index: u16) -> Result<String, DecompileError> {
+        self.lookup_names
+            .get(&index)
+            .cloned()
+            .ok_or(DecompileError::ReadError(ReadError::OutOfBounds))
+    }
+
+    /// Write the statements returned by `f` for each subtable, separated by
+    /// subtable breaks.
+    fn subtables<T>(
+        &mut self,
+        subtables: impl Iterator<Item = Result<T, ReadError>>,
+        mut f: impl FnMut(&mut Self, T) -> Result<Vec<String>, DecompileError>,
+    ) -> Result<(), DecompileError> {
+        for (i, subtable) in subtables.enumerate() {
+            if i > 0 {
+                self.statement("subtable");
+            }
+            for statement in f(self, subtable?)? {
+                self.statement(&statement);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a single statement in the current lookup.
+    fn statement(&mut self, statement: &str) {
+        writeln!(self.lookups, "{INDENT}{statement};").unwrap();
+    }
+
+    fn mark_attach_class(&mut self, class: u16) -> Result<String, DecompileError> {
+        let name = format!("@MarkAttachClass{class}");
+        if !self.defined_classes.contains(&name) {
+            let glyphs = match self
+                .gdef
+                .as_ref()
+                .and_then(|gdef| gdef.mark_attach_class_def())
+                .transpose()?
+            {
+                Some(class_def) => self
+                    .class_members(&class_def)
+                    .remove(&class)
+                    .unwrap_or_default(),
+                None => Vec::new(),
+            };
+            self.define_class(&name, &glyphs)?;
+        }
+        Ok(name)
+    }
+
+    fn mark_filtering_set(&mut self, set: u16) -> Result<String, DecompileError> {
+        let name = format!("@MarkFilteringSet{set}");
+        if !self.defined_classes.contains(&name) {
+            let glyphs = match self
+                .gdef
+                .as_ref()
+                .and_then(|gdef| gdef.mark_glyph_sets_def())
+                .transpose()?
+            {
+                Some(sets) => sets
+                    .coverages()
+                    .get(set as usize)?
+                    .iter()
+                    .collect::<Vec<_>>(),
+                None => Vec::new(),
+            };
+            self.define_class(&name, &glyphs)?;
+        }
+        Ok(name)
+    }
+
+    fn define_class(&mut self, name: &str, glyphs: &[GlyphId16]) -> Result<(), DecompileError> {
+        let class = self.glyph_class(glyphs)?;
+        writeln!(self.classes, "{name} = {class};").unwrap();
+        self.defined_classes.insert(name.to_owned());
+        Ok(())
+    }
+
+    /// The name of the mark class with these members, defining it if needed.
+    ///
+    /// Identical mark classes (as are common between 'mark' and 'mkmk'
+    /// lookups) share a single definition.
+    fn mark_class(
+        &mut self,
+        mut members: Vec<(GlyphId16, String)>,
+    ) -> Result<String, DecompileError> {
+        members.sort();
+        if let Some(name) = self.mark_classes.get(&members) {
+            return Ok(name.clone());
+        }
+        let name = format!("@MC_{}", self.mark_classes.len());
+        let mut by_anchor: BTreeMap<&str, Vec<GlyphId16>> = BTreeMap::new();
+        for (glyph, anchor) in &members {
+            by_anchor.entry(anchor).or_default().push(*glyph);
+        }
+        for (anchor, glyphs) in by_anchor {
+            let glyphs = self.glyph_class(&glyphs)?;
+            writeln!(self.classes, "markClass {glyphs} {anchor} {name};").unwrap();
+        }
+        self.mark_classes.insert(members, name.clone());
+        Ok(name)
+    }
+
+    /// The members of each class in a class def, including class 0.
+    fn class_members(&self, class_def: &ClassDef) -> HashMap<u16, Vec<GlyphId16>> {
+        let mut classes: HashMap<u16, Vec<GlyphId16>> = HashMap::new();
+        for (glyph, class) in class_def.iter().filter(|(_, class)| *class != 0) {
+            classes.entry(class).or_default().push(glyph);
+        }
+        for glyphs in classes.values_mut() {
+            glyphs.sort();
+            glyphs.dedup();
+        }
+        let unassigned = (0..self.num_glyphs)
+            .map(GlyphId16::new)
+            .filter(|glyph| class_def.get(*glyph) == 0)
+            .collect();
+        classes.insert(0, unassigned);
+        classes
+    }
+
+    fn glyph(&self, glyph: GlyphId16) -> Result<&str, DecompileError> {
+        self.names
+            .get(&glyph)
+            .map(String::as_str)
+            .ok_or(DecompileError::UnknownGlyph(glyph))
+    }
+
+    /// A glyph class literal, such as `[a b c]`.
+    fn glyph_class(&self, glyphs: &[GlyphId16]) -> Result<String, DecompileError> {
+        let names = glyphs
+            .iter()
+            .map(|glyph| self.glyph(*glyph))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("[{}]", names.join(" ")))
+    }
+
+    /// A single glyph name, or a class literal if there are multiple glyphs.
+    fn glyph_or_class(&self, glyphs: &[GlyphId16]) -> Result<String, DecompileError> {
+        match glyphs {
+            [glyph] => self.glyph(*glyph).map(str::to_owned),
+            _ => self.glyph_class(glyphs),
+        }
+    }
+}
+
+fn coverage_glyphs(
+    coverage: Result<CoverageTable, ReadError>,
+) -> Result<Vec<GlyphId16>, ReadError> {
+    coverage.map(|coverage| coverage.iter().collect())
+}
+
+/// The order in which to write the lookups of a table.
+///
+/// This is lookup list order, except that lookups referenced by contextual
+/// rules are moved before the first lookup that references them.
+fn lookup_order(
+    table: Tag,
+    references: &[Vec<u16>],
+    in_features: &HashSet<u16>,
+) -> Result<Vec<u16>, DecompileError> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Pending,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        lookup: u16,
+        table: Tag,
+        references: &[Vec<u16>],
+        in_features: &HashSet<u16>,
+        states: &mut [State],
+        order: &mut Vec<u16>,
+    ) -> Result<(), DecompileError> {
+        states[lookup as usize] = State::Visiting;
+        for &target in &references[lookup as usize] {
+            let Some(state) = states.get(target as usize).copied() else {
+                return Err(ReadError::OutOfBounds.into());
+            };
+            match state {
+                State::Done => (),
+                // moving a lookup used by a feature would change the order
+                // in which it is applied, and cycles can't be ordered at all
+                State::Visiting => {
+                    return Err(DecompileError::ForwardLookupReference {
+                        table,
+                        lookup,
+                        target,
+                    });
+                }
+                State::Pending if in_features.contains(&target) => {
+                    return Err(DecompileError::ForwardLookupReference {
+                        table,
+                        lookup,
+                        target,
+                    });
+                }
+                State::Pending => visit(target, table, references, in_features, states, order)?,
+            }
+        }
+        states[lookup as usize] = State::Done;
+        order.push(lookup);
+        Ok(())
+    }
+
+    let mut states = vec![State::Pending; references.len()];
+    let mut order = Vec::with_capacity(references.len());
+    for lookup in 0..references.len() as u16 {
+        if states[lookup as usize] == State::Pending {
+            visit(
+                lookup,
+                table,
+                references,
+                in_features,
+                &mut states,
+                &mut order,
+            )?;
+        }
+    }
+    Ok(order)
+}
+
+/// Tags in FEA do not include trailing spaces.
+fn fea_tag(tag: Tag) -> String {
+    tag.to_string().trim_end().to_owned()
+}
+
+fn join(values: &[impl ToString]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn anchor(anchor: Option<Result<AnchorTable, ReadError>>) -> Result<String, ReadError> {
+    Ok(match anchor.transpose()? {
+        None => "<anchor NULL>".to_owned(),
+        Some(AnchorTable::Format2(anchor)) => format!(
+            "<anchor {} {} contourpoint {}>",
+            anchor.x_coordinate(),
+            anchor.y_coordinate(),
+            anchor.anchor_point()
+        ),
+        Some(anchor) => format!(
+            "<anchor {} {}>",
+            anchor.x_coordinate(),
+            anchor.y_coordinate()
+        ),
+    })
+}
+
+fn value_record(record: &ValueRecord) -> String {
+    format!(
+        "<{} {} {} {}>",
+        record.x_placement().unwrap_or_default(),
+        record.y_placement().unwrap_or_default(),
+        record.x_advance().unwrap_or_default(),
+        record.y_advance().unwrap_or_default()
+    )
+}
+
+fn is_empty_value_record(record: &ValueRecord) -> bool {
+    [
+        record.x_placement(),
+        record.y_placement(),
+        record.x_advance(),
+        record.y_advance(),
+    ]
+    .iter()
+    .all(|value| value.unwrap_or_default() == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use write_fonts::{
+        from_obj::ToOwnedTable,
+        read::FontRef,
+        tables::{
+            gsub::{Gsub, SubstitutionLookup},
+            layout::ChainedSequenceContext,
+        },
+    };
+
+    use super::*;
+    use crate::compile::{Compiler, MockVariationInfo, NopFeatureProvider};
+
+    fn glyph_map() -> GlyphMap {
+        [
+            ".notdef",
+            "a",
+            "b",
+            "c",
+            "f",
+            "i",
+            "f_i",
+            "a.alt1",
+            "a.alt2",
+            "b.sc",
+            "acutecomb",
+            "gravecomb",
+            "sub",
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn compile(fea: &str, glyph_map: &GlyphMap) -> Result<Vec<u8>, String> {
+        let (tree, diagnostics) = crate::parse::parse_string(fea);
+        if diagnostics.has_errors() {
+            return Err(diagnostics.display().to_string());
+        }
+        let (compilation, _) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+            &tree,
+            glyph_map,
+            None,
+            None,
+            Default::default(),
+        )
+        .map_err(|errors| errors.display().to_string())?;
+        compilation
+            .to_binary(glyph_map)
+            .map_err(|err| err.to_string())
+    }
+
+    fn round_trip(fea: &str) -> String {
+        let glyph_map = glyph_map();
+        let bytes = compile(fea, &glyph_map).unwrap();
+        let decompiled = decompile(&FontRef::new(&bytes).unwrap(), &glyph_map).unwrap();
+        // the output compiles, and decompiles back to the same thing
+        let bytes = compile(&decompiled, &glyph_map).unwrap_or_else(|err| panic!("{err}"));
+        let again = decompile(&FontRef::new(&bytes).unwrap(), &glyph_map).unwrap();
+        assert_eq!(decompiled, again);
+        decompiled
+    }
+
+    #[test]
+    fn substitutions() {
+        let decompiled = round_trip(
+            "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn TRK;
+
+feature liga {
+    sub f i by f_i;
+} liga;
+
+feature salt {
+    sub a from [a.alt2 a.alt1];
+    script latn;
+    language TRK exclude_dflt;
+    sub b by b.sc;
+    sub c by a b;
+} salt;
+",
+        );
+        assert_eq!(
+            decompiled,
+            "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn TRK;
+
+lookup GSUB_0 {
+    sub f i by f_i;
+} GSUB_0;
+
+lookup GSUB_1 {
+    sub a from [a.alt2 a.alt1];
+} GSUB_1;
+
+lookup GSUB_2 {
+    sub b by b.sc;
+    sub c by a b;
+} GSUB_2;
+
+feature liga {
+    script DFLT;
+    lookup GSUB_0;
+    script latn;
+    lookup GSUB_0;
+    language TRK exclude_dflt;
+    lookup GSUB_0;
+} liga;
+
+feature salt {
+    script DFLT;
+    lookup GSUB_1;
+    script latn;
+    lookup GSUB_1;
+    language TRK exclude_dflt;
+    lookup GSUB_2;
+} salt;
+"
+        );
+    }
+
+    #[test]
+    fn contextual_and_escaped_names() {
+        let decompiled = round_trip(
+            "\
+lookup single {
+    sub a by b;
+} single;
+
+feature calt {
+    lookupflag IgnoreMarks;
+    sub [a b] c a' lookup single \\sub;
+    ignore sub c a' b;
+} calt;
+",
+        );
+        assert!(
+            decompiled
+                .contains("    lookupflag IgnoreMarks;\n    sub [a b] c a' lookup GSUB_0 \\sub;\n"),
+            "{decompiled}"
+        );
+        assert!(
+            decompiled.contains("    ignore sub c a' b;\n"),
+            "{decompiled}"
+        );
+    }
+
+    #[test]
+    fn positioning() {
+        let decompiled = round_trip(
+            "\
+markClass [acutecomb gravecomb] <anchor 100 500> @TOP;
+
+feature kern {
+    pos a b -20;
+    pos [a c] [f i] <0 0 -10 0>;
+} kern;
+
+feature mark {
+    pos base [a b] <anchor 250 500> mark @TOP;
+    pos ligature f_i <anchor 150 500> mark @TOP ligComponent <anchor NULL>;
+} mark;
+
+feature mkmk {
+    lookupflag UseMarkFilteringSet [acutecomb];
+    pos mark acutecomb <anchor 100 800> mark @TOP;
+} mkmk;
+",
+        );
+        for expected in [
+            "@MarkFilteringSet0 = [acutecomb];\n",
+            "markClass [acutecomb gravecomb] <anchor 100 500> @MC_0;\n",
+            "    pos a b <0 0 -20 0>;\n",
+            "    pos [a c] [f i] <0 0 -10 0>;\n",
+            "    pos base a <anchor 250 500> mark @MC_0;\n",
+            "    pos ligature f_i <anchor 150 500> mark @MC_0 ligComponent <anchor NULL>;\n",
+            "    lookupflag UseMarkFilteringSet @MarkFilteringSet0;\n",
+            "    pos mark acutecomb <anchor 100 800> mark @MC_0;\n",
+            "table GDEF {\n    GlyphClassDef [a b], [f_i], [acutecomb gravecomb], ;\n} GDEF;\n",
+        ] {
+            assert!(
+                decompiled.contains(expected),
+                "missing '{expected}' in\n{decompiled}"
+            );
+        }
+    }
+
+    #[test]
+    fn feature_writer_lookups() {
+        use write_fonts::tables::{
+            gpos::builders::{PairPosBuilder, ValueRecordBuilder},
+            gsub::builders::SingleSubBuilder,
+            layout::LookupFlag,
+        };
+
+        use crate::compile::{FeatureBuilder, PendingLookup};
+
+        struct Writer;
+        impl FeatureProvider for Writer {
+            fn add_features(&self, builder: &mut FeatureBuilder) {
+                let mut kern = PairPosBuilder::default();
+                kern.insert_pair(
+                    GlyphId16::new(1),
+                    ValueRecordBuilder::new().with_x_advance(-20),
+                    GlyphId16::new(2),
+                    ValueRecordBuilder::new(),
+                );
+                let lookup = PendingLookup::new(vec![kern], LookupFlag::IGNORE_MARKS, None);
+                let id = builder.add_lookup(lookup);
+                builder.add_to_default_language_systems(Tag::new(b"kern"), &[id]);
+
+                let mut smcp = SingleSubBuilder::default();
+                smcp.insert(GlyphId16::new(2), GlyphId16::new(9));
+                let id =
+                    builder.add_lookup(PendingLookup::new(vec![smcp], LookupFlag::empty(), None));
+                builder.add_to_default_language_systems(Tag::new(b"smcp"), &[id]);
+            }
+        }
+
+        let glyph_map = glyph_map();
+        let decompiled = decompile_feature_writer(&Writer, &glyph_map).unwrap();
+        for expected in [
+            "lookup GSUB_0 {\n    sub b by b.sc;\n} GSUB_0;\n",
+            "lookup GPOS_0 {\n    lookupflag IgnoreMarks;\n    pos a b <0 0 -20 0>;\n} GPOS_0;\n",
+            "feature smcp {\n",
+            "feature kern {\n",
+        ] {
+            assert!(
+                decompiled.contains(expected),
+                "missing '{expected}' in\n{decompiled}"
+            );
+        }
+        // and the output compiles
+        compile(&decompiled, &glyph_map).unwrap();
+    }
+
+    // lookups only used in contextual rules are moved before their first use
+    #[test]
+    fn inline_lookups_are_hoisted() {
+        let decompiled = round_trip(
+            "\
+feature calt {
+    sub c a' by b;
+} calt;
+",
+        );
+        assert!(
+            decompiled.contains(
+                "lookup GSUB_0 {\n    sub a by b;\n} GSUB_0;\n\nlookup GSUB_1 {\n    sub c a' lookup GSUB_0;\n"
+            ),
+            "{decompiled}"
+        );
+    }
+
+    #[test]
+    fn aalt_rules_are_written_inline() {
+        let decompiled = round_trip(
+            "\
+feature aalt {
+    feature salt;
+} aalt;
+
+feature salt {
+    sub a from [a.alt1 a.alt2];
+} salt;
+",
+        );
+        assert!(
+            decompiled.contains("feature aalt {\n    sub a from [a.alt1 a.alt2];\n} aalt;\n"),
+            "{decompiled}"
+        );
+        assert!(!decompiled.contains("lookup GSUB_1"), "{decompiled}");
+    }
+
+    #[test]
+    fn forward_reference_to_feature_lookup_is_an_error() {
+        let glyph_map = glyph_map();
+        let bytes = compile(
+            "\
+lookup single {
+    sub a by b;
+} single;
+
+feature calt {
+    sub c a' lookup single;
+} calt;
+
+feature test {
+    lookup single;
+} test;
+",
+            &glyph_map,
+        )
+        .unwrap();
+        // swap the two lookups, so that the contextual lookup comes first
+        let font = FontRef::new(&bytes).unwrap();
+        let mut gsub: Gsub = font.gsub().unwrap().to_owned_table();
+        gsub.lookup_list.lookups.swap(0, 1);
+        let SubstitutionLookup::ChainContextual(lookup) = gsub.lookup_list.lookups[0].as_mut()
+        else {
+            panic!("expected a contextual lookup");
+        };
+        let ChainedSequenceContext::Format3(subtable) = &mut **lookup.subtables[0] else {
+            panic!("expected a format 3 subtable");
+        };
+        subtable.seq_lookup_records[0].lookup_list_index = 1;
+        let bytes = write_fonts::FontBuilder::new()
+            .add_table(&gsub)
+            .unwrap()
+            .build();
+        let result = decompile(&FontRef::new(&bytes).unwrap(), &glyph_map);
+        assert!(matches!(
+            result,
+            Err(DecompileError::ForwardLookupReference {
+                lookup: 0,
+                target: 1,
+                ..
+            })
+        ));
+    }
+
+    // compile, decompile, and recompile the feaLib test files; decompiling the
+    // recompiled font should produce the same output.
+    #[test]
+    fn round_trip_test_files() {
+        let glyph_map = crate::util::ttx::fonttools_test_glyph_order();
+        let mut n_round_tripped = 0;
+        let mut failures = Vec::new();
+        for path in std::fs::read_dir("./test-data/fonttools-tests")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "fea"))
+        {
+            if crate::util::ttx::is_variable(&path)
+                || crate::util::ttx::needs_feature_provider(&path)
+            {
+                continue;
+            }
+            let Ok(bytes) = Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(
+                path.clone(),
+                &glyph_map,
+            )
+            .print_warnings(false)
+            .compile_binary() else {
+                continue;
+            };
+            let result = decompile(&FontRef::new(&bytes).unwrap(), &glyph_map)
+                .map_err(|err| err.to_string())
+                .and_then(|decompiled| {
+                    let bytes = compile(&decompiled, &glyph_map)?;
+                    let again = decompile(&FontRef::new(&bytes).unwrap(), &glyph_map)
+                        .map_err(|err| err.to_string())?;
+                    if decompiled == again {
+                        Ok(())
+                    } else {
+                        Err(crate::util::ttx::plain_text_diff(&decompiled, &again))
+                    }
+                });
+            match result {
+                Ok(()) => n_round_tripped += 1,
+                Err(err) => failures.push(format!("{}: {err}", path.display())),
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+        assert!(n_round_tripped > 50, "{n_round_tripped}");
+    }
+}
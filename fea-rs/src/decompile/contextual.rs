@@ -0,0 +1,250 @@
+//! Contextual and chaining contextual rules, shared by GSUB and GPOS.
+
+use std::collections::HashMap;
+
+use write_fonts::{
+    read::{
+        ReadError,
+        tables::layout::{ChainedSequenceContext, SequenceContext, SequenceLookupRecord},
+    },
+    types::{BigEndian, GlyphId16},
+};
+
+use super::{DecompileError, Decompiler, coverage_glyphs};
+
+/// A contextual rule, with each position resolved to a set of glyphs.
+///
+/// All three subtable formats are converted to this representation.
+#[derive(Default)]
+pub(super) struct ContextRule {
+    /// In logical order (the reverse of the order in the binary)
+    backtrack: Vec<Vec<GlyphId16>>,
+    input: Vec<Vec<GlyphId16>>,
+    lookahead: Vec<Vec<GlyphId16>>,
+    /// (sequence index, lookup index)
+    lookups: Vec<(u16, u16)>,
+}
+
+impl Decompiler<'_> {
+    pub(super) fn context_rules(
+        &self,
+        subtable: &SequenceContext,
+    ) -> Result<Vec<ContextRule>, DecompileError> {
+        let mut rules = Vec::new();
+        match subtable {
+            SequenceContext::Format1(table) => {
+                let coverage = table.coverage()?;
+                for (first, rule_set) in coverage.iter().zip(table.seq_rule_sets().iter()) {
+                    let Some(rule_set) = rule_set.transpose()? else {
+                        continue;
+                    };
+                    for rule in rule_set.seq_rules().iter() {
+                        let rule = rule?;
+                        rules.push(ContextRule {
+                            input: glyph_sequence(first, rule.input_sequence()),
+                            lookups: lookup_records(rule.seq_lookup_records()),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+            SequenceContext::Format2(table) => {
+                let coverage = table.coverage()?;
+                let class_def = table.class_def()?;
+                let classes = self.class_members(&class_def);
+                for (class, rule_set) in table.class_seq_rule_sets().iter().enumerate() {
+                    let Some(rule_set) = rule_set.transpose()? else {
+                        continue;
+                    };
+                    let first = coverage
+                        .iter()
+                        .filter(|glyph| class_def.get(*glyph) as usize == class)
+                        .collect::<Vec<_>>();
+                    if first.is_empty() {
+                        continue;
+                    }
+                    for rule in rule_set.class_seq_rules().iter() {
+                        let rule = rule?;
+                        let mut input = vec![first.clone()];
+                        input.extend(class_sequence(&classes, rule.input_sequence()));
+                        rules.push(ContextRule {
+                            input,
+                            lookups: lookup_records(rule.seq_lookup_records()),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+            SequenceContext::Format3(table) => rules.push(ContextRule {
+                input: table
+                    .coverages()
+                    .iter()
+                    .map(coverage_glyphs)
+                    .collect::<Result<_, _>>()?,
+                lookups: lookup_records(table.seq_lookup_records()),
+                ..Default::default()
+            }),
+        }
+        Ok(rules)
+    }
+
+    pub(super) fn chain_context_rules(
+        &self,
+        subtable: &ChainedSequenceContext,
+    ) -> Result<Vec<ContextRule>, DecompileError> {
+        let mut rules = Vec::new();
+        match subtable {
+            ChainedSequenceContext::Format1(table) => {
+                let coverage = table.coverage()?;
+                for (first, rule_set) in coverage.iter().zip(table.chained_seq_rule_sets().iter()) {
+                    let Some(rule_set) = rule_set.transpose()? else {
+                        continue;
+                    };
+                    for rule in rule_set.chained_seq_rules().iter() {
+                        let rule = rule?;
+                        let mut backtrack = glyph_sequence_rest(rule.backtrack_sequence());
+                        backtrack.reverse();
+                        rules.push(ContextRule {
+                            backtrack,
+                            input: glyph_sequence(first, rule.input_sequence()),
+                            lookahead: glyph_sequence_rest(rule.lookahead_sequence()),
+                            lookups: lookup_records(rule.seq_lookup_records()),
+                        });
+                    }
+                }
+            }
+            ChainedSequenceContext::Format2(table) => {
+                let coverage = table.coverage()?;
+                let input_class_def = table.input_class_def()?;
+                let backtrack_classes = self.class_members(&table.backtrack_class_def()?);
+                let input_classes = self.class_members(&input_class_def);
+                let lookahead_classes = self.class_members(&table.lookahead_class_def()?);
+                for (class, rule_set) in table.chained_class_seq_rule_sets().iter().enumerate() {
+                    let Some(rule_set) = rule_set.transpose()? else {
+                        continue;
+                    };
+                    let first = coverage
+                        .iter()
+                        .filter(|glyph| input_class_def.get(*glyph) as usize == class)
+                        .collect::<Vec<_>>();
+                    if first.is_empty() {
+                        continue;
+                    }
+                    for rule in rule_set.chained_class_seq_rules().iter() {
+                        let rule = rule?;
+                        let mut backtrack =
+                            class_sequence(&backtrack_classes, rule.backtrack_sequence());
+                        backtrack.reverse();
+                        let mut input = vec![first.clone()];
+                        input.extend(class_sequence(&input_classes, rule.input_sequence()));
+                        rules.push(ContextRule {
+                            backtrack,
+                            input,
+                            lookahead: class_sequence(
+                                &lookahead_classes,
+                                rule.lookahead_sequence(),
+                            ),
+                            lookups: lookup_records(rule.seq_lookup_records()),
+                        });
+                    }
+                }
+            }
+            ChainedSequenceContext::Format3(table) => {
+                let mut backtrack = table
+                    .backtrack_coverages()
+                    .iter()
+                    .map(coverage_glyphs)
+                    .collect::<Result<Vec<_>, _>>()?;
+                backtrack.reverse();
+                rules.push(ContextRule {
+                    backtrack,
+                    input: table
+                        .input_coverages()
+                        .iter()
+                        .map(coverage_glyphs)
+                        .collect::<Result<_, _>>()?,
+                    lookahead: table
+                        .lookahead_coverages()
+                        .iter()
+                        .map(coverage_glyphs)
+                        .collect::<Result<_, _>>()?,
+                    lookups: lookup_records(table.seq_lookup_records()),
+                })
+            }
+        }
+        Ok(rules)
+    }
+
+    /// The lookups referenced by the rules in these subtables.
+    pub(super) fn lookup_references<T>(
+        &self,
+        subtables: impl Iterator<Item = Result<T, ReadError>>,
+        rules: impl Fn(&Self, &T) -> Result<Vec<ContextRule>, DecompileError>,
+    ) -> Result<Vec<u16>, DecompileError> {
+        let mut references = Vec::new();
+        for subtable in subtables {
+            for rule in rules(self, &subtable?)? {
+                references.extend(rule.lookups.iter().map(|(_, lookup)| *lookup));
+            }
+        }
+        references.sort();
+        references.dedup();
+        Ok(references)
+    }
+
+    /// Format a contextual rule.
+    ///
+    /// `keyword` is either 'sub' or 'pos'. Rules that do not apply any lookups
+    /// are written as 'ignore' rules.
+    pub(super) fn context_rule(
+        &self,
+        keyword: &str,
+        rule: &ContextRule,
+    ) -> Result<String, DecompileError> {
+        let mut items = Vec::new();
+        if rule.lookups.is_empty() {
+            items.push("ignore".to_owned());
+        }
+        items.push(keyword.to_owned());
+        for glyphs in &rule.backtrack {
+            items.push(self.glyph_or_class(glyphs)?);
+        }
+        for (i, glyphs) in rule.input.iter().enumerate() {
+            items.push(format!("{}'", self.glyph_or_class(glyphs)?));
+            for (_, target) in rule.lookups.iter().filter(|(seq, _)| *seq as usize == i) {
+                items.push(format!("lookup {}", self.lookup_name(*target)?));
+            }
+        }
+        for glyphs in &rule.lookahead {
+            items.push(self.glyph_or_class(glyphs)?);
+        }
+        Ok(items.join(" "))
+    }
+}
+
+fn glyph_sequence(first: GlyphId16, rest: &[BigEndian<GlyphId16>]) -> Vec<Vec<GlyphId16>> {
+    let mut sequence = vec![vec![first]];
+    sequence.extend(glyph_sequence_rest(rest));
+    sequence
+}
+
+fn glyph_sequence_rest(glyphs: &[BigEndian<GlyphId16>]) -> Vec<Vec<GlyphId16>> {
+    glyphs.iter().map(|glyph| vec![glyph.get()]).collect()
+}
+
+fn class_sequence(
+    classes: &HashMap<u16, Vec<GlyphId16>>,
+    sequence: &[BigEndian<u16>],
+) -> Vec<Vec<GlyphId16>> {
+    sequence
+        .iter()
+        .map(|class| classes.get(&class.get()).cloned().unwrap_or_default())
+        .collect()
+}
+
+fn lookup_records(records: &[SequenceLookupRecord]) -> Vec<(u16, u16)> {
+    records
+        .iter()
+        .map(|record| (record.sequence_index(), record.lookup_list_index()))
+        .collect()
+}
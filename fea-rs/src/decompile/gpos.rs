@@ -0,0 +1,318 @@
+//! Decompiling GPOS lookups.
+
+use std::collections::BTreeMap;
+
+use write_fonts::{
+    read::{
+        ReadError,
+        tables::gpos::{
+            AnchorTable, CoverageTable, CursivePosFormat1, MarkArray, MarkBasePosFormat1,
+            MarkLigPosFormat1, MarkMarkPosFormat1, PairPos, PositionLookup, PositionSubtables,
+            SinglePos,
+        },
+    },
+    types::GlyphId16,
+};
+
+use super::{
+    DecompileError, Decompiler, anchor, coverage_glyphs, is_empty_value_record, value_record,
+};
+
+impl Decompiler<'_> {
+    pub(super) fn gpos_lookup_references(
+        &self,
+        lookup: &PositionLookup,
+    ) -> Result<Vec<u16>, DecompileError> {
+        match lookup.subtables()? {
+            PositionSubtables::Contextual(subtables) => {
+                self.lookup_references(subtables.iter(), Self::context_rules)
+            }
+            PositionSubtables::ChainContextual(subtables) => {
+                self.lookup_references(subtables.iter(), Self::chain_context_rules)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub(super) fn gpos_lookup(
+        &mut self,
+        index: u16,
+        lookup: &PositionLookup,
+    ) -> Result<(), DecompileError> {
+        self.start_lookup(
+            index,
+            lookup.lookup_flag(),
+            lookup.mark_filtering_set(),
+            lookup.lookup_type() == 9,
+        )?;
+        match lookup.subtables()? {
+            PositionSubtables::Single(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.single_pos(&sub))
+            }
+            PositionSubtables::Pair(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.pair_pos(&sub))
+            }
+            PositionSubtables::Cursive(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.cursive_pos(&sub))
+            }
+            PositionSubtables::MarkToBase(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.mark_base_pos(&sub))
+            }
+            PositionSubtables::MarkToLig(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.mark_lig_pos(&sub))
+            }
+            PositionSubtables::MarkToMark(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.mark_mark_pos(&sub))
+            }
+            PositionSubtables::Contextual(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| {
+                    this.context_rules(&sub)?
+                        .iter()
+                        .map(|rule| this.context_rule("pos", rule))
+                        .collect()
+                })
+            }
+            PositionSubtables::ChainContextual(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| {
+                    this.chain_context_rules(&sub)?
+                        .iter()
+                        .map(|rule| this.context_rule("pos", rule))
+                        .collect()
+                })
+            }
+        }?;
+        self.end_lookup(index)
+    }
+
+    fn single_pos(&mut self, subtable: &SinglePos) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        match subtable {
+            SinglePos::Format1(table) => {
+                let glyphs = coverage_glyphs(table.coverage())?;
+                let statement = format!(
+                    "pos {} {}",
+                    self.glyph_or_class(&glyphs)?,
+                    value_record(&table.value_record())
+                );
+                statements.push(statement);
+            }
+            SinglePos::Format2(table) => {
+                for (glyph, record) in table.coverage()?.iter().zip(table.value_records().iter()) {
+                    let statement =
+                        format!("pos {} {}", self.glyph(glyph)?, value_record(&record?));
+                    statements.push(statement);
+                }
+            }
+        }
+        Ok(statements)
+    }
+
+    fn pair_pos(&mut self, subtable: &PairPos) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        match subtable {
+            PairPos::Format1(table) => {
+                let has_second = !table.value_format2().is_empty();
+                for (first, pair_set) in table.coverage()?.iter().zip(table.pair_sets().iter()) {
+                    for record in pair_set?.pair_value_records().iter() {
+                        let record = record?;
+                        let first = self.glyph(first)?;
+                        let second = self.glyph(record.second_glyph())?;
+                        let value1 = value_record(record.value_record1());
+                        let statement = if has_second {
+                            let value2 = value_record(record.value_record2());
+                            format!("pos {first} {value1} {second} {value2}")
+                        } else {
+                            format!("pos {first} {second} {value1}")
+                        };
+                        statements.push(statement);
+                    }
+                }
+            }
+            PairPos::Format2(table) => {
+                let has_second = !table.value_format2().is_empty();
+                let class_def1 = table.class_def1()?;
+                let classes2 = self.class_members(&table.class_def2()?);
+                let coverage = table.coverage()?;
+                for (class1, class1_record) in table.class1_records().iter().enumerate() {
+                    let firsts = coverage
+                        .iter()
+                        .filter(|glyph| class_def1.get(*glyph) as usize == class1)
+                        .collect::<Vec<_>>();
+                    let class1_record = class1_record?;
+                    if firsts.is_empty() {
+                        continue;
+                    }
+                    for (class2, record) in class1_record.class2_records().iter().enumerate() {
+                        let record = record?;
+                        if is_empty_value_record(record.value_record1())
+                            && is_empty_value_record(record.value_record2())
+                        {
+                            continue;
+                        }
+                        let Some(seconds) = classes2
+                            .get(&(class2 as u16))
+                            .filter(|glyphs| !glyphs.is_empty())
+                        else {
+                            continue;
+                        };
+                        // always use class literals, so that these remain class pairs
+                        let first = self.glyph_class(&firsts)?;
+                        let second = self.glyph_class(seconds)?;
+                        let value1 = value_record(record.value_record1());
+                        let statement = if has_second {
+                            let value2 = value_record(record.value_record2());
+                            format!("pos {first} {value1} {second} {value2}")
+                        } else {
+                            format!("pos {first} {second} {value1}")
+                        };
+                        statements.push(statement);
+                    }
+                }
+            }
+        }
+        Ok(statements)
+    }
+
+    fn cursive_pos(&mut self, subtable: &CursivePosFormat1) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        let data = subtable.offset_data();
+        for (glyph, record) in subtable
+            .coverage()?
+            .iter()
+            .zip(subtable.entry_exit_record())
+        {
+            let statement = format!(
+                "pos cursive {} {} {}",
+                self.glyph(glyph)?,
+                anchor(record.entry_anchor(data))?,
+                anchor(record.exit_anchor(data))?
+            );
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn mark_base_pos(
+        &mut self,
+        subtable: &MarkBasePosFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        let mark_classes = self.mark_array(
+            subtable.mark_coverage(),
+            &subtable.mark_array()?,
+            subtable.mark_class_count(),
+        )?;
+        let base_array = subtable.base_array()?;
+        for (base, record) in subtable
+            .base_coverage()?
+            .iter()
+            .zip(base_array.base_records().iter())
+        {
+            let anchors = record?.base_anchors(base_array.offset_data());
+            let Some(marks) = attachments(&mark_classes, anchors.iter())? else {
+                continue;
+            };
+            let statement = format!("pos base {} {marks}", self.glyph(base)?);
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn mark_lig_pos(
+        &mut self,
+        subtable: &MarkLigPosFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        let mark_classes = self.mark_array(
+            subtable.mark_coverage(),
+            &subtable.mark_array()?,
+            subtable.mark_class_count(),
+        )?;
+        let ligature_array = subtable.ligature_array()?;
+        for (ligature, attach) in subtable
+            .ligature_coverage()?
+            .iter()
+            .zip(ligature_array.ligature_attaches().iter())
+        {
+            let attach = attach?;
+            let mut components = Vec::new();
+            for record in attach.component_records().iter() {
+                let anchors = record?.ligature_anchors(attach.offset_data());
+                let marks = attachments(&mark_classes, anchors.iter())?;
+                components.push(marks.unwrap_or_else(|| "<anchor NULL>".to_owned()));
+            }
+            if components.is_empty() {
+                continue;
+            }
+            let statement = format!(
+                "pos ligature {} {}",
+                self.glyph(ligature)?,
+                components.join(" ligComponent ")
+            );
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn mark_mark_pos(
+        &mut self,
+        subtable: &MarkMarkPosFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        let mark_classes = self.mark_array(
+            subtable.mark1_coverage(),
+            &subtable.mark1_array()?,
+            subtable.mark_class_count(),
+        )?;
+        let mark2_array = subtable.mark2_array()?;
+        for (mark2, record) in subtable
+            .mark2_coverage()?
+            .iter()
+            .zip(mark2_array.mark2_records().iter())
+        {
+            let anchors = record?.mark2_anchors(mark2_array.offset_data());
+            let Some(marks) = attachments(&mark_classes, anchors.iter())? else {
+                continue;
+            };
+            let statement = format!("pos mark {} {marks}", self.glyph(mark2)?);
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    /// Define the mark classes in a mark array, returning their names.
+    fn mark_array(
+        &mut self,
+        coverage: Result<CoverageTable, ReadError>,
+        mark_array: &MarkArray,
+        class_count: u16,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut classes: BTreeMap<u16, Vec<(GlyphId16, String)>> = BTreeMap::new();
+        for (glyph, record) in coverage?.iter().zip(mark_array.mark_records()) {
+            let mark_anchor = anchor(Some(record.mark_anchor(mark_array.offset_data())))?;
+            classes
+                .entry(record.mark_class())
+                .or_default()
+                .push((glyph, mark_anchor));
+        }
+        (0..class_count)
+            .map(|class| self.mark_class(classes.remove(&class).unwrap_or_default()))
+            .collect()
+    }
+}
+
+/// The `<anchor> mark @class` pairs for one base, ligature component or mark.
+///
+/// Returns `None` if there are no anchors.
+fn attachments<'a>(
+    mark_classes: &[String],
+    anchors: impl Iterator<Item = Option<Result<AnchorTable<'a>, ReadError>>>,
+) -> Result<Option<String>, ReadError> {
+    let mut items = Vec::new();
+    for (class, base_anchor) in mark_classes.iter().zip(anchors) {
+        if base_anchor.is_some() {
+            items.push(format!("{} mark {class}", anchor(base_anchor)?));
+        }
+    }
+    Ok((!items.is_empty()).then(|| items.join(" ")))
+}
@@ -0,0 +1,233 @@
+//! Decompiling GSUB lookups.
+
+use write_fonts::read::tables::{
+    gsub::{
+        AlternateSubstFormat1, LigatureSubstFormat1, MultipleSubstFormat1,
+        ReverseChainSingleSubstFormat1, SingleSubst, SubstitutionLookup, SubstitutionSubtables,
+    },
+    layout::LookupFlag,
+};
+
+use super::{DecompileError, Decompiler, coverage_glyphs};
+
+impl Decompiler<'_> {
+    pub(super) fn gsub_lookup_references(
+        &self,
+        lookup: &SubstitutionLookup,
+    ) -> Result<Vec<u16>, DecompileError> {
+        match lookup.subtables()? {
+            SubstitutionSubtables::Contextual(subtables) => {
+                self.lookup_references(subtables.iter(), Self::context_rules)
+            }
+            SubstitutionSubtables::ChainContextual(subtables) => {
+                self.lookup_references(subtables.iter(), Self::chain_context_rules)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub(super) fn gsub_lookup(
+        &mut self,
+        index: u16,
+        lookup: &SubstitutionLookup,
+    ) -> Result<(), DecompileError> {
+        self.start_lookup(
+            index,
+            lookup.lookup_flag(),
+            lookup.mark_filtering_set(),
+            lookup.lookup_type() == 7,
+        )?;
+        match lookup.subtables()? {
+            SubstitutionSubtables::Single(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.single_subst(&sub))
+            }
+            SubstitutionSubtables::Multiple(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.multiple_subst(&sub))
+            }
+            SubstitutionSubtables::Alternate(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.alternate_subst(&sub))
+            }
+            SubstitutionSubtables::Ligature(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.ligature_subst(&sub))
+            }
+            SubstitutionSubtables::Contextual(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| {
+                    this.context_rules(&sub)?
+                        .iter()
+                        .map(|rule| this.context_rule("sub", rule))
+                        .collect()
+                })
+            }
+            SubstitutionSubtables::ChainContextual(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| {
+                    this.chain_context_rules(&sub)?
+                        .iter()
+                        .map(|rule| this.context_rule("sub", rule))
+                        .collect()
+                })
+            }
+            SubstitutionSubtables::Reverse(subtables) => {
+                self.subtables(subtables.iter(), |this, sub| this.reverse_subst(&sub))
+            }
+        }?;
+        self.end_lookup(index)
+    }
+
+    /// The rules of a lookup, if it can be written directly in an 'aalt'
+    /// feature block.
+    ///
+    /// This is only possible for single and alternate substitution lookups
+    /// with a single subtable and no lookup flags.
+    pub(super) fn aalt_rules(
+        &self,
+        lookup: &SubstitutionLookup,
+    ) -> Result<Option<Vec<String>>, DecompileError> {
+        if lookup.lookup_flag() != LookupFlag::empty() {
+            return Ok(None);
+        }
+        let rules = match lookup.subtables()? {
+            SubstitutionSubtables::Single(subtables) if subtables.len() == 1 => {
+                self.single_subst(&subtables.get(0)?)?
+            }
+            SubstitutionSubtables::Alternate(subtables) if subtables.len() == 1 => {
+                self.alternate_subst(&subtables.get(0)?)?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(rules))
+    }
+
+    fn single_subst(&self, subtable: &SingleSubst) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        let pairs = match subtable {
+            SingleSubst::Format1(table) => {
+                let delta = table.delta_glyph_id();
+                table
+                    .coverage()?
+                    .iter()
+                    .map(|glyph| {
+                        let target = (glyph.to_u16() as i32 + delta as i32).rem_euclid(0x10000);
+                        (glyph, target as u16)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            SingleSubst::Format2(table) => table
+                .coverage()?
+                .iter()
+                .zip(table.substitute_glyph_ids())
+                .map(|(glyph, target)| (glyph, target.get().to_u16()))
+                .collect(),
+        };
+        for (glyph, target) in pairs {
+            let statement = format!(
+                "sub {} by {}",
+                self.glyph(glyph)?,
+                self.glyph(target.into())?
+            );
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn multiple_subst(
+        &self,
+        subtable: &MultipleSubstFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        for (glyph, sequence) in subtable.coverage()?.iter().zip(subtable.sequences().iter()) {
+            let targets = sequence?
+                .substitute_glyph_ids()
+                .iter()
+                .map(|target| self.glyph(target.get()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let targets = if targets.is_empty() {
+                "NULL".to_owned()
+            } else {
+                targets.join(" ")
+            };
+            let statement = format!("sub {} by {targets}", self.glyph(glyph)?);
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn alternate_subst(
+        &self,
+        subtable: &AlternateSubstFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        for (glyph, alternates) in subtable
+            .coverage()?
+            .iter()
+            .zip(subtable.alternate_sets().iter())
+        {
+            let alternates = alternates?
+                .alternate_glyph_ids()
+                .iter()
+                .map(|alt| alt.get())
+                .collect::<Vec<_>>();
+            let statement = format!(
+                "sub {} from {}",
+                self.glyph(glyph)?,
+                self.glyph_class(&alternates)?
+            );
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn ligature_subst(
+        &self,
+        subtable: &LigatureSubstFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut statements = Vec::new();
+        for (first, ligatures) in subtable
+            .coverage()?
+            .iter()
+            .zip(subtable.ligature_sets().iter())
+        {
+            for ligature in ligatures?.ligatures().iter() {
+                let ligature = ligature?;
+                let mut components = vec![self.glyph(first)?];
+                for component in ligature.component_glyph_ids() {
+                    components.push(self.glyph(component.get())?);
+                }
+                let statement = format!(
+                    "sub {} by {}",
+                    components.join(" "),
+                    self.glyph(ligature.ligature_glyph())?
+                );
+                statements.push(statement);
+            }
+        }
+        Ok(statements)
+    }
+
+    fn reverse_subst(
+        &self,
+        subtable: &ReverseChainSingleSubstFormat1,
+    ) -> Result<Vec<String>, DecompileError> {
+        let mut items = vec!["rsub".to_owned()];
+        let mut backtrack = subtable
+            .backtrack_coverages()
+            .iter()
+            .map(coverage_glyphs)
+            .collect::<Result<Vec<_>, _>>()?;
+        backtrack.reverse();
+        for glyphs in &backtrack {
+            items.push(self.glyph_or_class(glyphs)?);
+        }
+        let input = coverage_glyphs(subtable.coverage())?;
+        items.push(format!("{}'", self.glyph_or_class(&input)?));
+        for glyphs in subtable.lookahead_coverages().iter() {
+            items.push(self.glyph_or_class(&glyphs?.iter().collect::<Vec<_>>())?);
+        }
+        let substitutes = subtable
+            .substitute_glyph_ids()
+            .iter()
+            .map(|glyph| glyph.get())
+            .collect::<Vec<_>>();
+        items.push(format!("by {}", self.glyph_or_class(&substitutes)?));
+        Ok(vec![items.join(" ")])
+    }
+}
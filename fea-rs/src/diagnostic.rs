@@ -24,6 +24,97 @@ pub enum Level {
     Info,
 }
 
+/// A stable code identifying the kind of problem a diagnostic reports.
+///
+/// Unlike the message text, these codes are not expected to change between
+/// releases, and so can be relied upon by tools that consume diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DiagnosticCode {
+    /// A token that could not be lexed, such as an unterminated string
+    InvalidToken,
+    /// A token other than the one required by the grammar
+    UnexpectedToken,
+    /// A block or glyph class that is never closed
+    UnterminatedBlock,
+    /// A block whose closing tag or label does not match its opening one
+    MismatchedTag,
+    /// A construct that is not allowed by the grammar in this position
+    InvalidSyntax,
+    /// Syntax that is accepted, but which is not allowed by the spec
+    NonStandardSyntax,
+    /// An included file could not be loaded
+    IncludeLoad,
+    /// A file includes itself, directly or indirectly
+    IncludeCycle,
+    /// Includes are nested more deeply than the configured maximum
+    IncludeTooDeep,
+    /// A glyph or CID that is not in the font
+    UndefinedGlyph,
+    /// A reference to a glyph class that is not defined
+    UndefinedClass,
+    /// A reference to a mark class that is not defined
+    UndefinedMarkClass,
+    /// A reference to a lookup that is not defined
+    UndefinedLookup,
+    /// A reference to a named anchor that is not defined
+    UndefinedAnchor,
+    /// A reference to a named value record that is not defined
+    UndefinedValueRecord,
+    /// A reference to a condition set that is not defined
+    UndefinedConditionSet,
+    /// A reference to an axis that is not in the font
+    UndefinedAxis,
+    /// A reference to a feature that is not defined
+    UndefinedFeature,
+    /// A reference to a name ID that is not in the name table
+    UndefinedNameId,
+    /// A name that has already been defined
+    DuplicateDefinition,
+    /// A statement or value that has already been given
+    DuplicateStatement,
+    /// A glyph range that cannot be expanded
+    InvalidGlyphRange,
+    /// A malformed or inappropriate tag
+    InvalidTag,
+    /// A number outside of the range allowed for this value
+    ValueOutOfRange,
+    /// A value that is not valid for this field
+    InvalidValue,
+    /// An anchor that is malformed or not allowed here
+    InvalidAnchor,
+    /// A lookupflag statement that is malformed
+    InvalidLookupFlag,
+    /// A rule whose glyphs or values cannot be combined as written
+    InvalidRule,
+    /// Rules or lookups of different kinds in a lookup that requires one kind
+    InconsistentLookup,
+    /// A rule that conflicts with an earlier rule
+    ConflictingRule,
+    /// A glyph that is assigned to conflicting classes
+    ClassConflict,
+    /// A problem with a variable value or condition
+    InvalidVariation,
+    /// A statement that is not allowed where it appears
+    MisplacedStatement,
+    /// A statement that is required, but missing
+    MissingStatement,
+    /// A statement that has no effect, and will be ignored
+    IgnoredStatement,
+    /// A named lookup or glyph class that is never used
+    Unused,
+    /// Variable syntax used when compiling a static font
+    RequiresVariableFont,
+    /// Valid syntax that is not yet supported by the compiler
+    Unsupported,
+    /// A table that is not known to the compiler, and will be passed through
+    UnknownTable,
+    /// More items than the output format can represent
+    LimitExceeded,
+    /// A problem that should have been caught earlier; this is a bug
+    Internal,
+}
+
 /// A message, associated with a location in a file.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Message {
@@ -41,6 +132,23 @@ pub struct Diagnostic {
     pub message: Message,
     /// The diagnostic level
     pub level: Level,
+    /// The kind of problem this diagnostic reports
+    pub code: DiagnosticCode,
+    /// Other locations relevant to this diagnostic, such as a previous definition
+    pub related: Vec<Message>,
+}
+
+/// Configuration for how warnings are reported.
+///
+/// Warnings are selected by their [`DiagnosticCode`], so that for instance
+/// [`DiagnosticCode::Unused`] matches every warning about an unused lookup
+/// or glyph class.
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticConfig {
+    promoted: Vec<DiagnosticCode>,
+    silenced: Vec<DiagnosticCode>,
+    warnings_as_errors: bool,
+    pub(crate) max_warnings: Option<usize>,
 }
 
 /// A set of diagnostics with the associated source info
@@ -58,8 +166,73 @@ impl Span {
     }
 }
 
+impl Level {
+    /// A lowercase name for this level, e.g. `"error"`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Info => "info",
+        }
+    }
+}
+
+impl DiagnosticCode {
+    /// The stable string representation of this code, e.g. `"undefined-glyph"`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::InvalidToken => "invalid-token",
+            DiagnosticCode::UnexpectedToken => "unexpected-token",
+            DiagnosticCode::UnterminatedBlock => "unterminated-block",
+            DiagnosticCode::MismatchedTag => "mismatched-tag",
+            DiagnosticCode::InvalidSyntax => "invalid-syntax",
+            DiagnosticCode::NonStandardSyntax => "non-standard-syntax",
+            DiagnosticCode::IncludeLoad => "include-load",
+            DiagnosticCode::IncludeCycle => "include-cycle",
+            DiagnosticCode::IncludeTooDeep => "include-too-deep",
+            DiagnosticCode::UndefinedGlyph => "undefined-glyph",
+            DiagnosticCode::UndefinedClass => "undefined-class",
+            DiagnosticCode::UndefinedMarkClass => "undefined-mark-class",
+            DiagnosticCode::UndefinedLookup => "undefined-lookup",
+            DiagnosticCode::UndefinedAnchor => "undefined-anchor",
+            DiagnosticCode::UndefinedValueRecord => "undefined-value-record",
+            DiagnosticCode::UndefinedConditionSet => "undefined-condition-set",
+            DiagnosticCode::UndefinedAxis => "undefined-axis",
+            DiagnosticCode::UndefinedFeature => "undefined-feature",
+            DiagnosticCode::UndefinedNameId => "undefined-name-id",
+            DiagnosticCode::DuplicateDefinition => "duplicate-definition",
+            DiagnosticCode::DuplicateStatement => "duplicate-statement",
+            DiagnosticCode::InvalidGlyphRange => "invalid-glyph-range",
+            DiagnosticCode::InvalidTag => "invalid-tag",
+            DiagnosticCode::ValueOutOfRange => "value-out-of-range",
+            DiagnosticCode::InvalidValue => "invalid-value",
+            DiagnosticCode::InvalidAnchor => "invalid-anchor",
+            DiagnosticCode::InvalidLookupFlag => "invalid-lookup-flag",
+            DiagnosticCode::InvalidRule => "invalid-rule",
+            DiagnosticCode::InconsistentLookup => "inconsistent-lookup",
+            DiagnosticCode::ConflictingRule => "conflicting-rule",
+            DiagnosticCode::ClassConflict => "class-conflict",
+            DiagnosticCode::InvalidVariation => "invalid-variation",
+            DiagnosticCode::MisplacedStatement => "misplaced-statement",
+            DiagnosticCode::MissingStatement => "missing-statement",
+            DiagnosticCode::IgnoredStatement => "ignored-statement",
+            DiagnosticCode::Unused => "unused",
+            DiagnosticCode::RequiresVariableFont => "requires-variable-font",
+            DiagnosticCode::Unsupported => "unsupported",
+            DiagnosticCode::UnknownTable => "unknown-table",
+            DiagnosticCode::LimitExceeded => "limit-exceeded",
+            DiagnosticCode::Internal => "internal",
+        }
+    }
+}
+
 impl Diagnostic {
     /// Create a new diagnostic
+    ///
+    /// The code defaults to [`DiagnosticCode::InvalidSyntax`]; use
+    /// [`with_code`] to set a different one.
+    ///
+    /// [`with_code`]: Diagnostic::with_code
     pub fn new(
         level: Level,
         file: FileId,
@@ -76,9 +249,45 @@ impl Diagnostic {
                 file,
             },
             level,
+            code: DiagnosticCode::InvalidSyntax,
+            related: Vec::new(),
         }
     }
 
+    /// Builder style method to set the code of this diagnostic
+    pub fn with_code(mut self, code: DiagnosticCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Builder style method to add a related location to this diagnostic
+    pub fn with_related(
+        mut self,
+        file: FileId,
+        range: Range<usize>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.add_related(file, range, message);
+        self
+    }
+
+    /// Add a related location to this diagnostic
+    pub(crate) fn add_related(
+        &mut self,
+        file: FileId,
+        range: Range<usize>,
+        message: impl Into<String>,
+    ) {
+        self.related.push(Message {
+            text: message.into(),
+            file,
+            span: Span {
+                start: range.start.try_into().unwrap(),
+                end: range.end.try_into().unwrap(),
+            },
+        });
+    }
+
     /// Create a new error, at the provided location
     pub fn error(file: FileId, span: Range<usize>, message: impl Into<String>) -> Self {
         Diagnostic::new(Level::Error, file, span, message)
@@ -105,6 +314,47 @@ impl Diagnostic {
     }
 }
 
+impl DiagnosticConfig {
+    /// Create a new configuration that reports all warnings as warnings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat warnings with the given `code` as errors.
+    pub fn promote(mut self, code: DiagnosticCode) -> Self {
+        self.promoted.push(code);
+        self
+    }
+
+    /// Discard warnings with the given `code`.
+    ///
+    /// A warning that is also promoted is not discarded.
+    pub fn silence(mut self, code: DiagnosticCode) -> Self {
+        self.silenced.push(code);
+        self
+    }
+
+    /// If `true`, treat all warnings as errors. Default is `false`.
+    pub fn warnings_as_errors(mut self, flag: bool) -> Self {
+        self.warnings_as_errors = flag;
+        self
+    }
+
+    /// Report at most `max` warnings; any additional warnings are discarded.
+    pub fn max_warnings(mut self, max: usize) -> Self {
+        self.max_warnings = Some(max);
+        self
+    }
+
+    fn is_promoted(&self, diagnostic: &Diagnostic) -> bool {
+        self.warnings_as_errors || self.promoted.contains(&diagnostic.code)
+    }
+
+    fn is_silenced(&self, diagnostic: &Diagnostic) -> bool {
+        self.silenced.contains(&diagnostic.code)
+    }
+}
+
 // we don't want diagnostic set to impl display itself, because we want to change
 // behaviour based on whether we think we're writing to a terminal, and that is
 // error prone.
@@ -134,6 +384,13 @@ impl DiagnosticSet {
         self.messages.iter().any(|msg| msg.is_error())
     }
 
+    /// Append the messages from another set.
+    ///
+    /// Both sets are expected to refer to the same parse tree.
+    pub fn extend(&mut self, other: DiagnosticSet) {
+        self.messages.extend(other.messages);
+    }
+
     /// Set the max number of messages to print.
     pub fn set_max_to_print(&mut self, max_to_print: usize) {
         self.max_to_print = max_to_print;
@@ -156,6 +413,32 @@ impl DiagnosticSet {
         })
     }
 
+    /// Promote, silence, and limit the warnings in this set according to `config`.
+    ///
+    /// Returns the number of warnings that remain. When applying a config to
+    /// the diagnostics of several compilation stages, the caller is
+    /// responsible for reducing the warning limit accordingly.
+    pub fn apply_config(&mut self, config: &DiagnosticConfig) -> usize {
+        let mut n_warnings = 0;
+        self.messages.retain_mut(|diagnostic| {
+            if diagnostic.level != Level::Warning {
+                return true;
+            }
+            if config.is_promoted(diagnostic) {
+                diagnostic.level = Level::Error;
+                return true;
+            }
+            if config.is_silenced(diagnostic)
+                || config.max_warnings.is_some_and(|max| n_warnings >= max)
+            {
+                return false;
+            }
+            n_warnings += 1;
+            true
+        });
+        n_warnings
+    }
+
     /// Return the underlying diagnostics, as a slice
     pub fn diagnostics(&self) -> &[Diagnostic] {
         &self.messages
@@ -181,6 +464,46 @@ impl DiagnosticSet {
         Ok(())
     }
 
+    /// Serialize these diagnostics as a JSON array.
+    ///
+    /// Each diagnostic is an object with `code`, `severity`, `message`, `file`,
+    /// `span` (the byte range in that file), `line` (1-based) and `column`
+    /// (a 0-based byte offset in that line) fields, as well as a `related`
+    /// array of objects with the same `message`, `file`, `span`, `line` and
+    /// `column` fields for any other locations that the diagnostic refers to.
+    /// Unlike [`display`], this includes every diagnostic in the set.
+    ///
+    /// [`display`]: DiagnosticSet::display
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self) -> String {
+        let message_json = |message: &Message| {
+            let span = message.span.range();
+            let source = self.sources.get(&message.file);
+            let (line, column) = source
+                .map(|source| source.line_col_for_offset(span.start))
+                .unwrap_or_default();
+            serde_json::json!({
+                "message": message.text,
+                "file": source.map(|source| source.path().display().to_string()),
+                "span": { "start": span.start, "end": span.end },
+                "line": line,
+                "column": column,
+            })
+        };
+        let messages = self
+            .messages
+            .iter()
+            .map(|diagnostic| {
+                let mut json = message_json(&diagnostic.message);
+                json["code"] = diagnostic.code.as_str().into();
+                json["severity"] = diagnostic.level.as_str().into();
+                json["related"] = diagnostic.related.iter().map(message_json).collect();
+                json
+            })
+            .collect::<Vec<_>>();
+        serde_json::Value::Array(messages).to_string()
+    }
+
     #[cfg(any(test, feature = "test"))]
     pub(crate) fn to_string(&self, colorize: bool) -> String {
         let mut out = String::new();
@@ -0,0 +1,315 @@
+//! Formatting (pretty-printing) of FEA sources.
+//!
+//! The formatter works on the token tree, and only ever changes whitespace:
+//! each statement is placed on its own line and indented according to the
+//! block it is in, runs of spaces are collapsed, and brackets and value records
+//! are spaced consistently. Comments are preserved, as are single blank lines
+//! between statements.
+//!
+//! Line breaks inside of a statement (such as in a long glyph class) are kept,
+//! with the continuation lines indented by one additional level.
+
+use crate::{Diagnostic, Kind, Node, NodeOrToken, Token};
+
+const INDENT: &str = "    ";
+
+/// Format a single FEA source.
+///
+/// Include statements are not resolved; each file is formatted on its own.
+///
+/// If the source cannot be parsed, the errors are returned and nothing is
+/// formatted.
+pub fn format_string(text: &str) -> Result<String, Vec<Diagnostic>> {
+    let (node, diagnostics) = crate::parse::parse_single_source(text);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(diagnostics);
+    }
+    Ok(format_node(&node))
+}
+
+/// Format an already-parsed node.
+///
+/// This should generally be the root node of a single source; the output
+/// will be indented relative to the start of the node.
+pub fn format_node(node: &Node) -> String {
+    let mut formatter = Formatter::default();
+    formatter.node(node);
+    formatter.finish()
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    indent: usize,
+    // the previous token, and the kind of its parent node
+    prev: Option<(Kind, Kind)>,
+    // the next token starts a new statement, and goes on a new line
+    line_break: bool,
+    // number of newlines in the whitespace since the last token
+    pending_newlines: usize,
+    pending_space: bool,
+}
+
+impl Formatter {
+    fn node(&mut self, node: &Node) {
+        for child in node.iter_children() {
+            match child {
+                // the contents of anonymous blocks are not FEA, so we leave them alone.
+                NodeOrToken::Node(inner) if inner.kind() == Kind::AnonBlockNode => {
+                    self.verbatim(&inner)
+                }
+                // glyphs.app number values use braces, and are sensitive to spacing
+                NodeOrToken::Node(inner) if inner.kind() == Kind::GlyphsNumberValueNode => {
+                    self.inline_verbatim(&inner)
+                }
+                NodeOrToken::Node(inner) => self.node(&inner),
+                NodeOrToken::Token(token) => self.token(&token, node.kind()),
+            }
+        }
+    }
+
+    fn token(&mut self, token: &Token, parent: Kind) {
+        match token.kind {
+            Kind::Whitespace => {
+                self.pending_newlines += token.text.matches('\n').count();
+                self.pending_space = true;
+            }
+            Kind::Comment => self.comment(token, parent),
+            Kind::RBrace => {
+                self.indent = self.indent.saturating_sub(1);
+                self.pending_newlines = self.pending_newlines.min(1);
+                self.line_break = true;
+                self.write(token, parent);
+            }
+            kind => {
+                if !self.line_break {
+                    if self.pending_newlines > 0 && !self.prev_is(Kind::RBrace) {
+                        self.newline(self.indent + 1);
+                    } else if self.wants_space(kind, parent) {
+                        self.out.push(' ');
+                    }
+                }
+                self.write(token, parent);
+                match kind {
+                    Kind::Semi => self.line_break = true,
+                    Kind::LBrace => {
+                        self.indent += 1;
+                        self.line_break = true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn comment(&mut self, token: &Token, parent: Kind) {
+        if self.out.is_empty() || self.pending_newlines > 0 {
+            self.write(token, parent);
+        } else {
+            // a comment trailing a statement stays on the same line.
+            self.out.push(' ');
+            self.out.push_str(&token.text);
+            self.clear_pending();
+        }
+        self.line_break = true;
+    }
+
+    /// Write a node exactly as it appeared in the source.
+    fn verbatim(&mut self, node: &Node) {
+        let mut tokens = node.iter_tokens();
+        if let Some(first) = tokens.next() {
+            self.write(&first, node.kind());
+        }
+        for token in tokens {
+            self.out.push_str(&token.text);
+        }
+        self.line_break = true;
+        self.prev = None;
+    }
+
+    /// Write a node that is part of a statement, without changing its contents.
+    fn inline_verbatim(&mut self, node: &Node) {
+        let mut tokens = node.iter_tokens();
+        if let Some(first) = tokens.next() {
+            self.token(&first, node.kind());
+        }
+        for token in tokens {
+            self.out.push_str(&token.text);
+            self.prev = Some((token.kind, node.kind()));
+        }
+    }
+
+    /// Write a token, first starting a new line if required.
+    fn write(&mut self, token: &Token, parent: Kind) {
+        if self.line_break {
+            if !self.out.is_empty() {
+                // keep single blank lines, except at the start of a block
+                if self.pending_newlines > 1 && !self.prev_is(Kind::LBrace) {
+                    self.out.push('\n');
+                }
+                self.newline(self.indent);
+            }
+            self.line_break = false;
+        }
+        self.out.push_str(&token.text);
+        self.prev = Some((token.kind, parent));
+        self.clear_pending();
+    }
+
+    fn prev_is(&self, kind: Kind) -> bool {
+        self.prev.is_some_and(|(prev, _)| prev == kind)
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        for _ in 0..indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn wants_space(&self, next: Kind, parent: Kind) -> bool {
+        let Some((prev, prev_parent)) = self.prev else {
+            return false;
+        };
+        match (prev, next) {
+            (_, Kind::Semi | Kind::Comma | Kind::RSquare | Kind::RAngle | Kind::RParen) => false,
+            (Kind::LSquare | Kind::LAngle | Kind::LParen | Kind::Backslash, _) => false,
+            (Kind::IncludeKw, Kind::LParen) => false,
+            (Kind::RBrace, _) | (_, Kind::LBrace) => true,
+            (Kind::Eq, _) if prev_parent == Kind::GlyphClassDefNode => true,
+            (_, Kind::Eq) if parent == Kind::GlyphClassDefNode => true,
+            _ => self.pending_space,
+        }
+    }
+
+    fn clear_pending(&mut self) {
+        self.pending_newlines = 0;
+        self.pending_space = false;
+    }
+
+    fn finish(mut self) -> String {
+        let trimmed_len = self.out.trim_end().len();
+        self.out.truncate(trimmed_len);
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(text: &str) -> String {
+        match format_string(text) {
+            Ok(formatted) => formatted,
+            Err(errors) => panic!("failed to parse: {errors:?}"),
+        }
+    }
+
+    // the tokens of a source, ignoring whitespace
+    fn significant_tokens(text: &str) -> Vec<(Kind, String)> {
+        let (node, _) = crate::parse::parse_single_source(text);
+        node.iter_tokens()
+            .filter(|token| token.kind != Kind::Whitespace)
+            .map(|token| (token.kind, token.text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn blocks_and_spacing() {
+        let input = "\
+languagesystem  DFLT dflt ;
+@upper=[A  B ];
+
+feature kern{ # kerning
+pos A B < 0 0 -10 0 >;
+
+lookup kern1 {pos [ A B ]  C -20;} kern1;
+}kern;
+";
+        let expected = "\
+languagesystem DFLT dflt;
+@upper = [A B];
+
+feature kern { # kerning
+    pos A B <0 0 -10 0>;
+
+    lookup kern1 {
+        pos [A B] C -20;
+    } kern1;
+} kern;
+";
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn comments_and_continuations() {
+        let input = "\
+# a file
+include( other.fea );
+
+
+feature liga {
+    # a comment on its own line
+  sub f i by f_i; # and a trailing one
+@long = [a b
+c d];
+} liga;";
+        let expected = "\
+# a file
+include(other.fea);
+
+feature liga {
+    # a comment on its own line
+    sub f i by f_i; # and a trailing one
+    @long = [a b
+        c d];
+} liga;
+";
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn anonymous_blocks_are_untouched() {
+        let input = "anon sbit {\n  72 % {    -1 ; }\n} sbit;\nfeature test{sub a by b;}test;\n";
+        let expected =
+            "anon sbit {\n  72 % {    -1 ; }\n} sbit;\nfeature test {\n    sub a by b;\n} test;\n";
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn refuse_invalid_input() {
+        assert!(format_string("feature liga { sub a by ; } liga;").is_err());
+    }
+
+    // formatting changes only whitespace, and formatting twice changes nothing
+    #[test]
+    fn round_trip_test_files() {
+        let paths = [
+            "./test-data/parse-tests/good",
+            "./test-data/fonttools-tests",
+        ]
+        .into_iter()
+        .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "fea"));
+        let mut n_formatted = 0;
+        for path in paths {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let Ok(formatted) = format_string(&text) else {
+                continue;
+            };
+            assert_eq!(
+                significant_tokens(&text),
+                significant_tokens(&formatted),
+                "{}",
+                path.display()
+            );
+            assert_eq!(format(&formatted), formatted, "{}", path.display());
+            n_formatted += 1;
+        }
+        assert!(n_formatted > 50);
+    }
+}
@@ -5,11 +5,31 @@
 
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
+/// Enter a `tracing` span for the rest of the enclosing block.
+///
+/// Fields are recorded with their `Display` impl. This does nothing unless
+/// the `tracing` feature is enabled.
+macro_rules! trace_span {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name $(, $field = %$value)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        {
+            $(let _ = &$value;)*
+        }
+    };
+}
+
 mod common;
 pub mod compile;
+pub mod decompile;
 mod diagnostic;
+pub mod format;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod parse;
 mod token_tree;
+pub mod ttx;
 pub mod util;
 
 #[cfg(test)]
@@ -17,6 +37,6 @@ mod tests;
 
 pub use common::{GlyphIdent, GlyphMap, GlyphSet};
 pub use compile::{Compiler, Opts};
-pub use diagnostic::{Diagnostic, DiagnosticSet, Level};
+pub use diagnostic::{Diagnostic, DiagnosticCode, DiagnosticConfig, DiagnosticSet, Level};
 pub use parse::{ParseTree, TokenSet};
-pub use token_tree::{Kind, Node, NodeOrToken, Token, typed};
+pub use token_tree::{Kind, Node, NodeOrToken, Token, typed, visit};
@@ -0,0 +1,876 @@
+//! A minimal language server for FEA files.
+//!
+//! This implements enough of the [language server protocol][lsp] to be useful
+//! in an editor:
+//!
+//! - diagnostics, published when a document is opened or saved
+//! - document symbols for features, lookups, glyph classes, mark classes and
+//!   named anchors
+//! - completion of glyph names, if the server was started with a glyph map
+//! - semantic tokens, from [`semantic_tokens`]
+//! - go to definition and find references for lookups, classes and anchors,
+//!   from a [`SymbolIndex`]
+//!
+//! Documents are synced in full on every change. Included files are loaded
+//! from disk, relative to the document that includes them.
+//!
+//! The `fea-lsp` binary runs a [`Server`] over stdin and stdout.
+//!
+//! [lsp]: https://microsoft.github.io/language-server-protocol/
+//! [`semantic_tokens`]: crate::parse::semantic_tokens
+//! [`SymbolIndex`]: crate::parse::SymbolIndex
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    Diagnostic, DiagnosticCode, GlyphIdent, GlyphMap, Kind, Level, Node, NodeOrToken,
+    compile::{self, NopVariationInfo},
+    parse::{
+        self, FileSystemResolver, LineCol, LineIndex, SemanticKind, SourceLoadError,
+        SourceResolver, Symbol, SymbolIndex,
+    },
+};
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enum values
+const SYNC_FULL: u8 = 1;
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const SEVERITY_INFO: u8 = 3;
+const SYMBOL_MODULE: u8 = 2;
+const SYMBOL_CLASS: u8 = 5;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_CONSTANT: u8 = 14;
+const COMPLETION_VALUE: u8 = 12;
+
+/// The semantic token types we report, indexed by [`token_type`].
+const TOKEN_TYPES: &[&str] = &[
+    "keyword", "variable", "class", "type", "function", "property", "number", "string", "comment",
+];
+
+/// A language server for FEA files.
+pub struct Server {
+    glyph_map: Option<GlyphMap>,
+    /// The text of each open document, by uri
+    documents: HashMap<String, Arc<str>>,
+}
+
+impl Server {
+    /// Create a new server.
+    ///
+    /// If a `glyph_map` is provided, it is used to validate glyph names and to
+    /// offer them as completions.
+    pub fn new(glyph_map: Option<GlyphMap>) -> Self {
+        Server {
+            glyph_map,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Handle messages from `input` until the client exits or closes the
+    /// connection, writing responses and notifications to `output`.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let method = message["method"].as_str().unwrap_or_default();
+            if method == "exit" {
+                break;
+            }
+            let params = &message["params"];
+            let mut outgoing = Vec::new();
+            let result = self.handle(method, params, &mut outgoing);
+            // requests have an id and expect a response; notifications don't
+            if let Some(id) = message.get("id") {
+                let response = match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                };
+                outgoing.insert(0, response);
+            }
+            for message in outgoing {
+                write_message(&mut output, &message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a single request or notification.
+    ///
+    /// Any notifications to send to the client are added to `outgoing`.
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Value,
+        outgoing: &mut Vec<Value>,
+    ) -> Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str();
+        match (method, uri) {
+            ("initialize", _) => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {
+                        "openClose": true,
+                        "change": SYNC_FULL,
+                        "save": { "includeText": false },
+                    },
+                    "documentSymbolProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "fea-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            ("shutdown", _) => Ok(Value::Null),
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_owned(), text.into());
+                outgoing.push(self.publish_diagnostics(uri));
+                Ok(Value::Null)
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // with full sync, the last change contains the whole text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_owned(), text.into());
+                }
+                Ok(Value::Null)
+            }
+            ("textDocument/didSave", Some(uri)) => {
+                outgoing.push(self.publish_diagnostics(uri));
+                Ok(Value::Null)
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                outgoing.push(notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                ));
+                Ok(Value::Null)
+            }
+            ("textDocument/documentSymbol", Some(uri)) => {
+                let text = self.document(uri)?;
+                let (root, _) = parse::parse_single_source(&text);
+                Ok(Value::Array(document_symbols(&root, &LineIndex::new(text))))
+            }
+            ("textDocument/semanticTokens/full", Some(uri)) => {
+                let text = self.document(uri)?;
+                let (root, _) = parse::parse_single_source(&text);
+                Ok(json!({ "data": semantic_token_data(&root, &LineIndex::new(text)) }))
+            }
+            ("textDocument/definition", Some(uri)) => {
+                self.symbol_locations(uri, &params["position"], |symbol| {
+                    symbol.definitions.clone()
+                })
+            }
+            ("textDocument/references", Some(uri)) => {
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or_default();
+                self.symbol_locations(uri, &params["position"], |symbol| {
+                    let mut ranges = symbol.references.clone();
+                    if include_declaration {
+                        ranges.extend(symbol.definitions.iter().cloned());
+                        ranges.sort_by_key(|range| range.start);
+                    }
+                    ranges
+                })
+            }
+            ("textDocument/completion", Some(_)) => Ok(json!({
+                "isIncomplete": false,
+                "items": self.glyph_completions(),
+            })),
+            (method, _) if method.starts_with("$/") || !method.contains('/') => Ok(Value::Null),
+            (method, _) => Err((METHOD_NOT_FOUND, format!("unhandled method '{method}'"))),
+        }
+    }
+
+    fn document(&self, uri: &str) -> Result<Arc<str>, (i64, String)> {
+        self.documents
+            .get(uri)
+            .cloned()
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document '{uri}'")))
+    }
+
+    /// The locations of the symbol at `position` in a document, selected by
+    /// `ranges`, or `null` if there is no symbol there.
+    fn symbol_locations(
+        &self,
+        uri: &str,
+        position: &Value,
+        ranges: impl Fn(&Symbol) -> Vec<Range<usize>>,
+    ) -> Result<Value, (i64, String)> {
+        let text = self.document(uri)?;
+        let lines = LineIndex::new(text.clone());
+        let (Some(line), Some(character)) =
+            (position["line"].as_u64(), position["character"].as_u64())
+        else {
+            return Err((INVALID_PARAMS, "missing position".into()));
+        };
+        let (root, _) = parse::parse_single_source(&text);
+        let index = SymbolIndex::new(&root);
+        let offset = lines.offset_utf16(LineCol {
+            line: line as usize,
+            col: character as usize,
+        });
+        Ok(match index.symbol_at(offset) {
+            Some(symbol) => ranges(symbol)
+                .into_iter()
+                .map(|range| json!({ "uri": uri, "range": lsp_range(&lines, range) }))
+                .collect(),
+            None => Value::Null,
+        })
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let diagnostics = match self.documents.get(uri) {
+            Some(text) => self.diagnostics(uri, text.clone()),
+            None => Vec::new(),
+        };
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// Parse and validate a document, returning the diagnostics that belong
+    /// to it (and not to any files it includes).
+    fn diagnostics(&self, uri: &str, text: Arc<str>) -> Vec<Value> {
+        let path = uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri));
+        let lines = LineIndex::new(text.clone());
+        let resolver = DocumentResolver {
+            path: path.clone(),
+            text: text.clone(),
+            files: FileSystemResolver::new(path.parent().map(Path::to_owned).unwrap_or_default()),
+        };
+        let (tree, mut diagnostics) =
+            match parse::parse_root(path.clone(), self.glyph_map.as_ref(), Box::new(resolver)) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    return vec![lsp_diagnostic(
+                        &lines,
+                        0..0,
+                        Level::Error,
+                        DiagnosticCode::IncludeLoad.as_str(),
+                        &err.to_string(),
+                    )];
+                }
+            };
+        if let Some(glyph_map) = &self.glyph_map {
+            diagnostics.extend(compile::validate::<NopVariationInfo>(
+                &tree, glyph_map, None,
+            ));
+        }
+        let root_id = tree.sources.id_for_path(&path);
+        diagnostics
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| Some(diagnostic.message.file) == root_id)
+            .map(|diagnostic: &Diagnostic| {
+                let mut value = lsp_diagnostic(
+                    &lines,
+                    diagnostic.span(),
+                    diagnostic.level,
+                    diagnostic.code.as_str(),
+                    diagnostic.text(),
+                );
+                // related locations in other files would need their own line index
+                let related = diagnostic
+                    .related
+                    .iter()
+                    .filter(|related| Some(related.file) == root_id)
+                    .map(|related| {
+                        json!({
+                            "location": {
+                                "uri": uri,
+                                "range": lsp_range(&lines, related.span.range()),
+                            },
+                            "message": related.text,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                if !related.is_empty() {
+                    value["relatedInformation"] = related.into();
+                }
+                value
+            })
+            .collect()
+    }
+
+    fn glyph_completions(&self) -> Vec<Value> {
+        self.glyph_map
+            .iter()
+            .flat_map(GlyphMap::iter)
+            .filter_map(|glyph| match glyph {
+                GlyphIdent::Name(name) => {
+                    Some(json!({ "label": name.as_str(), "kind": COMPLETION_VALUE }))
+                }
+                GlyphIdent::Cid(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Resolves the root source to the text of an open document, and everything
+/// else from disk.
+struct DocumentResolver {
+    path: PathBuf,
+    text: Arc<str>,
+    files: FileSystemResolver,
+}
+
+impl SourceResolver for DocumentResolver {
+    fn get_contents(&self, path: &Path) -> Result<Arc<str>, SourceLoadError> {
+        if path == self.path {
+            Ok(self.text.clone())
+        } else {
+            self.files.get_contents(path)
+        }
+    }
+
+    fn resolve_raw_path(&self, path: &Path, included_from: Option<&Path>) -> PathBuf {
+        self.files.resolve_raw_path(path, included_from)
+    }
+}
+
+fn lsp_diagnostic(
+    lines: &LineIndex,
+    span: Range<usize>,
+    level: Level,
+    code: &str,
+    message: &str,
+) -> Value {
+    let severity = match level {
+        Level::Error => SEVERITY_ERROR,
+        Level::Warning => SEVERITY_WARNING,
+        Level::Info => SEVERITY_INFO,
+    };
+    json!({
+        "range": lsp_range(lines, span),
+        "severity": severity,
+        "code": code,
+        "source": "fea-rs",
+        "message": message,
+    })
+}
+
+/// The symbols defined in a node, nested according to the tree.
+fn document_symbols(node: &Node, lines: &LineIndex) -> Vec<Value> {
+    let mut symbols = Vec::new();
+    for child in node.iter_children() {
+        let Some(child) = child.as_node() else {
+            continue;
+        };
+        let children = document_symbols(child, lines);
+        match symbol_name(child) {
+            Some((name, kind, name_range)) => symbols.push(json!({
+                "name": name,
+                "kind": kind,
+                "range": lsp_range(lines, child.range()),
+                "selectionRange": lsp_range(lines, name_range),
+                "children": children,
+            })),
+            None => symbols.extend(children),
+        }
+    }
+    symbols
+}
+
+/// The name, symbol kind and name range of a node that defines something.
+fn symbol_name(node: &Node) -> Option<(String, u8, Range<usize>)> {
+    // these lookups don't use the typed AST, since they should also work for
+    // nodes that contain errors.
+    let find_token = |kind: Kind, after: Option<Kind>| {
+        node.iter_children()
+            .skip_while(|child| after.is_some_and(|after| child.kind() != after))
+            .find(|child| child.kind() == kind)
+            .and_then(NodeOrToken::into_token)
+            .map(|token| (token.as_str().to_owned(), token.range()))
+    };
+    let (symbol_kind, (name, range)) = match node.kind() {
+        Kind::FeatureNode => (SYMBOL_MODULE, find_token(Kind::Tag, None)?),
+        Kind::LookupBlockNode => (SYMBOL_FUNCTION, find_token(Kind::Label, None)?),
+        Kind::GlyphClassDefNode => (SYMBOL_CLASS, find_token(Kind::NamedGlyphClass, None)?),
+        Kind::MarkClassNode => (
+            SYMBOL_CLASS,
+            find_token(Kind::NamedGlyphClass, Some(Kind::AnchorNode))?,
+        ),
+        Kind::AnchorDefNode => (SYMBOL_CONSTANT, find_token(Kind::Ident, None)?),
+        _ => return None,
+    };
+    Some((name, symbol_kind, range))
+}
+
+/// Encode the semantic tokens in a tree as the LSP expects.
+///
+/// Each token is five numbers: the line and start character (relative to the
+/// previous token), the length, the token type, and modifiers. Tokens spanning
+/// more than one line are skipped.
+fn semantic_token_data(root: &Node, lines: &LineIndex) -> Vec<usize> {
+    let mut data = Vec::new();
+    let (mut prev_line, mut prev_start) = (0, 0);
+    for (range, kind) in parse::semantic_tokens(root) {
+        let LineCol { line, col: start } = lines.line_col_utf16(range.start);
+        let LineCol {
+            line: end_line,
+            col: end,
+        } = lines.line_col_utf16(range.end);
+        if end_line != line {
+            continue;
+        }
+        let delta_start = if line == prev_line {
+            start - prev_start
+        } else {
+            start
+        };
+        data.extend([
+            line - prev_line,
+            delta_start,
+            end - start,
+            token_type(kind),
+            0,
+        ]);
+        (prev_line, prev_start) = (line, start);
+    }
+    data
+}
+
+/// The index of this kind in [`TOKEN_TYPES`].
+fn token_type(kind: SemanticKind) -> usize {
+    match kind {
+        SemanticKind::Keyword => 0,
+        SemanticKind::GlyphName => 1,
+        SemanticKind::GlyphClass => 2,
+        SemanticKind::Tag => 3,
+        SemanticKind::Lookup => 4,
+        SemanticKind::Anchor => 5,
+        SemanticKind::Number => 6,
+        SemanticKind::String => 7,
+        SemanticKind::Comment => 8,
+    }
+}
+
+/// The LSP position (line, and UTF-16 code units) of a byte offset.
+fn position(lines: &LineIndex, offset: usize) -> Value {
+    let LineCol { line, col } = lines.line_col_utf16(offset);
+    json!({ "line": line, "character": col })
+}
+
+/// The LSP range of a span of bytes.
+fn lsp_range(lines: &LineIndex, range: Range<usize>) -> Value {
+    json!({ "start": position(lines, range.start), "end": position(lines, range.end) })
+}
+
+/// Convert a `file://` uri to a path, decoding any percent-escapes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Read a single message, returning `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(len) = content_length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message is missing Content-Length header",
+        ));
+    };
+    let mut content = vec![0; len];
+    input.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a server over these messages, returning everything it sends back.
+    fn run(glyph_map: Option<GlyphMap>, messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        Server::new(glyph_map)
+            .run(input.as_slice(), &mut output)
+            .unwrap();
+        let mut output = output.as_slice();
+        std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+    }
+
+    fn request(id: u32, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn did_open(uri: &str, text: &str) -> Value {
+        notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "fea", "version": 1, "text": text } }),
+        )
+    }
+
+    const URI: &str = "file:///tmp/fea-lsp-test/features.fea";
+
+    #[test]
+    fn initialize_and_shutdown() {
+        let output = run(
+            None,
+            &[
+                request(1, "initialize", json!({})),
+                notification("initialized", json!({})),
+                request(2, "shutdown", Value::Null),
+                notification("exit", Value::Null),
+                // never handled
+                request(3, "shutdown", Value::Null),
+            ],
+        );
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0]["id"], 1);
+        assert_eq!(
+            output[0]["result"]["capabilities"]["documentSymbolProvider"],
+            true
+        );
+        assert_eq!(
+            output[1],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+    }
+
+    #[test]
+    fn unknown_request() {
+        let output = run(None, &[request(1, "textDocument/hover", json!({}))]);
+        assert_eq!(output[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn diagnostics_on_open_and_save() {
+        let glyph_map = ["a", "b"].into_iter().collect::<GlyphMap>();
+        let save = notification(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": URI } }),
+        );
+        let output = run(
+            Some(glyph_map),
+            &[
+                did_open(URI, "feature liga {\n  sub a by c;\n} liga;\n"),
+                notification(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": URI, "version": 2 },
+                        "contentChanges": [{ "text": "feature liga {\n  sub a by b;\n} liga;\n" }],
+                    }),
+                ),
+                save.clone(),
+                notification(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": URI, "version": 3 },
+                        "contentChanges": [{ "text": "feature liga {\n  sub a by b\n} liga;\n" }],
+                    }),
+                ),
+                save,
+            ],
+        );
+        assert_eq!(output.len(), 3);
+        assert!(
+            output
+                .iter()
+                .all(|message| message["method"] == "textDocument/publishDiagnostics")
+        );
+
+        // an unknown glyph
+        let diagnostics = output[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({
+                "start": { "line": 1, "character": 11 },
+                "end": { "line": 1, "character": 12 },
+            })
+        );
+        // fixed
+        assert_eq!(output[1]["params"]["diagnostics"], json!([]));
+        // a syntax error
+        let diagnostics = output[2]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics[0]["code"], "unexpected-token");
+    }
+
+    #[test]
+    fn diagnostics_related_information() {
+        let glyph_map = ["a", "b"].into_iter().collect::<GlyphMap>();
+        let output = run(
+            Some(glyph_map),
+            &[did_open(
+                URI,
+                "@x = [a];\n@x = [b];\nfeature liga {\n  sub @x by a;\n} liga;\n",
+            )],
+        );
+        let diagnostics = output[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0]["code"], "duplicate-definition");
+        assert_eq!(
+            diagnostics[0]["relatedInformation"],
+            json!([{
+                "location": {
+                    "uri": URI,
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 2 },
+                    },
+                },
+                "message": "previous definition",
+            }])
+        );
+    }
+
+    #[test]
+    fn symbols() {
+        let fea = "\
+@vowels = [a e];
+markClass [acute] <anchor 0 500> @TOP;
+anchorDef 100 200 ANCHOR_1;
+
+lookup outer {
+    sub a by b;
+} outer;
+
+feature liga {
+    lookup inner {
+        sub a by b;
+    } inner;
+} liga;
+";
+        let output = run(
+            None,
+            &[
+                did_open(URI, fea),
+                request(
+                    1,
+                    "textDocument/documentSymbol",
+                    json!({ "textDocument": { "uri": URI } }),
+                ),
+            ],
+        );
+        let symbols = output[1]["result"].as_array().unwrap();
+        let names = symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol["name"].as_str().unwrap(),
+                    symbol["kind"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("@vowels", SYMBOL_CLASS as u64),
+                ("@TOP", SYMBOL_CLASS as u64),
+                ("ANCHOR_1", SYMBOL_CONSTANT as u64),
+                ("outer", SYMBOL_FUNCTION as u64),
+                ("liga", SYMBOL_MODULE as u64),
+            ]
+        );
+        let liga = &symbols[4];
+        assert_eq!(liga["children"][0]["name"], "inner");
+        assert_eq!(
+            liga["selectionRange"]["start"],
+            json!({ "line": 8, "character": 8 })
+        );
+    }
+
+    #[test]
+    fn semantic_tokens() {
+        let output = run(
+            None,
+            &[
+                did_open(URI, "feature liga {\n  sub f i by f_i;\n} liga;\n"),
+                request(
+                    1,
+                    "textDocument/semanticTokens/full",
+                    json!({ "textDocument": { "uri": URI } }),
+                ),
+            ],
+        );
+        let data = output[1]["result"]["data"].as_array().unwrap();
+        let data = data.iter().map(|x| x.as_u64().unwrap()).collect::<Vec<_>>();
+        #[rustfmt::skip]
+        assert_eq!(
+            data,
+            [
+                0, 0, 7, 0, 0, // feature
+                0, 8, 4, 3, 0, // liga
+                1, 2, 3, 0, 0, // sub
+                0, 4, 1, 1, 0, // f
+                0, 2, 1, 1, 0, // i
+                0, 2, 2, 0, 0, // by
+                0, 3, 3, 1, 0, // f_i
+                1, 2, 4, 3, 0, // liga
+            ]
+        );
+    }
+
+    #[test]
+    fn glyph_completion() {
+        let glyph_map = ["a", "b"].into_iter().collect::<GlyphMap>();
+        let output = run(
+            Some(glyph_map),
+            &[request(
+                1,
+                "textDocument/completion",
+                json!({
+                    "textDocument": { "uri": URI },
+                    "position": { "line": 0, "character": 0 },
+                }),
+            )],
+        );
+        let labels = output[0]["result"]["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["a", "b"]);
+    }
+
+    #[test]
+    fn definition_and_references() {
+        let fea = "\
+lookup kern1 {
+    pos a b 10;
+} kern1;
+feature kern {
+    lookup kern1;
+} kern;
+";
+        let position = json!({ "line": 4, "character": 12 });
+        let output = run(
+            None,
+            &[
+                did_open(URI, fea),
+                request(
+                    1,
+                    "textDocument/definition",
+                    json!({ "textDocument": { "uri": URI }, "position": position }),
+                ),
+                request(
+                    2,
+                    "textDocument/references",
+                    json!({
+                        "textDocument": { "uri": URI },
+                        "position": position,
+                        "context": { "includeDeclaration": true },
+                    }),
+                ),
+                request(
+                    3,
+                    "textDocument/definition",
+                    json!({
+                        "textDocument": { "uri": URI },
+                        "position": { "line": 1, "character": 5 },
+                    }),
+                ),
+            ],
+        );
+        assert_eq!(
+            output[1]["result"],
+            json!([{
+                "uri": URI,
+                "range": {
+                    "start": { "line": 0, "character": 7 },
+                    "end": { "line": 0, "character": 12 },
+                },
+            }])
+        );
+        let lines = output[2]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["range"]["start"]["line"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [0, 2, 4]);
+        // a glyph name is not a symbol
+        assert_eq!(output[3]["result"], Value::Null);
+    }
+
+    #[test]
+    fn line_index_offsets() {
+        let text = "ab\n\u{1F600}c\n";
+        let lines = LineIndex::new(text);
+        let offset = |line, col| lines.offset_utf16(LineCol { line, col });
+        assert_eq!(offset(0, 1), 1);
+        // past the end of a line
+        assert_eq!(offset(0, 10), 2);
+        // the emoji is two UTF-16 units
+        assert_eq!(offset(1, 2), 7);
+        assert_eq!(offset(5, 0), text.len());
+    }
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(
+            uri_to_path("file:///my%20fonts/features.fea"),
+            Some(PathBuf::from("/my fonts/features.fea"))
+        );
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+}
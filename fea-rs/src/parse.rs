@@ -5,9 +5,13 @@
 
 mod context;
 pub(crate) mod grammar;
+mod incremental;
 mod lexer;
+mod line_index;
 mod parser;
+mod semantic;
 mod source;
+mod symbols;
 mod tree;
 
 use std::{
@@ -15,11 +19,15 @@ use std::{
     sync::Arc,
 };
 
-pub use lexer::TokenSet;
+pub use incremental::{IncrementalParse, Reparsed};
+pub use lexer::{TokenSet, Tokens, lex};
+pub use line_index::{LineCol, LineIndex};
+pub use semantic::{SemanticKind, semantic_tokens};
 pub use source::{FileSystemResolver, SourceLoadError, SourceResolver};
+pub use symbols::{Symbol, SymbolIndex, SymbolKind};
 pub use tree::ParseTree;
 
-pub(crate) use context::{IncludeStatement, ParseContext};
+pub(crate) use context::{DEFAULT_MAX_INCLUDE_DEPTH, IncludeStatement, ParseContext};
 pub(crate) use parser::Parser;
 pub(crate) use source::{FileId, Source, SourceList, SourceMap};
 
@@ -73,7 +81,23 @@ pub fn parse_root(
     glyph_map: Option<&GlyphMap>,
     resolver: Box<dyn SourceResolver>,
 ) -> Result<(ParseTree, DiagnosticSet), SourceLoadError> {
-    context::ParseContext::parse(path, glyph_map, resolver).map(|ctx| ctx.generate_parse_tree())
+    context::ParseContext::parse(path, glyph_map, resolver, false)
+        .map(|ctx| ctx.generate_parse_tree())
+}
+
+/// Parse several root sources, as if they were concatenated in order.
+///
+/// This is useful when the features for a font come from more than one place,
+/// for instance a hand-written file along with generated kerning; each source
+/// can use the glyph classes and named lookups defined in the sources before
+/// it. Each path is resolved as a root path would be by [`parse_root`].
+pub fn parse_roots(
+    paths: &[PathBuf],
+    glyph_map: Option<&GlyphMap>,
+    resolver: Box<dyn SourceResolver>,
+) -> Result<(ParseTree, DiagnosticSet), SourceLoadError> {
+    context::ParseContext::parse_roots(paths, glyph_map, resolver, false)
+        .map(|ctx| ctx.generate_parse_tree())
 }
 
 /// Convenience method to parse a block of FEA from memory.
@@ -106,6 +130,20 @@ pub fn parse_string(text: impl Into<Arc<str>>) -> (ParseTree, DiagnosticSet) {
     .unwrap()
 }
 
+/// Parse a single source, without resolving any include statements.
+pub(crate) fn parse_single_source(text: &str) -> (crate::Node, Vec<crate::Diagnostic>) {
+    let mut sink = crate::token_tree::AstSink::new(text, FileId::CURRENT_FILE, None);
+    let mut parser = Parser::new(text, &mut sink);
+    grammar::root(&mut parser);
+    let (root, errs, _) = sink.finish();
+    (root, errs)
+}
+
+/// Returns `true` if `word` would be lexed as a keyword.
+pub(crate) fn is_keyword(word: &str) -> bool {
+    lexer::Kind::from_keyword(word.as_bytes()).is_some()
+}
+
 /// Parse an arbitrary block of FEA text with a specific parsing function.
 ///
 /// This can be used to parse any part of the grammar, including elements that
@@ -7,17 +7,27 @@ use std::{
     sync::Arc,
 };
 
+use smol_str::SmolStr;
+
 use super::source::{Source, SourceLoadError, SourceLoader, SourceResolver};
 use super::{FileId, ParseTree, Parser, SourceList, SourceMap};
 use crate::{
-    Diagnostic, DiagnosticSet, GlyphMap, Kind, Node,
+    Diagnostic, DiagnosticCode, DiagnosticSet, GlyphMap, Kind, Node,
     token_tree::{
         AstSink,
         typed::{self, AstNode as _},
     },
 };
 
-const MAX_INCLUDE_DEPTH: usize = 50;
+/// The default maximum nesting depth of include statements.
+///
+/// An include statement in the root file has a depth of 1, an include in
+/// that included file has a depth of 2, and so on; statements nested more
+/// deeply than this are errors.
+pub(crate) const DEFAULT_MAX_INCLUDE_DEPTH: usize = 50;
+
+/// The path of the source generated to include multiple roots.
+const ROOTS_PATH: &str = "<roots>";
 
 /// Oversees parsing, following, resolving and validating input statements.
 ///
@@ -58,19 +68,22 @@ pub(crate) struct ParseContext {
     sources: Arc<SourceList>,
     parsed_files: HashMap<FileId, (Node, Vec<Diagnostic>)>,
     graph: IncludeGraph,
+    max_include_depth: usize,
 }
 
 /// A simple graph of files and their includes.
 ///
 /// We maintain this in order to validate that the input does not contain
 /// any cyclical include statements, and does not exceed the maximum include
-/// depth of 50.
+/// depth.
 #[derive(Clone, Debug, Default)]
 struct IncludeGraph {
     // source file -> (destination file, span-in-source-for-error)
-    nodes: HashMap<FileId, Vec<(FileId, Range<usize>)>>,
+    nodes: HashMap<FileId, Vec<IncludeEdge>>,
 }
 
+type IncludeEdge = (FileId, Range<usize>);
+
 /// An include statement in a source file.
 pub struct IncludeStatement {
     pub(crate) stmt: typed::Include,
@@ -84,19 +97,27 @@ struct IncludeError {
     statement_idx: usize,
     range: Range<usize>,
     kind: IncludeErrorKind,
+    /// The files from the start of the problem to the included file.
+    ///
+    /// For a cycle this starts and ends with the same file; if the depth is
+    /// exceeded it starts at the root.
+    chain: Vec<FileId>,
+    /// The include statements that lead from the start of the chain to the
+    /// problem statement.
+    via: Vec<IncludeEdge>,
 }
 
 enum IncludeErrorKind {
     Cycle,
-    ToDeep,
+    ToDeep { max: usize },
 }
 
 impl IncludeStatement {
     /// The path part of the statement.
     ///
     /// For the statement `include(file.fea)`, this is `file.fea`.
-    fn path(&self) -> &str {
-        &self.stmt.path().text
+    fn path(&self) -> SmolStr {
+        self.stmt.path().text
     }
 
     /// The range of the entire include statement.
@@ -119,14 +140,76 @@ impl ParseContext {
     /// After parsing, you can call [`generate_parse_tree`] in order to generate
     /// a unified parse tree suitable for compilation.
     ///
+    /// If `liberal_glyph_names` is `true`, glyph names that are not allowed
+    /// by the spec but are accepted by feaLib are parsed with a warning.
+    ///
     /// [`generate_parse_tree`]: ParseContext::generate_parse_tree
     pub(crate) fn parse(
         path: PathBuf,
         glyph_map: Option<&GlyphMap>,
         resolver: Box<dyn SourceResolver>,
+        liberal_glyph_names: bool,
     ) -> Result<Self, SourceLoadError> {
         let mut sources = SourceLoader::new(resolver);
         let root_id = sources.source_for_path(&path, None)?;
+        Ok(Self::parse_from(
+            sources,
+            root_id,
+            false,
+            glyph_map,
+            liberal_glyph_names,
+        ))
+    }
+
+    /// Attempt to parse several root files, as if they were concatenated.
+    ///
+    /// This is implemented by generating a root source that includes each
+    /// of the roots in turn; these includes are resolved the same way as a
+    /// single root path would be, and not relative to one another. Each file
+    /// can reference the classes and lookups defined in the files before it.
+    ///
+    /// Paths that contain a closing parenthesis cannot be included, and are
+    /// reported as an error.
+    pub(crate) fn parse_roots(
+        paths: &[PathBuf],
+        glyph_map: Option<&GlyphMap>,
+        resolver: Box<dyn SourceResolver>,
+        liberal_glyph_names: bool,
+    ) -> Result<Self, SourceLoadError> {
+        let mut sources = SourceLoader::new(resolver);
+        let mut text = String::new();
+        for path in paths {
+            // make sure that we fail early if a root is missing
+            sources.source_for_path(path, None)?;
+            if path.as_os_str().to_string_lossy().contains(')') {
+                return Err(SourceLoadError::new(
+                    path.to_owned(),
+                    "cannot include a path containing ')'",
+                ));
+            }
+            text.push_str(&format!("include({});\n", path.display()));
+        }
+        let root_id = sources.add_generated(ROOTS_PATH.into(), text);
+        Ok(Self::parse_from(
+            sources,
+            root_id,
+            true,
+            glyph_map,
+            liberal_glyph_names,
+        ))
+    }
+
+    /// Parse the source with `root_id` and all the sources it includes.
+    ///
+    /// If `generated_root` is true, the includes in the root are resolved as
+    /// if they were themselves root paths.
+    fn parse_from(
+        mut sources: SourceLoader,
+        root_id: FileId,
+        generated_root: bool,
+        glyph_map: Option<&GlyphMap>,
+        liberal_glyph_names: bool,
+    ) -> Self {
         let mut queue = vec![(root_id, Kind::SourceFile)];
         let mut parsed_files = HashMap::new();
         let mut includes = IncludeGraph::default();
@@ -137,7 +220,8 @@ impl ParseContext {
                 continue;
             }
             let source = sources.get(&id).unwrap();
-            let (node, mut errors, include_stmts) = parse_src(source, glyph_map, scope);
+            let (node, mut errors, include_stmts) =
+                parse_src(source, glyph_map, scope, liberal_glyph_names);
             errors.iter_mut().for_each(|e| e.message.file = id);
 
             parsed_files.insert(source.id(), (node, errors));
@@ -147,31 +231,41 @@ impl ParseContext {
 
             // we need to drop `source` so we can mutate source_map below
             let source_id = source.id();
+            let included_by = (!generated_root || id != root_id).then_some(source_id);
 
             for include in &include_stmts {
-                match sources.source_for_path(Path::new(include.path()), Some(source_id)) {
+                match sources.source_for_path(Path::new(include.path().as_str()), included_by) {
                     Ok(included_id) => {
                         includes.add_edge(id, (included_id, include.stmt_range()));
                         queue.push((included_id, include.scope));
                     }
                     Err(e) => {
                         let range = include.path_range();
-                        parsed_files.get_mut(&id).unwrap().1.push(Diagnostic::error(
-                            id,
-                            range,
-                            e.to_string(),
-                        ));
+                        parsed_files.get_mut(&id).unwrap().1.push(
+                            Diagnostic::error(id, range, e.to_string())
+                                .with_code(DiagnosticCode::IncludeLoad),
+                        );
                     }
                 }
             }
         }
 
-        Ok(ParseContext {
+        ParseContext {
             root_id,
             sources: sources.into_inner(),
             parsed_files,
             graph: includes,
-        })
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+        }
+    }
+
+    /// Set the maximum depth of nested include statements.
+    ///
+    /// An include statement in the root file has a depth of 1. Statements that
+    /// exceed this are reported as errors when the tree is generated.
+    pub(crate) fn with_max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = max_include_depth;
+        self
     }
 
     pub(crate) fn root_id(&self) -> FileId {
@@ -188,29 +282,46 @@ impl ParseContext {
             .flat_map(|(_, (_, errs))| errs.iter())
             .cloned()
             .collect::<Vec<_>>();
-        let include_errors = self.graph.validate(self.root_id());
+        let include_errors = self.graph.validate(self.root_id(), self.max_include_depth);
         // record any errors:
         for IncludeError {
-            file, range, kind, ..
+            file,
+            range,
+            kind,
+            chain,
+            via,
+            ..
         } in &include_errors
         {
-            // find statement
-            let message = match kind {
-                IncludeErrorKind::Cycle => "cyclical include statement",
-                IncludeErrorKind::ToDeep => "exceded maximum include depth",
+            let chain = chain
+                .iter()
+                .map(|id| match self.sources.get(id) {
+                    Some(source) => source.path().display().to_string(),
+                    None => "<unknown>".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let (code, message) = match kind {
+                IncludeErrorKind::Cycle => (
+                    DiagnosticCode::IncludeCycle,
+                    format!("cyclical include statement: {chain}"),
+                ),
+                IncludeErrorKind::ToDeep { max } => (
+                    DiagnosticCode::IncludeTooDeep,
+                    format!("exceeded maximum include depth of {max}: {chain}"),
+                ),
             };
-            all_errors.push(Diagnostic::error(*file, range.clone(), message));
+            let diagnostic = via.iter().fold(
+                Diagnostic::error(*file, range.clone(), message).with_code(code),
+                |diagnostic, (file, range)| {
+                    diagnostic.with_related(*file, range.clone(), "included here")
+                },
+            );
+            all_errors.push(diagnostic);
         }
 
         let mut map = SourceMap::default();
-        let mut root = self.generate_recurse(self.root_id(), &include_errors, &mut map, 0);
-        let needs_update_positions = self.parsed_files.len() > 1;
-        // we need to do this before updating positions, since it mutates and
-        // requires that there exist only one reference (via Arc) to the node
-        drop(self.parsed_files);
-        if needs_update_positions {
-            root.update_positions_from_root();
-        }
+        let root = self.generate_recurse(self.root_id(), &include_errors, &mut map, 0);
 
         let diagnostics = DiagnosticSet {
             messages: all_errors,
@@ -278,11 +389,11 @@ impl ParseContext {
 }
 
 impl IncludeGraph {
-    fn add_edge(&mut self, from: FileId, to: (FileId, Range<usize>)) {
+    fn add_edge(&mut self, from: FileId, to: IncludeEdge) {
         self.nodes.entry(from).or_default().push(to);
     }
 
-    fn includes_for_file(&self, file: FileId) -> Option<&[(FileId, Range<usize>)]> {
+    fn includes_for_file(&self, file: FileId) -> Option<&[IncludeEdge]> {
         self.nodes.get(&file).map(|f| f.as_slice())
     }
 
@@ -291,7 +402,7 @@ impl IncludeGraph {
     /// If the result is non-empty, each returned error should be converted to
     /// d to diagnostics by the caller, and those statements should
     /// not be resolved when building the final tree.
-    fn validate(&self, root: FileId) -> Vec<IncludeError> {
+    fn validate(&self, root: FileId, max_depth: usize) -> Vec<IncludeError> {
         let edges = match self.nodes.get(&root) {
             None => return Vec::new(),
             Some(edges) => edges,
@@ -305,12 +416,16 @@ impl IncludeGraph {
             if let Some((child, stmt)) = edges.get(cur_edge) {
                 // push parent, advancing idx
                 stack.push((node, edges, cur_edge + 1));
-                if stack.len() >= MAX_INCLUDE_DEPTH - 1 {
+                // the stack holds every file from the root to this one, so
+                // its length is the depth of the included file.
+                if stack.len() > max_depth {
                     bad_edges.push(IncludeError {
                         file: node,
                         statement_idx: cur_edge,
                         range: stmt.clone(),
-                        kind: IncludeErrorKind::ToDeep,
+                        kind: IncludeErrorKind::ToDeep { max: max_depth },
+                        chain: include_chain(&stack, root, *child),
+                        via: include_statements(&stack, root),
                     });
                     continue;
                 }
@@ -327,6 +442,8 @@ impl IncludeGraph {
                         statement_idx: cur_edge,
                         range: stmt.clone(),
                         kind: IncludeErrorKind::Cycle,
+                        chain: include_chain(&stack, *child, *child),
+                        via: include_statements(&stack, *child),
                     });
                 }
             }
@@ -335,15 +452,49 @@ impl IncludeGraph {
     }
 }
 
+/// The files on the stack, starting from `start`, followed by `child`.
+fn include_chain<T>(stack: &[(FileId, T, usize)], start: FileId, child: FileId) -> Vec<FileId> {
+    let start_idx = stack
+        .iter()
+        .position(|(id, _, _)| *id == start)
+        .unwrap_or_default();
+    stack[start_idx..]
+        .iter()
+        .map(|(id, _, _)| *id)
+        .chain(Some(child))
+        .collect()
+}
+
+/// The include statements on the stack, starting from `start`.
+///
+/// The last item on the stack is the file containing the current statement,
+/// which is not included.
+fn include_statements(
+    stack: &[(FileId, &Vec<IncludeEdge>, usize)],
+    start: FileId,
+) -> Vec<IncludeEdge> {
+    let start_idx = stack
+        .iter()
+        .position(|(id, _, _)| *id == start)
+        .unwrap_or_default();
+    stack[start_idx..stack.len().saturating_sub(1)]
+        .iter()
+        // the stack holds the index of the next statement in each file
+        .map(|(id, edges, next_edge)| (*id, edges[next_edge - 1].1.clone()))
+        .collect()
+}
+
 /// Parse a single source file.
 fn parse_src(
     src: &Source,
     glyph_map: Option<&GlyphMap>,
     scope: Kind,
+    liberal_glyph_names: bool,
 ) -> (Node, Vec<Diagnostic>, Vec<IncludeStatement>) {
     let mut sink = AstSink::new(src.text(), src.id(), glyph_map);
     {
-        let mut parser = Parser::new(src.text(), &mut sink);
+        let mut parser =
+            Parser::new(src.text(), &mut sink).with_liberal_glyph_names(liberal_glyph_names);
         match scope {
             Kind::FeatureNode => {
                 parser.start_node(Kind::SourceFile);
@@ -366,7 +517,7 @@ fn parse_src(
 mod tests {
     use super::*;
     use crate::{
-        Kind,
+        Kind, NodeOrToken,
         token_tree::{TreeBuilder, typed},
     };
 
@@ -391,16 +542,54 @@ mod tests {
             builder.finish_node(false, None);
             builder.finish()
         };
-        let statement = typed::Include::cast(&statement.into()).unwrap();
+        let statement = typed::Include::try_from_node(&statement).unwrap();
         let mut graph = IncludeGraph::default();
         graph.add_edge(a, (b, statement.range()));
         graph.add_edge(b, (c, statement.range()));
         graph.add_edge(c, (d, statement.range()));
         graph.add_edge(d, (b, statement.range()));
 
-        let result = graph.validate(a);
+        let result = graph.validate(a, DEFAULT_MAX_INCLUDE_DEPTH);
         assert_eq!(result[0].file, d);
         assert_eq!(result[0].range, 0..18);
+        assert_eq!(result[0].chain, [b, c, d, b]);
+        assert_eq!(
+            result[0].via,
+            [(b, statement.range()), (c, statement.range())]
+        );
+
+        let result = graph.validate(a, 2);
+        assert!(matches!(
+            result[0].kind,
+            IncludeErrorKind::ToDeep { max: 2 }
+        ));
+        assert_eq!(result[0].file, c);
+        assert_eq!(result[0].chain, [a, b, c, d]);
+        assert_eq!(
+            result[0].via,
+            [(a, statement.range()), (b, statement.range())]
+        );
+    }
+
+    #[test]
+    fn max_include_depth_boundary() {
+        let ids = make_ids::<{ DEFAULT_MAX_INCLUDE_DEPTH + 2 }>();
+        let mut graph = IncludeGraph::default();
+        for pair in ids.windows(2) {
+            graph.add_edge(pair[0], (pair[1], 0..1));
+        }
+
+        // the last file is one level deeper than the limit
+        let result = graph.validate(ids[0], DEFAULT_MAX_INCLUDE_DEPTH);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file, ids[DEFAULT_MAX_INCLUDE_DEPTH]);
+        assert_eq!(result[0].chain, ids);
+
+        assert!(
+            graph
+                .validate(ids[0], DEFAULT_MAX_INCLUDE_DEPTH + 1)
+                .is_empty()
+        );
     }
 
     #[test]
@@ -416,13 +605,65 @@ mod tests {
                     std::io::Error::new(std::io::ErrorKind::NotFound, "oh no"),
                 )),
             }),
+            false,
         )
         .unwrap();
         let (resolved, errs) = parse.generate_parse_tree();
         assert_eq!(errs.len(), 1);
+        // the root is only marked as seen once it is included, so the cycle
+        // is reported at the second include of 'bb'
+        assert_eq!(
+            errs.diagnostics()[0].text(),
+            "cyclical include statement: bb -> a -> bb"
+        );
         assert_eq!(resolved.root.text_len(), "include(bb);".len());
     }
 
+    #[test]
+    fn multiple_roots() {
+        let parse = ParseContext::parse_roots(
+            &["a".into(), "dir/b".into()],
+            None,
+            Box::new(|path: &Path| match path.to_str().unwrap() {
+                "a" => Ok("@left = [a b];\n".into()),
+                "dir/b" => Ok("include(c);\n".into()),
+                "c" => Ok("feature kern { pos @left c 20; } kern;\n".into()),
+                _ => Err(SourceLoadError::new(
+                    path.to_owned(),
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "oh no"),
+                )),
+            }),
+            false,
+        )
+        .unwrap();
+        let (resolved, errs) = parse.generate_parse_tree();
+        assert!(errs.is_empty(), "{errs:?}");
+        let top_level_nodes = resolved
+            .root
+            .iter_children()
+            .filter_map(NodeOrToken::into_node)
+            .map(|node| node.kind())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            top_level_nodes,
+            [Kind::GlyphClassDefNode, Kind::FeatureNode]
+        );
+
+        let missing = ParseContext::parse_roots(
+            &["a".into(), "nope".into()],
+            None,
+            Box::new(|path: &Path| match path.to_str().unwrap() {
+                "a" => Ok("".into()),
+                _ => Err(SourceLoadError::new(
+                    path.to_owned(),
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "oh no"),
+                )),
+            }),
+            false,
+        );
+        assert!(missing.is_err());
+    }
+
     #[test]
     fn assembly_basic() {
         let file_a = "\
@@ -447,6 +688,7 @@ mod tests {
                     std::io::Error::new(std::io::ErrorKind::NotFound, "oh no"),
                 )),
             }),
+            false,
         )
         .unwrap();
 
@@ -459,7 +701,7 @@ mod tests {
         let top_level_nodes = resolved
             .root
             .iter_children()
-            .filter_map(|n| n.as_node())
+            .filter_map(NodeOrToken::into_node)
             .collect::<Vec<_>>();
         let inter_node_len = "\n# hmm\n".len();
         assert_eq!(top_level_nodes.len(), 2);
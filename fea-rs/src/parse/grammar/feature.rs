@@ -1,6 +1,7 @@
 use super::super::lexer::{Kind as LexemeKind, TokenSet};
 use super::{glyph, gpos, gsub, metrics};
 
+use crate::DiagnosticCode;
 use crate::parse::Parser;
 use crate::token_tree::Kind;
 
@@ -17,13 +18,21 @@ pub(crate) fn feature(parser: &mut Parser) {
         if !parser.expect_recover(Kind::RBrace, TokenSet::TOP_SEMI)
             && let Some(tag) = open_tag.as_ref()
         {
-            parser.raw_error(tag.range.clone(), "Feature block is unclosed");
+            parser.raw_error(
+                DiagnosticCode::UnterminatedBlock,
+                tag.range.clone(),
+                "Feature block is unclosed",
+            );
         };
         let close_tag = parser.expect_tag(TokenSet::TOP_LEVEL);
         if let (Some(open), Some(close)) = (open_tag, close_tag)
             && open.tag != close.tag
         {
-            parser.raw_error(close.range, format!("expected tag '{}'", open.tag));
+            parser.raw_error(
+                DiagnosticCode::MismatchedTag,
+                close.range,
+                format!("expected tag '{}'", open.tag),
+            );
         }
         parser.expect_semi();
     }
@@ -59,7 +68,11 @@ pub(crate) fn lookup_block(parser: &mut Parser, recovery: TokenSet) {
         while !parser.at_eof() && !parser.matches(0, Kind::RBrace) {
             if !statement(parser, recovery, true) {
                 if let Some(range) = raw_label_range {
-                    parser.raw_error(range, "Table is unclosed");
+                    parser.raw_error(
+                        DiagnosticCode::UnterminatedBlock,
+                        range,
+                        "Table is unclosed",
+                    );
                 }
                 break;
             }
@@ -97,7 +110,7 @@ pub(crate) fn statement(parser: &mut Parser, recovery: TokenSet, in_lookup: bool
             parser.expect_recover(Kind::Semi, recovery);
         }),
         Kind::LookupKw if in_lookup => {
-            parser.err_and_bump("lookups cannot be nested.");
+            parser.err_and_bump(DiagnosticCode::InvalidSyntax, "lookups cannot be nested.");
             parser.eat_until(recovery);
         }
         Kind::IncludeKw => super::include(parser),
@@ -126,20 +139,29 @@ pub(crate) fn statement(parser: &mut Parser, recovery: TokenSet, in_lookup: bool
             parser.expect_recover(Kind::Semi, recovery);
         }),
         Kind::CvParametersKw if in_lookup => {
-            parser.err_and_bump("'cvParameters' invalid in lookup block");
+            parser.err_and_bump(
+                DiagnosticCode::InvalidSyntax,
+                "'cvParameters' invalid in lookup block",
+            );
             parser.eat_until(recovery);
         }
         Kind::CvParametersKw => cv_parameters(parser, recovery),
         Kind::FeatureNamesKw => feature_names(parser, recovery),
         Kind::Semi => {
-            parser.warn("';' should only follow a statement");
+            parser.warn(
+                DiagnosticCode::NonStandardSyntax,
+                "';' should only follow a statement",
+            );
             parser.eat_raw();
         }
 
         _ => {
             let token = parser.current_token_text();
             let scope = if in_lookup { "lookup" } else { "feature" };
-            parser.err(format!("'{token}' Not valid in a {scope} block"));
+            parser.err(
+                DiagnosticCode::InvalidSyntax,
+                format!("'{token}' Not valid in a {scope} block"),
+            );
             parser.eat_until(TokenSet::TOP_AND_FEATURE.add(LexemeKind::RBrace));
         }
     }
@@ -152,15 +174,23 @@ pub(crate) fn pos_or_sub_rule(parser: &mut Parser, recovery: TokenSet) {
         Kind::EnumKw if parser.nth(1).kind.to_token_kind() == Kind::PosKw => {
             gpos::gpos_rule(parser, recovery)
         }
-        Kind::EnumKw => parser.err_and_bump("'enum' keyword must be followed by position rule"),
+        Kind::EnumKw => parser.err_and_bump(
+            DiagnosticCode::UnexpectedToken,
+            "'enum' keyword must be followed by position rule",
+        ),
         Kind::IgnoreKw => match parser.nth(1).kind.to_token_kind() {
             Kind::PosKw => gpos::gpos_rule(parser, recovery),
             Kind::SubKw => gsub::gsub_rule(parser, recovery),
-            _ => parser
-                .err_and_bump("'ignore' keyword must be followed by position or substitution rule"),
+            _ => parser.err_and_bump(
+                DiagnosticCode::UnexpectedToken,
+                "'ignore' keyword must be followed by position or substitution rule",
+            ),
         },
         Kind::SubKw | Kind::RsubKw => gsub::gsub_rule(parser, recovery),
-        other => panic!("'{other}' is not a valid gpos or gsub token"),
+        other => parser.err_and_bump(
+            DiagnosticCode::UnexpectedToken,
+            format!("'{other}' is not a valid gpos or gsub token"),
+        ),
     }
 }
 fn name_entry(parser: &mut Parser, recovery: TokenSet) {
@@ -217,7 +247,10 @@ fn cv_parameters(parser: &mut Parser, recovery: TokenSet) {
                 parser.expect_semi();
             });
         } else {
-            parser.err_and_bump("token not valid in cvParameters block");
+            parser.err_and_bump(
+                DiagnosticCode::InvalidSyntax,
+                "token not valid in cvParameters block",
+            );
             parser.eat_until(recovery);
             parser.eat(Kind::Semi);
         }
@@ -255,7 +288,10 @@ fn lookupflag(parser: &mut Parser, recovery: TokenSet) {
                 if !parser.eat(Kind::NamedGlyphClass)
                     && !glyph::eat_glyph_class_list(parser, recovery)
                 {
-                    parser.err("lookupflag '{}' must be followed by a glyph class.");
+                    parser.err(
+                        DiagnosticCode::UnexpectedToken,
+                        "lookupflag '{}' must be followed by a glyph class.",
+                    );
                 }
                 true
             }
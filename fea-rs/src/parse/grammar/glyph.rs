@@ -1,3 +1,4 @@
+use crate::DiagnosticCode;
 use crate::parse::{
     Parser,
     lexer::{Kind, TokenSet},
@@ -24,6 +25,7 @@ pub(crate) fn named_glyph_class_decl(parser: &mut Parser, recovery: TokenSet) {
             // noop
         } else if !parser.matches(0, Kind::LSquare) {
             parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
                 "Expected named glyph class or '['.",
                 recovery.add(Kind::Semi),
             );
@@ -48,7 +50,11 @@ pub(crate) fn expect_glyph_or_glyph_class(parser: &mut Parser, recovery: TokenSe
         return true;
     }
 
-    parser.err_recover("Expected glyph or glyph class", recovery);
+    parser.err_recover(
+        DiagnosticCode::UnexpectedToken,
+        "Expected glyph or glyph class",
+        recovery,
+    );
     false
 }
 
@@ -61,7 +67,11 @@ pub(crate) fn expect_named_or_unnamed_glyph_class(parser: &mut Parser, recovery:
         return true;
     }
 
-    parser.err_recover("Expected glyph class", recovery);
+    parser.err_recover(
+        DiagnosticCode::UnexpectedToken,
+        "Expected glyph class",
+        recovery,
+    );
     false
 }
 
@@ -78,10 +88,17 @@ pub(crate) fn eat_glyph_class_list(parser: &mut Parser, recovery: TokenSet) -> b
         super::greedy(glyph_class_list_member)(parser, recovery);
 
         if !parser.eat(Kind::RSquare) {
-            parser.err("Unexpected token, expected glyph or glyph class");
+            parser.err(
+                DiagnosticCode::UnexpectedToken,
+                "Unexpected token, expected glyph or glyph class",
+            );
             parser.eat_until(recovery);
             if !parser.eat(Kind::RSquare) {
-                parser.raw_error(range, "Unclosed glyph class.")
+                parser.raw_error(
+                    DiagnosticCode::UnterminatedBlock,
+                    range,
+                    "Unclosed glyph class.",
+                )
             }
         }
     });
@@ -126,7 +143,11 @@ pub(crate) fn expect_glyph_name_like(parser: &mut Parser, recovery: TokenSet) ->
         return true;
     }
 
-    parser.err_recover("Expected glyph name or CID", recovery);
+    parser.err_recover(
+        DiagnosticCode::UnexpectedToken,
+        "Expected glyph name or CID",
+        recovery,
+    );
     false
 }
 
@@ -137,7 +158,10 @@ pub(crate) fn eat_glyph_name_like(parser: &mut Parser) -> bool {
     } else if parser.matches(0, Kind::NullKw) {
         // this is not technically allowed but is common in noto fonts
         // and accepted by feaLib so we will accept it as well
-        parser.warn(" when used as glyph name 'NULL' should be escaped ('\\NULL')");
+        parser.warn(
+            DiagnosticCode::NonStandardSyntax,
+            " when used as glyph name 'NULL' should be escaped ('\\NULL')",
+        );
         parser.eat_remap(Kind::NullKw, AstKind::GlyphName);
         true
     } else {
@@ -148,7 +172,19 @@ pub(crate) fn eat_glyph_name_like(parser: &mut Parser) -> bool {
 fn eat_and_validate_glyph_name(parser: &mut Parser) {
     debug_assert!(parser.matches(0, TokenSet::IDENT_LIKE));
     let raw = parser.nth_raw(0);
-    match validate_glyph_name(raw) {
+    let mut name_type = validate_glyph_name(raw);
+    if matches!(name_type, NameType::Invalid(_)) && parser.liberal_glyph_names() {
+        let liberal = validate_liberal_glyph_name(raw);
+        if !matches!(liberal, NameType::Invalid(_)) {
+            let name = String::from_utf8_lossy(raw).into_owned();
+            parser.warn(
+                DiagnosticCode::NonStandardSyntax,
+                format!("glyph name '{name}' is not allowed by the spec"),
+            );
+            name_type = liberal;
+        }
+    }
+    match name_type {
         NameType::Valid => {
             parser.eat_remap(TokenSet::IDENT_LIKE, AstKind::GlyphName);
         }
@@ -156,6 +192,7 @@ fn eat_and_validate_glyph_name(parser: &mut Parser) {
             parser.eat_remap(TokenSet::IDENT_LIKE, AstKind::GlyphNameOrRange);
         }
         NameType::Invalid(pos) => {
+            let raw = parser.nth_raw(0);
             let err = match std::str::from_utf8(&raw[pos..])
                 .ok()
                 .and_then(|t| t.chars().next())
@@ -163,7 +200,7 @@ fn eat_and_validate_glyph_name(parser: &mut Parser) {
                 Some(chr) => format!("Invalid char '{chr}' in glyph name"),
                 None => "Invalid char in glyph name".to_string(),
             };
-            parser.err_and_bump(err);
+            parser.err_and_bump(DiagnosticCode::InvalidToken, err);
         }
     }
 }
@@ -210,6 +247,28 @@ fn validate_glyph_name(name: &[u8]) -> NameType {
         _ => NameType::Invalid(0),
     }
 }
+
+/// Validate a name using the more permissive rules of feaLib.
+///
+/// This is a superset of what is allowed by the spec, and is only used if
+/// liberal glyph names are enabled. feaLib also allows '*' and '+', but our
+/// lexer always splits those into their own tokens.
+fn validate_liberal_glyph_name(name: &[u8]) -> NameType {
+    let mut range = false;
+    for (idx, byte) in name.iter().enumerate() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'.' | b'_' | b':' | b'^' | b'|' | b'~' | b'!' => (),
+            b'0'..=b'9' | b'/' if idx > 0 => (),
+            b'-' if idx > 0 => range = true,
+            _ => return NameType::Invalid(idx),
+        }
+    }
+    if range {
+        NameType::MaybeRange
+    } else {
+        NameType::Valid
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -246,6 +305,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn liberal_names() {
+        for raw in [".hi", "hi!", "a/b", "^x"] {
+            let mut sink = AstSink::new(raw, FileId::CURRENT_FILE, None);
+            let mut parser = Parser::new(raw, &mut sink).with_liberal_glyph_names(true);
+            parser.start_node(AstKind::SourceFile);
+            assert!(eat_glyph_name_like(&mut parser));
+            parser.finish_node();
+            let (node, errs, _) = sink.finish();
+            assert_eq!(errs.len(), 1, "'{raw}'");
+            assert!(!errs[0].is_error(), "'{raw}'");
+            let token = node.cursor().next_token().unwrap();
+            assert_eq!(token.kind, AstKind::GlyphName, "'{raw}'");
+        }
+
+        // still not allowed
+        for raw in ["hî", "a$b"] {
+            let mut sink = AstSink::new(raw, FileId::CURRENT_FILE, None);
+            let mut parser = Parser::new(raw, &mut sink).with_liberal_glyph_names(true);
+            eat_glyph_name_like(&mut parser);
+            assert!(sink.errors()[0].is_error(), "'{raw}'");
+        }
+    }
+
     #[test]
     fn disambiguate_range() {
         let fea = "[a-b]";
@@ -1,5 +1,6 @@
 use super::{glyph, metrics};
 
+use crate::DiagnosticCode;
 use crate::parse::{
     Parser,
     lexer::{Kind, TokenSet},
@@ -168,7 +169,7 @@ fn finish_chain_rule(parser: &mut Parser, recovery: TokenSet) -> AstKind {
     super::greedy(glyph::eat_glyph_or_glyph_class)(parser, recovery.union(RECOVERY));
 
     if !parser.matches(0, Kind::SingleQuote) {
-        parser.err("expected marked glyph");
+        parser.err(DiagnosticCode::UnexpectedToken, "expected marked glyph");
         parser.eat_until(recovery);
         return AstKind::GposNode;
     }
@@ -201,7 +202,11 @@ fn finish_chain_rule(parser: &mut Parser, recovery: TokenSet) -> AstKind {
 fn eat_lookup(parser: &mut Parser, recovery: TokenSet) -> bool {
     if parser.eat(Kind::LookupKw) {
         if !parser.eat(Kind::Ident) {
-            parser.err_recover("expected named lookup", recovery);
+            parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
+                "expected named lookup",
+                recovery,
+            );
         }
         return true;
     }
@@ -1,5 +1,6 @@
 use super::glyph;
 
+use crate::DiagnosticCode;
 use crate::parse::{
     Parser,
     lexer::{Kind, TokenSet},
@@ -29,7 +30,10 @@ pub(crate) fn gsub_rule(parser: &mut Parser, recovery: TokenSet) {
 
         let target_is_class = matches!(parser.nth(0).kind, Kind::LSquare | Kind::NamedGlyphClass);
         if !glyph::eat_glyph_or_glyph_class(parser, recovery.union(RECOVERY)) {
-            parser.err_and_bump("Expected glyph or glyph class");
+            parser.err_and_bump(
+                DiagnosticCode::UnexpectedToken,
+                "Expected glyph or glyph class",
+            );
             parser.eat_until(recovery.union(Kind::Semi.into()));
             return AstKind::GsubNode;
         }
@@ -64,7 +68,10 @@ pub(crate) fn gsub_rule(parser: &mut Parser, recovery: TokenSet) {
             parser.expect_semi();
             return AstKind::GsubType3;
         } else if parser.matches(0, Kind::FromKw) {
-            parser.err_and_bump("'from' can only follow glyph, not glyph class");
+            parser.err_and_bump(
+                DiagnosticCode::InvalidSyntax,
+                "'from' can only follow glyph, not glyph class",
+            );
             parser.eat_until(recovery.union(Kind::Semi.into()));
             return AstKind::GsubNode;
         }
@@ -84,9 +91,15 @@ pub(crate) fn gsub_rule(parser: &mut Parser, recovery: TokenSet) {
             finish_chain_rule(parser, recovery)
         } else {
             if parser.matches(0, Kind::ByKw) {
-                parser.err("ligature substitution must replace two or more glyphs");
+                parser.err(
+                    DiagnosticCode::InvalidRule,
+                    "ligature substitution must replace two or more glyphs",
+                );
             } else {
-                parser.err("expected ligature substitution or marked glyph");
+                parser.err(
+                    DiagnosticCode::UnexpectedToken,
+                    "expected ligature substitution or marked glyph",
+                );
             }
             parser.eat_until(recovery.union(Kind::Semi.into()));
             AstKind::GsubNode
@@ -107,7 +120,7 @@ fn finish_chain_rule(parser: &mut Parser, recovery: TokenSet) -> AstKind {
     while parser.eat(Kind::SingleQuote) {
         while parser.eat(Kind::LookupKw) {
             if !parser.eat(Kind::Ident) {
-                parser.err("expected named lookup");
+                parser.err(DiagnosticCode::UnexpectedToken, "expected named lookup");
                 parser.eat_until(recovery);
                 return AstKind::GsubNode;
             }
@@ -175,14 +188,17 @@ fn parse_rsub(parser: &mut Parser, recovery: TokenSet) -> AstKind {
     super::greedy(glyph::eat_glyph_or_glyph_class)(parser, recovery);
 
     if parser.matches(0, Kind::SingleQuote) {
-        parser.err("reversesub rule can have only one marked glyph");
+        parser.err(
+            DiagnosticCode::InvalidRule,
+            "reversesub rule can have only one marked glyph",
+        );
         parser.eat_until(recovery);
         parser.expect_semi();
         return AstKind::GsubNode;
     }
     if parser.eat(Kind::ByKw) {
         if parser.matches(0, Kind::NullKw) {
-            parser.err("Although explicitly part of the FEA spec, 'by NULL' in rsub rules is meaningless.\nSee https://github.com/fonttools/fonttools/issues/2952 for more information");
+            parser.err(DiagnosticCode::InvalidRule, "Although explicitly part of the FEA spec, 'by NULL' in rsub rules is meaningless.\nSee https://github.com/fonttools/fonttools/issues/2952 for more information");
             parser.eat_until(recovery);
             parser.expect_semi();
             return AstKind::GsubNode;
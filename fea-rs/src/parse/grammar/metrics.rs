@@ -1,5 +1,6 @@
 use std::ops::Range;
 
+use crate::DiagnosticCode;
 use crate::parse::{
     Parser,
     lexer::{Kind, TokenSet},
@@ -43,7 +44,11 @@ pub(crate) fn expect_value_record(parser: &mut Parser, recovery: TokenSet) -> bo
     if eat_value_record(parser, recovery) {
         true
     } else {
-        parser.err_recover("expected valuerecord", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "expected valuerecord",
+            recovery,
+        );
         false
     }
 }
@@ -103,7 +108,11 @@ pub(crate) fn eat_value_record(parser: &mut Parser, recovery: TokenSet) -> bool
 
 pub(crate) fn expect_metric(parser: &mut Parser, recovery: TokenSet) -> bool {
     if !eat_metric(parser, recovery) {
-        parser.err_recover("expected metric (scalar or variable)", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "expected metric (scalar or variable)",
+            recovery,
+        );
         return false;
     }
     true
@@ -165,7 +174,11 @@ pub(crate) fn expect_glyphs_number_value(parser: &mut Parser, recovery: TokenSet
                 parser.expect_recover(Kind::RBrace, recovery) && looks_okay
             })
         } else {
-            parser.err_recover("expected '$ident' or '${predicate}'", recovery);
+            parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
+                "expected '$ident' or '${predicate}'",
+                recovery,
+            );
             false
         }
     })
@@ -179,7 +192,7 @@ fn eat_expr_body(parser: &mut Parser) -> bool {
     loop {
         if parser.matches(0, Kind::RBrace) {
             if !after_ident_or_number {
-                parser.err("expected ident or number");
+                parser.err(DiagnosticCode::UnexpectedToken, "expected ident or number");
                 return false;
             }
             return true;
@@ -198,7 +211,7 @@ fn eat_expr_body(parser: &mut Parser) -> bool {
         }
         if parser.eat(TokenSet::FLOAT_LIKE) {
             if after_ident_or_number {
-                parser.err("expected '}' or operator");
+                parser.err(DiagnosticCode::UnexpectedToken, "expected '}' or operator");
                 return false;
             }
             after_ident_or_number = true;
@@ -206,7 +219,7 @@ fn eat_expr_body(parser: &mut Parser) -> bool {
         }
         if parser.eat(TokenSet::OPERATORS) {
             if !after_ident_or_number {
-                parser.err("expected ident or number");
+                parser.err(DiagnosticCode::UnexpectedToken, "expected ident or number");
                 return false;
             }
             after_ident_or_number = false;
@@ -215,7 +228,7 @@ fn eat_expr_body(parser: &mut Parser) -> bool {
         if parser.matches(0, Kind::Ident) {
             if !parser.current_token_text().contains(['-', '/']) {
                 if after_ident_or_number {
-                    parser.err("expected '}' or operator");
+                    parser.err(DiagnosticCode::UnexpectedToken, "expected '}' or operator");
                     return false;
                 }
                 parser.eat_remap(Kind::Ident, AstKind::GlyphsNumberIdent);
@@ -229,7 +242,10 @@ fn eat_expr_body(parser: &mut Parser) -> bool {
             }
         } else {
             // any other token here is an error
-            parser.err("expected value, operator, or '}'");
+            parser.err(
+                DiagnosticCode::UnexpectedToken,
+                "expected value, operator, or '}'",
+            );
             return false;
         }
     }
@@ -285,7 +301,11 @@ fn take_next_token(text: &str) -> Option<(usize, AstKind)> {
 
 fn expect_variation_location_and_value(parser: &mut Parser, recovery: TokenSet) -> bool {
     if !eat_variation_location_and_value(parser, recovery) {
-        parser.err_recover("expected variation location spec", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "expected variation location spec",
+            recovery,
+        );
         return false;
     }
     true
@@ -315,7 +335,11 @@ fn eat_location_spec(parser: &mut Parser, recovery: TokenSet) -> bool {
 
 fn expect_location_item(parser: &mut Parser, recovery: TokenSet) -> bool {
     if !eat_location_item(parser, recovery) {
-        parser.err_recover("expected <tag>=<number>", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "expected <tag>=<number>",
+            recovery,
+        );
         return false;
     }
     true
@@ -331,6 +355,7 @@ fn eat_location_item(parser: &mut Parser, recovery: TokenSet) -> bool {
         parser.expect_recover(Kind::Eq, recovery.add(Kind::Comma));
         if !expect_axis_location(parser) {
             parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
                 "expected axis location (number or float)",
                 recovery.add(Kind::Comma),
             );
@@ -362,7 +387,11 @@ fn expect_axis_location(parser: &mut Parser) -> bool {
 fn expect_device(parser: &mut Parser, recovery: TokenSet) -> bool {
     let result = eat_device(parser, recovery);
     if !result {
-        parser.err_recover("expected device record", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "expected device record",
+            recovery,
+        );
     }
     result
 }
@@ -422,7 +451,11 @@ pub(crate) fn expect_name_record(parser: &mut Parser, recovery: TokenSet) {
     const NUM_TYPES: TokenSet = TokenSet::new(&[Kind::Number, Kind::Octal, Kind::Hex]);
     parser.in_node(AstKind::NameSpecNode, |parser| {
         if parser.eat(NUM_TYPES) && parser.eat(NUM_TYPES) && !parser.eat(NUM_TYPES) {
-            parser.err_recover("name record must contain 1 or 3 numbers", recovery);
+            parser.err_recover(
+                DiagnosticCode::InvalidSyntax,
+                "name record must contain 1 or 3 numbers",
+                recovery,
+            );
         }
         parser.expect_recover(Kind::String, recovery.union(Kind::Semi.into()));
     })
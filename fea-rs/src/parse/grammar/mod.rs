@@ -1,5 +1,6 @@
 use super::Parser;
 use super::lexer::{Kind, TokenSet};
+use crate::DiagnosticCode;
 use crate::token_tree::Kind as AstKind;
 
 mod feature;
@@ -58,10 +59,13 @@ fn top_level_element(parser: &mut Parser) {
     } else if parser.matches(0, Kind::ValueRecordDefKw) {
         value_record_def(parser, TokenSet::TOP_LEVEL)
     } else {
-        parser.err_and_bump(format!(
-            "Unexpected token '{}', expected global keyword.",
-            parser.current_token_text()
-        ));
+        parser.err_and_bump(
+            DiagnosticCode::UnexpectedToken,
+            format!(
+                "Unexpected token '{}', expected global keyword.",
+                parser.current_token_text()
+            ),
+        );
         advance_to_top_level(parser);
     }
 }
@@ -119,14 +123,20 @@ fn include(parser: &mut Parser) {
                 buf.push((path.len() - trailing..path.len(), AstKind::Whitespace));
             }
         }) {
-            parser.err("Include statement missing path");
+            parser.err(
+                DiagnosticCode::UnexpectedToken,
+                "Include statement missing path",
+            );
             return advance_to_top_level(parser);
         }
         if !parser.expect(Kind::RParen) {
             return advance_to_top_level(parser);
         }
         if !parser.eat(Kind::Semi) {
-            parser.warn_before_ws("include statement is missing ';'");
+            parser.warn_before_ws(
+                DiagnosticCode::NonStandardSyntax,
+                "include statement is missing ';'",
+            );
         }
     }
 
@@ -154,7 +164,7 @@ fn lookup_block_or_reference(parser: &mut Parser, recovery: TokenSet) {
     } else {
         parser.eat(Kind::LookupKw);
         if parser.eat(Kind::Ident) {
-            parser.err_before_ws("Expected ';' or '{'");
+            parser.err_before_ws(DiagnosticCode::UnexpectedToken, "Expected ';' or '{'");
             parser.eat_unless(recovery);
         } else {
             parser.expect_recover(Kind::Ident, recovery);
@@ -201,14 +211,17 @@ fn mark_class(parser: &mut Parser) {
         } else if parser.matches(0, Kind::LSquare) {
             glyph::eat_glyph_class_list(parser, ANCHOR_START)
         } else {
-            parser.eat_remap(TokenSet::IDENT_LIKE, AstKind::GlyphName)
+            parser.eat_remap(TokenSet::IDENT_LIKE, AstKind::GlyphName) || parser.eat(Kind::Cid)
         }
     }
 
     fn mark_class_body(parser: &mut Parser) {
         assert!(parser.eat(Kind::MarkClassKw));
         if !glyph_or_class(parser) {
-            parser.err("Expected glyph name or class");
+            parser.err(
+                DiagnosticCode::UnexpectedToken,
+                "Expected glyph name or class",
+            );
         }
         metrics::anchor(parser, TokenSet::new(&[Kind::Semi, Kind::NamedGlyphClass]));
         parser.expect_recover(Kind::NamedGlyphClass, Kind::Semi);
@@ -276,7 +289,11 @@ fn anonymous(parser: &mut Parser) {
                 }
                 _ => {
                     if parser.nth(1).kind == Kind::Eof {
-                        parser.raw_error(raw_label_range.unwrap(), "unterminated anonymous block");
+                        parser.raw_error(
+                            DiagnosticCode::UnterminatedBlock,
+                            raw_label_range.unwrap(),
+                            "unterminated anonymous block",
+                        );
                         parser.eat_raw();
                         break;
                     }
@@ -293,7 +310,11 @@ fn anonymous(parser: &mut Parser) {
 fn expect_ignore_pattern_body(parser: &mut Parser, recovery: TokenSet) -> bool {
     let recovery = recovery.add(Kind::Semi);
     if !eat_ignore_statement_item(parser, recovery) {
-        parser.err_recover("Expected ignore pattern", recovery);
+        parser.err_recover(
+            DiagnosticCode::UnexpectedToken,
+            "Expected ignore pattern",
+            recovery,
+        );
         parser.eat_until(recovery);
         return false;
     }
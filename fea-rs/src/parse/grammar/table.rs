@@ -3,6 +3,7 @@ use std::ops::Range;
 use write_fonts::types::Tag;
 
 use crate::{
+    DiagnosticCode,
     parse::{
         Parser,
         lexer::{Kind, TokenSet},
@@ -32,7 +33,7 @@ pub(crate) fn table(parser: &mut Parser) {
     let tag = match parser.eat_tag() {
         Some(tag) => tag,
         None => {
-            parser.err("expected tag");
+            parser.err(DiagnosticCode::UnexpectedToken, "expected tag");
             parser.eat_until(TokenSet::TOP_LEVEL);
             parser.finish_node();
             return;
@@ -67,7 +68,11 @@ fn table_impl(parser: &mut Parser, tag: Tag, table_fn: impl Fn(&mut Parser, Toke
     if let Some(close) = parser.expect_tag(TokenSet::TOP_SEMI)
         && close.tag != tag
     {
-        parser.raw_error(close.range, format!("expected tag '{tag}'"));
+        parser.raw_error(
+            DiagnosticCode::MismatchedTag,
+            close.range,
+            format!("expected tag '{tag}'"),
+        );
     }
 
     parser.expect_semi();
@@ -84,7 +89,10 @@ fn unknown_table(parser: &mut Parser, open_tag: Range<usize>) {
             }
             _ => {
                 if parser.nth(1).kind == Kind::Eof {
-                    parser.err_and_bump("unterminated anonymous block");
+                    parser.err_and_bump(
+                        DiagnosticCode::UnterminatedBlock,
+                        "unterminated anonymous block",
+                    );
                     break;
                 }
                 parser.eat_raw();
@@ -398,7 +406,11 @@ mod os2 {
                 parser.expect_semi();
             })
         } else {
-            parser.err_recover("Expected OS/2 table keyword", recovery_semi);
+            parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
+                "Expected OS/2 table keyword",
+                recovery_semi,
+            );
             parser.eat_until(recovery);
         }
     }
@@ -496,7 +508,11 @@ mod stat {
                 parser.expect_semi();
             })
         } else {
-            parser.err_recover("not valid in STAT table", recovery);
+            parser.err_recover(
+                DiagnosticCode::InvalidSyntax,
+                "not valid in STAT table",
+                recovery,
+            );
             parser.eat_until(recovery);
         }
     }
@@ -2,6 +2,7 @@
 //!
 //! See <https://github.com/adobe-type-tools/afdko/pull/1350>
 
+use crate::DiagnosticCode;
 use crate::parse::{
     Parser,
     lexer::{Kind, TokenSet},
@@ -39,7 +40,11 @@ pub(crate) fn condition_set(parser: &mut Parser) {
         while !parser.at_eof() && !parser.matches(0, Kind::RBrace) {
             if !condition(parser) {
                 if let Some(range) = raw_label_range {
-                    parser.raw_error(range, "Table is unclosed");
+                    parser.raw_error(
+                        DiagnosticCode::UnterminatedBlock,
+                        range,
+                        "Table is unclosed",
+                    );
                 }
                 break;
             }
@@ -66,6 +71,7 @@ pub(crate) fn variation(parser: &mut Parser) {
         let open_tag = parser.expect_tag(TokenSet::TOP_LEVEL.add(Kind::Ident).add(Kind::LBrace));
         if !parser.eat(Kind::NullKw) && !parser.eat_remap(TokenSet::IDENT_LIKE, AstKind::Label) {
             parser.err_recover(
+                DiagnosticCode::UnexpectedToken,
                 "expected label or NULL",
                 TokenSet::TOP_LEVEL.add(Kind::LBrace),
             );
@@ -76,7 +82,11 @@ pub(crate) fn variation(parser: &mut Parser) {
         while !parser.at_eof() && !parser.matches(0, Kind::RBrace) {
             if !super::feature::statement(parser, TokenSet::FEATURE_STATEMENT, false) {
                 if let Some(tag) = open_tag.as_ref() {
-                    parser.raw_error(tag.range.clone(), "Variation block is unclosed");
+                    parser.raw_error(
+                        DiagnosticCode::UnterminatedBlock,
+                        tag.range.clone(),
+                        "Variation block is unclosed",
+                    );
                 }
                 break;
             }
@@ -86,7 +96,11 @@ pub(crate) fn variation(parser: &mut Parser) {
         if let (Some(open), Some(close)) = (open_tag, close_tag)
             && open.tag != close.tag
         {
-            parser.raw_error(close.range, format!("expected tag '{}'", open.tag));
+            parser.raw_error(
+                DiagnosticCode::MismatchedTag,
+                close.range,
+                format!("expected tag '{}'", open.tag),
+            );
         }
         parser.expect_semi();
     }
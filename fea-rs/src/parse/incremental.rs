@@ -0,0 +1,294 @@
+//! Reparsing a single source after small edits.
+
+use std::ops::Range;
+
+use super::lexer::{Kind, Lexer};
+use crate::{Diagnostic, Node, token_tree::typed};
+
+/// A parsed source that can be edited without reparsing all of it.
+///
+/// This is intended for editors and other tools that need to keep a parse
+/// tree up to date as the user types. It works on a single source: include
+/// statements are left unresolved, and glyph names that could be ranges are
+/// not disambiguated.
+///
+/// After an edit, only the top-level items (such as feature and lookup blocks)
+/// touched by the edit are reparsed, and the rest of the tree is reused. If
+/// that might produce a different result than parsing the whole text (for
+/// instance because the edit opens a comment that runs into the next item, or
+/// because the source contains syntax errors) the whole text is reparsed
+/// instead. Either way, the result is the same as parsing the edited text from
+/// scratch.
+#[derive(Clone, Debug)]
+pub struct IncrementalParse {
+    text: String,
+    root: Node,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The children of the root node that changed after an edit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reparsed {
+    /// The indices of the replaced children, in the tree before the edit.
+    pub old_children: Range<usize>,
+    /// The indices of the children that replaced them, in the tree after the edit.
+    ///
+    /// Every child outside of this range is unchanged, apart from its position.
+    pub new_children: Range<usize>,
+}
+
+impl IncrementalParse {
+    /// Parse `text`.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let (root, diagnostics) = super::parse_single_source(&text);
+        IncrementalParse {
+            text,
+            root,
+            diagnostics,
+        }
+    }
+
+    /// The current source text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The root node of the current tree.
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// The root node, as a typed AST node.
+    pub fn typed_root(&self) -> typed::Root {
+        typed::Root::try_from_node(&self.root).expect("parse tree has invalid root node type")
+    }
+
+    /// Any errors or warnings produced while parsing the current text.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Replace the text in `range` with `replace_with`, and update the tree.
+    ///
+    /// Returns the children of the root node that were replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or does not fall on a `char`
+    /// boundary.
+    pub fn edit(&mut self, range: Range<usize>, replace_with: &str) -> Reparsed {
+        let old_len = self.root.iter_children().count();
+        self.text.replace_range(range.clone(), replace_with);
+        if let Some(reparsed) = self.reparse_children(range, replace_with.len()) {
+            return reparsed;
+        }
+        *self = IncrementalParse::new(std::mem::take(&mut self.text));
+        Reparsed {
+            old_children: 0..old_len,
+            new_children: 0..self.root.iter_children().count(),
+        }
+    }
+
+    /// Reparse only the top-level children touched by an edit, if that is
+    /// guaranteed to match the result of parsing the whole text.
+    fn reparse_children(&mut self, edit: Range<usize>, new_len: usize) -> Option<Reparsed> {
+        // error recovery can consume tokens past the end of a broken item, so
+        // item boundaries are only reliable if there are no errors.
+        if self.diagnostics.iter().any(Diagnostic::is_error) {
+            return None;
+        }
+        // include children that only touch the edit, since inserted text
+        // may join with them.
+        let children = self
+            .root
+            .iter_children()
+            .map(|child| child.range())
+            .collect::<Vec<_>>();
+        let first = children.iter().position(|child| child.end >= edit.start)?;
+        let last = children.iter().rposition(|child| child.start <= edit.end)?;
+        let old_range = children[first].start..children[last].end;
+        let new_end = old_range.end + new_len - edit.len();
+        let new_range = old_range.start..new_end;
+
+        if !ends_on_token_boundary(&self.text[new_range.start..], new_range.len()) {
+            return None;
+        }
+        let (fragment, diagnostics) = super::parse_single_source(&self.text[new_range.clone()]);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return None;
+        }
+
+        let new_children = fragment.iter_children().collect::<Vec<_>>();
+        let new_count = new_children.len();
+        self.root.splice_children(first..last + 1, new_children);
+
+        // diagnostics before the reparsed items are unchanged, and those
+        // after them only move.
+        let (before, after): (Vec<_>, Vec<_>) = std::mem::take(&mut self.diagnostics)
+            .into_iter()
+            .filter(|diagnostic| {
+                let span = diagnostic.span();
+                span.end <= old_range.start || span.start >= old_range.end
+            })
+            .partition(|diagnostic| diagnostic.span().end <= old_range.start);
+        let delta = new_end as isize - old_range.end as isize;
+        self.diagnostics = before
+            .into_iter()
+            .chain(
+                diagnostics
+                    .into_iter()
+                    .map(|diagnostic| shift(diagnostic, new_range.start as isize)),
+            )
+            .chain(after.into_iter().map(|diagnostic| shift(diagnostic, delta)))
+            .collect();
+
+        Some(Reparsed {
+            old_children: first..last + 1,
+            new_children: first..first + new_count,
+        })
+    }
+}
+
+/// `true` if lexing `text` produces a token that ends at `len`, in a state
+/// where the next token is lexed independently of what came before.
+fn ends_on_token_boundary(text: &str, len: usize) -> bool {
+    let mut lexer = Lexer::new(text);
+    let mut pos = 0;
+    let mut last_kind = Kind::Eof;
+    while pos < len {
+        let token = lexer.next_token();
+        if token.kind == Kind::Eof {
+            return false;
+        }
+        pos += token.len;
+        last_kind = token.kind;
+    }
+    // these tokens change how the token after them is lexed
+    pos == len
+        && !matches!(
+            last_kind,
+            Kind::Backslash | Kind::Number | Kind::Float | Kind::IncludeKw | Kind::LParen
+        )
+}
+
+fn shift(diagnostic: Diagnostic, delta: isize) -> Diagnostic {
+    let shift_range = |span: Range<usize>| {
+        span.start.saturating_add_signed(delta)..span.end.saturating_add_signed(delta)
+    };
+    let shifted = Diagnostic::new(
+        diagnostic.level,
+        diagnostic.message.file,
+        shift_range(diagnostic.span()),
+        diagnostic.message.text,
+    )
+    .with_code(diagnostic.code);
+    diagnostic
+        .related
+        .into_iter()
+        .fold(shifted, |shifted, related| {
+            shifted.with_related(
+                related.file,
+                shift_range(related.span.range()),
+                related.text,
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_tree::Kind;
+
+    static FEA: &str = "\
+languagesystem DFLT dflt;
+
+@vowels = [a e i o u];
+
+feature liga {
+    sub f i by f_i;
+} liga;
+
+# kerning
+feature kern {
+    pos a b -10;
+} kern;
+";
+
+    /// Apply an edit, and check the result matches parsing from scratch.
+    fn edit(parse: &mut IncrementalParse, find: &str, replace_with: &str) -> Reparsed {
+        let start = parse.text().find(find).unwrap();
+        let reparsed = parse.edit(start..start + find.len(), replace_with);
+        let (root, diagnostics) = crate::parse::parse_single_source(parse.text());
+        assert_eq!(parse.root(), &root, "{}", parse.text());
+        assert_eq!(parse.diagnostics(), diagnostics.as_slice());
+        reparsed
+    }
+
+    #[test]
+    fn only_touched_items_are_reparsed() {
+        let mut parse = IncrementalParse::new(FEA);
+        let n_children = parse.root().iter_children().count();
+        let reparsed = edit(&mut parse, "sub f i by f_i;", "sub f l by f_l;");
+        assert_eq!(reparsed.old_children, reparsed.new_children);
+        assert!(reparsed.new_children.len() < n_children / 2);
+        let pos = parse.text().find("f_l").unwrap();
+        let changed = parse
+            .root()
+            .iter_children()
+            .position(|child| child.range().contains(&pos));
+        assert!(reparsed.new_children.contains(&changed.unwrap()));
+
+        // items can be added and removed
+        let reparsed = edit(
+            &mut parse,
+            "# kerning\n",
+            "lookup x {\n    sub a by b;\n} x;\n",
+        );
+        let added = parse
+            .root()
+            .iter_children()
+            .skip(reparsed.new_children.start)
+            .take(reparsed.new_children.len())
+            .any(|child| child.kind() == Kind::LookupBlockNode);
+        assert!(added);
+        let reparsed = edit(&mut parse, "@vowels = [a e i o u];\n", "");
+        assert!(reparsed.new_children.len() < reparsed.old_children.len());
+    }
+
+    #[test]
+    fn edits_that_change_later_tokens_reparse_everything() {
+        let mut parse = IncrementalParse::new(FEA);
+        let n_children = parse.root().iter_children().count();
+        // the string now runs past the end of the edited item
+        let reparsed = edit(&mut parse, "DFLT dflt", "DFLT \"dflt");
+        assert_eq!(reparsed.old_children, 0..n_children);
+        assert!(parse.diagnostics().iter().any(Diagnostic::is_error));
+    }
+
+    #[test]
+    fn errors_are_reported_and_fixed() {
+        let mut parse = IncrementalParse::new(FEA);
+        edit(&mut parse, "pos a b -10;", "pos a b -10");
+        assert!(parse.diagnostics().iter().any(Diagnostic::is_error));
+        edit(&mut parse, "pos a b -10", "pos a b -10;");
+        assert!(parse.diagnostics().is_empty());
+
+        // inserting at the very end
+        let len = parse.text().len();
+        parse.edit(len..len, "feature ss01 { sub a by b; } ss01;");
+        let (root, _) = crate::parse::parse_single_source(parse.text());
+        assert_eq!(parse.root(), &root);
+    }
+
+    #[test]
+    fn every_deletion_matches_full_parse() {
+        for i in 0..FEA.len() {
+            let mut parse = IncrementalParse::new(FEA);
+            parse.edit(i..i + 1, "");
+            let (root, diagnostics) = crate::parse::parse_single_source(parse.text());
+            assert_eq!(parse.root(), &root, "deleting {i}");
+            assert_eq!(parse.diagnostics(), diagnostics.as_slice(), "deleting {i}");
+        }
+    }
+}
@@ -5,10 +5,13 @@
 //! keyword from a glyph name. Instead we are just describing the most basic
 //! structure of the document.
 //!
-//! The `Lexer` type is driven by a [Parser].
+//! The `Lexer` type is driven by a [Parser]. It can also be used on its own,
+//! via [`lex`], by tools that only need the raw tokens.
 //!
 //! [Parser]: super::Parser
 
+use std::{iter::FusedIterator, ops::Range};
+
 mod lexeme;
 mod token_set;
 
@@ -17,6 +20,7 @@ pub use token_set::TokenSet;
 
 const EOF: u8 = 0x0;
 
+#[derive(Clone)]
 pub(crate) struct Lexer<'a> {
     input: &'a str,
     pos: usize,
@@ -263,6 +267,65 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Iterate over the raw tokens in `text`, without building a tree.
+///
+/// This is intended for tools that only care about the surface of a source,
+/// such as syntax highlighters or quick statistics. Each item is a token kind
+/// and its byte range; ranges are contiguous, and together cover all of the
+/// input, including whitespace and comments.
+///
+/// Since no parsing is done, keywords are only recognized by their spelling,
+/// and glyph names, tags and labels are all reported as [`Kind::Ident`].
+/// Malformed tokens are reported as [`Kind::StringUnterminated`] or
+/// [`Kind::HexEmpty`], and are otherwise not treated as errors.
+///
+/// [`Kind::Ident`]: crate::Kind::Ident
+/// [`Kind::StringUnterminated`]: crate::Kind::StringUnterminated
+/// [`Kind::HexEmpty`]: crate::Kind::HexEmpty
+pub fn lex(text: &str) -> Tokens<'_> {
+    Tokens {
+        lexer: Lexer::new(text),
+        pos: 0,
+        done: false,
+    }
+}
+
+/// An iterator over the raw tokens in a source.
+///
+/// See [`lex`] for more information.
+#[derive(Clone)]
+pub struct Tokens<'a> {
+    lexer: Lexer<'a>,
+    pos: usize,
+    done: bool,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = (crate::Kind, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let Lexeme { len, kind } = self.lexer.next_token();
+        let kind = match kind {
+            Kind::Eof => {
+                self.done = true;
+                return None;
+            }
+            // these are errors that the parser replaces; here we just report them
+            Kind::StringUnterminated => crate::Kind::StringUnterminated,
+            Kind::HexEmpty => crate::Kind::HexEmpty,
+            other => other.to_token_kind(),
+        };
+        let range = self.pos..self.pos + len;
+        self.pos = range.end;
+        Some((kind, range))
+    }
+}
+
+impl FusedIterator for Tokens<'_> {}
+
 #[cfg(test)]
 pub(crate) fn tokenize(text: &str) -> Vec<Lexeme> {
     iter_tokens(text).collect()
@@ -324,6 +387,33 @@ pub(crate) fn debug_tokens2(tokens: &[Lexeme], src: &str) -> Vec<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn public_lex() {
+        use crate::Kind as AstKind;
+        let fea = "sub a by \"b; # hi\n0x";
+        let tokens = lex(fea).collect::<Vec<_>>();
+        let kinds = tokens.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                AstKind::SubKw,
+                AstKind::Whitespace,
+                AstKind::Ident,
+                AstKind::Whitespace,
+                AstKind::ByKw,
+                AstKind::Whitespace,
+                AstKind::StringUnterminated,
+            ]
+        );
+        assert_eq!(tokens[2].1, 4..5);
+        // every byte is covered
+        assert_eq!(tokens.last().unwrap().1.end, fea.len());
+
+        let tokens = lex("pos a 0x;").collect::<Vec<_>>();
+        assert_eq!(tokens[4], (AstKind::HexEmpty, 6..8));
+        assert_eq!(tokens[5], (AstKind::Semi, 8..9));
+    }
+
     #[test]
     fn empty_hex() {
         let fea = "0x 0x11 0xzz";
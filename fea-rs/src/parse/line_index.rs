@@ -0,0 +1,180 @@
+//! Converting between byte offsets and line/column positions.
+
+use std::sync::Arc;
+
+/// A position in a source, as a (0-indexed) line and column.
+///
+/// Whether the column counts bytes or UTF-16 code units depends on the method
+/// that produced it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    /// The 0-indexed line number
+    pub line: usize,
+    /// The 0-indexed offset in the line
+    pub col: usize,
+}
+
+/// An index of the lines in a source, for converting byte offsets to
+/// positions and back.
+///
+/// Diagnostics and tokens carry byte ranges; editors (and the language server
+/// protocol in particular) want lines and columns, with the columns counted
+/// in UTF-16 code units.
+///
+/// This is cheap to clone.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineIndex {
+    text: Arc<str>,
+    /// The offset of the start of each line; the first is always 0.
+    line_starts: Arc<[usize]>,
+}
+
+impl LineIndex {
+    /// Create a new index for this text.
+    pub fn new(text: impl Into<Arc<str>>) -> Self {
+        let text = text.into();
+        // we could use memchr for this; benefits would require benchmarking
+        let line_starts = std::iter::once(0)
+            .chain(
+                text.bytes()
+                    .enumerate()
+                    .filter_map(|(i, b)| (b == b'\n').then_some(i + 1)),
+            )
+            .collect();
+        LineIndex { text, line_starts }
+    }
+
+    /// The indexed text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The number of lines in the text.
+    ///
+    /// A trailing newline starts a new (empty) line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The byte offset of the start of this (0-indexed) line, if it exists.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    /// The text of this (0-indexed) line, without its trailing newline.
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        let start = self.line_start(line)?;
+        let end = self.line_start(line + 1).unwrap_or(self.text.len());
+        Some(self.text[start..end].trim_end_matches('\n'))
+    }
+
+    /// The line containing this byte offset.
+    ///
+    /// Offsets past the end of the text are clamped to the end.
+    pub fn line(&self, offset: usize) -> usize {
+        let offset = offset.min(self.text.len());
+        // cannot underflow as 0 is always in the list
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    /// The line and column of this byte offset, with the column in bytes.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.text.len());
+        let line = self.line(offset);
+        LineCol {
+            line,
+            col: offset - self.line_starts[line],
+        }
+    }
+
+    /// The line and column of this byte offset, with the column in UTF-16
+    /// code units.
+    ///
+    /// An offset inside a multi-byte character is treated as the end of
+    /// that character.
+    pub fn line_col_utf16(&self, offset: usize) -> LineCol {
+        let LineCol { line, col } = self.line_col(offset);
+        let line_start = self.line_starts[line];
+        let col = self.text[line_start..]
+            .char_indices()
+            .take_while(|(i, _)| *i < col)
+            .map(|(_, c)| c.len_utf16())
+            .sum();
+        LineCol { line, col }
+    }
+
+    /// The byte offset of this position, with the column in bytes.
+    ///
+    /// Returns `None` if the line does not exist, or if the column is past the
+    /// end of the line or not on a character boundary.
+    pub fn offset(&self, pos: LineCol) -> Option<usize> {
+        let line = self.line_text(pos.line)?;
+        line.is_char_boundary(pos.col)
+            .then(|| self.line_starts[pos.line] + pos.col)
+    }
+
+    /// The byte offset of this position, with the column in UTF-16 code units.
+    ///
+    /// Positions past the end of a line are clamped to the end of that line,
+    /// and positions past the last line are clamped to the end of the text.
+    /// A column in the middle of a surrogate pair resolves to the start of
+    /// that character.
+    pub fn offset_utf16(&self, pos: LineCol) -> usize {
+        let Some(line_start) = self.line_start(pos.line) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        self.text[line_start..]
+            .char_indices()
+            .find(|(_, c)| {
+                units += c.len_utf16();
+                *c == '\n' || units > pos.col
+            })
+            .map(|(i, _)| line_start + i)
+            .unwrap_or(self.text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: usize, col: usize) -> LineCol {
+        LineCol { line, col }
+    }
+
+    #[test]
+    fn byte_positions() {
+        let index = LineIndex::new("ab\ncd\n\nef");
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(0), pos(0, 0));
+        assert_eq!(index.line_col(2), pos(0, 2));
+        assert_eq!(index.line_col(3), pos(1, 0));
+        assert_eq!(index.line_col(6), pos(2, 0));
+        assert_eq!(index.line_col(8), pos(3, 1));
+        assert_eq!(index.line_col(100), pos(3, 2));
+        assert_eq!(index.line_text(1), Some("cd"));
+        assert_eq!(index.line_text(2), Some(""));
+        assert_eq!(index.offset(pos(1, 1)), Some(4));
+        assert_eq!(index.offset(pos(1, 3)), None);
+        assert_eq!(index.offset(pos(4, 0)), None);
+    }
+
+    #[test]
+    fn utf16_positions() {
+        // 'é' is two bytes and one code unit, '𝒜' is four bytes and two
+        let index = LineIndex::new("sub é by 𝒜;\n𝒜 x");
+        let x = index.text().rfind('x').unwrap();
+        assert_eq!(index.line_col_utf16(x), pos(1, 3));
+        assert_eq!(index.line_col(x), pos(1, 5));
+        let semi = index.text().find(';').unwrap();
+        assert_eq!(index.line_col_utf16(semi), pos(0, 11));
+        assert_eq!(index.offset_utf16(pos(0, 11)), semi);
+        assert_eq!(index.offset_utf16(pos(1, 3)), x);
+        // clamped to the end of the line
+        assert_eq!(index.offset_utf16(pos(0, 50)), semi + 1);
+        assert_eq!(index.offset_utf16(pos(9, 0)), index.text().len());
+        assert_eq!(index.offset(pos(0, 5)), None);
+    }
+}
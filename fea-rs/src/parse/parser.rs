@@ -11,7 +11,7 @@ use super::{
 };
 use crate::token_tree::{AstSink, Kind};
 
-use crate::diagnostic::Diagnostic;
+use crate::diagnostic::{Diagnostic, DiagnosticCode};
 
 const LOOKAHEAD: usize = 4;
 const LOOKAHEAD_MAX: usize = LOOKAHEAD - 1;
@@ -31,6 +31,7 @@ pub struct Parser<'a, 'b> {
     text: &'a str,
     buf: [PendingToken; LOOKAHEAD],
     split_buf: Vec<(Range<usize>, Kind)>,
+    liberal_glyph_names: bool,
 }
 
 /// A non-trivia token, as well as any trivia preceding that token.
@@ -75,6 +76,7 @@ impl<'b, 'a> Parser<'a, 'b> {
             text,
             split_buf: Default::default(),
             buf: [PendingToken::EMPTY; LOOKAHEAD],
+            liberal_glyph_names: false,
         };
 
         // preload the buffer; this accumulates any errors
@@ -84,6 +86,16 @@ impl<'b, 'a> Parser<'a, 'b> {
         this
     }
 
+    /// Accept glyph names that are not allowed by the spec, with a warning.
+    pub(crate) fn with_liberal_glyph_names(mut self, flag: bool) -> Self {
+        self.liberal_glyph_names = flag;
+        self
+    }
+
+    pub(crate) fn liberal_glyph_names(&self) -> bool {
+        self.liberal_glyph_names
+    }
+
     pub(crate) fn nth_range(&self, n: usize) -> Range<usize> {
         assert!(n < LOOKAHEAD);
         let start = self.buf[n].start_pos + self.buf[n].trivia_len;
@@ -177,8 +189,10 @@ impl<'b, 'a> Parser<'a, 'b> {
             if replace_kind == LexemeKind::String {
                 range.end = range.start + 1;
             }
-            self.sink
-                .error(Diagnostic::error(FileId::CURRENT_FILE, range, error));
+            self.sink.error(
+                Diagnostic::error(FileId::CURRENT_FILE, range, error)
+                    .with_code(DiagnosticCode::InvalidToken),
+            );
             self.buf[LOOKAHEAD_MAX].token.kind = replace_kind;
         }
     }
@@ -306,55 +320,63 @@ impl<'b, 'a> Parser<'a, 'b> {
         self.buf[0].trivia_len = 0;
     }
 
-    pub(crate) fn err_and_bump(&mut self, error: impl Into<String>) {
-        self.err(error);
+    pub(crate) fn err_and_bump(&mut self, code: DiagnosticCode, error: impl Into<String>) {
+        self.err(code, error);
         self.eat_raw();
     }
 
-    pub(crate) fn raw_error(&mut self, range: Range<usize>, message: impl Into<String>) {
+    pub(crate) fn raw_error(
+        &mut self,
+        code: DiagnosticCode,
+        range: Range<usize>,
+        message: impl Into<String>,
+    ) {
         self.sink
-            .error(Diagnostic::error(FileId::CURRENT_FILE, range, message));
+            .error(Diagnostic::error(FileId::CURRENT_FILE, range, message).with_code(code));
     }
 
     /// Error, and advance unless the current token matches a predicate.
     pub(crate) fn err_recover(
         &mut self,
+        code: DiagnosticCode,
         error: impl Into<String>,
         predicate: impl TokenComparable,
     ) {
-        self.err(error);
+        self.err(code, error);
         if !self.matches(0, predicate) {
             self.eat_raw();
         }
     }
 
     /// write an error, do not advance
-    pub(crate) fn err(&mut self, error: impl Into<String>) {
-        let err = Diagnostic::error(FileId::CURRENT_FILE, self.nth_range(0), error);
+    pub(crate) fn err(&mut self, code: DiagnosticCode, error: impl Into<String>) {
+        let err = Diagnostic::error(FileId::CURRENT_FILE, self.nth_range(0), error).with_code(code);
         self.sink.error(err);
     }
 
     /// write a warning, do not advance
-    pub(crate) fn warn(&mut self, message: impl Into<String>) {
-        let err = Diagnostic::warning(FileId::CURRENT_FILE, self.nth_range(0), message);
+    pub(crate) fn warn(&mut self, code: DiagnosticCode, message: impl Into<String>) {
+        let err =
+            Diagnostic::warning(FileId::CURRENT_FILE, self.nth_range(0), message).with_code(code);
         self.sink.error(err);
     }
 
     /// Write an error associated *before* the whitespace of the current token.
     ///
     /// In practice this is useful when missing things like semis or braces.
-    pub(crate) fn err_before_ws(&mut self, error: impl Into<String>) {
+    pub(crate) fn err_before_ws(&mut self, code: DiagnosticCode, error: impl Into<String>) {
         let pos = self.buf[0].start_pos;
-        self.raw_error(pos..pos + 1, error);
+        self.raw_error(code, pos..pos + 1, error);
     }
 
     /// Write a *warning* before the whitespace of the associated token.
     ///
     /// This only exists so we can warn if a semi is missing after an include
     /// statement (which is common in the wild)
-    pub(crate) fn warn_before_ws(&mut self, error: impl Into<String>) {
+    pub(crate) fn warn_before_ws(&mut self, code: DiagnosticCode, error: impl Into<String>) {
         let pos = self.buf[0].start_pos;
-        let diagnostic = Diagnostic::warning(FileId::CURRENT_FILE, pos..pos + 1, error);
+        let diagnostic =
+            Diagnostic::warning(FileId::CURRENT_FILE, pos..pos + 1, error).with_code(code);
         self.sink.error(diagnostic);
     }
 
@@ -363,7 +385,10 @@ impl<'b, 'a> Parser<'a, 'b> {
         if self.eat(kind) {
             return true;
         }
-        self.err(format!("Expected {}, found {}", kind, self.nth(0).kind));
+        self.err(
+            DiagnosticCode::UnexpectedToken,
+            format!("Expected {}, found {}", kind, self.nth(0).kind),
+        );
         false
     }
 
@@ -371,7 +396,7 @@ impl<'b, 'a> Parser<'a, 'b> {
     /// and we want to include whitespace in the range (i.e, it hugs the previous line).
     pub(crate) fn expect_semi(&mut self) -> bool {
         if !self.eat(LexemeKind::Semi) {
-            self.err_before_ws("Expected ';'");
+            self.err_before_ws(DiagnosticCode::UnexpectedToken, "Expected ';'");
             return false;
         }
         true
@@ -385,7 +410,10 @@ impl<'b, 'a> Parser<'a, 'b> {
         if self.eat(kind) {
             return true;
         }
-        self.err(format!("Expected {} found {}", kind, self.nth(0).kind));
+        self.err(
+            DiagnosticCode::UnexpectedToken,
+            format!("Expected {} found {}", kind, self.nth(0).kind),
+        );
         if !self.matches(0, recover) {
             self.eat_raw();
         }
@@ -401,7 +429,10 @@ impl<'b, 'a> Parser<'a, 'b> {
         if self.eat_remap(expect, remap) {
             return true;
         }
-        self.err(format!("Expected {} found {}", remap, self.nth(0).kind));
+        self.err(
+            DiagnosticCode::UnexpectedToken,
+            format!("Expected {} found {}", remap, self.nth(0).kind),
+        );
         if !self.matches(0, recover) {
             self.eat_raw();
         }
@@ -423,10 +454,14 @@ impl<'b, 'a> Parser<'a, 'b> {
         if self.matches(0, TokenSet::TAG_LIKE) {
             match self.eat_tag() {
                 Some(tag) => return Some(tag),
-                None => self.err_and_bump("invalid tag"),
+                None => self.err_and_bump(DiagnosticCode::InvalidTag, "invalid tag"),
             }
         } else {
-            self.err_recover(format!("expected tag, found {}", self.nth(0).kind), recover);
+            self.err_recover(
+                DiagnosticCode::UnexpectedToken,
+                format!("expected tag, found {}", self.nth(0).kind),
+                recover,
+            );
         }
         None
     }
@@ -0,0 +1,157 @@
+//! Classifying tokens for syntax highlighting.
+
+use std::ops::Range;
+
+use crate::{Kind, Node, NodeOrToken, Token};
+
+/// The role of a token in the source, for syntax highlighting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SemanticKind {
+    /// A keyword, such as `feature`, `sub` or `lookupflag`
+    Keyword,
+    /// A glyph name or CID
+    GlyphName,
+    /// A named glyph class or mark class, such as `@vowels`
+    GlyphClass,
+    /// A feature, script or language tag, or a table name
+    Tag,
+    /// The name of a lookup
+    Lookup,
+    /// The name of a named anchor
+    Anchor,
+    /// A number
+    Number,
+    /// A string
+    String,
+    /// A comment
+    Comment,
+}
+
+/// Iterate over the tokens in `root` that have a semantic kind, in order.
+///
+/// Whitespace, punctuation and any tokens that could not be classified are
+/// skipped. Ranges are byte ranges into the source.
+///
+/// Because keywords, glyph names and tags are only distinguished during
+/// parsing, this gives more accurate results than looking at the raw
+/// tokens.
+pub fn semantic_tokens(root: &Node) -> impl Iterator<Item = (Range<usize>, SemanticKind)> + '_ {
+    let mut cursor = root.cursor();
+    std::iter::from_fn(move || {
+        loop {
+            let parent = cursor.parent_kind();
+            let pos = cursor.pos();
+            let current = cursor.current()?;
+            cursor.advance();
+            if let NodeOrToken::Token(token) = current
+                && let Some(kind) = SemanticKind::for_token(&token, parent)
+            {
+                return Some((pos..pos + token.as_str().len(), kind));
+            }
+        }
+    })
+}
+
+impl SemanticKind {
+    fn for_token(token: &Token, parent: Kind) -> Option<Self> {
+        Some(match token.kind {
+            Kind::Comment => SemanticKind::Comment,
+            Kind::String => SemanticKind::String,
+            Kind::Number | Kind::Octal | Kind::Hex | Kind::Float | Kind::NumberSuffix => {
+                SemanticKind::Number
+            }
+            Kind::GlyphName | Kind::Cid | Kind::GlyphNameOrRange => SemanticKind::GlyphName,
+            Kind::NamedGlyphClass => SemanticKind::GlyphClass,
+            Kind::Tag => SemanticKind::Tag,
+            Kind::Label => SemanticKind::Lookup,
+            Kind::Ident if parent == Kind::LookupRefNode => SemanticKind::Lookup,
+            Kind::Ident if matches!(parent, Kind::AnchorDefNode | Kind::AnchorNode) => {
+                SemanticKind::Anchor
+            }
+            // keywords each have their own kind, so we check the text instead
+            // of listing them all here.
+            Kind::Ident | Kind::Whitespace => return None,
+            _ if super::is_keyword(token.as_str()) => SemanticKind::Keyword,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(fea: &str) -> Vec<(&str, SemanticKind)> {
+        let (root, errors) = crate::parse::parse_single_source(fea);
+        assert!(errors.is_empty(), "{errors:?}");
+        semantic_tokens(&root)
+            .map(|(range, kind)| (&fea[range], kind))
+            .collect()
+    }
+
+    #[test]
+    fn classify_tokens() {
+        use SemanticKind::*;
+        let fea = "\
+languagesystem DFLT dflt;
+anchorDef 120 -20 TOP;
+@vowels = [a \\sub];
+# comment
+lookup kern1 {
+    pos cursive @vowels <anchor TOP> <anchor NULL>;
+    pos @vowels b 10;
+} kern1;
+feature liga {
+    lookup kern1;
+    sub f i by f_i;
+    sub a' lookup kern1 b;
+} liga;
+";
+        assert_eq!(
+            classify(fea),
+            [
+                ("languagesystem", Keyword),
+                ("DFLT", Tag),
+                ("dflt", Tag),
+                ("anchorDef", Keyword),
+                ("120", Number),
+                ("-20", Number),
+                ("TOP", Anchor),
+                ("@vowels", GlyphClass),
+                ("a", GlyphName),
+                ("sub", GlyphName),
+                ("# comment", Comment),
+                ("lookup", Keyword),
+                ("kern1", Lookup),
+                ("pos", Keyword),
+                ("cursive", Keyword),
+                ("@vowels", GlyphClass),
+                ("anchor", Keyword),
+                ("TOP", Anchor),
+                ("anchor", Keyword),
+                ("NULL", Keyword),
+                ("pos", Keyword),
+                ("@vowels", GlyphClass),
+                ("b", GlyphName),
+                ("10", Number),
+                ("kern1", Lookup),
+                ("feature", Keyword),
+                ("liga", Tag),
+                ("lookup", Keyword),
+                ("kern1", Lookup),
+                ("sub", Keyword),
+                ("f", GlyphName),
+                ("i", GlyphName),
+                ("by", Keyword),
+                ("f_i", GlyphName),
+                ("sub", Keyword),
+                ("a", GlyphName),
+                ("lookup", Keyword),
+                ("kern1", Lookup),
+                ("b", GlyphName),
+                ("liga", Tag),
+            ]
+        );
+    }
+}
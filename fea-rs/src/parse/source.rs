@@ -9,6 +9,7 @@ use std::{
     sync::Arc,
 };
 
+use super::{LineCol, LineIndex};
 use crate::{Diagnostic, util};
 
 /// Uniquely identifies a source file.
@@ -29,10 +30,9 @@ pub struct Source {
     id: FileId,
     /// The non-canonicalized path to this source, suitable for printing.
     path: PathBuf,
-    contents: Arc<str>,
-    /// The index of each newline character, for efficiently fetching lines
-    /// (for error reporting, e.g.)
-    line_offsets: Arc<[usize]>,
+    /// The contents, and the offset of each line, for efficiently fetching
+    /// lines (for error reporting, e.g.)
+    lines: LineIndex,
 }
 
 /// A list of sources in a project.
@@ -139,6 +139,7 @@ where
 #[derive(Default)]
 pub struct FileSystemResolver {
     project_root: PathBuf,
+    include_dirs: Vec<PathBuf>,
 }
 
 impl FileSystemResolver {
@@ -148,7 +149,19 @@ impl FileSystemResolver {
     /// cases, it is likely the directory containing the root feature file.
     /// If the path is empty (i.e. ""), the current working directory is assumed.
     pub fn new(project_root: PathBuf) -> Self {
-        Self { project_root }
+        Self {
+            project_root,
+            include_dirs: Vec::new(),
+        }
+    }
+
+    /// Provide additional directories to search for included files.
+    ///
+    /// These are only consulted if a path cannot be resolved relative to
+    /// the project root or the including file, and are searched in order.
+    pub fn with_include_dirs(mut self, include_dirs: Vec<PathBuf>) -> Self {
+        self.include_dirs = include_dirs;
+        self
     }
 }
 
@@ -162,7 +175,7 @@ impl SourceResolver for FileSystemResolver {
     fn resolve_raw_path(&self, path: &Path, included_from: Option<&Path>) -> PathBuf {
         let path = Path::new(path);
         let included_from = included_from.map(Path::new).and_then(Path::parent);
-        util::paths::resolve_path(path, &self.project_root, included_from)
+        util::paths::resolve_path(path, &self.project_root, included_from, &self.include_dirs)
     }
 
     fn canonicalize(&self, path: &Path) -> Result<PathBuf, SourceLoadError> {
@@ -183,18 +196,16 @@ impl FileId {
 
 impl Source {
     pub(crate) fn new(path: PathBuf, contents: Arc<str>) -> Self {
-        let line_offsets = line_offsets(&contents);
         Source {
             path,
             id: FileId::next(),
-            contents,
-            line_offsets,
+            lines: LineIndex::new(contents),
         }
     }
 
     /// The raw text for this source
     pub fn text(&self) -> &str {
-        &self.contents
+        self.lines.text()
     }
 
     /// The source's path.
@@ -211,55 +222,35 @@ impl Source {
         self.id
     }
 
+    /// The [`LineIndex`] for this source, for converting offsets to positions.
+    pub fn line_index(&self) -> &LineIndex {
+        &self.lines
+    }
+
     /// Compute the line and column for a given utf-8 offset.
+    ///
+    /// The line is 1-indexed, and the column is a 0-indexed byte offset.
     pub fn line_col_for_offset(&self, offset: usize) -> (usize, usize) {
-        let offset_idx = match self.line_offsets.binary_search(&offset) {
-            Ok(x) => x,
-            Err(x) => x - 1, // cannot underflow as 0 is always in list
-        };
-        let offset_of_line = self.line_offsets[offset_idx];
-        let offset_in_line = offset - offset_of_line;
-        (offset_idx + 1, offset_in_line)
+        let LineCol { line, col } = self.lines.line_col(offset);
+        (line + 1, col)
     }
 
     /// returns the (1-indexed) number and text.
     pub fn line_containing_offset(&self, offset: usize) -> (usize, &str) {
-        let offset_idx = match self.line_offsets.binary_search(&offset) {
-            Ok(x) => x,
-            Err(x) => x - 1, // cannot underflow as 0 is always in list
-        };
-        let start_offset = self.line_offsets[offset_idx];
-        let end_offset = self
-            .line_offsets
-            .get(offset_idx + 1)
-            .copied()
-            .unwrap_or(self.contents.len());
-
-        (
-            offset_idx + 1,
-            self.contents[start_offset..end_offset].trim_end_matches('\n'),
-        )
+        let line = self.lines.line(offset);
+        (line + 1, self.lines.line_text(line).unwrap_or_default())
     }
 
     /// Return the offset of the start of the (1-indexed) line.
     ///
     /// Panics if the line number exceeds the total number of lines in the file.
     pub fn offset_for_line_number(&self, line_number: usize) -> usize {
-        self.line_offsets[line_number - 1]
+        self.lines
+            .line_start(line_number - 1)
+            .expect("line number out of bounds")
     }
 }
 
-fn line_offsets(text: &str) -> Arc<[usize]> {
-    // we could use memchar for this; benefits would require benchmarking
-    let mut result = vec![0];
-    result.extend(
-        text.bytes()
-            .enumerate()
-            .filter_map(|(i, b)| if b == b'\n' { Some(i + 1) } else { None }),
-    );
-    result.into()
-}
-
 impl SourceMap {
     pub(crate) fn add_entry(&mut self, src: Range<usize>, dest: (FileId, usize)) {
         if !src.is_empty() {
@@ -300,6 +291,14 @@ impl SourceLoader {
         self.sources.get(id)
     }
 
+    /// Add a source that was generated, instead of loaded by the resolver.
+    pub(crate) fn add_generated(&mut self, path: PathBuf, contents: String) -> FileId {
+        let source = Source::new(path.clone(), contents.into());
+        let id = source.id;
+        self.sources.add(path, source);
+        id
+    }
+
     /// Attempt to load the source at the provided path.
     ///
     /// This uses the [`SourceResolver`] that was passed in at construction time,
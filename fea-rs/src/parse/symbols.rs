@@ -0,0 +1,278 @@
+//! An index of the names defined and referenced in a source.
+
+use std::ops::Range;
+
+use indexmap::IndexMap;
+use smol_str::SmolStr;
+
+use crate::{Kind, Node, NodeOrToken, Token};
+
+/// The kinds of things that can be named in FEA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymbolKind {
+    /// A named lookup block
+    Lookup,
+    /// A named glyph class, such as `@vowels = [a e i o u];`
+    GlyphClass,
+    /// A mark class, defined by one or more `markClass` statements
+    MarkClass,
+    /// A named anchor, defined with `anchorDef`
+    Anchor,
+}
+
+/// A named item, and everywhere it is defined and referenced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// What sort of thing this is
+    pub kind: SymbolKind,
+    /// The name, including the leading `@` for classes
+    pub name: SmolStr,
+    /// The ranges of the names at each definition site.
+    ///
+    /// Mark classes can have many definitions, and this is empty if the
+    /// symbol is referenced but never defined.
+    pub definitions: Vec<Range<usize>>,
+    /// The ranges of every reference to this symbol.
+    ///
+    /// This includes the label at the end of a lookup block.
+    pub references: Vec<Range<usize>>,
+}
+
+/// The definitions and references of the lookups, classes and anchors in a
+/// parse tree.
+///
+/// This is intended for tooling, such as go-to-definition and
+/// find-all-references in an editor. It is built from the tree alone, and so
+/// it also works for trees that contain errors.
+///
+/// All ranges are byte ranges of the tokens in the tree, so for a
+/// [`ParseTree`][crate::ParseTree] with included files they are positions in
+/// the combined tree, not in individual sources.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolIndex {
+    symbols: IndexMap<(SymbolKind, SmolStr), Symbol>,
+    /// (range, index into symbols), sorted by position
+    occurrences: Vec<(Range<usize>, usize)>,
+}
+
+impl SymbolIndex {
+    /// Build an index of the symbols in the tree rooted at `root`.
+    pub fn new(root: &Node) -> Self {
+        let mut builder = Builder::default();
+        builder.visit(root);
+        builder.finish()
+    }
+
+    /// The symbol defined or referenced at this byte offset, if any.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let idx = self
+            .occurrences
+            .partition_point(|(range, _)| range.end <= offset);
+        self.occurrences
+            .get(idx)
+            .filter(|(range, _)| range.contains(&offset))
+            .map(|(_, symbol)| &self.symbols[*symbol])
+    }
+
+    /// Look up a symbol by kind and name.
+    pub fn get(&self, kind: SymbolKind, name: &str) -> Option<&Symbol> {
+        self.symbols.get(&(kind, SmolStr::from(name)))
+    }
+
+    /// Iterate over all symbols, in the order they first appear.
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    symbols: IndexMap<(SymbolKind, SmolStr), Symbol>,
+    /// references to named classes, which may be glyph or mark classes
+    class_references: Vec<(SmolStr, Range<usize>)>,
+}
+
+impl Builder {
+    fn visit(&mut self, node: &Node) {
+        let definition = definition_token(node);
+        for child in node.iter_children() {
+            match child {
+                NodeOrToken::Node(child) => self.visit(&child),
+                NodeOrToken::Token(token) => match definition {
+                    Some((kind, ref def)) if token.range() == def.range() => {
+                        self.symbol(kind, &token.text)
+                            .definitions
+                            .push(token.range());
+                    }
+                    _ => self.reference(node.kind(), &token),
+                },
+            }
+        }
+    }
+
+    fn reference(&mut self, parent: Kind, token: &Token) {
+        let kind = match (token.kind, parent) {
+            (Kind::NamedGlyphClass, _) => {
+                self.class_references
+                    .push((token.text.clone(), token.range()));
+                return;
+            }
+            (Kind::Label, Kind::LookupBlockNode) | (Kind::Ident, Kind::LookupRefNode) => {
+                SymbolKind::Lookup
+            }
+            (Kind::Ident, Kind::AnchorNode) => SymbolKind::Anchor,
+            _ => return,
+        };
+        self.symbol(kind, &token.text)
+            .references
+            .push(token.range());
+    }
+
+    fn symbol(&mut self, kind: SymbolKind, name: &SmolStr) -> &mut Symbol {
+        self.symbols
+            .entry((kind, name.clone()))
+            .or_insert_with(|| Symbol {
+                kind,
+                name: name.clone(),
+                definitions: Vec::new(),
+                references: Vec::new(),
+            })
+    }
+
+    fn finish(mut self) -> SymbolIndex {
+        // mark classes can be used anywhere a glyph class can, so we only know
+        // which a reference is once we've seen all the definitions.
+        for (name, range) in std::mem::take(&mut self.class_references) {
+            let kind = if self
+                .symbols
+                .contains_key(&(SymbolKind::MarkClass, name.clone()))
+            {
+                SymbolKind::MarkClass
+            } else {
+                SymbolKind::GlyphClass
+            };
+            self.symbol(kind, &name).references.push(range);
+        }
+        let mut occurrences = self
+            .symbols
+            .values()
+            .enumerate()
+            .flat_map(|(i, symbol)| {
+                symbol
+                    .definitions
+                    .iter()
+                    .chain(&symbol.references)
+                    .map(move |range| (range.clone(), i))
+            })
+            .collect::<Vec<_>>();
+        occurrences.sort_by_key(|(range, _)| range.start);
+        SymbolIndex {
+            symbols: self.symbols,
+            occurrences,
+        }
+    }
+}
+
+/// The token naming the symbol defined by this node, if it defines one.
+fn definition_token(node: &Node) -> Option<(SymbolKind, Token)> {
+    let mut children = node.iter_children();
+    let (kind, token) = match node.kind() {
+        Kind::LookupBlockNode => (
+            SymbolKind::Lookup,
+            children.find(|child| child.kind() == Kind::Label)?,
+        ),
+        Kind::GlyphClassDefNode => (
+            SymbolKind::GlyphClass,
+            children.find(|child| child.kind() == Kind::NamedGlyphClass)?,
+        ),
+        // the class name follows the anchor; a class before it is a reference
+        Kind::MarkClassNode => (
+            SymbolKind::MarkClass,
+            children
+                .skip_while(|child| child.kind() != Kind::AnchorNode)
+                .find(|child| child.kind() == Kind::NamedGlyphClass)?,
+        ),
+        Kind::AnchorDefNode => (
+            SymbolKind::Anchor,
+            children.find(|child| child.kind() == Kind::Ident)?,
+        ),
+        _ => return None,
+    };
+    Some((kind, token.into_token()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FEA: &str = "\
+anchorDef 120 -20 TOP;
+@vowels = [a e];
+@more = [@vowels b];
+markClass [acute] <anchor TOP> @MARKS;
+markClass [grave] <anchor 10 20> @MARKS;
+
+lookup kern1 {
+    pos @vowels b 10;
+} kern1;
+
+feature liga {
+    lookup kern1;
+    sub @more' lookup missing b;
+    pos base [a b] <anchor TOP> mark @MARKS;
+} liga;
+";
+
+    fn at<'a>(index: &'a SymbolIndex, needle: &str, nth: usize) -> Option<&'a Symbol> {
+        let offset = FEA.match_indices(needle).nth(nth).unwrap().0;
+        index.symbol_at(offset + 1)
+    }
+
+    fn texts(ranges: &[Range<usize>]) -> Vec<&str> {
+        ranges.iter().map(|range| &FEA[range.clone()]).collect()
+    }
+
+    #[test]
+    fn definitions_and_references() {
+        let (root, _) = crate::parse::parse_single_source(FEA);
+        let index = SymbolIndex::new(&root);
+
+        let kern = index.get(SymbolKind::Lookup, "kern1").unwrap();
+        assert_eq!(texts(&kern.definitions), ["kern1"]);
+        assert_eq!(kern.definitions[0].start, FEA.find("kern1").unwrap());
+        // the closing label, and the reference in the feature
+        assert_eq!(kern.references.len(), 2);
+
+        let vowels = index.get(SymbolKind::GlyphClass, "@vowels").unwrap();
+        assert_eq!(vowels.definitions.len(), 1);
+        assert_eq!(vowels.references.len(), 2);
+
+        let marks = index.get(SymbolKind::MarkClass, "@MARKS").unwrap();
+        assert_eq!(marks.definitions.len(), 2);
+        assert_eq!(marks.references.len(), 1);
+        assert!(index.get(SymbolKind::GlyphClass, "@MARKS").is_none());
+
+        let top = index.get(SymbolKind::Anchor, "TOP").unwrap();
+        assert_eq!(top.definitions.len(), 1);
+        assert_eq!(top.references.len(), 2);
+
+        // referenced, but never defined
+        let missing = index.get(SymbolKind::Lookup, "missing").unwrap();
+        assert!(missing.definitions.is_empty());
+        assert_eq!(missing.references.len(), 1);
+    }
+
+    #[test]
+    fn lookup_by_offset() {
+        let (root, _) = crate::parse::parse_single_source(FEA);
+        let index = SymbolIndex::new(&root);
+
+        assert_eq!(at(&index, "@more", 1).unwrap().name, "@more");
+        assert_eq!(at(&index, "@MARKS", 2).unwrap().kind, SymbolKind::MarkClass);
+        assert_eq!(at(&index, "kern1", 2).unwrap().kind, SymbolKind::Lookup);
+        assert_eq!(at(&index, "TOP", 2).unwrap().kind, SymbolKind::Anchor);
+        // not a symbol
+        assert!(at(&index, "acute", 0).is_none());
+        assert!(index.symbol_at(FEA.len() + 10).is_none());
+    }
+}
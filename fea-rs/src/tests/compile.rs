@@ -1,11 +1,15 @@
 //! tests of the full compiler, including expected successes and failures
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     GlyphMap,
     compile::{
-        Compilation, Compiler, MockVariationInfo, NopFeatureProvider, Opts, error::CompilerError,
+        AnonBlock, Compilation, Compiler, MockVariationInfo, NopFeatureProvider, Opts,
+        UnknownTable, error::CompilerError,
     },
     util::ttx::{self as test_utils, Filter, Report, TestCase, TestResult},
 };
@@ -17,6 +21,8 @@ static BAD_DIR: &str = "bad";
 static GLYPH_ORDER: &str = "glyph_order.txt";
 static BAD_OUTPUT_EXTENSION: &str = "ERR";
 static IMPORT_RESOLUTION_TEST: &str = "./test-data/include-resolution-tests/dir1/test1.fea";
+static INCLUDE_DIRS_TEST: &str = "./test-data/include-resolution-tests/include-dirs";
+static FONTTOOLS_TEST_DIR: &str = "./test-data/fonttools-tests";
 
 // tests taken directly from fonttools; these require some special handling.
 #[test]
@@ -26,6 +32,13 @@ fn fonttools_tests() -> Result<(), Report> {
     test_utils::run_fonttools_tests(None).into_error()
 }
 
+// the same tests, compared structurally without needing the ttx executable
+#[test]
+fn fonttools_tests_builtin() -> Result<(), Report> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    test_utils::run_fonttools_tests_builtin(None).into_error()
+}
+
 #[test]
 fn should_fail() -> Result<(), Report> {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -66,6 +79,38 @@ fn should_pass() -> Result<(), Report> {
     test_utils::finalize_results(results).into_error()
 }
 
+#[test]
+fn should_pass_builtin() -> Result<(), Report> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut results = Vec::new();
+
+    for (glyph_map, var_info, tests) in iter_test_groups(GOOD_DIR) {
+        results.extend(
+            tests
+                .into_iter()
+                .filter(|path| !path.with_extension("expected_diff").exists())
+                .map(|path| test_utils::run_builtin_test(path, &glyph_map, &var_info)),
+        );
+    }
+    test_utils::finalize_results(results).into_error()
+}
+
+// compiling the same input repeatedly should always produce identical bytes
+#[test]
+fn output_is_deterministic() -> Result<(), Report> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut results = Vec::new();
+
+    for (glyph_map, var_info, tests) in iter_test_groups(GOOD_DIR) {
+        results.extend(
+            tests
+                .into_iter()
+                .map(|path| test_utils::assert_deterministic(path, &glyph_map, &var_info, 3)),
+        );
+    }
+    test_utils::finalize_results(results).into_error()
+}
+
 fn iter_test_groups(
     test_dir: &str,
 ) -> impl Iterator<Item = (GlyphMap, MockVariationInfo, Vec<PathBuf>)> + '_ {
@@ -109,6 +154,15 @@ fn run_bad_test(
     }
 }
 
+/// The glyph order used by the mini-latin test cases.
+fn mini_latin_glyph_map() -> GlyphMap {
+    let glyph_order_path = Path::new(ROOT_TEST_DIR)
+        .join("mini-latin")
+        .join(GLYPH_ORDER);
+    let glyph_order = std::fs::read_to_string(glyph_order_path).unwrap();
+    glyph_order.lines().map(GlyphName::new).collect()
+}
+
 /// Compile a FEA string using the mini-latin glyph order.
 fn compile_fea(fea: &str, test_name: &str) -> Compilation {
     let dir = std::env::temp_dir().join(format!("fea_rs_test_{test_name}"));
@@ -116,11 +170,7 @@ fn compile_fea(fea: &str, test_name: &str) -> Compilation {
     let fea_path = dir.join(format!("{test_name}.fea"));
     std::fs::write(&fea_path, fea).unwrap();
 
-    let glyph_order_path = Path::new(ROOT_TEST_DIR)
-        .join("mini-latin")
-        .join(GLYPH_ORDER);
-    let glyph_order = std::fs::read_to_string(glyph_order_path).unwrap();
-    let glyph_map: GlyphMap = glyph_order.lines().map(GlyphName::new).collect();
+    let glyph_map = mini_latin_glyph_map();
 
     Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(fea_path, &glyph_map)
         .compile()
@@ -134,11 +184,7 @@ fn compile_fea_variable(fea: &str, test_name: &str) -> Compilation {
     let fea_path = dir.join(format!("{test_name}.fea"));
     std::fs::write(&fea_path, fea).unwrap();
 
-    let glyph_order_path = Path::new(ROOT_TEST_DIR)
-        .join("mini-latin")
-        .join(GLYPH_ORDER);
-    let glyph_order = std::fs::read_to_string(glyph_order_path).unwrap();
-    let glyph_map: GlyphMap = glyph_order.lines().map(GlyphName::new).collect();
+    let glyph_map = mini_latin_glyph_map();
     let var_info = test_utils::make_var_info();
 
     Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(fea_path, &glyph_map)
@@ -147,6 +193,40 @@ fn compile_fea_variable(fea: &str, test_name: &str) -> Compilation {
         .expect("compilation should succeed")
 }
 
+/// Compile a FEA string using the mini-latin glyph order, returning any warnings.
+fn compile_fea_with_warnings(fea: &str) -> (Compilation, Vec<String>) {
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, diagnostics) = crate::parse::parse_string(fea);
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    let (compilation, warnings) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    )
+    .unwrap_or_else(|errs| panic!("{}", errs.display()));
+    let warnings = warnings
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.text().to_owned())
+        .collect();
+    (compilation, warnings)
+}
+
+/// Run only the validation pass, returning the text of any warnings
+fn validate_fea_warnings(fea: &str) -> Vec<String> {
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, _) = crate::parse::parse_string(fea);
+    let diagnostics = crate::compile::validate::<MockVariationInfo>(&tree, &glyph_map, None);
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    diagnostics
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.text().to_owned())
+        .collect()
+}
+
 // Regression test for https://github.com/googlefonts/fontc/issues/1847
 //
 // When a variable font has no mark attachment lookups, finalize_gdef_table()
@@ -288,6 +368,8 @@ fn bad_test_body(
         // this means we have a test case that doesn't exist or something weird
         Err(CompilerError::SourceLoad(err)) => panic!("{err}"),
         Err(CompilerError::WriteFail(err)) => panic!("{err}"),
+        // no cancellation token is set for these tests
+        Err(err @ CompilerError::Cancelled(_)) => panic!("{err}"),
         Err(CompilerError::ParseFail(errs)) => Err(TestResult::ParseFail(errs.to_string(true))),
         Err(CompilerError::ValidationFail(errs) | CompilerError::CompilationFail(errs)) => {
             let msg = errs.to_string(false);
@@ -300,3 +382,3427 @@ fn bad_test_body(
         }
     }
 }
+
+// A `language` statement with the `required` keyword should set the feature
+// as the required feature of that language system, and not list it among the
+// regular feature indices.
+#[test]
+fn language_required_feature() {
+    use write_fonts::types::Tag;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn TRK;
+
+feature locl {
+    script latn;
+    language TRK required;
+    sub i by j;
+} locl;
+
+feature liga {
+    sub f i by f_i;
+} liga;
+",
+        "language_required",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let feature_idx = |tag: &[u8; 4]| {
+        gsub.feature_list
+            .feature_records
+            .iter()
+            .position(|rec| rec.feature_tag == Tag::new(tag))
+            .unwrap() as u16
+    };
+    let locl = feature_idx(b"locl");
+    let latn = gsub
+        .script_list
+        .script_records
+        .iter()
+        .find(|rec| rec.script_tag == Tag::new(b"latn"))
+        .unwrap();
+    let trk = latn
+        .script
+        .lang_sys_records
+        .iter()
+        .find(|rec| rec.lang_sys_tag == Tag::new(b"TRK "))
+        .unwrap();
+
+    assert_eq!(trk.lang_sys.required_feature_index, locl);
+    assert!(!trk.lang_sys.feature_indices.contains(&locl));
+    assert!(trk.lang_sys.feature_indices.contains(&feature_idx(b"liga")));
+    // the required flag only applies to the language where it was declared
+    let latn_dflt = latn.script.default_lang_sys.as_ref().unwrap();
+    assert_eq!(latn_dflt.required_feature_index, 0xFFFF);
+}
+
+// `feature xxxx;` references inside `aalt` pull in the single and alternate
+// substitutions of the referenced features, in the order of the references.
+#[test]
+fn aalt_feature_references() {
+    use write_fonts::tables::gsub::SubstitutionLookup;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+feature aalt {
+    feature smcp;
+    feature salt;
+} aalt;
+
+feature salt {
+    sub a by b;
+    sub g by g.salt;
+} salt;
+
+feature smcp {
+    sub a by A;
+} smcp;
+",
+        "aalt_feature_references",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let lookups = &gsub.lookup_list.lookups;
+    // the aalt lookups are inserted at the front of the lookup list
+    let aalt = gsub
+        .feature_list
+        .feature_records
+        .iter()
+        .find(|rec| rec.feature_tag == "aalt")
+        .unwrap();
+    assert_eq!(aalt.feature.lookup_list_indices, [0, 1]);
+
+    let SubstitutionLookup::Single(single) = lookups[0].as_ref() else {
+        panic!("expected single sub, found {:?}", lookups[0]);
+    };
+    let SubstitutionLookup::Alternate(alternate) = lookups[1].as_ref() else {
+        panic!("expected alternate sub, found {:?}", lookups[1]);
+    };
+    assert_eq!(single.subtables.len(), 1);
+    assert_eq!(alternate.subtables.len(), 1);
+
+    // 'a' has alternates from both features, ordered by reference order
+    let alt_sets = &alternate.subtables[0].alternate_sets;
+    assert_eq!(alt_sets.len(), 1);
+    let glyph_map = mini_latin_glyph_map();
+    assert_eq!(
+        alt_sets[0].alternate_glyph_ids,
+        [glyph_map.get("A").unwrap(), glyph_map.get("b").unwrap()]
+    );
+}
+
+#[test]
+fn lookup_names() {
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+lookup upper {
+    sub a by A;
+} upper;
+
+feature aalt {
+    feature smcp;
+} aalt;
+
+feature smcp {
+    lookup upper;
+    sub b by B;
+} smcp;
+
+feature kern {
+    pos a b -10;
+} kern;
+",
+        "lookup_names",
+    );
+
+    // the aalt lookup is inserted first, and the ids of the others are updated
+    assert_eq!(
+        compilation.gsub_lookup_names,
+        ["aalt/DFLT/dflt auto #0", "upper", "smcp/DFLT/dflt auto #1"]
+    );
+    assert_eq!(compilation.gpos_lookup_names, ["kern/DFLT/dflt auto #0"]);
+    assert_eq!(
+        compilation.gsub.unwrap().lookup_list.lookups.len(),
+        compilation.gsub_lookup_names.len()
+    );
+}
+
+#[test]
+fn debug_table() {
+    let fea = "\
+languagesystem DFLT dflt;
+lookup upper {
+    sub a by A;
+} upper;
+feature smcp {
+    lookup upper;
+    sub b by B;
+} smcp;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_debug_table");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("debug_table.fea");
+    std::fs::write(&path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+    let compile = |opts: Opts| {
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map)
+            .with_opts(opts)
+            .compile()
+            .unwrap()
+    };
+
+    assert!(compile(Opts::new()).debg.is_none());
+    let debg = compile(Opts::new().debug_table(true)).debg.unwrap();
+    let debg = String::from_utf8(debg).unwrap();
+    assert!(debg.starts_with(r#"{"com.github.fonttools.feaLib": {"GPOS": {}, "GSUB": {"0": ["#));
+    for expected in [
+        r#"debug_table.fea:2:1", "upper", ["DFLT", "dflt", "smcp"]]"#,
+        r#"debug_table.fea:7:5", null, ["DFLT", "dflt", "smcp"]]"#,
+    ] {
+        assert!(debg.contains(expected), "missing '{expected}' in {debg}");
+    }
+}
+
+// `featureNames` in stylistic set features produce name records, and the
+// feature records get StylisticSet feature params pointing at them.
+#[test]
+fn stylistic_set_feature_names() {
+    use write_fonts::{tables::layout::FeatureParams, types::NameId};
+
+    let compilation = compile_fea(
+        r#"
+languagesystem DFLT dflt;
+
+feature ss01 {
+    featureNames {
+        name "Fancy";
+        name 1 "Fancy Mac";
+    };
+    sub a by b;
+} ss01;
+
+feature ss02 {
+    featureNames {
+        name "Fancier";
+    };
+    sub a by c;
+} ss02;
+"#,
+        "stylistic_set_feature_names",
+    );
+
+    let name = compilation.name.unwrap();
+    let gsub = compilation.gsub.unwrap();
+    let ui_name_id = |tag: &str| {
+        let record = gsub
+            .feature_list
+            .feature_records
+            .iter()
+            .find(|rec| rec.feature_tag == tag)
+            .unwrap();
+        match record.feature.feature_params.as_ref() {
+            Some(FeatureParams::StylisticSet(params)) => params.ui_name_id,
+            other => panic!("unexpected feature params {other:?}"),
+        }
+    };
+    let strings_for_id = |id: NameId| {
+        name.name_record
+            .iter()
+            .filter(|rec| rec.name_id == id)
+            .map(|rec| (rec.platform_id, rec.string.as_str()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ui_name_id("ss01"), NameId::new(256));
+    assert_eq!(ui_name_id("ss02"), NameId::new(257));
+    assert_eq!(
+        strings_for_id(NameId::new(256)),
+        [(1, "Fancy Mac"), (3, "Fancy")]
+    );
+    assert_eq!(strings_for_id(NameId::new(257)), [(3, "Fancier")]);
+}
+
+// All the parts of a `cvParameters` block end up in the CharacterVariant
+// feature params, with name ids assigned in declaration order.
+#[test]
+fn character_variant_params() {
+    use write_fonts::{tables::layout::FeatureParams, types::NameId};
+
+    let compilation = compile_fea(
+        r#"
+languagesystem DFLT dflt;
+
+feature cv01 {
+    cvParameters {
+        FeatUILabelNameID {
+            name "Alternate a";
+        };
+        FeatUITooltipTextNameID {
+            name "Tooltip";
+        };
+        SampleTextNameID {
+            name "aaa";
+        };
+        ParamUILabelNameID {
+            name "first";
+        };
+        ParamUILabelNameID {
+            name "second";
+        };
+        Character 0x61;
+        Character 0x1F600;
+    };
+    sub a by b;
+} cv01;
+"#,
+        "character_variant_params",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let record = &gsub.feature_list.feature_records[0];
+    assert_eq!(record.feature_tag, "cv01");
+    let Some(FeatureParams::CharacterVariant(params)) = record.feature.feature_params.as_ref()
+    else {
+        panic!("missing cv params");
+    };
+    assert_eq!(params.feat_ui_label_name_id, NameId::new(256));
+    assert_eq!(params.feat_ui_tooltip_text_name_id, NameId::new(257));
+    assert_eq!(params.sample_text_name_id, NameId::new(258));
+    assert_eq!(params.first_param_ui_label_name_id, NameId::new(259));
+    assert_eq!(params.num_named_parameters, 2);
+    assert_eq!(
+        params
+            .character
+            .iter()
+            .map(|c| c.to_u32())
+            .collect::<Vec<_>>(),
+        [0x61, 0x1F600]
+    );
+
+    let name = compilation.name.unwrap();
+    let strings = name
+        .name_record
+        .iter()
+        .map(|rec| (rec.name_id.to_u16(), rec.string.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        strings,
+        [
+            (256, "Alternate a"),
+            (257, "Tooltip"),
+            (258, "aaa"),
+            (259, "first"),
+            (260, "second")
+        ]
+    );
+}
+
+// The last FontRevision in a `table head` block is surfaced in the output;
+// an empty block produces no head table.
+#[test]
+fn head_table_font_revision() {
+    use write_fonts::types::Fixed;
+
+    let compilation = compile_fea(
+        "\
+table head {
+    FontRevision 1.001;
+    FontRevision 1.002;
+} head;
+",
+        "head_table_font_revision",
+    );
+    assert!(compilation.has_non_layout_tables());
+    let head = compilation.head.unwrap();
+    assert_eq!(head.font_revision, Fixed::from_f64(1.002));
+
+    let compilation = compile_fea("table head {\n} head;\n", "head_table_empty");
+    assert!(compilation.head.is_none());
+}
+
+// The metrics in a `table hhea` block are surfaced in the output hhea table.
+#[test]
+fn hhea_table_metrics() {
+    let compilation = compile_fea(
+        "\
+table hhea {
+    CaretOffset -50;
+    Ascender 800;
+    Descender -200;
+    LineGap 150;
+} hhea;
+",
+        "hhea_table_metrics",
+    );
+    let hhea = compilation.hhea.unwrap();
+    assert_eq!(hhea.caret_offset, -50);
+    assert_eq!(hhea.ascender.to_i16(), 800);
+    assert_eq!(hhea.descender.to_i16(), -200);
+    assert_eq!(hhea.line_gap.to_i16(), 150);
+}
+
+// The metrics in a `table vhea` block are surfaced in the output vhea table.
+#[test]
+fn vhea_table_metrics() {
+    let compilation = compile_fea(
+        "\
+table vhea {
+    VertTypoAscender 500;
+    VertTypoDescender -500;
+    VertTypoLineGap 1000;
+} vhea;
+",
+        "vhea_table_metrics",
+    );
+    let vhea = compilation.vhea.unwrap();
+    assert_eq!(vhea.ascender.to_i16(), 500);
+    assert_eq!(vhea.descender.to_i16(), -500);
+    assert_eq!(vhea.line_gap.to_i16(), 1000);
+}
+
+// The values in a `table OS/2` block are surfaced in the output OS/2 table.
+#[test]
+fn os2_table_values() {
+    use write_fonts::types::Tag;
+
+    let compilation = compile_fea(
+        r#"
+table OS/2 {
+    FSType 4;
+    Panose 2 15 0 0 2 2 4 3 2 4;
+    UnicodeRange 0 1 33;
+    CodePageRange 1252 1251 437;
+    TypoAscender 800;
+    TypoDescender -200;
+    TypoLineGap 200;
+    winAscent 900;
+    winDescent 300;
+    XHeight 400;
+    CapHeight 700;
+    WeightClass 700;
+    WidthClass 5;
+    Vendor "ADBE";
+    FamilyClass 0x0805;
+} OS/2;
+"#,
+        "os2_table_values",
+    );
+    let os2 = compilation.os2.unwrap();
+    assert_eq!(os2.fs_type, 4);
+    assert_eq!(os2.panose_10, [2, 15, 0, 0, 2, 2, 4, 3, 2, 4]);
+    assert_eq!(os2.ul_unicode_range_1, 0b11);
+    assert_eq!(os2.ul_unicode_range_2, 0b10);
+    // 1252 -> bit 0, 1251 -> bit 2, 437 -> bit 63
+    assert_eq!(os2.ul_code_page_range_1, Some(0b101));
+    assert_eq!(os2.ul_code_page_range_2, Some(1 << 31));
+    assert_eq!(os2.s_typo_ascender, 800);
+    assert_eq!(os2.s_typo_descender, -200);
+    assert_eq!(os2.s_typo_line_gap, 200);
+    assert_eq!(os2.us_win_ascent, 900);
+    assert_eq!(os2.us_win_descent, 300);
+    assert_eq!(os2.sx_height, Some(400));
+    assert_eq!(os2.s_cap_height, Some(700));
+    assert_eq!(os2.us_weight_class, 700);
+    assert_eq!(os2.us_width_class, 5);
+    assert_eq!(os2.ach_vend_id, Tag::new(b"ADBE"));
+    assert_eq!(os2.s_family_class, 0x0805);
+}
+
+// `nameid` statements produce name records, handling the platform, encoding
+// and language arguments as well as string escapes. If the same record is
+// specified more than once, the last one wins.
+#[test]
+fn name_table_records() {
+    let compilation = compile_fea(
+        r#"
+table name {
+    nameid 256 "overwritten";
+    nameid 256 "Win \00e9";
+    nameid 256 1 "Mac \8e";
+    nameid 257 3 1 0x0411 "Japanese";
+    nameid 258 1 0 0 "Mac Roman";
+} name;
+"#,
+        "name_table_records",
+    );
+    let name = compilation.name.unwrap();
+    let records = name
+        .name_record
+        .iter()
+        .map(|rec| {
+            (
+                rec.name_id.to_u16(),
+                rec.platform_id,
+                rec.encoding_id,
+                rec.language_id,
+                rec.string.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        [
+            (256, 1, 0, 0, "Mac é"),
+            (258, 1, 0, 0, "Mac Roman"),
+            (256, 3, 1, 0x409, "Win é"),
+            (257, 3, 1, 0x411, "Japanese"),
+        ]
+    );
+}
+
+// The statements in a `table GDEF` block are compiled into the GDEF table,
+// alongside the mark attachment data collected from lookupflags.
+#[test]
+fn gdef_table_statements() {
+    use write_fonts::tables::gdef::{CaretValue, GlyphClassDef};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+table GDEF {
+    GlyphClassDef [a b], [f_i f_f_i], [acutecomb brevecomb], ;
+    Attach a 3 7;
+    Attach b 1;
+    LigatureCaretByPos f_i 300;
+    LigatureCaretByIndex f_f_i 5 2;
+    LigatureCaretByPos f_i 500;
+} GDEF;
+
+feature test {
+    lookupflag MarkAttachmentType [acutecomb];
+    pos a 10;
+    lookupflag UseMarkFilteringSet [brevecomb];
+    pos b 10;
+} test;
+",
+        "gdef_table_statements",
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+    let gdef = compilation.gdef.unwrap();
+
+    let class_def = gdef.glyph_class_def.as_ref().unwrap();
+    for (glyph, class) in [
+        ("a", GlyphClassDef::Base),
+        ("f_i", GlyphClassDef::Ligature),
+        ("acutecomb", GlyphClassDef::Mark),
+    ] {
+        assert_eq!(class_def.get(gid(glyph)), class as u16, "{glyph}");
+    }
+    // explicit classes are exposed to the caller
+    let classes = compilation.gdef_classes.unwrap();
+    assert_eq!(classes.get(&gid("f_f_i")), Some(&GlyphClassDef::Ligature));
+
+    let attach = gdef.attach_list.as_ref().unwrap();
+    let points = attach
+        .attach_points
+        .iter()
+        .map(|p| p.point_indices.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(points, [vec![3, 7], vec![1]]);
+
+    let carets = gdef.lig_caret_list.as_ref().unwrap();
+    let carets = carets
+        .lig_glyphs
+        .iter()
+        .map(|lig| {
+            lig.caret_values
+                .iter()
+                .map(|caret| match caret.as_ref() {
+                    CaretValue::Format1(val) => val.coordinate as i32,
+                    CaretValue::Format2(val) => -(val.caret_value_point_index as i32),
+                    CaretValue::Format3(val) => val.coordinate as i32,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // the second rule for f_i is ignored; point indices are negated here
+    assert_eq!(carets, [vec![300], vec![-2, -5]]);
+
+    let mark_attach = gdef.mark_attach_class_def.as_ref().unwrap();
+    assert_eq!(mark_attach.get(gid("acutecomb")), 1);
+    let mark_sets = gdef.mark_glyph_sets_def.as_ref().unwrap();
+    assert_eq!(mark_sets.coverages.len(), 1);
+}
+
+// BASE tags are sorted when compiled; the per-script values must follow them.
+#[test]
+fn base_table_values() {
+    use write_fonts::{tables::base::BaseCoord, types::Tag};
+
+    let compilation = compile_fea(
+        "\
+table BASE {
+    HorizAxis.BaseTagList romn ideo;
+    HorizAxis.BaseScriptList latn romn 0 -120, hani ideo 0 -120;
+    HorizAxis.MinMax latn dflt -200, 800;
+    HorizAxis.MinMax latn TRK -250, 850;
+} BASE;
+",
+        "base_table_values",
+    );
+
+    let base = compilation.base.unwrap();
+    assert!(base.vert_axis.is_none());
+    let horiz = base.horiz_axis.as_ref().unwrap();
+    let tags = &horiz.base_tag_list.as_ref().unwrap().baseline_tags;
+    assert_eq!(tags, &[Tag::new(b"ideo"), Tag::new(b"romn")]);
+
+    let records = &horiz.base_script_list.base_script_records;
+    assert_eq!(records[0].base_script_tag, Tag::new(b"hani"));
+    assert_eq!(records[1].base_script_tag, Tag::new(b"latn"));
+    let latn = &records[1].base_script;
+    let values = latn.base_values.as_ref().unwrap();
+    assert_eq!(values.default_baseline_index, 1);
+    let coords = values
+        .base_coords
+        .iter()
+        .map(|coord| match coord.as_ref() {
+            BaseCoord::Format1(coord) => coord.coordinate,
+            other => panic!("unexpected coord {other:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(coords, [-120, 0]);
+    assert!(latn.default_min_max.is_some());
+    assert_eq!(latn.base_lang_sys_records.len(), 1);
+}
+
+// anonymous blocks are not interpreted, but are passed through to the caller
+#[test]
+fn anon_blocks_are_preserved() {
+    let compilation = compile_fea(
+        "\
+anon sbit {
+  72 % dpi
+  sizes { 10, 12, 14 }
+} sbit;
+
+feature test {
+    sub a by b;
+} test;
+
+anonymous foo {} foo;
+",
+        "anon_blocks_are_preserved",
+    );
+
+    assert_eq!(
+        compilation.anon_blocks,
+        [
+            AnonBlock {
+                tag: "sbit".into(),
+                contents: "\n  72 % dpi\n  sizes { 10, 12, 14 }\n".into(),
+            },
+            AnonBlock {
+                tag: "foo".into(),
+                contents: "".into(),
+            },
+        ]
+    );
+    assert!(compilation.gsub.is_some());
+}
+
+// things that are normally caught in validation are reported, not panics
+#[test]
+fn unvalidated_errors_are_reported() {
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, diagnostics) = crate::parse::parse_string(
+        "\
+feature test {
+    sub a by zzz;
+    pos @nope 10;
+    pos base a <anchor 0 0> mark @MISSING;
+    lookup not_a_lookup;
+} test;
+",
+    );
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    let Err(errors) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    ) else {
+        panic!("compilation should fail");
+    };
+    let messages = errors
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.text().to_owned())
+        .collect::<Vec<_>>();
+    for expected in [
+        "glyph 'zzz' is not in the font",
+        "glyph class '@nope' is not defined",
+        "mark class '@MISSING' is not defined",
+        "lookup 'not_a_lookup' is not defined",
+    ] {
+        assert!(messages.iter().any(|m| m == expected), "{messages:?}");
+    }
+}
+
+// too many mark attachment classes is an error, even if validation was skipped
+#[test]
+fn unvalidated_too_many_mark_attach_classes() {
+    use crate::DiagnosticCode;
+
+    let glyph_map = mini_latin_glyph_map();
+    let lookups = "abcdefghijklmnop"
+        .chars()
+        .map(|glyph| format!("lookup l_{glyph} {{ lookupflag MarkAttachmentType [{glyph}]; pos a b 1; }} l_{glyph};\n"))
+        .collect::<String>();
+    let (tree, diagnostics) = crate::parse::parse_string(lookups);
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    let Err(errors) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    ) else {
+        panic!("compilation should fail");
+    };
+    let [error] = errors.diagnostics() else {
+        panic!("expected one error, found {}", errors.display());
+    };
+    assert_eq!(error.code, DiagnosticCode::LimitExceeded);
+}
+
+// an ElidedFallbackNameID that isn't in the name table is an error, even if
+// validation was skipped
+#[test]
+fn stat_undefined_fallback_name_id() {
+    use crate::DiagnosticCode;
+
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, diagnostics) = crate::parse::parse_string(
+        "\
+table STAT {
+    ElidedFallbackNameID 300;
+    DesignAxis wght 0 { name \"W\"; };
+} STAT;
+",
+    );
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    let Err(errors) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    ) else {
+        panic!("compilation should fail");
+    };
+    let [error] = errors.diagnostics() else {
+        panic!("expected one error, found {}", errors.display());
+    };
+    assert_eq!(error.code, DiagnosticCode::UndefinedNameId);
+
+    // once the name is defined, compilation succeeds
+    let (tree, _) = crate::parse::parse_string(
+        "\
+table name { nameid 300 \"Regular\"; } name;
+table STAT {
+    ElidedFallbackNameID 300;
+    DesignAxis wght 0 { name \"W\"; };
+} STAT;
+",
+    );
+    let (compilation, _) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    )
+    .unwrap();
+    assert_eq!(
+        compilation.stat.unwrap().elided_fallback_name_id,
+        Some(300.into())
+    );
+}
+
+// tables we don't support are passed through, with a warning
+#[test]
+fn unknown_tables_are_preserved() {
+    use write_fonts::types::Tag;
+
+    let fea = "\
+table ZZZZ {
+    Something { 1 2 3 };
+} ZZZZ;
+
+feature test {
+    sub a by b;
+} test;
+";
+    let (compilation, _) = compile_fea_with_warnings(fea);
+
+    assert_eq!(
+        compilation.unknown_tables,
+        [UnknownTable {
+            tag: Tag::new(b"ZZZZ"),
+            contents: "\n    Something { 1 2 3 };\n".into(),
+        }]
+    );
+    assert!(compilation.gsub.is_some());
+    // the warning comes from validation
+    let warnings = validate_fea_warnings(fea);
+    assert!(
+        warnings.iter().any(|w| w.contains("'ZZZZ'")),
+        "{warnings:?}"
+    );
+}
+
+#[test]
+fn include_dirs_are_searched() {
+    let glyph_map = mini_latin_glyph_map();
+    let path = Path::new(INCLUDE_DIRS_TEST);
+    let compile = |include_dirs| {
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(path.join("main.fea"), &glyph_map)
+            .with_include_dirs(include_dirs)
+            .compile()
+    };
+
+    assert!(matches!(
+        compile(Vec::new()),
+        Err(CompilerError::ParseFail(_))
+    ));
+
+    let compilation = compile(vec![path.join("lib")]).unwrap();
+    assert!(compilation.gsub.is_some());
+}
+
+// diagnostics in included files are reported at their location in that file,
+// not at their offset in the combined tree
+#[test]
+fn diagnostics_in_included_files() {
+    let dir = std::env::temp_dir().join("fea_rs_test_diagnostics_in_included_files");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("main.fea"),
+        "languagesystem DFLT dflt;\ninclude(included.fea);\nfeature test { sub a by bb; } test;\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("included.fea"),
+        "# comment\nfeature liga {\n    sub f i by f_j;\n} liga;\n",
+    )
+    .unwrap();
+
+    let glyph_map = mini_latin_glyph_map();
+    let Err(CompilerError::ValidationFail(diagnostics)) =
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(dir.join("main.fea"), &glyph_map)
+            .compile()
+    else {
+        panic!("expected validation to fail");
+    };
+    let messages = diagnostics.to_string(false);
+    let included = dir.join("included.fea");
+    let main = dir.join("main.fea");
+    assert!(
+        messages.contains(&format!("in {} at 3:15", included.display())),
+        "{messages}"
+    );
+    assert!(
+        messages.contains(&format!("in {} at 3:24", main.display())),
+        "{messages}"
+    );
+}
+
+// lookups declared with `useExtension` (and any anonymous lookups they contain)
+// are wrapped in extension subtables.
+#[test]
+fn use_extension_lookups() {
+    use write_fonts::tables::{gpos::PositionLookup, gsub::SubstitutionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+lookup liga1 useExtension {
+    sub f i by f_i;
+} liga1;
+
+lookup liga2 {
+    sub f f by f_f;
+} liga2;
+
+lookup liga3 useExtension {
+    sub a f' f' i' by f_f_i;
+} liga3;
+
+feature aalt {
+    sub a by b;
+} aalt;
+
+feature liga {
+    lookup liga1;
+    lookup liga2;
+    lookup liga3;
+} liga;
+
+lookup kern1 useExtension {
+    pos A V -100;
+} kern1;
+
+feature kern {
+    lookup kern1;
+    pos V A -100;
+} kern;
+",
+        "use_extension_lookups",
+    );
+
+    let gsub = compilation.gsub.as_ref().unwrap();
+    let is_ext = gsub
+        .lookup_list
+        .lookups
+        .iter()
+        .map(|lookup| matches!(**lookup, SubstitutionLookup::Extension(_)))
+        .collect::<Vec<_>>();
+    // the aalt lookup is inserted at the front
+    assert_eq!(is_ext, [false, true, false, true, true]);
+
+    let gpos = compilation.gpos.as_ref().unwrap();
+    let is_ext = gpos
+        .lookup_list
+        .lookups
+        .iter()
+        .map(|lookup| matches!(**lookup, PositionLookup::Extension(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(is_ext, [true, false]);
+
+    compilation.to_binary(&mini_latin_glyph_map()).unwrap();
+}
+
+// an explicit subtable break starts a new class-based subtable, but glyph
+// pairs are always collected in a single subtable.
+#[test]
+fn pair_pos_subtable_breaks() {
+    use write_fonts::tables::gpos::{PairPos, PositionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+@group1 = [b o];
+@group2 = [c d];
+@group3 = [v w];
+
+feature kern {
+    pos A V -34;
+    pos @group1 @group2 -12;
+    subtable;
+    pos A W -30;
+    pos @group1 @group3 -10;
+} kern;
+",
+        "pair_pos_subtable_breaks",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Pair(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected pair pos lookup");
+    };
+    let subtables = lookup
+        .subtables
+        .iter()
+        .map(|sub| match sub.as_ref() {
+            PairPos::Format1(sub) => (
+                1,
+                sub.pair_sets
+                    .iter()
+                    .map(|set| set.pair_value_records.len())
+                    .sum(),
+            ),
+            PairPos::Format2(sub) => (2, sub.class1_records.len()),
+        })
+        .collect::<Vec<_>>();
+    // one subtable with both glyph pairs, and one per class group
+    assert_eq!(subtables, [(1, 2), (2, 1), (2, 1)]);
+}
+
+// the largest first class becomes class 0, which is left out of ClassDef1
+#[test]
+fn pair_pos_largest_class_is_class_zero() {
+    use write_fonts::tables::gpos::{PairPos, PositionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+@small = [e];
+@large = [a b c];
+@right = [d];
+
+feature kern {
+    pos @small @right -10;
+    pos @large @right -20;
+} kern;
+",
+        "pair_pos_largest_class_is_class_zero",
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Pair(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected pair pos lookup");
+    };
+    let PairPos::Format2(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected pair pos format 2");
+    };
+    assert_eq!(subtable.class1_records.len(), 2);
+    assert_eq!(subtable.class_def1.iter().count(), 1);
+    assert_eq!(subtable.class_def1.get(gid("e")), 1);
+    for glyph in ["a", "b", "c"] {
+        assert_eq!(subtable.class_def1.get(gid(glyph)), 0);
+    }
+}
+
+// mark classes can be used anywhere a glyph class is expected in a lookupflag
+#[test]
+fn lookupflag_mark_classes() {
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+markClass [acutecomb] <anchor 0 500> @TOP_MARKS;
+markClass [brevecomb] <anchor 0 500> @TOP_MARKS;
+markClass [dotbelowcomb] <anchor 0 -50> @BOTTOM_MARKS;
+
+feature test {
+    lookupflag MarkAttachmentType @TOP_MARKS;
+    pos a 10;
+    lookupflag UseMarkFilteringSet @BOTTOM_MARKS;
+    pos b 10;
+} test;
+",
+        "lookupflag_mark_classes",
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+    let gdef = compilation.gdef.unwrap();
+
+    let mark_attach = gdef.mark_attach_class_def.as_ref().unwrap();
+    assert_eq!(mark_attach.get(gid("acutecomb")), 1);
+    assert_eq!(mark_attach.get(gid("brevecomb")), 1);
+    assert_eq!(mark_attach.get(gid("dotbelowcomb")), 0);
+
+    let mark_sets = gdef.mark_glyph_sets_def.as_ref().unwrap();
+    assert_eq!(mark_sets.coverages.len(), 1);
+    let coverage = mark_sets.coverages[0].iter().collect::<Vec<_>>();
+    assert_eq!(coverage, [gid("dotbelowcomb")]);
+
+    let gpos = compilation.gpos.unwrap();
+    let flags = gpos
+        .lookup_list
+        .lookups
+        .iter()
+        .map(|lookup| match lookup.as_ref() {
+            write_fonts::tables::gpos::PositionLookup::Single(lookup) => {
+                (lookup.lookup_flag.to_bits(), lookup.mark_filtering_set)
+            }
+            _ => panic!("unexpected lookup type"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(flags, [(0x100, None), (0x10, Some(0))]);
+}
+
+// mark attachment classes and filter sets from lookupflags end up in the
+// compiled GDEF table, even if there is no GDEF block in the source.
+#[test]
+fn lookupflag_gdef_round_trip() {
+    use write_fonts::read::{FontRef, TableProvider, types::MajorMinor};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+feature test {
+    lookupflag MarkAttachmentType [acutecomb brevecomb];
+    pos a 10;
+    lookupflag UseMarkFilteringSet [dotbelowcomb];
+    pos b 10;
+    lookupflag UseMarkFilteringSet [ogonekcomb];
+    pos c 10;
+} test;
+",
+        "lookupflag_gdef_round_trip",
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+    let bytes = compilation.to_binary(&glyph_map).unwrap();
+    let font = FontRef::new(&bytes).unwrap();
+    let gdef = font.gdef().unwrap();
+    // mark glyph sets require version 1.2
+    assert_eq!(gdef.version(), MajorMinor::VERSION_1_2);
+
+    let mark_attach = gdef.mark_attach_class_def().unwrap().unwrap();
+    assert_eq!(mark_attach.get(gid("acutecomb")), 1);
+    assert_eq!(mark_attach.get(gid("brevecomb")), 1);
+
+    let mark_sets = gdef.mark_glyph_sets_def().unwrap().unwrap();
+    let sets = mark_sets
+        .coverages()
+        .iter()
+        .map(|cov| cov.unwrap().iter().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(sets, [vec![gid("dotbelowcomb")], vec![gid("ogonekcomb")]]);
+}
+
+// anchors with a contourpoint are compiled to AnchorFormat2
+#[test]
+fn contourpoint_anchors() {
+    use write_fonts::tables::gpos::{AnchorTable, PositionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+anchorDef 0 500 contourpoint 3 TOP_ANCHOR;
+markClass [acutecomb] <anchor TOP_ANCHOR> @TOP;
+
+feature mark {
+    pos base a <anchor 250 450 contourpoint 5> mark @TOP;
+} mark;
+",
+        "contourpoint_anchors",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::MarkToBase(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected mark-to-base lookup");
+    };
+    let subtable = &lookup.subtables[0];
+    let anchor_point = |anchor: &AnchorTable| match anchor {
+        AnchorTable::Format2(anchor) => (
+            anchor.x_coordinate,
+            anchor.y_coordinate,
+            anchor.anchor_point,
+        ),
+        other => panic!("expected format 2 anchor, found {other:?}"),
+    };
+
+    let mark = &subtable.mark_array.mark_records[0].mark_anchor;
+    assert_eq!(anchor_point(mark), (0, 500, 3));
+    let base = subtable.base_array.base_records[0].base_anchors[0]
+        .as_ref()
+        .unwrap();
+    assert_eq!(anchor_point(base), (250, 450, 5));
+}
+
+// anchors with device tables are compiled to AnchorFormat3
+#[test]
+fn device_anchors() {
+    use write_fonts::tables::gpos::{AnchorTable, PositionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+markClass [acutecomb] <anchor 120 -20 <device 11 1> <device NULL>> @TOP;
+
+feature mark {
+    pos base a <anchor 250 450 <device NULL> <device 14 -2, 11 1, 12 -1>> mark @TOP;
+} mark;
+",
+        "device_anchors",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::MarkToBase(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected mark-to-base lookup");
+    };
+    let subtable = &lookup.subtables[0];
+
+    let AnchorTable::Format3(mark) = subtable.mark_array.mark_records[0].mark_anchor.as_ref()
+    else {
+        panic!("expected format 3 anchor");
+    };
+    assert_eq!((mark.x_coordinate, mark.y_coordinate), (120, -20));
+    assert!(mark.x_device.is_some());
+    assert!(mark.y_device.is_none());
+
+    let base = subtable.base_array.base_records[0].base_anchors[0]
+        .as_ref()
+        .unwrap();
+    let AnchorTable::Format3(base) = base else {
+        panic!("expected format 3 anchor");
+    };
+    assert!(base.x_device.is_none());
+    // sizes are sorted, and gaps are filled with zeros
+    let expected = write_fonts::tables::layout::Device::new(11, 14, &[1, -1, 0, -2]);
+    let y_device = base.y_device.as_ref().unwrap();
+    assert_eq!(
+        y_device,
+        &write_fonts::tables::layout::DeviceOrVariationIndex::Device(expected)
+    );
+}
+
+// device tables in value records set the appropriate value format bits
+#[test]
+fn value_record_devices() {
+    use write_fonts::tables::gpos::{PositionLookup, SinglePos, ValueFormat};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+feature kern {
+    pos a <-80 0 -160 0 <device 9 -1, 10 -1> <device NULL> <device NULL> <device NULL>>;
+} kern;
+",
+        "value_record_devices",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Single(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 1");
+    };
+    assert_eq!(
+        subtable.value_record.format(),
+        ValueFormat::X_PLACEMENT | ValueFormat::X_ADVANCE | ValueFormat::X_PLACEMENT_DEVICE
+    );
+    assert_eq!(subtable.value_record.x_placement, Some(-80));
+    assert_eq!(subtable.value_record.x_advance, Some(-160));
+    assert!(subtable.value_record.x_placement_device.is_some());
+    assert!(subtable.value_record.x_advance_device.is_none());
+}
+
+// variable value records and anchors add deltas to the GDEF variation store
+#[test]
+fn variable_value_records_and_anchors() {
+    use write_fonts::tables::{
+        gpos::{AnchorTable, PositionLookup, SinglePos, ValueFormat},
+        layout::DeviceOrVariationIndex,
+    };
+
+    let compilation = compile_fea_variable(
+        "\
+languagesystem DFLT dflt;
+
+markClass acutecomb <anchor 0 (wght=200:500 wght=1000:520)> @TOP;
+
+feature kern {
+    pos a <(wght=200:-80 wght=1000:-120) 0 0 0>;
+} kern;
+
+feature mark {
+    pos base b <anchor (wght=200:250 wght=1000:300) 500> mark @TOP;
+} mark;
+",
+        "variable_value_records_and_anchors",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Single(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 1");
+    };
+    let record = &subtable.value_record;
+    assert_eq!(
+        record.format(),
+        ValueFormat::X_PLACEMENT | ValueFormat::X_PLACEMENT_DEVICE
+    );
+    assert_eq!(record.x_placement, Some(-80));
+    assert!(matches!(
+        record.x_placement_device.as_ref(),
+        Some(DeviceOrVariationIndex::VariationIndex(_))
+    ));
+
+    let PositionLookup::MarkToBase(lookup) = gpos.lookup_list.lookups[1].as_ref() else {
+        panic!("expected mark to base lookup");
+    };
+    let base_anchor = lookup.subtables[0].base_array.base_records[0].base_anchors[0]
+        .as_ref()
+        .unwrap();
+    let AnchorTable::Format3(base_anchor) = base_anchor else {
+        panic!("expected format 3 anchor");
+    };
+    assert_eq!(base_anchor.x_coordinate, 250);
+    assert!(matches!(
+        base_anchor.x_device.as_ref(),
+        Some(DeviceOrVariationIndex::VariationIndex(_))
+    ));
+    assert!(base_anchor.y_device.is_none());
+
+    let gdef = compilation.gdef.unwrap();
+    let var_store = gdef.item_var_store.as_ref().unwrap();
+    // one region, shared by all three variable metrics
+    assert_eq!(var_store.variation_region_list.variation_regions.len(), 1);
+    // all deltas fit in a byte
+    let mut deltas = var_store.item_variation_data[0]
+        .as_ref()
+        .unwrap()
+        .delta_sets
+        .iter()
+        .map(|byte| *byte as i8)
+        .collect::<Vec<_>>();
+    deltas.sort_unstable();
+    assert_eq!(deltas, [-40, 20, 50]);
+}
+
+// pairs with the same variable metric share their variation data
+#[test]
+fn variable_value_records_are_shared() {
+    use write_fonts::tables::gpos::{PairPos, PositionLookup};
+
+    let compilation = compile_fea_variable(
+        "\
+languagesystem DFLT dflt;
+
+feature kern {
+    pos a b <0 0 (wght=200:-80 wght=1000:-120) 0>;
+    pos c d <0 0 (wght=1000:-120 wght=200:-80) 0>;
+    pos e f <0 0 (wght=200:-40 wght=1000:-60) 0>;
+} kern;
+",
+        "variable_value_records_are_shared",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Pair(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected pair pos lookup");
+    };
+    let PairPos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected pair pos format 1");
+    };
+    let devices = subtable
+        .pair_sets
+        .iter()
+        .map(|pair_set| {
+            pair_set.pair_value_records[0]
+                .value_record1
+                .x_advance_device
+                .as_ref()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(devices.len(), 3);
+    assert_eq!(devices[0], devices[1]);
+    assert_ne!(devices[0], devices[2]);
+
+    // one row of deltas for each distinct metric, each of which fits a byte
+    let gdef = compilation.gdef.unwrap();
+    let var_store = gdef.item_var_store.as_ref().unwrap();
+    let data = var_store.item_variation_data[0].as_ref().unwrap();
+    assert_eq!(data.delta_sets.len(), 2);
+}
+
+// zero fields are pruned from value records, including variable metrics that
+// have the same value at every location
+#[test]
+fn value_record_zeros_are_pruned() {
+    use write_fonts::tables::gpos::{PairPos, PositionLookup, SinglePos, ValueFormat};
+
+    let compilation = compile_fea_variable(
+        "\
+languagesystem DFLT dflt;
+
+feature kern {
+    lookup single {
+        pos a <(wght=200:-80 wght=1000:-80) 0 (wght=200:0 wght=1000:0) 0>;
+    } single;
+    lookup zero {
+        pos b <0 0 0 0>;
+    } zero;
+    lookup pair {
+        pos a b <0 0 (wght=200:0 wght=1000:0) 0>;
+    } pair;
+} kern;
+",
+        "value_record_zeros_are_pruned",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let lookups = &gpos.lookup_list.lookups;
+    let PositionLookup::Single(lookup) = lookups[0].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 1");
+    };
+    assert_eq!(subtable.value_record.format(), ValueFormat::X_PLACEMENT);
+    assert_eq!(subtable.value_record.x_placement, Some(-80));
+
+    let PositionLookup::Single(lookup) = lookups[1].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 1");
+    };
+    assert_eq!(subtable.value_record.format(), ValueFormat::empty());
+
+    // in a pair, an all-zero record is a zero advance
+    let PositionLookup::Pair(lookup) = lookups[2].as_ref() else {
+        panic!("expected pair pos lookup");
+    };
+    let PairPos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected pair pos format 1");
+    };
+    let record = &subtable.pair_sets[0].pair_value_records[0];
+    assert_eq!(record.value_record1.format(), ValueFormat::X_ADVANCE);
+    assert_eq!(record.value_record1.x_advance, Some(0));
+    assert_eq!(record.value_record2.format(), ValueFormat::empty());
+    assert!(
+        compilation
+            .gdef
+            .is_none_or(|gdef| gdef.item_var_store.is_none())
+    );
+}
+
+// a variation block adds a FeatureVariations record that adds the
+// variation's lookups when the conditions are met
+#[test]
+fn feature_variations_rvrn() {
+    use write_fonts::{tables::layout::Condition, types::Tag};
+
+    let compilation = compile_fea_variable(
+        "\
+languagesystem DFLT dflt;
+
+conditionset heavy {
+    wght 600 1000;
+} heavy;
+
+feature rvrn {
+    sub a by b;
+} rvrn;
+
+variation rvrn heavy {
+    sub a by c;
+} rvrn;
+",
+        "feature_variations_rvrn",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    assert_eq!(gsub.lookup_list.lookups.len(), 2);
+    let variations = gsub.feature_variations.as_ref().unwrap();
+    assert_eq!(variations.feature_variation_records.len(), 1);
+    let record = &variations.feature_variation_records[0];
+
+    let conditions = &record.condition_set.as_ref().unwrap().conditions;
+    assert_eq!(conditions.len(), 1);
+    let Condition::Format1AxisRange(condition) = conditions[0].as_ref() else {
+        panic!("expected axis range condition");
+    };
+    assert_eq!(condition.axis_index, 0);
+    assert_eq!(condition.filter_range_min_value.to_f32(), 0.5);
+    assert_eq!(condition.filter_range_max_value.to_f32(), 1.0);
+
+    let substitutions = record.feature_table_substitution.as_ref().unwrap();
+    let substitution = &substitutions.substitutions[0];
+    assert_eq!(
+        gsub.feature_list.feature_records[substitution.feature_index as usize].feature_tag,
+        Tag::new(b"rvrn")
+    );
+    // the alternate feature keeps the default lookups, as in feaLib
+    assert_eq!(substitution.alternate_feature.lookup_list_indices, [0, 1]);
+}
+
+// mark class names can be used anywhere a glyph class is legal
+#[test]
+fn mark_class_as_glyph_class() {
+    use write_fonts::tables::gsub::{SingleSubst, SubstitutionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+markClass [acutecomb brevecomb] <anchor 0 500> @TOP;
+@TOP_AND_A = [@TOP a];
+
+feature test {
+    sub @TOP_AND_A by b;
+    sub c @TOP' by f;
+} test;
+",
+        "mark_class_as_glyph_class",
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+    let gsub = compilation.gsub.unwrap();
+    let SubstitutionLookup::Single(lookup) = gsub.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single subst lookup");
+    };
+    let coverage = match lookup.subtables[0].as_ref() {
+        SingleSubst::Format1(table) => table.coverage.iter().collect::<Vec<_>>(),
+        SingleSubst::Format2(table) => table.coverage.iter().collect::<Vec<_>>(),
+    };
+    assert_eq!(coverage, [gid("a"), gid("acutecomb"), gid("brevecomb")]);
+    assert!(matches!(
+        gsub.lookup_list.lookups[1].as_ref(),
+        SubstitutionLookup::ChainContextual(_)
+    ));
+}
+
+// CIDs can be used in rules, classes, mark classes and (sparse) ranges
+#[test]
+fn cid_keyed_glyph_map() {
+    use crate::GlyphIdent;
+    use write_fonts::tables::{gpos::PositionLookup, gsub::SubstitutionLookup};
+
+    let dir = std::env::temp_dir().join("fea_rs_test_cid_keyed_glyph_map");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fea_path = dir.join("cid_keyed_glyph_map.fea");
+    std::fs::write(
+        &fea_path,
+        "\
+languagesystem DFLT dflt;
+@RANGE = [\\1-\\6];
+markClass \\8 <anchor 0 500> @TOP;
+
+feature test {
+    sub @RANGE by \\7;
+} test;
+
+feature mark {
+    pos base \\2 <anchor 100 400> mark @TOP;
+} mark;
+",
+    )
+    .unwrap();
+
+    // a CID-keyed font where CID 5 is missing
+    let glyph_map = std::iter::once(GlyphIdent::from(".notdef"))
+        .chain([1, 2, 3, 4, 6, 7, 8].map(GlyphIdent::Cid))
+        .collect::<GlyphMap>();
+    let gid = |cid: u16| glyph_map.get(&cid).unwrap();
+
+    let compilation =
+        Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(fea_path, &glyph_map)
+            .compile()
+            .expect("compilation should succeed");
+
+    let gsub = compilation.gsub.unwrap();
+    let SubstitutionLookup::Single(lookup) = gsub.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single subst lookup");
+    };
+    let coverage = match lookup.subtables[0].as_ref() {
+        write_fonts::tables::gsub::SingleSubst::Format1(table) => table.coverage.iter(),
+        write_fonts::tables::gsub::SingleSubst::Format2(table) => table.coverage.iter(),
+    }
+    .collect::<Vec<_>>();
+    assert_eq!(coverage, [1, 2, 3, 4, 6].map(gid));
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::MarkToBase(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected mark-to-base lookup");
+    };
+    let subtable = &lookup.subtables[0];
+    assert_eq!(subtable.mark_coverage.iter().collect::<Vec<_>>(), [gid(8)]);
+    assert_eq!(subtable.base_coverage.iter().collect::<Vec<_>>(), [gid(2)]);
+}
+
+#[test]
+fn glyph_aliases() {
+    let mut glyph_map = mini_latin_glyph_map();
+    glyph_map.add_alias("alpha", "a").unwrap();
+    let (tree, diagnostics) = crate::parse::parse_string(
+        "\
+feature test {
+    sub alpha by b;
+    sub a by c;
+} test;
+",
+    );
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    assert!(!crate::compile::validate::<MockVariationInfo>(&tree, &glyph_map, None).has_errors());
+
+    let warnings = |opts| {
+        let (_, warnings) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+            &tree, &glyph_map, None, None, opts,
+        )
+        .unwrap_or_else(|errs| panic!("{}", errs.display()));
+        warnings
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.text().to_owned())
+            .collect::<Vec<_>>()
+    };
+    // both names resolve to the same glyph, so the rules conflict
+    assert_eq!(
+        warnings(Opts::new()),
+        ["conflicting rule for 'a', keeping the earlier rule at 2:4"]
+    );
+    assert_eq!(
+        warnings(Opts::new().glyph_aliases_in_diagnostics(true)),
+        ["conflicting rule for 'alpha', keeping the earlier rule at 2:4"]
+    );
+}
+
+// when rules in a lookup conflict we keep the first and warn, as feaLib does
+#[test]
+fn conflicting_rules_keep_first() {
+    use write_fonts::tables::{
+        gpos::{PairPos, PositionLookup},
+        gsub::{SingleSubst, SubstitutionLookup},
+    };
+
+    let (compilation, warnings) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+
+feature test {
+    sub a by b;
+    sub a by c;
+    sub [a f] by [b d];
+} test;
+
+feature liga {
+    sub f i by f_i;
+    sub f i by f_f;
+    sub f i by f_i;
+} liga;
+
+feature kern {
+    pos a v -10;
+    enum pos [a b] [v w] -20;
+} kern;
+",
+    );
+
+    assert_eq!(
+        warnings,
+        [
+            "conflicting rule for 'a', keeping the earlier rule at 4:4",
+            "conflicting rule for 'f i', keeping the earlier rule at 10:4",
+            "conflicting rule for 'a v', keeping the earlier rule at 16:4",
+        ]
+    );
+
+    let glyph_map = mini_latin_glyph_map();
+    let gid = |name: &str| glyph_map.get(name).unwrap();
+
+    let gsub = compilation.gsub.unwrap();
+    let SubstitutionLookup::Single(lookup) = gsub.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single subst lookup");
+    };
+    let SingleSubst::Format2(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single subst format 2");
+    };
+    assert_eq!(subtable.substitute_glyph_ids, [gid("b"), gid("d")]);
+
+    let SubstitutionLookup::Ligature(lookup) = gsub.lookup_list.lookups[1].as_ref() else {
+        panic!("expected ligature subst lookup");
+    };
+    let ligature_set = &lookup.subtables[0].ligature_sets[0];
+    assert_eq!(ligature_set.ligatures.len(), 1);
+    assert_eq!(ligature_set.ligatures[0].ligature_glyph, gid("f_i"));
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Pair(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected pair pos lookup");
+    };
+    let PairPos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected pair pos format 1");
+    };
+    let first_pair = &subtable.pair_sets[0].pair_value_records[0];
+    assert_eq!(first_pair.second_glyph, gid("v"));
+    assert_eq!(first_pair.value_record1.x_advance, Some(-10));
+}
+
+#[test]
+fn unused_lookups_and_classes() {
+    let warnings = validate_fea_warnings(
+        "\
+languagesystem DFLT dflt;
+
+@used = [a b];
+@alias = @used;
+@unused = [c d];
+@also_unused = [f];
+
+lookup referenced {
+    sub @alias by f;
+} referenced;
+
+lookup nested {
+    sub b by c;
+} nested;
+
+lookup unreferenced {
+    sub c' lookup nested d;
+} unreferenced;
+
+feature test {
+    lookup referenced;
+    lookup inline {
+        sub d by a;
+    } inline;
+} test;
+",
+    );
+
+    assert_eq!(
+        warnings,
+        [
+            "glyph class '@unused' is never used",
+            "glyph class '@also_unused' is never used",
+            "lookup 'unreferenced' is never used",
+        ]
+    );
+}
+
+#[test]
+fn languagesystem_tag_warnings() {
+    let warnings = validate_fea_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem Latn dflt;
+languagesystem latn deu;
+languagesystem dflt dflt;
+languagesystem cyrl DFLT;
+languagesystem nko dflt;
+languagesystem cyrl SRB;
+languagesystem cyrl SRB;
+",
+    );
+
+    assert_eq!(
+        warnings,
+        [
+            "malformed script tag 'Latn', script tags are lowercase",
+            "malformed language tag 'deu ', language tags are uppercase",
+            "'dflt' is the default language tag, the default script is 'DFLT'",
+            "'DFLT' is the default script tag, the default language is 'dflt'",
+            "Duplicate languagesystem definition",
+        ]
+    );
+}
+
+#[test]
+fn gsub_closure() {
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+lookup nested {
+    sub c by d;
+} nested;
+
+feature test {
+    sub a by b;
+    sub b from [c A];
+    sub f f i by f_f_i;
+    sub f i by f_i;
+    sub v' lookup nested w;
+} test;
+",
+        "closure",
+    );
+    let glyph_map = mini_latin_glyph_map();
+    let gids = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| glyph_map.get(*name).unwrap())
+            .collect::<BTreeSet<_>>()
+    };
+
+    assert_eq!(
+        compilation.gsub_closure(gids(&["a"])),
+        gids(&["a", "b", "c", "d", "A"])
+    );
+    // ligatures need all their components
+    assert_eq!(
+        compilation.gsub_closure(gids(&["f", "i"])),
+        gids(&["f", "i", "f_i", "f_f_i"])
+    );
+    assert_eq!(compilation.gsub_closure(gids(&["i"])), gids(&["i"]));
+}
+
+#[test]
+fn compilation_stats() {
+    use write_fonts::types::Tag;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+
+@TOP_MARKS = [acutecomb];
+@BOTTOM_MARKS = [dotbelowcomb];
+
+lookup single {
+    sub a by b;
+} single;
+
+feature liga {
+    sub f i by f_i;
+    sub f f i by f_f_i;
+    lookup single;
+} liga;
+
+feature salt {
+    lookup single;
+} salt;
+
+feature kern {
+    lookupflag MarkAttachmentType @TOP_MARKS;
+    pos a b -10;
+    lookupflag UseMarkFilteringSet @BOTTOM_MARKS;
+    pos a c -20;
+} kern;
+",
+        "stats",
+    );
+    let stats = compilation.stats();
+    assert_eq!(stats.tables.len(), 2);
+    let gsub = &stats.tables[0];
+    assert_eq!(gsub.tag, Tag::new(b"GSUB"));
+    let features = gsub
+        .features
+        .iter()
+        .map(|feature| (feature.tag.to_string(), feature.lookup_count))
+        .collect::<Vec<_>>();
+    assert_eq!(features, [("liga".into(), 2), ("salt".into(), 1)]);
+    let kinds = gsub
+        .lookups
+        .iter()
+        .map(|lookup| (lookup.kind, lookup.subtable_count))
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, [("SingleSubst", 1), ("LigatureSubst", 1)]);
+    assert!(gsub.lookups.iter().all(|lookup| lookup.size.unwrap() > 0));
+    assert!(gsub.size.unwrap() > gsub.lookups[0].size.unwrap());
+
+    let gpos = &stats.tables[1];
+    assert_eq!(gpos.lookups.len(), 2);
+    assert_eq!(gpos.lookups[0].kind, "PairPos");
+    assert_eq!(stats.mark_attachment_classes, 1);
+    assert_eq!(stats.mark_filtering_sets, 1);
+
+    let text = stats.to_string();
+    assert!(text.contains("feature liga: 2 lookups"), "{text}");
+    assert!(text.contains("GDEF:"), "{text}");
+    let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+    assert_eq!(json["tables"][0]["features"][0]["tag"], "liga");
+    assert_eq!(json["tables"][1]["lookups"][0]["kind"], "PairPos");
+    assert_eq!(json["mark_filtering_sets"], 1);
+}
+
+/// Compile the test at `path`, and check that our TTX output for each of the
+/// `tags` matches the expected `.ttx` file, returning our output.
+fn assert_ttx_tables_match(path: &Path, glyph_map: &GlyphMap, tags: &[&str]) -> String {
+    let compilation = Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(path, glyph_map)
+        .compile()
+        .unwrap();
+    let ttx = compilation.to_ttx(glyph_map).unwrap();
+
+    // the expected file may also contain tables we don't write
+    let expected = std::fs::read_to_string(path.with_extension("ttx")).unwrap();
+    let table = |ttx: &str, tag: &str| {
+        let start = ttx
+            .find(&format!("  <{tag}>"))
+            .unwrap_or_else(|| panic!("no {tag} in {}", path.display()));
+        let end = ttx.find(&format!("  </{tag}>")).unwrap();
+        ttx[start..end].to_string()
+    };
+    for tag in tags {
+        assert_eq!(
+            table(&ttx, tag),
+            table(&expected, tag),
+            "{tag} in {}",
+            path.display()
+        );
+    }
+    ttx
+}
+
+// our TTX output matches the layout tables in ttx's output for the same font
+#[test]
+fn ttx_dump() {
+    let path = Path::new(ROOT_TEST_DIR)
+        .join("mini-latin")
+        .join(GOOD_DIR)
+        .join("contextual_format_2.fea");
+    let ttx = assert_ttx_tables_match(&path, &mini_latin_glyph_map(), &["GDEF", "GSUB"]);
+    assert!(ttx.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ttFont>\n"));
+    assert!(ttx.ends_with("  </GSUB>\n\n</ttFont>\n"));
+}
+
+#[test]
+fn ttx_dump_gpos() {
+    let glyph_map = mini_latin_glyph_map();
+    let dir = Path::new(ROOT_TEST_DIR).join("mini-latin").join(GOOD_DIR);
+    // pair pos formats 1 and 2, and mark-to-base with a null anchor
+    for (name, tags) in [
+        ("basic_kern.fea", &["GPOS"][..]),
+        ("pairpos_format_choice.fea", &["GPOS"]),
+        ("mark_base_null_anchor.fea", &["GDEF", "GPOS"]),
+    ] {
+        assert_ttx_tables_match(&dir.join(name), &glyph_map, tags);
+    }
+
+    // mark-to-ligature and mark-to-mark
+    let glyph_map = test_utils::fonttools_test_glyph_order();
+    let dir = Path::new(FONTTOOLS_TEST_DIR);
+    for name in ["GPOS_5.fea", "GPOS_6.fea"] {
+        assert_ttx_tables_match(&dir.join(name), &glyph_map, &["GDEF", "GPOS"]);
+    }
+}
+
+#[test]
+fn ttx_dump_base() {
+    let path = Path::new(ROOT_TEST_DIR)
+        .join("mini-latin")
+        .join(GOOD_DIR)
+        .join("base_table.fea");
+    assert_ttx_tables_match(&path, &mini_latin_glyph_map(), &["BASE"]);
+
+    // with min/max coordinates
+    let path = Path::new(FONTTOOLS_TEST_DIR).join("spec9a2.fea");
+    assert_ttx_tables_match(&path, &test_utils::fonttools_test_glyph_order(), &["BASE"]);
+}
+
+#[test]
+fn ttx_dump_stat() {
+    let glyph_map = test_utils::fonttools_test_glyph_order();
+    for name in ["STAT_test.fea", "STAT_test_elidedFallbackNameID.fea"] {
+        let path = Path::new(FONTTOOLS_TEST_DIR).join(name);
+        assert_ttx_tables_match(&path, &glyph_map, &["STAT"]);
+    }
+}
+
+// NULL anchors compile to null anchor offsets
+#[test]
+fn null_anchors() {
+    use write_fonts::tables::gpos::PositionLookup;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+markClass acutecomb <anchor 0 500> @TOP;
+markClass dotbelowcomb <anchor 0 -50> @BOTTOM;
+
+feature curs {
+    pos cursive a <anchor NULL> <anchor 500 0>;
+} curs;
+
+feature mark {
+    pos base b <anchor 250 450> mark @TOP <anchor NULL> mark @BOTTOM;
+    pos base c <anchor NULL> mark @TOP;
+    pos ligature f_i <anchor 200 500> mark @TOP
+        ligComponent <anchor NULL> mark @TOP;
+} mark;
+
+feature mkmk {
+    pos mark brevecomb <anchor NULL> mark @TOP;
+} mkmk;
+",
+        "null_anchors",
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let lookups = &gpos.lookup_list.lookups;
+
+    let PositionLookup::Cursive(lookup) = lookups[0].as_ref() else {
+        panic!("expected cursive lookup");
+    };
+    let record = &lookup.subtables[0].entry_exit_record[0];
+    assert!(record.entry_anchor.is_none());
+    assert!(record.exit_anchor.is_some());
+
+    let PositionLookup::MarkToBase(lookup) = lookups[1].as_ref() else {
+        panic!("expected mark-to-base lookup");
+    };
+    let subtable = &lookup.subtables[0];
+    assert_eq!(subtable.mark_array.mark_records.len(), 2);
+    let anchors = &subtable.base_array.base_records[0].base_anchors;
+    assert_eq!(anchors.len(), 2);
+    assert!(anchors[0].is_some());
+    assert!(anchors[1].is_none());
+    // a base whose anchors are all NULL is still covered, as in feaLib
+    assert_eq!(subtable.base_coverage.iter().count(), 2);
+    let anchors = &subtable.base_array.base_records[1].base_anchors;
+    assert_eq!(anchors.len(), 2);
+    assert!(anchors.iter().all(|anchor| anchor.is_none()));
+
+    let PositionLookup::MarkToLig(lookup) = lookups[2].as_ref() else {
+        panic!("expected mark-to-ligature lookup");
+    };
+    let components = &lookup.subtables[0].ligature_array.ligature_attaches[0].component_records;
+    assert_eq!(components.len(), 2);
+    assert!(components[0].ligature_anchors[0].is_some());
+    assert!(components[1].ligature_anchors[0].is_none());
+
+    // likewise for a base mark whose anchors are all NULL
+    let PositionLookup::MarkToMark(lookup) = lookups[3].as_ref() else {
+        panic!("expected mark-to-mark lookup");
+    };
+    let subtable = &lookup.subtables[0];
+    assert_eq!(subtable.mark2_coverage.iter().count(), 1);
+    let anchors = &subtable.mark2_array.mark2_records[0].mark2_anchors;
+    assert_eq!(anchors.len(), 1);
+    assert!(anchors[0].is_none());
+}
+
+// rules for the same ligature with different mark classes are merged, and
+// component counts are checked against the GDEF ligature carets
+#[test]
+fn mark_to_lig_merges_rules() {
+    use write_fonts::tables::gpos::PositionLookup;
+
+    let (compilation, warnings) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+
+markClass acutecomb <anchor 0 500> @TOP;
+markClass dotbelowcomb <anchor 0 -50> @BOTTOM;
+
+table GDEF {
+    LigatureCaretByPos f_i 300;
+    LigatureCaretByPos f_f_i 200;
+} GDEF;
+
+feature mark {
+    pos ligature f_i <anchor 200 500> mark @TOP
+        ligComponent <anchor 500 500> mark @TOP;
+    pos ligature f_i <anchor 200 -50> mark @BOTTOM
+        ligComponent <anchor NULL>;
+    pos ligature f_f_i <anchor 100 500> mark @TOP
+        ligComponent <anchor 300 500> mark @TOP
+        ligComponent <anchor 500 500> mark @TOP;
+} mark;
+",
+    );
+
+    assert_eq!(
+        warnings,
+        ["ligature 'f_f_i' has 3 components, but 1 ligature carets in GDEF"]
+    );
+
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::MarkToLig(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected mark-to-ligature lookup");
+    };
+    let subtable = &lookup.subtables[0];
+    let glyph_map = mini_latin_glyph_map();
+    let f_i_idx = subtable
+        .ligature_coverage
+        .iter()
+        .position(|gid| gid == glyph_map.get("f_i").unwrap())
+        .unwrap();
+    let components = &subtable.ligature_array.ligature_attaches[f_i_idx].component_records;
+    assert_eq!(components.len(), 2);
+    // the first component has anchors for both classes, the second only for @TOP
+    assert!(components[0].ligature_anchors.iter().all(|a| a.is_some()));
+    assert_eq!(
+        components[1]
+            .ligature_anchors
+            .iter()
+            .filter(|a| a.is_some())
+            .count(),
+        1
+    );
+}
+
+// ignore rules and contextual rules in the same block share a lookup, so that
+// their relative order is preserved
+#[test]
+fn ignore_and_contextual_rules_share_lookup() {
+    use write_fonts::tables::{gpos::PositionLookup, gsub::SubstitutionLookup};
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+lookup single {
+    sub c by d;
+} single;
+
+feature test {
+    ignore sub a b';
+    sub b' lookup single c;
+    ignore sub d c';
+    sub c' d by f;
+    sub a' by b;
+} test;
+
+feature kern {
+    ignore pos a b';
+    pos b' 10 c;
+    ignore pos d c';
+    pos c' d' 20;
+} kern;
+",
+        "ignore_contextual",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let lookups = &gsub.lookup_list.lookups;
+    assert_eq!(lookups.len(), 3);
+    let SubstitutionLookup::ChainContextual(chain) = lookups[1].as_ref() else {
+        panic!("expected chain contextual lookup");
+    };
+    assert_eq!(chain.subtables.len(), 1);
+    // both inline rules share a single anonymous lookup
+    assert!(matches!(lookups[2].as_ref(), SubstitutionLookup::Single(_)));
+
+    let gpos = compilation.gpos.unwrap();
+    let lookups = &gpos.lookup_list.lookups;
+    assert_eq!(lookups.len(), 2);
+    let PositionLookup::ChainContextual(chain) = lookups[0].as_ref() else {
+        panic!("expected chain contextual lookup");
+    };
+    assert_eq!(chain.subtables.len(), 1);
+}
+
+// a named lookup referenced from several features (and more than once from
+// the same feature) is only added to the lookup list once
+#[test]
+fn shared_named_lookup() {
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+
+lookup shared {
+    sub a by b;
+} shared;
+
+feature liga {
+    lookup shared;
+} liga;
+
+feature calt {
+    lookup shared;
+    sub c by d;
+} calt;
+
+feature ss01 {
+    script latn;
+    lookup shared;
+    lookup shared;
+} ss01;
+",
+        "shared_named_lookup",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    assert_eq!(gsub.lookup_list.lookups.len(), 2);
+    let features = gsub
+        .feature_list
+        .feature_records
+        .iter()
+        .map(|record| {
+            (
+                record.feature_tag.to_string(),
+                record.feature.lookup_list_indices.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        features,
+        [
+            ("calt".to_string(), vec![0, 1]),
+            ("liga".to_string(), vec![0]),
+            ("ss01".to_string(), vec![0]),
+        ]
+    );
+}
+
+// glyph deletion is encoded as a multiple substitution with an empty sequence
+#[test]
+fn sub_by_null_deletes_glyph() {
+    use write_fonts::tables::gsub::SubstitutionLookup;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+
+feature test {
+    sub a by NULL;
+    sub b' c by NULL;
+} test;
+",
+        "sub_by_null",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let lookups = &gsub.lookup_list.lookups;
+    let SubstitutionLookup::Multiple(lookup) = lookups[0].as_ref() else {
+        panic!("expected multiple substitution");
+    };
+    assert!(
+        lookup.subtables[0].sequences[0]
+            .substitute_glyph_ids
+            .is_empty()
+    );
+    // the inline deletion is also a multiple substitution
+    let SubstitutionLookup::Multiple(lookup) = lookups[2].as_ref() else {
+        panic!("expected multiple substitution");
+    };
+    assert!(
+        lookup.subtables[0].sequences[0]
+            .substitute_glyph_ids
+            .is_empty()
+    );
+}
+
+// lookupflag persists within a feature, resets at feature boundaries, script
+// statements and standalone lookup blocks, and leaks out of a lookup block
+// nested in a feature (as in feaLib)
+#[test]
+fn lookupflag_scoping() {
+    use write_fonts::tables::layout::LookupFlag;
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+
+lookup standalone {
+    lookupflag IgnoreMarks;
+    sub a by b;
+} standalone;
+
+lookup after_standalone {
+    sub b by c;
+} after_standalone;
+
+feature tst1 {
+    lookupflag IgnoreLigatures;
+    sub c by d;
+    sub d by f;
+    lookup nested {
+        sub f by A;
+    } nested;
+    lookup nested_flag {
+        lookupflag RightToLeft;
+        sub A by B;
+    } nested_flag;
+    sub B by C;
+    lookupflag 0;
+    sub C by D;
+    lookupflag IgnoreMarks;
+    script latn;
+    sub D by E;
+} tst1;
+
+feature tst2 {
+    sub E by F;
+} tst2;
+",
+        "lookupflag_scoping",
+    );
+
+    let gsub = compilation.gsub.unwrap();
+    let flags = gsub
+        .lookup_list
+        .lookups
+        .iter()
+        .map(|lookup| match lookup.as_ref() {
+            write_fonts::tables::gsub::SubstitutionLookup::Single(lookup) => lookup.lookup_flag,
+            other => panic!("unexpected lookup {other:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        flags,
+        [
+            // standalone
+            LookupFlag::IGNORE_MARKS,
+            // after_standalone
+            LookupFlag::empty(),
+            // c by d, d by f
+            LookupFlag::IGNORE_LIGATURES,
+            // nested
+            LookupFlag::IGNORE_LIGATURES,
+            // nested_flag
+            LookupFlag::RIGHT_TO_LEFT,
+            // B by C: the flag set in nested_flag is still in effect
+            LookupFlag::RIGHT_TO_LEFT,
+            // C by D: after lookupflag 0
+            LookupFlag::empty(),
+            // D by E: script statement resets the flags
+            LookupFlag::empty(),
+            // tst2
+            LookupFlag::empty(),
+        ]
+    );
+}
+
+// glyph classes are inferred from mark classes and mark positioning rules,
+// optionally from ligature substitutions, and inference can be disabled
+#[test]
+fn infer_gdef_glyph_classes() {
+    let fea = "\
+languagesystem DFLT dflt;
+
+markClass acutecomb <anchor 0 500> @TOP;
+
+feature liga {
+    sub f i by f_i;
+    sub f f i by f_f_i;
+} liga;
+
+feature mark {
+    pos base a <anchor 250 450> mark @TOP;
+    pos ligature f_i <anchor 200 500> mark @TOP
+        ligComponent <anchor 500 500> mark @TOP;
+} mark;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_infer_gdef_glyph_classes");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fea_path = dir.join("infer.fea");
+    std::fs::write(&fea_path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+    let names = glyph_map.reverse_map();
+    let compile = |opts: Opts| {
+        Compiler::<'_, NopFeatureProvider, MockVariationInfo>::new(&fea_path, &glyph_map)
+            .with_opts(opts)
+            .compile()
+            .unwrap()
+    };
+    let classes = |compilation: Compilation| {
+        compilation
+            .gdef
+            .and_then(|gdef| gdef.glyph_class_def.into_inner())
+            .map(|class_def| {
+                class_def
+                    .iter()
+                    .map(|(gid, class)| (names[&gid].to_string(), class))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let class = |name: &str, class: u16| (name.to_string(), class);
+
+    assert_eq!(
+        classes(compile(Opts::new())),
+        [class("a", 1), class("f_i", 2), class("acutecomb", 3)]
+    );
+    assert_eq!(
+        classes(compile(Opts::new().infer_gsub_ligature_classes(true))),
+        [
+            class("a", 1),
+            class("f_i", 2),
+            class("f_f_i", 2),
+            class("acutecomb", 3)
+        ]
+    );
+    assert!(classes(compile(Opts::new().infer_glyph_classes(false))).is_empty());
+}
+
+// compiled tables replace the layout tables of an existing font, and other
+// tables are copied unchanged
+#[test]
+fn apply_to_font() {
+    use write_fonts::read::{FontRef, TableProvider, types::Tag};
+
+    let original = compile_fea(
+        "\
+languagesystem DFLT dflt;
+table GDEF { LigatureCaretByPos f_i 300; } GDEF;
+feature liga { sub f i by f_i; } liga;
+",
+        "apply_to_font_original",
+    );
+    let glyph_map = mini_latin_glyph_map();
+    let original = original.to_binary(&glyph_map).unwrap();
+    let original = FontRef::new(&original).unwrap();
+
+    let compilation = compile_fea(
+        "\
+languagesystem DFLT dflt;
+feature kern { pos a b -10; } kern;
+",
+        "apply_to_font",
+    );
+    let bytes = compilation.apply_to_font(original.clone()).unwrap();
+    let font = FontRef::new(&bytes).unwrap();
+
+    assert!(font.gpos().is_ok());
+    assert!(font.gsub().is_err(), "stale GSUB should be dropped");
+    assert!(font.gdef().is_err(), "stale GDEF should be dropped");
+    let maxp = Tag::new(b"maxp");
+    assert_eq!(
+        font.table_data(maxp).unwrap().as_bytes(),
+        original.table_data(maxp).unwrap().as_bytes()
+    );
+}
+
+// FEA only sets some fields of head, hhea, OS/2 and name; when compiling into
+// an existing font the rest of those tables are kept
+#[test]
+fn apply_to_font_keeps_table_values() {
+    use write_fonts::{
+        FontBuilder,
+        read::{FontRef, TableProvider},
+        tables::{
+            head::Head,
+            hhea::Hhea,
+            name::{Name, NameRecord},
+            os2::Os2,
+        },
+        types::{Fixed, NameId, Tag},
+    };
+
+    let mut original = FontBuilder::new();
+    original
+        .add_table(&Head {
+            units_per_em: 2048,
+            index_to_loc_format: 1,
+            x_max: 1500,
+            ..Default::default()
+        })
+        .unwrap();
+    original
+        .add_table(&Hhea {
+            ascender: 700.into(),
+            number_of_h_metrics: 5,
+            ..Default::default()
+        })
+        .unwrap();
+    original
+        .add_table(&Os2 {
+            us_weight_class: 300,
+            ach_vend_id: Tag::new(b"XXXX"),
+            ..Default::default()
+        })
+        .unwrap();
+    original
+        .add_table(&Name::new(vec![
+            NameRecord::new(
+                3,
+                1,
+                0x409,
+                NameId::FAMILY_NAME,
+                "Family".to_string().into(),
+            ),
+            NameRecord::new(3, 1, 0x409, NameId::DESIGNER, "Someone".to_string().into()),
+        ]))
+        .unwrap();
+    let original = original.build();
+    let original = FontRef::new(&original).unwrap();
+
+    let compilation = compile_fea(
+        r#"
+table head { FontRevision 2.5; } head;
+table hhea { Ascender 800; } hhea;
+table OS/2 { WeightClass 700; } OS/2;
+table name { nameid 9 "Me"; } name;
+"#,
+        "apply_to_font_keeps_table_values",
+    );
+    let bytes = compilation.apply_to_font(original).unwrap();
+    let font = FontRef::new(&bytes).unwrap();
+
+    let head = font.head().unwrap();
+    assert_eq!(head.font_revision(), Fixed::from_f64(2.5));
+    assert_eq!(head.units_per_em(), 2048);
+    assert_eq!(head.index_to_loc_format(), 1);
+    assert_eq!(head.x_max(), 1500);
+
+    let hhea = font.hhea().unwrap();
+    assert_eq!(hhea.ascender().to_i16(), 800);
+    assert_eq!(hhea.number_of_h_metrics(), 5);
+
+    let os2 = font.os2().unwrap();
+    assert_eq!(os2.us_weight_class(), 700);
+    assert_eq!(os2.ach_vend_id(), Tag::new(b"XXXX"));
+
+    let name = font.name().unwrap();
+    let strings = name
+        .name_record()
+        .iter()
+        .map(|record| {
+            let string = record.string(name.string_data()).unwrap();
+            (record.name_id(), string.chars().collect::<String>())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        strings,
+        [
+            (NameId::FAMILY_NAME, "Family".to_string()),
+            (NameId::DESIGNER, "Me".to_string()),
+        ]
+    );
+}
+
+// diagnostics are tagged with a code for the kind of problem they report
+#[test]
+fn diagnostic_codes() {
+    use crate::DiagnosticCode;
+
+    let (_, parse_errors) = crate::parse::parse_string("feature test { sub a by; } test;");
+    let codes = parse_errors
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.code)
+        .collect::<Vec<_>>();
+    assert_eq!(codes.first(), Some(&DiagnosticCode::UnexpectedToken));
+    assert!(codes.contains(&DiagnosticCode::UnterminatedBlock));
+
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, _) = crate::parse::parse_string("feature test { sub xyzzy by a; } test;");
+    let diagnostics = crate::compile::validate::<MockVariationInfo>(&tree, &glyph_map, None);
+    let [diagnostic] = diagnostics.diagnostics() else {
+        panic!("expected one diagnostic, found {}", diagnostics.display());
+    };
+    assert_eq!(diagnostic.code, DiagnosticCode::UndefinedGlyph);
+
+    #[cfg(feature = "serde_json")]
+    {
+        let json: serde_json::Value = serde_json::from_str(&diagnostics.to_json()).unwrap();
+        assert_eq!(json[0]["code"], "undefined-glyph");
+        assert_eq!(json[0]["severity"], "error");
+        assert_eq!(json[0]["line"], 1);
+        assert_eq!(json[0]["column"], 19);
+        assert_eq!(json[0]["span"]["start"], 19);
+        assert_eq!(json[0]["related"], serde_json::json!([]));
+    }
+}
+
+// a redefinition points back at the previous definition
+#[test]
+fn diagnostic_related_spans() {
+    use crate::DiagnosticCode;
+
+    let fea = "@a = [a];\n@a = [b];\nfeature test { sub @a by c; } test;";
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, _) = crate::parse::parse_string(fea);
+    let diagnostics = crate::compile::validate::<MockVariationInfo>(&tree, &glyph_map, None);
+    let [diagnostic] = diagnostics.diagnostics() else {
+        panic!("expected one diagnostic, found {}", diagnostics.display());
+    };
+    assert_eq!(diagnostic.code, DiagnosticCode::DuplicateDefinition);
+    assert_eq!(diagnostic.span(), 10..12);
+    let [related] = diagnostic.related.as_slice() else {
+        panic!("expected one related span, found {:?}", diagnostic.related);
+    };
+    assert_eq!(related.span.range(), 0..2);
+    assert_eq!(related.text, "previous definition");
+
+    #[cfg(feature = "serde_json")]
+    {
+        let json: serde_json::Value = serde_json::from_str(&diagnostics.to_json()).unwrap();
+        assert_eq!(json[0]["code"], "duplicate-definition");
+        assert_eq!(json[0]["related"][0]["message"], "previous definition");
+        assert_eq!(json[0]["related"][0]["line"], 1);
+        assert_eq!(json[0]["related"][0]["column"], 0);
+        assert_eq!(json[0]["related"][0]["span"]["start"], 0);
+        assert_eq!(json[0]["related"][0]["span"]["end"], 2);
+    }
+}
+
+// warnings can be promoted to errors, silenced, or limited in number
+#[test]
+fn diagnostic_config() {
+    use crate::{DiagnosticCode, DiagnosticConfig, Level};
+
+    let fea = "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn dflt;
+lookup unused { sub a by b; } unused;
+feature test { sub c by d; sub c by e; } test;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_diagnostic_config");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("diagnostic_config.fea");
+    std::fs::write(&path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+    let compile = |config: DiagnosticConfig| {
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map)
+            .with_diagnostic_config(config)
+            .compile()
+    };
+
+    assert!(compile(DiagnosticConfig::new()).is_ok());
+    let Err(CompilerError::ValidationFail(errors)) =
+        compile(DiagnosticConfig::new().promote(DiagnosticCode::DuplicateStatement))
+    else {
+        panic!("expected promoted warning to fail validation");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        compile(DiagnosticConfig::new().promote(DiagnosticCode::ConflictingRule)),
+        Err(CompilerError::CompilationFail(_))
+    ));
+    assert!(matches!(
+        compile(DiagnosticConfig::new().warnings_as_errors(true)),
+        Err(CompilerError::ValidationFail(_))
+    ));
+
+    let (tree, _) = crate::parse::parse_string(fea);
+    let validate = || crate::compile::validate::<MockVariationInfo>(&tree, &glyph_map, None);
+    let texts = |diagnostics: &crate::DiagnosticSet| {
+        diagnostics
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.text().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let mut diagnostics = validate();
+    assert_eq!(diagnostics.apply_config(&DiagnosticConfig::new()), 2);
+    assert_eq!(
+        texts(&diagnostics),
+        [
+            "Duplicate languagesystem definition",
+            "lookup 'unused' is never used"
+        ]
+    );
+
+    let mut diagnostics = validate();
+    let config = DiagnosticConfig::new().silence(DiagnosticCode::Unused);
+    assert_eq!(diagnostics.apply_config(&config), 1);
+    assert_eq!(texts(&diagnostics), ["Duplicate languagesystem definition"]);
+
+    let mut diagnostics = validate();
+    assert_eq!(
+        diagnostics.apply_config(&DiagnosticConfig::new().max_warnings(0)),
+        0
+    );
+    assert!(diagnostics.is_empty());
+
+    // promotion takes precedence over silencing
+    let mut diagnostics = validate();
+    let config = DiagnosticConfig::new()
+        .silence(DiagnosticCode::Unused)
+        .promote(DiagnosticCode::Unused);
+    assert_eq!(diagnostics.apply_config(&config), 1);
+    assert_eq!(diagnostics.diagnostics()[1].level, Level::Error);
+}
+
+// a malformed statement doesn't prevent us from reporting problems elsewhere
+#[test]
+fn parse_errors_include_validation_errors() {
+    let fea = "\
+@broken = [a b;
+feature test {
+    sub a by ;
+    sub @broken by c;
+    sub b by @undefined;
+    pos;
+} test;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_parse_error_recovery");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("recovery.fea");
+    std::fs::write(&path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+    let Err(CompilerError::ParseFail(errors)) =
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map).compile()
+    else {
+        panic!("expected parsing to fail");
+    };
+    let texts = errors
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.text())
+        .collect::<Vec<_>>();
+    assert!(errors.len() >= 4, "{texts:?}");
+    // the malformed class is still declared, so using it is not an error
+    assert!(
+        !texts.iter().any(|text| text.contains("broken")),
+        "{texts:?}"
+    );
+    assert!(
+        texts
+            .iter()
+            .any(|text| text.contains("undefined glyph class")),
+        "{texts:?}"
+    );
+}
+
+// checking a source reports the warnings from every stage, without building
+#[test]
+fn check_returns_warnings() {
+    use crate::{DiagnosticCode, DiagnosticConfig};
+
+    let fea = "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn dflt;
+lookup unused { sub a by b; } unused;
+feature test { sub c by d; sub c by e; } test;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_check");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("check.fea");
+    std::fs::write(&path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+    let check = |config: DiagnosticConfig| {
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map)
+            .with_diagnostic_config(config)
+            .check()
+    };
+
+    let warnings = check(DiagnosticConfig::new()).unwrap();
+    assert_eq!(warnings.len(), 3, "{}", warnings.display());
+    assert!(!warnings.has_errors());
+    let warnings = check(DiagnosticConfig::new().silence(DiagnosticCode::Unused)).unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert!(matches!(
+        check(DiagnosticConfig::new().promote(DiagnosticCode::ConflictingRule)),
+        Err(CompilerError::CompilationFail(_))
+    ));
+}
+
+// a cancelled compilation stops early, returning the diagnostics found so far
+#[test]
+fn cancelled_compilation() {
+    use crate::compile::CancellationToken;
+
+    let fea = "\
+languagesystem DFLT dflt;
+feature test { sub a by b; } test;
+";
+    let dir = std::env::temp_dir().join("fea_rs_test_cancel");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cancel.fea");
+    std::fs::write(&path, fea).unwrap();
+    let glyph_map = mini_latin_glyph_map();
+
+    let token = CancellationToken::new();
+    let compiler = Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map)
+        .with_cancellation(token.clone());
+    token.cancel();
+    let Err(CompilerError::Cancelled(diagnostics)) = compiler.compile() else {
+        panic!("expected compilation to be cancelled");
+    };
+    assert!(!diagnostics.has_errors());
+
+    // an uncancelled token has no effect
+    assert!(
+        Compiler::<NopFeatureProvider, MockVariationInfo>::new(&path, &glyph_map)
+            .with_cancellation(CancellationToken::new())
+            .compile()
+            .is_ok()
+    );
+}
+
+// identical pair sets in a glyph pair subtable are only written once
+#[test]
+fn identical_pair_sets_are_shared() {
+    let gpos_size = |fea: &str| {
+        let (compilation, _) = compile_fea_with_warnings(fea);
+        write_fonts::dump_table(compilation.gpos.as_ref().unwrap())
+            .unwrap()
+            .len()
+    };
+    let shared =
+        gpos_size("feature kern { pos a x -10; pos a y -20; pos b x -10; pos b y -20; } kern;");
+    let distinct =
+        gpos_size("feature kern { pos a x -10; pos a y -20; pos b x -10; pos b y -30; } kern;");
+    // the second pair set: a count, and two records of a glyph and an advance
+    assert_eq!(distinct - shared, 2 + 2 * (2 + 2));
+}
+
+// dense glyph sets are written as ranges
+#[test]
+fn dense_coverage_uses_ranges() {
+    use write_fonts::tables::{
+        gpos::{PositionLookup, SinglePos},
+        layout::CoverageTable,
+    };
+
+    let (compilation, _) = compile_fea_with_warnings("feature kern { pos [a - z] -10; } kern;");
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Single(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 1");
+    };
+    assert!(matches!(
+        subtable.coverage.as_ref(),
+        CoverageTable::Format2(_)
+    ));
+}
+
+// fields that are zero are dropped from single pos value records
+#[test]
+fn single_pos_drops_zero_fields() {
+    use write_fonts::tables::gpos::{PositionLookup, SinglePos, ValueFormat};
+
+    let (compilation, _) =
+        compile_fea_with_warnings("feature kern { pos a <0 0 -10 0>; pos b <0 0 -20 0>; } kern;");
+    let gpos = compilation.gpos.unwrap();
+    let PositionLookup::Single(lookup) = gpos.lookup_list.lookups[0].as_ref() else {
+        panic!("expected single pos lookup");
+    };
+    let SinglePos::Format2(subtable) = lookup.subtables[0].as_ref() else {
+        panic!("expected single pos format 2");
+    };
+    assert!(
+        subtable
+            .value_records
+            .iter()
+            .all(|record| record.format() == ValueFormat::X_ADVANCE)
+    );
+}
+
+// longer ligatures are tried first, regardless of the order of the rules
+#[test]
+fn ligatures_are_ordered_by_length() {
+    use write_fonts::tables::gsub::SubstitutionLookup;
+
+    let (compilation, warnings) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+
+feature liga {
+    sub f i by f_i;
+    sub f f i by f_f_i;
+    sub f f by f_f;
+} liga;
+",
+    );
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    let glyph_map = mini_latin_glyph_map();
+    let gsub = compilation.gsub.unwrap();
+    let SubstitutionLookup::Ligature(lookup) = gsub.lookup_list.lookups[0].as_ref() else {
+        panic!("expected ligature subst lookup");
+    };
+    let ligature_set = &lookup.subtables[0].ligature_sets[0];
+    assert_eq!(ligature_set.ligatures.len(), 3);
+    assert_eq!(
+        ligature_set.ligatures[0].ligature_glyph,
+        glyph_map.get("f_f_i").unwrap()
+    );
+    assert!(
+        ligature_set
+            .ligatures
+            .windows(2)
+            .all(|pair| pair[0].component_glyph_ids.len() >= pair[1].component_glyph_ids.len())
+    );
+}
+
+// ligatures of the same length are tried in the order of their rules; a
+// repeated rule is dropped, and one with a different ligature is reported
+#[test]
+fn ligatures_of_equal_length_keep_rule_order() {
+    use write_fonts::tables::gsub::SubstitutionLookup;
+
+    let glyph_map = mini_latin_glyph_map();
+    let ligatures = |fea: &str| {
+        let (compilation, warnings) = compile_fea_with_warnings(fea);
+        let gsub = compilation.gsub.unwrap();
+        let SubstitutionLookup::Ligature(lookup) = gsub.lookup_list.lookups[0].as_ref() else {
+            panic!("expected ligature subst lookup");
+        };
+        let ligatures = lookup.subtables[0].ligature_sets[0]
+            .ligatures
+            .iter()
+            .map(|lig| lig.ligature_glyph)
+            .collect::<Vec<_>>();
+        (ligatures, warnings)
+    };
+    let gids = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| glyph_map.get(*name).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let (order, warnings) = ligatures(
+        "\
+languagesystem DFLT dflt;
+feature liga {
+    sub f i by f_i;
+    sub f f by f_f;
+} liga;
+",
+    );
+    assert!(warnings.is_empty(), "{warnings:?}");
+    assert_eq!(order, gids(&["f_i", "f_f"]));
+
+    let (order, warnings) = ligatures(
+        "\
+languagesystem DFLT dflt;
+feature liga {
+    sub f f by f_f;
+    sub f i by f_i;
+} liga;
+",
+    );
+    assert!(warnings.is_empty(), "{warnings:?}");
+    assert_eq!(order, gids(&["f_f", "f_i"]));
+
+    // longer ligatures still come first, and ties keep the rule order
+    let (order, warnings) = ligatures(
+        "\
+languagesystem DFLT dflt;
+feature liga {
+    sub f f by f_f;
+    sub f i by f_i;
+    sub f f i by f_f_i;
+    sub f i by f_i;
+    sub f i by f_f;
+} liga;
+",
+    );
+    assert_eq!(
+        warnings,
+        ["conflicting rule for 'f i', keeping the earlier rule at 4:4"]
+    );
+    assert_eq!(order, gids(&["f_f_i", "f_f", "f_i"]));
+}
+
+// contextual lookups use whichever subtable format is smallest
+#[test]
+fn chain_context_format_selection() {
+    use write_fonts::tables::{gsub::SubstitutionLookup, layout::ChainedSequenceContext};
+
+    let (compilation, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+
+lookup sub_b {
+    sub a by b;
+} sub_b;
+
+feature test {
+    sub [c d e f] [a b]' lookup sub_b [g h i j];
+} test;
+",
+    );
+    let gsub = compilation.gsub.unwrap();
+    let SubstitutionLookup::ChainContextual(lookup) = gsub.lookup_list.lookups[1].as_ref() else {
+        panic!("expected a contextual lookup");
+    };
+    // a single rule of classes is smallest as coverage tables
+    assert!(matches!(
+        &**lookup.subtables[0],
+        ChainedSequenceContext::Format3(_)
+    ));
+}
+
+// lookups added by a feature writer can contain variable metrics
+#[test]
+fn variable_feature_writer_lookups() {
+    use write_fonts::{
+        tables::{
+            gpos::{
+                AnchorTable, PairPos, PositionLookup,
+                builders::{
+                    AnchorBuilder, CursivePosBuilder, MarkToBaseBuilder, PairPosBuilder,
+                    ValueRecordBuilder,
+                },
+            },
+            layout::{DeviceOrVariationIndex, LookupFlag},
+            variations::{RegionAxisCoordinates, VariationRegion},
+        },
+        types::{F2Dot14, GlyphId16, Tag},
+    };
+
+    use crate::compile::{FeatureBuilder, FeatureProvider, PendingLookup};
+
+    struct Writer;
+    impl FeatureProvider for Writer {
+        fn add_features(&self, builder: &mut FeatureBuilder) {
+            let coords = |peak: f32| RegionAxisCoordinates {
+                start_coord: F2Dot14::from_f32(0.0),
+                peak_coord: F2Dot14::from_f32(peak),
+                end_coord: F2Dot14::from_f32(peak),
+            };
+            // the max of the first axis
+            let region = VariationRegion::new(vec![coords(1.0), coords(0.0)]);
+
+            let mut kern = PairPosBuilder::default();
+            kern.insert_pair(
+                GlyphId16::new(1),
+                ValueRecordBuilder::new()
+                    .with_x_advance(-20)
+                    .with_x_advance_device(vec![(region.clone(), -10)]),
+                GlyphId16::new(2),
+                ValueRecordBuilder::new(),
+            );
+            let mut curs = CursivePosBuilder::default();
+            curs.insert(
+                GlyphId16::new(3),
+                Some(AnchorBuilder::new(100, 0).with_x_device(vec![(region.clone(), 20)])),
+                None,
+            );
+            let mut mark = MarkToBaseBuilder::default();
+            mark.insert_mark(GlyphId16::new(4), "top", AnchorBuilder::new(0, 500))
+                .unwrap();
+            mark.insert_base(
+                GlyphId16::new(5),
+                "top",
+                AnchorBuilder::new(250, 500).with_y_device(vec![(region, 30)]),
+            );
+
+            let ids = [
+                builder.add_lookup(PendingLookup::new(vec![kern], LookupFlag::empty(), None)),
+                builder.add_lookup(PendingLookup::new(vec![curs], LookupFlag::empty(), None)),
+                builder.add_lookup(PendingLookup::new(vec![mark], LookupFlag::empty(), None)),
+            ];
+            for (tag, id) in [b"kern", b"curs", b"mark"].into_iter().zip(ids) {
+                builder.add_to_default_language_systems(Tag::new(tag), &[id]);
+            }
+        }
+    }
+
+    let glyph_map = mini_latin_glyph_map();
+    let var_info = test_utils::make_var_info();
+    let (tree, diagnostics) = crate::parse::parse_string("languagesystem DFLT dflt;");
+    assert!(!diagnostics.has_errors(), "{}", diagnostics.display());
+    let (compilation, _) = crate::compile::compile(
+        &tree,
+        &glyph_map,
+        Some(&var_info),
+        Some(&Writer),
+        Opts::new(),
+    )
+    .unwrap_or_else(|errs| panic!("{}", errs.display()));
+
+    let is_variation_index = |device: Option<&DeviceOrVariationIndex>| {
+        matches!(device, Some(DeviceOrVariationIndex::VariationIndex(_)))
+    };
+    let gpos = compilation.gpos.unwrap();
+    let mut n_lookups = 0;
+    for lookup in gpos.lookup_list.lookups.iter() {
+        match lookup.as_ref() {
+            PositionLookup::Pair(lookup) => {
+                let PairPos::Format1(subtable) = lookup.subtables[0].as_ref() else {
+                    panic!("expected pair pos format 1");
+                };
+                let record = &subtable.pair_sets[0].pair_value_records[0].value_record1;
+                assert!(is_variation_index(record.x_advance_device.as_ref()));
+            }
+            PositionLookup::Cursive(lookup) => {
+                let entry = lookup.subtables[0].entry_exit_record[0]
+                    .entry_anchor
+                    .as_ref()
+                    .unwrap();
+                let AnchorTable::Format3(entry) = entry else {
+                    panic!("expected format 3 anchor");
+                };
+                assert!(is_variation_index(entry.x_device.as_ref()));
+            }
+            PositionLookup::MarkToBase(lookup) => {
+                let base_anchor = lookup.subtables[0].base_array.base_records[0].base_anchors[0]
+                    .as_ref()
+                    .unwrap();
+                let AnchorTable::Format3(base_anchor) = base_anchor else {
+                    panic!("expected format 3 anchor");
+                };
+                assert!(is_variation_index(base_anchor.y_device.as_ref()));
+            }
+            _ => panic!("unexpected lookup"),
+        }
+        n_lookups += 1;
+    }
+    assert_eq!(n_lookups, 3);
+    assert!(compilation.gdef.unwrap().item_var_store.is_some());
+}
+
+// merging two compilations remaps their lookups and combines their features
+#[test]
+fn merge_compilations() {
+    use write_fonts::{
+        tables::{
+            gpos::PositionLookup,
+            gsub::SubstitutionLookup,
+            layout::{ChainedSequenceContext, FeatureList, LangSys},
+        },
+        types::Tag,
+    };
+
+    let (mut ours, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+
+feature liga {
+    sub f i by f_i;
+} liga;
+
+feature kern {
+    lookupflag UseMarkFilteringSet [brevecomb];
+    pos a b -10;
+} kern;
+",
+    );
+    let (theirs, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem latn TRK;
+
+lookup sub_b {
+    sub a by b;
+} sub_b;
+
+feature calt {
+    sub [c d e f] [a b]' lookup sub_b [g h i j];
+} calt;
+
+feature kern {
+    lookupflag UseMarkFilteringSet [acutecomb];
+    pos c d -20;
+} kern;
+",
+    );
+    ours.merge(theirs).unwrap();
+
+    let feature_tags = |features: &FeatureList, lang_sys: &LangSys| {
+        lang_sys
+            .feature_indices
+            .iter()
+            .map(|idx| features.feature_records[*idx as usize].feature_tag)
+            .collect::<Vec<_>>()
+    };
+
+    let gsub = ours.gsub.as_ref().unwrap();
+    assert_eq!(gsub.lookup_list.lookups.len(), 3);
+    assert_eq!(ours.gsub_lookup_names.len(), 3);
+    let SubstitutionLookup::ChainContextual(lookup) = gsub.lookup_list.lookups[2].as_ref() else {
+        panic!("expected a contextual lookup");
+    };
+    let ChainedSequenceContext::Format3(subtable) = &**lookup.subtables[0] else {
+        panic!("expected a format 3 subtable");
+    };
+    assert_eq!(subtable.seq_lookup_records[0].lookup_list_index, 1);
+    // we get our latn features in their latn/TRK language system
+    let latn = gsub
+        .script_list
+        .script_records
+        .iter()
+        .find(|rec| rec.script_tag == Tag::new(b"latn"))
+        .unwrap();
+    let trk = latn
+        .script
+        .lang_sys_records
+        .iter()
+        .find(|rec| rec.lang_sys_tag == Tag::new(b"TRK "))
+        .unwrap();
+    assert_eq!(
+        feature_tags(&gsub.feature_list, &trk.lang_sys),
+        [Tag::new(b"calt"), Tag::new(b"liga")]
+    );
+
+    // both kern features are combined
+    let gpos = ours.gpos.as_ref().unwrap();
+    assert_eq!(gpos.feature_list.feature_records.len(), 1);
+    assert_eq!(
+        gpos.feature_list.feature_records[0]
+            .feature
+            .lookup_list_indices,
+        [0, 1]
+    );
+    let PositionLookup::Pair(lookup) = gpos.lookup_list.lookups[1].as_ref() else {
+        panic!("expected a pair lookup");
+    };
+    assert_eq!(lookup.mark_filtering_set, Some(1));
+    let gdef = ours.gdef.as_ref().unwrap();
+    assert_eq!(
+        gdef.mark_glyph_sets_def.as_ref().unwrap().coverages.len(),
+        2
+    );
+}
+
+// their Debg entries and insertion markers follow their lookups
+#[test]
+fn merge_compilations_debug_info() {
+    use write_fonts::types::Tag;
+
+    let compile = |fea: &str, debug_table: bool| {
+        let glyph_map = mini_latin_glyph_map();
+        let (tree, _) = crate::parse::parse_string(fea);
+        crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+            &tree,
+            &glyph_map,
+            None,
+            None,
+            Opts::new().debug_table(debug_table),
+        )
+        .unwrap_or_else(|errs| panic!("{}", errs.display()))
+        .0
+    };
+    let mut ours = compile("feature kern { pos a b -10; } kern;", true);
+    let theirs = compile(
+        "\
+feature kern {
+    pos c d -20;
+} kern;
+
+feature mark {
+    pos e f -30;
+    # Automatic Code
+} mark;
+",
+        true,
+    );
+    ours.merge(theirs).unwrap();
+    let mark = Tag::new(b"mark");
+    assert_eq!(ours.insert_markers[&mark].lookup_id.to_raw(), 3);
+    let debg = String::from_utf8(ours.debg.clone().unwrap()).unwrap();
+    assert!(debg.contains(r#""2": ["#), "{debg}");
+
+    assert!(!debg.contains(r#""3": ["#), "{debg}");
+
+    // if only one compilation has a Debg table, the other gets empty entries
+    let mut ours = compile("feature kern { pos a b -10; } kern;", false);
+    ours.merge(compile("feature kern { pos c d -20; } kern;", true))
+        .unwrap();
+    let debg = String::from_utf8(ours.debg.clone().unwrap()).unwrap();
+    assert!(debg.contains(r#""0": [null, null, null]"#), "{debg}");
+    assert!(debg.contains(r#""1": ["#), "{debg}");
+}
+
+#[test]
+fn merge_mark_attach_class_conflict() {
+    use crate::compile::error::MergeError;
+
+    let (mut ours, _) = compile_fea_with_warnings(
+        "\
+@TOP = [acutecomb];
+feature kern {
+    lookupflag MarkAttachmentType @TOP;
+    pos a b -10;
+} kern;
+",
+    );
+    let (theirs, _) = compile_fea_with_warnings(
+        "\
+@TOP = [acutecomb brevecomb];
+feature kern {
+    lookupflag MarkAttachmentType @TOP;
+    pos c d -20;
+} kern;
+",
+    );
+    let err = ours.merge(theirs).unwrap_err();
+    assert!(
+        matches!(err, MergeError::MarkAttachClassConflict { .. }),
+        "{err}"
+    );
+    // nothing has changed
+    assert_eq!(ours.gpos.as_ref().unwrap().lookup_list.lookups.len(), 1);
+}
+
+#[test]
+fn dedup_lookups() {
+    let (mut compilation, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem cyrl dflt;
+
+feature kern {
+    script latn;
+    pos a b -10;
+    script cyrl;
+    pos a b -10;
+} kern;
+",
+    );
+    assert_eq!(compilation.gpos_lookup_names.len(), 2);
+    assert_eq!(compilation.dedup_lookups(), 1);
+    assert_eq!(compilation.gpos_lookup_names.len(), 1);
+
+    let gpos = compilation.gpos.as_ref().unwrap();
+    assert_eq!(gpos.lookup_list.lookups.len(), 1);
+    // both the latn and cyrl features now use the remaining lookup
+    assert!(
+        gpos.feature_list
+            .feature_records
+            .iter()
+            .flat_map(|record| record.feature.lookup_list_indices.iter())
+            .all(|idx| *idx == 0)
+    );
+}
+
+// removing lookups updates the Debg table and the insertion markers
+#[test]
+fn dedup_lookups_updates_debug_info() {
+    use write_fonts::types::Tag;
+
+    let fea = "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem cyrl dflt;
+
+feature kern {
+    script latn;
+    pos a b -10;
+    script cyrl;
+    pos a b -10;
+} kern;
+
+feature mark {
+    pos c d -20;
+    # Automatic Code
+} mark;
+";
+    let glyph_map = mini_latin_glyph_map();
+    let (tree, _) = crate::parse::parse_string(fea);
+    let (mut compilation, _) = crate::compile::compile::<MockVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new().debug_table(true),
+    )
+    .unwrap_or_else(|errs| panic!("{}", errs.display()));
+    let mark = Tag::new(b"mark");
+    assert_eq!(compilation.insert_markers[&mark].lookup_id.to_raw(), 3);
+    let debg = String::from_utf8(compilation.debg.clone().unwrap()).unwrap();
+    assert!(debg.contains(r#""2": ["#), "{debg}");
+
+    assert_eq!(compilation.dedup_lookups(), 1);
+    assert_eq!(compilation.insert_markers[&mark].lookup_id.to_raw(), 2);
+    let debg = String::from_utf8(compilation.debg.clone().unwrap()).unwrap();
+    assert!(debg.contains(r#""1": ["#), "{debg}");
+    assert!(!debg.contains(r#""2": ["#), "{debg}");
+}
+
+// lookups that are never applied together can be reordered by size
+#[test]
+fn reorder_lookups() {
+    use write_fonts::types::Tag;
+
+    let (mut compilation, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem cyrl dflt;
+
+feature kern {
+    script latn;
+    pos [a b c d] [e f g h] -10;
+    script cyrl;
+    pos a b -10;
+} kern;
+
+feature dist {
+    script latn;
+    pos a c -20;
+} dist;
+",
+    );
+    let names = compilation.gpos_lookup_names.clone();
+    let sizes = |compilation: &Compilation| {
+        compilation
+            .gpos
+            .as_ref()
+            .unwrap()
+            .lookup_list
+            .lookups
+            .iter()
+            .map(|lookup| write_fonts::dump_table(&**lookup).unwrap().len())
+            .collect::<Vec<_>>()
+    };
+    let before = sizes(&compilation);
+    assert!(before[0] > before[1]);
+
+    compilation.reorder_lookups();
+    // the small cyrl lookup moves to the front, but the second latn lookup
+    // must still come after the first.
+    assert_eq!(
+        compilation.gpos_lookup_names,
+        [names[1].clone(), names[0].clone(), names[2].clone()]
+    );
+    assert_eq!(sizes(&compilation), [before[1], before[0], before[2]]);
+    let gpos = compilation.gpos.as_ref().unwrap();
+    let lookups = |script: &[u8; 4]| {
+        let record = gpos
+            .script_list
+            .script_records
+            .iter()
+            .find(|record| record.script_tag == Tag::new(script))
+            .unwrap();
+        record
+            .script
+            .default_lang_sys
+            .as_ref()
+            .unwrap()
+            .feature_indices
+            .iter()
+            .flat_map(|idx| {
+                gpos.feature_list.feature_records[*idx as usize]
+                    .feature
+                    .lookup_list_indices
+                    .clone()
+            })
+            .collect::<BTreeSet<_>>()
+    };
+    assert_eq!(lookups(b"latn"), BTreeSet::from([1, 2]));
+    assert_eq!(lookups(b"cyrl"), BTreeSet::from([0]));
+}
+
+// lookups are not moved past an insertion marker
+#[test]
+fn reorder_lookups_with_insert_marker() {
+    use write_fonts::types::Tag;
+
+    let (mut compilation, _) = compile_fea_with_warnings(
+        "\
+languagesystem DFLT dflt;
+languagesystem latn dflt;
+languagesystem cyrl dflt;
+languagesystem grek dflt;
+
+feature kern {
+    script cyrl;
+    pos [a b c d] [e f g h] -10;
+    # Automatic Code
+    script latn;
+    pos [a b c d] [e f g h] -10;
+} kern;
+
+feature dist {
+    script grek;
+    pos a c -20;
+} dist;
+",
+    );
+    let kern = Tag::new(b"kern");
+    let names = compilation.gpos_lookup_names.clone();
+    assert_eq!(compilation.insert_markers[&kern].lookup_id.to_raw(), 1);
+
+    compilation.reorder_lookups();
+    // the small grek lookup could be applied first, but it is only moved
+    // ahead of the latn lookup, since the cyrl lookup is before the marker.
+    assert_eq!(
+        compilation.gpos_lookup_names,
+        [names[0].clone(), names[2].clone(), names[1].clone()]
+    );
+    assert_eq!(compilation.insert_markers[&kern].lookup_id.to_raw(), 1);
+}
+
+#[test]
+fn dedup_lookups_preserves_order() {
+    // the last lookup cannot be replaced by the first, because that would
+    // change the order in which they are applied relative to the second
+    let (mut compilation, _) = compile_fea_with_warnings(
+        "\
+feature kern {
+    lookup first {
+        pos a b -10;
+    } first;
+    lookup second {
+        pos a b 20;
+    } second;
+    lookup third {
+        pos a b -10;
+    } third;
+} kern;
+",
+    );
+    assert_eq!(compilation.dedup_lookups(), 0);
+    let gpos = compilation.gpos.as_ref().unwrap();
+    assert_eq!(gpos.lookup_list.lookups.len(), 3);
+}
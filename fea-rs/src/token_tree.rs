@@ -1,11 +1,19 @@
 use std::{fmt::Write, io::Write as _};
 
-use std::{ops::Range, sync::Arc};
+use std::{
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::Arc,
+};
 
 use smol_str::SmolStr;
 
 use crate::parse::{FileId, IncludeStatement};
-use crate::{GlyphMap, Level, diagnostic::Diagnostic};
+use crate::{
+    GlyphMap, Level,
+    diagnostic::{Diagnostic, DiagnosticCode},
+};
 
 use self::cursor::Cursor;
 use typed::AstNode as _;
@@ -16,6 +24,7 @@ mod rewrite;
 mod stack;
 mod token;
 pub mod typed;
+pub mod visit;
 
 use rewrite::ReparseCtx;
 pub use token::Kind;
@@ -29,9 +38,10 @@ pub struct Node {
     /// The ``Kind` of this node.
     kind: Kind,
 
-    // NOTE: the absolute position within the tree is not known when the node
-    // is created; this is updated (and correct) only when the node has been
-    // accessed via a `Cursor`.
+    // NOTE: children are stored without positions, so that identical subtrees
+    // can share their storage. The absolute position is set when a node is
+    // accessed via a `Cursor`; it is always `0` for a node that is stored
+    // as the child of another node.
     abs_pos: u32,
     /// The length of the text spanned by this node
     text_len: u32,
@@ -40,7 +50,9 @@ pub struct Node {
     /// This is not recursive; it is only true for the direct parent of an error span.
     pub error: bool,
     //NOTE: children should not be accessed directly, but only via a cursor.
-    // this ensures that their positions are updated correctly.
+    // this ensures that they have the correct positions.
+    //
+    // Identical children are shared between nodes; see `TreeBuilder`.
     children: Arc<Vec<NodeOrToken>>,
 }
 
@@ -50,7 +62,9 @@ pub struct Node {
 pub struct Token {
     /// The [`Kind`] of this token
     pub kind: Kind,
-    /// The absolute position in the source where this token starts
+    /// The absolute position in the source where this token starts.
+    ///
+    /// As with nodes, this is only set when the token is accessed via a cursor.
     abs_pos: u32,
     /// The token text
     pub text: SmolStr,
@@ -68,13 +82,21 @@ pub enum NodeOrToken {
 
 #[derive(Clone, Debug, Default)]
 pub(crate) struct TreeBuilder {
-    //TODO: reuse tokens
-    //token_cache: HashMap<Arc<Token>>,
     // the kind of the parent, and the index in children of the first child.
     parents: Vec<(Kind, usize)>,
     children: Vec<NodeOrToken>,
+    // the children of every node we have finished, so that identical subtrees
+    // (repeated rules or glyph classes, e.g.) share a single allocation
+    finished: HashSet<SharedChildren>,
 }
 
+/// The children of a finished node, compared by their contents.
+///
+/// Because nodes are finished bottom-up, identical child nodes will already
+/// share their children, and we only need to compare those by pointer.
+#[derive(Clone, Debug)]
+struct SharedChildren(Arc<Vec<NodeOrToken>>);
+
 /// Consumes tokens during parsing, building up an AST.
 pub(crate) struct AstSink<'a> {
     text: &'a str,
@@ -84,9 +106,14 @@ pub(crate) struct AstSink<'a> {
     // reuseable buffer for reparsing
     reparse_buf: Vec<NodeOrToken>,
     glyph_map: Option<&'a GlyphMap>,
+    // the text of every token too long to be stored inline, so that repeated
+    // tokens (long glyph names in kerning rules, e.g.) share a single allocation
+    token_texts: HashSet<SmolStr>,
     errors: Vec<Diagnostic>,
     include_statement_count: usize,
     cur_node_contains_error: bool,
+    // the error state of each enclosing node, restored when a child finishes
+    parent_errors: Vec<bool>,
 }
 
 //NOTE: the inner type is option because we reuse this in the `typed` module,
@@ -105,8 +132,10 @@ impl<'a> AstSink<'a> {
             text_pos: 0,
             builder: TreeBuilder::default(),
             glyph_map,
+            token_texts: HashSet::new(),
             errors: Vec::new(),
             cur_node_contains_error: false,
+            parent_errors: Vec::new(),
             include_statement_count: 0,
             reparse_buf: Default::default(),
         }
@@ -121,6 +150,8 @@ impl<'a> AstSink<'a> {
 
     pub(crate) fn start_node(&mut self, kind: Kind) {
         self.builder.start_node(kind);
+        self.parent_errors.push(self.cur_node_contains_error);
+        self.cur_node_contains_error = false;
     }
 
     pub(crate) fn finish_node(&mut self, kind: Option<Kind>) {
@@ -129,7 +160,7 @@ impl<'a> AstSink<'a> {
             .unwrap();
         let kind = self.maybe_rewrite_current_node(cur_kind).or(kind);
         self.builder.finish_node(self.cur_node_contains_error, kind);
-        self.cur_node_contains_error = false;
+        self.cur_node_contains_error = self.parent_errors.pop().unwrap_or_default();
         // if this is an include statement we store a copy.
         if self.builder.children.last().map(|n| n.kind()) == Some(Kind::IncludeNode) {
             self.include_statement_count += 1;
@@ -144,12 +175,11 @@ impl<'a> AstSink<'a> {
         let is_hard_error = error.level == Level::Error;
         error.message.file = self.file_id;
         self.errors.push(error);
-        self.cur_node_contains_error = is_hard_error;
+        self.cur_node_contains_error |= is_hard_error;
     }
 
     pub fn finish(self) -> (Node, Vec<Diagnostic>, Vec<IncludeStatement>) {
-        let mut node = self.builder.finish();
-        node.update_positions_from_root();
+        let node = self.builder.finish();
         let mut includes = Vec::new();
         if self.include_statement_count > 0 {
             node.find_include_nodes(&mut includes, self.include_statement_count);
@@ -171,17 +201,36 @@ impl<'a> AstSink<'a> {
             && let Some(map) = self.glyph_map
         {
             if map.contains(text) {
-                return Token::new(Kind::GlyphName, text.into()).into();
+                return Token::new(Kind::GlyphName, self.intern_text(text)).into();
             }
             match try_split_range(text, map) {
                 Ok(node) => return node.into(),
                 Err(message) => {
                     let range = self.text_pos..self.text_pos + text.len();
-                    self.error(Diagnostic::error(FileId::CURRENT_FILE, range, message));
+                    self.error(
+                        Diagnostic::error(FileId::CURRENT_FILE, range, message)
+                            .with_code(DiagnosticCode::InvalidGlyphRange),
+                    );
                 }
             }
         }
-        Token::new(kind, text.into()).into()
+        Token::new(kind, self.intern_text(text)).into()
+    }
+
+    /// Return a `SmolStr` for this text, reusing the storage of any identical
+    /// token we have already seen.
+    fn intern_text(&mut self, text: &str) -> SmolStr {
+        // short strings are stored inline, and don't allocate
+        const MAX_INLINE_LEN: usize = 23;
+        if text.len() <= MAX_INLINE_LEN {
+            return text.into();
+        }
+        if let Some(existing) = self.token_texts.get(text) {
+            return existing.clone();
+        }
+        let text = SmolStr::from(text);
+        self.token_texts.insert(text.clone());
+        text
     }
 
     /// Called before finishing a node.
@@ -239,50 +288,35 @@ impl<'a> AstSink<'a> {
 }
 
 impl Node {
-    fn new(kind: Kind, children: Vec<NodeOrToken>, error: bool) -> Self {
+    fn new(kind: Kind, children: Arc<Vec<NodeOrToken>>, error: bool) -> Self {
         let text_len = children.iter().map(|x| x.text_len() as u32).sum();
         Node {
             kind,
             text_len,
             abs_pos: 0,
-            children: children.into(),
+            children,
             error,
         }
     }
 
-    /// recursively compute and update the positions of each child.
-    ///
-    /// This should only be called on a root node; it assumes the position
-    /// of the callee is `0`.
-    ///
-    /// This is required in order for us to correctly associate diagnostics
-    /// with their locations in the source.
-    pub(crate) fn update_positions_from_root(&mut self) {
-        self.update_positions_recurse(0)
-    }
-
-    fn update_positions_recurse(&mut self, mut pos: usize) {
-        self.abs_pos = pos as _;
-        let children = Arc::make_mut(&mut self.children);
-
-        for child in children {
-            child.update_positions(pos);
-            pos += child.text_len();
-        }
-    }
-
     /// Construct a new cursor for navigating the node's children
     pub(crate) fn cursor(&self) -> Cursor<'_> {
         Cursor::new(self)
     }
 
     /// Iterate over tokens, descending into child nodes.
-    pub fn iter_tokens(&self) -> impl Iterator<Item = &Token> {
+    ///
+    /// Children are shared between identical subtrees, so the tokens are
+    /// yielded as cheap positioned clones, not references into the tree.
+    pub fn iter_tokens(&self) -> impl Iterator<Item = Token> + '_ {
         let mut cursor = self.cursor();
         std::iter::from_fn(move || cursor.next_token())
     }
 
     /// Iterate over this node's direct children, without descending.
+    ///
+    /// As with [`iter_tokens`](Self::iter_tokens), the children are yielded
+    /// as positioned clones.
     pub fn iter_children(&self) -> ChildIter<'_> {
         ChildIter(Some(self.cursor()))
     }
@@ -299,12 +333,24 @@ impl Node {
 
     /// The range in the original source of this node.
     ///
-    /// Only correct if this node is accessed via a cursor.
+    /// Only correct if this node is accessed via a cursor (or is the root).
     pub fn range(&self) -> Range<usize> {
         let start = self.abs_pos as usize;
         start..start + (self.text_len as usize)
     }
 
+    /// `true` if an error was encountered in this node or any of its descendants.
+    ///
+    /// Nodes containing errors are still present in the tree, so that later
+    /// passes can skip them and continue reporting problems elsewhere.
+    pub fn contains_error(&self) -> bool {
+        self.error
+            || self
+                .children
+                .iter()
+                .any(|child| child.as_node().is_some_and(Node::contains_error))
+    }
+
     /// Create a new tree, replacing the provided ranges with the provided
     /// nodes.
     ///
@@ -316,10 +362,19 @@ impl Node {
         edit::apply_edits(self, edits, skip_parent)
     }
 
+    /// Replace a range of this node's direct children.
+    pub(crate) fn splice_children(
+        &mut self,
+        range: Range<usize>,
+        replace_with: impl IntoIterator<Item = NodeOrToken>,
+    ) {
+        edit::splice_children(self, range, replace_with)
+    }
+
     fn find_include_nodes(&self, collect: &mut Vec<IncludeStatement>, num: usize) {
         for item in self.iter_children() {
             if let Some(node) = item.as_node() {
-                if let Some(include) = typed::Include::cast(item) {
+                if let Some(include) = typed::Include::cast(&item) {
                     collect.push(IncludeStatement {
                         stmt: include,
                         scope: self.kind,
@@ -399,8 +454,8 @@ impl Node {
     }
 }
 
-impl<'a> Iterator for ChildIter<'a> {
-    type Item = &'a NodeOrToken;
+impl Iterator for ChildIter<'_> {
+    type Item = NodeOrToken;
 
     fn next(&mut self) -> Option<Self::Item> {
         let current = self.0.as_ref()?.current();
@@ -420,7 +475,9 @@ impl TreeBuilder {
         self.push_raw(token.into());
     }
 
-    fn push_raw(&mut self, item: NodeOrToken) {
+    fn push_raw(&mut self, mut item: NodeOrToken) {
+        // children are stored without positions
+        item.set_pos(0);
         self.children.push(item)
     }
 
@@ -436,10 +493,22 @@ impl TreeBuilder {
     pub(crate) fn finish_node(&mut self, error: bool, new_kind: Option<Kind>) {
         let (kind, first_child) = self.parents.pop().unwrap();
         let kind = new_kind.unwrap_or(kind);
-        let node = Node::new(kind, self.children.split_off(first_child), error);
+        let children = self.children.split_off(first_child);
+        let children = self.share_children(children);
+        let node = Node::new(kind, children, error);
         self.push_raw(node.into());
     }
 
+    /// Return the children of an identical finished node, if there is one.
+    fn share_children(&mut self, children: Vec<NodeOrToken>) -> Arc<Vec<NodeOrToken>> {
+        let children = SharedChildren(Arc::new(children));
+        if let Some(existing) = self.finished.get(&children) {
+            return existing.0.clone();
+        }
+        self.finished.insert(children.clone());
+        children.0
+    }
+
     pub(crate) fn finish(mut self) -> Node {
         assert_eq!(self.children.len(), 1);
         self.children.pop().unwrap().into_node().unwrap()
@@ -447,10 +516,10 @@ impl TreeBuilder {
 }
 
 impl NodeOrToken {
-    fn update_positions(&mut self, pos: usize) {
+    fn set_pos(&mut self, pos: usize) {
         match self {
             NodeOrToken::Token(t) => t.abs_pos = pos as _,
-            NodeOrToken::Node(n) => n.update_positions_recurse(pos),
+            NodeOrToken::Node(n) => n.abs_pos = pos as _,
         }
     }
 
@@ -514,6 +583,14 @@ impl NodeOrToken {
         }
     }
 
+    /// If this is a `Token`, return it
+    pub fn into_token(self) -> Option<Token> {
+        match self {
+            NodeOrToken::Node(_) => None,
+            NodeOrToken::Token(token) => Some(token),
+        }
+    }
+
     /// IF this is a token, return a reference to it.
     pub fn as_token(&self) -> Option<&Token> {
         match self {
@@ -523,6 +600,48 @@ impl NodeOrToken {
     }
 }
 
+impl AsRef<NodeOrToken> for NodeOrToken {
+    fn as_ref(&self) -> &NodeOrToken {
+        self
+    }
+}
+
+impl PartialEq for SharedChildren {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|pair| match pair {
+                (NodeOrToken::Token(one), NodeOrToken::Token(two)) => one == two,
+                (NodeOrToken::Node(one), NodeOrToken::Node(two)) => {
+                    one.kind == two.kind
+                        && one.error == two.error
+                        && Arc::ptr_eq(&one.children, &two.children)
+                }
+                _ => false,
+            })
+    }
+}
+
+impl Eq for SharedChildren {}
+
+impl Hash for SharedChildren {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        for child in self.0.iter() {
+            match child {
+                NodeOrToken::Token(token) => {
+                    token.kind.hash(state);
+                    token.text.hash(state);
+                }
+                NodeOrToken::Node(node) => {
+                    node.kind.hash(state);
+                    node.error.hash(state);
+                    Arc::as_ptr(&node.children).hash(state);
+                }
+            }
+        }
+    }
+}
+
 impl From<Node> for NodeOrToken {
     fn from(src: Node) -> NodeOrToken {
         NodeOrToken::Node(src)
@@ -641,8 +760,53 @@ mod tests {
         let reconstruct = ast
             .root()
             .iter_tokens()
-            .map(Token::as_str)
+            .map(|token| token.text.to_string())
             .collect::<String>();
         crate::assert_eq_str!(SAMPLE_FEA, reconstruct);
     }
+
+    #[test]
+    fn long_token_text_is_shared() {
+        let fea =
+            "pos very_long_glyph_name.alternate a -5;\npos very_long_glyph_name.alternate b -5;";
+        let (ast, _errs) = crate::parse::parse_string(fea);
+        let long_names = ast
+            .root()
+            .iter_tokens()
+            .filter(|token| token.as_str() == "very_long_glyph_name.alternate")
+            .collect::<Vec<_>>();
+        assert_eq!(long_names.len(), 2);
+        assert_eq!(long_names[0].text, long_names[1].text);
+        assert!(std::ptr::eq(
+            long_names[0].as_str().as_ptr(),
+            long_names[1].as_str().as_ptr()
+        ));
+    }
+
+    #[test]
+    fn identical_subtrees_are_shared() {
+        let fea = "feature kern {\n    pos a b -5;\n    pos a b -5;\n} kern;";
+        let (ast, _errs) = crate::parse::parse_string(fea);
+        let feature = ast
+            .root()
+            .iter_children()
+            .find_map(NodeOrToken::into_node)
+            .unwrap();
+        let rules = feature
+            .iter_children()
+            .filter_map(NodeOrToken::into_node)
+            .filter(|node| node.kind() == Kind::GposType2)
+            .collect::<Vec<_>>();
+        assert_eq!(rules.len(), 2);
+        assert!(Arc::ptr_eq(&rules[0].children, &rules[1].children));
+        // each copy still has its own position
+        assert_eq!(rules[0].range(), 19..30);
+        assert_eq!(rules[1].range(), 35..46);
+        let values = rules[1]
+            .iter_tokens()
+            .filter(|token| token.kind == Kind::Number)
+            .map(|token| token.range())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![43..45]);
+    }
 }
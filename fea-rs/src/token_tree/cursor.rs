@@ -46,13 +46,18 @@ impl<'a> Cursor<'a> {
         self.current.node.kind
     }
 
-    pub fn next_token(&mut self) -> Option<&'a Token> {
+    pub fn next_token(&mut self) -> Option<Token> {
         loop {
-            let current = self.current();
+            let current = self.current_stored();
+            let pos = self.pos;
             self.advance();
             match current {
                 Some(NodeOrToken::Node(_)) => (),
-                Some(NodeOrToken::Token(t)) => return Some(t),
+                Some(NodeOrToken::Token(t)) => {
+                    let mut token = t.clone();
+                    token.abs_pos = pos as _;
+                    return Some(token);
+                }
                 None => break None,
             }
         }
@@ -60,7 +65,10 @@ impl<'a> Cursor<'a> {
 
     /// advance the cursor, stepping over nodes.
     pub fn step_over(&mut self) {
-        let len = self.current().map(NodeOrToken::text_len).unwrap_or(0);
+        let len = self
+            .current_stored()
+            .map(NodeOrToken::text_len)
+            .unwrap_or(0);
         self.current.advance();
         self.pos += len;
     }
@@ -70,7 +78,7 @@ impl<'a> Cursor<'a> {
     /// This descends or returns into and from child nodes.
     pub fn advance(&mut self) {
         self.pos += self.text_len_if_at_token().unwrap_or(0);
-        match self.current() {
+        match self.current_stored() {
             Some(NodeOrToken::Token(_)) => {
                 self.current.advance();
             }
@@ -87,7 +95,7 @@ impl<'a> Cursor<'a> {
 
         // if we are finished a node (including when we just advanced) we restore
         // the previous unfinished parent
-        if self.current().is_none() {
+        if self.current_stored().is_none() {
             assert!(self.current.is_done());
             while self.current.is_done() {
                 match self.parents.pop() {
@@ -102,7 +110,7 @@ impl<'a> Cursor<'a> {
     }
 
     fn text_len_if_at_token(&self) -> Option<usize> {
-        match self.current()? {
+        match self.current_stored()? {
             NodeOrToken::Token(t) => Some(t.text.len()),
             _ => None,
         }
@@ -114,7 +122,14 @@ impl<'a> Cursor<'a> {
     ///
     /// This will never point to the root node itself, only its descendents.
     //TODO: we could solve this with a dummy root, do we care?
-    pub fn current(&self) -> Option<&'a NodeOrToken> {
+    pub fn current(&self) -> Option<NodeOrToken> {
+        let mut current = self.current_stored()?.clone();
+        current.set_pos(self.pos);
+        Some(current)
+    }
+
+    // the current item as it is stored in the tree, without a position.
+    fn current_stored(&self) -> Option<&'a NodeOrToken> {
         self.current.current()
     }
 
@@ -135,7 +150,7 @@ impl<'a> Cursor<'a> {
 
     pub fn descend_current(&mut self) {
         let new_current = self
-            .current()
+            .current_stored()
             .and_then(NodeOrToken::as_node)
             .expect("descend_current expects current to be Node");
         self.descend(new_current)
@@ -235,7 +250,7 @@ mod tests {
     fn at_node(cursor: &Cursor, kind: Kind) -> bool {
         cursor
             .current()
-            .and_then(NodeOrToken::as_node)
+            .and_then(NodeOrToken::into_node)
             .map(|n| n.kind == kind)
             .unwrap_or(false)
     }
@@ -243,7 +258,7 @@ mod tests {
     fn at_token(cursor: &Cursor, kind: Kind) -> bool {
         cursor
             .current()
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
             .map(|n| n.kind == kind)
             .unwrap_or(false)
     }
@@ -1,11 +1,27 @@
 //! facilities for editing the AST.
-//!
-//! This is currently unused.
 
-#![allow(dead_code)]
-use std::ops::Range;
+use std::{ops::Range, sync::Arc};
 
-use super::{Node, TreeBuilder, cursor::Cursor};
+use super::{Node, NodeOrToken, TreeBuilder, cursor::Cursor};
+
+/// Replace the children of `base` in `range`.
+pub(crate) fn splice_children(
+    base: &mut Node,
+    range: Range<usize>,
+    replace_with: impl IntoIterator<Item = NodeOrToken>,
+) {
+    // if nothing else holds on to the children they are edited in place
+    let children = Arc::make_mut(&mut base.children);
+    // children are stored without positions
+    children.splice(
+        range,
+        replace_with.into_iter().map(|mut child| {
+            child.set_pos(0);
+            child
+        }),
+    );
+    base.text_len = children.iter().map(|x| x.text_len() as u32).sum();
+}
 
 /// if 'skip_parent' is true, then the children of inserted nodes are added
 /// but the inserted node itself is skipped.
@@ -32,7 +48,7 @@ fn apply_edits_recurse(
     while let Some(current) = cursor.current() {
         let next_edit_range = match edits.last() {
             None => {
-                builder.push_raw(current.clone());
+                builder.push_raw(current);
                 cursor.step_over();
                 continue;
             }
@@ -47,7 +63,7 @@ fn apply_edits_recurse(
         let cur_range = cursor.pos()..cursor.pos() + current.text_len();
         match op_for_node(cur_range, next_edit_range) {
             EditOp::Copy => {
-                builder.push_raw(current.clone());
+                builder.push_raw(current);
                 cursor.step_over();
                 //continue;
             }
@@ -142,7 +158,10 @@ feature liga {
 
         let edits = vec![(0..25, replace_lang), (72..94, replace_sub)];
         let edited = apply_edits(&root, edits, false);
-        let result = edited.iter_tokens().map(|t| t.as_str()).collect::<String>();
+        let result = edited
+            .iter_tokens()
+            .map(|t| t.text.to_string())
+            .collect::<String>();
         crate::assert_eq_str!(expected, result);
     }
 }
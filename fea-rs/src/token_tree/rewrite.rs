@@ -5,7 +5,7 @@
 //! lookahead. Instead, when we encounter a mark glyph we parse the statement
 //! naively, and then reparse it again afterwards.
 
-use crate::{Diagnostic, Level, NodeOrToken, parse::FileId};
+use crate::{Diagnostic, DiagnosticCode, Level, NodeOrToken, parse::FileId};
 
 use super::{AstSink, Kind};
 
@@ -70,7 +70,10 @@ impl<'a, 'b> ReparseCtx<'a, 'b> {
 
     fn expect(&mut self, kind: Kind) -> bool {
         if !self.eat(kind) {
-            self.err_and_bump(format!("expected '{}' found '{}'", kind, self.nth_kind(0)));
+            self.err_and_bump(
+                DiagnosticCode::UnexpectedToken,
+                format!("expected '{}' found '{}'", kind, self.nth_kind(0)),
+            );
             return false;
         }
         true
@@ -82,31 +85,34 @@ impl<'a, 'b> ReparseCtx<'a, 'b> {
         // errored.
 
         if !self.eat(Kind::Semi) && !self.sink.current_node_has_error() {
-            self.error("unexpected tokens in rewriter, please file a bug?");
+            self.error(
+                DiagnosticCode::Internal,
+                "unexpected tokens in rewriter, please file a bug?",
+            );
             while !self.in_buf.is_empty() {
                 self.bump_raw();
             }
         }
     }
 
-    fn error(&mut self, message: impl Into<String>) {
-        self.add_diagnostic(Level::Error, message);
+    fn error(&mut self, code: DiagnosticCode, message: impl Into<String>) {
+        self.add_diagnostic(Level::Error, code, message);
     }
 
-    fn warn(&mut self, message: impl Into<String>) {
-        self.add_diagnostic(Level::Warning, message);
+    fn warn(&mut self, code: DiagnosticCode, message: impl Into<String>) {
+        self.add_diagnostic(Level::Warning, code, message);
     }
 
-    fn add_diagnostic(&mut self, level: Level, message: impl Into<String>) {
+    fn add_diagnostic(&mut self, level: Level, code: DiagnosticCode, message: impl Into<String>) {
         self.eat_trivia();
         let cur_len = self.nth(0).map(NodeOrToken::text_len).unwrap_or(0);
         let range = self.text_pos..self.text_pos + cur_len;
-        let error = Diagnostic::new(level, FileId::CURRENT_FILE, range, message);
+        let error = Diagnostic::new(level, FileId::CURRENT_FILE, range, message).with_code(code);
         self.sink.error(error)
     }
 
-    fn err_and_bump(&mut self, message: impl Into<String>) {
-        self.error(message);
+    fn err_and_bump(&mut self, code: DiagnosticCode, message: impl Into<String>) {
+        self.error(code, message);
         self.eat_any();
     }
 
@@ -181,7 +187,10 @@ pub(crate) fn reparse_contextual_sub_rule(rewriter: &mut ReparseCtx) -> Kind {
         });
     }
     if rewriter.matches(0, Kind::FromKw) {
-        rewriter.err_and_bump("alternate substition rules cannot be specified inline");
+        rewriter.err_and_bump(
+            DiagnosticCode::InvalidRule,
+            "alternate substition rules cannot be specified inline",
+        );
         eat_glyph_or_glyph_class(rewriter);
     }
     rewriter.expect_semi_and_nothing_else();
@@ -229,6 +238,7 @@ pub(crate) fn reparse_contextual_pos_rule(rewriter: &mut ReparseCtx) -> Kind {
         rewriter.eat(Kind::ValueRecordNode);
     } else if rewriter.matches(0, Kind::ValueRecordNode) {
         rewriter.err_and_bump(
+            DiagnosticCode::InvalidRule,
             "trailing value record only valid if there is a single marked glyph, \
             no inline lookups, and at least one lookahead glyph.",
         );
@@ -275,7 +285,7 @@ fn expect_ignore_rule_statement(rewriter: &mut ReparseCtx) {
         // the degen case, where there are no mark glyphs and so we just make
         // the first non-marked glyph be the input sequence:
     } else {
-        rewriter.warn("No marked glyphs in sequence. This glyph will be treated as input, all others as lookahead.");
+        rewriter.warn(DiagnosticCode::InvalidRule, "No marked glyphs in sequence. This glyph will be treated as input, all others as lookahead.");
         rewriter.in_node(Kind::IgnoreRuleStatementNode, |rewriter| {
             rewriter.in_node(Kind::BacktrackSequence, |_| {}); // empty backtrack
             rewriter.in_node(Kind::ContextSequence, |rewriter| {
@@ -333,11 +343,14 @@ fn expect(
     expected_name: &str,
 ) -> bool {
     if !eat_fn(rewriter) {
-        rewriter.err_and_bump(format!(
-            "expected '{}', found '{}",
-            expected_name,
-            rewriter.nth_kind(0)
-        ));
+        rewriter.err_and_bump(
+            DiagnosticCode::UnexpectedToken,
+            format!(
+                "expected '{}', found '{}",
+                expected_name,
+                rewriter.nth_kind(0)
+            ),
+        );
         return false;
     }
     true
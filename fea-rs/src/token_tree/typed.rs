@@ -22,7 +22,10 @@ use super::{ChildIter, Token};
 /// Implementations of this type are generally generated via macro.
 pub trait AstNode {
     /// Attempt to cast from some node or token to this type.
-    fn cast(node: &NodeOrToken) -> Option<Self>
+    ///
+    /// This accepts either a reference or an owned value, so that it can be
+    /// used directly with the items of [`Node::iter_children`].
+    fn cast(node: impl AsRef<NodeOrToken>) -> Option<Self>
     where
         Self: Sized;
 
@@ -67,8 +70,8 @@ macro_rules! ast_token {
         }
 
         impl AstNode for $typ {
-            fn cast(node: &NodeOrToken) -> Option<Self> {
-                if let NodeOrToken::Token(t) = node {
+            fn cast(node: impl AsRef<NodeOrToken>) -> Option<Self> {
+                if let NodeOrToken::Token(t) = node.as_ref() {
                     if t.kind == $kind {
                         return Some(Self { inner: t.clone() });
                     }
@@ -103,10 +106,10 @@ macro_rules! ast_node {
             }
 
             #[allow(dead_code)]
-            pub(crate) fn find_token(&self, kind: Kind) -> Option<&Token> {
+            pub(crate) fn find_token(&self, kind: Kind) -> Option<Token> {
                 self.iter()
                     .find(|t| t.kind() == kind)
-                    .and_then(NodeOrToken::as_token)
+                    .and_then(NodeOrToken::into_token)
             }
 
             /// Return a reference to the underlying `Node`.
@@ -123,8 +126,8 @@ macro_rules! ast_node {
         }
 
         impl AstNode for $typ {
-            fn cast(node: &NodeOrToken) -> Option<Self> {
-                if let NodeOrToken::Node(inner) = node {
+            fn cast(node: impl AsRef<NodeOrToken>) -> Option<Self> {
+                if let NodeOrToken::Node(inner) = node.as_ref() {
                     return Self::try_from_node(inner);
                 }
                 None
@@ -154,7 +157,8 @@ macro_rules! ast_enum {
         }
 
         impl AstNode for $typ {
-            fn cast(node: &NodeOrToken) -> Option<Self> {
+            fn cast(node: impl AsRef<NodeOrToken>) -> Option<Self> {
+                let node = node.as_ref();
                 $(
                     if let Some(thing) = $member::cast(node) {
                         return Some(Self::$name(thing));
@@ -174,8 +178,9 @@ macro_rules! ast_enum {
         }
 
         impl $typ {
+            /// Return a reference to the underlying `Node`, if this is not a token.
             #[allow(unused)]
-            pub(crate) fn node(&self) -> Option<&Node> {
+            pub fn node(&self) -> Option<&Node> {
                 match self {
                     $(
                         Self::$name(inner) => inner.node_(),
@@ -204,6 +209,7 @@ ast_token!(Octal, Kind::Octal);
 ast_token!(Hex, Kind::Hex);
 ast_token!(Null, Kind::NullKw);
 ast_node!(Root, Kind::SourceFile);
+ast_node!(AnonBlock, Kind::AnonBlockNode);
 ast_node!(GlyphRange, Kind::GlyphRange);
 ast_node!(GlyphClassDef, Kind::GlyphClassDefNode);
 ast_node!(MarkClassDef, Kind::MarkClassNode);
@@ -458,7 +464,7 @@ impl ContextualRuleNode for IgnoreRule {}
 
 impl Root {
     /// Iterate over all top-level statements
-    pub fn statements(&self) -> impl Iterator<Item = &NodeOrToken> {
+    pub fn statements(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
         self.iter().filter(|t| !t.kind().is_trivia())
     }
 }
@@ -481,7 +487,7 @@ impl LanguageSystem {
 }
 
 impl Include {
-    pub(crate) fn path(&self) -> &Token {
+    pub(crate) fn path(&self) -> Token {
         self.find_token(Kind::Path).unwrap()
     }
 }
@@ -498,27 +504,30 @@ impl Tag {
 }
 
 impl GlyphClassDef {
-    pub(crate) fn class_name(&self) -> GlyphClassName {
+    /// The name of the class being defined
+    pub fn class_name(&self) -> GlyphClassName {
         self.inner
             .iter_children()
             .find_map(GlyphClassName::cast)
             .unwrap()
     }
 
-    pub(crate) fn class_alias(&self) -> Option<GlyphClassName> {
+    /// The class this is an alias of, if defined as `@a = @b;`
+    pub fn class_alias(&self) -> Option<GlyphClassName> {
         //TODO: ensure this returns non in presence of named glyph class inside class block
         self.iter()
             .skip_while(|t| t.kind() != Kind::Eq)
             .find_map(GlyphClassName::cast)
     }
 
-    pub(crate) fn class_def(&self) -> Option<GlyphClassLiteral> {
+    /// The literal class, if defined as `@a = [b c];`
+    pub fn class_def(&self) -> Option<GlyphClassLiteral> {
         self.inner.iter_children().find_map(GlyphClassLiteral::cast)
     }
 }
 
 impl GlyphClassLiteral {
-    pub(crate) fn items(&self) -> impl Iterator<Item = &NodeOrToken> {
+    pub(crate) fn items(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
         self.iter()
             .skip_while(|t| t.kind() != Kind::LSquare)
             .skip(1)
@@ -534,18 +543,18 @@ impl Cid {
 }
 
 impl GlyphRange {
-    pub(crate) fn start(&self) -> &Token {
+    pub(crate) fn start(&self) -> Token {
         self.iter()
             .find(|i| i.kind() == Kind::Cid || i.kind() == Kind::GlyphName)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
             .unwrap()
     }
 
-    pub(crate) fn end(&self) -> &Token {
+    pub(crate) fn end(&self) -> Token {
         self.iter()
             .skip_while(|t| t.kind() != Kind::Hyphen)
             .find(|i| i.kind() == Kind::Cid || i.kind() == Kind::GlyphName)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
             .unwrap()
     }
 }
@@ -557,19 +566,22 @@ impl GlyphOrClass {
 }
 
 impl MarkClassDef {
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         self.find_token(Kind::MarkClassKw).unwrap()
     }
 
-    pub(crate) fn glyph_class(&self) -> GlyphOrClass {
+    /// The glyph or glyphs being added to the mark class
+    pub fn glyph_class(&self) -> GlyphOrClass {
         self.iter().find_map(GlyphOrClass::cast).expect("validated")
     }
 
-    pub(crate) fn anchor(&self) -> Anchor {
+    /// The anchor for these glyphs
+    pub fn anchor(&self) -> Anchor {
         self.iter().find_map(Anchor::cast).unwrap()
     }
 
-    pub(crate) fn mark_class_name(&self) -> GlyphClassName {
+    /// The name of the mark class
+    pub fn mark_class_name(&self) -> GlyphClassName {
         self.iter()
             .skip_while(|t| t.kind() != Kind::AnchorNode)
             .find_map(GlyphClassName::cast)
@@ -582,17 +594,19 @@ impl ValueRecordDef {
         self.iter().find_map(ValueRecord::cast).unwrap()
     }
 
-    pub(crate) fn name(&self) -> &Token {
+    pub(crate) fn name(&self) -> Token {
         self.find_token(Kind::Ident).expect("validated")
     }
 }
 
 impl AnchorDef {
-    pub(crate) fn anchor(&self) -> Anchor {
+    /// The anchor being named
+    pub fn anchor(&self) -> Anchor {
         self.iter().find_map(Anchor::cast).unwrap()
     }
 
-    pub(crate) fn name(&self) -> &Token {
+    /// The name of the anchor
+    pub fn name(&self) -> Token {
         self.find_token(Kind::Ident).expect("pre-validated")
     }
 }
@@ -626,11 +640,11 @@ impl Anchor {
             .map(|first| (first, iter.next().expect("one device implies another")))
     }
 
-    pub(crate) fn null(&self) -> Option<&Token> {
+    pub(crate) fn null(&self) -> Option<Token> {
         self.find_token(Kind::NullKw)
     }
 
-    pub(crate) fn name(&self) -> Option<&Token> {
+    pub(crate) fn name(&self) -> Option<Token> {
         self.find_token(Kind::Ident)
     }
 }
@@ -679,8 +693,9 @@ impl Feature {
         self.statements().any(|s| s.kind() == Kind::Comment)
     }
 
-    pub(crate) fn statements(&self) -> impl Iterator<Item = &NodeOrToken> {
-        fn filter_trivia_except_for_magic_insertion_comments(item: &&NodeOrToken) -> bool {
+    /// Iterate over the statements in this feature block
+    pub fn statements(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
+        fn filter_trivia_except_for_magic_insertion_comments(item: &NodeOrToken) -> bool {
             match item.kind() {
                 Kind::Comment => item
                     .token_text()
@@ -700,25 +715,60 @@ impl Feature {
     }
 }
 
+impl AnonBlock {
+    /// The block's tag, or `None` if the block is malformed.
+    pub(crate) fn label(&self) -> Option<Token> {
+        self.iter()
+            .skip(1)
+            .find(|t| !t.kind().is_trivia())
+            .and_then(NodeOrToken::into_token)
+            .filter(|t| t.kind != Kind::LBrace)
+    }
+
+    /// The raw text between the opening and closing braces.
+    pub(crate) fn contents(&self) -> String {
+        raw_block_contents(self.iter())
+    }
+}
+
+impl UnimplentedTable {
+    /// The raw text between the opening and closing braces.
+    pub(crate) fn contents(&self) -> String {
+        raw_block_contents(self.iter())
+    }
+}
+
+fn raw_block_contents(children: ChildIter) -> String {
+    let body = children
+        .skip_while(|t| t.kind() != Kind::LBrace)
+        .skip(1)
+        .collect::<Vec<_>>();
+    let end = body
+        .iter()
+        .rposition(|t| t.kind() == Kind::RBrace)
+        .unwrap_or(body.len());
+    body[..end].iter().filter_map(|t| t.token_text()).collect()
+}
+
 impl LookupBlock {
-    #[allow(unused)]
-    //TODO: do we want to support this syntax?
-    pub(crate) fn use_extension(&self) -> Option<&Token> {
+    pub(crate) fn use_extension(&self) -> Option<Token> {
         self.iter()
             .take_while(|t| t.kind() != Kind::LBrace)
             .find(|t| t.kind() == Kind::UseExtensionKw)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
     }
 
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         self.find_token(Kind::LookupKw).unwrap()
     }
 
-    pub(crate) fn label(&self) -> &Token {
+    /// The name of this lookup
+    pub fn label(&self) -> Token {
         self.find_token(Kind::Label).unwrap()
     }
 
-    pub(crate) fn statements(&self) -> impl Iterator<Item = &NodeOrToken> {
+    /// Iterate over the statements in this lookup block
+    pub fn statements(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
         self.iter()
             .skip_while(|t| t.kind() != Kind::LBrace)
             .skip(1)
@@ -728,11 +778,11 @@ impl LookupBlock {
 }
 
 impl ConditionSet {
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         self.find_token(Kind::ConditionSetKw).unwrap()
     }
 
-    pub(crate) fn label(&self) -> &Token {
+    pub(crate) fn label(&self) -> Token {
         self.find_token(Kind::Label).unwrap()
     }
 
@@ -761,15 +811,15 @@ impl FeatureVariation {
     }
 
     /// optional; if this is 'none' then 'null' must be present
-    pub(crate) fn condition_set(&self) -> Option<&Token> {
+    pub(crate) fn condition_set(&self) -> Option<Token> {
         self.find_token(Kind::Label)
     }
 
-    pub(crate) fn null(&self) -> Option<&Token> {
+    pub(crate) fn null(&self) -> Option<Token> {
         self.find_token(Kind::NullKw)
     }
 
-    pub(crate) fn statements(&self) -> impl Iterator<Item = &NodeOrToken> {
+    pub(crate) fn statements(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
         self.iter()
             .skip_while(|t| t.kind() != Kind::LBrace)
             .skip(1)
@@ -791,15 +841,15 @@ impl Language {
 
     //FIXME: I believe this is never meaningful, as it is the default behaviour?
     #[allow(unused)]
-    pub(crate) fn include_dflt(&self) -> Option<&Token> {
+    pub(crate) fn include_dflt(&self) -> Option<Token> {
         self.find_token(Kind::IncludeDfltKw)
     }
 
-    pub(crate) fn exclude_dflt(&self) -> Option<&Token> {
+    pub(crate) fn exclude_dflt(&self) -> Option<Token> {
         self.find_token(Kind::ExcludeDfltKw)
     }
 
-    pub(crate) fn required(&self) -> Option<&Token> {
+    pub(crate) fn required(&self) -> Option<Token> {
         self.find_token(Kind::RequiredKw)
     }
 }
@@ -809,7 +859,7 @@ impl LookupFlag {
         self.iter().find_map(Number::cast)
     }
 
-    pub(crate) fn values(&self) -> impl Iterator<Item = &NodeOrToken> + '_ {
+    pub(crate) fn values(&self) -> impl Iterator<Item = NodeOrToken> + '_ {
         self.iter()
             .skip(1)
             .take_while(|t| t.kind() != Kind::Number && t.kind() != Kind::Semi)
@@ -818,7 +868,8 @@ impl LookupFlag {
 }
 
 impl LookupRef {
-    pub(crate) fn label(&self) -> &Token {
+    /// The name of the referenced lookup
+    pub fn label(&self) -> Token {
         self.find_token(Kind::Ident).unwrap()
     }
 }
@@ -958,11 +1009,11 @@ impl Gpos1 {
 }
 
 impl Gpos2 {
-    pub(crate) fn enum_(&self) -> Option<&Token> {
+    pub(crate) fn enum_(&self) -> Option<Token> {
         self.iter()
             .take_while(|t| t.kind() != Kind::PosKw)
             .find(|t| t.kind() == Kind::EnumKw)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
     }
 
     pub(crate) fn first_item(&self) -> GlyphOrClass {
@@ -1081,14 +1132,14 @@ impl ValueRecord {
         self.iter().next().and_then(Metric::cast)
     }
 
-    pub(crate) fn null(&self) -> Option<&Token> {
+    pub(crate) fn null(&self) -> Option<Token> {
         self.iter()
             .take(3)
             .find(|t| t.kind() == Kind::NullKw)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
     }
 
-    pub(crate) fn named(&self) -> Option<&Token> {
+    pub(crate) fn named(&self) -> Option<Token> {
         self.find_token(Kind::Ident)
     }
 
@@ -1125,14 +1176,14 @@ impl ValueRecord {
 }
 
 impl Device {
-    fn null(&self) -> Option<&Token> {
+    fn null(&self) -> Option<Token> {
         self.iter()
             .take(4)
             .find(|t| t.kind() == Kind::NullKw)
-            .and_then(NodeOrToken::as_token)
+            .and_then(NodeOrToken::into_token)
     }
 
-    fn entries(&self) -> impl Iterator<Item = (Number, Number)> + '_ {
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (Number, Number)> + '_ {
         let mut iter = self
             .iter()
             .filter(|i| i.kind() == Kind::Number || i.kind() == Kind::Comma);
@@ -1149,10 +1200,18 @@ impl Device {
             return None;
         }
 
+        let mut sorted = self
+            .entries()
+            .map(|(ppem, pix)| {
+                let ppem = ppem.parse_unsigned().expect("validated before now");
+                (ppem, pix.parse_signed())
+            })
+            .collect::<Vec<_>>();
+        // sizes are not required to be in order
+        sorted.sort_unstable_by_key(|(ppem, _)| *ppem);
+
         let mut entries = Vec::new();
-        for (ppem, pix) in self.entries() {
-            let ppem = ppem.parse_unsigned().expect("validated before now");
-            let pix = pix.parse_signed();
+        for (ppem, pix) in sorted {
             // if there are gaps in the range, add zeros
             if let Some(prev) = entries.last().map(|(pp, _)| *pp) {
                 for missing in (prev + 1)..ppem {
@@ -1189,45 +1248,61 @@ impl BaseTable {
     pub(crate) fn horiz_base_tag_list(&self) -> Option<BaseTagList> {
         self.iter()
             .filter_map(BaseTagList::cast)
-            .find(BaseTagList::is_horiz)
+            .find(|b| b.is_horiz() == Some(true))
     }
 
     pub(crate) fn vert_base_tag_list(&self) -> Option<BaseTagList> {
         self.iter()
             .filter_map(BaseTagList::cast)
-            .find(|b| !b.is_horiz())
+            .find(|b| b.is_horiz() == Some(false))
     }
 
     pub(crate) fn horiz_base_script_record_list(&self) -> Option<BaseScriptList> {
         self.iter()
             .filter_map(BaseScriptList::cast)
-            .find(BaseScriptList::is_horiz)
+            .find(|b| b.is_horiz() == Some(true))
     }
 
     pub(crate) fn vert_base_script_record_list(&self) -> Option<BaseScriptList> {
         self.iter()
             .filter_map(BaseScriptList::cast)
-            .find(|b| !b.is_horiz())
+            .find(|b| b.is_horiz() == Some(false))
     }
     pub(crate) fn iter_horiz_min_max(&self) -> impl Iterator<Item = BaseMinMax> {
         self.iter()
             .filter_map(BaseMinMax::cast)
-            .filter(BaseMinMax::is_horiz)
+            .filter(|b| b.is_horiz() == Some(true))
     }
 
     pub(crate) fn iter_vert_min_max(&self) -> impl Iterator<Item = BaseMinMax> {
         self.iter()
             .filter_map(BaseMinMax::cast)
-            .filter(|b| !b.is_horiz())
+            .filter(|b| b.is_horiz() == Some(false))
+    }
+
+    /// The range of any tag list, script list or min/max item that is not
+    /// marked as either horizontal or vertical.
+    ///
+    /// These are skipped by the accessors above.
+    pub(crate) fn iter_unknown_axis_items(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.iter().filter_map(|item| {
+            let is_horiz = BaseTagList::cast(&item)
+                .map(|list| list.is_horiz())
+                .or_else(|| BaseScriptList::cast(&item).map(|list| list.is_horiz()))
+                .or_else(|| BaseMinMax::cast(&item).map(|minmax| minmax.is_horiz()))?;
+            is_horiz.is_none().then(|| item.range())
+        })
     }
 }
 
 impl BaseTagList {
-    fn is_horiz(&self) -> bool {
+    /// Whether this is a horizontal or vertical list, or `None` if it is
+    /// neither.
+    fn is_horiz(&self) -> Option<bool> {
         match self.iter().next().map(|t| t.kind()) {
-            Some(Kind::HorizAxisBaseTagListKw) => true,
-            Some(Kind::VertAxisBaseTagListKw) => false,
-            other => panic!("unexpected token in BaseTagList {other:?}"),
+            Some(Kind::HorizAxisBaseTagListKw) => Some(true),
+            Some(Kind::VertAxisBaseTagListKw) => Some(false),
+            _ => None,
         }
     }
 
@@ -1240,11 +1315,13 @@ impl BaseTagList {
 }
 
 impl BaseScriptList {
-    fn is_horiz(&self) -> bool {
+    /// Whether this is a horizontal or vertical list, or `None` if it is
+    /// neither.
+    fn is_horiz(&self) -> Option<bool> {
         match self.iter().next().map(|t| t.kind()) {
-            Some(Kind::HorizAxisBaseScriptListKw) => true,
-            Some(Kind::VertAxisBaseScriptListKw) => false,
-            other => panic!("unexpected token in BaseScriptList {other:?}"),
+            Some(Kind::HorizAxisBaseScriptListKw) => Some(true),
+            Some(Kind::VertAxisBaseScriptListKw) => Some(false),
+            _ => None,
         }
     }
 
@@ -1271,11 +1348,13 @@ impl ScriptRecord {
 }
 
 impl BaseMinMax {
-    pub(crate) fn is_horiz(&self) -> bool {
+    /// Whether this is for the horizontal or vertical axis, or `None` if it
+    /// is neither.
+    pub(crate) fn is_horiz(&self) -> Option<bool> {
         match self.iter().next().map(|t| t.kind()) {
-            Some(Kind::HorizAxisMinMaxKw) => true,
-            Some(Kind::VertAxisMinMaxKw) => false,
-            _other => unreachable!("parsing invariant violation"),
+            Some(Kind::HorizAxisMinMaxKw) => Some(true),
+            Some(Kind::VertAxisMinMaxKw) => Some(false),
+            _ => None,
         }
     }
 
@@ -1315,8 +1394,11 @@ impl VmtxTable {
 }
 
 impl VmtxEntry {
-    pub(crate) fn keyword(&self) -> &Token {
-        self.iter().next().and_then(NodeOrToken::as_token).unwrap()
+    pub(crate) fn keyword(&self) -> Token {
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn glyph(&self) -> Glyph {
@@ -1329,8 +1411,11 @@ impl VmtxEntry {
 }
 
 impl MetricRecord {
-    pub(crate) fn keyword(&self) -> &Token {
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+    pub(crate) fn keyword(&self) -> Token {
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn metric(&self) -> Metric {
@@ -1395,7 +1480,7 @@ impl AxisLocation {
 
     fn value(&self) -> f64 {
         let raw = self.iter().next().unwrap();
-        Number::cast(raw)
+        Number::cast(&raw)
             .map(|num| num.parse_signed() as f64)
             .or_else(|| Float::cast(raw).map(|num| num.parse()))
             .unwrap()
@@ -1413,8 +1498,11 @@ impl Os2Table {
 }
 
 impl NumberRecord {
-    pub(crate) fn keyword(&self) -> &Token {
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+    pub(crate) fn keyword(&self) -> Token {
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn number(&self) -> Number {
@@ -1423,7 +1511,7 @@ impl NumberRecord {
 }
 
 impl VendorRecord {
-    pub(crate) fn value(&self) -> &Token {
+    pub(crate) fn value(&self) -> Token {
         self.find_token(Kind::String).unwrap()
     }
 
@@ -1436,8 +1524,11 @@ impl VendorRecord {
 }
 
 impl Os2NumberList {
-    pub(crate) fn keyword(&self) -> &Token {
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+    pub(crate) fn keyword(&self) -> Token {
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn values(&self) -> impl Iterator<Item = Number> + '_ {
@@ -1452,9 +1543,12 @@ impl Os2FamilyClass {
 }
 
 impl FeatureNames {
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         debug_assert_eq!(self.iter().next().unwrap().kind(), Kind::FeatureNamesKw);
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn statements(&self) -> impl Iterator<Item = NameSpec> + '_ {
@@ -1463,9 +1557,12 @@ impl FeatureNames {
 }
 
 impl CvParameters {
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         debug_assert_eq!(self.iter().next().unwrap().kind(), Kind::CvParametersKw);
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn find_node(&self, kind: Kind) -> Option<CvParametersName> {
@@ -1498,8 +1595,11 @@ impl CvParameters {
 }
 
 impl CvParametersName {
-    pub(crate) fn keyword(&self) -> &Token {
-        self.iter().next().and_then(|t| t.as_token()).unwrap()
+    pub(crate) fn keyword(&self) -> Token {
+        self.iter()
+            .next()
+            .and_then(NodeOrToken::into_token)
+            .unwrap()
     }
 
     pub(crate) fn statements(&self) -> impl Iterator<Item = NameSpec> + '_ {
@@ -1544,15 +1644,16 @@ impl NameSpec {
         }
     }
 
-    pub(crate) fn string_token(&self) -> &Token {
+    pub(crate) fn string_token(&self) -> Token {
         // There is always a string
         self.find_token(Kind::String).unwrap()
     }
 
-    pub(crate) fn string(&self) -> &str {
+    pub(crate) fn string(&self) -> SmolStr {
         // The value is always doublequoted so slice out the actual string
-        let s = self.string_token().as_str();
-        &s[1..s.len() - 1]
+        let token = self.string_token();
+        let s = token.as_str();
+        s[1..s.len() - 1].into()
     }
 }
 
@@ -1625,11 +1726,11 @@ impl GdefAttach {
 }
 
 impl GdefLigatureCaret {
-    fn by_pos(&self) -> bool {
+    fn by_pos(&self) -> Option<bool> {
         match self.iter().next().map(|t| t.kind()) {
-            Some(Kind::LigatureCaretByPosKw) => true,
-            Some(Kind::LigatureCaretByIndexKw) => false,
-            other => panic!("unexpected token in ligaturecaret {other:?}"),
+            Some(Kind::LigatureCaretByPosKw) => Some(true),
+            Some(Kind::LigatureCaretByIndexKw) => Some(false),
+            _ => None,
         }
     }
 
@@ -1637,12 +1738,14 @@ impl GdefLigatureCaret {
         self.iter().find_map(GlyphOrClass::cast).unwrap()
     }
 
-    pub(crate) fn values(&self) -> LigatureCaretValue<'_> {
-        if self.by_pos() {
+    /// The caret values, or `None` if this is neither `LigatureCaretByPos`
+    /// nor `LigatureCaretByIndex`.
+    pub(crate) fn values(&self) -> Option<LigatureCaretValue<'_>> {
+        Some(if self.by_pos()? {
             LigatureCaretValue::Pos(LigatureCaretIter(self))
         } else {
             LigatureCaretValue::Index(LigatureCaretIter(self))
-        }
+        })
     }
 }
 
@@ -1729,8 +1832,8 @@ impl StatAxisFlag {
             .filter_map(|t| match t.kind() {
                 Kind::OlderSiblingFontAttributeKw => Some(0x01),
                 Kind::ElidableAxisValueNameKw => Some(0x02),
-                t if t.is_trivia() => None,
-                other => panic!("parser error '{other}'"),
+                // anything else is a parse error, and has already been reported
+                _ => None,
             })
     }
 }
@@ -1803,7 +1906,7 @@ impl Parameters {
 }
 
 impl FeatureRef {
-    pub(crate) fn keyword(&self) -> &Token {
+    pub(crate) fn keyword(&self) -> Token {
         self.find_token(Kind::FeatureKw).unwrap()
     }
 
@@ -0,0 +1,286 @@
+//! Traversal of the typed AST.
+//!
+//! The [`Visitor`] trait lets external tools inspect the statements in a
+//! parsed source (for linting, analysis, and so on) without having to
+//! re-implement the casting logic used by the compiler.
+
+use super::typed::{self, AstNode};
+use crate::{Kind, Node, NodeOrToken};
+
+/// A visitor over the typed AST.
+///
+/// Each method has a default implementation that continues the traversal
+/// via the corresponding `walk_*` function, so an implementation only needs to
+/// override the methods for the items it cares about. An overriding method
+/// that still wants to visit the children of its item should call that
+/// function itself.
+///
+/// Statements that failed to parse are passed to [`visit_other`], and are not
+/// descended into.
+///
+/// [`visit_other`]: Visitor::visit_other
+pub trait Visitor {
+    /// Visit the root of a source.
+    fn visit_root(&mut self, node: &typed::Root) {
+        walk_root(self, node)
+    }
+
+    /// Visit any statement that is not handled by a more specific method.
+    fn visit_other(&mut self, _item: &NodeOrToken) {}
+
+    /// Visit a `languagesystem` statement.
+    fn visit_language_system(&mut self, _node: &typed::LanguageSystem) {}
+
+    /// Visit a `table` block.
+    fn visit_table(&mut self, _node: &typed::Table) {}
+
+    /// Visit a `feature` block.
+    fn visit_feature(&mut self, node: &typed::Feature) {
+        walk_feature(self, node)
+    }
+
+    /// Visit a `lookup` block.
+    fn visit_lookup_block(&mut self, node: &typed::LookupBlock) {
+        walk_lookup_block(self, node)
+    }
+
+    /// Visit a reference to a named lookup, e.g. `lookup MY_LOOKUP;`
+    fn visit_lookup_ref(&mut self, _node: &typed::LookupRef) {}
+
+    /// Visit a `lookupflag` statement.
+    fn visit_lookup_flag(&mut self, _node: &typed::LookupFlag) {}
+
+    /// Visit a `script` statement.
+    fn visit_script(&mut self, _node: &typed::Script) {}
+
+    /// Visit a `language` statement.
+    fn visit_language(&mut self, _node: &typed::Language) {}
+
+    /// Visit a named glyph class definition.
+    fn visit_glyph_class_def(&mut self, node: &typed::GlyphClassDef) {
+        walk_glyph_class_def(self, node)
+    }
+
+    /// Visit a `markClass` statement.
+    fn visit_mark_class_def(&mut self, node: &typed::MarkClassDef) {
+        walk_mark_class_def(self, node)
+    }
+
+    /// Visit an `anchorDef` statement.
+    fn visit_anchor_def(&mut self, node: &typed::AnchorDef) {
+        self.visit_anchor(&node.anchor())
+    }
+
+    /// Visit a substitution rule.
+    fn visit_gsub_statement(&mut self, node: &typed::GsubStatement) {
+        if let Some(node) = node.node() {
+            walk_descendants(self, node)
+        }
+    }
+
+    /// Visit a positioning rule.
+    fn visit_gpos_statement(&mut self, node: &typed::GposStatement) {
+        if let Some(node) = node.node() {
+            walk_descendants(self, node)
+        }
+    }
+
+    /// Visit a glyph class, either a literal or a reference to a named class.
+    ///
+    /// This is called for the classes that appear in definitions and rules,
+    /// but not for the names of the classes being defined.
+    fn visit_glyph_class(&mut self, _node: &typed::GlyphClass) {}
+
+    /// Visit an anchor that appears in a definition or a rule.
+    fn visit_anchor(&mut self, _node: &typed::Anchor) {}
+}
+
+/// Visit each top-level statement in a source.
+pub fn walk_root<V: Visitor + ?Sized>(visitor: &mut V, node: &typed::Root) {
+    for item in node.statements() {
+        walk_statement(visitor, &item);
+    }
+}
+
+/// Visit each statement in a feature block.
+pub fn walk_feature<V: Visitor + ?Sized>(visitor: &mut V, node: &typed::Feature) {
+    for item in node.statements() {
+        walk_statement(visitor, &item);
+    }
+}
+
+/// Visit each statement in a lookup block.
+pub fn walk_lookup_block<V: Visitor + ?Sized>(visitor: &mut V, node: &typed::LookupBlock) {
+    for item in node.statements() {
+        walk_statement(visitor, &item);
+    }
+}
+
+/// Visit the class on the right-hand side of a glyph class definition.
+pub fn walk_glyph_class_def<V: Visitor + ?Sized>(visitor: &mut V, node: &typed::GlyphClassDef) {
+    if let Some(literal) = node.class_def() {
+        visitor.visit_glyph_class(&typed::GlyphClass::Literal(literal));
+    } else if let Some(alias) = node.class_alias() {
+        visitor.visit_glyph_class(&typed::GlyphClass::Named(alias));
+    }
+}
+
+/// Visit the glyphs and anchor of a `markClass` statement.
+pub fn walk_mark_class_def<V: Visitor + ?Sized>(visitor: &mut V, node: &typed::MarkClassDef) {
+    match node.glyph_class() {
+        typed::GlyphOrClass::Class(literal) => {
+            visitor.visit_glyph_class(&typed::GlyphClass::Literal(literal))
+        }
+        typed::GlyphOrClass::NamedClass(name) => {
+            visitor.visit_glyph_class(&typed::GlyphClass::Named(name))
+        }
+        _ => (),
+    }
+    visitor.visit_anchor(&node.anchor());
+}
+
+/// Dispatch a single statement to the appropriate method on the visitor.
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, item: &NodeOrToken) {
+    if item.kind().is_trivia() || item.kind() == Kind::Semi {
+        return;
+    }
+    if is_malformed(item) {
+        return visitor.visit_other(item);
+    }
+    if let Some(node) = typed::LanguageSystem::cast(item) {
+        visitor.visit_language_system(&node);
+    } else if let Some(node) = typed::Table::cast(item) {
+        visitor.visit_table(&node);
+    } else if let Some(node) = typed::Feature::cast(item) {
+        visitor.visit_feature(&node);
+    } else if let Some(node) = typed::LookupBlock::cast(item) {
+        visitor.visit_lookup_block(&node);
+    } else if let Some(node) = typed::LookupRef::cast(item) {
+        visitor.visit_lookup_ref(&node);
+    } else if let Some(node) = typed::LookupFlag::cast(item) {
+        visitor.visit_lookup_flag(&node);
+    } else if let Some(node) = typed::Script::cast(item) {
+        visitor.visit_script(&node);
+    } else if let Some(node) = typed::Language::cast(item) {
+        visitor.visit_language(&node);
+    } else if let Some(node) = typed::GlyphClassDef::cast(item) {
+        visitor.visit_glyph_class_def(&node);
+    } else if let Some(node) = typed::MarkClassDef::cast(item) {
+        visitor.visit_mark_class_def(&node);
+    } else if let Some(node) = typed::AnchorDef::cast(item) {
+        visitor.visit_anchor_def(&node);
+    } else if let Some(node) = typed::GsubStatement::cast(item) {
+        visitor.visit_gsub_statement(&node);
+    } else if let Some(node) = typed::GposStatement::cast(item) {
+        visitor.visit_gpos_statement(&node);
+    } else {
+        visitor.visit_other(item);
+    }
+}
+
+/// Visit every glyph class and anchor below this node.
+///
+/// We do not descend into the classes and anchors that are found.
+pub fn walk_descendants<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    for child in node.iter_children() {
+        if let Some(class) = typed::GlyphClass::cast(&child) {
+            visitor.visit_glyph_class(&class);
+        } else if let Some(anchor) = typed::Anchor::cast(&child) {
+            visitor.visit_anchor(&anchor);
+        } else if let Some(node) = child.as_node() {
+            walk_descendants(visitor, node);
+        }
+    }
+}
+
+// blocks are only considered malformed if the error is in the block itself,
+// so that a single bad rule doesn't hide everything else in the block.
+fn is_malformed(item: &NodeOrToken) -> bool {
+    item.as_node().is_some_and(|node| match node.kind() {
+        Kind::FeatureNode | Kind::LookupBlockNode => node.error,
+        _ => node.contains_error(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collector {
+        features: Vec<String>,
+        lookups: Vec<String>,
+        classes: Vec<String>,
+        anchors: usize,
+        rules: usize,
+        other: usize,
+    }
+
+    impl Visitor for Collector {
+        fn visit_feature(&mut self, node: &typed::Feature) {
+            self.features.push(node.tag().to_raw().to_string());
+            walk_feature(self, node);
+        }
+
+        fn visit_lookup_block(&mut self, node: &typed::LookupBlock) {
+            self.lookups.push(node.label().text.to_string());
+            walk_lookup_block(self, node);
+        }
+
+        fn visit_gsub_statement(&mut self, node: &typed::GsubStatement) {
+            self.rules += 1;
+            walk_descendants(self, node.node().unwrap());
+        }
+
+        fn visit_gpos_statement(&mut self, node: &typed::GposStatement) {
+            self.rules += 1;
+            walk_descendants(self, node.node().unwrap());
+        }
+
+        fn visit_glyph_class(&mut self, node: &typed::GlyphClass) {
+            let text = match node {
+                typed::GlyphClass::Named(name) => name.text().to_string(),
+                typed::GlyphClass::Literal(_) => "[]".to_string(),
+            };
+            self.classes.push(text);
+        }
+
+        fn visit_anchor(&mut self, _node: &typed::Anchor) {
+            self.anchors += 1;
+        }
+
+        fn visit_other(&mut self, _item: &NodeOrToken) {
+            self.other += 1;
+        }
+    }
+
+    #[test]
+    fn collect_items() {
+        let fea = "\
+@upper = [A B];
+@alias = @upper;
+markClass [acutecomb] <anchor 100 200> @TOP;
+lookup one {
+    sub @upper by a;
+} one;
+feature liga {
+    lookup one;
+    lookup two {
+        pos base a <anchor 1 2> mark @TOP;
+    } two;
+    sub f i by f_i;
+    sub by b;
+} liga;
+";
+        let (tree, _) = crate::parse::parse_string(fea);
+        let mut collector = Collector::default();
+        collector.visit_root(&tree.typed_root());
+        assert_eq!(collector.features, ["liga"]);
+        assert_eq!(collector.lookups, ["one", "two"]);
+        assert_eq!(collector.classes, ["[]", "@upper", "[]", "@upper", "@TOP"]);
+        assert_eq!(collector.anchors, 2);
+        assert_eq!(collector.rules, 3);
+        // the malformed rule
+        assert_eq!(collector.other, 1);
+    }
+}
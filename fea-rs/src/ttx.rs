@@ -0,0 +1,728 @@
+//! Dumping binary layout tables as TTX.
+//!
+//! The main entry point is [`dump`], which writes the BASE, GDEF, GSUB, GPOS
+//! and STAT tables of a font as XML in the format used by the fontTools `ttx`
+//! tool.
+//! This makes it possible to compare our output with that of feaLib without
+//! needing Python; the output for a given table should be identical to what
+//! `ttx -t GSUB` would produce for the same binary.
+//!
+//! Only those tables are written, and there is no support for reading TTX
+//! back in.
+
+use std::fmt::{Display, Write};
+
+use write_fonts::{
+    read::{
+        FontRead, FontRef, ReadError, TableProvider,
+        tables::{
+            gdef::{CaretValue, Gdef},
+            layout::{
+                ClassDef, CoverageTable, DeltaFormat, Device, DeviceOrVariationIndex, Feature,
+                FeatureList, FeatureParams, FeatureVariations, LangSys, Lookup, LookupFlag,
+                ScriptList,
+            },
+            name::Name,
+            variations::ItemVariationStore,
+        },
+    },
+    types::{F2Dot14, Fixed, GlyphId16, MajorMinor, NameId},
+};
+
+use crate::{GlyphIdent, GlyphMap, decompile::optional_table};
+
+mod base;
+mod contextual;
+mod gpos;
+mod gsub;
+mod stat;
+
+const INDENT: &str = "  ";
+
+/// Write the BASE, GDEF, GSUB, GPOS and STAT tables in `font` as TTX.
+///
+/// The `glyph_map` is used to name glyphs; as in fontTools, glyphs that are not
+/// in the map are given names like `glyph00042`. If the font has a `name`
+/// table, it is used to annotate name ids.
+///
+/// Tables that are not present in the font are skipped. The tables are written
+/// in the order BASE, GDEF, GSUB, GPOS, STAT, which is the order used by the
+/// expected output in our test suites.
+pub fn dump(font: &FontRef, glyph_map: &GlyphMap) -> Result<String, ReadError> {
+    let mut writer = TtxWriter::new(glyph_map, optional_table(font.name())?);
+    writer
+        .out
+        .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ttFont>\n");
+    writer.indent += 1;
+    if let Some(base) = optional_table(font.base())? {
+        writer.out.push('\n');
+        writer.base(&base)?;
+    }
+    if let Some(gdef) = optional_table(font.gdef())? {
+        writer.out.push('\n');
+        writer.gdef(&gdef)?;
+    }
+    if let Some(gsub) = optional_table(font.gsub())? {
+        writer.out.push('\n');
+        writer.begin("GSUB", &[]);
+        writer.version(gsub.version());
+        writer.script_list(&gsub.script_list()?)?;
+        writer.feature_list(&gsub.feature_list()?)?;
+        writer.gsub_lookup_list(&gsub.lookup_list()?)?;
+        if let Some(variations) = gsub.feature_variations().transpose()? {
+            writer.feature_variations(&variations)?;
+        }
+        writer.end("GSUB");
+    }
+    if let Some(gpos) = optional_table(font.gpos())? {
+        writer.out.push('\n');
+        writer.begin("GPOS", &[]);
+        writer.version(gpos.version());
+        writer.script_list(&gpos.script_list()?)?;
+        writer.feature_list(&gpos.feature_list()?)?;
+        writer.gpos_lookup_list(&gpos.lookup_list()?)?;
+        if let Some(variations) = gpos.feature_variations().transpose()? {
+            writer.feature_variations(&variations)?;
+        }
+        writer.end("GPOS");
+    }
+    if let Some(stat) = optional_table(font.stat())? {
+        writer.out.push('\n');
+        writer.stat(&stat)?;
+    }
+    writer.out.push_str("\n</ttFont>\n");
+    Ok(writer.out)
+}
+
+/// An attribute value, written with `Display`.
+type Attr<'a> = (&'a str, &'a dyn Display);
+
+struct TtxWriter<'a> {
+    out: String,
+    indent: usize,
+    names: Vec<String>,
+    name_table: Option<Name<'a>>,
+}
+
+impl<'a> TtxWriter<'a> {
+    fn new(glyph_map: &GlyphMap, name_table: Option<Name<'a>>) -> Self {
+        let mut names = Vec::with_capacity(glyph_map.len());
+        for (gid, ident) in glyph_map.reverse_map() {
+            // fill any holes the way fontTools names glyphs without a name
+            while names.len() < gid.to_u16() as usize {
+                names.push(format!("glyph{:05}", names.len()));
+            }
+            names.push(match ident {
+                GlyphIdent::Name(name) => name.to_string(),
+                GlyphIdent::Cid(cid) => format!("cid{cid:05}"),
+            });
+        }
+        TtxWriter {
+            out: String::new(),
+            indent: 0,
+            names,
+            name_table,
+        }
+    }
+
+    fn glyph(&self, gid: GlyphId16) -> String {
+        self.names
+            .get(gid.to_u16() as usize)
+            .cloned()
+            .unwrap_or_else(|| format!("glyph{:05}", gid.to_u16()))
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn write_tag(&mut self, name: &str, attrs: &[Attr]) {
+        self.write_indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        for (key, value) in attrs {
+            write!(self.out, " {key}=\"{}\"", escape(&value.to_string())).unwrap();
+        }
+    }
+
+    /// `<name attrs>`, increasing the indent
+    fn begin(&mut self, name: &str, attrs: &[Attr]) {
+        self.write_tag(name, attrs);
+        self.out.push_str(">\n");
+        self.indent += 1;
+    }
+
+    /// `</name>`, decreasing the indent
+    fn end(&mut self, name: &str) {
+        self.indent -= 1;
+        self.write_indent();
+        writeln!(self.out, "</{name}>").unwrap();
+    }
+
+    /// `<name attrs/>`
+    fn simple(&mut self, name: &str, attrs: &[Attr]) {
+        self.write_tag(name, attrs);
+        self.out.push_str("/>\n");
+    }
+
+    /// `<name value="value"/>`
+    fn value(&mut self, name: &str, value: impl Display) {
+        self.simple(name, &[("value", &value)]);
+    }
+
+    /// `<name index="index" value="value"/>`
+    fn indexed_value(&mut self, name: &str, index: usize, value: impl Display) {
+        self.simple(name, &[("index", &index), ("value", &value)]);
+    }
+
+    /// `<!-- name=count -->`, as fontTools writes the length of arrays
+    fn count(&mut self, name: &str, count: impl Display) {
+        self.write_indent();
+        writeln!(self.out, "<!-- {name}={count} -->").unwrap();
+    }
+
+    /// Begin a table, with its index in the parent array and its format, if any.
+    fn begin_table(&mut self, name: &str, index: Option<usize>, format: Option<u16>) {
+        match (index, format) {
+            (Some(index), Some(format)) => {
+                self.begin(name, &[("index", &index), ("Format", &format)])
+            }
+            (Some(index), None) => self.begin(name, &[("index", &index)]),
+            (None, Some(format)) => self.begin(name, &[("Format", &format)]),
+            (None, None) => self.begin(name, &[]),
+        }
+    }
+
+    /// A null offset in an array, which fontTools keeps to preserve indices.
+    fn empty(&mut self, name: &str, index: usize) {
+        self.simple(name, &[("index", &index), ("empty", &1)]);
+    }
+
+    fn version(&mut self, version: MajorMinor) {
+        self.value(
+            "Version",
+            format_args!("0x{:04X}{:04X}", version.major, version.minor),
+        );
+    }
+
+    fn name_id(&mut self, name: &str, id: NameId) {
+        let id = id.to_u16();
+        self.write_tag(name, &[("value", &id)]);
+        self.out.push_str("/>");
+        if let Some(name_table) = self.name_table.as_ref().filter(|_| id != 0) {
+            let comment = if id == 0xFFFF {
+                "None".to_string()
+            } else {
+                debug_name(name_table, id).unwrap_or_else(|| "missing from name table".into())
+            };
+            write!(self.out, "  <!-- {} -->", escape(&comment)).unwrap();
+        }
+        self.out.push('\n');
+    }
+
+    fn script_list(&mut self, script_list: &ScriptList) -> Result<(), ReadError> {
+        self.begin("ScriptList", &[]);
+        self.count("ScriptCount", script_list.script_count());
+        for (i, record) in script_list.script_records().iter().enumerate() {
+            let script = record.script(script_list.offset_data())?;
+            self.begin_table("ScriptRecord", Some(i), None);
+            self.value("ScriptTag", record.script_tag());
+            self.begin("Script", &[]);
+            if let Some(lang_sys) = script.default_lang_sys().transpose()? {
+                self.lang_sys("DefaultLangSys", &lang_sys);
+            }
+            self.count("LangSysCount", script.lang_sys_count());
+            for (j, record) in script.lang_sys_records().iter().enumerate() {
+                self.begin_table("LangSysRecord", Some(j), None);
+                self.value("LangSysTag", record.lang_sys_tag());
+                self.lang_sys("LangSys", &record.lang_sys(script.offset_data())?);
+                self.end("LangSysRecord");
+            }
+            self.end("Script");
+            self.end("ScriptRecord");
+        }
+        self.end("ScriptList");
+        Ok(())
+    }
+
+    fn lang_sys(&mut self, name: &str, lang_sys: &LangSys) {
+        self.begin(name, &[]);
+        self.value("ReqFeatureIndex", lang_sys.required_feature_index());
+        self.count("FeatureCount", lang_sys.feature_index_count());
+        for (i, idx) in lang_sys.feature_indices().iter().enumerate() {
+            self.indexed_value("FeatureIndex", i, idx.get());
+        }
+        self.end(name);
+    }
+
+    fn feature_list(&mut self, feature_list: &FeatureList) -> Result<(), ReadError> {
+        self.begin("FeatureList", &[]);
+        self.count("FeatureCount", feature_list.feature_count());
+        for (i, record) in feature_list.feature_records().iter().enumerate() {
+            self.begin_table("FeatureRecord", Some(i), None);
+            self.value("FeatureTag", record.feature_tag());
+            self.feature(&record.feature(feature_list.offset_data())?)?;
+            self.end("FeatureRecord");
+        }
+        self.end("FeatureList");
+        Ok(())
+    }
+
+    fn feature(&mut self, feature: &Feature) -> Result<(), ReadError> {
+        self.begin("Feature", &[]);
+        if let Some(params) = feature.feature_params().transpose()? {
+            self.feature_params(&params);
+        }
+        self.count("LookupCount", feature.lookup_index_count());
+        for (i, idx) in feature.lookup_list_indices().iter().enumerate() {
+            self.indexed_value("LookupListIndex", i, idx.get());
+        }
+        self.end("Feature");
+        Ok(())
+    }
+
+    fn feature_params(&mut self, params: &FeatureParams) {
+        match params {
+            FeatureParams::Size(size) => {
+                self.begin("FeatureParamsSize", &[]);
+                self.value("DesignSize", deci_points(size.design_size()));
+                self.value("SubfamilyID", size.identifier());
+                self.name_id("SubfamilyNameID", NameId::new(size.name_entry()));
+                self.value("RangeStart", deci_points(size.range_start()));
+                self.value("RangeEnd", deci_points(size.range_end()));
+                self.end("FeatureParamsSize");
+            }
+            FeatureParams::StylisticSet(params) => {
+                self.begin("FeatureParamsStylisticSet", &[]);
+                self.value("Version", params.version());
+                self.name_id("UINameID", params.ui_name_id());
+                self.end("FeatureParamsStylisticSet");
+            }
+            FeatureParams::CharacterVariant(params) => {
+                let name = "FeatureParamsCharacterVariants";
+                self.begin_table(name, None, Some(params.format()));
+                self.value("Format", params.format());
+                self.name_id("FeatUILabelNameID", params.feat_ui_label_name_id());
+                self.name_id(
+                    "FeatUITooltipTextNameID",
+                    params.feat_ui_tooltip_text_name_id(),
+                );
+                self.name_id("SampleTextNameID", params.sample_text_name_id());
+                self.value("NumNamedParameters", params.num_named_parameters());
+                self.name_id(
+                    "FirstParamUILabelNameID",
+                    params.first_param_ui_label_name_id(),
+                );
+                self.count("CharCount", params.char_count());
+                for (i, c) in params.character().iter().enumerate() {
+                    self.indexed_value("Character", i, c.get().to_u32());
+                }
+                self.end(name);
+            }
+        }
+    }
+
+    /// A GSUB or GPOS lookup, calling `subtable` to write each subtable.
+    fn lookup<'b, T: FontRead<'b>>(
+        &mut self,
+        lookup: &Lookup<'b, T>,
+        mut subtable: impl FnMut(&mut Self, Option<usize>, T) -> Result<(), ReadError>,
+    ) -> Result<(), ReadError> {
+        self.lookup_header(
+            lookup.lookup_type(),
+            lookup.lookup_flag(),
+            lookup.sub_table_count(),
+        );
+        for (i, sub) in lookup.subtables().iter().enumerate() {
+            subtable(self, Some(i), sub?)?;
+        }
+        if let Some(set) = lookup.mark_filtering_set() {
+            self.value("MarkFilteringSet", set);
+        }
+        Ok(())
+    }
+
+    /// The fields shared by GSUB and GPOS lookups that precede the subtables.
+    fn lookup_header(&mut self, lookup_type: u16, flag: LookupFlag, subtable_count: u16) {
+        self.value("LookupType", lookup_type);
+        let flag = flag.to_bits();
+        self.write_tag("LookupFlag", &[("value", &flag)]);
+        self.out.push_str("/>");
+        let mut names = Vec::new();
+        for (bit, name) in [
+            (0x01, "rightToLeft"),
+            (0x02, "ignoreBaseGlyphs"),
+            (0x04, "ignoreLigatures"),
+            (0x08, "ignoreMarks"),
+            (0x10, "useMarkFilteringSet"),
+        ] {
+            if flag & bit != 0 {
+                names.push(name.to_string());
+            }
+        }
+        if flag & 0xFF00 != 0 {
+            names.push(format!("markAttachmentType[{}]", flag >> 8));
+        }
+        if !names.is_empty() {
+            write!(self.out, "<!-- {} -->", names.join(" ")).unwrap();
+        }
+        self.out.push('\n');
+        self.count("SubTableCount", subtable_count);
+    }
+
+    /// A coverage table, written as a list of glyphs.
+    fn coverage(&mut self, name: &str, index: Option<usize>, coverage: &CoverageTable) {
+        self.begin_table(name, index, None);
+        for gid in coverage.iter() {
+            let glyph = self.glyph(gid);
+            self.value("Glyph", glyph);
+        }
+        self.end(name);
+    }
+
+    /// A class definition table, written as a list of glyphs and their class,
+    /// sorted by glyph name.
+    fn class_def(&mut self, name: &str, class_def: &ClassDef) {
+        let mut items = class_def
+            .iter()
+            .filter(|(_, class)| *class != 0)
+            .map(|(gid, class)| (self.glyph(gid), class))
+            .collect::<Vec<_>>();
+        items.sort();
+        self.begin(name, &[]);
+        for (glyph, class) in items {
+            self.simple("ClassDef", &[("glyph", &glyph), ("class", &class)]);
+        }
+        self.end(name);
+    }
+
+    fn device(&mut self, name: &str, device: &DeviceOrVariationIndex) {
+        self.begin(name, &[]);
+        match device {
+            DeviceOrVariationIndex::Device(device) => {
+                self.value("StartSize", device.start_size());
+                self.value("EndSize", device.end_size());
+                self.value("DeltaFormat", device.delta_format() as u16);
+                let values = device_deltas(device)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>();
+                self.value("DeltaValue", format_args!("[{}]", values.join(", ")));
+            }
+            DeviceOrVariationIndex::VariationIndex(index) => {
+                self.value("StartSize", index.delta_set_outer_index());
+                self.value("EndSize", index.delta_set_inner_index());
+                self.value("DeltaFormat", DeltaFormat::VariationIndex as u16);
+            }
+        }
+        self.end(name);
+    }
+
+    fn feature_variations(&mut self, variations: &FeatureVariations) -> Result<(), ReadError> {
+        let data = variations.offset_data();
+        self.begin("FeatureVariations", &[]);
+        self.version(variations.version());
+        self.count(
+            "FeatureVariationCount",
+            variations.feature_variation_record_count(),
+        );
+        for (i, record) in variations.feature_variation_records().iter().enumerate() {
+            self.begin_table("FeatureVariationRecord", Some(i), None);
+            if let Some(condition_set) = record.condition_set(data).transpose()? {
+                self.begin("ConditionSet", &[]);
+                self.count("ConditionCount", condition_set.condition_count());
+                for (j, condition) in condition_set.conditions().iter().enumerate() {
+                    let condition = condition?;
+                    self.begin_table("ConditionTable", Some(j), Some(condition.format()));
+                    if let write_fonts::read::tables::layout::Condition::Format1AxisRange(
+                        condition,
+                    ) = condition
+                    {
+                        self.value("AxisIndex", condition.axis_index());
+                        self.value(
+                            "FilterRangeMinValue",
+                            f2dot14(condition.filter_range_min_value()),
+                        );
+                        self.value(
+                            "FilterRangeMaxValue",
+                            f2dot14(condition.filter_range_max_value()),
+                        );
+                    }
+                    self.end("ConditionTable");
+                }
+                self.end("ConditionSet");
+            }
+            if let Some(substitution) = record.feature_table_substitution(data).transpose()? {
+                self.begin("FeatureTableSubstitution", &[]);
+                self.version(substitution.version());
+                self.count("SubstitutionCount", substitution.substitution_count());
+                for (j, record) in substitution.substitutions().iter().enumerate() {
+                    self.begin_table("SubstitutionRecord", Some(j), None);
+                    self.value("FeatureIndex", record.feature_index());
+                    self.feature(&record.alternate_feature(substitution.offset_data())?)?;
+                    self.end("SubstitutionRecord");
+                }
+                self.end("FeatureTableSubstitution");
+            }
+            self.end("FeatureVariationRecord");
+        }
+        self.end("FeatureVariations");
+        Ok(())
+    }
+
+    fn gdef(&mut self, gdef: &Gdef) -> Result<(), ReadError> {
+        self.begin("GDEF", &[]);
+        self.version(gdef.version());
+        if let Some(class_def) = gdef.glyph_class_def().transpose()? {
+            self.class_def("GlyphClassDef", &class_def);
+        }
+        if let Some(attach_list) = gdef.attach_list().transpose()? {
+            self.begin("AttachList", &[]);
+            self.coverage("Coverage", None, &attach_list.coverage()?);
+            self.count("GlyphCount", attach_list.glyph_count());
+            for (i, point) in attach_list.attach_points().iter().enumerate() {
+                let point = point?;
+                self.begin_table("AttachPoint", Some(i), None);
+                self.count("PointCount", point.point_count());
+                for (j, idx) in point.point_indices().iter().enumerate() {
+                    self.indexed_value("PointIndex", j, idx.get());
+                }
+                self.end("AttachPoint");
+            }
+            self.end("AttachList");
+        }
+        if let Some(lig_caret_list) = gdef.lig_caret_list().transpose()? {
+            self.begin("LigCaretList", &[]);
+            self.coverage("Coverage", None, &lig_caret_list.coverage()?);
+            self.count("LigGlyphCount", lig_caret_list.lig_glyph_count());
+            for (i, lig_glyph) in lig_caret_list.lig_glyphs().iter().enumerate() {
+                let lig_glyph = lig_glyph?;
+                self.begin_table("LigGlyph", Some(i), None);
+                self.count("CaretCount", lig_glyph.caret_count());
+                for (j, caret) in lig_glyph.caret_values().iter().enumerate() {
+                    let caret = caret?;
+                    self.begin_table("CaretValue", Some(j), Some(caret.caret_value_format()));
+                    match &caret {
+                        CaretValue::Format1(caret) => self.value("Coordinate", caret.coordinate()),
+                        CaretValue::Format2(caret) => {
+                            self.value("CaretValuePoint", caret.caret_value_point_index())
+                        }
+                        CaretValue::Format3(caret) => {
+                            self.value("Coordinate", caret.coordinate());
+                            self.device("DeviceTable", &caret.device()?);
+                        }
+                    }
+                    self.end("CaretValue");
+                }
+                self.end("LigGlyph");
+            }
+            self.end("LigCaretList");
+        }
+        if let Some(class_def) = gdef.mark_attach_class_def().transpose()? {
+            self.class_def("MarkAttachClassDef", &class_def);
+        }
+        if let Some(mark_sets) = gdef.mark_glyph_sets_def().transpose()? {
+            self.begin("MarkGlyphSetsDef", &[]);
+            self.value("MarkSetTableFormat", mark_sets.format());
+            self.count("MarkSetCount", mark_sets.mark_glyph_set_count());
+            for (i, coverage) in mark_sets.coverages().iter().enumerate() {
+                self.coverage("Coverage", Some(i), &coverage?);
+            }
+            self.end("MarkGlyphSetsDef");
+        }
+        if let Some(var_store) = gdef.item_var_store().transpose()? {
+            self.var_store(&var_store)?;
+        }
+        self.end("GDEF");
+        Ok(())
+    }
+
+    fn var_store(&mut self, var_store: &ItemVariationStore) -> Result<(), ReadError> {
+        self.begin_table("VarStore", None, Some(var_store.format()));
+        self.value("Format", var_store.format());
+        let region_list = var_store.variation_region_list()?;
+        self.begin("VarRegionList", &[]);
+        self.count("RegionAxisCount", region_list.axis_count());
+        self.count("RegionCount", region_list.region_count());
+        for (i, region) in region_list.variation_regions().iter().enumerate() {
+            let region = region?;
+            self.begin_table("Region", Some(i), None);
+            for (j, axis) in region.region_axes().iter().enumerate() {
+                self.begin_table("VarRegionAxis", Some(j), None);
+                self.value("StartCoord", f2dot14(axis.start_coord()));
+                self.value("PeakCoord", f2dot14(axis.peak_coord()));
+                self.value("EndCoord", f2dot14(axis.end_coord()));
+                self.end("VarRegionAxis");
+            }
+            self.end("Region");
+        }
+        self.end("VarRegionList");
+        self.count("VarDataCount", var_store.item_variation_data_count());
+        for (i, data) in var_store.item_variation_data().iter().enumerate() {
+            let Some(data) = data.transpose()? else {
+                self.empty("VarData", i);
+                continue;
+            };
+            self.begin_table("VarData", Some(i), None);
+            self.count("ItemCount", data.item_count());
+            self.value("NumShorts", data.word_delta_count());
+            self.count("VarRegionCount", data.region_index_count());
+            for (j, idx) in data.region_indexes().iter().enumerate() {
+                self.indexed_value("VarRegionIndex", j, idx.get());
+            }
+            for j in 0..data.item_count() {
+                let deltas = data
+                    .delta_set(j)
+                    .map(|delta| delta.to_string())
+                    .collect::<Vec<_>>();
+                self.indexed_value("Item", j as usize, format_args!("[{}]", deltas.join(", ")));
+            }
+            self.end("VarData");
+        }
+        self.end("VarStore");
+        Ok(())
+    }
+}
+
+/// Look up a name the way fontTools' `getDebugName` does, preferring English.
+fn debug_name(name_table: &Name, id: u16) -> Option<String> {
+    let mut some_name = None;
+    for record in name_table.name_record() {
+        if record.name_id().to_u16() != id {
+            continue;
+        }
+        let Ok(string) = record.string(name_table.string_data()) else {
+            continue;
+        };
+        let string = string.to_string();
+        if matches!(
+            (record.platform_id(), record.language_id()),
+            (1, 0) | (3, 0x409)
+        ) {
+            return Some(string);
+        }
+        some_name.get_or_insert(string);
+    }
+    some_name
+}
+
+/// Decode the packed deltas of a device table.
+///
+/// We don't use `Device::iter` here, since it sign-extends values incorrectly
+/// for the 2-bit format.
+fn device_deltas(device: &Device) -> Vec<i8> {
+    let bits = match device.delta_format() {
+        DeltaFormat::Local2BitDeltas => 2,
+        DeltaFormat::Local4BitDeltas => 4,
+        DeltaFormat::Local8BitDeltas => 8,
+        _ => return Vec::new(),
+    };
+    let count = (device.end_size().saturating_sub(device.start_size())) as usize + 1;
+    let per_word = 16 / bits;
+    (0..count)
+        .filter_map(|i| {
+            let word = device.delta_value().get(i / per_word)?.get();
+            let shift = 16 - bits * (i % per_word + 1);
+            let raw = (word >> shift) & ((1 << bits) - 1);
+            // shift the sign bit into the top of an i16, then back down
+            Some((((raw << (16 - bits)) as i16) >> (16 - bits)) as i8)
+        })
+        .collect()
+}
+
+/// Size feature values are in tenths of a point, and written as floats.
+fn deci_points(value: u16) -> String {
+    format!("{:?}", value as f64 / 10.0)
+}
+
+fn f2dot14(value: F2Dot14) -> String {
+    fixed_to_str(value.to_bits() as i32, 14)
+}
+
+fn fixed(value: Fixed) -> String {
+    fixed_to_str(value.to_bits(), 16)
+}
+
+/// Write a fixed-point number with the shortest decimal representation that
+/// round-trips, matching fontTools' `fixedToStr`.
+fn fixed_to_str(raw: i32, precision_bits: u32) -> String {
+    if raw == 0 {
+        return "0.0".into();
+    }
+    let scale = (1_i64 << precision_bits) as f64;
+    let value = raw as f64 / scale;
+    let eps = 0.5 / scale;
+    let lo = value - eps;
+    let hi = value + eps;
+    // if the range of valid choices spans an integer, use the integer
+    if lo.trunc() != hi.trunc() {
+        return format!("{:?}", value.round());
+    }
+    let lo = format!("{lo:.8}");
+    let hi = format!("{hi:.8}");
+    let first_diff = lo
+        .bytes()
+        .zip(hi.bytes())
+        .position(|(a, b)| a != b)
+        .unwrap_or(lo.len());
+    let period = lo.find('.').unwrap_or_default();
+    format!("{value:.prec$}", prec = first_diff.saturating_sub(period))
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f2dot14_shortest_repr() {
+        for (value, expected) in [
+            (0.0, "0.0"),
+            (1.0, "1.0"),
+            (-1.0, "-1.0"),
+            (0.5, "0.5"),
+            (-0.5, "-0.5"),
+            (0.875, "0.875"),
+            (0.3, "0.3"),
+            (0.625, "0.625"),
+        ] {
+            assert_eq!(f2dot14(F2Dot14::from_f32(value)), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn fixed_shortest_repr() {
+        for (value, expected) in [
+            (0.0, "0.0"),
+            (8.0, "8.0"),
+            (-400.0, "-400.0"),
+            (16.7, "16.7"),
+            (0.1, "0.1"),
+            (1.5, "1.5"),
+        ] {
+            assert_eq!(fixed(Fixed::from_f64(value)), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn deci_points_as_float() {
+        assert_eq!(deci_points(100), "10.0");
+        assert_eq!(deci_points(123), "12.3");
+        assert_eq!(deci_points(0), "0.0");
+    }
+}
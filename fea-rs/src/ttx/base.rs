@@ -0,0 +1,130 @@
+//! Dumping the BASE table.
+
+use write_fonts::read::{
+    ReadError,
+    tables::base::{Axis, Base, BaseCoord, BaseScript, MinMax},
+};
+
+use super::TtxWriter;
+
+impl TtxWriter<'_> {
+    pub(super) fn base(&mut self, base: &Base) -> Result<(), ReadError> {
+        self.begin("BASE", &[]);
+        self.version(base.version());
+        if let Some(axis) = base.horiz_axis().transpose()? {
+            self.base_axis("HorizAxis", &axis)?;
+        }
+        if let Some(axis) = base.vert_axis().transpose()? {
+            self.base_axis("VertAxis", &axis)?;
+        }
+        if let Some(var_store) = base.item_var_store().transpose()? {
+            self.var_store(&var_store)?;
+        }
+        self.end("BASE");
+        Ok(())
+    }
+
+    fn base_axis(&mut self, name: &str, axis: &Axis) -> Result<(), ReadError> {
+        self.begin(name, &[]);
+        if let Some(tag_list) = axis.base_tag_list().transpose()? {
+            self.begin("BaseTagList", &[]);
+            self.count("BaseTagCount", tag_list.base_tag_count());
+            for (i, tag) in tag_list.baseline_tags().iter().enumerate() {
+                self.indexed_value("BaselineTag", i, tag.get());
+            }
+            self.end("BaseTagList");
+        }
+        let script_list = axis.base_script_list()?;
+        self.begin("BaseScriptList", &[]);
+        self.count("BaseScriptCount", script_list.base_script_count());
+        for (i, record) in script_list.base_script_records().iter().enumerate() {
+            self.begin_table("BaseScriptRecord", Some(i), None);
+            self.value("BaseScriptTag", record.base_script_tag());
+            self.base_script(&record.base_script(script_list.offset_data())?)?;
+            self.end("BaseScriptRecord");
+        }
+        self.end("BaseScriptList");
+        self.end(name);
+        Ok(())
+    }
+
+    fn base_script(&mut self, script: &BaseScript) -> Result<(), ReadError> {
+        self.begin("BaseScript", &[]);
+        if let Some(values) = script.base_values().transpose()? {
+            self.begin("BaseValues", &[]);
+            self.value("DefaultIndex", values.default_baseline_index());
+            self.count("BaseCoordCount", values.base_coord_count());
+            for (i, coord) in values.base_coords().iter().enumerate() {
+                self.base_coord("BaseCoord", Some(i), &coord?)?;
+            }
+            self.end("BaseValues");
+        }
+        if let Some(min_max) = script.default_min_max().transpose()? {
+            self.min_max("DefaultMinMax", &min_max)?;
+        }
+        self.count("BaseLangSysCount", script.base_lang_sys_count());
+        for (i, record) in script.base_lang_sys_records().iter().enumerate() {
+            self.begin_table("BaseLangSysRecord", Some(i), None);
+            self.value("BaseLangSysTag", record.base_lang_sys_tag());
+            self.min_max("MinMax", &record.min_max(script.offset_data())?)?;
+            self.end("BaseLangSysRecord");
+        }
+        self.end("BaseScript");
+        Ok(())
+    }
+
+    fn min_max(&mut self, name: &str, min_max: &MinMax) -> Result<(), ReadError> {
+        self.begin(name, &[]);
+        if let Some(coord) = min_max.min_coord().transpose()? {
+            self.base_coord("MinCoord", None, &coord)?;
+        }
+        if let Some(coord) = min_max.max_coord().transpose()? {
+            self.base_coord("MaxCoord", None, &coord)?;
+        }
+        self.count("FeatMinMaxCount", min_max.feat_min_max_count());
+        for (i, record) in min_max.feat_min_max_records().iter().enumerate() {
+            self.begin_table("FeatMinMaxRecord", Some(i), None);
+            self.value("FeatureTableTag", record.feature_table_tag());
+            let data = min_max.offset_data();
+            if let Some(coord) = record.min_coord(data).transpose()? {
+                self.base_coord("MinCoord", None, &coord)?;
+            }
+            if let Some(coord) = record.max_coord(data).transpose()? {
+                self.base_coord("MaxCoord", None, &coord)?;
+            }
+            self.end("FeatMinMaxRecord");
+        }
+        self.end(name);
+        Ok(())
+    }
+
+    fn base_coord(
+        &mut self,
+        name: &str,
+        index: Option<usize>,
+        coord: &BaseCoord,
+    ) -> Result<(), ReadError> {
+        match coord {
+            BaseCoord::Format1(coord) => {
+                self.begin_table(name, index, Some(1));
+                self.value("Coordinate", coord.coordinate());
+            }
+            BaseCoord::Format2(coord) => {
+                self.begin_table(name, index, Some(2));
+                self.value("Coordinate", coord.coordinate());
+                let glyph = self.glyph(coord.reference_glyph().into());
+                self.value("ReferenceGlyph", glyph);
+                self.value("BaseCoordPoint", coord.base_coord_point());
+            }
+            BaseCoord::Format3(coord) => {
+                self.begin_table(name, index, Some(3));
+                self.value("Coordinate", coord.coordinate());
+                if let Some(device) = coord.device().transpose()? {
+                    self.device("DeviceTable", &device);
+                }
+            }
+        }
+        self.end(name);
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+//! Dumping contextual and chaining contextual subtables, shared by GSUB and GPOS.
+
+use write_fonts::{
+    read::{
+        ReadError,
+        tables::layout::{ChainedSequenceContext, SequenceContext, SequenceLookupRecord},
+    },
+    types::{BigEndian, GlyphId16},
+};
+
+use super::TtxWriter;
+
+/// Whether a contextual subtable belongs to GSUB or GPOS.
+///
+/// The tables are identical, but fontTools uses different element names.
+#[derive(Clone, Copy)]
+pub(super) enum Kind {
+    Subst,
+    Pos,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Subst => "Subst",
+            Kind::Pos => "Pos",
+        }
+    }
+
+    /// The prefix used for rule sets and rules, e.g. `SubRuleSet`
+    fn rule_prefix(self) -> &'static str {
+        match self {
+            Kind::Subst => "Sub",
+            Kind::Pos => "Pos",
+        }
+    }
+}
+
+impl TtxWriter<'_> {
+    pub(super) fn context(
+        &mut self,
+        kind: Kind,
+        index: Option<usize>,
+        subtable: &SequenceContext,
+    ) -> Result<(), ReadError> {
+        let name = format!("Context{}", kind.name());
+        let prefix = kind.rule_prefix();
+        self.begin_table(&name, index, Some(subtable.format()));
+        match subtable {
+            SequenceContext::Format1(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                let set_name = format!("{prefix}RuleSet");
+                let rule_name = format!("{prefix}Rule");
+                self.count(&format!("{set_name}Count"), table.seq_rule_set_count());
+                for (i, set) in table.seq_rule_sets().iter().enumerate() {
+                    let Some(set) = set.transpose()? else {
+                        self.empty(&set_name, i);
+                        continue;
+                    };
+                    self.begin_table(&set_name, Some(i), None);
+                    self.count(&format!("{rule_name}Count"), set.seq_rule_count());
+                    for (j, rule) in set.seq_rules().iter().enumerate() {
+                        let rule = rule?;
+                        self.begin_table(&rule_name, Some(j), None);
+                        self.count("GlyphCount", rule.glyph_count());
+                        self.count(&format!("{}Count", kind.name()), rule.seq_lookup_count());
+                        self.glyph_values("Input", rule.input_sequence());
+                        self.lookup_records(kind, rule.seq_lookup_records());
+                        self.end(&rule_name);
+                    }
+                    self.end(&set_name);
+                }
+            }
+            SequenceContext::Format2(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.class_def("ClassDef", &table.class_def()?);
+                let set_name = format!("{prefix}ClassSet");
+                let rule_name = format!("{prefix}ClassRule");
+                self.count(
+                    &format!("{set_name}Count"),
+                    table.class_seq_rule_set_count(),
+                );
+                for (i, set) in table.class_seq_rule_sets().iter().enumerate() {
+                    let Some(set) = set.transpose()? else {
+                        self.empty(&set_name, i);
+                        continue;
+                    };
+                    self.begin_table(&set_name, Some(i), None);
+                    self.count(&format!("{rule_name}Count"), set.class_seq_rule_count());
+                    for (j, rule) in set.class_seq_rules().iter().enumerate() {
+                        let rule = rule?;
+                        self.begin_table(&rule_name, Some(j), None);
+                        self.count("GlyphCount", rule.glyph_count());
+                        self.count(&format!("{}Count", kind.name()), rule.seq_lookup_count());
+                        self.class_values("Class", rule.input_sequence());
+                        self.lookup_records(kind, rule.seq_lookup_records());
+                        self.end(&rule_name);
+                    }
+                    self.end(&set_name);
+                }
+            }
+            SequenceContext::Format3(table) => {
+                self.count("GlyphCount", table.glyph_count());
+                self.count(&format!("{}Count", kind.name()), table.seq_lookup_count());
+                for (i, coverage) in table.coverages().iter().enumerate() {
+                    self.coverage("Coverage", Some(i), &coverage?);
+                }
+                self.lookup_records(kind, table.seq_lookup_records());
+            }
+        }
+        self.end(&name);
+        Ok(())
+    }
+
+    pub(super) fn chain_context(
+        &mut self,
+        kind: Kind,
+        index: Option<usize>,
+        subtable: &ChainedSequenceContext,
+    ) -> Result<(), ReadError> {
+        let name = format!("ChainContext{}", kind.name());
+        let prefix = kind.rule_prefix();
+        self.begin_table(&name, index, Some(subtable.format()));
+        match subtable {
+            ChainedSequenceContext::Format1(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                let set_name = format!("Chain{prefix}RuleSet");
+                let rule_name = format!("Chain{prefix}Rule");
+                self.count(
+                    &format!("{set_name}Count"),
+                    table.chained_seq_rule_set_count(),
+                );
+                for (i, set) in table.chained_seq_rule_sets().iter().enumerate() {
+                    let Some(set) = set.transpose()? else {
+                        self.empty(&set_name, i);
+                        continue;
+                    };
+                    self.begin_table(&set_name, Some(i), None);
+                    self.count(&format!("{rule_name}Count"), set.chained_seq_rule_count());
+                    for (j, rule) in set.chained_seq_rules().iter().enumerate() {
+                        let rule = rule?;
+                        self.begin_table(&rule_name, Some(j), None);
+                        self.count("BacktrackGlyphCount", rule.backtrack_glyph_count());
+                        self.glyph_values("Backtrack", rule.backtrack_sequence());
+                        self.count("InputGlyphCount", rule.input_glyph_count());
+                        self.glyph_values("Input", rule.input_sequence());
+                        self.count("LookAheadGlyphCount", rule.lookahead_glyph_count());
+                        self.glyph_values("LookAhead", rule.lookahead_sequence());
+                        self.count(&format!("{}Count", kind.name()), rule.seq_lookup_count());
+                        self.lookup_records(kind, rule.seq_lookup_records());
+                        self.end(&rule_name);
+                    }
+                    self.end(&set_name);
+                }
+            }
+            ChainedSequenceContext::Format2(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.class_def("BacktrackClassDef", &table.backtrack_class_def()?);
+                self.class_def("InputClassDef", &table.input_class_def()?);
+                self.class_def("LookAheadClassDef", &table.lookahead_class_def()?);
+                let set_name = format!("Chain{prefix}ClassSet");
+                let rule_name = format!("Chain{prefix}ClassRule");
+                self.count(
+                    &format!("{set_name}Count"),
+                    table.chained_class_seq_rule_set_count(),
+                );
+                for (i, set) in table.chained_class_seq_rule_sets().iter().enumerate() {
+                    let Some(set) = set.transpose()? else {
+                        self.empty(&set_name, i);
+                        continue;
+                    };
+                    self.begin_table(&set_name, Some(i), None);
+                    self.count(
+                        &format!("{rule_name}Count"),
+                        set.chained_class_seq_rule_count(),
+                    );
+                    for (j, rule) in set.chained_class_seq_rules().iter().enumerate() {
+                        let rule = rule?;
+                        self.begin_table(&rule_name, Some(j), None);
+                        self.count("BacktrackGlyphCount", rule.backtrack_glyph_count());
+                        self.class_values("Backtrack", rule.backtrack_sequence());
+                        self.count("InputGlyphCount", rule.input_glyph_count());
+                        self.class_values("Input", rule.input_sequence());
+                        self.count("LookAheadGlyphCount", rule.lookahead_glyph_count());
+                        self.class_values("LookAhead", rule.lookahead_sequence());
+                        self.count(&format!("{}Count", kind.name()), rule.seq_lookup_count());
+                        self.lookup_records(kind, rule.seq_lookup_records());
+                        self.end(&rule_name);
+                    }
+                    self.end(&set_name);
+                }
+            }
+            ChainedSequenceContext::Format3(table) => {
+                self.count("BacktrackGlyphCount", table.backtrack_glyph_count());
+                for (i, coverage) in table.backtrack_coverages().iter().enumerate() {
+                    self.coverage("BacktrackCoverage", Some(i), &coverage?);
+                }
+                self.count("InputGlyphCount", table.input_glyph_count());
+                for (i, coverage) in table.input_coverages().iter().enumerate() {
+                    self.coverage("InputCoverage", Some(i), &coverage?);
+                }
+                self.count("LookAheadGlyphCount", table.lookahead_glyph_count());
+                for (i, coverage) in table.lookahead_coverages().iter().enumerate() {
+                    self.coverage("LookAheadCoverage", Some(i), &coverage?);
+                }
+                self.count(&format!("{}Count", kind.name()), table.seq_lookup_count());
+                self.lookup_records(kind, table.seq_lookup_records());
+            }
+        }
+        self.end(&name);
+        Ok(())
+    }
+
+    fn glyph_values(&mut self, name: &str, glyphs: &[BigEndian<GlyphId16>]) {
+        for (i, gid) in glyphs.iter().enumerate() {
+            let glyph = self.glyph(gid.get());
+            self.indexed_value(name, i, glyph);
+        }
+    }
+
+    fn class_values(&mut self, name: &str, classes: &[BigEndian<u16>]) {
+        for (i, class) in classes.iter().enumerate() {
+            self.indexed_value(name, i, class.get());
+        }
+    }
+
+    fn lookup_records(&mut self, kind: Kind, records: &[SequenceLookupRecord]) {
+        let name = format!("{}LookupRecord", kind.name());
+        for (i, record) in records.iter().enumerate() {
+            self.begin_table(&name, Some(i), None);
+            self.value("SequenceIndex", record.sequence_index());
+            self.value("LookupListIndex", record.lookup_list_index());
+            self.end(&name);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Dumping GPOS lookups.
+
+use write_fonts::read::{
+    FontData, ReadError,
+    tables::gpos::{
+        AnchorTable, CursivePosFormat1, ExtensionSubtable, MarkArray, MarkBasePosFormat1,
+        MarkLigPosFormat1, MarkMarkPosFormat1, PairPos, PositionLookup, PositionLookupList,
+        SinglePos, ValueFormat, ValueRecord,
+    },
+};
+
+use super::{TtxWriter, contextual::Kind};
+
+impl TtxWriter<'_> {
+    pub(super) fn gpos_lookup_list(
+        &mut self,
+        lookup_list: &PositionLookupList,
+    ) -> Result<(), ReadError> {
+        self.begin("LookupList", &[]);
+        self.count("LookupCount", lookup_list.lookup_count());
+        for (i, lookup) in lookup_list.lookups().iter().enumerate() {
+            self.begin_table("Lookup", Some(i), None);
+            match lookup? {
+                PositionLookup::Single(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.single_pos(i, &sub))
+                }
+                PositionLookup::Pair(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.pair_pos(i, &sub))
+                }
+                PositionLookup::Cursive(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.cursive_pos(i, &sub))
+                }
+                PositionLookup::MarkToBase(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.mark_base_pos(i, &sub))
+                }
+                PositionLookup::MarkToLig(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.mark_lig_pos(i, &sub))
+                }
+                PositionLookup::MarkToMark(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.mark_mark_pos(i, &sub))
+                }
+                PositionLookup::Contextual(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.context(Kind::Pos, i, &sub))
+                }
+                PositionLookup::ChainContextual(lookup) => self.lookup(&lookup, |this, i, sub| {
+                    this.chain_context(Kind::Pos, i, &sub)
+                }),
+                PositionLookup::Extension(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.extension_pos(i, &sub))
+                }
+            }?;
+            self.end("Lookup");
+        }
+        self.end("LookupList");
+        Ok(())
+    }
+
+    fn extension_pos(
+        &mut self,
+        index: Option<usize>,
+        subtable: &ExtensionSubtable,
+    ) -> Result<(), ReadError> {
+        self.begin_table("ExtensionPos", index, Some(1));
+        match subtable {
+            ExtensionSubtable::Single(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.single_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Pair(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.pair_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Cursive(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.cursive_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::MarkToBase(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.mark_base_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::MarkToLig(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.mark_lig_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::MarkToMark(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.mark_mark_pos(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Contextual(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.context(Kind::Pos, None, &ext.extension()?)
+            }
+            ExtensionSubtable::ChainContextual(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.chain_context(Kind::Pos, None, &ext.extension()?)
+            }
+        }?;
+        self.end("ExtensionPos");
+        Ok(())
+    }
+
+    fn single_pos(&mut self, index: Option<usize>, subtable: &SinglePos) -> Result<(), ReadError> {
+        self.begin_table("SinglePos", index, Some(subtable.pos_format()));
+        match subtable {
+            SinglePos::Format1(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.value("ValueFormat", table.value_format().bits());
+                let data = table.offset_data();
+                self.value_record("Value", None, &table.value_record(), data)?;
+            }
+            SinglePos::Format2(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.value("ValueFormat", table.value_format().bits());
+                self.count("ValueCount", table.value_count());
+                let data = table.offset_data();
+                for (i, record) in table.value_records().iter().enumerate() {
+                    self.value_record("Value", Some(i), &record?, data)?;
+                }
+            }
+        }
+        self.end("SinglePos");
+        Ok(())
+    }
+
+    fn pair_pos(&mut self, index: Option<usize>, subtable: &PairPos) -> Result<(), ReadError> {
+        self.begin_table("PairPos", index, Some(subtable.pos_format()));
+        match subtable {
+            PairPos::Format1(table) => {
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.value("ValueFormat1", table.value_format1().bits());
+                self.value("ValueFormat2", table.value_format2().bits());
+                self.count("PairSetCount", table.pair_set_count());
+                for (i, pair_set) in table.pair_sets().iter().enumerate() {
+                    let pair_set = pair_set?;
+                    let data = pair_set.offset_data();
+                    self.begin_table("PairSet", Some(i), None);
+                    self.count("PairValueCount", pair_set.pair_value_count());
+                    for (j, record) in pair_set.pair_value_records().iter().enumerate() {
+                        let record = record?;
+                        self.begin_table("PairValueRecord", Some(j), None);
+                        let second = self.glyph(record.second_glyph());
+                        self.value("SecondGlyph", second);
+                        self.value_record("Value1", None, record.value_record1(), data)?;
+                        self.value_record("Value2", None, record.value_record2(), data)?;
+                        self.end("PairValueRecord");
+                    }
+                    self.end("PairSet");
+                }
+            }
+            PairPos::Format2(table) => {
+                let data = table.offset_data();
+                self.coverage("Coverage", None, &table.coverage()?);
+                self.value("ValueFormat1", table.value_format1().bits());
+                self.value("ValueFormat2", table.value_format2().bits());
+                self.class_def("ClassDef1", &table.class_def1()?);
+                self.class_def("ClassDef2", &table.class_def2()?);
+                self.count("Class1Count", table.class1_count());
+                self.count("Class2Count", table.class2_count());
+                for (i, class1) in table.class1_records().iter().enumerate() {
+                    let class1 = class1?;
+                    self.begin_table("Class1Record", Some(i), None);
+                    for (j, class2) in class1.class2_records().iter().enumerate() {
+                        let class2 = class2?;
+                        self.begin_table("Class2Record", Some(j), None);
+                        self.value_record("Value1", None, class2.value_record1(), data)?;
+                        self.value_record("Value2", None, class2.value_record2(), data)?;
+                        self.end("Class2Record");
+                    }
+                    self.end("Class1Record");
+                }
+            }
+        }
+        self.end("PairPos");
+        Ok(())
+    }
+
+    fn cursive_pos(
+        &mut self,
+        index: Option<usize>,
+        subtable: &CursivePosFormat1,
+    ) -> Result<(), ReadError> {
+        let data = subtable.offset_data();
+        self.begin_table("CursivePos", index, Some(subtable.pos_format()));
+        self.coverage("Coverage", None, &subtable.coverage()?);
+        self.count("EntryExitCount", subtable.entry_exit_count());
+        for (i, record) in subtable.entry_exit_record().iter().enumerate() {
+            self.begin_table("EntryExitRecord", Some(i), None);
+            if let Some(anchor) = record.entry_anchor(data).transpose()? {
+                self.anchor("EntryAnchor", None, &anchor)?;
+            }
+            if let Some(anchor) = record.exit_anchor(data).transpose()? {
+                self.anchor("ExitAnchor", None, &anchor)?;
+            }
+            self.end("EntryExitRecord");
+        }
+        self.end("CursivePos");
+        Ok(())
+    }
+
+    fn mark_base_pos(
+        &mut self,
+        index: Option<usize>,
+        subtable: &MarkBasePosFormat1,
+    ) -> Result<(), ReadError> {
+        self.begin_table("MarkBasePos", index, Some(subtable.pos_format()));
+        self.coverage("MarkCoverage", None, &subtable.mark_coverage()?);
+        self.coverage("BaseCoverage", None, &subtable.base_coverage()?);
+        self.count("ClassCount", subtable.mark_class_count());
+        self.mark_array("MarkArray", &subtable.mark_array()?)?;
+        let base_array = subtable.base_array()?;
+        let data = base_array.offset_data();
+        self.begin("BaseArray", &[]);
+        self.count("BaseCount", base_array.base_count());
+        for (i, record) in base_array.base_records().iter().enumerate() {
+            let record = record?;
+            self.begin_table("BaseRecord", Some(i), None);
+            for (j, anchor) in record.base_anchors(data).iter().enumerate() {
+                self.optional_anchor("BaseAnchor", j, anchor.transpose()?)?;
+            }
+            self.end("BaseRecord");
+        }
+        self.end("BaseArray");
+        self.end("MarkBasePos");
+        Ok(())
+    }
+
+    fn mark_lig_pos(
+        &mut self,
+        index: Option<usize>,
+        subtable: &MarkLigPosFormat1,
+    ) -> Result<(), ReadError> {
+        self.begin_table("MarkLigPos", index, Some(subtable.pos_format()));
+        self.coverage("MarkCoverage", None, &subtable.mark_coverage()?);
+        self.coverage("LigatureCoverage", None, &subtable.ligature_coverage()?);
+        self.count("ClassCount", subtable.mark_class_count());
+        self.mark_array("MarkArray", &subtable.mark_array()?)?;
+        let ligature_array = subtable.ligature_array()?;
+        self.begin("LigatureArray", &[]);
+        self.count("LigatureCount", ligature_array.ligature_count());
+        for (i, attach) in ligature_array.ligature_attaches().iter().enumerate() {
+            let attach = attach?;
+            let data = attach.offset_data();
+            self.begin_table("LigatureAttach", Some(i), None);
+            self.count("ComponentCount", attach.component_count());
+            for (j, record) in attach.component_records().iter().enumerate() {
+                let record = record?;
+                self.begin_table("ComponentRecord", Some(j), None);
+                for (k, anchor) in record.ligature_anchors(data).iter().enumerate() {
+                    self.optional_anchor("LigatureAnchor", k, anchor.transpose()?)?;
+                }
+                self.end("ComponentRecord");
+            }
+            self.end("LigatureAttach");
+        }
+        self.end("LigatureArray");
+        self.end("MarkLigPos");
+        Ok(())
+    }
+
+    fn mark_mark_pos(
+        &mut self,
+        index: Option<usize>,
+        subtable: &MarkMarkPosFormat1,
+    ) -> Result<(), ReadError> {
+        self.begin_table("MarkMarkPos", index, Some(subtable.pos_format()));
+        self.coverage("Mark1Coverage", None, &subtable.mark1_coverage()?);
+        self.coverage("Mark2Coverage", None, &subtable.mark2_coverage()?);
+        self.count("ClassCount", subtable.mark_class_count());
+        self.mark_array("Mark1Array", &subtable.mark1_array()?)?;
+        let mark2_array = subtable.mark2_array()?;
+        let data = mark2_array.offset_data();
+        self.begin("Mark2Array", &[]);
+        self.count("Mark2Count", mark2_array.mark2_count());
+        for (i, record) in mark2_array.mark2_records().iter().enumerate() {
+            let record = record?;
+            self.begin_table("Mark2Record", Some(i), None);
+            for (j, anchor) in record.mark2_anchors(data).iter().enumerate() {
+                self.optional_anchor("Mark2Anchor", j, anchor.transpose()?)?;
+            }
+            self.end("Mark2Record");
+        }
+        self.end("Mark2Array");
+        self.end("MarkMarkPos");
+        Ok(())
+    }
+
+    fn mark_array(&mut self, name: &str, mark_array: &MarkArray) -> Result<(), ReadError> {
+        let data = mark_array.offset_data();
+        self.begin(name, &[]);
+        self.count("MarkCount", mark_array.mark_count());
+        for (i, record) in mark_array.mark_records().iter().enumerate() {
+            self.begin_table("MarkRecord", Some(i), None);
+            self.value("Class", record.mark_class());
+            self.anchor("MarkAnchor", None, &record.mark_anchor(data)?)?;
+            self.end("MarkRecord");
+        }
+        self.end(name);
+        Ok(())
+    }
+
+    /// An anchor in an array of nullable offsets.
+    fn optional_anchor(
+        &mut self,
+        name: &str,
+        index: usize,
+        anchor: Option<AnchorTable>,
+    ) -> Result<(), ReadError> {
+        match anchor {
+            Some(anchor) => self.anchor(name, Some(index), &anchor),
+            None => {
+                self.empty(name, index);
+                Ok(())
+            }
+        }
+    }
+
+    fn anchor(
+        &mut self,
+        name: &str,
+        index: Option<usize>,
+        anchor: &AnchorTable,
+    ) -> Result<(), ReadError> {
+        self.begin_table(name, index, Some(anchor.anchor_format()));
+        self.value("XCoordinate", anchor.x_coordinate());
+        self.value("YCoordinate", anchor.y_coordinate());
+        match anchor {
+            AnchorTable::Format1(_) => (),
+            AnchorTable::Format2(anchor) => self.value("AnchorPoint", anchor.anchor_point()),
+            AnchorTable::Format3(anchor) => {
+                if let Some(device) = anchor.x_device().transpose()? {
+                    self.device("XDeviceTable", &device);
+                }
+                if let Some(device) = anchor.y_device().transpose()? {
+                    self.device("YDeviceTable", &device);
+                }
+            }
+        }
+        self.end(name);
+        Ok(())
+    }
+
+    /// A value record; `data` is the table that device offsets are relative to.
+    ///
+    /// As in fontTools, nothing is written for a record with an empty format.
+    fn value_record(
+        &mut self,
+        name: &str,
+        index: Option<usize>,
+        record: &ValueRecord,
+        data: FontData,
+    ) -> Result<(), ReadError> {
+        if record.format == ValueFormat::empty() {
+            return Ok(());
+        }
+        let mut attrs = Vec::new();
+        for (attr, value) in [
+            ("XPlacement", record.x_placement()),
+            ("YPlacement", record.y_placement()),
+            ("XAdvance", record.x_advance()),
+            ("YAdvance", record.y_advance()),
+        ] {
+            if let Some(value) = value {
+                attrs.push((attr, value));
+            }
+        }
+        let mut devices = Vec::new();
+        for (device_name, device) in [
+            ("XPlaDevice", record.x_placement_device(data)),
+            ("YPlaDevice", record.y_placement_device(data)),
+            ("XAdvDevice", record.x_advance_device(data)),
+            ("YAdvDevice", record.y_advance_device(data)),
+        ] {
+            if let Some(device) = device.transpose()? {
+                devices.push((device_name, device));
+            }
+        }
+        let mut all_attrs = Vec::new();
+        if let Some(index) = &index {
+            all_attrs.push(("index", index as &dyn std::fmt::Display));
+        }
+        all_attrs.extend(
+            attrs
+                .iter()
+                .map(|(attr, value)| (*attr, value as &dyn std::fmt::Display)),
+        );
+        if devices.is_empty() {
+            self.simple(name, &all_attrs);
+        } else {
+            self.begin(name, &all_attrs);
+            for (device_name, device) in devices {
+                self.device(device_name, &device);
+            }
+            self.end(name);
+        }
+        Ok(())
+    }
+}
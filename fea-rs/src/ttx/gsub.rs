@@ -0,0 +1,248 @@
+//! Dumping GSUB lookups.
+
+use write_fonts::read::{
+    ReadError,
+    tables::gsub::{
+        AlternateSubstFormat1, ExtensionSubtable, LigatureSubstFormat1, MultipleSubstFormat1,
+        ReverseChainSingleSubstFormat1, SingleSubst, SubstitutionLookup, SubstitutionLookupList,
+    },
+};
+
+use super::{TtxWriter, contextual::Kind};
+
+impl TtxWriter<'_> {
+    pub(super) fn gsub_lookup_list(
+        &mut self,
+        lookup_list: &SubstitutionLookupList,
+    ) -> Result<(), ReadError> {
+        self.begin("LookupList", &[]);
+        self.count("LookupCount", lookup_list.lookup_count());
+        for (i, lookup) in lookup_list.lookups().iter().enumerate() {
+            self.begin_table("Lookup", Some(i), None);
+            match lookup? {
+                SubstitutionLookup::Single(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.single_subst(i, &sub))
+                }
+                SubstitutionLookup::Multiple(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.multiple_subst(i, &sub))
+                }
+                SubstitutionLookup::Alternate(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.alternate_subst(i, &sub))
+                }
+                SubstitutionLookup::Ligature(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.ligature_subst(i, &sub))
+                }
+                SubstitutionLookup::Contextual(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.context(Kind::Subst, i, &sub))
+                }
+                SubstitutionLookup::ChainContextual(lookup) => self
+                    .lookup(&lookup, |this, i, sub| {
+                        this.chain_context(Kind::Subst, i, &sub)
+                    }),
+                SubstitutionLookup::Extension(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.extension_subst(i, &sub))
+                }
+                SubstitutionLookup::Reverse(lookup) => {
+                    self.lookup(&lookup, |this, i, sub| this.reverse_subst(i, &sub))
+                }
+            }?;
+            self.end("Lookup");
+        }
+        self.end("LookupList");
+        Ok(())
+    }
+
+    fn extension_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &ExtensionSubtable,
+    ) -> Result<(), ReadError> {
+        self.begin_table("ExtensionSubst", index, Some(1));
+        match subtable {
+            ExtensionSubtable::Single(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.single_subst(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Multiple(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.multiple_subst(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Alternate(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.alternate_subst(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Ligature(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.ligature_subst(None, &ext.extension()?)
+            }
+            ExtensionSubtable::Contextual(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.context(Kind::Subst, None, &ext.extension()?)
+            }
+            ExtensionSubtable::ChainContextual(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.chain_context(Kind::Subst, None, &ext.extension()?)
+            }
+            ExtensionSubtable::Reverse(ext) => {
+                self.value("ExtensionLookupType", ext.extension_lookup_type());
+                self.reverse_subst(None, &ext.extension()?)
+            }
+        }?;
+        self.end("ExtensionSubst");
+        Ok(())
+    }
+
+    fn single_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &SingleSubst,
+    ) -> Result<(), ReadError> {
+        let mut pairs = match subtable {
+            SingleSubst::Format1(table) => {
+                let delta = table.delta_glyph_id() as i32;
+                table
+                    .coverage()?
+                    .iter()
+                    .map(|gid| {
+                        let target = (gid.to_u16() as i32 + delta).rem_euclid(0x10000);
+                        (self.glyph(gid), self.glyph((target as u16).into()))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            SingleSubst::Format2(table) => table
+                .coverage()?
+                .iter()
+                .zip(table.substitute_glyph_ids())
+                .map(|(gid, target)| (self.glyph(gid), self.glyph(target.get())))
+                .collect(),
+        };
+        pairs.sort();
+        self.begin_table("SingleSubst", index, None);
+        for (input, output) in pairs {
+            self.simple("Substitution", &[("in", &input), ("out", &output)]);
+        }
+        self.end("SingleSubst");
+        Ok(())
+    }
+
+    fn multiple_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &MultipleSubstFormat1,
+    ) -> Result<(), ReadError> {
+        let mut items = Vec::new();
+        for (gid, sequence) in subtable.coverage()?.iter().zip(subtable.sequences().iter()) {
+            let targets = sequence?
+                .substitute_glyph_ids()
+                .iter()
+                .map(|gid| self.glyph(gid.get()))
+                .collect::<Vec<_>>();
+            items.push((self.glyph(gid), targets.join(",")));
+        }
+        items.sort();
+        self.begin_table("MultipleSubst", index, None);
+        for (input, output) in items {
+            self.simple("Substitution", &[("in", &input), ("out", &output)]);
+        }
+        self.end("MultipleSubst");
+        Ok(())
+    }
+
+    fn alternate_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &AlternateSubstFormat1,
+    ) -> Result<(), ReadError> {
+        let mut items = Vec::new();
+        for (gid, set) in subtable
+            .coverage()?
+            .iter()
+            .zip(subtable.alternate_sets().iter())
+        {
+            let alternates = set?
+                .alternate_glyph_ids()
+                .iter()
+                .map(|gid| self.glyph(gid.get()))
+                .collect::<Vec<_>>();
+            items.push((self.glyph(gid), alternates));
+        }
+        items.sort();
+        self.begin_table("AlternateSubst", index, None);
+        for (glyph, alternates) in items {
+            self.begin("AlternateSet", &[("glyph", &glyph)]);
+            for alternate in alternates {
+                self.simple("Alternate", &[("glyph", &alternate)]);
+            }
+            self.end("AlternateSet");
+        }
+        self.end("AlternateSubst");
+        Ok(())
+    }
+
+    fn ligature_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &LigatureSubstFormat1,
+    ) -> Result<(), ReadError> {
+        let mut items = Vec::new();
+        for (gid, set) in subtable
+            .coverage()?
+            .iter()
+            .zip(subtable.ligature_sets().iter())
+        {
+            let mut ligatures = Vec::new();
+            for ligature in set?.ligatures().iter() {
+                let ligature = ligature?;
+                let components = ligature
+                    .component_glyph_ids()
+                    .iter()
+                    .map(|gid| self.glyph(gid.get()))
+                    .collect::<Vec<_>>();
+                ligatures.push((components.join(","), self.glyph(ligature.ligature_glyph())));
+            }
+            items.push((self.glyph(gid), ligatures));
+        }
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        self.begin_table("LigatureSubst", index, None);
+        for (glyph, ligatures) in items {
+            self.begin("LigatureSet", &[("glyph", &glyph)]);
+            for (components, ligature) in ligatures {
+                self.simple(
+                    "Ligature",
+                    &[("components", &components), ("glyph", &ligature)],
+                );
+            }
+            self.end("LigatureSet");
+        }
+        self.end("LigatureSubst");
+        Ok(())
+    }
+
+    fn reverse_subst(
+        &mut self,
+        index: Option<usize>,
+        subtable: &ReverseChainSingleSubstFormat1,
+    ) -> Result<(), ReadError> {
+        self.begin_table(
+            "ReverseChainSingleSubst",
+            index,
+            Some(subtable.subst_format()),
+        );
+        self.coverage("Coverage", None, &subtable.coverage()?);
+        self.count("BacktrackGlyphCount", subtable.backtrack_glyph_count());
+        for (i, coverage) in subtable.backtrack_coverages().iter().enumerate() {
+            self.coverage("BacktrackCoverage", Some(i), &coverage?);
+        }
+        self.count("LookAheadGlyphCount", subtable.lookahead_glyph_count());
+        for (i, coverage) in subtable.lookahead_coverages().iter().enumerate() {
+            self.coverage("LookAheadCoverage", Some(i), &coverage?);
+        }
+        self.count("GlyphCount", subtable.glyph_count());
+        for (i, gid) in subtable.substitute_glyph_ids().iter().enumerate() {
+            let glyph = self.glyph(gid.get());
+            self.indexed_value("Substitute", i, glyph);
+        }
+        self.end("ReverseChainSingleSubst");
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Dumping the STAT table.
+
+use std::fmt::Write;
+
+use write_fonts::{
+    read::{
+        ReadError,
+        tables::stat::{AxisValue, AxisValueTableFlags, Stat},
+    },
+    types::Fixed,
+};
+
+use super::{TtxWriter, fixed};
+
+impl TtxWriter<'_> {
+    pub(super) fn stat(&mut self, stat: &Stat) -> Result<(), ReadError> {
+        self.begin("STAT", &[]);
+        self.version(stat.version());
+        self.value("DesignAxisRecordSize", stat.design_axis_size());
+        self.count("DesignAxisCount", stat.design_axis_count());
+        if stat.design_axis_count() > 0 {
+            self.begin("DesignAxisRecord", &[]);
+            for (i, axis) in stat.design_axes()?.iter().enumerate() {
+                self.begin_table("Axis", Some(i), None);
+                self.value("AxisTag", axis.axis_tag());
+                self.name_id("AxisNameID", axis.axis_name_id());
+                self.value("AxisOrdering", axis.axis_ordering());
+                self.end("Axis");
+            }
+            self.end("DesignAxisRecord");
+        }
+        self.count("AxisValueCount", stat.axis_value_count());
+        if let Some(values) = stat.offset_to_axis_values().transpose()? {
+            self.begin("AxisValueArray", &[]);
+            for (i, value) in values.axis_values().iter().enumerate() {
+                self.axis_value(i, &value?);
+            }
+            self.end("AxisValueArray");
+        }
+        if let Some(name_id) = stat.elided_fallback_name_id() {
+            self.name_id("ElidedFallbackNameID", name_id);
+        }
+        self.end("STAT");
+        Ok(())
+    }
+
+    fn axis_value(&mut self, index: usize, value: &AxisValue) {
+        match value {
+            AxisValue::Format1(value) => {
+                self.begin_table("AxisValue", Some(index), Some(1));
+                self.value("AxisIndex", value.axis_index());
+                self.axis_value_flags(value.flags());
+                self.name_id("ValueNameID", value.value_name_id());
+                self.fixed_value("Value", value.value());
+            }
+            AxisValue::Format2(value) => {
+                self.begin_table("AxisValue", Some(index), Some(2));
+                self.value("AxisIndex", value.axis_index());
+                self.axis_value_flags(value.flags());
+                self.name_id("ValueNameID", value.value_name_id());
+                self.fixed_value("NominalValue", value.nominal_value());
+                self.fixed_value("RangeMinValue", value.range_min_value());
+                self.fixed_value("RangeMaxValue", value.range_max_value());
+            }
+            AxisValue::Format3(value) => {
+                self.begin_table("AxisValue", Some(index), Some(3));
+                self.value("AxisIndex", value.axis_index());
+                self.axis_value_flags(value.flags());
+                self.name_id("ValueNameID", value.value_name_id());
+                self.fixed_value("Value", value.value());
+                self.fixed_value("LinkedValue", value.linked_value());
+            }
+            AxisValue::Format4(value) => {
+                self.begin_table("AxisValue", Some(index), Some(4));
+                self.count("AxisCount", value.axis_count());
+                self.axis_value_flags(value.flags());
+                self.name_id("ValueNameID", value.value_name_id());
+                for (i, record) in value.axis_values().iter().enumerate() {
+                    self.begin_table("AxisValueRecord", Some(i), None);
+                    self.value("AxisIndex", record.axis_index());
+                    self.fixed_value("Value", record.value());
+                    self.end("AxisValueRecord");
+                }
+            }
+        }
+        self.end("AxisValue");
+    }
+
+    /// The flags, followed by a comment naming the bits that are set.
+    fn axis_value_flags(&mut self, flags: AxisValueTableFlags) {
+        let flags = flags.bits();
+        self.write_tag("Flags", &[("value", &flags)]);
+        self.out.push_str("/>");
+        let names = (0..16)
+            .filter(|bit| flags & (1 << bit) != 0)
+            .map(|bit| match bit {
+                0 => "OlderSiblingFontAttribute".to_string(),
+                1 => "ElidableAxisValueName".to_string(),
+                _ => format!("bit{bit}"),
+            })
+            .collect::<Vec<_>>();
+        if !names.is_empty() {
+            write!(self.out, "  <!-- {} -->", names.join(" ")).unwrap();
+        }
+        self.out.push('\n');
+    }
+
+    fn fixed_value(&mut self, name: &str, value: Fixed) {
+        self.value(name, fixed(value));
+    }
+}
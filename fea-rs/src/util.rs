@@ -6,6 +6,8 @@ pub mod paths;
 pub mod pretty_diff;
 #[cfg(any(test, feature = "test"))]
 pub mod ttx;
+#[cfg(any(test, feature = "test"))]
+pub mod ttx_diff;
 
 pub use highlighting::style_for_kind;
 #[cfg(any(test, feature = "diff"))]
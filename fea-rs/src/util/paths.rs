@@ -38,8 +38,11 @@ fn rebase_path(path: &Path, base: &Path) -> PathBuf {
                     Some(Component::Normal(_)) => (),
                     // push a pardir
                     Some(Component::CurDir) | None => components.push(Component::ParentDir),
-                    // no absolute paths make it this far
-                    _ => unreachable!(),
+                    // an include dir may be absolute, and the parent of the
+                    // root is the root
+                    Some(root @ (Component::RootDir | Component::Prefix(_))) => {
+                        components.push(root)
+                    }
                 }
             }
             Component::Normal(_) => components.push(component),
@@ -56,10 +59,16 @@ fn rebase_path(path: &Path, base: &Path) -> PathBuf {
 /// Given a relative path, resolve it to a specific path per [the spec][].
 ///
 /// The second argument is the root of the project, and the third argument is the
-/// path to the *including* file, if one exists.
+/// path to the *including* file, if one exists. If the path is not found relative
+/// to either of these, each of the `include_dirs` is tried in order.
 ///
 /// [the spec]: http://adobe-type-tools.github.io/afdko/OpenTypeFeatureFileSpecification.html#3-including-files
-pub(crate) fn resolve_path(path: &Path, root: &Path, parent: Option<&Path>) -> PathBuf {
+pub(crate) fn resolve_path(
+    path: &Path,
+    root: &Path,
+    parent: Option<&Path>,
+    include_dirs: &[PathBuf],
+) -> PathBuf {
     if path.is_absolute() {
         log::info!("path {} is absolute", path.display());
         return path.to_path_buf();
@@ -73,6 +82,9 @@ pub(crate) fn resolve_path(path: &Path, root: &Path, parent: Option<&Path>) -> P
     {
         return rebase_path(path, parent);
     }
+    if let Some(dir) = include_dirs.iter().find(|dir| dir.join(path).exists()) {
+        return rebase_path(path, dir);
+    }
     path.to_owned()
 }
 
@@ -113,4 +125,26 @@ mod tests {
             Path::new("font/includes/features.fea")
         );
     }
+
+    #[test]
+    fn resolve_path_in_include_dir() {
+        let dir = std::env::temp_dir().join("fea_rs_test_resolve_path");
+        let include_dir = dir.join("includes");
+        std::fs::create_dir_all(include_dir.join("shared")).unwrap();
+        std::fs::write(include_dir.join("shared/common.fea"), "").unwrap();
+
+        let path = Path::new("./shared/../shared/common.fea");
+        let resolved = resolve_path(
+            path,
+            &dir.join("root"),
+            None,
+            std::slice::from_ref(&include_dir),
+        );
+        assert_eq!(resolved, include_dir.join("shared/common.fea"));
+
+        // not found anywhere
+        let path = Path::new("missing.fea");
+        let resolved = resolve_path(path, &dir.join("root"), None, &[include_dir]);
+        assert_eq!(resolved, path);
+    }
 }
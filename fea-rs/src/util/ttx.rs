@@ -27,7 +27,10 @@ use write_fonts::{
     types::{GlyphId16, Tag},
 };
 
-use super::FEA_FILTER_TESTS;
+use super::{
+    FEA_FILTER_TESTS,
+    ttx_diff::{self, TtxDifference},
+};
 
 static IGNORED_TESTS: &[&str] = &[
     // ## tests with unofficial syntax extensiosn we haven't implemented yet ## //
@@ -116,6 +119,11 @@ pub enum TestResult {
     /// the actual diff.
     #[allow(missing_docs)]
     ExpectedDiffFail { expected: String, result: String },
+    /// Compiling the same input more than once produced different output
+    #[allow(missing_docs)]
+    Nondeterministic { run: usize },
+    /// The layout tables differed structurally from the reference TTX
+    TableDiffFail(Vec<TtxDifference>),
 }
 
 struct ReasonPrinter<'a> {
@@ -174,6 +182,28 @@ impl Filter {
 /// `filter` is an optional comma-separated list of strings. If present, only
 /// tests which contain one of the strings in the list will be run.
 pub fn run_fonttools_tests(filter: Option<String>) -> Report {
+    run_fonttools_tests_impl(filter, run_test)
+}
+
+/// Run the fonttools tests without using the `ttx` executable.
+///
+/// Instead of converting our output with `ttx`, this uses [`compare_to_fealib`]
+/// to compare our layout tables with the expected output.
+/// Tests with a saved 'expected diff' are skipped, since that diff is against
+/// the complete `ttx` output.
+pub fn run_fonttools_tests_builtin(filter: Option<String>) -> Report {
+    run_fonttools_tests_impl(filter, |path, glyph_map, var_info| {
+        if path.with_extension("expected_diff").exists() {
+            return Ok(path);
+        }
+        run_builtin_test(path, glyph_map, var_info)
+    })
+}
+
+fn run_fonttools_tests_impl(
+    filter: Option<String>,
+    run: impl Fn(PathBuf, &GlyphMap, &MockVariationInfo) -> Result<PathBuf, TestCase> + Sync,
+) -> Report {
     let fonttools_data_dir = test_data_dir().join("fonttools-tests");
     let glyph_map = fonttools_test_glyph_order();
     let filter = Filter::new(filter);
@@ -185,7 +215,7 @@ pub fn run_fonttools_tests(filter: Option<String>) -> Report {
                 && !IGNORED_TESTS.contains(&test.file_name().unwrap().to_str().unwrap())
         })
         .par_bridge()
-        .map(|path| run_test(path, &glyph_map, &var_info))
+        .map(|path| run(path, &glyph_map, &var_info))
         .collect::<Vec<_>>();
 
     finalize_results(result)
@@ -272,27 +302,105 @@ pub(crate) fn run_test(
     fvar: &MockVariationInfo,
 ) -> Result<PathBuf, TestCase> {
     let run_result = std::panic::catch_unwind(|| {
-        let mut compiler: Compiler<'_, TestFeatureProvider, MockVariationInfo> =
-            Compiler::new(path.clone(), glyph_map)
-                .print_warnings(std::env::var(super::VERBOSE).is_ok())
-                .with_opts(Opts::new().make_post_table(true));
-        if is_variable(&path) {
-            compiler = compiler.with_variable_info(fvar);
+        compile_test_case(&path, glyph_map, fvar).and_then(|result| compare_ttx(&result, &path))
+    });
+
+    match run_result {
+        Err(_) => Err(TestResult::Panic),
+        Ok(Err(reason)) => Err(reason),
+        Ok(Ok(_)) => return Ok(path),
+    }
+    .map_err(|reason| TestCase { reason, path })
+}
+
+/// Run the test case at the provided path, comparing with the expected output
+/// in the sibling '.ttx' file without using the `ttx` executable.
+///
+/// See [`compare_to_fealib`] for details.
+pub fn run_builtin_test(
+    path: PathBuf,
+    glyph_map: &GlyphMap,
+    fvar: &MockVariationInfo,
+) -> Result<PathBuf, TestCase> {
+    let run_result = std::panic::catch_unwind(|| {
+        let fvar = is_variable(&path).then_some(fvar);
+        compare_to_fealib(&path, &path.with_extension("ttx"), glyph_map, fvar)
+    });
+
+    match run_result {
+        Err(_) => Err(TestResult::Panic),
+        Ok(Err(reason)) => Err(reason),
+        Ok(Ok(diffs)) if diffs.is_empty() => return Ok(path),
+        Ok(Ok(diffs)) => Err(TestResult::TableDiffFail(diffs)),
+    }
+    .map_err(|reason| TestCase { reason, path })
+}
+
+/// Compile a FEA file and compare the result with a reference TTX file
+/// produced by feaLib.
+///
+/// Only the BASE, GDEF, GSUB, GPOS and STAT tables are compared. The
+/// differences are structural, each reported with its location in the table
+/// (such as `GSUB/LookupList/Lookup[2]`), and an empty list means the tables
+/// match.
+///
+/// The reference can be generated by compiling the FEA with fontTools and
+/// running `ttx -t BASE -t GDEF -t GSUB -t GPOS -t STAT` on the result.
+pub fn compare_to_fealib(
+    fea_path: &Path,
+    reference: &Path,
+    glyph_map: &GlyphMap,
+    fvar: Option<&MockVariationInfo>,
+) -> Result<Vec<TtxDifference>, TestResult> {
+    let mut compiler: Compiler<'_, TestFeatureProvider, MockVariationInfo> =
+        Compiler::new(fea_path, glyph_map).print_warnings(std::env::var(super::VERBOSE).is_ok());
+    if let Some(fvar) = fvar {
+        compiler = compiler.with_variable_info(fvar);
+    }
+    if needs_feature_provider(fea_path) {
+        compiler = compiler.with_feature_writer(&TestFeatureProvider)
+    }
+    let compilation = match compiler.compile() {
+        Err(CompilerError::SourceLoad(err)) => panic!("{err}"),
+        Err(CompilerError::WriteFail(err)) => panic!("{err}"),
+        Err(CompilerError::Cancelled(_)) => unreachable!("tests are never cancelled"),
+        Err(CompilerError::ParseFail(errs)) => {
+            return Err(TestResult::ParseFail(errs.to_string(true)));
         }
-        if needs_feature_provider(&path) {
-            compiler = compiler.with_feature_writer(&TestFeatureProvider)
+        Err(CompilerError::ValidationFail(errs) | CompilerError::CompilationFail(errs)) => {
+            return Err(TestResult::CompileFail(errs.to_string(true)));
         }
+        Ok(compilation) => compilation,
+    };
+    let result = compilation
+        .to_ttx(glyph_map)
+        .unwrap_or_else(|err| panic!("failed to write ttx: {err}"));
+    let expected = std::fs::read_to_string(reference)
+        .unwrap_or_else(|err| panic!("failed to read {}: {err}", reference.display()));
+    let diffs = ttx_diff::diff_ttx(&expected, &result)
+        .unwrap_or_else(|err| panic!("failed to parse {}: {err}", reference.display()));
+    Ok(diffs)
+}
 
-        match compiler.compile_binary() {
-            // this means we have a test case that doesn't exist or something weird
-            Err(CompilerError::SourceLoad(err)) => panic!("{err}"),
-            Err(CompilerError::WriteFail(err)) => panic!("{err}"),
-            Err(CompilerError::ParseFail(errs)) => Err(TestResult::ParseFail(errs.to_string(true))),
-            Err(CompilerError::ValidationFail(errs) | CompilerError::CompilationFail(errs)) => {
-                Err(TestResult::CompileFail(errs.to_string(true)))
+/// Compile the test case at the provided path `runs` times, and ensure that
+/// every run produces byte-identical output.
+///
+/// This does not compare against any expected output; it is intended to catch
+/// output that depends on things like hash iteration order.
+pub fn assert_deterministic(
+    path: PathBuf,
+    glyph_map: &GlyphMap,
+    fvar: &MockVariationInfo,
+    runs: usize,
+) -> Result<PathBuf, TestCase> {
+    let run_result = std::panic::catch_unwind(|| {
+        let first = compile_test_case(&path, glyph_map, fvar)?;
+        for run in 1..runs {
+            if compile_test_case(&path, glyph_map, fvar)? != first {
+                return Err(TestResult::Nondeterministic { run });
             }
-            Ok(result) => compare_ttx(&result, &path),
         }
+        Ok(())
     });
 
     match run_result {
@@ -303,6 +411,35 @@ pub(crate) fn run_test(
     .map_err(|reason| TestCase { reason, path })
 }
 
+fn compile_test_case(
+    path: &Path,
+    glyph_map: &GlyphMap,
+    fvar: &MockVariationInfo,
+) -> Result<Vec<u8>, TestResult> {
+    let mut compiler: Compiler<'_, TestFeatureProvider, MockVariationInfo> =
+        Compiler::new(path, glyph_map)
+            .print_warnings(std::env::var(super::VERBOSE).is_ok())
+            .with_opts(Opts::new().make_post_table(true));
+    if is_variable(path) {
+        compiler = compiler.with_variable_info(fvar);
+    }
+    if needs_feature_provider(path) {
+        compiler = compiler.with_feature_writer(&TestFeatureProvider)
+    }
+
+    match compiler.compile_binary() {
+        // this means we have a test case that doesn't exist or something weird
+        Err(CompilerError::SourceLoad(err)) => panic!("{err}"),
+        Err(CompilerError::WriteFail(err)) => panic!("{err}"),
+        Err(CompilerError::Cancelled(_)) => unreachable!("tests are never cancelled"),
+        Err(CompilerError::ParseFail(errs)) => Err(TestResult::ParseFail(errs.to_string(true))),
+        Err(CompilerError::ValidationFail(errs) | CompilerError::CompilationFail(errs)) => {
+            Err(TestResult::CompileFail(errs.to_string(true)))
+        }
+        Ok(result) => Ok(result),
+    }
+}
+
 fn print_diagnostics_if_verbose(diagnostics: &DiagnosticSet) {
     if std::env::var(super::VERBOSE).is_ok() && !diagnostics.is_empty() {
         eprintln!("{}", diagnostics.display());
@@ -652,7 +789,9 @@ impl Report {
                 TestResult::CompileFail(_) => summary.compile += 1,
                 TestResult::UnexpectedSuccess
                 | TestResult::TtxFail { .. }
-                | TestResult::ExpectedDiffFail { .. } => summary.other += 1,
+                | TestResult::ExpectedDiffFail { .. }
+                | TestResult::Nondeterministic { .. } => summary.other += 1,
+                TestResult::TableDiffFail(_) => summary.compare += 1,
                 TestResult::CompareFail { diff_percent, .. } => {
                     summary.compare += 1;
                     summary.sum_compare_perc += diff_percent;
@@ -673,6 +812,8 @@ impl TestResult {
             Self::UnexpectedSuccess => 6,
             Self::TtxFail { .. } => 10,
             Self::ExpectedDiffFail { .. } => 15,
+            Self::Nondeterministic { .. } => 20,
+            Self::TableDiffFail(_) => 45,
             Self::CompareFail { .. } => 50,
         }
     }
@@ -843,6 +984,25 @@ impl Display for ReasonPrinter<'_> {
                     write!(f, "{}", Color::Yellow.paint("expected diff fail"))
                 }
             }
+            TestResult::Nondeterministic { run } => write!(
+                f,
+                "{} (run {run})",
+                Color::Red.paint("nondeterministic output")
+            ),
+            TestResult::TableDiffFail(diffs) => {
+                write!(
+                    f,
+                    "{} ({} differences)",
+                    Color::Blue.paint("table diff failure"),
+                    diffs.len()
+                )?;
+                if self.verbose {
+                    for diff in diffs {
+                        write!(f, "\n  {diff}")?;
+                    }
+                }
+                Ok(())
+            }
             TestResult::CompareFail {
                 expected,
                 result,
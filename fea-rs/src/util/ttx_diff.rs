@@ -0,0 +1,401 @@
+//! Structural comparison of TTX output.
+//!
+//! Comparing TTX as text works well when the output is expected to match
+//! exactly, but when it doesn't the resulting diff is often hard to read: a
+//! single missing subtable can shift everything after it. This module parses
+//! both inputs into a tree of elements and reports each difference along with
+//! its location, e.g. `GSUB/LookupList/Lookup[3]/SingleSubst[0]`.
+//!
+//! Only the BASE, GDEF, GSUB, GPOS and STAT tables are compared.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// The tables that are compared.
+const LAYOUT_TABLES: &[&str] = &["BASE", "GDEF", "GSUB", "GPOS", "STAT"];
+
+/// A single difference between two TTX documents.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TtxDifference {
+    /// The location of the difference, as a path of element names.
+    ///
+    /// Elements with an `index` attribute include it, e.g. `Lookup[2]`.
+    pub path: String,
+    /// What is different at this location.
+    pub kind: DifferenceKind,
+}
+
+/// The type of a [`TtxDifference`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DifferenceKind {
+    /// An element in the expected output is missing from the result.
+    Missing(String),
+    /// An element in the result does not exist in the expected output.
+    Unexpected(String),
+    /// An attribute has a different value in the result.
+    #[allow(missing_docs)]
+    Attribute {
+        name: String,
+        expected: Option<String>,
+        result: Option<String>,
+    },
+}
+
+/// An error encountered while parsing TTX.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("malformed TTX at byte {pos}: {message}")]
+pub struct TtxParseError {
+    pos: usize,
+    message: &'static str,
+}
+
+/// A parsed XML element.
+///
+/// Comments and text content are discarded; in the layout tables the only
+/// comments are array lengths, which are redundant.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+/// Compare the layout tables in two TTX documents.
+///
+/// Returns an empty list if the tables are structurally identical.
+pub fn diff_ttx(expected: &str, result: &str) -> Result<Vec<TtxDifference>, TtxParseError> {
+    let expected = layout_tables(parse(expected)?);
+    let result = layout_tables(parse(result)?);
+    let mut diffs = Vec::new();
+    diff_children("", &expected, &result, &mut diffs);
+    Ok(diffs)
+}
+
+fn layout_tables(root: Element) -> Vec<Element> {
+    root.children
+        .into_iter()
+        .filter(|table| LAYOUT_TABLES.contains(&table.name.as_str()))
+        .collect()
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+    }
+
+    /// The name of this element in a path, including any index.
+    fn label(&self) -> String {
+        match self.attr("index") {
+            Some(index) => format!("{}[{index}]", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// A compact one-line rendering, used when reporting missing elements.
+    fn summary(&self) -> String {
+        let mut out = format!("<{}", self.name);
+        for (key, value) in &self.attrs {
+            out.push_str(&format!(" {key}=\"{value}\""));
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+        } else {
+            out.push_str(&format!(">…({} children)", self.children.len()));
+        }
+        out
+    }
+}
+
+fn join(parent: &str, child: &str) -> String {
+    if parent.is_empty() {
+        child.to_owned()
+    } else {
+        format!("{parent}/{child}")
+    }
+}
+
+fn diff_element(path: &str, expected: &Element, result: &Element, diffs: &mut Vec<TtxDifference>) {
+    let mut names: Vec<&str> = Vec::new();
+    for (key, _) in expected.attrs.iter().chain(&result.attrs) {
+        if key != "index" && !names.contains(&key.as_str()) {
+            names.push(key);
+        }
+    }
+    for name in names {
+        let (old, new) = (expected.attr(name), result.attr(name));
+        if old != new {
+            diffs.push(TtxDifference {
+                path: path.to_owned(),
+                kind: DifferenceKind::Attribute {
+                    name: name.to_owned(),
+                    expected: old.map(str::to_owned),
+                    result: new.map(str::to_owned),
+                },
+            });
+        }
+    }
+    diff_children(path, &expected.children, &result.children, diffs);
+}
+
+/// Compare two lists of child elements.
+///
+/// Children are paired up by label, so that items in indexed arrays are
+/// compared with the item at the same index. Repeated leaf elements without an
+/// index (such as the glyphs in a coverage table) are compared as sets, so
+/// that one missing glyph is reported once, and not as a change to every
+/// following glyph.
+fn diff_children(
+    path: &str,
+    expected: &[Element],
+    result: &[Element],
+    diffs: &mut Vec<TtxDifference>,
+) {
+    let mut unmatched = result.iter().map(Some).collect::<Vec<_>>();
+    for (i, old) in expected.iter().enumerate() {
+        let label = old.label();
+        let is_set_item = old.children.is_empty()
+            && old.attr("index").is_none()
+            && expected
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && other.name == old.name);
+        let found = unmatched.iter_mut().find(|new| {
+            new.is_some_and(|new| {
+                if is_set_item {
+                    new == old
+                } else {
+                    new.label() == label
+                }
+            })
+        });
+        match found.and_then(Option::take) {
+            Some(new) => diff_element(&join(path, &label), old, new, diffs),
+            None => diffs.push(TtxDifference {
+                path: path.to_owned(),
+                kind: DifferenceKind::Missing(old.summary()),
+            }),
+        }
+    }
+    for new in unmatched.into_iter().flatten() {
+        diffs.push(TtxDifference {
+            path: path.to_owned(),
+            kind: DifferenceKind::Unexpected(new.summary()),
+        });
+    }
+}
+
+impl Display for TtxDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "<root>"
+        } else {
+            &self.path
+        };
+        match &self.kind {
+            DifferenceKind::Missing(element) => write!(f, "{path}: missing {element}"),
+            DifferenceKind::Unexpected(element) => write!(f, "{path}: unexpected {element}"),
+            DifferenceKind::Attribute {
+                name,
+                expected,
+                result,
+            } => write!(
+                f,
+                "{path}: {name} expected {}, found {}",
+                expected.as_deref().unwrap_or("(none)"),
+                result.as_deref().unwrap_or("(none)")
+            ),
+        }
+    }
+}
+
+/// Parse a TTX document, returning the root element.
+fn parse(text: &str) -> Result<Element, TtxParseError> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut stack = vec![Element::default()];
+    while let Some(start) = parser.text[parser.pos..].find('<') {
+        parser.pos += start;
+        let rest = &parser.text[parser.pos..];
+        if rest.starts_with("<?") {
+            parser.skip_past("?>")?;
+        } else if rest.starts_with("<!--") {
+            parser.skip_past("-->")?;
+        } else if rest.starts_with("</") {
+            parser.skip_past(">")?;
+            let element = stack.pop().filter(|_| !stack.is_empty());
+            let parent = stack.last_mut();
+            match (element, parent) {
+                (Some(element), Some(parent)) => parent.children.push(element),
+                _ => return Err(parser.error("unbalanced end tag")),
+            }
+        } else {
+            parser.pos += 1;
+            let (element, closed) = parser.start_tag()?;
+            if closed {
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+    if stack.len() != 1 {
+        return Err(parser.error("unclosed element"));
+    }
+    let mut root = stack.pop().unwrap();
+    match root.children.len() {
+        1 => Ok(root.children.pop().unwrap()),
+        _ => Err(parser.error("expected a single root element")),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> TtxParseError {
+        TtxParseError {
+            pos: self.pos,
+            message,
+        }
+    }
+
+    fn skip_past(&mut self, delim: &str) -> Result<(), TtxParseError> {
+        match self.text[self.pos..].find(delim) {
+            Some(end) => {
+                self.pos += end + delim.len();
+                Ok(())
+            }
+            None => Err(self.error("unterminated tag")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &str {
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Parse a start tag, after the '<'. Returns `true` if it is self-closing.
+    fn start_tag(&mut self) -> Result<(Element, bool), TtxParseError> {
+        let is_name = |c: char| !c.is_whitespace() && !matches!(c, '/' | '>' | '=');
+        let name = self.take_while(is_name).to_owned();
+        if name.is_empty() {
+            return Err(self.error("expected element name"));
+        }
+        let mut element = Element {
+            name,
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            let rest = &self.text[self.pos..];
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok((element, true));
+            } else if rest.starts_with('>') {
+                self.pos += 1;
+                return Ok((element, false));
+            }
+            let key = self.take_while(is_name).to_owned();
+            self.skip_whitespace();
+            if key.is_empty() || !self.text[self.pos..].starts_with("=\"") {
+                return Err(self.error("expected attribute"));
+            }
+            self.pos += 2;
+            let Some(len) = self.text[self.pos..].find('"') else {
+                return Err(self.error("unterminated attribute value"));
+            };
+            let value = unescape(&self.text[self.pos..self.pos + len]);
+            self.pos += len + 1;
+            element.attrs.push((key, value));
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(tables: &str) -> String {
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ttFont>\n{tables}</ttFont>\n")
+    }
+
+    fn diff(expected: &str, result: &str) -> Vec<String> {
+        diff_ttx(&wrap(expected), &wrap(result))
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn identical() {
+        let ttx = "<GSUB>\n  <!-- LookupCount=1 -->\n  <Lookup index=\"0\">\n    <Glyph value=\"a\"/>\n  </Lookup>\n</GSUB>\n";
+        assert!(diff(ttx, ttx).is_empty());
+    }
+
+    #[test]
+    fn other_tables_ignored() {
+        let expected = "<head>\n  <unitsPerEm value=\"1000\"/>\n</head>\n<GDEF>\n</GDEF>\n";
+        assert!(diff(expected, "<GDEF>\n</GDEF>\n").is_empty());
+    }
+
+    #[test]
+    fn changed_attribute() {
+        let expected = "<GPOS><Lookup index=\"1\"><Value1 XAdvance=\"-100\"/></Lookup></GPOS>";
+        let result = "<GPOS><Lookup index=\"1\"><Value1 XAdvance=\"-90\"/></Lookup></GPOS>";
+        assert_eq!(
+            diff(expected, result),
+            ["GPOS/Lookup[1]/Value1: XAdvance expected -100, found -90"]
+        );
+    }
+
+    #[test]
+    fn missing_glyph_reported_once() {
+        let expected = "<GSUB><Coverage><Glyph value=\"a\"/><Glyph value=\"b\"/><Glyph value=\"c\"/></Coverage></GSUB>";
+        let result = "<GSUB><Coverage><Glyph value=\"a\"/><Glyph value=\"c\"/><Glyph value=\"d\"/></Coverage></GSUB>";
+        assert_eq!(
+            diff(expected, result),
+            [
+                "GSUB/Coverage: missing <Glyph value=\"b\"/>",
+                "GSUB/Coverage: unexpected <Glyph value=\"d\"/>",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_table_and_lookup() {
+        let expected = "<GSUB><Lookup index=\"0\"/><Lookup index=\"1\"/></GSUB><GPOS></GPOS>";
+        let result = "<GSUB><Lookup index=\"0\"/></GSUB>";
+        assert_eq!(
+            diff(expected, result),
+            [
+                "GSUB: missing <Lookup index=\"1\"/>",
+                "<root>: missing <GPOS/>",
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(diff_ttx("<ttFont><GSUB></ttFont>", "<ttFont/>").is_err());
+        assert!(diff_ttx("<ttFont><GSUB value=></GSUB></ttFont>", "<ttFont/>").is_err());
+    }
+}